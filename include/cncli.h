@@ -0,0 +1,41 @@
+/*
+ * The leader election math of cncli, exported by the shared library that
+ * `cargo rustc --release --lib --features ffi --crate-type cdylib` builds. See "Leader election from other languages"
+ * in USAGE.md.
+ * Every function returns CNCLI_OK or one of the error codes below and writes its result only on CNCLI_OK.
+ */
+#ifndef CNCLI_H
+#define CNCLI_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CNCLI_OK 0
+#define CNCLI_NULL_POINTER -1
+#define CNCLI_INVALID_ARGUMENT -2
+
+/* consensus is "tpraos", "praos" or "cpraos". epoch_nonce and vrf_input are 32 bytes. */
+int cncli_vrf_input(const char *consensus, uint64_t slot, const uint8_t *epoch_nonce, uint8_t *vrf_input);
+
+/* eta_v and next_eta_v are 32 bytes, nonce_vrf_output is the block's nonce vrf output as sync stores it. */
+int cncli_rolling_nonce(const uint8_t *eta_v, const uint8_t *nonce_vrf_output, size_t nonce_vrf_output_len,
+                        uint8_t *next_eta_v);
+
+/* candidate_nonce, lab_nonce and epoch_nonce are 32 bytes. extra_entropy may be NULL. */
+int cncli_epoch_nonce(const uint8_t *candidate_nonce, const uint8_t *lab_nonce, const uint8_t *extra_entropy,
+                      size_t extra_entropy_len, uint8_t *epoch_nonce);
+
+/* vrf_output is 64 bytes, active_slots_coeff is f from the shelley genesis. */
+int cncli_is_slot_leader(const char *consensus, const uint8_t *vrf_output, uint64_t pool_stake,
+                         uint64_t active_stake, double active_slots_coeff, bool *is_leader);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -1,19 +1,47 @@
-use std::io::stdout;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::string::ParseError;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
+use structopt::clap::{App, Shell};
 use structopt::StructOpt;
+use tracing::error;
 
+use crate::nodeclient::blockstore::sqlite::SqliteTuning;
+use crate::nodeclient::completions::CompletionValues;
+use crate::nodeclient::crosscheck::CrosscheckSource;
+use crate::nodeclient::exportchain::ExportField;
+use crate::nodeclient::leaderlog::battle;
 use crate::nodeclient::leaderlog::handle_error;
+use crate::nodeclient::leaderlog::schedule::ScheduleExport;
+use crate::nodeclient::leaderlog::simulate;
+use crate::nodeclient::leaderlog::trusted_nonces::read_trusted_nonces;
+use crate::nodeclient::leaderlog::vectors;
+use crate::nodeclient::leaderlog::vrf_keys::read_vrf_key_history;
+use crate::nodeclient::leaderlog::LeaderlogOptions;
+use crate::nodeclient::otlp::Telemetry;
+use crate::nodeclient::ping::PingTarget;
+use crate::nodeclient::poolid::{BlockHash, PoolId};
+use crate::nodeclient::reporter::human;
+use crate::nodeclient::snapshot::output::SnapshotOutput;
+use crate::nodeclient::sync::peers::Peer;
 use crate::nodeclient::sync::pooltool;
 use crate::nodeclient::sync::pooltool::PooltoolConfig;
-use crate::nodeclient::{leaderlog, ping, sign, snapshot, sync, validate};
+use crate::nodeclient::{
+    audit, blockdetail, cancel, completions, convert, crosscheck, db, density, exportchain, healthcheck, inspectkey,
+    leaderlog, opcert, overlay, ping, poolblocks, poolcheck, proxy, reporter, runstate, serve, sign, slots, snapshot,
+    stakehistory, stdinconfig, sync, telemetry, topology, tui, validate, verifyserver,
+};
 
 pub(crate) mod nodeclient;
 
+pub use crate::nodeclient::logfile::{
+    flush as flush_log_file, init as init_log_file, Error as LogFileError, LogRotation, LogSize,
+};
+pub use crate::nodeclient::sync::{Error as SyncError, SyncEvent, SyncService, SyncedBlock};
+
 pub static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
 #[derive(Debug)]
@@ -21,31 +49,150 @@ pub enum LedgerSet {
     Mark,
     Set,
     Go,
+    /// The given number of epochs after the current one, at least 2. Only usable with a supplied nonce.
+    Future(u64),
 }
 
 impl FromStr for LedgerSet {
     type Err = ParseError;
     fn from_str(ledger_set: &str) -> Result<Self, Self::Err> {
-        match ledger_set {
-            "next" => Ok(LedgerSet::Mark),
-            "current" => Ok(LedgerSet::Set),
-            "prev" => Ok(LedgerSet::Go),
+        // next+N and current+N select epochs further ahead than next
+        let epochs_ahead = match ledger_set.split_once('+') {
+            Some(("next", offset)) => offset.parse::<u64>().ok().map(|offset| offset + 1),
+            Some(("current", offset)) => offset.parse::<u64>().ok(),
+            _ => None,
+        };
+        match (ledger_set, epochs_ahead) {
+            (_, Some(0)) => Ok(LedgerSet::Set),
+            (_, Some(1)) => Ok(LedgerSet::Mark),
+            (_, Some(epochs_ahead)) => Ok(LedgerSet::Future(epochs_ahead)),
+            ("next", None) => Ok(LedgerSet::Mark),
+            ("current", None) => Ok(LedgerSet::Set),
+            ("prev", None) => Ok(LedgerSet::Go),
             _ => Ok(LedgerSet::Set),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum ScheduleFormat {
+    Systemd,
+    Cron,
+}
+
+impl FromStr for ScheduleFormat {
+    type Err = String;
+    fn from_str(schedule_format: &str) -> Result<Self, Self::Err> {
+        match schedule_format {
+            "systemd" => Ok(ScheduleFormat::Systemd),
+            "cron" => Ok(ScheduleFormat::Cron),
+            _ => Err(format!("Invalid schedule format: {schedule_format}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SlotsFormat {
+    CardanoCli,
+    Cncli,
+}
+
+impl FromStr for SlotsFormat {
+    type Err = String;
+    fn from_str(slots_format: &str) -> Result<Self, Self::Err> {
+        match slots_format {
+            "cardano-cli" => Ok(SlotsFormat::CardanoCli),
+            "cncli" => Ok(SlotsFormat::Cncli),
+            _ => Err(format!("Invalid slots format: {slots_format}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportFormat {
+    Json,
+    Human,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+    fn from_str(report_format: &str) -> Result<Self, Self::Err> {
+        match report_format {
+            "json" => Ok(ReportFormat::Json),
+            "human" => Ok(ReportFormat::Human),
+            _ => Err(format!("Invalid format: {report_format}")),
+        }
+    }
+}
+
+/// Where command results are written: stdout, a file://<path> or an http(s):// webhook that gets them POSTed
+#[derive(Debug, Clone)]
+pub enum Out {
+    Stdout,
+    File(PathBuf),
+    Webhook(String),
+}
+
+impl FromStr for Out {
+    type Err = String;
+    fn from_str(out: &str) -> Result<Self, Self::Err> {
+        match out {
+            "-" | "stdout" => Ok(Out::Stdout),
+            _ if out.starts_with("http://") || out.starts_with("https://") => Ok(Out::Webhook(out.to_string())),
+            _ => match out.strip_prefix("file://") {
+                Some(path) if !path.is_empty() => Ok(Out::File(PathBuf::from(path))),
+                _ => Err(format!("Invalid out: {out}, expected file://<path> or https://<url>")),
+            },
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 pub enum Command {
     Ping {
-        #[structopt(short, long, help = "cardano-node hostname to connect to")]
-        host: String,
+        #[structopt(
+            short,
+            long,
+            required_unless = "socket-path",
+            number_of_values = 1,
+            help = "cardano-node hostname to connect to. Repeat it or give a comma separated list to ping several nodes at once"
+        )]
+        host: Vec<String>,
         #[structopt(short, long, default_value = "3001", help = "cardano-node port")]
         port: u16,
         #[structopt(long, default_value = "764824073", help = "network magic.")]
         network_magic: u64,
         #[structopt(short, long, default_value = "2", help = "connect timeout in seconds")]
         timeout_seconds: u64,
+        #[structopt(
+            long,
+            help = "perform a node-to-client handshake against a TCP-exposed local socket instead of node-to-node"
+        )]
+        client_protocol: bool,
+        #[structopt(
+            long,
+            help = "SOCKS5 proxy to connect through, socks5://[user:password@]host:port, e.g. one opened with ssh -D"
+        )]
+        proxy: Option<String>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "cardano-node socket path to ping with a node-to-client handshake, without opening a TCP port"
+        )]
+        socket_path: Option<PathBuf>,
+        #[structopt(
+            long,
+            default_value = "0",
+            conflicts_with = "client-protocol",
+            help = "keepalive round trips to time after a node-to-node handshake"
+        )]
+        keepalive_count: usize,
+        #[structopt(
+            long,
+            conflicts_with = "keepalive-count",
+            help = "list the protocol versions and parameters the node supports with a handshake query instead of pinging it"
+        )]
+        query_versions: bool,
     },
     Validate {
         #[structopt(long, help = "full or partial block hash to validate")]
@@ -58,6 +205,66 @@ pub enum Command {
             help = "sqlite database file"
         )]
         db: PathBuf,
+        #[structopt(
+            long,
+            default_value = "5000",
+            help = "milliseconds to wait for a sqlite db locked by another process such as a running sync"
+        )]
+        busy_timeout: u64,
+        #[structopt(long, help = "open the sqlite db read-only so it never blocks a running sync")]
+        read_only: bool,
+        #[structopt(long, help = "print pool ids as bech32 (pool1...) instead of hex")]
+        bech32: bool,
+        #[structopt(
+            long,
+            default_value = "json",
+            possible_values = &["json", "human"],
+            help = "human prints aligned tables, colored on a terminal unless NO_COLOR is set"
+        )]
+        format: ReportFormat,
+    },
+    BlockDetail {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(
+            long,
+            required_unless = "slot",
+            conflicts_with = "slot",
+            help = "full or partial block hash to show"
+        )]
+        hash: Option<String>,
+        #[structopt(long, help = "slot number of the block(s) to show")]
+        slot: Option<u64>,
+    },
+    OpcertHistory {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(long, help = "pool id in hex or bech32 (pool1...)")]
+        pool_id: PoolId,
+    },
+    StakeHistory {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(long, help = "pool id in hex or bech32 (pool1...)")]
+        pool_id: PoolId,
     },
     Sync {
         #[structopt(
@@ -65,13 +272,24 @@ pub enum Command {
             short,
             long,
             default_value = "./cncli.db",
-            help = "sqlite database file"
+            help = "sqlite database file, or :memory: to keep the blocks in memory until cncli exits"
         )]
         db: PathBuf,
-        #[structopt(short, long, help = "cardano-node hostname to connect to")]
-        host: String,
+        #[structopt(
+            short,
+            long,
+            required_unless = "replay",
+            help = "cardano-node hostname to connect to"
+        )]
+        host: Option<String>,
         #[structopt(short, long, default_value = "3001", help = "cardano-node port")]
         port: u16,
+        #[structopt(
+            long = "peer",
+            number_of_values = 1,
+            help = "another cardano-node to sync from as host:port. Repeat for more peers. Sync switches to another peer when the one it follows cannot be reached or most others are on a longer chain"
+        )]
+        peers: Vec<Peer>,
         #[structopt(long, default_value = "764824073", help = "network magic.")]
         network_magic: u64,
         #[structopt(long, help = "Exit at 100% sync'd.")]
@@ -85,113 +303,139 @@ pub enum Command {
         shelley_genesis_hash: String,
         #[structopt(long, help = "Use the redb database instead of sqlite")]
         use_redb: bool,
-    },
-    Leaderlog {
+        #[structopt(
+            long,
+            help = "Fetch tpraos era block bodies to store d and extra entropy protocol update proposals"
+        )]
+        capture_protocol_updates: bool,
         #[structopt(
             parse(from_os_str),
-            short,
             long,
-            default_value = "./cncli.db",
-            help = "sqlite database file"
+            help = "Also write every block to this database using the other backend (redb if --use-redb is not set, otherwise sqlite)"
         )]
-        db: PathBuf,
-        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
-        byron_genesis: PathBuf,
-        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
-        shelley_genesis: PathBuf,
-        #[structopt(long, help = "pool active stake snapshot value in lovelace")]
-        pool_stake: u64,
-        #[structopt(long, help = "total active stake snapshot value in lovelace")]
-        active_stake: u64,
-        #[structopt(long = "d", default_value = "0", help = "decentralization parameter")]
-        d: f64,
-        #[structopt(long, help = "hex string of the extra entropy value")]
-        extra_entropy: Option<String>,
+        mirror_db: Option<PathBuf>,
         #[structopt(
             long,
-            default_value = "current",
-            help = "Which ledger data to use. prev - previous epoch, current - current epoch, next - future epoch"
+            visible_alias = "at-slot",
+            requires_all = &["from-hash", "trust-nonce"],
+            help = "Start syncing an empty database from this slot instead of the beginning of the chain"
         )]
-        ledger_set: LedgerSet,
-        #[structopt(long, help = "lower-case hex pool id")]
-        pool_id: String,
-        #[structopt(parse(from_os_str), long, help = "pool's vrf.skey file")]
-        pool_vrf_skey: PathBuf,
+        from_slot: Option<u64>,
+        #[structopt(long, requires = "from-slot", help = "Block hash at --from-slot in hex")]
+        from_hash: Option<BlockHash>,
         #[structopt(
-            long = "tz",
-            default_value = "America/Los_Angeles",
-            help = "TimeZone string from the IANA database - https://en.wikipedia.org/wiki/List_of_tz_database_time_zones"
+            long,
+            requires = "from-slot",
+            help = "Trusted rolling nonce (eta_v) in hex after the block at --from-slot, e.g. evolvingNonce from 'cardano-cli query protocol-state'"
         )]
-        timezone: String,
+        trust_nonce: Option<String>,
+        #[structopt(
+            long = "filter-pool-id",
+            number_of_values = 1,
+            help = "pool id in hex or bech32 to store full block headers for. Repeat for more pools. Blocks from other pools only keep what the nonce calculation needs"
+        )]
+        filter_pool_ids: Vec<PoolId>,
         #[structopt(
-            short,
             long,
-            default_value = "praos",
-            help = "Consensus algorithm - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos"
+            help = "Also store each header's original cbor so it can be parsed again later without a resync"
         )]
-        consensus: String,
+        store_raw_headers: bool,
         #[structopt(
             long,
-            env = "SHELLEY_TRANS_EPOCH",
-            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+            help = "Also write sync progress as one json object per line for installers and scripts"
         )]
-        shelley_transition_epoch: Option<u64>,
+        progress_json: bool,
         #[structopt(
             long,
-            help = "Provide a nonce value in lower-case hex instead of calculating from the db"
+            help = "SOCKS5 proxy to connect through, socks5://[user:password@]host:port, e.g. one opened with ssh -D"
         )]
-        nonce: Option<String>,
+        proxy: Option<String>,
         #[structopt(
             long,
-            help = "Provide a specific epoch number to calculate for and ignore --ledger-set option"
+            env = "CNCLI_SQLITE_WAL_AUTOCHECKPOINT",
+            help = "sqlite wal_autocheckpoint in pages, 0 turns automatic checkpoints off"
         )]
-        epoch: Option<u64>,
-    },
-    Sendtip {
+        sqlite_wal_autocheckpoint: Option<u32>,
         #[structopt(
-            parse(from_os_str),
             long,
-            default_value = "./pooltool.json",
-            help = "pooltool config file for sending tips"
+            env = "CNCLI_SQLITE_SYNCHRONOUS",
+            possible_values = &["off", "normal", "full", "extra"],
+            help = "sqlite synchronous setting, normal is safe in WAL mode and fsyncs only on checkpoints"
         )]
-        config: PathBuf,
+        sqlite_synchronous: Option<String>,
         #[structopt(
-            parse(from_os_str),
             long,
-            help = "path to cardano-node executable for gathering version info"
+            env = "CNCLI_SQLITE_CACHE_SIZE",
+            allow_hyphen_values = true,
+            help = "sqlite cache_size, in pages or in KiB when negative"
         )]
-        cardano_node: PathBuf,
-    },
-    Sendslots {
+        sqlite_cache_size: Option<i64>,
         #[structopt(
-            parse(from_os_str),
             long,
-            default_value = "./pooltool.json",
-            help = "pooltool config file for sending slots"
+            env = "CNCLI_FLUSH_INTERVAL_MS",
+            default_value = "5000",
+            help = "How long blocks are batched before they are written while catching up"
         )]
-        config: PathBuf,
+        flush_interval_ms: u64,
+        #[structopt(
+            long,
+            env = "CNCLI_WAL_CHECKPOINT_INTERVAL_SECS",
+            help = "Checkpoint and truncate the sqlite WAL this often"
+        )]
+        wal_checkpoint_interval_secs: Option<u64>,
+        #[structopt(
+            long,
+            default_value = "50",
+            help = "Chainsync requests kept in flight at most while catching up, 1 to not pipeline. At the tip only one is"
+        )]
+        max_pipeline_depth: usize,
+        #[structopt(
+            long,
+            requires_all = &["byron-genesis", "shelley-genesis", "report-pool-id"],
+            help = "At each epoch boundary write the pool-blocks report of the finished epoch to file://<path> or POST it to https://<url>"
+        )]
+        post_epoch_report: Option<Out>,
+        #[structopt(
+            long = "report-pool-id",
+            number_of_values = 1,
+            help = "pool id in hex or bech32 to write --post-epoch-report for. Repeat for more pools"
+        )]
+        report_pool_ids: Vec<PoolId>,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file, for --post-epoch-report")]
+        byron_genesis: Option<PathBuf>,
         #[structopt(
             parse(from_os_str),
-            short,
             long,
-            default_value = "./cncli.db",
-            help = "sqlite database file"
+            help = "shelley genesis json file, for --post-epoch-report"
         )]
-        db: PathBuf,
-        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
-        byron_genesis: PathBuf,
-        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
-        shelley_genesis: PathBuf,
+        shelley_genesis: Option<PathBuf>,
         #[structopt(
             long,
             env = "SHELLEY_TRANS_EPOCH",
             help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
         )]
         shelley_transition_epoch: Option<u64>,
-        #[structopt(long, env = "OVERRIDE_TIME", hide_env_values = true, hidden = true)]
-        override_time: Option<String>,
+        #[structopt(
+            long,
+            help = "OpenTelemetry collector to send spans and counters to with OTLP over HTTP, e.g. http://localhost:4318"
+        )]
+        otlp_endpoint: Option<String>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            conflicts_with = "replay",
+            help = "Append every chainsync message and block body received to this session file"
+        )]
+        record: Option<PathBuf>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            conflicts_with_all = &["host", "peers"],
+            help = "Run the messages of a session file made with --record through sync and the database instead of connecting to a node"
+        )]
+        replay: Option<PathBuf>,
     },
-    Status {
+    Serve {
         #[structopt(
             parse(from_os_str),
             short,
@@ -210,150 +454,1528 @@ pub enum Command {
             help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
         )]
         shelley_transition_epoch: Option<u64>,
+        #[structopt(long, default_value = "127.0.0.1:8787", help = "address and port to serve HTTP on")]
+        listen: String,
+        #[structopt(
+            long,
+            help = "OpenTelemetry collector to send spans and counters to with OTLP over HTTP, e.g. http://localhost:4318"
+        )]
+        otlp_endpoint: Option<String>,
     },
-    Nonce {
+    Leaderlog {
         #[structopt(
             parse(from_os_str),
             short,
             long,
             default_value = "./cncli.db",
-            help = "sqlite or redb database file"
+            help = "sqlite database file"
         )]
         db: PathBuf,
-        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
-        byron_genesis: PathBuf,
-        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
-        shelley_genesis: PathBuf,
-        #[structopt(long, help = "hex string of the extra entropy value")]
-        extra_entropy: Option<String>,
         #[structopt(
+            parse(from_os_str),
             long,
-            default_value = "current",
-            help = "Which ledger data to use. prev - previous epoch, current - current epoch, next - future epoch"
+            required_unless = "from-archive",
+            help = "byron genesis json file"
         )]
-        ledger_set: LedgerSet,
+        byron_genesis: Option<PathBuf>,
         #[structopt(
+            parse(from_os_str),
             long,
-            env = "SHELLEY_TRANS_EPOCH",
-            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+            required_unless = "from-archive",
+            help = "shelley genesis json file"
         )]
-        shelley_transition_epoch: Option<u64>,
+        shelley_genesis: Option<PathBuf>,
         #[structopt(
-            short,
             long,
-            default_value = "praos",
-            help = "Consensus algorithm - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos"
+            required_unless_one = &["from-archive", "stdin-config"],
+            help = "pool active stake snapshot value in lovelace"
         )]
-        consensus: String,
+        pool_stake: Option<u64>,
         #[structopt(
             long,
-            help = "Provide a specific epoch number to calculate for and ignore --ledger-set option"
+            required_unless_one = &["from-archive", "stdin-config"],
+            help = "total active stake snapshot value in lovelace"
         )]
-        epoch: Option<u64>,
-    },
-    Challenge {
-        #[structopt(long, help = "validating domain e.g. pooltool.io")]
-        domain: String,
-    },
-    Sign {
-        #[structopt(parse(from_os_str), long, help = "pool's vrf.skey file")]
-        pool_vrf_skey: PathBuf,
-        #[structopt(long, help = "validating domain e.g. pooltool.io")]
-        domain: String,
-        #[structopt(long, help = "nonce value in lower-case hex")]
-        nonce: String,
-    },
-    Verify {
-        #[structopt(parse(from_os_str), long, help = "pool's vrf.vkey file")]
-        pool_vrf_vkey: PathBuf,
+        active_stake: Option<u64>,
+        #[structopt(long = "d", default_value = "0", help = "decentralization parameter")]
+        d: f64,
+        #[structopt(long, help = "hex string of the extra entropy value")]
+        extra_entropy: Option<String>,
         #[structopt(
             long,
-            help = "pool's vrf hash in hex retrieved from 'cardano-cli query pool-params...'"
+            help = "read poolStake, activeStake, nonce and extraEntropy from a JSON document on stdin, they take precedence over the arguments"
         )]
-        pool_vrf_vkey_hash: String,
-        #[structopt(long, help = "validating domain e.g. pooltool.io")]
-        domain: String,
-        #[structopt(long, help = "nonce value in lower-case hex")]
-        nonce: String,
-        #[structopt(long, help = "signature to verify in hex")]
-        signature: String,
-    },
-    Snapshot {
-        #[structopt(parse(from_os_str), long, help = "cardano-node socket path")]
-        socket_path: PathBuf,
-        #[structopt(long, default_value = "764824073", help = "network magic.")]
-        network_magic: u64,
-        #[structopt(long, default_value = "mark", help = "Snapshot name to retrieve (mark, set, go)")]
-        name: String,
+        stdin_config: bool,
         #[structopt(
             long,
-            default_value = "1",
-            help = "The network identifier, (1 for mainnet, 0 for testnet)"
+            default_value = "current",
+            help = "Which ledger data to use. prev - previous epoch, current - current epoch, next - future epoch, next+N - N epochs after next, only with --nonce"
         )]
-        network_id: u8,
+        ledger_set: LedgerSet,
+        #[structopt(long, help = "pool id in hex or bech32 (pool1...)")]
+        pool_id: PoolId,
         #[structopt(
+            parse(from_os_str),
             long,
-            default_value = "stake",
-            help = "The prefix for stake addresses, (stake for mainnet, stake_test for testnet)"
+            required_unless_one = &["from-archive", "pool-vrf-skey-history"],
+            help = "pool's vrf.skey file"
         )]
-        stake_prefix: String,
-        #[structopt(long, default_value = "mark.csv", help = "The name of the output file (CSV format)")]
-        output_file: String,
-    },
-    PoolStake {
-        #[structopt(parse(from_os_str), long, help = "cardano-node socket path")]
-        socket_path: PathBuf,
-        #[structopt(long, default_value = "764824073", help = "network magic.")]
-        network_magic: u64,
+        pool_vrf_skey: Option<PathBuf>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            conflicts_with = "pool-vrf-skey",
+            help = "JSON file of the pool's vrf.skey files and the epochs each was registered in, to pick the key of the target epoch"
+        )]
+        pool_vrf_skey_history: Option<PathBuf>,
+        #[structopt(
+            long = "tz",
+            default_value = "America/Los_Angeles",
+            help = "TimeZone string from the IANA database - https://en.wikipedia.org/wiki/List_of_tz_database_time_zones, or local for the system timezone"
+        )]
+        timezone: String,
+        #[structopt(
+            short,
+            long,
+            default_value = "praos",
+            help = "Consensus algorithm - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos, all evaluates each of them and tags every slot with the ones that assign it"
+        )]
+        consensus: String,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(
+            long,
+            help = "Provide a nonce value in lower-case hex instead of calculating from the db"
+        )]
+        nonce: Option<String>,
+        #[structopt(
+            long,
+            help = "Provide a specific epoch number to calculate for and ignore --ledger-set option. Epochs after the current one need --nonce"
+        )]
+        epoch: Option<u64>,
+        #[structopt(
+            long,
+            help = "Use the d and extra entropy values stored during sync with --capture-protocol-updates instead of --d and --extra-entropy"
+        )]
+        protocol_params_from_db: bool,
+        #[structopt(long, help = "Do not print the leaderlog, only save the slots to the db")]
+        quiet: bool,
+        #[structopt(long, help = "Print the leaderlog as single-line JSON")]
+        json_compact: bool,
+        #[structopt(
+            long,
+            help = "Fail instead of warning when the vrf.skey permissions or ownership are unsafe"
+        )]
+        strict: bool,
+        #[structopt(long, help = "Only check the vrf.skey permissions and ownership, then exit")]
+        check_perms: bool,
+        #[structopt(
+            long,
+            help = "Exit with code 2 when no slots are assigned, and with code 1 when the leaderlog could not be calculated"
+        )]
+        fail_if_zero: bool,
+        #[structopt(
+            long,
+            default_value = "5000",
+            help = "milliseconds to wait for a sqlite db locked by another process such as a running sync"
+        )]
+        busy_timeout: u64,
+        #[structopt(
+            long,
+            help = "open the sqlite db read-only so it never blocks a running sync. Slots are not saved for sendslots"
+        )]
+        read_only: bool,
+        #[structopt(
+            long,
+            help = "After a successful leaderlog, HTTP PUT the slots table and recent chain as JSON to this url (S3 presigned, WebDAV or HTTP). {epoch} is replaced with the epoch"
+        )]
+        backup_url: Option<String>,
+        #[structopt(
+            long,
+            possible_values = &["systemd", "cron"],
+            requires = "schedule-command",
+            help = "Print the assigned slots as systemd timer units or crontab lines instead of the leaderlog"
+        )]
+        emit_schedule: Option<ScheduleFormat>,
+        #[structopt(
+            long,
+            help = "Command the schedule runs before each slot. {slot} and {at} are replaced with the slot number and its UTC time"
+        )]
+        schedule_command: Option<String>,
+        #[structopt(
+            long,
+            default_value = "60",
+            help = "How many seconds before each slot the schedule command runs"
+        )]
+        schedule_lead_seconds: u64,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "JSON file of known-good epoch nonces. Fail instead of printing a result when the calculated nonce differs"
+        )]
+        trusted_nonces: Option<PathBuf>,
+        #[structopt(
+            long,
+            requires = "trusted-nonces",
+            help = "hex ed25519 public key the --trusted-nonces file must be signed with"
+        )]
+        trusted_nonces_vkey: Option<String>,
+        #[structopt(
+            long,
+            help = "Add a reminderAt time this many minutes before each assigned slot to the leaderlog"
+        )]
+        reminder_lead_minutes: Option<u64>,
+        #[structopt(
+            long,
+            default_value = "cncli",
+            possible_values = &["cncli", "cardano-cli"],
+            help = "cardano-cli prints the slots like cardano-cli query leadership-schedule instead of the leaderlog"
+        )]
+        output_format: SlotsFormat,
+        #[structopt(long, help = "print the pool id as bech32 (pool1...) instead of hex")]
+        bech32: bool,
+        #[structopt(
+            long,
+            default_value = "json",
+            possible_values = &["json", "human"],
+            help = "human prints aligned tables, colored on a terminal unless NO_COLOR is set"
+        )]
+        format: ReportFormat,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "Also write the leaderlog to epoch_<epoch>_<pool id>.json in this directory"
+        )]
+        archive_dir: Option<PathBuf>,
+        #[structopt(
+            long,
+            requires = "archive-dir",
+            conflicts_with = "emit-schedule",
+            help = "Print the leaderlog archived in --archive-dir for --epoch, or the latest archived epoch, instead of calculating it"
+        )]
+        from_archive: bool,
+        #[structopt(
+            long,
+            conflicts_with = "socket-path",
+            help = "pool's registered vrf hash in hex from 'cardano-cli query pool-params...'. Fail before the slot scan when the vrf.skey does not match it"
+        )]
+        pool_vrf_vkey_hash: Option<String>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "cardano-node socket path to fetch the pool's registered vrf hash from and check the vrf.skey against it"
+        )]
+        socket_path: Option<PathBuf>,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+    },
+    Sendtip {
+        #[structopt(
+            parse(from_os_str),
+            long,
+            default_value = "./pooltool.json",
+            help = "pooltool config file for sending tips"
+        )]
+        config: PathBuf,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "path to cardano-node executable for gathering version info"
+        )]
+        cardano_node: PathBuf,
+        #[structopt(long, help = "read the pooltool config from stdin instead of --config")]
+        stdin_config: bool,
+        #[structopt(
+            long,
+            help = "SOCKS5 proxy to connect through, socks5://[user:password@]host:port, e.g. one opened with ssh -D"
+        )]
+        proxy: Option<String>,
+    },
+    Sendslots {
+        #[structopt(
+            parse(from_os_str),
+            long,
+            default_value = "./pooltool.json",
+            help = "pooltool config file for sending slots"
+        )]
+        config: PathBuf,
+        #[structopt(long, help = "read the pooltool config from stdin instead of --config")]
+        stdin_config: bool,
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(long, env = "OVERRIDE_TIME", hide_env_values = true, hidden = true)]
+        override_time: Option<String>,
+        #[structopt(
+            long,
+            help = "print what would be sent to pooltool with the leaderlog inputs of the slots instead of sending it"
+        )]
+        dry_run: bool,
+    },
+    Status {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(
+            long,
+            default_value = "5000",
+            help = "milliseconds to wait for a sqlite db locked by another process such as a running sync"
+        )]
+        busy_timeout: u64,
+        #[structopt(long, help = "open the sqlite db read-only so it never blocks a running sync")]
+        read_only: bool,
+        #[structopt(
+            long,
+            default_value = "json",
+            possible_values = &["json", "human"],
+            help = "human prints aligned tables, colored on a terminal unless NO_COLOR is set"
+        )]
+        format: ReportFormat,
+    },
+    Nonce {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(long, help = "hex string of the extra entropy value")]
+        extra_entropy: Option<String>,
+        #[structopt(
+            long,
+            default_value = "current",
+            help = "Which ledger data to use. prev - previous epoch, current - current epoch, next - future epoch"
+        )]
+        ledger_set: LedgerSet,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(
+            short,
+            long,
+            default_value = "praos",
+            help = "Consensus algorithm - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos"
+        )]
+        consensus: String,
+        #[structopt(
+            long,
+            help = "Provide a specific epoch number to calculate for and ignore --ledger-set option"
+        )]
+        epoch: Option<u64>,
+        #[structopt(
+            long,
+            requires = "to-slot",
+            help = "Trace each block's contribution to the rolling nonce starting at this slot instead of calculating the epoch nonce"
+        )]
+        trace_from_slot: Option<u64>,
+        #[structopt(long, requires = "trace-from-slot", help = "Last slot to include in the nonce trace")]
+        to_slot: Option<u64>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "JSON file of known-good epoch nonces. Fail instead of printing a result when the calculated nonce differs"
+        )]
+        trusted_nonces: Option<PathBuf>,
+        #[structopt(
+            long,
+            requires = "trusted-nonces",
+            help = "hex ed25519 public key the --trusted-nonces file must be signed with"
+        )]
+        trusted_nonces_vkey: Option<String>,
+        #[structopt(
+            long,
+            conflicts_with_all = &["epoch", "trace-from-slot", "trusted-nonces"],
+            help = "Report the next epoch nonce as it stands at the db tip, with how many slots remain until it is final"
+        )]
+        predict: bool,
+    },
+    Simulate {
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(long, help = "Epoch number the nonce belongs to")]
+        epoch: u64,
+        #[structopt(long, help = "epoch nonce value in lower-case hex")]
+        epoch_nonce: String,
+        #[structopt(parse(from_os_str), long, alias = "vrf-skey", help = "pool's vrf.skey file")]
+        pool_vrf_skey: PathBuf,
+        #[structopt(
+            long,
+            help = "Sigma values to simulate as start:end:step, e.g. 0.001:0.01:0.001. At most 1000 values"
+        )]
+        sigma_range: String,
+        #[structopt(long = "d", default_value = "0", help = "decentralization parameter")]
+        d: f64,
+        #[structopt(
+            short,
+            long,
+            default_value = "praos",
+            help = "Consensus algorithm - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos"
+        )]
+        consensus: String,
+    },
+    BattleOdds {
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(long, help = "slot to estimate the slot battle odds for")]
+        slot: u64,
+        #[structopt(long, help = "epoch nonce value in lower-case hex")]
+        epoch_nonce: String,
+        #[structopt(parse(from_os_str), long, alias = "vrf-skey", help = "pool's vrf.skey file")]
+        pool_vrf_skey: PathBuf,
+        #[structopt(
+            short,
+            long,
+            default_value = "praos",
+            help = "Consensus algorithm - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos"
+        )]
+        consensus: String,
+        #[structopt(long, help = "our pool id in hex or bech32, left out of the competitors")]
+        pool_id: Option<PoolId>,
+        #[structopt(
+            long,
+            help = "our sigma. Omitted means take it from --pool-stake when --pool-id is given"
+        )]
+        sigma: Option<f64>,
+        #[structopt(
+            long = "competitor",
+            number_of_values = 1,
+            help = "competing pool as pool_id:sigma, can be repeated"
+        )]
+        competitors: Vec<String>,
+        #[structopt(parse(from_os_str), long, help = "csv written by pool-stake, all its pools compete")]
+        pool_stake: Option<PathBuf>,
+    },
+    Challenge {
+        #[structopt(long, help = "validating domain e.g. pooltool.io")]
+        domain: String,
+    },
+    Sign {
+        #[structopt(parse(from_os_str), long, help = "pool's vrf.skey file")]
+        pool_vrf_skey: PathBuf,
+        #[structopt(long, help = "validating domain e.g. pooltool.io")]
+        domain: String,
+        #[structopt(long, help = "nonce value in lower-case hex")]
+        nonce: String,
+        #[structopt(
+            long,
+            help = "Fail instead of warning when the vrf.skey permissions or ownership are unsafe"
+        )]
+        strict: bool,
+        #[structopt(long, help = "Only check the vrf.skey permissions and ownership, then exit")]
+        check_perms: bool,
+    },
+    Verify {
+        #[structopt(parse(from_os_str), long, help = "pool's vrf.vkey file")]
+        pool_vrf_vkey: PathBuf,
+        #[structopt(
+            long,
+            help = "pool's vrf hash in hex retrieved from 'cardano-cli query pool-params...'. If omitted, it is fetched from the node using --socket-path and --pool-id"
+        )]
+        pool_vrf_vkey_hash: Option<String>,
+        #[structopt(parse(from_os_str), long, help = "cardano-node socket path")]
+        socket_path: Option<PathBuf>,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+        #[structopt(long, help = "pool id in hex or bech32 used to fetch the registered vrf hash")]
+        pool_id: Option<PoolId>,
+        #[structopt(long, help = "validating domain e.g. pooltool.io")]
+        domain: String,
+        #[structopt(long, help = "nonce value in lower-case hex")]
+        nonce: String,
+        #[structopt(long, help = "signature to verify in hex")]
+        signature: String,
+    },
+    VerifyServer {
+        #[structopt(long, help = "validating domain the challenges are for e.g. pooltool.io")]
+        domain: String,
+        #[structopt(long, default_value = "127.0.0.1", help = "address to serve HTTP on")]
+        host: String,
+        #[structopt(long, default_value = "8788", help = "port to serve HTTP on")]
+        port: u16,
+        #[structopt(
+            long,
+            default_value = "300",
+            help = "seconds a challenge can be signed and verified in"
+        )]
+        challenge_ttl_secs: u64,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "cardano-node socket path, lets /verify look up the registered vrf hash of a poolId"
+        )]
+        socket_path: Option<PathBuf>,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+    },
+    InspectKey {
+        #[structopt(parse(from_os_str), long, help = "skey, vkey or opcert file to inspect")]
+        file: PathBuf,
+    },
+    /// Convert a pool id, stake address or verification key between hex and bech32 and derive its hashes
+    Convert {
+        #[structopt(long, help = "pool id, stake address or verification key in hex or bech32")]
+        input: String,
+        #[structopt(
+            long,
+            possible_values = &["pool", "stake", "stake_test", "pool_vk", "stake_vk", "vrf_vk"],
+            help = "what hex input is. Omitted means a pool id for 28 bytes or a stake address for 29 bytes"
+        )]
+        hrp: Option<String>,
+        #[structopt(
+            long,
+            default_value = "1",
+            help = "The network identifier of the stake address derived from a stake_vk, (1 for mainnet, 0 for testnet)"
+        )]
+        network_id: u8,
+    },
+    PoolVrfHash {
+        #[structopt(parse(from_os_str), long, help = "cardano-node socket path")]
+        socket_path: PathBuf,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+        #[structopt(long, help = "pool id in hex or bech32")]
+        pool_id: PoolId,
+    },
+    PoolCheck {
+        #[structopt(parse(from_os_str), long, help = "cardano-node socket path")]
+        socket_path: PathBuf,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            default_value = "./pooltool.json",
+            help = "pooltool config file with the pools to check"
+        )]
+        config: PathBuf,
+    },
+    Snapshot {
+        #[structopt(parse(from_os_str), long, help = "cardano-node socket path")]
+        socket_path: PathBuf,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+        #[structopt(long, default_value = "mark", help = "Snapshot name to retrieve (mark, set, go)")]
+        name: String,
+        #[structopt(
+            long,
+            conflicts_with = "manifest",
+            help = "Write the snapshot as it was in this epoch, up to two epochs before the ledger state"
+        )]
+        epoch: Option<u64>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "Write the snapshots listed in this manifest file, skipping the ones it records as done"
+        )]
+        manifest: Option<PathBuf>,
+        #[structopt(
+            long,
+            default_value = "1",
+            help = "The network identifier, (1 for mainnet, 0 for testnet)"
+        )]
+        network_id: u8,
+        #[structopt(
+            long,
+            default_value = "stake",
+            help = "The prefix for stake addresses, (stake for mainnet, stake_test for testnet)"
+        )]
+        stake_prefix: String,
+        #[structopt(long, default_value = "mark.csv", help = "The name of the output file (CSV format)")]
+        output_file: String,
+        #[structopt(
+            long,
+            help = "Write the rows to a database table instead of --output-file: sqlite://<file.db>[#table] or postgres://<connection string>[#table]"
+        )]
+        output: Option<SnapshotOutput>,
+        #[structopt(long, help = "Only write stake addresses with at least this many lovelace")]
+        min_lovelace: Option<u64>,
+        #[structopt(long, help = "Only write this stake address (stake1...), repeat for more addresses")]
+        address: Vec<String>,
+        #[structopt(
+            long,
+            default_value = "10",
+            help = "seconds to wait for the node socket to accept the connection"
+        )]
+        connect_timeout_seconds: u64,
+        #[structopt(
+            long,
+            default_value = "600",
+            help = "seconds to wait for the node to answer each step of the ledger state query"
+        )]
+        query_timeout_seconds: u64,
+    },
+    PoolStake {
+        #[structopt(parse(from_os_str), long, help = "cardano-node socket path")]
+        socket_path: PathBuf,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
         #[structopt(
             long,
             default_value = "mark",
             help = "PoolStake snapshot name to retrieve (mark, set, go)"
         )]
-        name: String,
+        name: String,
+        #[structopt(
+            long,
+            conflicts_with = "manifest",
+            help = "Write the snapshot as it was in this epoch, up to two epochs before the ledger state"
+        )]
+        epoch: Option<u64>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "Write the snapshots listed in this manifest file, skipping the ones it records as done"
+        )]
+        manifest: Option<PathBuf>,
+        #[structopt(
+            long,
+            default_value = "1",
+            help = "Unused, the pool stake does not depend on the network identifier. Kept for compatibility"
+        )]
+        network_id: u8,
+        #[structopt(long, default_value = "mark.csv", help = "The name of the output file (CSV format)")]
+        output_file: String,
+        #[structopt(
+            long,
+            help = "Write the rows to a database table instead of --output-file: sqlite://<file.db>[#table] or postgres://<connection string>[#table]"
+        )]
+        output: Option<SnapshotOutput>,
+        #[structopt(long, help = "write pool ids as bech32 (pool1...) instead of hex")]
+        bech32: bool,
+        #[structopt(
+            long,
+            default_value = "10",
+            help = "seconds to wait for the node socket to accept the connection"
+        )]
+        connect_timeout_seconds: u64,
+        #[structopt(
+            long,
+            default_value = "600",
+            help = "seconds to wait for the node to answer each step of the ledger state query"
+        )]
+        query_timeout_seconds: u64,
+    },
+    Density {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(long, help = "Epoch number to analyze")]
+        epoch: u64,
+        #[structopt(
+            long,
+            default_value = "100",
+            help = "Report stretches of consecutive slots without blocks longer than this many slots"
+        )]
+        min_gap_slots: u64,
+    },
+    /// Compare a sample of the blocks of an epoch in the db with the chain koios or blockfrost follows
+    Crosscheck {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(long, help = "Epoch number to check")]
+        epoch: u64,
+        #[structopt(long, possible_values = &["koios", "blockfrost"], help = "external source to compare with")]
+        source: CrosscheckSource,
+        #[structopt(long, default_value = "20", help = "how many block hashes to look up at the source")]
+        samples: u64,
+        #[structopt(
+            long,
+            help = "source API url. Omitted means the public one for the network of the genesis files"
+        )]
+        api_url: Option<String>,
+        #[structopt(
+            long,
+            env = "CNCLI_CROSSCHECK_API_KEY",
+            hide_env_values = true,
+            help = "blockfrost project id or koios bearer token"
+        )]
+        api_key: Option<String>,
+    },
+    Overlay {
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(long, help = "Epoch number to list the overlay slots for")]
+        epoch: u64,
+        #[structopt(long, help = "decentralization parameter")]
+        d: f64,
+        #[structopt(
+            long,
+            help = "Print every slot of the epoch as CSV instead of the overlay slots as JSON"
+        )]
+        csv: bool,
+    },
+    PoolBlocks {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(long, help = "pool id in hex or bech32 (pool1...)")]
+        pool_id: PoolId,
+        #[structopt(long, help = "Epoch number to list the minted blocks for")]
+        epoch: u64,
+        #[structopt(long, help = "Print the blocks as CSV without the totals instead of JSON")]
+        csv: bool,
+        #[structopt(long, help = "Print the pool id as bech32 (pool1...) instead of hex")]
+        bech32: bool,
+    },
+    /// Recalculate a finished epoch's nonce and schedule and compare them with the saved slots and minted blocks
+    AuditEpoch {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(long, help = "pool id in hex or bech32 (pool1...)")]
+        pool_id: PoolId,
+        #[structopt(parse(from_os_str), long, help = "pool's vrf.skey file, the audit is signed with it")]
+        pool_vrf_skey: PathBuf,
+        #[structopt(long, help = "Finished epoch number to audit")]
+        epoch: u64,
+        #[structopt(
+            long,
+            requires = "active-stake",
+            help = "pool active stake snapshot value in lovelace. Omitted means the stake leaderlog saved for the epoch"
+        )]
+        pool_stake: Option<u64>,
+        #[structopt(
+            long,
+            requires = "pool-stake",
+            help = "total active stake snapshot value in lovelace. Omitted means the stake leaderlog saved for the epoch"
+        )]
+        active_stake: Option<u64>,
+        #[structopt(
+            short,
+            long,
+            default_value = "praos",
+            possible_values = &["tpraos", "praos", "cpraos"],
+            help = "Consensus algorithm of the epoch - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos"
+        )]
+        consensus: String,
+        #[structopt(
+            long,
+            help = "Fail instead of warning when the vrf.skey permissions or ownership are unsafe"
+        )]
+        strict: bool,
+    },
+    /// Export the blocks of a slot range as CSV or JSON lines
+    ExportChain {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(long, help = "first slot to export")]
+        from_slot: u64,
+        #[structopt(long, help = "last slot to export. Omitted means the db tip")]
+        to_slot: Option<u64>,
         #[structopt(
             long,
-            default_value = "1",
-            help = "The network identifier, (1 for mainnet, 0 for testnet)"
+            use_delimiter = true,
+            default_value = "slot,block_number,hash,pool_id",
+            help = "comma separated block fields to export, named like the columns of the chain table"
         )]
-        network_id: u8,
-        #[structopt(long, default_value = "mark.csv", help = "The name of the output file (CSV format)")]
-        output_file: String,
+        fields: Vec<ExportField>,
+        #[structopt(long, default_value = "csv", possible_values = &["csv", "jsonl"], help = "output format")]
+        format: String,
+        #[structopt(long, help = "also export orphaned blocks")]
+        include_orphans: bool,
+    },
+    /// Show the last successful sync, leaderlog, sendslots and sendtip runs recorded in the --state-file
+    LastRuns {
+        #[structopt(
+            long,
+            possible_values = &["sync", "leaderlog", "sendslots", "sendtip"],
+            help = "only show runs of this operation"
+        )]
+        operation: Option<String>,
+        #[structopt(long, help = "only show runs for this pool id in hex or bech32")]
+        pool_id: Option<PoolId>,
+        #[structopt(long, help = "only show runs for this epoch")]
+        epoch: Option<u64>,
+    },
+    Healthcheck {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(
+            long,
+            default_value = "120",
+            help = "report unhealthy when the db tip is older than this many seconds"
+        )]
+        max_tip_age_seconds: i64,
+        #[structopt(long, help = "cardano-node hostname to ping. Omitted means skip the ping check")]
+        host: Option<String>,
+        #[structopt(long, default_value = "3001", help = "cardano-node port")]
+        port: u16,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+        #[structopt(long, default_value = "2", help = "connect timeout in seconds")]
+        timeout_seconds: u64,
+        #[structopt(
+            long,
+            help = "report unhealthy when the last keepalive round trip of a running sync or sendtip, read from --state-file, took longer than this many milliseconds"
+        )]
+        max_keepalive_rtt_ms: Option<u64>,
+        #[structopt(
+            long,
+            default_value = "120",
+            help = "with --max-keepalive-rtt-ms, report unhealthy when the last keepalive round trip is older than this many seconds, e.g. because sync stopped"
+        )]
+        max_keepalive_age_seconds: i64,
+    },
+    /// Show a terminal dashboard of the sync status, upcoming slots, recent blocks and pooltool submissions
+    Tui {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "byron genesis json file")]
+        byron_genesis: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "shelley genesis json file")]
+        shelley_genesis: PathBuf,
+        #[structopt(
+            long,
+            env = "SHELLEY_TRANS_EPOCH",
+            help = "Epoch number where we transition from Byron to Shelley. Omitted means guess based on genesis files"
+        )]
+        shelley_transition_epoch: Option<u64>,
+        #[structopt(
+            long = "pool-id",
+            number_of_values = 1,
+            help = "pool id in hex or bech32 to show the slots and blocks of. Repeat for more pools"
+        )]
+        pool_ids: Vec<PoolId>,
+        #[structopt(
+            long,
+            help = "cncli serve base url, e.g. http://127.0.0.1:8080, to read leaderlogs from that are not saved in the db"
+        )]
+        serve_url: Option<String>,
+        #[structopt(long, default_value = "5", help = "seconds between reading the db again")]
+        refresh_seconds: u64,
+    },
+    Db {
+        #[structopt(subcommand)]
+        cmd: DbCommand,
+    },
+    Slots {
+        #[structopt(subcommand)]
+        cmd: SlotsCommand,
+    },
+    Topology {
+        #[structopt(subcommand)]
+        cmd: TopologyCommand,
+    },
+    /// Show or turn off the anonymous run metrics sent after opting in with --telemetry
+    Telemetry {
+        #[structopt(subcommand)]
+        cmd: TelemetryCommand,
+    },
+    /// Print a shell completion script that also completes saved epochs, pool ids and snapshot names
+    Completions {
+        #[structopt(possible_values = &Shell::variants(), help = "shell to print the completion script for")]
+        shell: Shell,
+    },
+    /// Print the values the completion scripts offer for a flag, one per line
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    Complete {
+        #[structopt(possible_values = &["epochs", "pool-ids", "snapshot-names"])]
+        values: CompletionValues,
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite database file"
+        )]
+        db: PathBuf,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            default_value = "./pooltool.json",
+            help = "pooltool config file"
+        )]
+        config: PathBuf,
+    },
+    /// Development helpers
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    Dev {
+        #[structopt(subcommand)]
+        cmd: DevCommand,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum DbCommand {
+    /// Compare the chain tables of two databases row-by-row
+    Diff {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "sqlite or redb database file to compare against")]
+        other_db: PathBuf,
+    },
+    /// Write a consistent snapshot of the database to a tar.zst archive, also while sync is running
+    Backup {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "tar.zst archive to write, must not exist yet")]
+        archive: PathBuf,
+    },
+    /// Replace the database with a snapshot written by db backup
+    Restore {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "tar.zst archive written by db backup")]
+        archive: PathBuf,
+        #[structopt(long, help = "replace the database if it already exists")]
+        force: bool,
+    },
+    /// Repair a redb database after an unclean shutdown and check its indexes
+    RepairRedb {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "also copy every readable record to this new redb file with rebuilt indexes, must not exist yet"
+        )]
+        salvage_to: Option<PathBuf>,
+    },
+    /// Download a pre-synced database snapshot, verify it and put it in place of the database
+    Bootstrap {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(long, help = "https url of the snapshot, zstd compressed when it ends in .zst")]
+        url: String,
+        #[structopt(long, help = "expected sha256 of the downloaded file in hex")]
+        sha256: String,
+        #[structopt(
+            long,
+            default_value = "129600",
+            help = "check the hash chain and rolling nonce of the blocks in this many slots before the snapshot tip"
+        )]
+        verify_slots: u64,
+        #[structopt(long, help = "replace the database if it already exists")]
+        force: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum DevCommand {
+    /// Print the vrf input, vrf output and leader value of slots to compare against other implementations
+    GenVectors {
+        #[structopt(long, help = "epoch nonce value in lower-case hex")]
+        epoch_nonce: String,
+        #[structopt(long, use_delimiter = true, required = true, help = "comma separated slots")]
+        slots: Vec<u64>,
+        #[structopt(
+            short,
+            long,
+            default_value = "praos",
+            help = "Consensus algorithm - Alonzo and earlier uses tpraos, Babbage uses praos, Conway uses cpraos"
+        )]
+        consensus: String,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "pool's vrf.skey file. Omitted means use the built-in test key"
+        )]
+        pool_vrf_skey: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SlotsCommand {
+    /// Save a leadership schedule computed elsewhere so sendslots can send it
+    Import {
+        #[structopt(
+            parse(from_os_str),
+            short,
+            long,
+            default_value = "./cncli.db",
+            help = "sqlite or redb database file"
+        )]
+        db: PathBuf,
+        #[structopt(parse(from_os_str), long, help = "leadership schedule json file")]
+        file: PathBuf,
+        #[structopt(
+            long,
+            default_value = "cardano-cli",
+            possible_values = &["cardano-cli", "cncli"],
+            help = "cardano-cli - output of cardano-cli query leadership-schedule, cncli - output of cncli leaderlog"
+        )]
+        format: SlotsFormat,
+        #[structopt(long, help = "pool id in hex or bech32. Required for cardano-cli schedules")]
+        pool_id: Option<PoolId>,
+        #[structopt(long, help = "Epoch of the schedule. Required for cardano-cli schedules")]
+        epoch: Option<u64>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum TopologyCommand {
+    /// Measure the handshake latency and availability of relays from this host over several rounds
+    PingMesh {
+        #[structopt(
+            long = "relay",
+            number_of_values = 1,
+            help = "relay as host:port. Repeat for more relays"
+        )]
+        relays: Vec<String>,
+        #[structopt(
+            parse(from_os_str),
+            long,
+            help = "cardano-node topology file to take the relays from, legacy or p2p format"
+        )]
+        topology: Option<PathBuf>,
+        #[structopt(long, default_value = "5", help = "rounds of handshakes with every relay")]
+        count: usize,
+        #[structopt(long, default_value = "1", help = "seconds to wait between rounds")]
+        interval_seconds: u64,
+        #[structopt(long, default_value = "764824073", help = "network magic.")]
+        network_magic: u64,
+        #[structopt(short, long, default_value = "2", help = "connect timeout in seconds")]
+        timeout_seconds: u64,
+        #[structopt(
+            long,
+            default_value = "json",
+            possible_values = &["json", "human"],
+            help = "human prints aligned tables, colored on a terminal unless NO_COLOR is set"
+        )]
+        format: ReportFormat,
     },
 }
 
-pub async fn start(cmd: Command) {
+#[derive(Debug, StructOpt)]
+pub enum TelemetryCommand {
+    /// Show whether telemetry is on and where run metrics are sent to
+    Status,
+    /// Stop sending run metrics until the next --telemetry
+    Off,
+}
+
+impl Command {
+    /// The subcommand name run metrics are reported under
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Ping { .. } => "ping",
+            Command::Validate { .. } => "validate",
+            Command::BlockDetail { .. } => "block-detail",
+            Command::OpcertHistory { .. } => "opcert-history",
+            Command::StakeHistory { .. } => "stake-history",
+            Command::Sync { .. } => "sync",
+            Command::Serve { .. } => "serve",
+            Command::Leaderlog { .. } => "leaderlog",
+            Command::Sendtip { .. } => "sendtip",
+            Command::Sendslots { .. } => "sendslots",
+            Command::Status { .. } => "status",
+            Command::Nonce { .. } => "nonce",
+            Command::Simulate { .. } => "simulate",
+            Command::BattleOdds { .. } => "battle-odds",
+            Command::Challenge { .. } => "challenge",
+            Command::Sign { .. } => "sign",
+            Command::Verify { .. } => "verify",
+            Command::VerifyServer { .. } => "verify-server",
+            Command::InspectKey { .. } => "inspect-key",
+            Command::Convert { .. } => "convert",
+            Command::PoolVrfHash { .. } => "pool-vrf-hash",
+            Command::PoolCheck { .. } => "pool-check",
+            Command::Snapshot { .. } => "snapshot",
+            Command::PoolStake { .. } => "pool-stake",
+            Command::Density { .. } => "density",
+            Command::Crosscheck { .. } => "crosscheck",
+            Command::Overlay { .. } => "overlay",
+            Command::PoolBlocks { .. } => "pool-blocks",
+            Command::AuditEpoch { .. } => "audit-epoch",
+            Command::ExportChain { .. } => "export-chain",
+            Command::LastRuns { .. } => "last-runs",
+            Command::Healthcheck { .. } => "healthcheck",
+            Command::Tui { .. } => "tui",
+            Command::Db { .. } => "db",
+            Command::Slots { .. } => "slots",
+            Command::Topology { .. } => "topology",
+            Command::Telemetry { .. } => "telemetry",
+            Command::Completions { .. } => "completions",
+            Command::Complete { .. } => "complete",
+            Command::Dev { .. } => "dev",
+        }
+    }
+
+    /// The network magic of the commands that talk to a node
+    fn network_magic(&self) -> Option<u64> {
+        match self {
+            Command::Ping { network_magic, .. }
+            | Command::Sync { network_magic, .. }
+            | Command::Leaderlog { network_magic, .. }
+            | Command::Verify { network_magic, .. }
+            | Command::VerifyServer { network_magic, .. }
+            | Command::PoolVrfHash { network_magic, .. }
+            | Command::PoolCheck { network_magic, .. }
+            | Command::Snapshot { network_magic, .. }
+            | Command::PoolStake { network_magic, .. }
+            | Command::Healthcheck { network_magic, .. } => Some(*network_magic),
+            Command::Topology {
+                cmd: TopologyCommand::PingMesh { network_magic, .. },
+            } => Some(*network_magic),
+            _ => None,
+        }
+    }
+}
+
+/// Print the completion script of a cli whose subcommands are Command, such as the cncli binary with its global
+/// options
+pub fn print_completions(app: App<'static, 'static>, shell: Shell) {
+    if let Err(error) = reporter::write_report(&completions::completion_script(app, shell)) {
+        handle_error(error);
+    }
+}
+
+/// Exit with the log file flushed and the run metrics sent, process::exit does not run the destructors that would
+pub(crate) fn exit(code: i32) -> ! {
+    telemetry::finish(code == 0);
+    flush_log_file();
+    std::process::exit(code)
+}
+
+pub async fn start(cmd: Command, out: Out, state_file: Option<PathBuf>, opt_in_telemetry: bool, timeout: Option<u64>) {
+    reporter::set_out(out);
+    runstate::set_state_file(state_file);
+    // the telemetry command itself is never timed, so turning it off is not reported either. Completing runs on
+    // every tab press and is not timed either.
+    if !matches!(
+        cmd,
+        Command::Telemetry { .. } | Command::Completions { .. } | Command::Complete { .. }
+    ) {
+        telemetry::start(cmd.name(), cmd.network_magic(), opt_in_telemetry);
+    }
+    let fail_if_error = matches!(cmd, Command::Leaderlog { fail_if_zero: true, .. });
+    // the tui handles ctrl-c itself and has to restore the terminal when it stops
+    if !matches!(
+        cmd,
+        Command::Tui { .. } | Command::Completions { .. } | Command::Complete { .. }
+    ) {
+        cancel::watch(timeout.map(Duration::from_secs));
+    }
+    let running = run(cmd);
+    tokio::pin!(running);
+    // a cancellation that comes after the command finished is too late to matter, the run succeeded
+    let cancelled = tokio::select! {
+        _ = &mut running => None,
+        cancelled = cancel::wait() => {
+            if cancel::is_cooperative() {
+                // the command stops after its current step and reports what it got done
+                running.await;
+            } else {
+                cancel::report(cancelled);
+            }
+            Some(cancelled)
+        }
+    };
+    if let Some(cancelled) = cancelled {
+        exit(cancelled.exit_code());
+    }
+    // leaderlog reports every failure, from checking its arguments to calculating, before it returns
+    if fail_if_error && reporter::error_reported() {
+        exit(leaderlog::EXIT_LEADERLOG_FAILED);
+    }
+    telemetry::finish(!reporter::error_reported());
+}
+
+async fn run(cmd: Command) {
     match cmd {
         Command::Ping {
             ref host,
             ref port,
             ref network_magic,
             ref timeout_seconds,
+            ref client_protocol,
+            ref proxy,
+            ref socket_path,
+            ref keepalive_count,
+            ref query_versions,
+        } => {
+            let proxy = match proxy::parse_proxy(proxy) {
+                Ok(proxy) => proxy,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
+            let mut targets: Vec<PingTarget> = socket_path.iter().cloned().map(PingTarget::Socket).collect();
+            targets.extend(ping::host_targets(host, *port));
+            if targets.is_empty() {
+                handle_error("--host needs at least one host name");
+                return;
+            }
+            let mut ping_result: Vec<u8> = Vec::new();
+            let exit_code = if *query_versions {
+                ping::versions::query_targets(
+                    &mut ping_result,
+                    &targets,
+                    *network_magic,
+                    *timeout_seconds,
+                    *client_protocol,
+                    &proxy,
+                )
+                .await
+            } else {
+                ping::ping_targets(
+                    &mut ping_result,
+                    &targets,
+                    *network_magic,
+                    *timeout_seconds,
+                    *client_protocol,
+                    *keepalive_count,
+                    &proxy,
+                )
+                .await
+            };
+            if let Err(error) = reporter::write_report(&ping_result) {
+                error!("Could not report ping result: {}", error);
+            }
+            if exit_code != 0 {
+                exit(exit_code);
+            }
+        }
+        Command::Validate {
+            ref db,
+            ref hash,
+            ref busy_timeout,
+            ref read_only,
+            ref bech32,
+            ref format,
         } => {
-            ping::ping(&mut stdout(), host.as_str(), *port, *network_magic, *timeout_seconds).await;
+            validate::validate_block(db, hash.as_str(), *busy_timeout, *read_only, *bech32, format);
+        }
+        Command::BlockDetail {
+            ref db,
+            ref hash,
+            ref slot,
+        } => {
+            if let Err(error) = blockdetail::block_detail(db, hash, slot) {
+                handle_error(error);
+            }
+        }
+        Command::OpcertHistory { ref db, ref pool_id } => {
+            if let Err(error) = opcert::opcert_history(db, pool_id) {
+                handle_error(error);
+            }
         }
-        Command::Validate { ref db, ref hash } => {
-            validate::validate_block(db, hash.as_str());
+        Command::StakeHistory { ref db, ref pool_id } => {
+            if let Err(error) = stakehistory::stake_history(db, pool_id) {
+                handle_error(error);
+            }
         }
         Command::Sync {
             ref db,
             ref host,
             ref port,
+            ref peers,
             ref network_magic,
             ref no_service,
             ref shelley_genesis_hash,
             ref use_redb,
+            ref capture_protocol_updates,
+            ref mirror_db,
+            ref from_slot,
+            ref from_hash,
+            ref trust_nonce,
+            ref filter_pool_ids,
+            ref store_raw_headers,
+            ref progress_json,
+            ref proxy,
+            ref sqlite_wal_autocheckpoint,
+            ref sqlite_synchronous,
+            ref sqlite_cache_size,
+            ref flush_interval_ms,
+            ref wal_checkpoint_interval_secs,
+            ref max_pipeline_depth,
+            ref post_epoch_report,
+            ref report_pool_ids,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref otlp_endpoint,
+            ref record,
+            ref replay,
+        } => {
+            let proxy = match proxy::parse_proxy(proxy) {
+                Ok(proxy) => proxy,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
+            if *wal_checkpoint_interval_secs == Some(0) {
+                handle_error("--wal-checkpoint-interval-secs must be at least 1");
+                return;
+            }
+            let mut sync_service = SyncService::new(host.as_deref().unwrap_or_default(), *port)
+                .db(db)
+                .use_redb(*use_redb)
+                .network_magic(*network_magic)
+                .shelley_genesis_hash(shelley_genesis_hash)
+                .exit_when_tip_reached(*no_service)
+                .capture_protocol_updates(*capture_protocol_updates)
+                .filter_pool_ids(filter_pool_ids.iter().map(PoolId::to_bytes).collect())
+                .store_raw_headers(*store_raw_headers)
+                .progress_json(*progress_json)
+                .proxy(proxy)
+                .sqlite_tuning(SqliteTuning {
+                    wal_autocheckpoint: *sqlite_wal_autocheckpoint,
+                    synchronous: sqlite_synchronous.clone(),
+                    cache_size: *sqlite_cache_size,
+                })
+                .flush_interval(Duration::from_millis(*flush_interval_ms))
+                .checkpoint_interval(wal_checkpoint_interval_secs.map(Duration::from_secs))
+                .max_pipeline_depth(*max_pipeline_depth);
+            for peer in peers {
+                sync_service = sync_service.peer(&peer.host, peer.port);
+            }
+            if let Some(record) = record {
+                sync_service = sync_service.record(record);
+            }
+            if let Some(replay) = replay {
+                sync_service = sync_service.replay(replay);
+            }
+            if let Some(mirror_db) = mirror_db {
+                sync_service = sync_service.mirror_db(mirror_db);
+            }
+            if let (Some(out), Some(byron_genesis), Some(shelley_genesis)) =
+                (post_epoch_report, byron_genesis, shelley_genesis)
+            {
+                match poolblocks::PostEpochReport::new(
+                    byron_genesis,
+                    shelley_genesis,
+                    shelley_transition_epoch,
+                    report_pool_ids,
+                    out.clone(),
+                ) {
+                    Ok(post_epoch_report) => sync_service = sync_service.post_epoch_report(Some(post_epoch_report)),
+                    Err(error) => {
+                        handle_error(error);
+                        return;
+                    }
+                }
+            }
+            if let (Some(from_slot), Some(from_hash), Some(trust_nonce)) = (from_slot, from_hash, trust_nonce) {
+                if !matches!(hex::decode(trust_nonce), Ok(nonce) if nonce.len() == 32) {
+                    handle_error("--trust-nonce must be a 32 byte nonce in hex");
+                    return;
+                }
+                sync_service = sync_service.checkpoint(*from_slot, from_hash.clone(), trust_nonce);
+            }
+            let telemetry = match Telemetry::from_endpoint(otlp_endpoint, "cncli-sync") {
+                Ok(telemetry) => telemetry,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
+            let result = sync_service.telemetry(telemetry.clone()).run().await;
+            // the last spans and counts are sent with a blocking client
+            let _ = tokio::task::spawn_blocking(move || telemetry.shutdown()).await;
+            if let Err(error) = result {
+                handle_error(error);
+                exit(1);
+            }
+        }
+        Command::Serve {
+            ref db,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref listen,
+            ref otlp_endpoint,
         } => {
-            sync::sync(
+            let telemetry = match Telemetry::from_endpoint(otlp_endpoint, "cncli-serve") {
+                Ok(telemetry) => telemetry,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
+            let result = serve::serve(
                 db,
-                host.as_str(),
-                *port,
-                *network_magic,
-                shelley_genesis_hash.as_str(),
-                *no_service,
-                *use_redb,
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                listen,
+                telemetry.clone(),
             )
             .await;
+            let _ = tokio::task::spawn_blocking(move || telemetry.shutdown()).await;
+            if let Err(error) = result {
+                handle_error(error);
+            }
         }
         Command::Leaderlog {
             ref db,
@@ -363,34 +1985,185 @@ pub async fn start(cmd: Command) {
             ref active_stake,
             ref d,
             ref extra_entropy,
+            ref stdin_config,
             ref ledger_set,
             ref pool_id,
             ref pool_vrf_skey,
+            ref pool_vrf_skey_history,
             ref timezone,
             ref consensus,
             ref shelley_transition_epoch,
             ref nonce,
             ref epoch,
+            ref protocol_params_from_db,
+            ref quiet,
+            ref json_compact,
+            ref strict,
+            ref check_perms,
+            ref fail_if_zero,
+            ref busy_timeout,
+            ref read_only,
+            ref backup_url,
+            ref emit_schedule,
+            ref schedule_command,
+            ref schedule_lead_seconds,
+            ref trusted_nonces,
+            ref trusted_nonces_vkey,
+            ref reminder_lead_minutes,
+            ref output_format,
+            ref bech32,
+            ref format,
+            ref archive_dir,
+            ref from_archive,
+            ref pool_vrf_vkey_hash,
+            ref socket_path,
+            ref network_magic,
         } => {
-            if let Err(error) = leaderlog::calculate_leader_logs(
-                db,
+            if *format == ReportFormat::Human
+                && (*json_compact || emit_schedule.is_some() || matches!(output_format, SlotsFormat::CardanoCli))
+            {
+                handle_error("--format human cannot be combined with --json-compact, --emit-schedule or --output-format cardano-cli");
+                return;
+            }
+            if let (true, Some(archive_dir)) = (*from_archive, archive_dir) {
+                match leaderlog::archive::leader_log_from_archive(
+                    archive_dir,
+                    pool_id,
+                    *epoch,
+                    *json_compact,
+                    output_format,
+                    *bech32,
+                    format,
+                ) {
+                    Ok(0) if *fail_if_zero => exit(leaderlog::EXIT_NO_SLOTS_ASSIGNED),
+                    Ok(_) => {}
+                    Err(error) => match format {
+                        ReportFormat::Json => handle_error(error),
+                        ReportFormat::Human => human::report_error(error),
+                    },
+                }
+                return;
+            }
+            let stdin_config = if *stdin_config {
+                match stdinconfig::read::<stdinconfig::LeaderlogConfig>() {
+                    Ok(stdin_config) => stdin_config,
+                    Err(error) => {
+                        handle_error(error);
+                        return;
+                    }
+                }
+            } else {
+                stdinconfig::LeaderlogConfig::default()
+            };
+            let pool_stake = stdin_config.pool_stake.or(*pool_stake);
+            let active_stake = stdin_config.active_stake.or(*active_stake);
+            let nonce = stdin_config.nonce.or_else(|| nonce.clone());
+            let extra_entropy = stdin_config.extra_entropy.or_else(|| extra_entropy.clone());
+            let (Some(byron_genesis), Some(shelley_genesis), Some(pool_stake), Some(active_stake)) =
+                (byron_genesis, shelley_genesis, pool_stake, active_stake)
+            else {
+                handle_error("--byron-genesis, --shelley-genesis, --pool-stake and --active-stake are required");
+                return;
+            };
+            let pool_vrf_skey_history = match pool_vrf_skey_history {
+                Some(pool_vrf_skey_history) => match read_vrf_key_history(pool_vrf_skey_history) {
+                    Ok(pool_vrf_skey_history) => pool_vrf_skey_history,
+                    Err(error) => {
+                        handle_error(error);
+                        return;
+                    }
+                },
+                None => vec![],
+            };
+            let key_paths: Vec<&Path> = pool_vrf_skey_history
+                .iter()
+                .map(|vrf_key_version| vrf_key_version.skey.as_path())
+                .chain(pool_vrf_skey.as_deref())
+                .collect();
+            if key_paths.is_empty() {
+                handle_error("--pool-vrf-skey or --pool-vrf-skey-history is required");
+                return;
+            }
+            if !leaderlog::enforce_keys_permissions(&key_paths, *strict, *check_perms) {
+                return;
+            }
+            let pool_vrf_skey = pool_vrf_skey.clone().unwrap_or_default();
+            let schedule_export = emit_schedule.as_ref().map(|format| ScheduleExport {
+                format: format.clone(),
+                command: schedule_command.clone().unwrap_or_default(),
+                lead_seconds: *schedule_lead_seconds,
+            });
+            let trusted_nonces = match trusted_nonces {
+                Some(trusted_nonces) => match read_trusted_nonces(trusted_nonces, trusted_nonces_vkey) {
+                    Ok(trusted_nonces) => trusted_nonces,
+                    Err(error) => {
+                        handle_error(error);
+                        return;
+                    }
+                },
+                None => vec![],
+            };
+            let pool_vrf_vkey_hash = match (pool_vrf_vkey_hash, socket_path) {
+                (Some(pool_vrf_vkey_hash), _) => Some(pool_vrf_vkey_hash.clone()),
+                (None, Some(socket_path)) => {
+                    match snapshot::get_pool_vrf_hash(
+                        socket_path,
+                        *network_magic,
+                        pool_id,
+                        &snapshot::QueryTimeouts::default(),
+                    )
+                    .await
+                    {
+                        Ok(pool_vrf_vkey_hash) => Some(pool_vrf_vkey_hash),
+                        Err(error) => {
+                            match format {
+                                ReportFormat::Json => handle_error(error),
+                                ReportFormat::Human => human::report_error(error),
+                            }
+                            return;
+                        }
+                    }
+                }
+                (None, None) => None,
+            };
+            match leaderlog::calculate_leader_logs(&LeaderlogOptions {
+                db_path: db,
                 byron_genesis,
                 shelley_genesis,
                 pool_stake,
                 active_stake,
-                d,
-                extra_entropy,
+                d: *d,
+                extra_entropy: &extra_entropy,
                 ledger_set,
                 pool_id,
-                pool_vrf_skey,
+                pool_vrf_skey_path: &pool_vrf_skey,
                 timezone,
-                false,
                 consensus,
                 shelley_transition_epoch,
-                nonce,
+                nonce: &nonce,
                 epoch,
-            ) {
-                handle_error(error);
+                protocol_params_from_db: *protocol_params_from_db,
+                quiet: *quiet,
+                json_compact: *json_compact,
+                busy_timeout_ms: *busy_timeout,
+                read_only: *read_only,
+                backup_url,
+                schedule_export: &schedule_export,
+                trusted_nonces: &trusted_nonces,
+                reminder_lead_minutes,
+                output_format,
+                bech32: *bech32,
+                format,
+                archive_dir,
+                pool_vrf_vkey_hash: &pool_vrf_vkey_hash,
+                pool_vrf_skey_history: &pool_vrf_skey_history,
+            }) {
+                Ok(0) if *fail_if_zero => exit(leaderlog::EXIT_NO_SLOTS_ASSIGNED),
+                Ok(_) => {}
+                Err(error) => match format {
+                    ReportFormat::Json => handle_error(error),
+                    ReportFormat::Human => human::report_error(error),
+                },
             }
         }
         Command::Nonce {
@@ -402,24 +2175,107 @@ pub async fn start(cmd: Command) {
             ref shelley_transition_epoch,
             ref consensus,
             ref epoch,
+            ref trace_from_slot,
+            ref to_slot,
+            ref trusted_nonces,
+            ref trusted_nonces_vkey,
+            ref predict,
         } => {
-            if let Err(error) = leaderlog::calculate_leader_logs(
+            if *predict {
+                if let Err(error) = leaderlog::predict_nonce(
+                    db,
+                    byron_genesis,
+                    shelley_genesis,
+                    shelley_transition_epoch,
+                    consensus,
+                    extra_entropy,
+                ) {
+                    handle_error(error);
+                }
+                return;
+            }
+            if let (Some(trace_from_slot), Some(to_slot)) = (trace_from_slot, to_slot) {
+                if let Err(error) = leaderlog::trace_nonce(db, *trace_from_slot, *to_slot) {
+                    handle_error(error);
+                }
+                return;
+            }
+            let trusted_nonces = match trusted_nonces {
+                Some(trusted_nonces) => match read_trusted_nonces(trusted_nonces, trusted_nonces_vkey) {
+                    Ok(trusted_nonces) => trusted_nonces,
+                    Err(error) => {
+                        handle_error(error);
+                        return;
+                    }
+                },
+                None => vec![],
+            };
+            if let Err(error) = leaderlog::calculate_nonce(
                 db,
                 byron_genesis,
                 shelley_genesis,
-                &0u64,
-                &0u64,
-                &0f64,
                 extra_entropy,
                 ledger_set,
-                "nonce",
-                &PathBuf::new(),
-                "America/Los_Angeles",
-                true,
                 consensus,
                 shelley_transition_epoch,
-                &None,
                 epoch,
+                &trusted_nonces,
+            ) {
+                handle_error(error);
+            }
+        }
+        Command::Simulate {
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref epoch,
+            ref epoch_nonce,
+            ref pool_vrf_skey,
+            ref sigma_range,
+            ref d,
+            ref consensus,
+        } => {
+            if !leaderlog::enforce_key_permissions(pool_vrf_skey, false, false) {
+                return;
+            }
+            if let Err(error) = simulate::simulate(
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                *epoch,
+                epoch_nonce,
+                pool_vrf_skey,
+                sigma_range,
+                *d,
+                consensus,
+            ) {
+                handle_error(error);
+            }
+        }
+        Command::BattleOdds {
+            ref shelley_genesis,
+            slot,
+            ref epoch_nonce,
+            ref pool_vrf_skey,
+            ref consensus,
+            ref pool_id,
+            ref sigma,
+            ref competitors,
+            ref pool_stake,
+        } => {
+            if !leaderlog::enforce_key_permissions(pool_vrf_skey, false, false) {
+                return;
+            }
+            if let Err(error) = battle::battle_odds_for_slot(
+                shelley_genesis,
+                slot,
+                epoch_nonce,
+                pool_vrf_skey,
+                consensus,
+                pool_id,
+                sigma,
+                competitors,
+                pool_stake,
             ) {
                 handle_error(error);
             }
@@ -427,8 +2283,17 @@ pub async fn start(cmd: Command) {
         Command::Sendtip {
             ref config,
             ref cardano_node,
+            ref stdin_config,
+            ref proxy,
         } => {
-            if !config.exists() {
+            let proxy = match proxy::parse_proxy(proxy) {
+                Ok(proxy) => proxy,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
+            if !*stdin_config && !config.exists() {
                 handle_error("config not found!");
                 return;
             }
@@ -437,19 +2302,32 @@ pub async fn start(cmd: Command) {
                 return;
             }
 
-            let pooltool_config: PooltoolConfig = pooltool::get_pooltool_config(config);
+            let pooltool_config = if *stdin_config {
+                pooltool::get_pooltool_config_from_stdin()
+            } else {
+                pooltool::get_pooltool_config(config)
+            };
+            let pooltool_config: PooltoolConfig = match pooltool_config {
+                Ok(pooltool_config) => pooltool_config,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
             let mut handles: Vec<JoinHandle<_>> = vec![];
             for pool in pooltool_config.pools.into_iter() {
                 let api_key = pooltool_config.api_key.clone();
                 let cardano_node_path = cardano_node.clone();
+                let proxy = proxy.clone();
                 handles.push(thread::spawn(move || {
                     tokio::runtime::Runtime::new().unwrap().block_on(sync::sendtip(
                         pool.name,
                         pool.pool_id,
-                        pool.host,
-                        pool.port,
+                        pool.relays,
+                        pool.quorum,
                         api_key,
                         &cardano_node_path,
+                        &proxy,
                     ));
                 }));
             }
@@ -460,17 +2338,30 @@ pub async fn start(cmd: Command) {
         }
         Command::Sendslots {
             ref config,
+            ref stdin_config,
             ref db,
             ref byron_genesis,
             ref shelley_genesis,
             ref shelley_transition_epoch,
             ref override_time,
+            ref dry_run,
         } => {
-            if !config.exists() {
+            if !*stdin_config && !config.exists() {
                 handle_error("config not found!");
                 return;
             }
-            let pooltool_config: PooltoolConfig = pooltool::get_pooltool_config(config);
+            let pooltool_config = if *stdin_config {
+                pooltool::get_pooltool_config_from_stdin()
+            } else {
+                pooltool::get_pooltool_config(config)
+            };
+            let pooltool_config: PooltoolConfig = match pooltool_config {
+                Ok(pooltool_config) => pooltool_config,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
             leaderlog::send_slots(
                 db,
                 byron_genesis,
@@ -478,6 +2369,7 @@ pub async fn start(cmd: Command) {
                 pooltool_config,
                 shelley_transition_epoch,
                 override_time,
+                *dry_run,
             );
         }
         Command::Status {
@@ -485,8 +2377,19 @@ pub async fn start(cmd: Command) {
             ref byron_genesis,
             ref shelley_genesis,
             ref shelley_transition_epoch,
+            ref busy_timeout,
+            ref read_only,
+            ref format,
         } => {
-            leaderlog::status(db, byron_genesis, shelley_genesis, shelley_transition_epoch);
+            leaderlog::status(
+                db,
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                *busy_timeout,
+                *read_only,
+                format,
+            );
         }
         Command::Challenge { ref domain } => {
             sign::create_challenge(domain);
@@ -495,40 +2398,155 @@ pub async fn start(cmd: Command) {
             ref pool_vrf_skey,
             ref domain,
             ref nonce,
+            ref strict,
+            ref check_perms,
         } => {
             if !pool_vrf_skey.exists() {
                 handle_error("vrf.skey not found!");
                 return;
             }
+            if !leaderlog::enforce_key_permissions(pool_vrf_skey, *strict, *check_perms) {
+                return;
+            }
             sign::sign_challenge(pool_vrf_skey, domain, nonce);
         }
         Command::Verify {
             ref pool_vrf_vkey,
             ref pool_vrf_vkey_hash,
+            ref socket_path,
+            ref network_magic,
+            ref pool_id,
             ref domain,
             ref nonce,
             ref signature,
         } => {
-            sign::verify_challenge(pool_vrf_vkey, pool_vrf_vkey_hash, domain, nonce, signature);
+            let pool_vrf_vkey_hash = match (pool_vrf_vkey_hash, socket_path, pool_id) {
+                (Some(pool_vrf_vkey_hash), _, _) => pool_vrf_vkey_hash.clone(),
+                (None, Some(socket_path), Some(pool_id)) => {
+                    match snapshot::get_pool_vrf_hash(
+                        socket_path,
+                        *network_magic,
+                        pool_id,
+                        &snapshot::QueryTimeouts::default(),
+                    )
+                    .await
+                    {
+                        Ok(pool_vrf_vkey_hash) => pool_vrf_vkey_hash,
+                        Err(error) => {
+                            handle_error(error);
+                            return;
+                        }
+                    }
+                }
+                _ => {
+                    handle_error("Either --pool-vrf-vkey-hash or both --socket-path and --pool-id are required!");
+                    return;
+                }
+            };
+            sign::verify_challenge(pool_vrf_vkey, &pool_vrf_vkey_hash, domain, nonce, signature);
+        }
+        Command::VerifyServer {
+            ref domain,
+            ref host,
+            ref port,
+            ref challenge_ttl_secs,
+            ref socket_path,
+            ref network_magic,
+        } => {
+            if *challenge_ttl_secs == 0 {
+                handle_error("--challenge-ttl-secs must be greater than 0");
+                return;
+            }
+            if let Err(error) =
+                verifyserver::verify_server(host, *port, domain, *challenge_ttl_secs, socket_path, *network_magic).await
+            {
+                handle_error(error);
+            }
+        }
+        Command::InspectKey { ref file } => {
+            if let Err(error) = inspectkey::inspect_key(file) {
+                handle_error(error);
+            }
+        }
+        Command::Convert {
+            ref input,
+            ref hrp,
+            ref network_id,
+        } => {
+            if let Err(error) = convert::convert(input, hrp, *network_id) {
+                handle_error(error);
+            }
+        }
+        Command::PoolVrfHash {
+            ref socket_path,
+            ref network_magic,
+            ref pool_id,
+        } => {
+            if let Err(error) = snapshot::pool_vrf_hash(socket_path, *network_magic, pool_id).await {
+                handle_error(error);
+            }
         }
+        Command::PoolCheck {
+            ref socket_path,
+            ref network_magic,
+            ref config,
+        } => match poolcheck::pool_check(socket_path, *network_magic, config).await {
+            Ok(passed) => {
+                if !passed {
+                    exit(1);
+                }
+            }
+            Err(error) => handle_error(error),
+        },
         Command::Snapshot {
             ref socket_path,
             ref network_magic,
             ref name,
+            ref epoch,
+            ref manifest,
             ref network_id,
             ref stake_prefix,
             ref output_file,
+            ref output,
+            ref min_lovelace,
+            ref address,
+            ref connect_timeout_seconds,
+            ref query_timeout_seconds,
         } => {
-            if let Err(error) = snapshot::dump(
-                socket_path,
-                *network_magic,
-                name.as_str(),
-                *network_id,
-                stake_prefix.as_str(),
-                output_file.as_str(),
-            )
-            .await
-            {
+            let filter = match snapshot::SnapshotFilter::new(*min_lovelace, address) {
+                Ok(filter) => filter,
+                Err(error) => {
+                    handle_error(error);
+                    return;
+                }
+            };
+            let kind = snapshot::DumpKind::Stake {
+                network_id: *network_id,
+                stake_prefix: stake_prefix.as_str(),
+                filter: &filter,
+            };
+            let output = output
+                .clone()
+                .unwrap_or_else(|| SnapshotOutput::Csv(PathBuf::from(output_file)));
+            let timeouts = snapshot::QueryTimeouts::new(*connect_timeout_seconds, *query_timeout_seconds);
+            let result = match manifest {
+                Some(manifest) => {
+                    snapshot::manifest::run(manifest, socket_path, *network_magic, &output, &kind, &timeouts).await
+                }
+                None => {
+                    snapshot::dump(
+                        socket_path,
+                        *network_magic,
+                        name.as_str(),
+                        *epoch,
+                        &output,
+                        &kind,
+                        &timeouts,
+                    )
+                    .await
+                }
+            };
+            if let Err(error) = result {
                 handle_error(error);
             }
         }
@@ -536,18 +2554,407 @@ pub async fn start(cmd: Command) {
             ref socket_path,
             ref network_magic,
             ref name,
-            ref network_id,
+            ref epoch,
+            ref manifest,
+            network_id: _,
             ref output_file,
+            ref output,
+            ref bech32,
+            ref connect_timeout_seconds,
+            ref query_timeout_seconds,
+        } => {
+            let kind = snapshot::DumpKind::PoolStake { bech32: *bech32 };
+            let output = output
+                .clone()
+                .unwrap_or_else(|| SnapshotOutput::Csv(PathBuf::from(output_file)));
+            let timeouts = snapshot::QueryTimeouts::new(*connect_timeout_seconds, *query_timeout_seconds);
+            let result = match manifest {
+                Some(manifest) => {
+                    snapshot::manifest::run(manifest, socket_path, *network_magic, &output, &kind, &timeouts).await
+                }
+                None => {
+                    snapshot::dump(
+                        socket_path,
+                        *network_magic,
+                        name.as_str(),
+                        *epoch,
+                        &output,
+                        &kind,
+                        &timeouts,
+                    )
+                    .await
+                }
+            };
+            if let Err(error) = result {
+                handle_error(error);
+            }
+        }
+        Command::Density {
+            ref db,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref epoch,
+            ref min_gap_slots,
+        } => {
+            if let Err(error) = density::calculate_density(
+                db,
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                *epoch,
+                *min_gap_slots,
+            ) {
+                handle_error(error);
+            }
+        }
+        Command::Crosscheck {
+            ref db,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref epoch,
+            ref source,
+            ref samples,
+            ref api_url,
+            ref api_key,
+        } => {
+            if *samples == 0 {
+                handle_error("--samples must be greater than 0");
+                return;
+            }
+            match crosscheck::crosscheck(
+                db,
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                *epoch,
+                *source,
+                *samples,
+                api_url,
+                api_key,
+            ) {
+                Ok(passed) => {
+                    if !passed {
+                        exit(1);
+                    }
+                }
+                Err(error) => handle_error(error),
+            }
+        }
+        Command::Overlay {
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref epoch,
+            ref d,
+            ref csv,
+        } => {
+            if let Err(error) = overlay::overlay_schedule(
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                *epoch,
+                *d,
+                *csv,
+            ) {
+                handle_error(error);
+            }
+        }
+        Command::PoolBlocks {
+            ref db,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref pool_id,
+            ref epoch,
+            ref csv,
+            ref bech32,
+        } => {
+            if let Err(error) = poolblocks::pool_blocks(
+                db,
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                pool_id,
+                *epoch,
+                *csv,
+                *bech32,
+            ) {
+                handle_error(error);
+            }
+        }
+        Command::AuditEpoch {
+            ref db,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref pool_id,
+            ref pool_vrf_skey,
+            ref epoch,
+            ref pool_stake,
+            ref active_stake,
+            ref consensus,
+            ref strict,
+        } => {
+            if !pool_vrf_skey.exists() {
+                handle_error("vrf.skey not found!");
+                return;
+            }
+            if !leaderlog::enforce_key_permissions(pool_vrf_skey, *strict, false) {
+                return;
+            }
+            match audit::audit_epoch(
+                db,
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                pool_id,
+                pool_vrf_skey,
+                *epoch,
+                pool_stake,
+                active_stake,
+                consensus,
+            ) {
+                Ok(consistent) => {
+                    if !consistent {
+                        exit(1);
+                    }
+                }
+                Err(error) => handle_error(error),
+            }
+        }
+        Command::ExportChain {
+            ref db,
+            ref from_slot,
+            ref to_slot,
+            ref fields,
+            ref format,
+            ref include_orphans,
+        } => {
+            if let Err(error) = exportchain::export_chain(db, *from_slot, to_slot, fields, format, *include_orphans) {
+                handle_error(error);
+            }
+        }
+        Command::LastRuns {
+            ref operation,
+            ref pool_id,
+            ref epoch,
+        } => {
+            if let Err(error) = runstate::last_runs(operation, pool_id, epoch) {
+                handle_error(error);
+            }
+        }
+        Command::Healthcheck {
+            ref db,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref max_tip_age_seconds,
+            ref host,
+            ref port,
+            ref network_magic,
+            ref timeout_seconds,
+            ref max_keepalive_rtt_ms,
+            ref max_keepalive_age_seconds,
         } => {
-            if let Err(error) = snapshot::pool_stake_dump(
-                socket_path,
+            let healthy = healthcheck::healthcheck(
+                db,
+                byron_genesis,
+                shelley_genesis,
+                shelley_transition_epoch,
+                *max_tip_age_seconds,
+                host,
+                *port,
                 *network_magic,
-                name.as_str(),
-                *network_id,
-                output_file.as_str(),
+                *timeout_seconds,
+                *max_keepalive_rtt_ms,
+                *max_keepalive_age_seconds,
             )
-            .await
-            {
+            .await;
+            exit(if healthy { 0 } else { 1 });
+        }
+        Command::Tui {
+            ref db,
+            ref byron_genesis,
+            ref shelley_genesis,
+            ref shelley_transition_epoch,
+            ref pool_ids,
+            ref serve_url,
+            ref refresh_seconds,
+        } => {
+            let (db, byron_genesis, shelley_genesis, shelley_transition_epoch, pool_ids, serve_url, refresh_seconds) = (
+                db.clone(),
+                byron_genesis.clone(),
+                shelley_genesis.clone(),
+                *shelley_transition_epoch,
+                pool_ids.clone(),
+                serve_url.clone(),
+                *refresh_seconds,
+            );
+            // the dashboard blocks on the terminal and the blocking http client, keep both off the runtime
+            let result = tokio::task::spawn_blocking(move || {
+                tui::tui(
+                    &db,
+                    &byron_genesis,
+                    &shelley_genesis,
+                    &shelley_transition_epoch,
+                    &pool_ids,
+                    &serve_url,
+                    refresh_seconds,
+                )
+            })
+            .await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => handle_error(error),
+                Err(error) => handle_error(error),
+            }
+        }
+        Command::Db { ref cmd } => match cmd {
+            DbCommand::Diff { ref db, ref other_db } => {
+                if let Err(error) = db::diff(db, other_db) {
+                    handle_error(error);
+                }
+            }
+            DbCommand::Backup { ref db, ref archive } => {
+                if let Err(error) = db::backup(db, archive) {
+                    handle_error(error);
+                }
+            }
+            DbCommand::Restore {
+                ref db,
+                ref archive,
+                force,
+            } => {
+                if let Err(error) = db::restore(db, archive, *force) {
+                    handle_error(error);
+                }
+            }
+            DbCommand::RepairRedb { ref db, ref salvage_to } => {
+                if let Err(error) = db::repair_redb(db, salvage_to) {
+                    handle_error(error);
+                }
+            }
+            DbCommand::Bootstrap {
+                ref db,
+                ref url,
+                ref sha256,
+                verify_slots,
+                force,
+            } => {
+                let (db, url, sha256, verify_slots, force) =
+                    (db.clone(), url.clone(), sha256.clone(), *verify_slots, *force);
+                cancel::set_cooperative();
+                // the download uses the blocking http client, keep it off the runtime
+                match tokio::task::spawn_blocking(move || db::bootstrap(&db, &url, &sha256, verify_slots, force)).await
+                {
+                    Ok(Ok(())) => {}
+                    Ok(Err(error)) => handle_error(error),
+                    Err(error) => handle_error(error),
+                }
+            }
+        },
+        Command::Slots { ref cmd } => match cmd {
+            SlotsCommand::Import {
+                ref db,
+                ref file,
+                ref format,
+                ref pool_id,
+                ref epoch,
+            } => {
+                if let Err(error) = slots::import_slots(db, file, format, pool_id, epoch) {
+                    handle_error(error);
+                }
+            }
+        },
+        Command::Topology { ref cmd } => match cmd {
+            TopologyCommand::PingMesh {
+                ref relays,
+                ref topology,
+                ref count,
+                ref interval_seconds,
+                ref network_magic,
+                ref timeout_seconds,
+                ref format,
+            } => {
+                let mut relay_addresses = match relays
+                    .iter()
+                    .map(|relay| topology::RelayAddress::parse(relay))
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(relay_addresses) => relay_addresses,
+                    Err(error) => {
+                        handle_error(error);
+                        return;
+                    }
+                };
+                if let Some(topology_file) = topology {
+                    match topology::read_topology(topology_file) {
+                        Ok(topology_relays) => relay_addresses.extend(topology_relays),
+                        Err(error) => {
+                            handle_error(error);
+                            return;
+                        }
+                    }
+                }
+                if *count == 0 {
+                    handle_error("--count must be at least 1");
+                    return;
+                }
+                if let Err(error) = topology::ping_mesh(
+                    &relay_addresses,
+                    *count,
+                    Duration::from_secs(*interval_seconds),
+                    *network_magic,
+                    *timeout_seconds,
+                    format,
+                )
+                .await
+                {
+                    match format {
+                        ReportFormat::Json => handle_error(error),
+                        ReportFormat::Human => human::report_error(error),
+                    }
+                }
+            }
+        },
+        Command::Dev { ref cmd } => match cmd {
+            DevCommand::GenVectors {
+                ref epoch_nonce,
+                ref slots,
+                ref consensus,
+                ref pool_vrf_skey,
+            } => {
+                if let Err(error) = vectors::gen_vectors(epoch_nonce, slots, consensus, pool_vrf_skey) {
+                    handle_error(error);
+                }
+            }
+        },
+        Command::Completions { shell } => {
+            // without the global options of the binary, which passes its whole cli to print_completions instead
+            print_completions(Command::clap().name("cncli"), shell);
+        }
+        Command::Complete {
+            ref values,
+            ref db,
+            ref config,
+        } => {
+            let mut body: Vec<u8> = Vec::new();
+            for value in completions::completion_values(values, db, config) {
+                body.extend_from_slice(value.as_bytes());
+                body.push(b'\n');
+            }
+            if let Err(error) = reporter::write_report(&body) {
+                error!("Could not report completion values: {}", error);
+            }
+        }
+        Command::Telemetry { ref cmd } => {
+            let result = match cmd {
+                TelemetryCommand::Status => telemetry::status(),
+                TelemetryCommand::Off => telemetry::off(),
+            };
+            if let Err(error) = result {
                 handle_error(error);
             }
         }
@@ -1,11 +1,12 @@
 extern crate chrono_tz;
 
 use std::env::{set_var, var};
+use std::path::PathBuf;
 use std::{panic, process};
 
 use structopt::StructOpt;
 
-use cncli::Command;
+use cncli::{flush_log_file, init_log_file, Command, LogRotation, LogSize, Out};
 
 pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
@@ -27,6 +28,63 @@ pub mod built_info {
 struct Cli {
     #[structopt(subcommand)]
     cmd: Command,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "-",
+        help = "where to write the result: - for stdout, file://<path>, or an http(s):// url to POST it to"
+    )]
+    out: Out,
+    #[structopt(
+        long,
+        global = true,
+        env = "CNCLI_STATE_FILE",
+        parse(from_os_str),
+        help = "json file to record the last successful sync, leaderlog, sendslots and sendtip runs in"
+    )]
+    state_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        global = true,
+        env = "CNCLI_LOG_FILE",
+        parse(from_os_str),
+        help = "write log messages to this file instead of stderr"
+    )]
+    log_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        global = true,
+        requires = "log-file",
+        help = "rotate the log file before it grows past this size, e.g. 100M or 1G"
+    )]
+    log_rotate_size: Option<LogSize>,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "never",
+        possible_values = &["never", "hourly", "daily"],
+        help = "also rotate the log file when a new hour or day (UTC) starts"
+    )]
+    log_rotate_interval: LogRotation,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "7",
+        help = "how many rotated log files to keep next to the log file, the oldest is deleted"
+    )]
+    log_keep: usize,
+    #[structopt(
+        long,
+        global = true,
+        help = "opt in to sending anonymous run metrics: command, duration, success, network and version, never pool ids"
+    )]
+    telemetry: bool,
+    #[structopt(
+        long,
+        global = true,
+        help = "seconds after which to cancel the command like ctrl-c does, reporting what it got done"
+    )]
+    timeout: Option<u64>,
 }
 
 #[tokio::main]
@@ -50,11 +108,22 @@ async fn main() {
         Err(_) => tracing::Level::INFO,
     };
 
-    tracing::subscriber::set_global_default(
-        tracing_subscriber::FmtSubscriber::builder()
-            .with_max_level(tracing_filter)
-            .finish(),
-    )
+    let args = Cli::from_args();
+    let subscriber = tracing_subscriber::FmtSubscriber::builder().with_max_level(tracing_filter);
+    match args.log_file {
+        Some(ref log_file) => {
+            match init_log_file(log_file, args.log_rotate_size, args.log_rotate_interval, args.log_keep) {
+                Ok(writer) => {
+                    tracing::subscriber::set_global_default(subscriber.with_ansi(false).with_writer(writer).finish())
+                }
+                Err(error) => {
+                    eprintln!("{error}");
+                    process::exit(1);
+                }
+            }
+        }
+        None => tracing::subscriber::set_global_default(subscriber.finish()),
+    }
     .unwrap();
 
     // take_hook() returns the default hook in case when a custom one is not set
@@ -62,9 +131,16 @@ async fn main() {
     panic::set_hook(Box::new(move |panic_info| {
         // invoke the default handler and exit the process
         orig_hook(panic_info);
+        flush_log_file();
         process::exit(1);
     }));
 
-    let args = Cli::from_args();
-    cncli::start(args.cmd).await;
+    // the completion script also completes the global options, which only the cli here knows about
+    if let Command::Completions { shell } = args.cmd {
+        cncli::print_completions(Cli::clap(), shell);
+        return;
+    }
+
+    cncli::start(args.cmd, args.out, args.state_file, args.telemetry, args.timeout).await;
+    flush_log_file();
 }
@@ -0,0 +1,380 @@
+use std::path::Path;
+
+use chrono::{SecondsFormat, Utc};
+use pallas_crypto::hash::Hasher;
+use pallas_math::math::FixedDecimal;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::BlockDetail;
+use crate::nodeclient::leaderlog;
+use crate::nodeclient::leaderlog::{
+    epoch_nonce_from_db, get_first_slot_of_epoch, get_first_slot_of_epoch_number, get_protocol_params_from_db,
+    guess_shelley_transition_epoch, leader_slots, read_byron_genesis, read_shelley_genesis, read_vrf_key,
+    slots_and_hash,
+};
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::reporter;
+use crate::nodeclient::sign;
+use crate::nodeclient::vrfkey::vrf_vkey_of_skey;
+use crate::APP_USER_AGENT;
+
+// the audit hash is signed as the nonce of a CIP-0022 challenge for this domain, so `cncli verify` can check it
+const AUDIT_DOMAIN: &str = "cncli-audit";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] leaderlog::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("FromHex error: {0}")]
+    FromHex(#[from] hex::FromHexError),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] reporter::Error),
+
+    #[error("Audit error: {0}")]
+    Audit(String),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditReport {
+    status: String,
+    audit: EpochAudit,
+    /// blake2b-256 of the audit as compact JSON with its keys sorted
+    audit_hash: String,
+    signature: AuditSignature,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EpochAudit {
+    network_magic: u32,
+    pool_id: String,
+    epoch: u64,
+    first_slot: u64,
+    last_slot: u64,
+    consensus: String,
+    decentralization: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extra_entropy: Option<String>,
+    epoch_nonce: String,
+    pool_stake: u64,
+    active_stake: u64,
+    scheduled_slots: Vec<u64>,
+    schedule_hash: String,
+    /// The hash of the schedule leaderlog saved for the epoch, None when it was not saved
+    saved_schedule_hash: Option<String>,
+    saved_schedule_matches: Option<bool>,
+    minted_blocks: Vec<AuditBlock>,
+    /// Scheduled slots without a block from the pool on chain. This includes lost slot battles.
+    missed_slots: Vec<u64>,
+    /// Slots the pool has a block on chain for that are not in the recalculated schedule
+    unscheduled_slots: Vec<u64>,
+    /// Whether the minted blocks carry the vrf vkey the audit is signed with, None without blocks
+    vrf_vkey_matches_blocks: Option<bool>,
+    consistent: bool,
+    generated_at: String,
+    generated_by: String,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct AuditBlock {
+    block_number: u64,
+    slot_number: u64,
+    hash: String,
+    orphaned: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditSignature {
+    domain: String,
+    pool_vrf_vkey: String,
+    pool_vrf_vkey_hash: String,
+    signature: String,
+}
+
+/// Recalculate the nonce and leader schedule of a finished epoch from the db, compare them with the schedule
+/// leaderlog saved and the blocks the pool minted, and report it all signed with the pool's vrf key. Returns whether
+/// everything agrees.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn audit_epoch(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    pool_id: &PoolId,
+    pool_vrf_skey: &Path,
+    epoch: u64,
+    pool_stake: &Option<u64>,
+    active_stake: &Option<u64>,
+    consensus: &str,
+) -> Result<bool, Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let byron = read_byron_genesis(byron_genesis)?;
+    debug!("{:?}", byron);
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    debug!("{:?}", shelley);
+
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let (tip_epoch, _) = get_first_slot_of_epoch(&byron, &shelley, tip_slot_number, shelley_transition_epoch);
+    if epoch <= shelley_transition_epoch || epoch >= tip_epoch {
+        return Err(Error::Audit(format!(
+            "Invalid Epoch: --epoch {epoch} must be a finished epoch after the shelley transition epoch {shelley_transition_epoch}, db tip epoch: {tip_epoch}"
+        )));
+    }
+
+    let vrf_skey = read_vrf_key(pool_vrf_skey)?;
+    let pool_vrf_vkey = match vrf_vkey_of_skey(&vrf_skey.key) {
+        Some(pool_vrf_vkey) if vrf_skey.key_type == "VrfSigningKey_PraosVRF" => hex::encode(pool_vrf_vkey),
+        _ => {
+            return Err(Error::Audit(
+                "Pool VRF Skey must be of type: VrfSigningKey_PraosVRF".to_string(),
+            ))
+        }
+    };
+
+    let (pool_stake, active_stake) = match (pool_stake, active_stake) {
+        (Some(pool_stake), Some(active_stake)) => (*pool_stake, *active_stake),
+        _ => {
+            let stake_history = block_store
+                .get_stake_history(pool_id)?
+                .into_iter()
+                .find(|stake_history| stake_history.epoch == epoch)
+                .ok_or_else(|| {
+                    Error::Audit(format!(
+                        "No stake saved by leaderlog for epoch {epoch}, pass --pool-stake and --active-stake"
+                    ))
+                })?;
+            (stake_history.pool_stake, stake_history.active_stake)
+        }
+    };
+    if pool_stake == 0 || active_stake == 0 {
+        return Err(Error::Audit("Pool and active stake must not be 0".to_string()));
+    }
+
+    let first_slot = get_first_slot_of_epoch_number(&byron, &shelley, epoch, shelley_transition_epoch);
+    let last_slot = first_slot + shelley.epoch_length - 1;
+    debug!("first_slot: {}, last_slot: {}", first_slot, last_slot);
+
+    // d and extra entropy only ever applied under tpraos, where the update proposals synced with
+    // --capture-protocol-updates hold them. They are 0 and neutral since.
+    let (decentralization, extra_entropy) = if consensus == "tpraos" {
+        get_protocol_params_from_db(block_store.as_mut(), &byron, &shelley, shelley_transition_epoch, epoch)?
+    } else {
+        (0.0, None)
+    };
+    debug!(
+        "decentralization: {}, extra_entropy: {:?}",
+        decentralization, &extra_entropy
+    );
+
+    let epoch_nonce = epoch_nonce_from_db(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        first_slot,
+        consensus,
+        &extra_entropy,
+    )?;
+    debug!("epoch_nonce: {}", hex::encode(epoch_nonce));
+
+    let sigma = FixedDecimal::from(pool_stake) / FixedDecimal::from(active_stake);
+    let scheduled_slots: Vec<u64> = leader_slots(
+        &shelley,
+        first_slot,
+        decentralization,
+        &sigma,
+        epoch_nonce.as_slice(),
+        &vrf_skey.key,
+        &[static_consensus(consensus)],
+    )?
+    .into_iter()
+    .map(|(slot, _)| slot)
+    .collect();
+    let (_, schedule_hash) = slots_and_hash(scheduled_slots.iter().copied());
+
+    let saved_slots: Option<Vec<u64>> = match block_store.get_previous_slots(epoch, pool_id)? {
+        Some(slots) => Some(serde_json::from_str(&slots)?),
+        None => None,
+    };
+
+    let block_details = block_store.get_pool_block_details_in_slot_range(pool_id, first_slot, last_slot)?;
+    let vrf_vkey_matches_blocks = vrf_vkey_matches_blocks(&block_details, &pool_vrf_vkey);
+    let minted_blocks: Vec<AuditBlock> = block_details
+        .into_iter()
+        .map(|block_detail| AuditBlock {
+            block_number: block_detail.block_number,
+            slot_number: block_detail.slot_number,
+            hash: block_detail.hash,
+            orphaned: block_detail.orphaned,
+        })
+        .collect();
+    let (missed_slots, unscheduled_slots) = compare_blocks(&scheduled_slots, &minted_blocks);
+
+    let saved_schedule_matches = saved_slots.as_ref().map(|saved_slots| *saved_slots == scheduled_slots);
+    let consistent =
+        saved_schedule_matches != Some(false) && unscheduled_slots.is_empty() && vrf_vkey_matches_blocks != Some(false);
+    let audit = EpochAudit {
+        network_magic: shelley.network_magic,
+        pool_id: pool_id.to_string(),
+        epoch,
+        first_slot,
+        last_slot,
+        consensus: consensus.to_string(),
+        decentralization,
+        extra_entropy,
+        epoch_nonce: hex::encode(epoch_nonce),
+        pool_stake,
+        active_stake,
+        schedule_hash,
+        saved_schedule_hash: saved_slots.map(|saved_slots| slots_and_hash(saved_slots.into_iter()).1),
+        saved_schedule_matches,
+        scheduled_slots,
+        minted_blocks,
+        missed_slots,
+        unscheduled_slots,
+        vrf_vkey_matches_blocks,
+        consistent,
+        generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        generated_by: APP_USER_AGENT.to_string(),
+    };
+
+    let audit_hash = audit_hash(&audit)?;
+    let signature = sign::challenge_signature(&vrf_skey.key, AUDIT_DOMAIN, &audit_hash)?;
+    reporter::report(&AuditReport {
+        status: "ok".to_string(),
+        audit,
+        audit_hash,
+        signature: AuditSignature {
+            domain: AUDIT_DOMAIN.to_string(),
+            pool_vrf_vkey_hash: hex::encode(Hasher::<224>::hash(&hex::decode(&pool_vrf_vkey)?)),
+            pool_vrf_vkey,
+            signature,
+        },
+    })?;
+
+    Ok(consistent)
+}
+
+/// The consensus as the static str leader_slots tags the slots with
+fn static_consensus(consensus: &str) -> &'static str {
+    match consensus {
+        "tpraos" => "tpraos",
+        "cpraos" => "cpraos",
+        _ => "praos",
+    }
+}
+
+/// The blake2b-256 of the audit as compact JSON with its keys sorted, which `jq -S -c` reproduces
+fn audit_hash(audit: &EpochAudit) -> Result<String, Error> {
+    let canonical = serde_json::to_string(&serde_json::to_value(audit)?)?;
+    Ok(hex::encode(Hasher::<256>::hash(canonical.as_bytes())))
+}
+
+/// The scheduled slots without a block from the pool on chain, and the slots with one on chain that are not scheduled
+fn compare_blocks(scheduled_slots: &[u64], minted_blocks: &[AuditBlock]) -> (Vec<u64>, Vec<u64>) {
+    let on_chain_slots: Vec<u64> = minted_blocks
+        .iter()
+        .filter(|block| !block.orphaned)
+        .map(|block| block.slot_number)
+        .collect();
+    let missed_slots = scheduled_slots
+        .iter()
+        .filter(|slot| !on_chain_slots.contains(slot))
+        .copied()
+        .collect();
+    let unscheduled_slots = on_chain_slots
+        .iter()
+        .filter(|slot| !scheduled_slots.contains(slot))
+        .copied()
+        .collect();
+    (missed_slots, unscheduled_slots)
+}
+
+fn vrf_vkey_matches_blocks(block_details: &[BlockDetail], pool_vrf_vkey: &str) -> Option<bool> {
+    let vrf_vkeys: Vec<&str> = block_details
+        .iter()
+        .map(|block_detail| block_detail.node_vrf_vkey.as_str())
+        .filter(|node_vrf_vkey| !node_vrf_vkey.is_empty())
+        .collect();
+    if vrf_vkeys.is_empty() {
+        return None;
+    }
+    Some(
+        vrf_vkeys
+            .iter()
+            .all(|node_vrf_vkey| node_vrf_vkey.eq_ignore_ascii_case(pool_vrf_vkey)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(slot_number: u64, orphaned: bool) -> AuditBlock {
+        AuditBlock {
+            block_number: slot_number,
+            slot_number,
+            hash: String::new(),
+            orphaned,
+        }
+    }
+
+    #[test]
+    fn test_compare_blocks() {
+        // slot 20 lost its block to a fork and slot 35 was minted without being scheduled
+        let minted_blocks = vec![block(10, false), block(20, true), block(30, false), block(35, false)];
+        assert_eq!(
+            compare_blocks(&[10, 20, 30, 40], &minted_blocks),
+            (vec![20, 40], vec![35])
+        );
+    }
+
+    #[test]
+    fn test_audit_hash_sorts_keys() {
+        let value = serde_json::to_value(AuditBlock {
+            block_number: 1,
+            slot_number: 2,
+            hash: "ab".to_string(),
+            orphaned: false,
+        })
+        .unwrap();
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"blockNumber":1,"hash":"ab","orphaned":false,"slotNumber":2}"#
+        );
+    }
+}
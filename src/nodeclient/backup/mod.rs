@@ -0,0 +1,83 @@
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::info;
+
+use crate::nodeclient::blockstore::{Block, BlockStore, SavedSlots};
+use crate::nodeclient::http;
+use crate::nodeclient::http::HttpSettings;
+
+const BACKUP_VERSION: u64 = 1;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("HTTP error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Client(#[from] http::Error),
+
+    // the url is left out because presigned urls carry credentials
+    #[error("Backup upload failed with HTTP {status}: {body}")]
+    Upload { status: u16, body: String },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Backup {
+    version: u64,
+    created_at: String,
+    epoch: u64,
+    slots: Vec<SavedSlots>,
+    chain: Vec<Block>,
+}
+
+/// Upload the slots table and the chain between first_chain_slot and the tip as a single JSON document with an
+/// HTTP PUT. That covers S3 presigned urls, WebDAV and plain HTTP servers. `{epoch}` in the url is replaced with
+/// the epoch so each epoch can be kept as a separate object.
+pub(crate) fn upload_backup(
+    block_store: &mut Box<dyn BlockStore + Send>,
+    backup_url: &str,
+    epoch: u64,
+    first_chain_slot: u64,
+) -> Result<(), Error> {
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let backup = Backup {
+        version: BACKUP_VERSION,
+        created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        epoch,
+        slots: block_store.get_all_slots()?,
+        chain: block_store.get_blocks_in_slot_range(first_chain_slot, tip_slot_number)?,
+    };
+    let body = serde_json::to_vec(&backup)?;
+
+    let url = backup_url.replace("{epoch}", &epoch.to_string());
+    let client = http::client(&HttpSettings::from_env()?, None)?;
+    let response = client
+        .put(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .map_err(|error| error.without_url())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::Upload {
+            status: status.as_u16(),
+            body: response.text().unwrap_or_default(),
+        });
+    }
+    info!(
+        "Uploaded backup of {} slot records and {} blocks for epoch {}",
+        backup.slots.len(),
+        backup.chain.len(),
+        epoch
+    );
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::BlockDetail;
+use crate::nodeclient::reporter;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Block not found")]
+    NotFound,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockDetails {
+    status: String,
+    blocks: Vec<BlockDetail>,
+}
+
+/// Print every stored header field of the blocks matching a full or partial hash, or of the blocks at a slot.
+/// Orphaned blocks are included after the non-orphaned one.
+pub(crate) fn block_detail(db_path: &Path, hash: &Option<String>, slot: &Option<u64>) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let blocks = match (hash, slot) {
+        (Some(hash), _) => block_store.find_block_details_by_hash(&hash.to_lowercase())?,
+        (None, Some(slot)) => block_store.get_block_details_at_slot(*slot)?,
+        (None, None) => vec![],
+    };
+    if blocks.is_empty() {
+        return Err(Error::NotFound);
+    }
+
+    reporter::report(&BlockDetails {
+        status: "ok".to_string(),
+        blocks,
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::nodeclient::blockstore::{
+    Block, BlockDetail, BlockStore, Error, OpcertHistory, ProtocolUpdate, SavedSlots, SlotsInputs, StakeHistory,
+};
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::sync::BlockHeader;
+use lru::LruCache;
+use pallas_crypto::hash::Hash;
+use tracing::debug;
+
+// results kept per process, a few per epoch for every epoch of mainnet
+const CAPACITY: usize = 1024;
+// how long a tip read stays good enough to decide which results are stable, about one block on mainnet
+const TIP_REFRESH_INTERVAL: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Query {
+    EtaVBeforeSlot,
+    PrevHashBeforeSlot,
+}
+
+type NonceCache = LruCache<(PathBuf, Query, u64), Hash<32>>;
+
+fn new_nonce_cache() -> NonceCache {
+    LruCache::new(NonZeroUsize::new(CAPACITY).unwrap())
+}
+
+static NONCE_CACHE: OnceLock<Mutex<NonceCache>> = OnceLock::new();
+
+/// A least recently used cache of nonce query results, shared by every block store of the process
+fn nonce_cache() -> &'static Mutex<NonceCache> {
+    NONCE_CACHE.get_or_init(|| Mutex::new(new_nonce_cache()))
+}
+
+/// A BlockStore that remembers the results of get_eta_v_before_slot and get_prev_hash_before_slot for slots far
+/// enough behind the tip, so computing several nonces, e.g. in serve, does not repeat the same backwards scans. Every
+/// other call goes straight to the wrapped store.
+pub struct CachedBlockStore {
+    inner: Box<dyn BlockStore + Send>,
+    db_path: PathBuf,
+    // a rollback never reaches further back than this many slots, so results for slots this far behind the tip
+    // cannot change any more
+    stable_slots: u64,
+    // the last tip slot read from the inner store and when it was read
+    tip: Option<(u64, Instant)>,
+}
+
+impl CachedBlockStore {
+    /// Wrap inner, caching results for slots more than stable_slots behind the tip. Use 3k/f of the genesis, the
+    /// slots the last k blocks are spread over.
+    pub fn new(db_path: &Path, inner: Box<dyn BlockStore + Send>, stable_slots: u64) -> Self {
+        CachedBlockStore {
+            inner,
+            db_path: db_path.to_path_buf(),
+            stable_slots,
+            tip: None,
+        }
+    }
+
+    /// Whether slot_number is far enough behind the tip for its results to be cached. The tip is only read again when
+    /// the one we have is too old to say yes, and not more than once per TIP_REFRESH_INTERVAL.
+    fn is_stable(&mut self, slot_number: u64) -> Result<bool, Error> {
+        let stable = |tip_slot_number: u64| slot_number.saturating_add(self.stable_slots) <= tip_slot_number;
+        match self.tip {
+            Some((tip_slot_number, _)) if stable(tip_slot_number) => return Ok(true),
+            Some((_, read_at)) if read_at.elapsed() < TIP_REFRESH_INTERVAL => return Ok(false),
+            _ => {}
+        }
+        let tip_slot_number = self.inner.get_tip_slot_number()?;
+        self.tip = Some((tip_slot_number, Instant::now()));
+        Ok(stable(tip_slot_number))
+    }
+
+    fn cached_query(
+        &mut self,
+        query: Query,
+        slot_number: u64,
+        run: fn(&mut Box<dyn BlockStore + Send>, u64) -> Result<Hash<32>, Error>,
+    ) -> Result<Hash<32>, Error> {
+        let key = (self.db_path.clone(), query, slot_number);
+        if let Some(value) = nonce_cache()
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&key)
+            .copied()
+        {
+            debug!("nonce cache hit: {:?} {}", query, slot_number);
+            return Ok(value);
+        }
+        let value = run(&mut self.inner, slot_number)?;
+        if self.is_stable(slot_number)? {
+            nonce_cache()
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .put(key, value);
+        }
+        Ok(value)
+    }
+}
+
+impl BlockStore for CachedBlockStore {
+    fn save_block(&mut self, pending_blocks: &mut Vec<BlockHeader>, shelley_genesis_hash: &str) -> Result<(), Error> {
+        self.inner.save_block(pending_blocks, shelley_genesis_hash)
+    }
+
+    fn load_blocks(&mut self) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+        self.inner.load_blocks()
+    }
+
+    fn find_block_by_hash(&mut self, hash_start: &str) -> Result<Option<Block>, Error> {
+        self.inner.find_block_by_hash(hash_start)
+    }
+
+    fn find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, Error> {
+        self.inner.find_block_details_by_hash(hash_start)
+    }
+
+    fn get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, Error> {
+        self.inner.get_block_details_at_slot(slot_number)
+    }
+
+    fn get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, Error> {
+        self.inner.get_blocks_in_slot_range(first_slot, last_slot)
+    }
+
+    fn get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, Error> {
+        self.inner.get_competing_blocks(slot_number, block_number)
+    }
+
+    fn get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &PoolId,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        self.inner
+            .get_pool_block_details_in_slot_range(pool_id, first_slot, last_slot)
+    }
+
+    fn get_block_details_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<BlockDetail>, Error> {
+        self.inner.get_block_details_in_slot_range(first_slot, last_slot)
+    }
+
+    fn checkpoint(&mut self) -> Result<(), Error> {
+        self.inner.checkpoint()
+    }
+
+    fn backup_to(&mut self, backup_path: &Path) -> Result<(), Error> {
+        self.inner.backup_to(backup_path)
+    }
+
+    fn get_tip_slot_number(&mut self) -> Result<u64, Error> {
+        self.inner.get_tip_slot_number()
+    }
+
+    fn get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, Error> {
+        self.inner.get_tip_protocol_version()
+    }
+
+    fn get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
+        self.cached_query(Query::EtaVBeforeSlot, slot_number, |inner, slot_number| {
+            inner.get_eta_v_before_slot(slot_number)
+        })
+    }
+
+    fn get_prev_hash_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
+        self.cached_query(Query::PrevHashBeforeSlot, slot_number, |inner, slot_number| {
+            inner.get_prev_hash_before_slot(slot_number)
+        })
+    }
+
+    fn save_slots(
+        &mut self,
+        epoch: u64,
+        pool_id: &PoolId,
+        slot_qty: u64,
+        slots: &str,
+        hash: &str,
+        inputs: Option<&SlotsInputs>,
+    ) -> Result<(), Error> {
+        self.inner.save_slots(epoch, pool_id, slot_qty, slots, hash, inputs)
+    }
+
+    fn get_current_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<(u64, String), Error> {
+        self.inner.get_current_slots(epoch, pool_id)
+    }
+
+    fn get_previous_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<String>, Error> {
+        self.inner.get_previous_slots(epoch, pool_id)
+    }
+
+    fn get_slots_to_send(&mut self, epoch: u64, pool_id: &PoolId) -> Result<((u64, String), Option<String>), Error> {
+        self.inner.get_slots_to_send(epoch, pool_id)
+    }
+
+    fn get_slots_inputs(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<SlotsInputs>, Error> {
+        self.inner.get_slots_inputs(epoch, pool_id)
+    }
+
+    fn get_all_slots(&mut self) -> Result<Vec<SavedSlots>, Error> {
+        self.inner.get_all_slots()
+    }
+
+    fn get_opcert_history(&mut self, pool_id: &PoolId) -> Result<Vec<OpcertHistory>, Error> {
+        self.inner.get_opcert_history(pool_id)
+    }
+
+    fn save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), Error> {
+        self.inner.save_stake_history(stake_history)
+    }
+
+    fn get_stake_history(&mut self, pool_id: &PoolId) -> Result<Vec<StakeHistory>, Error> {
+        self.inner.get_stake_history(pool_id)
+    }
+
+    fn save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), Error> {
+        self.inner.save_protocol_update(protocol_update)
+    }
+
+    fn get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, Error> {
+        self.inner.get_protocol_updates_before_epoch(epoch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nodeclient::blockstore::memory::MemoryBlockStore;
+
+    #[test]
+    fn test_nonce_cache_evicts_least_recently_used() {
+        let mut nonce_cache = new_nonce_cache();
+        let key = |slot_number: u64| (PathBuf::from("cncli.db"), Query::EtaVBeforeSlot, slot_number);
+        for slot_number in 0..CAPACITY as u64 {
+            nonce_cache.put(key(slot_number), Hash::new([slot_number as u8; 32]));
+        }
+        // slot 0 was used most recently, so slot 1 is the one to make room
+        assert_eq!(nonce_cache.get(&key(0)), Some(&Hash::new([0; 32])));
+        nonce_cache.put(key(CAPACITY as u64), Hash::new([1; 32]));
+        assert_eq!(nonce_cache.len(), CAPACITY);
+        assert!(nonce_cache.get(&key(0)).is_some());
+        assert!(nonce_cache.get(&key(1)).is_none());
+        assert!(nonce_cache
+            .get(&(PathBuf::from("cncli.db"), Query::PrevHashBeforeSlot, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_is_stable_uses_stable_slots() {
+        let inner = MemoryBlockStore::new();
+        let mut block_store = CachedBlockStore::new(Path::new("cncli.db"), Box::new(inner), 100);
+        block_store.tip = Some((1000, Instant::now()));
+        assert!(block_store.is_stable(900).unwrap());
+        // the tip was read just now, so it is not read again for a slot it is too old for
+        assert!(!block_store.is_stable(901).unwrap());
+        assert_eq!(block_store.tip.map(|(tip_slot_number, _)| tip_slot_number), Some(1000));
+    }
+}
@@ -0,0 +1,540 @@
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::{
+    opcert_anomaly, Block, BlockDetail, BlockStore, Error, OpcertHistory, ProtocolUpdate, SavedSlots, SlotsInputs,
+    StakeHistory,
+};
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::sync::BlockHeader;
+use pallas_crypto::hash::{Hash, Hasher};
+use pallas_crypto::nonce::generate_rolling_nonce;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::warn;
+
+/// The --db value that keeps the blocks in memory instead of a file
+pub const MEMORY_DB: &str = ":memory:";
+
+#[derive(Debug, Default)]
+struct MemoryDb {
+    /// Every block, including orphans, in the order it was received
+    chain: Vec<BlockDetail>,
+    /// keyed by pool_id and sequence number
+    opcert_history: BTreeMap<(String, u64), OpcertHistory>,
+    /// keyed by epoch and pool_id
+    slots: BTreeMap<(u64, String), SavedSlots>,
+    /// keyed by pool_id and epoch
+    stake_history: BTreeMap<(String, u64), StakeHistory>,
+    protocol_updates: Vec<ProtocolUpdate>,
+}
+
+/// A BlockStore that keeps everything in memory and loses it when the process exits. Clones share the same blocks,
+/// so a sync that reconnects continues where it left off.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBlockStore {
+    db: Arc<Mutex<MemoryDb>>,
+}
+
+impl MemoryBlockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn db(&self) -> MutexGuard<'_, MemoryDb> {
+        // a panic holding the lock cannot leave the maps half updated in a way later reads care about
+        self.db.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn parse_hash(hex: &str) -> Result<Hash<32>, Error> {
+    Hash::from_str(hex).map_err(|error| Error::Blockstore(format!("Invalid hash {hex}: {error}")))
+}
+
+fn block(block_detail: &BlockDetail) -> Block {
+    Block {
+        block_number: block_detail.block_number,
+        slot_number: block_detail.slot_number,
+        hash: block_detail.hash.clone(),
+        prev_hash: block_detail.prev_hash.clone(),
+        pool_id: block_detail.pool_id.clone(),
+        leader_vrf: block_detail.leader_vrf_0.clone(),
+        eta_v: block_detail.eta_v.clone(),
+        eta_vrf_0: block_detail.eta_vrf_0.clone(),
+        orphaned: block_detail.orphaned,
+        received_at: block_detail.received_at.clone(),
+        source_peer: block_detail.source_peer.clone(),
+    }
+}
+
+/// The blocks between first_slot and last_slot ordered by slot_number, then orphaned and hash
+fn block_details_in_slot_range(chain: &[BlockDetail], first_slot: u64, last_slot: u64) -> Vec<&BlockDetail> {
+    let mut block_details: Vec<&BlockDetail> = chain
+        .iter()
+        .filter(|block_detail| block_detail.slot_number >= first_slot && block_detail.slot_number <= last_slot)
+        .collect();
+    block_details.sort_by(|a, b| {
+        a.slot_number
+            .cmp(&b.slot_number)
+            .then_with(|| a.orphaned.cmp(&b.orphaned))
+            .then_with(|| a.hash.cmp(&b.hash))
+    });
+    block_details
+}
+
+impl BlockStore for MemoryBlockStore {
+    fn save_block(&mut self, pending_blocks: &mut Vec<BlockHeader>, shelley_genesis_hash: &str) -> Result<(), Error> {
+        let first_pending_block_number = match pending_blocks.first() {
+            Some(block) => block.block_number,
+            None => return Ok(()),
+        };
+        let mut db = self.db();
+        let db = &mut *db;
+
+        // orphan the blocks the pending ones replace and continue the rolling nonce (eta_v) of the block before them
+        let mut prev_eta_v = parse_hash(shelley_genesis_hash)?;
+        for block_detail in db.chain.iter_mut().rev() {
+            if block_detail.orphaned {
+                continue;
+            }
+            if block_detail.block_number >= first_pending_block_number {
+                block_detail.orphaned = true;
+                continue;
+            }
+            prev_eta_v = parse_hash(&block_detail.eta_v)?;
+            break;
+        }
+
+        for block in pending_blocks.drain(..) {
+            // blake2b 224 of node_vkey is the pool_id
+            let pool_id = hex::encode(Hasher::<224>::hash(block.node_vkey.as_slice()));
+            let eta_v = generate_rolling_nonce(prev_eta_v, &block.eta_vrf_0);
+
+            // track the opcert sequence number of the pool
+            let opcert_key = (pool_id.clone(), block.unknown_0);
+            let previous_sequence_number = db
+                .opcert_history
+                .range((pool_id.clone(), 0)..=(pool_id.clone(), u64::MAX))
+                .next_back()
+                .map(|(_, opcert_history)| opcert_history.sequence_number);
+            match db.opcert_history.get_mut(&opcert_key) {
+                Some(opcert_history) => {
+                    opcert_history.first_slot_number = opcert_history.first_slot_number.min(block.slot_number);
+                    opcert_history.last_slot_number = opcert_history.last_slot_number.max(block.slot_number);
+                }
+                None => {
+                    let anomaly =
+                        opcert_anomaly(previous_sequence_number, block.unknown_0, block.protocol_major_version);
+                    if let Some(anomaly) = &anomaly {
+                        warn!(
+                            "Pool {} block {} at slot {}: opcert {}",
+                            pool_id, block.block_number, block.slot_number, anomaly
+                        );
+                    }
+                    db.opcert_history.insert(
+                        opcert_key,
+                        OpcertHistory {
+                            pool_id: pool_id.clone(),
+                            sequence_number: block.unknown_0,
+                            kes_period: block.unknown_1,
+                            first_slot_number: block.slot_number,
+                            last_slot_number: block.slot_number,
+                            anomaly,
+                        },
+                    );
+                }
+            }
+
+            db.chain.push(BlockDetail {
+                block_number: block.block_number,
+                slot_number: block.slot_number,
+                hash: hex::encode(&block.hash),
+                prev_hash: hex::encode(&block.prev_hash),
+                pool_id,
+                eta_v: hex::encode(eta_v),
+                node_vkey: hex::encode(&block.node_vkey),
+                node_vrf_vkey: hex::encode(&block.node_vrf_vkey),
+                block_vrf_0: hex::encode(&block.block_vrf_0),
+                block_vrf_1: hex::encode(&block.block_vrf_1),
+                eta_vrf_0: hex::encode(&block.eta_vrf_0),
+                eta_vrf_1: hex::encode(&block.eta_vrf_1),
+                leader_vrf_0: hex::encode(&block.leader_vrf_0),
+                leader_vrf_1: hex::encode(&block.leader_vrf_1),
+                block_size: block.block_size,
+                block_body_hash: hex::encode(&block.block_body_hash),
+                opcert_hot_vkey: hex::encode(&block.pool_opcert),
+                opcert_sequence_number: block.unknown_0,
+                opcert_kes_period: block.unknown_1,
+                opcert_sigma: hex::encode(&block.unknown_2),
+                protocol_major_version: block.protocol_major_version,
+                protocol_minor_version: block.protocol_minor_version,
+                orphaned: false,
+                header_cbor: if block.header_cbor.is_empty() {
+                    None
+                } else {
+                    Some(hex::encode(&block.header_cbor))
+                },
+                received_at: block.received_at.map(blockstore::format_received_at),
+                source_peer: block.source_peer,
+            });
+
+            prev_eta_v = eta_v;
+        }
+
+        Ok(())
+    }
+
+    fn load_blocks(&mut self) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+        self.db()
+            .chain
+            .iter()
+            .rev()
+            .filter(|block_detail| !block_detail.orphaned)
+            .take(33)
+            .map(|block_detail| Ok((block_detail.slot_number, parse_hash(&block_detail.hash)?.to_vec())))
+            .collect()
+    }
+
+    fn find_block_by_hash(&mut self, hash_start: &str) -> Result<Option<Block>, Error> {
+        Ok(self
+            .db()
+            .chain
+            .iter()
+            .rev()
+            .find(|block_detail| block_detail.hash.starts_with(hash_start))
+            .map(block))
+    }
+
+    fn find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, Error> {
+        let mut block_details: Vec<BlockDetail> = self
+            .db()
+            .chain
+            .iter()
+            .rev()
+            .filter(|block_detail| block_detail.hash.starts_with(hash_start))
+            .cloned()
+            .collect();
+        block_details.sort_by_key(|block_detail| block_detail.orphaned);
+        Ok(block_details)
+    }
+
+    fn get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, Error> {
+        let mut block_details: Vec<BlockDetail> = self
+            .db()
+            .chain
+            .iter()
+            .filter(|block_detail| block_detail.slot_number == slot_number)
+            .cloned()
+            .collect();
+        block_details.sort_by_key(|block_detail| block_detail.orphaned);
+        Ok(block_details)
+    }
+
+    fn get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, Error> {
+        let db = self.db();
+        Ok(block_details_in_slot_range(&db.chain, first_slot, last_slot)
+            .into_iter()
+            .map(block)
+            .collect())
+    }
+
+    fn get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, Error> {
+        Ok(self
+            .db()
+            .chain
+            .iter()
+            .filter(|block_detail| block_detail.slot_number == slot_number || block_detail.block_number == block_number)
+            .map(block)
+            .collect())
+    }
+
+    fn get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &PoolId,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        let db = self.db();
+        Ok(block_details_in_slot_range(&db.chain, first_slot, last_slot)
+            .into_iter()
+            .filter(|block_detail| block_detail.pool_id.eq_ignore_ascii_case(pool_id.as_str()))
+            .cloned()
+            .collect())
+    }
+
+    fn get_block_details_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<BlockDetail>, Error> {
+        let db = self.db();
+        Ok(block_details_in_slot_range(&db.chain, first_slot, last_slot)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    fn get_tip_slot_number(&mut self) -> Result<u64, Error> {
+        Ok(self
+            .db()
+            .chain
+            .iter()
+            .map(|block_detail| block_detail.slot_number)
+            .max()
+            .unwrap_or_default())
+    }
+
+    fn checkpoint(&mut self) -> Result<(), Error> {
+        // nothing is written anywhere
+        Ok(())
+    }
+
+    fn backup_to(&mut self, _backup_path: &Path) -> Result<(), Error> {
+        Err(Error::Blockstore(
+            "an in-memory database cannot be backed up".to_string(),
+        ))
+    }
+
+    fn get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, Error> {
+        Ok(self
+            .db()
+            .chain
+            .iter()
+            .filter(|block_detail| !block_detail.orphaned)
+            .max_by_key(|block_detail| block_detail.slot_number)
+            .map(|block_detail| (block_detail.protocol_major_version, block_detail.protocol_minor_version)))
+    }
+
+    fn get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
+        let db = self.db();
+        let block_detail = db
+            .chain
+            .iter()
+            .filter(|block_detail| !block_detail.orphaned && block_detail.slot_number < slot_number)
+            .max_by_key(|block_detail| block_detail.slot_number)
+            .ok_or_else(|| Error::Blockstore(format!("No block before slot {slot_number}")))?;
+        parse_hash(&block_detail.eta_v)
+    }
+
+    fn get_prev_hash_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
+        let db = self.db();
+        let block_detail = db
+            .chain
+            .iter()
+            .filter(|block_detail| !block_detail.orphaned && block_detail.slot_number < slot_number)
+            .max_by_key(|block_detail| block_detail.slot_number)
+            .ok_or_else(|| Error::Blockstore(format!("No block before slot {slot_number}")))?;
+        parse_hash(&block_detail.prev_hash)
+    }
+
+    fn save_slots(
+        &mut self,
+        epoch: u64,
+        pool_id: &PoolId,
+        slot_qty: u64,
+        slots: &str,
+        hash: &str,
+        inputs: Option<&SlotsInputs>,
+    ) -> Result<(), Error> {
+        self.db().slots.insert(
+            (epoch, pool_id.to_string()),
+            SavedSlots {
+                epoch,
+                pool_id: pool_id.to_string(),
+                slot_qty,
+                slots: slots.to_string(),
+                hash: hash.to_string(),
+                inputs: inputs.cloned(),
+            },
+        );
+        Ok(())
+    }
+
+    fn get_current_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<(u64, String), Error> {
+        self.db()
+            .slots
+            .get(&(epoch, pool_id.to_string()))
+            .map(|saved_slots| (saved_slots.slot_qty, saved_slots.hash.clone()))
+            .ok_or_else(|| Error::Blockstore(format!("No slots saved for epoch {epoch} and pool {pool_id}")))
+    }
+
+    fn get_previous_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<String>, Error> {
+        Ok(self
+            .db()
+            .slots
+            .get(&(epoch, pool_id.to_string()))
+            .map(|saved_slots| saved_slots.slots.clone()))
+    }
+
+    fn get_slots_to_send(&mut self, epoch: u64, pool_id: &PoolId) -> Result<((u64, String), Option<String>), Error> {
+        // one lock for both reads, so a leaderlog saving slots at the same time is never seen half written
+        let db = self.db();
+        let current_slots = db
+            .slots
+            .get(&(epoch, pool_id.to_string()))
+            .ok_or_else(|| Error::Blockstore(format!("No slots saved for epoch {epoch} and pool {pool_id}")))?;
+        let previous_slots = epoch
+            .checked_sub(1)
+            .and_then(|previous_epoch| db.slots.get(&(previous_epoch, pool_id.to_string())))
+            .map(|saved_slots| saved_slots.slots.clone());
+        Ok(((current_slots.slot_qty, current_slots.hash.clone()), previous_slots))
+    }
+
+    fn get_slots_inputs(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<SlotsInputs>, Error> {
+        Ok(self
+            .db()
+            .slots
+            .get(&(epoch, pool_id.to_string()))
+            .and_then(|saved_slots| saved_slots.inputs.clone()))
+    }
+
+    fn get_all_slots(&mut self) -> Result<Vec<SavedSlots>, Error> {
+        Ok(self.db().slots.values().cloned().collect())
+    }
+
+    fn get_opcert_history(&mut self, pool_id: &PoolId) -> Result<Vec<OpcertHistory>, Error> {
+        let pool_id = pool_id.to_string();
+        Ok(self
+            .db()
+            .opcert_history
+            .range((pool_id.clone(), 0)..=(pool_id, u64::MAX))
+            .map(|(_, opcert_history)| opcert_history.clone())
+            .collect())
+    }
+
+    fn save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), Error> {
+        self.db().stake_history.insert(
+            (stake_history.pool_id.to_lowercase(), stake_history.epoch),
+            stake_history.clone(),
+        );
+        Ok(())
+    }
+
+    fn get_stake_history(&mut self, pool_id: &PoolId) -> Result<Vec<StakeHistory>, Error> {
+        let pool_id = pool_id.to_string();
+        Ok(self
+            .db()
+            .stake_history
+            .range((pool_id.clone(), 0)..=(pool_id, u64::MAX))
+            .map(|(_, stake_history)| stake_history.clone())
+            .collect())
+    }
+
+    fn save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), Error> {
+        let mut db = self.db();
+        // replace any proposal we already stored for this slot (re-sync after a rollback)
+        db.protocol_updates
+            .retain(|saved| saved.epoch != protocol_update.epoch || saved.slot_number != protocol_update.slot_number);
+        db.protocol_updates.push(protocol_update.clone());
+        Ok(())
+    }
+
+    fn get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, Error> {
+        let mut protocol_updates: Vec<ProtocolUpdate> = self
+            .db()
+            .protocol_updates
+            .iter()
+            .filter(|protocol_update| protocol_update.epoch < epoch)
+            .cloned()
+            .collect();
+        protocol_updates.sort_by_key(|protocol_update| protocol_update.slot_number);
+        Ok(protocol_updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HASH: &str = "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81";
+
+    fn header(block_number: u64, slot_number: u64, hash: u8) -> BlockHeader {
+        BlockHeader {
+            block_number,
+            slot_number,
+            hash: vec![hash; 32],
+            prev_hash: vec![hash.wrapping_sub(1); 32],
+            node_vkey: vec![1; 32],
+            eta_vrf_0: vec![hash; 64],
+            unknown_0: 2,
+            protocol_major_version: 8,
+            ..BlockHeader::default()
+        }
+    }
+
+    #[test]
+    fn test_save_block_rolls_back_and_continues_nonce() {
+        let mut block_store = MemoryBlockStore::new();
+        block_store
+            .save_block(
+                &mut vec![header(1, 10, 1), header(2, 20, 2), header(3, 30, 3)],
+                GENESIS_HASH,
+            )
+            .unwrap();
+        let eta_v_1 = block_store.get_eta_v_before_slot(11).unwrap();
+        assert_eq!(
+            eta_v_1,
+            generate_rolling_nonce(parse_hash(GENESIS_HASH).unwrap(), &[1; 64])
+        );
+
+        // a fork replacing block 2 and 3, reconnecting shares the blocks already saved
+        let mut reconnected = block_store.clone();
+        reconnected
+            .save_block(&mut vec![header(2, 25, 4)], GENESIS_HASH)
+            .unwrap();
+        assert_eq!(block_store.get_tip_slot_number().unwrap(), 30);
+        assert_eq!(
+            block_store.get_eta_v_before_slot(26).unwrap(),
+            generate_rolling_nonce(eta_v_1, &[4; 64])
+        );
+        let loaded: Vec<u64> = block_store
+            .load_blocks()
+            .unwrap()
+            .into_iter()
+            .map(|(slot_number, _)| slot_number)
+            .collect();
+        assert_eq!(loaded, vec![25, 10]);
+
+        let competing: Vec<(u64, bool)> = block_store
+            .get_competing_blocks(20, 2)
+            .unwrap()
+            .into_iter()
+            .map(|block| (block.slot_number, block.orphaned))
+            .collect();
+        assert_eq!(competing, vec![(20, true), (25, false)]);
+
+        let opcert_history = block_store
+            .get_opcert_history(&PoolId::from_str(&hex::encode(Hasher::<224>::hash(&[1; 32]))).unwrap())
+            .unwrap();
+        assert_eq!(opcert_history.len(), 1);
+        assert_eq!(
+            (opcert_history[0].first_slot_number, opcert_history[0].last_slot_number),
+            (10, 30)
+        );
+    }
+
+    #[test]
+    fn test_slots_to_send() {
+        let mut block_store = MemoryBlockStore::new();
+        let pool_id = PoolId::from_str(&"ab".repeat(28)).unwrap();
+        block_store.save_slots(100, &pool_id, 2, "[1,2]", "aa", None).unwrap();
+        let inputs = SlotsInputs {
+            nonce: "ee".to_string(),
+            sigma_numerator: 1,
+            sigma_denominator: 4,
+            d: 0.0,
+            consensus: "praos".to_string(),
+            cncli_version: "6.0.0".to_string(),
+        };
+        block_store
+            .save_slots(101, &pool_id, 1, "[3]", "bb", Some(&inputs))
+            .unwrap();
+        block_store
+            .save_slots(101, &pool_id, 3, "[3,4,5]", "cc", Some(&inputs))
+            .unwrap();
+        assert_eq!(
+            block_store.get_slots_to_send(101, &pool_id).unwrap(),
+            ((3, "cc".to_string()), Some("[1,2]".to_string()))
+        );
+        assert_eq!(block_store.get_all_slots().unwrap().len(), 2);
+        assert!(block_store.get_current_slots(102, &pool_id).is_err());
+        assert_eq!(block_store.get_slots_inputs(101, &pool_id).unwrap(), Some(inputs));
+        assert_eq!(block_store.get_slots_inputs(100, &pool_id).unwrap(), None);
+    }
+}
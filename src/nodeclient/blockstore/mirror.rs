@@ -0,0 +1,171 @@
+use crate::nodeclient::blockstore::{
+    Block, BlockDetail, BlockStore, Error, OpcertHistory, ProtocolUpdate, SavedSlots, SlotsInputs, StakeHistory,
+};
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::sync::BlockHeader;
+use pallas_crypto::hash::Hash;
+use std::path::Path;
+use tracing::{error, warn};
+
+/// A BlockStore that writes everything to a primary store and also to a mirror store. All reads are served from
+/// the primary store. Failures writing to the mirror are logged but never interrupt the primary store.
+pub struct MirrorBlockStore {
+    primary: Box<dyn BlockStore + Send>,
+    mirror: Box<dyn BlockStore + Send>,
+}
+
+impl MirrorBlockStore {
+    pub fn new(mut primary: Box<dyn BlockStore + Send>, mut mirror: Box<dyn BlockStore + Send>) -> Self {
+        let primary_tip = primary.get_tip_slot_number().unwrap_or_default();
+        let mirror_tip = mirror.get_tip_slot_number().unwrap_or_default();
+        if primary_tip != mirror_tip {
+            warn!(
+                "Mirror db tip slot {} does not match primary db tip slot {}. Start both databases from the same state for a meaningful comparison.",
+                mirror_tip, primary_tip
+            );
+        }
+        MirrorBlockStore { primary, mirror }
+    }
+}
+
+impl BlockStore for MirrorBlockStore {
+    fn save_block(&mut self, pending_blocks: &mut Vec<BlockHeader>, shelley_genesis_hash: &str) -> Result<(), Error> {
+        let mut mirror_pending_blocks = pending_blocks.clone();
+        self.primary.save_block(pending_blocks, shelley_genesis_hash)?;
+        if let Err(error) = self.mirror.save_block(&mut mirror_pending_blocks, shelley_genesis_hash) {
+            error!("Mirror db save_block error: {}", error);
+        }
+        Ok(())
+    }
+
+    fn load_blocks(&mut self) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+        self.primary.load_blocks()
+    }
+
+    fn find_block_by_hash(&mut self, hash_start: &str) -> Result<Option<Block>, Error> {
+        self.primary.find_block_by_hash(hash_start)
+    }
+
+    fn find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, Error> {
+        self.primary.find_block_details_by_hash(hash_start)
+    }
+
+    fn get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, Error> {
+        self.primary.get_block_details_at_slot(slot_number)
+    }
+
+    fn get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, Error> {
+        self.primary.get_blocks_in_slot_range(first_slot, last_slot)
+    }
+
+    fn get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, Error> {
+        self.primary.get_competing_blocks(slot_number, block_number)
+    }
+
+    fn get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &PoolId,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        self.primary
+            .get_pool_block_details_in_slot_range(pool_id, first_slot, last_slot)
+    }
+
+    fn get_block_details_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<BlockDetail>, Error> {
+        self.primary.get_block_details_in_slot_range(first_slot, last_slot)
+    }
+
+    fn checkpoint(&mut self) -> Result<(), Error> {
+        self.primary.checkpoint()?;
+        if let Err(error) = self.mirror.checkpoint() {
+            error!("Mirror db checkpoint error: {}", error);
+        }
+        Ok(())
+    }
+
+    fn backup_to(&mut self, backup_path: &Path) -> Result<(), Error> {
+        // the mirror is only there to be compared with the primary
+        self.primary.backup_to(backup_path)
+    }
+
+    fn get_tip_slot_number(&mut self) -> Result<u64, Error> {
+        self.primary.get_tip_slot_number()
+    }
+
+    fn get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, Error> {
+        self.primary.get_tip_protocol_version()
+    }
+
+    fn get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
+        self.primary.get_eta_v_before_slot(slot_number)
+    }
+
+    fn get_prev_hash_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
+        self.primary.get_prev_hash_before_slot(slot_number)
+    }
+
+    fn save_slots(
+        &mut self,
+        epoch: u64,
+        pool_id: &PoolId,
+        slot_qty: u64,
+        slots: &str,
+        hash: &str,
+        inputs: Option<&SlotsInputs>,
+    ) -> Result<(), Error> {
+        self.primary.save_slots(epoch, pool_id, slot_qty, slots, hash, inputs)?;
+        if let Err(error) = self.mirror.save_slots(epoch, pool_id, slot_qty, slots, hash, inputs) {
+            error!("Mirror db save_slots error: {}", error);
+        }
+        Ok(())
+    }
+
+    fn get_current_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<(u64, String), Error> {
+        self.primary.get_current_slots(epoch, pool_id)
+    }
+
+    fn get_previous_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<String>, Error> {
+        self.primary.get_previous_slots(epoch, pool_id)
+    }
+
+    fn get_slots_to_send(&mut self, epoch: u64, pool_id: &PoolId) -> Result<((u64, String), Option<String>), Error> {
+        self.primary.get_slots_to_send(epoch, pool_id)
+    }
+
+    fn get_slots_inputs(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<SlotsInputs>, Error> {
+        self.primary.get_slots_inputs(epoch, pool_id)
+    }
+
+    fn get_all_slots(&mut self) -> Result<Vec<SavedSlots>, Error> {
+        self.primary.get_all_slots()
+    }
+
+    fn get_opcert_history(&mut self, pool_id: &PoolId) -> Result<Vec<OpcertHistory>, Error> {
+        self.primary.get_opcert_history(pool_id)
+    }
+
+    fn save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), Error> {
+        self.primary.save_stake_history(stake_history)?;
+        if let Err(error) = self.mirror.save_stake_history(stake_history) {
+            error!("Mirror db save_stake_history error: {}", error);
+        }
+        Ok(())
+    }
+
+    fn get_stake_history(&mut self, pool_id: &PoolId) -> Result<Vec<StakeHistory>, Error> {
+        self.primary.get_stake_history(pool_id)
+    }
+
+    fn save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), Error> {
+        self.primary.save_protocol_update(protocol_update)?;
+        if let Err(error) = self.mirror.save_protocol_update(protocol_update) {
+            error!("Mirror db save_protocol_update error: {}", error);
+        }
+        Ok(())
+    }
+
+    fn get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, Error> {
+        self.primary.get_protocol_updates_before_epoch(epoch)
+    }
+}
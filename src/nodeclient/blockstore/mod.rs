@@ -1,8 +1,16 @@
+use std::path::Path;
+
+use chrono::{DateTime, SecondsFormat};
 use pallas_crypto::hash::Hash;
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::nodeclient::poolid::PoolId;
 use crate::nodeclient::sync::BlockHeader;
 
+pub(crate) mod cache;
+pub(crate) mod memory;
+pub(crate) mod mirror;
 pub(crate) mod redb;
 pub(crate) mod sqlite;
 
@@ -21,6 +29,31 @@ pub enum Error {
     Blockstore(String),
 }
 
+/// Open the database at db_path as redb or sqlite, depending on its magic number. A read-only sqlite store is not
+/// created or migrated and never takes a write lock, so it can be used next to a running sync.
+pub(crate) fn open(db_path: &Path, read_only: bool) -> Result<Box<dyn BlockStore + Send>, Error> {
+    open_with_busy_timeout(db_path, sqlite::DEFAULT_BUSY_TIMEOUT_MS, read_only)
+}
+
+/// Like open, waiting up to busy_timeout_ms for a sqlite database another process has locked
+pub(crate) fn open_with_busy_timeout(
+    db_path: &Path,
+    busy_timeout_ms: u64,
+    read_only: bool,
+) -> Result<Box<dyn BlockStore + Send>, Error> {
+    if redb::is_redb_database(db_path)? {
+        Ok(Box::new(redb::RedbBlockStore::new(db_path)?))
+    } else {
+        Ok(Box::new(sqlite::SqLiteBlockStore::new_with_options(
+            db_path,
+            busy_timeout_ms,
+            read_only,
+        )?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub(crate) struct Block {
     pub(crate) block_number: u64,
     pub(crate) slot_number: u64,
@@ -28,21 +61,351 @@ pub(crate) struct Block {
     pub(crate) prev_hash: String,
     pub(crate) pool_id: String,
     pub(crate) leader_vrf: String,
+    pub(crate) eta_v: String,
+    pub(crate) eta_vrf_0: String,
+    pub(crate) orphaned: bool,
+    /// When sync received the header, RFC 3339 with milliseconds. Not known for blocks saved before it was recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) received_at: Option<String>,
+    /// The node sync received the header from, as host:port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source_peer: Option<String>,
+}
+
+/// Every header field stored for a block. The operational certificate fields are stored in the unknown_* columns.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockDetail {
+    pub(crate) block_number: u64,
+    pub(crate) slot_number: u64,
+    pub(crate) hash: String,
+    pub(crate) prev_hash: String,
+    pub(crate) pool_id: String,
+    pub(crate) eta_v: String,
+    pub(crate) node_vkey: String,
+    pub(crate) node_vrf_vkey: String,
+    pub(crate) block_vrf_0: String,
+    pub(crate) block_vrf_1: String,
+    pub(crate) eta_vrf_0: String,
+    pub(crate) eta_vrf_1: String,
+    pub(crate) leader_vrf_0: String,
+    pub(crate) leader_vrf_1: String,
+    pub(crate) block_size: u64,
+    pub(crate) block_body_hash: String,
+    pub(crate) opcert_hot_vkey: String,
+    pub(crate) opcert_sequence_number: u64,
+    pub(crate) opcert_kes_period: u64,
+    pub(crate) opcert_sigma: String,
+    pub(crate) protocol_major_version: u64,
+    pub(crate) protocol_minor_version: u64,
     pub(crate) orphaned: bool,
+    /// The header as received from the node, only stored when syncing with --store-raw-headers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) header_cbor: Option<String>,
+    /// When sync received the header, RFC 3339 with milliseconds. Not known for blocks saved before it was recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) received_at: Option<String>,
+    /// The node sync received the header from, as host:port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source_peer: Option<String>,
+}
+
+/// A receipt time stored as unix milliseconds in RFC 3339 with milliseconds
+pub(crate) fn format_received_at(received_at_ms: u64) -> String {
+    DateTime::from_timestamp_millis(received_at_ms as i64)
+        .map(|received_at| received_at.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_default()
+}
+
+/// The slots in which a pool's blocks carried one operational certificate sequence number
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OpcertHistory {
+    pub(crate) pool_id: String,
+    pub(crate) sequence_number: u64,
+    pub(crate) kes_period: u64,
+    pub(crate) first_slot_number: u64,
+    pub(crate) last_slot_number: u64,
+    /// Set when the sequence number went backwards or skipped ahead compared to the ones seen before it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) anomaly: Option<String>,
+}
+
+/// Compare a block's opcert sequence number with the highest one seen before for its pool. A lower number usually
+/// means a failover node is running with an old opcert. Since babbage (protocol version 7) the ledger also rejects
+/// a number more than one above the last one on chain.
+pub(crate) fn opcert_anomaly(
+    previous_sequence_number: Option<u64>,
+    sequence_number: u64,
+    protocol_major_version: u64,
+) -> Option<String> {
+    let previous_sequence_number = previous_sequence_number?;
+    if sequence_number < previous_sequence_number {
+        Some(format!(
+            "sequence number went back from {previous_sequence_number} to {sequence_number}"
+        ))
+    } else if protocol_major_version >= 7 && sequence_number > previous_sequence_number + 1 {
+        Some(format!(
+            "sequence number jumped from {previous_sequence_number} to {sequence_number}"
+        ))
+    } else {
+        None
+    }
+}
+
+/// A leader schedule saved by leaderlog for one pool and epoch
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SavedSlots {
+    pub(crate) epoch: u64,
+    pub(crate) pool_id: String,
+    pub(crate) slot_qty: u64,
+    pub(crate) slots: String,
+    pub(crate) hash: String,
+    /// None for schedules imported or saved before the inputs were stored
+    #[serde(flatten)]
+    pub(crate) inputs: Option<SlotsInputs>,
+}
+
+/// What leaderlog calculated a saved schedule from, so it can be shown exactly what produced it later
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SlotsInputs {
+    /// The epoch nonce in hex
+    pub(crate) nonce: String,
+    /// The pool's active stake
+    pub(crate) sigma_numerator: u64,
+    /// The total active stake
+    pub(crate) sigma_denominator: u64,
+    pub(crate) d: f64,
+    pub(crate) consensus: String,
+    pub(crate) cncli_version: String,
+}
+
+/// The stake snapshot a leaderlog was calculated with for one pool and epoch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StakeHistory {
+    pub(crate) epoch: u64,
+    pub(crate) pool_id: String,
+    pub(crate) pool_stake: u64,
+    pub(crate) active_stake: u64,
+    pub(crate) sigma: f64,
+}
+
+/// A protocol parameter update proposal relevant to leader schedule calculation, found in a tpraos era block
+#[derive(Debug, Clone)]
+pub(crate) struct ProtocolUpdate {
+    /// The epoch the proposal was submitted for. It takes effect at the start of the following epoch.
+    pub(crate) epoch: u64,
+    pub(crate) slot_number: u64,
+    /// The proposed decentralization parameter as (numerator, denominator)
+    pub(crate) decentralization: Option<(u64, u64)>,
+    /// The proposed extra entropy in hex. An empty string is the neutral nonce.
+    pub(crate) extra_entropy: Option<String>,
+    /// How many genesis delegates proposed these values, None for proposals saved before they were counted
+    pub(crate) votes: Option<u64>,
 }
 
 pub(crate) trait BlockStore {
     fn save_block(&mut self, pending_blocks: &mut Vec<BlockHeader>, shelley_genesis_hash: &str) -> Result<(), Error>;
     fn load_blocks(&mut self) -> Result<Vec<(u64, Vec<u8>)>, Error>;
     fn find_block_by_hash(&mut self, hash_start: &str) -> Result<Option<Block>, Error>;
+
+    /// Get the full header fields of every block, including orphans, whose hash starts with hash_start
+    fn find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, Error>;
+
+    /// Get the full header fields of every block, including orphans, at slot_number
+    fn get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, Error>;
+
+    /// Get all blocks, including orphans, between first_slot and last_slot (inclusive) ordered by slot_number
+    fn get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, Error>;
+
+    /// Get every block, including orphans, at slot_number or at block_number, in the order they were received.
+    /// These are the blocks that competed in a slot or height battle.
+    fn get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, Error>;
+
+    /// Get the full header fields of every block, including orphans, minted by pool_id between first_slot and
+    /// last_slot (inclusive) ordered by slot_number
+    fn get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &PoolId,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error>;
+
+    /// Get the full header fields of every block, including orphans, between first_slot and last_slot (inclusive)
+    /// ordered by slot_number, then orphaned and hash
+    fn get_block_details_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<BlockDetail>, Error>;
     fn get_tip_slot_number(&mut self) -> Result<u64, Error>;
+    /// Fold the sqlite WAL back into the database so it does not keep growing. redb has nothing to do.
+    fn checkpoint(&mut self) -> Result<(), Error>;
+    /// Write a consistent copy of the database to backup_path while it stays open
+    fn backup_to(&mut self, backup_path: &Path) -> Result<(), Error>;
+    /// Get the (major, minor) protocol version from the most recent non-orphaned block header, if any
+    fn get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, Error>;
     fn get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error>;
     fn get_prev_hash_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error>;
-    fn save_slots(&mut self, epoch: u64, pool_id: &str, slot_qty: u64, slots: &str, hash: &str) -> Result<(), Error>;
+    /// Save the slots of the epoch and pool_id with the inputs they were calculated from, if known, replacing any saved
+    /// before
+    fn save_slots(
+        &mut self,
+        epoch: u64,
+        pool_id: &PoolId,
+        slot_qty: u64,
+        slots: &str,
+        hash: &str,
+        inputs: Option<&SlotsInputs>,
+    ) -> Result<(), Error>;
 
     /// Get the number of slots and the hash from the block store for the epoch and pool_id
-    fn get_current_slots(&mut self, epoch: u64, pool_id: &str) -> Result<(u64, String), Error>;
+    fn get_current_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<(u64, String), Error>;
 
     /// Get the previous slots list raw data String from the block store for the epoch and pool_id
-    fn get_previous_slots(&mut self, epoch: u64, pool_id: &str) -> Result<Option<String>, Error>;
+    fn get_previous_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<String>, Error>;
+
+    /// Get the number of slots and the hash for the epoch together with the slots of the epoch before, read
+    /// consistently so a leaderlog saving slots at the same time is never seen half written
+    fn get_slots_to_send(&mut self, epoch: u64, pool_id: &PoolId) -> Result<((u64, String), Option<String>), Error>;
+
+    /// Get the inputs saved with the slots for the epoch and pool_id, None when there are no slots or they were saved
+    /// without them
+    fn get_slots_inputs(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<SlotsInputs>, Error>;
+
+    /// Get every leader schedule saved in the block store ordered by epoch
+    fn get_all_slots(&mut self) -> Result<Vec<SavedSlots>, Error>;
+
+    /// Get the opcert sequence numbers seen for a pool during sync ordered by sequence number
+    fn get_opcert_history(&mut self, pool_id: &PoolId) -> Result<Vec<OpcertHistory>, Error>;
+
+    /// Save the stake a leaderlog was calculated with, replacing any saved for the same epoch and pool_id
+    fn save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), Error>;
+
+    /// Get the stake saved by leaderlog for a pool ordered by epoch
+    fn get_stake_history(&mut self, pool_id: &PoolId) -> Result<Vec<StakeHistory>, Error>;
+
+    /// Save a protocol parameter update proposal captured during sync
+    fn save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), Error>;
+
+    /// Get all protocol parameter update proposals submitted before the given epoch ordered by slot_number
+    fn get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcert_anomaly() {
+        assert_eq!(opcert_anomaly(None, 3, 8), None);
+        assert_eq!(opcert_anomaly(Some(3), 3, 8), None);
+        assert_eq!(opcert_anomaly(Some(3), 4, 8), None);
+        assert_eq!(
+            opcert_anomaly(Some(3), 2, 8),
+            Some("sequence number went back from 3 to 2".to_string())
+        );
+        assert_eq!(
+            opcert_anomaly(Some(3), 6, 8),
+            Some("sequence number jumped from 3 to 6".to_string())
+        );
+        // tpraos allows any increase
+        assert_eq!(opcert_anomaly(Some(3), 6, 6), None);
+    }
+
+    #[test]
+    fn test_format_received_at() {
+        assert_eq!(format_received_at(1_700_000_000_123), "2023-11-14T22:13:20.123Z");
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = std::env::temp_dir().join(format!("cncli_test_open_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        sqlite::SqLiteBlockStore::new(&dir.join("cncli.db")).unwrap();
+        redb::RedbBlockStore::new(&dir.join("cncli.redb")).unwrap();
+        for name in ["cncli.db", "cncli.redb"] {
+            assert!(open(&dir.join(name), true).unwrap().get_all_slots().unwrap().is_empty());
+        }
+
+        // read-only must not create the tables of a database sync never opened
+        let unmigrated = dir.join("unmigrated.db");
+        rusqlite::Connection::open(&unmigrated)
+            .unwrap()
+            .execute_batch("CREATE TABLE other (id INTEGER)")
+            .unwrap();
+        assert!(open(&unmigrated, true).is_err());
+        let tables: i64 = rusqlite::Connection::open(&unmigrated)
+            .unwrap()
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE name = 'db_version'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_block_by_hash_prefix() {
+        let dir = std::env::temp_dir().join(format!("cncli_test_hash_prefix_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let block_stores: Vec<Box<dyn BlockStore>> = vec![
+            Box::new(sqlite::SqLiteBlockStore::new(&dir.join("cncli.db")).unwrap()),
+            Box::new(redb::RedbBlockStore::new(&dir.join("cncli.redb")).unwrap()),
+            Box::new(memory::MemoryBlockStore::new()),
+        ];
+        for mut block_store in block_stores {
+            let header = BlockHeader {
+                block_number: 1,
+                slot_number: 10,
+                hash: vec![0xab; 32],
+                prev_hash: vec![0xcd; 32],
+                node_vkey: vec![1; 32],
+                eta_vrf_0: vec![2; 64],
+                unknown_0: 2,
+                protocol_major_version: 8,
+                ..BlockHeader::default()
+            };
+            block_store
+                .save_block(
+                    &mut vec![header],
+                    "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81",
+                )
+                .unwrap();
+
+            assert_eq!(block_store.find_block_details_by_hash("abab").unwrap().len(), 1);
+            assert!(block_store.find_block_by_hash("abab").unwrap().is_some());
+            // LIKE wildcards in the prefix match only themselves, like starts_with
+            assert!(block_store.find_block_details_by_hash("%").unwrap().is_empty());
+            assert!(block_store.find_block_details_by_hash("ab_b").unwrap().is_empty());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_slots_to_send_in_epoch_0() {
+        let dir = std::env::temp_dir().join(format!("cncli_test_slots_to_send_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let block_stores: Vec<Box<dyn BlockStore>> = vec![
+            Box::new(sqlite::SqLiteBlockStore::new(&dir.join("cncli.db")).unwrap()),
+            Box::new(redb::RedbBlockStore::new(&dir.join("cncli.redb")).unwrap()),
+            Box::new(memory::MemoryBlockStore::new()),
+        ];
+        let pool_id: PoolId = "ab".repeat(28).parse().unwrap();
+        for mut block_store in block_stores {
+            block_store.save_slots(0, &pool_id, 2, "[1,2]", "aa", None).unwrap();
+            // epoch 0 has no previous epoch, its own slots must not be sent as the previous ones
+            assert_eq!(
+                block_store.get_slots_to_send(0, &pool_id).unwrap(),
+                ((2, "aa".to_string()), None)
+            );
+            block_store.save_slots(1, &pool_id, 1, "[3]", "bb", None).unwrap();
+            assert_eq!(
+                block_store.get_slots_to_send(1, &pool_id).unwrap(),
+                ((1, "bb".to_string()), Some("[1,2]".to_string()))
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
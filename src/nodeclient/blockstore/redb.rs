@@ -1,17 +1,29 @@
 use crate::nodeclient::blockstore;
-use crate::nodeclient::blockstore::{Block, BlockStore};
+use crate::nodeclient::blockstore::{
+    opcert_anomaly, Block, BlockDetail, BlockStore, OpcertHistory, ProtocolUpdate, SavedSlots, SlotsInputs,
+    StakeHistory,
+};
+use crate::nodeclient::poolid::PoolId;
 use crate::nodeclient::sync::BlockHeader;
+use bincode::Options;
 use pallas_crypto::hash::{Hash, Hasher};
 use pallas_crypto::nonce::generate_rolling_nonce;
 use redb::{
-    Builder, Database, MultimapTableDefinition, MultimapValue, ReadableMultimapTable, ReadableTable, RepairSession,
-    TableDefinition, TypeName, Value,
+    Builder, Database, Key, MultimapTableDefinition, MultimapValue, ReadOnlyTable, ReadTransaction,
+    ReadableMultimapTable, ReadableTable, RepairSession, TableDefinition, TableHandle, TypeName, Value,
+    WriteTransaction,
 };
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::io::Read;
+use std::marker::PhantomData;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
 
 #[derive(Error, Debug)]
@@ -42,6 +54,78 @@ pub enum Error {
 
     #[error("Data not found")]
     DataNotFound,
+
+    #[error("Db error: {0}")]
+    Db(String),
+}
+
+impl Error {
+    /// Whether the database could not be opened because another process has it open
+    pub(crate) fn is_already_open(&self) -> bool {
+        matches!(self, Error::RedbDb(redb::DatabaseError::DatabaseAlreadyOpen))
+    }
+}
+
+/// Version of the layout of the chain and slots records, stored as the first byte of each record so the layout can
+/// change later without breaking existing databases
+const RECORD_FORMAT_VERSION: u8 = 1;
+
+/// Fixed size little-endian integers, so a database is readable on any host whatever its word size or endianness
+fn record_options() -> impl bincode::Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .allow_trailing_bytes()
+}
+
+fn encode_record<T: Serialize>(record: &T) -> Vec<u8> {
+    let mut bytes = vec![RECORD_FORMAT_VERSION];
+    record_options().serialize_into(&mut bytes, record).unwrap();
+    bytes
+}
+
+fn decode_record<T: DeserializeOwned>(data: &[u8]) -> T {
+    match data.split_first() {
+        Some((&RECORD_FORMAT_VERSION, record)) => record_options().deserialize(record).unwrap(),
+        _ => panic!("Unsupported record format version {:?}", data.first()),
+    }
+}
+
+/// The stored bytes of a record, to rewrite them in a migration without decoding them
+#[derive(Debug)]
+struct RawRecord<T>(PhantomData<T>);
+
+impl<T: Value + 'static> Value for RawRecord<T> {
+    type SelfType<'a> = &'a [u8]
+    where
+        Self: 'a;
+    type AsBytes<'a> = &'a [u8]
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        data
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        value
+    }
+
+    fn type_name() -> TypeName {
+        // the same table, so the same type name as the records it holds
+        T::type_name()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -86,7 +170,7 @@ impl Value for ChainRecord {
     where
         Self: 'a,
     {
-        bincode::deserialize(data).unwrap()
+        decode_record(data)
     }
 
     fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
@@ -94,7 +178,7 @@ impl Value for ChainRecord {
         Self: 'a,
         Self: 'b,
     {
-        bincode::serialize(value).unwrap()
+        encode_record(value)
     }
 
     fn type_name() -> TypeName {
@@ -122,6 +206,45 @@ impl Value for SlotsRecord {
         None
     }
 
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        decode_record(data)
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        encode_record(value)
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new(stringify!(SlotsRecord))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProtocolUpdateRecord {
+    epoch: u64,
+    slot_number: u64,
+    decentralization: Option<(u64, u64)>,
+    extra_entropy: Option<Vec<u8>>,
+}
+
+impl Value for ProtocolUpdateRecord {
+    type SelfType<'a> = Self;
+    type AsBytes<'a> = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        // dynamic sized object. not fixed width
+        None
+    }
+
     fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
     where
         Self: 'a,
@@ -138,7 +261,88 @@ impl Value for SlotsRecord {
     }
 
     fn type_name() -> TypeName {
-        TypeName::new(stringify!(SlotsRecord))
+        TypeName::new(stringify!(ProtocolUpdateRecord))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OpcertRecord {
+    pool_id: Vec<u8>,
+    sequence_number: u64,
+    kes_period: u64,
+    first_slot_number: u64,
+    last_slot_number: u64,
+    anomaly: Option<String>,
+}
+
+impl Value for OpcertRecord {
+    type SelfType<'a> = Self;
+    type AsBytes<'a> = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        // dynamic sized object. not fixed width
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).unwrap()
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        bincode::serialize(value).unwrap()
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new(stringify!(OpcertRecord))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StakeHistoryRecord {
+    epoch: u64,
+    pool_id: Vec<u8>,
+    pool_stake: u64,
+    active_stake: u64,
+    sigma: f64,
+}
+
+impl Value for StakeHistoryRecord {
+    type SelfType<'a> = Self;
+    type AsBytes<'a> = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        // dynamic sized object. not fixed width
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).unwrap()
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        bincode::serialize(value).unwrap()
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new(stringify!(StakeHistoryRecord))
     }
 }
 
@@ -146,12 +350,30 @@ impl Value for SlotsRecord {
 // This sequence is inspired by the PNG magic number.
 const MAGIC_NUMBER: &[u8; 9] = b"redb\x1A\x0A\xA9\x0D\x0A";
 
+// 3k/f slots on mainnet, the furthest back a rollback can reach
+const MAX_ROLLBACK_SLOTS: u64 = 129600;
+
 const VERSION_TABLE: TableDefinition<&str, u16> = TableDefinition::new("version");
 const CHAIN_TABLE: TableDefinition<u128, ChainRecord> = TableDefinition::new("chain");
 const CHAIN_TABLE_SLOT_INDEX: MultimapTableDefinition<u64, u128> = MultimapTableDefinition::new("chain_slot_index");
 const CHAIN_TABLE_HASH_INDEX: MultimapTableDefinition<&[u8], u128> = MultimapTableDefinition::new("chain_hash_index");
 const SLOTS_TABLE: TableDefinition<u128, SlotsRecord> = TableDefinition::new("slots");
 const SLOTS_TABLE_POOL_ID_EPOCH_INDEX: TableDefinition<&[u8], u128> = TableDefinition::new("slots_pool_id_epoch_index");
+const PROTOCOL_UPDATES_TABLE: TableDefinition<u128, ProtocolUpdateRecord> = TableDefinition::new("protocol_updates");
+// keyed by pool_id followed by the big-endian sequence number so a pool's records sort by sequence number
+const OPCERT_HISTORY_TABLE: TableDefinition<&[u8], OpcertRecord> = TableDefinition::new("opcert_history");
+// keyed by pool_id followed by the big-endian epoch so a pool's records sort by epoch
+const STAKE_HISTORY_TABLE: TableDefinition<&[u8], StakeHistoryRecord> = TableDefinition::new("stake_history");
+// the header cbor of blocks synced with --store-raw-headers, keyed like the chain table
+const RAW_HEADER_TABLE: TableDefinition<u128, &[u8]> = TableDefinition::new("raw_header");
+// when sync received each block, as unix milliseconds, and the node it came from, keyed like the chain table
+const BLOCK_RECEIPT_TABLE: TableDefinition<u128, (u64, &str)> = TableDefinition::new("block_receipt");
+// the leaderlog inputs of saved slots as nonce, sigma numerator, sigma denominator, d, consensus and cncli version,
+// keyed like the slots table
+const SLOTS_INPUTS_TABLE: TableDefinition<u128, (&str, u64, u64, f64, &str, &str)> =
+    TableDefinition::new("slots_inputs");
+// how many genesis delegates proposed a protocol update, keyed like the protocol updates table
+const PROTOCOL_UPDATE_VOTES_TABLE: TableDefinition<u128, u64> = TableDefinition::new("protocol_update_votes");
 
 pub(crate) fn is_redb_database(db_path: &Path) -> Result<bool, Error> {
     let mut file = std::fs::File::open(db_path)?;
@@ -160,17 +382,105 @@ pub(crate) fn is_redb_database(db_path: &Path) -> Result<bool, Error> {
     Ok(&magic_number == MAGIC_NUMBER)
 }
 
+// attempts to open a database another process has open before giving up
+const OPEN_ATTEMPTS: u32 = 5;
+
+/// The key of the slots index, the hash of the epoch and pool id
+fn slots_index_key(epoch: u64, pool_id: &str) -> Result<Hash<28>, Error> {
+    let mut hasher = Hasher::<224>::new();
+    hasher.input(&epoch.to_be_bytes());
+    hasher.input(hex::decode(pool_id)?.as_slice());
+    Ok(hasher.finalize())
+}
+
+fn slots_inputs_from_value(value: (&str, u64, u64, f64, &str, &str)) -> SlotsInputs {
+    let (nonce, sigma_numerator, sigma_denominator, d, consensus, cncli_version) = value;
+    SlotsInputs {
+        nonce: nonce.to_string(),
+        sigma_numerator,
+        sigma_denominator,
+        d,
+        consensus: consensus.to_string(),
+        cncli_version: cncli_version.to_string(),
+    }
+}
+
+/// The records copied from one table of a damaged database by salvage_to
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SalvagedTable {
+    pub(crate) table: String,
+    pub(crate) copied: u64,
+    pub(crate) skipped: u64,
+}
+
+/// Index entries that do not match the records they index, counted by check_indexes
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexCheck {
+    pub(crate) chain_records: u64,
+    /// chain records without an entry for their slot or hash
+    pub(crate) missing_slot_index_entries: u64,
+    pub(crate) missing_hash_index_entries: u64,
+    /// index entries pointing to a chain record that does not exist
+    pub(crate) dangling_slot_index_entries: u64,
+    pub(crate) dangling_hash_index_entries: u64,
+    pub(crate) slots_records: u64,
+    pub(crate) missing_slots_index_entries: u64,
+    pub(crate) dangling_slots_index_entries: u64,
+}
+
+impl IndexCheck {
+    pub(crate) fn is_valid(&self) -> bool {
+        self.missing_slot_index_entries == 0
+            && self.missing_hash_index_entries == 0
+            && self.dangling_slot_index_entries == 0
+            && self.dangling_hash_index_entries == 0
+            && self.missing_slots_index_entries == 0
+            && self.dangling_slots_index_entries == 0
+    }
+}
+
 pub struct RedbBlockStore {
     db: Database,
 }
 
 impl RedbBlockStore {
-    const DB_VERSION: u16 = 1;
+    const DB_VERSION: u16 = 9;
 
+    /// Open the database, retrying with backoff while another process such as a leaderlog has it open. Unlike
+    /// sqlite, redb allows only one process to open a database at a time.
     pub fn new(db_path: &Path) -> Result<Self, Error> {
-        let db = Builder::new()
-            .set_repair_callback(Self::repair_callback)
-            .create(db_path)?;
+        Self::open_with_attempts(db_path, OPEN_ATTEMPTS)
+    }
+
+    /// Open the database without waiting for another process to close it. Error::is_already_open tells when one has.
+    pub(crate) fn new_no_wait(db_path: &Path) -> Result<Self, Error> {
+        Self::open_with_attempts(db_path, 1)
+    }
+
+    fn open_with_attempts(db_path: &Path, attempts: u32) -> Result<Self, Error> {
+        let mut backoff = Duration::from_millis(500);
+        let mut attempt = 1;
+        let db = loop {
+            match Builder::new()
+                .set_repair_callback(Self::repair_callback)
+                .create(db_path)
+            {
+                Err(redb::DatabaseError::DatabaseAlreadyOpen) if attempt < attempts => {
+                    warn!(
+                        "Database is open in another process, retrying in {}ms (attempt {} of {})",
+                        backoff.as_millis(),
+                        attempt,
+                        attempts
+                    );
+                    sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => break result?,
+            }
+        };
         Self::migrate(&db)?;
         Ok(Self { db })
     }
@@ -195,15 +505,61 @@ impl RedbBlockStore {
             let write_tx = db.begin_write()?;
             {
                 let mut version_table = write_tx.open_table(VERSION_TABLE)?;
-                info!("Migrating database from version 0 to 1");
+                if current_version < 1 {
+                    info!("Migrating database from version 0 to 1");
+                    // create the chain table if it doesn't exist
+                    write_tx.open_table(CHAIN_TABLE)?;
+                    write_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+                    write_tx.open_multimap_table(CHAIN_TABLE_HASH_INDEX)?;
+                    // create the slots table if it doesn't exist
+                    write_tx.open_table(SLOTS_TABLE)?;
+                    write_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
+                }
+                if current_version < 2 {
+                    info!("Migrating database from version 1 to 2");
+                    // create the protocol updates table if it doesn't exist
+                    write_tx.open_table(PROTOCOL_UPDATES_TABLE)?;
+                }
+                if current_version < 3 {
+                    info!("Migrating database from version 2 to 3");
+                    // create the opcert history table if it doesn't exist
+                    write_tx.open_table(OPCERT_HISTORY_TABLE)?;
+                }
+                if current_version < 4 {
+                    info!("Migrating database from version 3 to 4");
+                    // create the stake history table if it doesn't exist
+                    write_tx.open_table(STAKE_HISTORY_TABLE)?;
+                }
+                if current_version < 5 {
+                    info!("Migrating database from version 4 to 5");
+                    // create the raw header table if it doesn't exist
+                    write_tx.open_table(RAW_HEADER_TABLE)?;
+                }
+                if current_version < 6 {
+                    info!("Migrating database from version 5 to 6");
+                    // create the block receipt table if it doesn't exist
+                    write_tx.open_table(BLOCK_RECEIPT_TABLE)?;
+                }
+                if current_version < 7 {
+                    info!("Migrating database from version 6 to 7");
+                    // the chain and slots records were written by bincode::serialize, which is the fixed size
+                    // little-endian layout of record_options, so only the format version byte is missing
+                    let migrated = Self::prefix_record_format_version::<ChainRecord>(&write_tx, CHAIN_TABLE.name())?;
+                    info!("Added the record format version to {} chain records", migrated);
+                    let migrated = Self::prefix_record_format_version::<SlotsRecord>(&write_tx, SLOTS_TABLE.name())?;
+                    info!("Added the record format version to {} slots records", migrated);
+                }
+                if current_version < 8 {
+                    info!("Migrating database from version 7 to 8");
+                    // create the slots inputs table if it doesn't exist
+                    write_tx.open_table(SLOTS_INPUTS_TABLE)?;
+                }
+                if current_version < 9 {
+                    info!("Migrating database from version 8 to 9");
+                    // create the protocol update votes table if it doesn't exist
+                    write_tx.open_table(PROTOCOL_UPDATE_VOTES_TABLE)?;
+                }
                 version_table.insert("version", Self::DB_VERSION)?;
-                // create the chain table if it doesn't exist
-                write_tx.open_table(CHAIN_TABLE)?;
-                write_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
-                write_tx.open_multimap_table(CHAIN_TABLE_HASH_INDEX)?;
-                // create the slots table if it doesn't exist
-                write_tx.open_table(SLOTS_TABLE)?;
-                write_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
             }
             write_tx.commit()?;
         }
@@ -211,6 +567,37 @@ impl RedbBlockStore {
         Ok(())
     }
 
+    /// Prefix every record of a table keyed by u128 with RECORD_FORMAT_VERSION, in batches so the table is not
+    /// borrowed by an iterator while it is written
+    fn prefix_record_format_version<T: Value + 'static>(
+        write_tx: &WriteTransaction,
+        table_name: &str,
+    ) -> Result<u64, Error> {
+        const BATCH_SIZE: usize = 10_000;
+        let mut table = write_tx.open_table(TableDefinition::<u128, RawRecord<T>>::new(table_name))?;
+        let mut next_key = Some(0u128);
+        let mut migrated = 0u64;
+        while let Some(start_key) = next_key {
+            let mut batch: Vec<(u128, Vec<u8>)> = Vec::with_capacity(BATCH_SIZE);
+            for record in table.range(start_key..)?.take(BATCH_SIZE) {
+                let (key, record) = record?;
+                let mut prefixed = Vec::with_capacity(record.value().len() + 1);
+                prefixed.push(RECORD_FORMAT_VERSION);
+                prefixed.extend_from_slice(record.value());
+                batch.push((key.value(), prefixed));
+            }
+            next_key = match batch.last() {
+                Some((key, _)) if batch.len() == BATCH_SIZE => key.checked_add(1),
+                _ => None,
+            };
+            for (key, record) in batch.iter() {
+                table.insert(key, record.as_slice())?;
+                migrated += 1;
+            }
+        }
+        Ok(migrated)
+    }
+
     fn redb_save_block(
         &mut self,
         pending_blocks: &mut Vec<BlockHeader>,
@@ -224,6 +611,9 @@ impl RedbBlockStore {
             let mut chain_table = write_tx.open_table(CHAIN_TABLE)?;
             let mut chain_table_slot_index = write_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
             let mut chain_table_hash_index = write_tx.open_multimap_table(CHAIN_TABLE_HASH_INDEX)?;
+            let mut opcert_history_table = write_tx.open_table(OPCERT_HISTORY_TABLE)?;
+            let mut raw_header_table = write_tx.open_table(RAW_HEADER_TABLE)?;
+            let mut block_receipt_table = write_tx.open_table(BLOCK_RECEIPT_TABLE)?;
             let mut chain_iter = chain_table.iter()?;
             let mut prev_eta_v: Hash<32> = shelley_genesis_hash.parse()?;
             let mut to_update: Vec<(u128, ChainRecord)> = Vec::new();
@@ -298,6 +688,54 @@ impl RedbBlockStore {
                 chain_table.insert(key, chain_record)?;
                 chain_table_slot_index.insert(block.slot_number, key)?;
                 chain_table_hash_index.insert(block.hash.as_slice(), key)?;
+                if !block.header_cbor.is_empty() {
+                    raw_header_table.insert(key, block.header_cbor.as_slice())?;
+                }
+                if let Some(received_at) = block.received_at {
+                    block_receipt_table.insert(key, (received_at, block.source_peer.as_deref().unwrap_or_default()))?;
+                }
+
+                // track the opcert sequence number of the pool
+                let opcert_key = [&pool_id[..], &block.unknown_0.to_be_bytes()].concat();
+                let existing_record = opcert_history_table
+                    .get(opcert_key.as_slice())?
+                    .map(|record| record.value());
+                let opcert_record = match existing_record {
+                    Some(record) => OpcertRecord {
+                        first_slot_number: record.first_slot_number.min(block.slot_number),
+                        last_slot_number: record.last_slot_number.max(block.slot_number),
+                        ..record
+                    },
+                    None => {
+                        let first_key = [&pool_id[..], &0u64.to_be_bytes()].concat();
+                        let last_key = [&pool_id[..], &u64::MAX.to_be_bytes()].concat();
+                        let previous_sequence_number = opcert_history_table
+                            .range(first_key.as_slice()..=last_key.as_slice())?
+                            .next_back()
+                            .transpose()?
+                            .map(|(_, record)| record.value().sequence_number);
+                        let anomaly =
+                            opcert_anomaly(previous_sequence_number, block.unknown_0, block.protocol_major_version);
+                        if let Some(anomaly) = &anomaly {
+                            warn!(
+                                "Pool {} block {} at slot {}: opcert {}",
+                                hex::encode(pool_id),
+                                block.block_number,
+                                block.slot_number,
+                                anomaly
+                            );
+                        }
+                        OpcertRecord {
+                            pool_id: pool_id.to_vec(),
+                            sequence_number: block.unknown_0,
+                            kes_period: block.unknown_1,
+                            first_slot_number: block.slot_number,
+                            last_slot_number: block.slot_number,
+                            anomaly,
+                        }
+                    }
+                };
+                opcert_history_table.insert(opcert_key.as_slice(), opcert_record)?;
 
                 prev_eta_v = eta_v;
             }
@@ -334,27 +772,260 @@ impl RedbBlockStore {
     fn redb_find_block_by_hash(&mut self, hash_start: &str) -> Result<Option<Block>, Error> {
         let read_tx = self.db.begin_read()?;
         let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let block_receipt_table = read_tx.open_table(BLOCK_RECEIPT_TABLE)?;
         let mut chain_iter = chain_table.iter()?;
         while let Some(record) = chain_iter.next_back() {
-            let (_, chain_record) = record?;
+            let (key, chain_record) = record?;
             let chain_record: ChainRecord = chain_record.value();
             if hex::encode(&chain_record.hash).starts_with(hash_start) {
-                let block = Block {
-                    block_number: chain_record.block_number,
-                    slot_number: chain_record.slot_number,
-                    hash: hex::encode(&chain_record.hash),
-                    prev_hash: hex::encode(&chain_record.prev_hash),
-                    pool_id: hex::encode(&chain_record.pool_id),
-                    leader_vrf: hex::encode(&chain_record.leader_vrf_0),
-                    orphaned: chain_record.orphaned,
-                };
-                return Ok(Some(block));
+                return Ok(Some(Self::block(&chain_record, &block_receipt_table, key.value())?));
             }
         }
 
         Ok(None)
     }
 
+    fn redb_find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let raw_header_table = read_tx.open_table(RAW_HEADER_TABLE)?;
+        let block_receipt_table = read_tx.open_table(BLOCK_RECEIPT_TABLE)?;
+        let mut chain_iter = chain_table.iter()?;
+        let mut block_details: Vec<BlockDetail> = Vec::new();
+        while let Some(record) = chain_iter.next_back() {
+            let (key, chain_record) = record?;
+            let chain_record: ChainRecord = chain_record.value();
+            if hex::encode(&chain_record.hash).starts_with(hash_start) {
+                block_details.push(Self::block_detail(
+                    &chain_record,
+                    &raw_header_table,
+                    &block_receipt_table,
+                    key.value(),
+                )?);
+            }
+        }
+        block_details.sort_by_key(|block_detail| block_detail.orphaned);
+
+        Ok(block_details)
+    }
+
+    fn redb_get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let raw_header_table = read_tx.open_table(RAW_HEADER_TABLE)?;
+        let block_receipt_table = read_tx.open_table(BLOCK_RECEIPT_TABLE)?;
+        let mut block_details: Vec<BlockDetail> = Vec::new();
+        for key in chain_table_slot_index.get(slot_number)? {
+            let key = key?.value();
+            if let Some(chain_record) = chain_table.get(key)? {
+                block_details.push(Self::block_detail(
+                    &chain_record.value(),
+                    &raw_header_table,
+                    &block_receipt_table,
+                    key,
+                )?);
+            }
+        }
+        block_details.sort_by_key(|block_detail| block_detail.orphaned);
+
+        Ok(block_details)
+    }
+
+    /// When and from which node sync received a block, unknown for blocks saved before version 6
+    fn block_receipt(
+        block_receipt_table: &ReadOnlyTable<u128, (u64, &'static str)>,
+        key: u128,
+    ) -> Result<(Option<String>, Option<String>), Error> {
+        Ok(match block_receipt_table.get(key)? {
+            Some(block_receipt) => {
+                let (received_at, source_peer) = block_receipt.value();
+                (
+                    Some(blockstore::format_received_at(received_at)),
+                    Some(source_peer.to_string()).filter(|source_peer| !source_peer.is_empty()),
+                )
+            }
+            None => (None, None),
+        })
+    }
+
+    fn block(
+        chain_record: &ChainRecord,
+        block_receipt_table: &ReadOnlyTable<u128, (u64, &'static str)>,
+        key: u128,
+    ) -> Result<Block, Error> {
+        let (received_at, source_peer) = Self::block_receipt(block_receipt_table, key)?;
+        Ok(Block {
+            block_number: chain_record.block_number,
+            slot_number: chain_record.slot_number,
+            hash: hex::encode(&chain_record.hash),
+            prev_hash: hex::encode(&chain_record.prev_hash),
+            pool_id: hex::encode(&chain_record.pool_id),
+            leader_vrf: hex::encode(&chain_record.leader_vrf_0),
+            eta_v: hex::encode(&chain_record.eta_v),
+            eta_vrf_0: hex::encode(&chain_record.eta_vrf_0),
+            orphaned: chain_record.orphaned,
+            received_at,
+            source_peer,
+        })
+    }
+
+    fn block_detail(
+        chain_record: &ChainRecord,
+        raw_header_table: &ReadOnlyTable<u128, &'static [u8]>,
+        block_receipt_table: &ReadOnlyTable<u128, (u64, &'static str)>,
+        key: u128,
+    ) -> Result<BlockDetail, Error> {
+        let (received_at, source_peer) = Self::block_receipt(block_receipt_table, key)?;
+        Ok(BlockDetail {
+            block_number: chain_record.block_number,
+            slot_number: chain_record.slot_number,
+            hash: hex::encode(&chain_record.hash),
+            prev_hash: hex::encode(&chain_record.prev_hash),
+            pool_id: hex::encode(&chain_record.pool_id),
+            eta_v: hex::encode(&chain_record.eta_v),
+            node_vkey: hex::encode(&chain_record.node_vkey),
+            node_vrf_vkey: hex::encode(&chain_record.node_vrf_vkey),
+            block_vrf_0: hex::encode(&chain_record.block_vrf_0),
+            block_vrf_1: hex::encode(&chain_record.block_vrf_1),
+            eta_vrf_0: hex::encode(&chain_record.eta_vrf_0),
+            eta_vrf_1: hex::encode(&chain_record.eta_vrf_1),
+            leader_vrf_0: hex::encode(&chain_record.leader_vrf_0),
+            leader_vrf_1: hex::encode(&chain_record.leader_vrf_1),
+            block_size: chain_record.block_size,
+            block_body_hash: hex::encode(&chain_record.block_body_hash),
+            opcert_hot_vkey: hex::encode(&chain_record.pool_opcert),
+            opcert_sequence_number: chain_record.unknown_0,
+            opcert_kes_period: chain_record.unknown_1,
+            opcert_sigma: hex::encode(&chain_record.unknown_2),
+            protocol_major_version: chain_record.protocol_major_version,
+            protocol_minor_version: chain_record.protocol_minor_version,
+            orphaned: chain_record.orphaned,
+            header_cbor: raw_header_table
+                .get(key)?
+                .map(|header_cbor| hex::encode(header_cbor.value())),
+            received_at,
+            source_peer,
+        })
+    }
+
+    fn redb_get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let block_receipt_table = read_tx.open_table(BLOCK_RECEIPT_TABLE)?;
+        let mut blocks: Vec<Block> = Vec::new();
+        for result in chain_table_slot_index.range(first_slot..=last_slot)? {
+            let (_, chain_keys) = result?;
+            for key in chain_keys {
+                let key = key?.value();
+                if let Some(chain_record) = chain_table.get(key)? {
+                    let chain_record: ChainRecord = chain_record.value();
+                    blocks.push(Self::block(&chain_record, &block_receipt_table, key)?);
+                }
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    fn redb_get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let block_receipt_table = read_tx.open_table(BLOCK_RECEIPT_TABLE)?;
+        // there is no block_number index, but a block at the same height can't be further away than a rollback
+        let first_slot = slot_number.saturating_sub(MAX_ROLLBACK_SLOTS);
+        let last_slot = slot_number.saturating_add(MAX_ROLLBACK_SLOTS);
+        let mut blocks: Vec<(u128, Block)> = Vec::new();
+        for result in chain_table_slot_index.range(first_slot..=last_slot)? {
+            let (_, chain_keys) = result?;
+            for key in chain_keys {
+                let key = key?.value();
+                if let Some(chain_record) = chain_table.get(key)? {
+                    let chain_record: ChainRecord = chain_record.value();
+                    if chain_record.slot_number != slot_number && chain_record.block_number != block_number {
+                        continue;
+                    }
+                    blocks.push((key, Self::block(&chain_record, &block_receipt_table, key)?));
+                }
+            }
+        }
+        // the keys are v7 uuids made when each block was received, so they sort in the order received
+        blocks.sort_by_key(|(key, _)| *key);
+
+        Ok(blocks.into_iter().map(|(_, block)| block).collect())
+    }
+
+    fn redb_get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &str,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        let pool_id = hex::decode(pool_id)?;
+        let read_tx = self.db.begin_read()?;
+        let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let raw_header_table = read_tx.open_table(RAW_HEADER_TABLE)?;
+        let block_receipt_table = read_tx.open_table(BLOCK_RECEIPT_TABLE)?;
+        let mut block_details: Vec<BlockDetail> = Vec::new();
+        for result in chain_table_slot_index.range(first_slot..=last_slot)? {
+            let (_, chain_keys) = result?;
+            let mut slot_block_details: Vec<BlockDetail> = Vec::new();
+            for key in chain_keys {
+                let key = key?.value();
+                if let Some(chain_record) = chain_table.get(key)? {
+                    let chain_record: ChainRecord = chain_record.value();
+                    if chain_record.pool_id == pool_id {
+                        slot_block_details.push(Self::block_detail(
+                            &chain_record,
+                            &raw_header_table,
+                            &block_receipt_table,
+                            key,
+                        )?);
+                    }
+                }
+            }
+            slot_block_details.sort_by_key(|block_detail| block_detail.orphaned);
+            block_details.extend(slot_block_details);
+        }
+
+        Ok(block_details)
+    }
+
+    fn redb_get_block_details_in_slot_range(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let raw_header_table = read_tx.open_table(RAW_HEADER_TABLE)?;
+        let block_receipt_table = read_tx.open_table(BLOCK_RECEIPT_TABLE)?;
+        let mut block_details: Vec<BlockDetail> = Vec::new();
+        for result in chain_table_slot_index.range(first_slot..=last_slot)? {
+            let (_, chain_keys) = result?;
+            let mut slot_block_details: Vec<BlockDetail> = Vec::new();
+            for key in chain_keys {
+                let key = key?.value();
+                if let Some(chain_record) = chain_table.get(key)? {
+                    slot_block_details.push(Self::block_detail(
+                        &chain_record.value(),
+                        &raw_header_table,
+                        &block_receipt_table,
+                        key,
+                    )?);
+                }
+            }
+            slot_block_details.sort_by(|a, b| a.orphaned.cmp(&b.orphaned).then_with(|| a.hash.cmp(&b.hash)));
+            block_details.extend(slot_block_details);
+        }
+
+        Ok(block_details)
+    }
+
     fn redb_get_tip_slot_number(&mut self) -> Result<u64, Error> {
         let read_tx = self.db.begin_read()?;
         let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
@@ -366,6 +1037,29 @@ impl RedbBlockStore {
         Ok(0)
     }
 
+    fn redb_get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        for result in chain_table_slot_index.iter()?.rev() {
+            let (_, chain_keys) = result?;
+            for key in chain_keys {
+                let key = key?.value();
+                if let Some(chain_record) = chain_table.get(key)? {
+                    let chain_record: ChainRecord = chain_record.value();
+                    if !chain_record.orphaned {
+                        return Ok(Some((
+                            chain_record.protocol_major_version,
+                            chain_record.protocol_minor_version,
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     fn redb_get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
         let read_tx = self.db.begin_read()?;
         let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
@@ -437,25 +1131,20 @@ impl RedbBlockStore {
         slot_qty: u64,
         slots: &str,
         hash: &str,
+        inputs: Option<&SlotsInputs>,
     ) -> Result<(), Error> {
-        // See if record exists already
-        let mut hasher = Hasher::<224>::new();
-        hasher.input(&epoch.to_be_bytes());
-        hasher.input(hex::decode(pool_id)?.as_slice());
-        let index_key = hasher.finalize();
-
-        let read_tx = self.db.begin_read()?;
-        let slots_key = {
-            let slots_table_pool_id_epoch_index = read_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
-            slots_table_pool_id_epoch_index
-                .get(index_key.as_slice())?
-                .map(|key| key.value())
-        };
+        let index_key = slots_index_key(epoch, pool_id)?;
 
         let write_tx = self.db.begin_write()?;
         {
+            // See if record exists already. This is looked up in the write transaction so a concurrent save of the
+            // same epoch and pool cannot add a second record.
+            let slots_key = write_tx
+                .open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?
+                .get(index_key.as_slice())?
+                .map(|key| key.value());
             let mut slots_table = write_tx.open_table(SLOTS_TABLE)?;
-            match slots_key {
+            let key = match slots_key {
                 Some(key) => {
                     // Update existing record
                     let mut slots_record: SlotsRecord = slots_table
@@ -466,6 +1155,7 @@ impl RedbBlockStore {
                     slots_record.slots = slots.to_string();
                     slots_record.hash = hex::decode(hash)?;
                     slots_table.insert(key, slots_record)?;
+                    key
                 }
                 None => {
                     // Add new record and index
@@ -480,6 +1170,27 @@ impl RedbBlockStore {
                     };
                     slots_table.insert(key, slots_record)?;
                     slots_table_pool_id_epoch_index.insert(index_key.as_slice(), key)?;
+                    key
+                }
+            };
+            // replace the inputs of slots saved before so they always describe the saved slots
+            let mut slots_inputs_table = write_tx.open_table(SLOTS_INPUTS_TABLE)?;
+            match inputs {
+                Some(inputs) => {
+                    slots_inputs_table.insert(
+                        key,
+                        (
+                            inputs.nonce.as_str(),
+                            inputs.sigma_numerator,
+                            inputs.sigma_denominator,
+                            inputs.d,
+                            inputs.consensus.as_str(),
+                            inputs.cncli_version.as_str(),
+                        ),
+                    )?;
+                }
+                None => {
+                    slots_inputs_table.remove(key)?;
                 }
             }
         }
@@ -489,10 +1200,7 @@ impl RedbBlockStore {
     }
 
     fn redb_get_current_slots(&mut self, epoch: u64, pool_id: &str) -> Result<(u64, String), Error> {
-        let mut hasher = Hasher::<224>::new();
-        hasher.input(&epoch.to_be_bytes());
-        hasher.input(hex::decode(pool_id)?.as_slice());
-        let index_key = hasher.finalize();
+        let index_key = slots_index_key(epoch, pool_id)?;
 
         let read_tx = self.db.begin_read()?;
         let slots_table_pool_id_epoch_index = read_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
@@ -510,11 +1218,37 @@ impl RedbBlockStore {
         Ok((slots_record.slot_qty, hex::encode(slots_record.hash)))
     }
 
+    fn redb_get_slots_to_send(&mut self, epoch: u64, pool_id: &str) -> Result<((u64, String), Option<String>), Error> {
+        let read_tx = self.db.begin_read()?;
+        let slots_table_pool_id_epoch_index = read_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
+        let slots_table = read_tx.open_table(SLOTS_TABLE)?;
+        let get_slots_record = |epoch: u64| -> Result<Option<SlotsRecord>, Error> {
+            match slots_table_pool_id_epoch_index
+                .get(slots_index_key(epoch, pool_id)?.as_slice())?
+                .map(|key| key.value())
+            {
+                Some(slots_key) => Ok(Some(
+                    slots_table
+                        .get(slots_key)?
+                        .map(|record| record.value())
+                        .ok_or(Error::DataNotFound)?,
+                )),
+                None => Ok(None),
+            }
+        };
+        let current_slots = get_slots_record(epoch)?.ok_or(Error::DataNotFound)?;
+        let previous_slots = match epoch.checked_sub(1) {
+            Some(previous_epoch) => get_slots_record(previous_epoch)?,
+            None => None,
+        };
+        Ok((
+            (current_slots.slot_qty, hex::encode(current_slots.hash)),
+            previous_slots.map(|slots_record| slots_record.slots),
+        ))
+    }
+
     fn redb_get_previous_slots(&mut self, epoch: u64, pool_id: &str) -> Result<Option<String>, Error> {
-        let mut hasher = Hasher::<224>::new();
-        hasher.input(&epoch.to_be_bytes());
-        hasher.input(hex::decode(pool_id)?.as_slice());
-        let index_key = hasher.finalize();
+        let index_key = slots_index_key(epoch, pool_id)?;
 
         let read_tx = self.db.begin_read()?;
         let slots_table_pool_id_epoch_index = read_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
@@ -532,6 +1266,402 @@ impl RedbBlockStore {
             Ok(None)
         }
     }
+
+    fn redb_get_slots_inputs(&mut self, epoch: u64, pool_id: &str) -> Result<Option<SlotsInputs>, Error> {
+        let index_key = slots_index_key(epoch, pool_id)?;
+
+        let read_tx = self.db.begin_read()?;
+        let slots_table_pool_id_epoch_index = read_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
+        match slots_table_pool_id_epoch_index
+            .get(index_key.as_slice())?
+            .map(|key| key.value())
+        {
+            Some(slots_key) => {
+                let slots_inputs_table = read_tx.open_table(SLOTS_INPUTS_TABLE)?;
+                Ok(slots_inputs_table
+                    .get(slots_key)?
+                    .map(|inputs| slots_inputs_from_value(inputs.value())))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn redb_get_all_slots(&mut self) -> Result<Vec<SavedSlots>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let slots_table = read_tx.open_table(SLOTS_TABLE)?;
+        let slots_inputs_table = read_tx.open_table(SLOTS_INPUTS_TABLE)?;
+        let mut saved_slots: Vec<SavedSlots> = Vec::new();
+        for result in slots_table.iter()? {
+            let (key, slots_record) = result?;
+            let inputs = slots_inputs_table
+                .get(key.value())?
+                .map(|inputs| slots_inputs_from_value(inputs.value()));
+            let slots_record: SlotsRecord = slots_record.value();
+            saved_slots.push(SavedSlots {
+                epoch: slots_record.epoch,
+                pool_id: hex::encode(&slots_record.pool_id),
+                slot_qty: slots_record.slot_qty,
+                slots: slots_record.slots,
+                hash: hex::encode(&slots_record.hash),
+                inputs,
+            });
+        }
+        saved_slots.sort_by(|a, b| a.epoch.cmp(&b.epoch).then_with(|| a.pool_id.cmp(&b.pool_id)));
+
+        Ok(saved_slots)
+    }
+
+    fn redb_get_opcert_history(&mut self, pool_id: &str) -> Result<Vec<OpcertHistory>, Error> {
+        let pool_id = hex::decode(pool_id)?;
+        let first_key = [pool_id.as_slice(), &0u64.to_be_bytes()].concat();
+        let last_key = [pool_id.as_slice(), &u64::MAX.to_be_bytes()].concat();
+        let read_tx = self.db.begin_read()?;
+        let opcert_history_table = read_tx.open_table(OPCERT_HISTORY_TABLE)?;
+        let mut opcert_history: Vec<OpcertHistory> = Vec::new();
+        for result in opcert_history_table.range(first_key.as_slice()..=last_key.as_slice())? {
+            let (_, record) = result?;
+            let record: OpcertRecord = record.value();
+            opcert_history.push(OpcertHistory {
+                pool_id: hex::encode(&record.pool_id),
+                sequence_number: record.sequence_number,
+                kes_period: record.kes_period,
+                first_slot_number: record.first_slot_number,
+                last_slot_number: record.last_slot_number,
+                anomaly: record.anomaly,
+            });
+        }
+
+        Ok(opcert_history)
+    }
+
+    fn redb_save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), Error> {
+        let pool_id = hex::decode(&stake_history.pool_id)?;
+        let key = [pool_id.as_slice(), &stake_history.epoch.to_be_bytes()].concat();
+        let write_tx = self.db.begin_write()?;
+        {
+            let mut stake_history_table = write_tx.open_table(STAKE_HISTORY_TABLE)?;
+            stake_history_table.insert(
+                key.as_slice(),
+                StakeHistoryRecord {
+                    epoch: stake_history.epoch,
+                    pool_id,
+                    pool_stake: stake_history.pool_stake,
+                    active_stake: stake_history.active_stake,
+                    sigma: stake_history.sigma,
+                },
+            )?;
+        }
+        write_tx.commit()?;
+
+        Ok(())
+    }
+
+    fn redb_get_stake_history(&mut self, pool_id: &str) -> Result<Vec<StakeHistory>, Error> {
+        let pool_id = hex::decode(pool_id)?;
+        let first_key = [pool_id.as_slice(), &0u64.to_be_bytes()].concat();
+        let last_key = [pool_id.as_slice(), &u64::MAX.to_be_bytes()].concat();
+        let read_tx = self.db.begin_read()?;
+        let stake_history_table = read_tx.open_table(STAKE_HISTORY_TABLE)?;
+        let mut stake_history: Vec<StakeHistory> = Vec::new();
+        for result in stake_history_table.range(first_key.as_slice()..=last_key.as_slice())? {
+            let (_, record) = result?;
+            let record: StakeHistoryRecord = record.value();
+            stake_history.push(StakeHistory {
+                epoch: record.epoch,
+                pool_id: hex::encode(&record.pool_id),
+                pool_stake: record.pool_stake,
+                active_stake: record.active_stake,
+                sigma: record.sigma,
+            });
+        }
+
+        Ok(stake_history)
+    }
+
+    fn redb_save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), Error> {
+        let protocol_update_record = ProtocolUpdateRecord {
+            epoch: protocol_update.epoch,
+            slot_number: protocol_update.slot_number,
+            decentralization: protocol_update.decentralization,
+            extra_entropy: match &protocol_update.extra_entropy {
+                Some(extra_entropy) => Some(hex::decode(extra_entropy)?),
+                None => None,
+            },
+        };
+
+        let write_tx = self.db.begin_write()?;
+        {
+            let mut protocol_updates_table = write_tx.open_table(PROTOCOL_UPDATES_TABLE)?;
+            let mut protocol_update_votes_table = write_tx.open_table(PROTOCOL_UPDATE_VOTES_TABLE)?;
+            // replace any proposal we already stored for this slot (re-sync after a rollback)
+            let mut to_remove: Vec<u128> = Vec::new();
+            for result in protocol_updates_table.iter()? {
+                let (key, record) = result?;
+                let record: ProtocolUpdateRecord = record.value();
+                if record.epoch == protocol_update.epoch && record.slot_number == protocol_update.slot_number {
+                    to_remove.push(key.value());
+                }
+            }
+            for key in to_remove {
+                protocol_updates_table.remove(key)?;
+                protocol_update_votes_table.remove(key)?;
+            }
+            let key = Uuid::now_v7().as_u128();
+            protocol_updates_table.insert(key, protocol_update_record)?;
+            if let Some(votes) = protocol_update.votes {
+                protocol_update_votes_table.insert(key, votes)?;
+            }
+        }
+        write_tx.commit()?;
+
+        Ok(())
+    }
+
+    fn redb_get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, Error> {
+        let read_tx = self.db.begin_read()?;
+        let protocol_updates_table = read_tx.open_table(PROTOCOL_UPDATES_TABLE)?;
+        let protocol_update_votes_table = read_tx.open_table(PROTOCOL_UPDATE_VOTES_TABLE)?;
+        let mut protocol_updates: Vec<ProtocolUpdate> = Vec::new();
+        for result in protocol_updates_table.iter()? {
+            let (key, record) = result?;
+            let record: ProtocolUpdateRecord = record.value();
+            if record.epoch < epoch {
+                protocol_updates.push(ProtocolUpdate {
+                    epoch: record.epoch,
+                    slot_number: record.slot_number,
+                    decentralization: record.decentralization,
+                    extra_entropy: record.extra_entropy.map(hex::encode),
+                    votes: protocol_update_votes_table.get(key.value())?.map(|votes| votes.value()),
+                });
+            }
+        }
+        protocol_updates.sort_by_key(|protocol_update| protocol_update.slot_number);
+
+        Ok(protocol_updates)
+    }
+
+    /// Open the database for db repair-redb. Unlike new, this never creates the file and logs each percent of
+    /// redb's repair as it goes instead of only the raw progress. Returns whether the database needed a repair.
+    pub(crate) fn open_for_repair(db_path: &Path) -> Result<(Self, bool), Error> {
+        let repaired = Arc::new(AtomicBool::new(false));
+        let last_percent = Arc::new(AtomicU64::new(u64::MAX));
+        let started = Instant::now();
+        let callback_repaired = repaired.clone();
+        let db = Builder::new()
+            .set_repair_callback(move |session: &mut RepairSession| {
+                callback_repaired.store(true, Ordering::Relaxed);
+                let percent = (session.progress() * 100.0) as u64;
+                if last_percent.swap(percent, Ordering::Relaxed) != percent {
+                    info!(
+                        "Redb repair {}% done after {:.1}s",
+                        percent,
+                        started.elapsed().as_secs_f64()
+                    );
+                }
+            })
+            .open(db_path)?;
+        if repaired.load(Ordering::Relaxed) {
+            info!("Redb repair finished after {:.1}s", started.elapsed().as_secs_f64());
+        }
+        Self::migrate(&db)?;
+        Ok((Self { db }, repaired.load(Ordering::Relaxed)))
+    }
+
+    /// Run redb's integrity check over the whole file, repairing it if possible. Returns false if it was repaired.
+    pub(crate) fn check_integrity(&mut self) -> Result<bool, Error> {
+        let started = Instant::now();
+        info!("Checking the integrity of the database");
+        let intact = self.db.check_integrity()?;
+        info!(
+            "Integrity check finished after {:.1}s, {}",
+            started.elapsed().as_secs_f64(),
+            if intact { "no problems found" } else { "the database was repaired" }
+        );
+        Ok(intact)
+    }
+
+    /// Check that the chain and slots indexes point at exactly the records they index
+    pub(crate) fn check_indexes(&self) -> Result<IndexCheck, Error> {
+        let mut index_check = IndexCheck::default();
+        let read_tx = self.db.begin_read()?;
+        let chain_table = read_tx.open_table(CHAIN_TABLE)?;
+        let chain_table_slot_index = read_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+        let chain_table_hash_index = read_tx.open_multimap_table(CHAIN_TABLE_HASH_INDEX)?;
+        for chain_record in chain_table.iter()? {
+            let (key, chain_record) = chain_record?;
+            let key = key.value();
+            let chain_record: ChainRecord = chain_record.value();
+            index_check.chain_records += 1;
+            if !Self::multimap_contains(chain_table_slot_index.get(chain_record.slot_number)?, key)? {
+                index_check.missing_slot_index_entries += 1;
+            }
+            if !Self::multimap_contains(chain_table_hash_index.get(chain_record.hash.as_slice())?, key)? {
+                index_check.missing_hash_index_entries += 1;
+            }
+            if index_check.chain_records % 1_000_000 == 0 {
+                info!("Checked the indexes of {} chain records", index_check.chain_records);
+            }
+        }
+        for entry in chain_table_slot_index.iter()? {
+            let (_, keys) = entry?;
+            for key in keys {
+                if chain_table.get(key?.value())?.is_none() {
+                    index_check.dangling_slot_index_entries += 1;
+                }
+            }
+        }
+        for entry in chain_table_hash_index.iter()? {
+            let (_, keys) = entry?;
+            for key in keys {
+                if chain_table.get(key?.value())?.is_none() {
+                    index_check.dangling_hash_index_entries += 1;
+                }
+            }
+        }
+
+        let slots_table = read_tx.open_table(SLOTS_TABLE)?;
+        let slots_table_pool_id_epoch_index = read_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
+        for slots_record in slots_table.iter()? {
+            let (key, slots_record) = slots_record?;
+            let slots_record: SlotsRecord = slots_record.value();
+            index_check.slots_records += 1;
+            let index_key = slots_index_key(slots_record.epoch, &hex::encode(&slots_record.pool_id))?;
+            let indexed_key = slots_table_pool_id_epoch_index
+                .get(index_key.as_slice())?
+                .map(|indexed_key| indexed_key.value());
+            if indexed_key != Some(key.value()) {
+                index_check.missing_slots_index_entries += 1;
+            }
+        }
+        for entry in slots_table_pool_id_epoch_index.iter()? {
+            let (_, key) = entry?;
+            if slots_table.get(key.value())?.is_none() {
+                index_check.dangling_slots_index_entries += 1;
+            }
+        }
+
+        Ok(index_check)
+    }
+
+    fn multimap_contains(keys: MultimapValue<u128>, key: u128) -> Result<bool, Error> {
+        for indexed_key in keys {
+            if indexed_key?.value() == key {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Write a consistent copy of the database to backup_path. The records are copied from a single read transaction,
+    /// so the copy is of one committed state however long it takes.
+    pub fn backup_to(&self, backup_path: &Path) -> Result<(), Error> {
+        let skipped: u64 = self.salvage_to(backup_path)?.iter().map(|salvaged| salvaged.skipped).sum();
+        if skipped > 0 {
+            return Err(Error::Db(format!("{skipped} records could not be read, run db repair-redb first")));
+        }
+        Ok(())
+    }
+
+    /// Copy every record that can still be read into a new database at path, skipping the ones that cannot. The
+    /// chain and slots indexes are rebuilt from the copied records rather than copied, so they match them.
+    pub(crate) fn salvage_to(&self, path: &Path) -> Result<Vec<SalvagedTable>, Error> {
+        let target = Self::new(path)?;
+        let read_tx = self.db.begin_read()?;
+        let write_tx = target.db.begin_write()?;
+        let mut salvaged_tables = vec![];
+        {
+            let mut salvaged = SalvagedTable {
+                table: CHAIN_TABLE.name().to_string(),
+                ..Default::default()
+            };
+            let source_table = read_tx.open_table(CHAIN_TABLE)?;
+            let mut chain_table = write_tx.open_table(CHAIN_TABLE)?;
+            let mut chain_table_slot_index = write_tx.open_multimap_table(CHAIN_TABLE_SLOT_INDEX)?;
+            let mut chain_table_hash_index = write_tx.open_multimap_table(CHAIN_TABLE_HASH_INDEX)?;
+            for chain_record in source_table.iter()? {
+                match chain_record {
+                    Ok((key, chain_record)) => {
+                        let key = key.value();
+                        let chain_record: ChainRecord = chain_record.value();
+                        chain_table.insert(key, &chain_record)?;
+                        chain_table_slot_index.insert(chain_record.slot_number, key)?;
+                        chain_table_hash_index.insert(chain_record.hash.as_slice(), key)?;
+                        salvaged.copied += 1;
+                    }
+                    Err(error) => {
+                        warn!("Skipping an unreadable record of table {}: {}", salvaged.table, error);
+                        salvaged.skipped += 1;
+                    }
+                }
+            }
+            info!("Copied {} records of table {}", salvaged.copied, salvaged.table);
+            salvaged_tables.push(salvaged);
+
+            let mut salvaged = SalvagedTable {
+                table: SLOTS_TABLE.name().to_string(),
+                ..Default::default()
+            };
+            let source_table = read_tx.open_table(SLOTS_TABLE)?;
+            let mut slots_table = write_tx.open_table(SLOTS_TABLE)?;
+            let mut slots_table_pool_id_epoch_index = write_tx.open_table(SLOTS_TABLE_POOL_ID_EPOCH_INDEX)?;
+            for slots_record in source_table.iter()? {
+                match slots_record {
+                    Ok((key, slots_record)) => {
+                        let key = key.value();
+                        let slots_record: SlotsRecord = slots_record.value();
+                        let index_key = slots_index_key(slots_record.epoch, &hex::encode(&slots_record.pool_id))?;
+                        slots_table.insert(key, &slots_record)?;
+                        slots_table_pool_id_epoch_index.insert(index_key.as_slice(), key)?;
+                        salvaged.copied += 1;
+                    }
+                    Err(error) => {
+                        warn!("Skipping an unreadable record of table {}: {}", salvaged.table, error);
+                        salvaged.skipped += 1;
+                    }
+                }
+            }
+            info!("Copied {} records of table {}", salvaged.copied, salvaged.table);
+            salvaged_tables.push(salvaged);
+
+            salvaged_tables.push(Self::salvage_table(&read_tx, &write_tx, PROTOCOL_UPDATES_TABLE)?);
+            salvaged_tables.push(Self::salvage_table(&read_tx, &write_tx, OPCERT_HISTORY_TABLE)?);
+            salvaged_tables.push(Self::salvage_table(&read_tx, &write_tx, STAKE_HISTORY_TABLE)?);
+            salvaged_tables.push(Self::salvage_table(&read_tx, &write_tx, RAW_HEADER_TABLE)?);
+            salvaged_tables.push(Self::salvage_table(&read_tx, &write_tx, BLOCK_RECEIPT_TABLE)?);
+            salvaged_tables.push(Self::salvage_table(&read_tx, &write_tx, SLOTS_INPUTS_TABLE)?);
+            salvaged_tables.push(Self::salvage_table(&read_tx, &write_tx, PROTOCOL_UPDATE_VOTES_TABLE)?);
+        }
+        write_tx.commit()?;
+
+        Ok(salvaged_tables)
+    }
+
+    fn salvage_table<K: Key + 'static, V: Value + 'static>(
+        read_tx: &ReadTransaction,
+        write_tx: &WriteTransaction,
+        definition: TableDefinition<K, V>,
+    ) -> Result<SalvagedTable, Error> {
+        let mut salvaged = SalvagedTable {
+            table: definition.name().to_string(),
+            ..Default::default()
+        };
+        let source_table = read_tx.open_table(definition)?;
+        let mut table = write_tx.open_table(definition)?;
+        for record in source_table.iter()? {
+            match record {
+                Ok((key, value)) => {
+                    table.insert(key.value(), value.value())?;
+                    salvaged.copied += 1;
+                }
+                Err(error) => {
+                    warn!("Skipping an unreadable record of table {}: {}", salvaged.table, error);
+                    salvaged.skipped += 1;
+                }
+            }
+        }
+        info!("Copied {} records of table {}", salvaged.copied, salvaged.table);
+        Ok(salvaged)
+    }
 }
 
 impl BlockStore for RedbBlockStore {
@@ -551,10 +1681,56 @@ impl BlockStore for RedbBlockStore {
         Ok(self.redb_find_block_by_hash(hash_start)?)
     }
 
+    fn find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.redb_find_block_details_by_hash(hash_start)?)
+    }
+
+    fn get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.redb_get_block_details_at_slot(slot_number)?)
+    }
+
+    fn get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, blockstore::Error> {
+        Ok(self.redb_get_blocks_in_slot_range(first_slot, last_slot)?)
+    }
+
+    fn get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, blockstore::Error> {
+        Ok(self.redb_get_competing_blocks(slot_number, block_number)?)
+    }
+
+    fn get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &PoolId,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.redb_get_pool_block_details_in_slot_range(pool_id.as_str(), first_slot, last_slot)?)
+    }
+
+    fn get_block_details_in_slot_range(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.redb_get_block_details_in_slot_range(first_slot, last_slot)?)
+    }
+
     fn get_tip_slot_number(&mut self) -> Result<u64, blockstore::Error> {
         Ok(self.redb_get_tip_slot_number()?)
     }
 
+    fn checkpoint(&mut self) -> Result<(), blockstore::Error> {
+        // every commit is durable, there is no log to fold back into the database
+        Ok(())
+    }
+
+    fn backup_to(&mut self, backup_path: &Path) -> Result<(), blockstore::Error> {
+        Ok(RedbBlockStore::backup_to(self, backup_path)?)
+    }
+
+    fn get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, blockstore::Error> {
+        Ok(self.redb_get_tip_protocol_version()?)
+    }
+
     fn get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, blockstore::Error> {
         Ok(self.redb_get_eta_v_before_slot(slot_number)?)
     }
@@ -566,19 +1742,112 @@ impl BlockStore for RedbBlockStore {
     fn save_slots(
         &mut self,
         epoch: u64,
-        pool_id: &str,
+        pool_id: &PoolId,
         slot_qty: u64,
         slots: &str,
         hash: &str,
+        inputs: Option<&SlotsInputs>,
     ) -> Result<(), blockstore::Error> {
-        Ok(self.redb_save_slots(epoch, pool_id, slot_qty, slots, hash)?)
+        Ok(self.redb_save_slots(epoch, pool_id.as_str(), slot_qty, slots, hash, inputs)?)
+    }
+
+    fn get_current_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<(u64, String), blockstore::Error> {
+        Ok(self.redb_get_current_slots(epoch, pool_id.as_str())?)
+    }
+
+    fn get_previous_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<String>, blockstore::Error> {
+        Ok(self.redb_get_previous_slots(epoch, pool_id.as_str())?)
+    }
+
+    fn get_slots_to_send(
+        &mut self,
+        epoch: u64,
+        pool_id: &PoolId,
+    ) -> Result<((u64, String), Option<String>), blockstore::Error> {
+        Ok(self.redb_get_slots_to_send(epoch, pool_id.as_str())?)
+    }
+
+    fn get_slots_inputs(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<SlotsInputs>, blockstore::Error> {
+        Ok(self.redb_get_slots_inputs(epoch, pool_id.as_str())?)
+    }
+
+    fn get_all_slots(&mut self) -> Result<Vec<SavedSlots>, blockstore::Error> {
+        Ok(self.redb_get_all_slots()?)
     }
 
-    fn get_current_slots(&mut self, epoch: u64, pool_id: &str) -> Result<(u64, String), blockstore::Error> {
-        Ok(self.redb_get_current_slots(epoch, pool_id)?)
+    fn get_opcert_history(&mut self, pool_id: &PoolId) -> Result<Vec<OpcertHistory>, blockstore::Error> {
+        Ok(self.redb_get_opcert_history(pool_id.as_str())?)
     }
 
-    fn get_previous_slots(&mut self, epoch: u64, pool_id: &str) -> Result<Option<String>, blockstore::Error> {
-        Ok(self.redb_get_previous_slots(epoch, pool_id)?)
+    fn save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), blockstore::Error> {
+        Ok(self.redb_save_stake_history(stake_history)?)
+    }
+
+    fn get_stake_history(&mut self, pool_id: &PoolId) -> Result<Vec<StakeHistory>, blockstore::Error> {
+        Ok(self.redb_get_stake_history(pool_id.as_str())?)
+    }
+
+    fn save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), blockstore::Error> {
+        Ok(self.redb_save_protocol_update(protocol_update)?)
+    }
+
+    fn get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, blockstore::Error> {
+        Ok(self.redb_get_protocol_updates_before_epoch(epoch)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redb::backends::InMemoryBackend;
+
+    #[test]
+    fn test_record_format_version_migration() {
+        let slots_record = SlotsRecord {
+            epoch: 500,
+            pool_id: vec![0xbe; 28],
+            slot_qty: 2,
+            slots: "[1,2]".to_string(),
+            hash: vec![0xef; 32],
+        };
+        let encoded = encode_record(&slots_record);
+        assert_eq!(encoded[0], RECORD_FORMAT_VERSION);
+        // the epoch is the first field, a little-endian u64 on every host
+        assert_eq!(&encoded[1..9], &500u64.to_le_bytes());
+        assert_eq!(&encoded[1..], bincode::serialize(&slots_record).unwrap().as_slice());
+
+        // a version 6 database holds the records without the format version byte
+        let db = Builder::new().create_with_backend(InMemoryBackend::new()).unwrap();
+        let write_tx = db.begin_write().unwrap();
+        {
+            let mut version_table = write_tx.open_table(VERSION_TABLE).unwrap();
+            version_table.insert("version", 6).unwrap();
+            let mut slots_table = write_tx
+                .open_table(TableDefinition::<u128, RawRecord<SlotsRecord>>::new(SLOTS_TABLE.name()))
+                .unwrap();
+            let legacy = bincode::serialize(&slots_record).unwrap();
+            for key in 0..3u128 {
+                slots_table.insert(key, legacy.as_slice()).unwrap();
+            }
+        }
+        write_tx.commit().unwrap();
+
+        RedbBlockStore::migrate(&db).unwrap();
+
+        let read_tx = db.begin_read().unwrap();
+        let version_table = read_tx.open_table(VERSION_TABLE).unwrap();
+        assert_eq!(
+            version_table.get("version").unwrap().unwrap().value(),
+            RedbBlockStore::DB_VERSION
+        );
+        let slots_table = read_tx.open_table(SLOTS_TABLE).unwrap();
+        assert_eq!(slots_table.len().unwrap(), 3);
+        for record in slots_table.iter().unwrap() {
+            let migrated = record.unwrap().1.value();
+            assert_eq!(migrated.epoch, slots_record.epoch);
+            assert_eq!(migrated.pool_id, slots_record.pool_id);
+            assert_eq!(migrated.slots, slots_record.slots);
+            assert_eq!(migrated.hash, slots_record.hash);
+        }
     }
 }
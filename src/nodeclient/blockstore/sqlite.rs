@@ -1,13 +1,35 @@
 use crate::nodeclient::blockstore;
-use crate::nodeclient::blockstore::{Block, BlockStore};
+use crate::nodeclient::blockstore::{
+    opcert_anomaly, Block, BlockDetail, BlockStore, OpcertHistory, ProtocolUpdate, SavedSlots, SlotsInputs,
+    StakeHistory,
+};
+use crate::nodeclient::poolid::PoolId;
 use crate::nodeclient::sync::BlockHeader;
 use pallas_crypto::hash::{Hash, Hasher};
 use pallas_crypto::nonce::generate_rolling_nonce;
-use rusqlite::{named_params, Connection, OptionalExtension};
+use rusqlite::{named_params, Connection, ErrorCode, OpenFlags, OptionalExtension};
 use std::path::Path;
 use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
 use thiserror::Error;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+/// How long sqlite waits on a lock held by another connection (e.g. a running sync) before giving up
+pub const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+
+// column order read by block_detail_from_row
+const BLOCK_DETAIL_COLUMNS: &str = "block_number,slot_number,hash,prev_hash,pool_id,eta_v,node_vkey,node_vrf_vkey,\
+    block_vrf_0,block_vrf_1,eta_vrf_0,eta_vrf_1,leader_vrf_0,leader_vrf_1,block_size,block_body_hash,pool_opcert,\
+    unknown_0,unknown_1,unknown_2,protocol_major_version,protocol_minor_version,orphaned,header_cbor,received_at,\
+    source_peer";
+
+// column order read by block_from_row
+const BLOCK_COLUMNS: &str =
+    "block_number,slot_number,hash,prev_hash,pool_id,leader_vrf_0,orphaned,eta_v,eta_vrf_0,received_at,source_peer";
+
+/// How many times opening the database is attempted when it stays locked past the busy timeout
+const OPEN_ATTEMPTS: u32 = 5;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -16,18 +38,150 @@ pub enum Error {
 
     #[error("FromHex error: {0}")]
     FromHex(#[from] hex::FromHexError),
+
+    #[error(
+        "Database is at version {0} but version {1} is required. Run sync once to upgrade it before using --read-only"
+    )]
+    ReadOnlyVersion(i64, i64),
+}
+
+/// Pragmas sync sets on its connection to trade durability and memory for write speed. None keeps the sqlite default.
+#[derive(Debug, Clone, Default)]
+pub struct SqliteTuning {
+    /// How many pages the WAL grows to before sqlite checkpoints it on commit
+    pub wal_autocheckpoint: Option<u32>,
+    /// off, normal, full or extra
+    pub synchronous: Option<String>,
+    /// Pages when positive, KiB when negative, like PRAGMA cache_size
+    pub cache_size: Option<i64>,
+}
+
+fn is_busy(error: &Error) -> bool {
+    match error {
+        Error::Sqlite(error) => matches!(
+            error.sqlite_error_code(),
+            Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked)
+        ),
+        _ => false,
+    }
+}
+
+/// Read the leaderlog inputs from the 6 slots columns starting at first, None when they weren't saved
+fn slots_inputs_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Option<SlotsInputs>> {
+    let nonce: Option<String> = row.get(first)?;
+    Ok(match nonce {
+        Some(nonce) => Some(SlotsInputs {
+            nonce,
+            sigma_numerator: row.get(first + 1)?,
+            sigma_denominator: row.get(first + 2)?,
+            d: row.get(first + 3)?,
+            consensus: row.get(first + 4)?,
+            cncli_version: row.get(first + 5)?,
+        }),
+        None => None,
+    })
 }
 
 pub struct SqLiteBlockStore {
     pub db: Connection,
 }
 
+/// A LIKE pattern for the values starting with prefix. The wildcards % and _ in prefix are escaped with a backslash,
+/// so a partial hash matches like starts_with does in the other block stores.
+fn like_prefix(prefix: &str) -> String {
+    let mut like = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    like.push('%');
+    like
+}
+
 impl SqLiteBlockStore {
-    const DB_VERSION: i64 = 4;
+    const DB_VERSION: i64 = 11;
 
     pub fn new(db_path: &Path) -> Result<SqLiteBlockStore, Error> {
+        Self::new_with_options(db_path, DEFAULT_BUSY_TIMEOUT_MS, false)
+    }
+
+    /// Open the database, retrying with backoff while another process holds a lock on it. A read-only store
+    /// never writes or migrates the database so it can be used next to a running sync. It does not use sqlite's
+    /// immutable mode because that ignores the WAL and would miss blocks sync has not checkpointed yet.
+    pub fn new_with_options(db_path: &Path, busy_timeout_ms: u64, read_only: bool) -> Result<SqLiteBlockStore, Error> {
+        let mut backoff = Duration::from_millis(500);
+        let mut attempt = 1;
+        loop {
+            let result = if read_only {
+                Self::open_read_only(db_path, busy_timeout_ms)
+            } else {
+                Self::open(db_path, busy_timeout_ms)
+            };
+            match result {
+                Err(error) if attempt < OPEN_ATTEMPTS && is_busy(&error) => {
+                    warn!(
+                        "Database is locked, retrying in {}ms (attempt {} of {})",
+                        backoff.as_millis(),
+                        attempt,
+                        OPEN_ATTEMPTS
+                    );
+                    sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Set the pragmas of a tuning on this connection
+    pub fn tune(&self, tuning: &SqliteTuning) -> Result<(), Error> {
+        if let Some(wal_autocheckpoint) = tuning.wal_autocheckpoint {
+            self.db.pragma_update(None, "wal_autocheckpoint", wal_autocheckpoint)?;
+        }
+        if let Some(ref synchronous) = tuning.synchronous {
+            self.db.pragma_update(None, "synchronous", synchronous)?;
+        }
+        if let Some(cache_size) = tuning.cache_size {
+            self.db.pragma_update(None, "cache_size", cache_size)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the WAL into the database and truncate it. Unlike the automatic checkpoints this also shrinks the WAL
+    /// file. Returns false when a reader, e.g. a leaderlog, kept it from completing.
+    pub fn checkpoint(&self) -> Result<bool, Error> {
+        let busy: i64 = self
+            .db
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+        Ok(busy == 0)
+    }
+
+    /// Write a consistent, compacted copy of the database to backup_path. VACUUM INTO reads within a single
+    /// transaction, so a running sync can keep writing meanwhile, and it works on a read-only connection.
+    pub fn backup_to(&self, backup_path: &Path) -> Result<(), Error> {
+        self.db
+            .execute("VACUUM INTO ?1", [backup_path.to_string_lossy().to_string()])?;
+        Ok(())
+    }
+
+    fn open_read_only(db_path: &Path, busy_timeout_ms: u64) -> Result<SqLiteBlockStore, Error> {
+        debug!("Opening database read-only");
+        let db = Connection::open_with_flags(
+            db_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        db.busy_timeout(Duration::from_millis(busy_timeout_ms))?;
+        let version: i64 = db
+            .query_row("SELECT version FROM db_version", [], |row| row.get(0))
+            .optional()?
+            .unwrap_or(-1);
+        if version != Self::DB_VERSION {
+            return Err(Error::ReadOnlyVersion(version, Self::DB_VERSION));
+        }
+        Ok(SqLiteBlockStore { db })
+    }
+
+    fn open(db_path: &Path, busy_timeout_ms: u64) -> Result<SqLiteBlockStore, Error> {
         debug!("Opening database");
         let mut db = Connection::open(db_path)?;
+        db.busy_timeout(Duration::from_millis(busy_timeout_ms))?;
         db.execute_batch("PRAGMA journal_mode=WAL")?;
 
         let tx = db.transaction()?;
@@ -149,6 +303,85 @@ impl SqLiteBlockStore {
                 tx.execute("ALTER TABLE chain ADD COLUMN block_vrf_1 TEXT NOT NULL DEFAULT ''", [])?;
             }
 
+            if version < 5 {
+                info!("Upgrade database to version 5...");
+                tx.execute(
+                    "CREATE TABLE IF NOT EXISTS protocol_updates (\
+                    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                    epoch INTEGER NOT NULL, \
+                    slot_number INTEGER NOT NULL, \
+                    d_numerator INTEGER, \
+                    d_denominator INTEGER, \
+                    extra_entropy TEXT, \
+                    UNIQUE(epoch,slot_number)
+                )",
+                    [],
+                )?;
+            }
+
+            if version < 6 {
+                info!("Upgrade database to version 6...");
+                tx.execute(
+                    "CREATE TABLE IF NOT EXISTS opcert_history (\
+                    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                    pool_id TEXT NOT NULL, \
+                    sequence_number INTEGER NOT NULL, \
+                    kes_period INTEGER NOT NULL, \
+                    first_slot_number INTEGER NOT NULL, \
+                    last_slot_number INTEGER NOT NULL, \
+                    anomaly TEXT, \
+                    UNIQUE(pool_id,sequence_number)
+                )",
+                    [],
+                )?;
+            }
+
+            if version < 7 {
+                info!("Upgrade database to version 7...");
+                tx.execute(
+                    "CREATE TABLE IF NOT EXISTS stake_history (\
+                    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                    epoch INTEGER NOT NULL, \
+                    pool_id TEXT NOT NULL, \
+                    pool_stake INTEGER NOT NULL, \
+                    active_stake INTEGER NOT NULL, \
+                    sigma REAL NOT NULL, \
+                    UNIQUE(epoch,pool_id)
+                )",
+                    [],
+                )?;
+            }
+
+            if version < 8 {
+                info!("Upgrade database to version 8...");
+                // NULL unless the block was synced with --store-raw-headers
+                tx.execute("ALTER TABLE chain ADD COLUMN header_cbor BLOB", [])?;
+            }
+
+            if version < 9 {
+                info!("Upgrade database to version 9...");
+                // unix milliseconds, NULL for blocks saved before version 9
+                tx.execute("ALTER TABLE chain ADD COLUMN received_at INTEGER", [])?;
+                tx.execute("ALTER TABLE chain ADD COLUMN source_peer TEXT", [])?;
+            }
+
+            if version < 10 {
+                info!("Upgrade database to version 10...");
+                // the leaderlog inputs, NULL for slots saved before version 10 or imported
+                tx.execute("ALTER TABLE slots ADD COLUMN nonce TEXT", [])?;
+                tx.execute("ALTER TABLE slots ADD COLUMN sigma_numerator INTEGER", [])?;
+                tx.execute("ALTER TABLE slots ADD COLUMN sigma_denominator INTEGER", [])?;
+                tx.execute("ALTER TABLE slots ADD COLUMN d REAL", [])?;
+                tx.execute("ALTER TABLE slots ADD COLUMN consensus TEXT", [])?;
+                tx.execute("ALTER TABLE slots ADD COLUMN cncli_version TEXT", [])?;
+            }
+
+            if version < 11 {
+                info!("Upgrade database to version 11...");
+                // the genesis delegates that proposed an update, NULL for proposals saved before version 11
+                tx.execute("ALTER TABLE protocol_updates ADD COLUMN votes INTEGER", [])?;
+            }
+
             // Update the db version now that we've upgraded the user's database fully
             if version < 0 {
                 tx.execute(
@@ -220,7 +453,10 @@ impl SqLiteBlockStore {
             unknown_1, \
             unknown_2, \
             protocol_major_version, \
-            protocol_minor_version) \
+            protocol_minor_version, \
+            header_cbor, \
+            received_at, \
+            source_peer) \
             VALUES (\
             :block_number, \
             :slot_number, \
@@ -243,7 +479,20 @@ impl SqLiteBlockStore {
             :unknown_1, \
             :unknown_2, \
             :protocol_major_version, \
-            :protocol_minor_version)",
+            :protocol_minor_version, \
+            :header_cbor, \
+            :received_at, \
+            :source_peer)",
+            )?;
+
+            let mut opcert_max_stmt =
+                tx.prepare("SELECT MAX(sequence_number) FROM opcert_history WHERE pool_id = ?1")?;
+            let mut opcert_stmt = tx.prepare(
+                "INSERT INTO opcert_history (pool_id, sequence_number, kes_period, first_slot_number, last_slot_number, anomaly) \
+                VALUES (:pool_id, :sequence_number, :kes_period, :slot_number, :slot_number, :anomaly) \
+                ON CONFLICT (pool_id, sequence_number) DO UPDATE SET \
+                first_slot_number=MIN(first_slot_number, excluded.first_slot_number), \
+                last_slot_number=MAX(last_slot_number, excluded.last_slot_number)",
             )?;
 
             for block in pending_blocks.drain(..) {
@@ -281,7 +530,7 @@ impl SqLiteBlockStore {
                     ":slot_number": block.slot_number,
                     ":hash" : hex::encode(block.hash),
                     ":prev_hash" : hex::encode(block.prev_hash),
-                    ":pool_id" : hex::encode(pool_id),
+                    ":pool_id" : hex::encode(&pool_id),
                     ":eta_v" : hex::encode(eta_v),
                     ":node_vkey" : hex::encode(block.node_vkey),
                     ":node_vrf_vkey" : hex::encode(block.node_vrf_vkey),
@@ -299,6 +548,26 @@ impl SqLiteBlockStore {
                     ":unknown_2" : hex::encode(block.unknown_2),
                     ":protocol_major_version" : block.protocol_major_version,
                     ":protocol_minor_version" : block.protocol_minor_version,
+                    ":header_cbor" : if block.header_cbor.is_empty() { None } else { Some(&block.header_cbor) },
+                    ":received_at" : block.received_at,
+                    ":source_peer" : block.source_peer,
+                })?;
+
+                // track the opcert sequence number of the pool
+                let previous_sequence_number: Option<u64> = opcert_max_stmt.query_row([&pool_id], |row| row.get(0))?;
+                let anomaly = opcert_anomaly(previous_sequence_number, block.unknown_0, block.protocol_major_version);
+                if let Some(anomaly) = &anomaly {
+                    warn!(
+                        "Pool {} block {} at slot {}: opcert {}",
+                        pool_id, block.block_number, block.slot_number, anomaly
+                    );
+                }
+                opcert_stmt.execute(named_params! {
+                    ":pool_id" : pool_id,
+                    ":sequence_number" : block.unknown_0,
+                    ":kes_period" : block.unknown_1,
+                    ":slot_number" : block.slot_number,
+                    ":anomaly" : anomaly,
                 })?;
 
                 prev_eta_v = eta_v;
@@ -325,30 +594,158 @@ impl SqLiteBlockStore {
 
     fn sql_find_block_by_hash(&mut self, hash_start: &str) -> Result<Option<Block>, Error> {
         let db = &self.db;
-        let like = format!("{hash_start}%");
+        let like = like_prefix(hash_start);
         Ok(db.query_row(
-            "SELECT block_number,slot_number,hash,prev_hash,pool_id,leader_vrf_0,orphaned FROM chain WHERE hash LIKE ? ORDER BY orphaned ASC",
+            &format!("SELECT {BLOCK_COLUMNS} FROM chain WHERE hash LIKE ? ESCAPE '\\' ORDER BY orphaned ASC"),
             [&like],
-            |row| {
-                Ok(Some(Block {
-                    block_number: row.get(0)?,
-                    slot_number: row.get(1)?,
-                    hash: row.get(2)?,
-                    prev_hash: row.get(3)?,
-                    pool_id: row.get(4)?,
-                    leader_vrf: row.get(5)?,
-                    orphaned: row.get(6)?,
-                }))
-            },
+            |row| Ok(Some(Self::block_from_row(row)?)),
         )?)
     }
 
+    fn sql_find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, Error> {
+        let db = &self.db;
+        let like = like_prefix(hash_start);
+        let mut stmt = db.prepare(&format!(
+            "SELECT {BLOCK_DETAIL_COLUMNS} FROM chain WHERE hash LIKE ?1 ESCAPE '\\' ORDER BY orphaned ASC, slot_number DESC"
+        ))?;
+        let block_details = stmt.query_map([&like], Self::block_detail_from_row)?;
+        Ok(block_details.collect::<Result<Vec<BlockDetail>, rusqlite::Error>>()?)
+    }
+
+    fn sql_get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {BLOCK_DETAIL_COLUMNS} FROM chain WHERE slot_number = ?1 ORDER BY orphaned ASC"
+        ))?;
+        let block_details = stmt.query_map([&slot_number], Self::block_detail_from_row)?;
+        Ok(block_details.collect::<Result<Vec<BlockDetail>, rusqlite::Error>>()?)
+    }
+
+    fn sql_get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &str,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        let db = &self.db;
+        // blocks saved by sync hold the pool_id hex encoded twice, blocks filled in by the v3 migration only once
+        let mut stmt = db.prepare(&format!(
+            "SELECT {BLOCK_DETAIL_COLUMNS} FROM chain WHERE slot_number >= :first_slot AND slot_number <= :last_slot AND pool_id IN (:pool_id, :pool_id_hex) ORDER BY slot_number ASC, orphaned ASC"
+        ))?;
+        let block_details = stmt.query_map(
+            named_params! {
+                ":first_slot": first_slot,
+                ":last_slot": last_slot,
+                ":pool_id": pool_id,
+                ":pool_id_hex": hex::encode(pool_id),
+            },
+            Self::block_detail_from_row,
+        )?;
+        Ok(block_details.collect::<Result<Vec<BlockDetail>, rusqlite::Error>>()?)
+    }
+
+    fn sql_get_block_details_in_slot_range(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {BLOCK_DETAIL_COLUMNS} FROM chain WHERE slot_number >= ?1 AND slot_number <= ?2 ORDER BY slot_number ASC, orphaned ASC, hash ASC"
+        ))?;
+        let block_details = stmt.query_map([&first_slot, &last_slot], Self::block_detail_from_row)?;
+        Ok(block_details.collect::<Result<Vec<BlockDetail>, rusqlite::Error>>()?)
+    }
+
+    fn block_detail_from_row(row: &rusqlite::Row) -> Result<BlockDetail, rusqlite::Error> {
+        Ok(BlockDetail {
+            block_number: row.get(0)?,
+            slot_number: row.get(1)?,
+            hash: row.get(2)?,
+            prev_hash: row.get(3)?,
+            pool_id: row.get(4)?,
+            eta_v: row.get(5)?,
+            node_vkey: row.get(6)?,
+            node_vrf_vkey: row.get(7)?,
+            block_vrf_0: row.get(8)?,
+            block_vrf_1: row.get(9)?,
+            eta_vrf_0: row.get(10)?,
+            eta_vrf_1: row.get(11)?,
+            leader_vrf_0: row.get(12)?,
+            leader_vrf_1: row.get(13)?,
+            block_size: row.get(14)?,
+            block_body_hash: row.get(15)?,
+            opcert_hot_vkey: row.get(16)?,
+            opcert_sequence_number: row.get(17)?,
+            opcert_kes_period: row.get(18)?,
+            opcert_sigma: row.get(19)?,
+            protocol_major_version: row.get(20)?,
+            protocol_minor_version: row.get(21)?,
+            orphaned: row.get(22)?,
+            header_cbor: row.get::<_, Option<Vec<u8>>>(23)?.map(hex::encode),
+            received_at: row.get::<_, Option<u64>>(24)?.map(blockstore::format_received_at),
+            source_peer: row.get(25)?,
+        })
+    }
+
+    fn block_from_row(row: &rusqlite::Row) -> Result<Block, rusqlite::Error> {
+        Ok(Block {
+            block_number: row.get(0)?,
+            slot_number: row.get(1)?,
+            hash: row.get(2)?,
+            prev_hash: row.get(3)?,
+            pool_id: row.get(4)?,
+            leader_vrf: row.get(5)?,
+            eta_v: row.get(7)?,
+            eta_vrf_0: row.get(8)?,
+            orphaned: row.get(6)?,
+            received_at: row.get::<_, Option<u64>>(9)?.map(blockstore::format_received_at),
+            source_peer: row.get(10)?,
+        })
+    }
+
+    fn sql_get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {BLOCK_COLUMNS} FROM chain WHERE slot_number >= ?1 AND slot_number <= ?2 ORDER BY slot_number ASC, orphaned ASC"
+        ))?;
+        let blocks = stmt.query_map([&first_slot, &last_slot], Self::block_from_row)?;
+        Ok(blocks.collect::<Result<Vec<Block>, rusqlite::Error>>()?)
+    }
+
+    fn sql_get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, Error> {
+        let db = &self.db;
+        // ids are assigned as blocks are received
+        let mut stmt = db.prepare(&format!(
+            "SELECT {BLOCK_COLUMNS} FROM chain WHERE slot_number = :slot_number OR block_number = :block_number ORDER BY id ASC"
+        ))?;
+        let blocks = stmt.query_map(
+            named_params! {
+                ":slot_number" : slot_number,
+                ":block_number" : block_number,
+            },
+            Self::block_from_row,
+        )?;
+        Ok(blocks.collect::<Result<Vec<Block>, rusqlite::Error>>()?)
+    }
+
     fn sql_get_tip_slot_number(&mut self) -> Result<u64, Error> {
         let db = &self.db;
         let tip_slot_number: u64 = db.query_row("SELECT MAX(slot_number) FROM chain", [], |row| row.get(0))?;
         Ok(tip_slot_number)
     }
 
+    fn sql_get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, Error> {
+        let db = &self.db;
+        Ok(db
+            .query_row(
+                "SELECT protocol_major_version, protocol_minor_version FROM chain WHERE orphaned = 0 ORDER BY slot_number DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?)
+    }
+
     fn sql_get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, Error> {
         let db = &self.db;
         let eta_v_hex: String = db.query_row(
@@ -378,17 +775,24 @@ impl SqLiteBlockStore {
         slot_qty: u64,
         slots: &str,
         hash: &str,
+        inputs: Option<&SlotsInputs>,
     ) -> Result<(), Error> {
         let db = &mut self.db;
         let tx = db.transaction()?;
         {
-            let mut stmt = tx.prepare("INSERT INTO slots (epoch, pool_id, slot_qty, slots, hash) VALUES (:epoch, :pool_id, :slot_qty, :slots, :hash) ON CONFLICT (epoch,pool_id) DO UPDATE SET slot_qty=excluded.slot_qty, slots=excluded.slots, hash=excluded.hash")?;
+            let mut stmt = tx.prepare("INSERT INTO slots (epoch, pool_id, slot_qty, slots, hash, nonce, sigma_numerator, sigma_denominator, d, consensus, cncli_version) VALUES (:epoch, :pool_id, :slot_qty, :slots, :hash, :nonce, :sigma_numerator, :sigma_denominator, :d, :consensus, :cncli_version) ON CONFLICT (epoch,pool_id) DO UPDATE SET slot_qty=excluded.slot_qty, slots=excluded.slots, hash=excluded.hash, nonce=excluded.nonce, sigma_numerator=excluded.sigma_numerator, sigma_denominator=excluded.sigma_denominator, d=excluded.d, consensus=excluded.consensus, cncli_version=excluded.cncli_version")?;
             stmt.execute(named_params! {
                 ":epoch" : epoch,
                 ":pool_id" : pool_id,
                 ":slot_qty" : slot_qty,
                 ":slots" : slots,
                 ":hash" : hash,
+                ":nonce" : inputs.map(|inputs| &inputs.nonce),
+                ":sigma_numerator" : inputs.map(|inputs| inputs.sigma_numerator),
+                ":sigma_denominator" : inputs.map(|inputs| inputs.sigma_denominator),
+                ":d" : inputs.map(|inputs| inputs.d),
+                ":consensus" : inputs.map(|inputs| &inputs.consensus),
+                ":cncli_version" : inputs.map(|inputs| &inputs.cncli_version),
             })?;
         }
         tx.commit()?;
@@ -427,6 +831,148 @@ impl SqLiteBlockStore {
             )
             .optional()?)
     }
+
+    fn sql_get_slots_to_send(&mut self, epoch: u64, pool_id: &str) -> Result<((u64, String), Option<String>), Error> {
+        // read both epochs in one transaction so a leaderlog saving slots meanwhile is seen completely or not at all
+        let tx = self.db.transaction()?;
+        let current_slots = tx.query_row(
+            "SELECT slot_qty, hash FROM slots WHERE epoch = :epoch AND pool_id = :pool_id",
+            named_params! {
+                ":epoch" : epoch,
+                ":pool_id" : pool_id,
+            },
+            |row| {
+                let slot_qty: u64 = row.get(0)?;
+                let hash: String = row.get(1)?;
+                Ok((slot_qty, hash))
+            },
+        )?;
+        // epoch 0 has no epoch before it
+        let previous_slots = match epoch.checked_sub(1) {
+            Some(previous_epoch) => tx
+                .query_row(
+                    "SELECT slots FROM slots WHERE epoch = :epoch AND pool_id = :pool_id",
+                    named_params! {
+                        ":epoch" : previous_epoch,
+                        ":pool_id" : pool_id,
+                    },
+                    |row| {
+                        let slots: String = row.get(0)?;
+                        Ok(slots)
+                    },
+                )
+                .optional()?,
+            None => None,
+        };
+        tx.commit()?;
+        Ok((current_slots, previous_slots))
+    }
+
+    fn sql_get_slots_inputs(&mut self, epoch: u64, pool_id: &str) -> Result<Option<SlotsInputs>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare("SELECT nonce, sigma_numerator, sigma_denominator, d, consensus, cncli_version FROM slots WHERE epoch = :epoch AND pool_id = :pool_id")?;
+        Ok(stmt
+            .query_row(
+                named_params! {
+                    ":epoch" : epoch,
+                    ":pool_id" : pool_id,
+                },
+                |row| slots_inputs_from_row(row, 0),
+            )
+            .optional()?
+            .flatten())
+    }
+
+    fn sql_get_all_slots(&mut self) -> Result<Vec<SavedSlots>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare("SELECT epoch, pool_id, slot_qty, slots, hash, nonce, sigma_numerator, sigma_denominator, d, consensus, cncli_version FROM slots ORDER BY epoch ASC, pool_id ASC")?;
+        let saved_slots = stmt.query_map([], |row| {
+            Ok(SavedSlots {
+                epoch: row.get(0)?,
+                pool_id: row.get(1)?,
+                slot_qty: row.get(2)?,
+                slots: row.get(3)?,
+                hash: row.get(4)?,
+                inputs: slots_inputs_from_row(row, 5)?,
+            })
+        })?;
+        Ok(saved_slots.collect::<Result<Vec<SavedSlots>, rusqlite::Error>>()?)
+    }
+
+    fn sql_get_opcert_history(&mut self, pool_id: &str) -> Result<Vec<OpcertHistory>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare("SELECT pool_id, sequence_number, kes_period, first_slot_number, last_slot_number, anomaly FROM opcert_history WHERE pool_id = :pool_id ORDER BY sequence_number ASC")?;
+        let opcert_history = stmt.query_map(named_params! { ":pool_id" : pool_id }, |row| {
+            Ok(OpcertHistory {
+                pool_id: row.get(0)?,
+                sequence_number: row.get(1)?,
+                kes_period: row.get(2)?,
+                first_slot_number: row.get(3)?,
+                last_slot_number: row.get(4)?,
+                anomaly: row.get(5)?,
+            })
+        })?;
+        Ok(opcert_history.collect::<Result<Vec<OpcertHistory>, rusqlite::Error>>()?)
+    }
+
+    fn sql_save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare("INSERT INTO stake_history (epoch, pool_id, pool_stake, active_stake, sigma) VALUES (:epoch, :pool_id, :pool_stake, :active_stake, :sigma) ON CONFLICT (epoch,pool_id) DO UPDATE SET pool_stake=excluded.pool_stake, active_stake=excluded.active_stake, sigma=excluded.sigma")?;
+        stmt.execute(named_params! {
+            ":epoch" : stake_history.epoch,
+            ":pool_id" : stake_history.pool_id,
+            ":pool_stake" : stake_history.pool_stake,
+            ":active_stake" : stake_history.active_stake,
+            ":sigma" : stake_history.sigma,
+        })?;
+        Ok(())
+    }
+
+    fn sql_get_stake_history(&mut self, pool_id: &str) -> Result<Vec<StakeHistory>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare("SELECT epoch, pool_id, pool_stake, active_stake, sigma FROM stake_history WHERE pool_id = :pool_id ORDER BY epoch ASC")?;
+        let stake_history = stmt.query_map(named_params! { ":pool_id" : pool_id }, |row| {
+            Ok(StakeHistory {
+                epoch: row.get(0)?,
+                pool_id: row.get(1)?,
+                pool_stake: row.get(2)?,
+                active_stake: row.get(3)?,
+                sigma: row.get(4)?,
+            })
+        })?;
+        Ok(stake_history.collect::<Result<Vec<StakeHistory>, rusqlite::Error>>()?)
+    }
+
+    fn sql_save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare("INSERT INTO protocol_updates (epoch, slot_number, d_numerator, d_denominator, extra_entropy, votes) VALUES (:epoch, :slot_number, :d_numerator, :d_denominator, :extra_entropy, :votes) ON CONFLICT (epoch,slot_number) DO UPDATE SET d_numerator=excluded.d_numerator, d_denominator=excluded.d_denominator, extra_entropy=excluded.extra_entropy, votes=excluded.votes")?;
+        stmt.execute(named_params! {
+            ":epoch" : protocol_update.epoch,
+            ":slot_number" : protocol_update.slot_number,
+            ":d_numerator" : protocol_update.decentralization.map(|d| d.0),
+            ":d_denominator" : protocol_update.decentralization.map(|d| d.1),
+            ":extra_entropy" : protocol_update.extra_entropy,
+            ":votes" : protocol_update.votes,
+        })?;
+        Ok(())
+    }
+
+    fn sql_get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, Error> {
+        let db = &self.db;
+        let mut stmt = db.prepare("SELECT epoch, slot_number, d_numerator, d_denominator, extra_entropy, votes FROM protocol_updates WHERE epoch < :epoch ORDER BY slot_number ASC")?;
+        let protocol_updates = stmt.query_map(named_params! { ":epoch" : epoch }, |row| {
+            let d_numerator: Option<u64> = row.get(2)?;
+            let d_denominator: Option<u64> = row.get(3)?;
+            Ok(ProtocolUpdate {
+                epoch: row.get(0)?,
+                slot_number: row.get(1)?,
+                decentralization: d_numerator.zip(d_denominator),
+                extra_entropy: row.get(4)?,
+                votes: row.get(5)?,
+            })
+        })?;
+        Ok(protocol_updates.collect::<Result<Vec<ProtocolUpdate>, rusqlite::Error>>()?)
+    }
 }
 
 impl BlockStore for SqLiteBlockStore {
@@ -446,10 +992,58 @@ impl BlockStore for SqLiteBlockStore {
         Ok(self.sql_find_block_by_hash(hash_start)?)
     }
 
+    fn find_block_details_by_hash(&mut self, hash_start: &str) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.sql_find_block_details_by_hash(hash_start)?)
+    }
+
+    fn get_block_details_at_slot(&mut self, slot_number: u64) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.sql_get_block_details_at_slot(slot_number)?)
+    }
+
+    fn get_blocks_in_slot_range(&mut self, first_slot: u64, last_slot: u64) -> Result<Vec<Block>, blockstore::Error> {
+        Ok(self.sql_get_blocks_in_slot_range(first_slot, last_slot)?)
+    }
+
+    fn get_competing_blocks(&mut self, slot_number: u64, block_number: u64) -> Result<Vec<Block>, blockstore::Error> {
+        Ok(self.sql_get_competing_blocks(slot_number, block_number)?)
+    }
+
+    fn get_pool_block_details_in_slot_range(
+        &mut self,
+        pool_id: &PoolId,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.sql_get_pool_block_details_in_slot_range(pool_id.as_str(), first_slot, last_slot)?)
+    }
+
+    fn get_block_details_in_slot_range(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, blockstore::Error> {
+        Ok(self.sql_get_block_details_in_slot_range(first_slot, last_slot)?)
+    }
+
     fn get_tip_slot_number(&mut self) -> Result<u64, blockstore::Error> {
         Ok(self.sql_get_tip_slot_number()?)
     }
 
+    fn checkpoint(&mut self) -> Result<(), blockstore::Error> {
+        if !SqLiteBlockStore::checkpoint(self)? {
+            warn!("WAL checkpoint did not complete, a reader is still using it");
+        }
+        Ok(())
+    }
+
+    fn backup_to(&mut self, backup_path: &Path) -> Result<(), blockstore::Error> {
+        Ok(SqLiteBlockStore::backup_to(self, backup_path)?)
+    }
+
+    fn get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, blockstore::Error> {
+        Ok(self.sql_get_tip_protocol_version()?)
+    }
+
     fn get_eta_v_before_slot(&mut self, slot_number: u64) -> Result<Hash<32>, blockstore::Error> {
         Ok(self.sql_get_eta_v_before_slot(slot_number)?)
     }
@@ -461,19 +1055,56 @@ impl BlockStore for SqLiteBlockStore {
     fn save_slots(
         &mut self,
         epoch: u64,
-        pool_id: &str,
+        pool_id: &PoolId,
         slot_qty: u64,
         slots: &str,
         hash: &str,
+        inputs: Option<&SlotsInputs>,
     ) -> Result<(), blockstore::Error> {
-        Ok(self.sql_save_slots(epoch, pool_id, slot_qty, slots, hash)?)
+        Ok(self.sql_save_slots(epoch, pool_id.as_str(), slot_qty, slots, hash, inputs)?)
+    }
+
+    fn get_current_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<(u64, String), blockstore::Error> {
+        Ok(self.sql_get_current_slots(epoch, pool_id.as_str())?)
+    }
+
+    fn get_previous_slots(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<String>, blockstore::Error> {
+        Ok(self.sql_get_previous_slots(epoch, pool_id.as_str())?)
+    }
+
+    fn get_slots_to_send(
+        &mut self,
+        epoch: u64,
+        pool_id: &PoolId,
+    ) -> Result<((u64, String), Option<String>), blockstore::Error> {
+        Ok(self.sql_get_slots_to_send(epoch, pool_id.as_str())?)
+    }
+
+    fn get_slots_inputs(&mut self, epoch: u64, pool_id: &PoolId) -> Result<Option<SlotsInputs>, blockstore::Error> {
+        Ok(self.sql_get_slots_inputs(epoch, pool_id.as_str())?)
+    }
+
+    fn get_all_slots(&mut self) -> Result<Vec<SavedSlots>, blockstore::Error> {
+        Ok(self.sql_get_all_slots()?)
+    }
+
+    fn get_opcert_history(&mut self, pool_id: &PoolId) -> Result<Vec<OpcertHistory>, blockstore::Error> {
+        Ok(self.sql_get_opcert_history(pool_id.as_str())?)
+    }
+
+    fn save_stake_history(&mut self, stake_history: &StakeHistory) -> Result<(), blockstore::Error> {
+        Ok(self.sql_save_stake_history(stake_history)?)
+    }
+
+    fn get_stake_history(&mut self, pool_id: &PoolId) -> Result<Vec<StakeHistory>, blockstore::Error> {
+        Ok(self.sql_get_stake_history(pool_id.as_str())?)
     }
 
-    fn get_current_slots(&mut self, epoch: u64, pool_id: &str) -> Result<(u64, String), blockstore::Error> {
-        Ok(self.sql_get_current_slots(epoch, pool_id)?)
+    fn save_protocol_update(&mut self, protocol_update: &ProtocolUpdate) -> Result<(), blockstore::Error> {
+        Ok(self.sql_save_protocol_update(protocol_update)?)
     }
 
-    fn get_previous_slots(&mut self, epoch: u64, pool_id: &str) -> Result<Option<String>, blockstore::Error> {
-        Ok(self.sql_get_previous_slots(epoch, pool_id)?)
+    fn get_protocol_updates_before_epoch(&mut self, epoch: u64) -> Result<Vec<ProtocolUpdate>, blockstore::Error> {
+        Ok(self.sql_get_protocol_updates_before_epoch(epoch)?)
     }
 }
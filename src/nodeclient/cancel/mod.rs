@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::future::pending;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::nodeclient::reporter;
+
+const RUNNING: u8 = 0;
+const INTERRUPTED: u8 = 1;
+const TIMED_OUT: u8 = 2;
+
+// the exit codes a shell and timeout(1) use
+const EXIT_INTERRUPTED: i32 = 130;
+const EXIT_TIMED_OUT: i32 = 124;
+
+static STATE: AtomicU8 = AtomicU8::new(RUNNING);
+static TIMEOUT_SECONDS: AtomicU64 = AtomicU64::new(0);
+// set by commands that check cancelled() themselves and stop with their own report
+static COOPERATIVE: AtomicBool = AtomicBool::new(false);
+static PROGRESS: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+static NOTIFY: OnceLock<Notify> = OnceLock::new();
+
+/// Why a run was cancelled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Cancelled {
+    Interrupted,
+    /// After the global --timeout, in seconds
+    TimedOut(u64),
+}
+
+impl Cancelled {
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            Cancelled::Interrupted => EXIT_INTERRUPTED,
+            Cancelled::TimedOut(_) => EXIT_TIMED_OUT,
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self {
+            Cancelled::Interrupted => "ctrl-c",
+            Cancelled::TimedOut(_) => "timeout",
+        }
+    }
+}
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cancelled::Interrupted => write!(f, "interrupted by ctrl-c"),
+            Cancelled::TimedOut(seconds) => write!(f, "stopped by --timeout after {seconds} seconds"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CancelledReport {
+    status: String,
+    error_message: String,
+    cancelled: String,
+    /// What the command got done before it was cancelled, e.g. rowsWritten
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    partial: BTreeMap<&'static str, u64>,
+}
+
+fn notify() -> &'static Notify {
+    NOTIFY.get_or_init(Notify::new)
+}
+
+/// Whether and why the run was cancelled
+pub(crate) fn cancelled() -> Option<Cancelled> {
+    match STATE.load(Ordering::SeqCst) {
+        INTERRUPTED => Some(Cancelled::Interrupted),
+        TIMED_OUT => Some(Cancelled::TimedOut(TIMEOUT_SECONDS.load(Ordering::SeqCst))),
+        _ => None,
+    }
+}
+
+/// Wait until the run is cancelled
+pub(crate) async fn wait() -> Cancelled {
+    loop {
+        let notified = notify().notified();
+        if let Some(cancelled) = cancelled() {
+            return cancelled;
+        }
+        notified.await;
+    }
+}
+
+fn cancel(state: u8) {
+    if STATE
+        .compare_exchange(RUNNING, state, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        notify().notify_waiters();
+    }
+}
+
+async fn interrupted() {
+    if let Err(error) = tokio::signal::ctrl_c().await {
+        warn!("Could not listen for ctrl-c: {}", error);
+        pending::<()>().await;
+    }
+}
+
+/// Cancel the run on ctrl-c or once timeout has passed. A second ctrl-c exits at once, for a command that does not
+/// stop.
+pub(crate) fn watch(timeout: Option<Duration>) {
+    if let Some(timeout) = timeout {
+        TIMEOUT_SECONDS.store(timeout.as_secs(), Ordering::SeqCst);
+    }
+    tokio::spawn(async move {
+        let timed_out = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => pending::<()>().await,
+            }
+        };
+        let state = tokio::select! {
+            _ = interrupted() => INTERRUPTED,
+            _ = timed_out => TIMED_OUT,
+        };
+        cancel(state);
+        if let Some(cancelled) = cancelled() {
+            warn!("Cancelling: {}", cancelled);
+        }
+        interrupted().await;
+        warn!("Exiting on the second ctrl-c");
+        crate::exit(EXIT_INTERRUPTED);
+    });
+}
+
+/// Mark the run as stopping on its own when it is cancelled, by checking cancelled() between steps and reporting
+/// what it got done. Other runs are dropped at their next await when they are cancelled.
+pub(crate) fn set_cooperative() {
+    COOPERATIVE.store(true, Ordering::SeqCst);
+}
+
+pub(crate) fn is_cooperative() -> bool {
+    COOPERATIVE.load(Ordering::SeqCst)
+}
+
+/// Record a count of what the command got done so far, reported when it is cancelled
+pub(crate) fn progress(name: &'static str, value: u64) {
+    if let Ok(mut progress) = PROGRESS.lock() {
+        progress.insert(name, value);
+    }
+}
+
+/// Report that a run was cancelled, with the progress recorded so far
+pub(crate) fn report(cancelled: Cancelled) {
+    let partial = PROGRESS.lock().map(|progress| progress.clone()).unwrap_or_default();
+    reporter::mark_error_reported();
+    if let Err(error) = reporter::report(&CancelledReport {
+        status: "error".to_string(),
+        error_message: format!("Cancelled: {cancelled}"),
+        cancelled: cancelled.reason().to_string(),
+        partial,
+    }) {
+        warn!("Could not report the cancellation: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel() {
+        assert_eq!(cancelled(), None);
+        progress("testRows", 10_000);
+        let waiting = tokio::spawn(wait());
+        tokio::task::yield_now().await;
+        TIMEOUT_SECONDS.store(60, Ordering::SeqCst);
+        cancel(TIMED_OUT);
+        // only the first cancellation counts
+        cancel(INTERRUPTED);
+        let cancelled = waiting.await.unwrap();
+        assert_eq!(cancelled, Cancelled::TimedOut(60));
+        assert_eq!(cancelled.exit_code(), 124);
+        assert_eq!(cancelled.to_string(), "stopped by --timeout after 60 seconds");
+        assert_eq!(PROGRESS.lock().unwrap().get("testRows"), Some(&10_000));
+    }
+}
@@ -0,0 +1,254 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use structopt::clap::{App, Shell};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::redb::is_redb_database;
+use crate::nodeclient::blockstore::SavedSlots;
+use crate::nodeclient::sync::pooltool;
+
+// completing must not hang the shell, so a locked database is given up on quickly
+const COMPLETION_BUSY_TIMEOUT_MS: u64 = 200;
+
+const SNAPSHOT_NAMES: [&str; 3] = ["mark", "set", "go"];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+}
+
+/// The flag values the completion scripts look up with `cncli complete`
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompletionValues {
+    /// Epochs with slots saved by leaderlog or slots import, for --epoch
+    Epochs,
+    /// Pools in the pooltool config and with saved slots, for --pool-id
+    PoolIds,
+    /// The ledger snapshots of snapshot and pool-stake, for --name
+    SnapshotNames,
+}
+
+impl FromStr for CompletionValues {
+    type Err = String;
+    fn from_str(completion_values: &str) -> Result<Self, Self::Err> {
+        match completion_values {
+            "epochs" => Ok(CompletionValues::Epochs),
+            "pool-ids" => Ok(CompletionValues::PoolIds),
+            "snapshot-names" => Ok(CompletionValues::SnapshotNames),
+            _ => Err(format!("Invalid completion values: {completion_values}")),
+        }
+    }
+}
+
+/// The completion script of the cli for a shell. For bash, zsh and fish it also completes the values of --epoch,
+/// --pool-id and --name by calling `cncli complete`, the other shells only complete subcommands and flags.
+pub(crate) fn completion_script(mut app: App<'static, 'static>, shell: Shell) -> Vec<u8> {
+    let bin_name = app.get_name().to_string();
+    let mut script: Vec<u8> = Vec::new();
+    app.gen_completions_to(&bin_name, shell, &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+    let script = match shell {
+        Shell::Bash => format!("{script}\n{BASH_DYNAMIC}"),
+        Shell::Zsh => zsh_with_dynamic(&script),
+        Shell::Fish => format!("{script}\n{FISH_DYNAMIC}"),
+        _ => script,
+    };
+    script.into_bytes()
+}
+
+/// The values to offer for a flag, one per line. Anything that cannot be read is left out rather than reported,
+/// since the output goes straight into the shell's completion menu.
+pub(crate) fn completion_values(completion_values: &CompletionValues, db_path: &Path, config: &Path) -> Vec<String> {
+    match completion_values {
+        CompletionValues::Epochs => {
+            let mut epochs: Vec<u64> = saved_slots(db_path)
+                .iter()
+                .map(|saved_slots| saved_slots.epoch)
+                .collect();
+            epochs.sort_unstable();
+            epochs.dedup();
+            epochs.iter().map(|epoch| epoch.to_string()).collect()
+        }
+        CompletionValues::PoolIds => {
+            let mut pool_ids: Vec<String> = match pooltool::get_pooltool_config(config) {
+                Ok(pooltool_config) => pooltool_config
+                    .pools
+                    .into_iter()
+                    .map(|pool| pool.pool_id.to_string())
+                    .collect(),
+                Err(error) => {
+                    debug!("No pool ids from {}: {}", config.display(), error);
+                    vec![]
+                }
+            };
+            pool_ids.extend(saved_slots(db_path).into_iter().map(|saved_slots| saved_slots.pool_id));
+            pool_ids.sort();
+            pool_ids.dedup();
+            pool_ids
+        }
+        CompletionValues::SnapshotNames => SNAPSHOT_NAMES.iter().map(|name| name.to_string()).collect(),
+    }
+}
+
+fn saved_slots(db_path: &Path) -> Vec<SavedSlots> {
+    match read_saved_slots(db_path) {
+        Ok(saved_slots) => saved_slots,
+        Err(error) => {
+            debug!("No saved slots from {}: {}", db_path.display(), error);
+            vec![]
+        }
+    }
+}
+
+/// The slots saved in a sqlite database. A redb database is held open by sync, which would keep every completion
+/// waiting, so it has none.
+fn read_saved_slots(db_path: &Path) -> Result<Vec<SavedSlots>, Error> {
+    if !db_path.exists() || is_redb_database(db_path)? {
+        return Ok(vec![]);
+    }
+    let mut block_store = blockstore::open_with_busy_timeout(db_path, COMPLETION_BUSY_TIMEOUT_MS, true)?;
+    Ok(block_store.get_all_slots()?)
+}
+
+/// Rename the function clap generates so the dynamic values are looked up before falling back to it
+fn zsh_with_dynamic(script: &str) -> String {
+    let (Some(definition), Some(call)) = (script.find("\n_cncli() {"), script.rfind("\n_cncli \"$@\"")) else {
+        debug!("Unexpected zsh completion script, completing without dynamic values");
+        return script.to_string();
+    };
+    format!(
+        "{}\n_cncli_static() {{{}\n{ZSH_DYNAMIC}{}",
+        &script[..definition],
+        &script[definition + "\n_cncli() {".len()..call],
+        &script[call..]
+    )
+}
+
+const BASH_DYNAMIC: &str = r#"_cncli_dynamic() {
+    local cur prev db config i
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    db="./cncli.db"
+    config="./pooltool.json"
+    for ((i = 1; i < COMP_CWORD - 1; i++)); do
+        case "${COMP_WORDS[i]}" in
+            -d|--db) db="${COMP_WORDS[i+1]}" ;;
+            --config) config="${COMP_WORDS[i+1]}" ;;
+        esac
+    done
+    case "${prev}" in
+        --epoch)
+            COMPREPLY=($(compgen -W "$(cncli complete epochs --db "${db}" 2>/dev/null)" -- "${cur}"))
+            return 0
+            ;;
+        --pool-id)
+            COMPREPLY=($(compgen -W "$(cncli complete pool-ids --db "${db}" --config "${config}" 2>/dev/null)" -- "${cur}"))
+            return 0
+            ;;
+        --name)
+            COMPREPLY=($(compgen -W "$(cncli complete snapshot-names 2>/dev/null)" -- "${cur}"))
+            return 0
+            ;;
+    esac
+    _cncli "$@"
+}
+
+complete -F _cncli_dynamic -o bashdefault -o default cncli
+"#;
+
+const ZSH_DYNAMIC: &str = r#"_cncli_dynamic_values() {
+    local db=./cncli.db config=./pooltool.json i
+    for ((i = 2; i < CURRENT - 1; i++)); do
+        case ${words[i]} in
+            -d|--db) db=${words[i+1]} ;;
+            --config) config=${words[i+1]} ;;
+        esac
+    done
+    case ${words[CURRENT-1]} in
+        --epoch) cncli complete epochs --db "$db" 2>/dev/null ;;
+        --pool-id) cncli complete pool-ids --db "$db" --config "$config" 2>/dev/null ;;
+        --name) cncli complete snapshot-names 2>/dev/null ;;
+        *) return 1 ;;
+    esac
+}
+
+_cncli() {
+    local values
+    if values=$(_cncli_dynamic_values); then
+        compadd -- ${(f)values}
+        return
+    fi
+    _cncli_static "$@"
+}
+"#;
+
+const FISH_DYNAMIC: &str = r#"function __fish_cncli_option_value
+    set -l words (commandline -opc)
+    set -l value $argv[1]
+    for i in (seq 2 (math (count $words) - 1))
+        if contains -- $words[$i] $argv[2..-1]
+            set value $words[(math $i + 1)]
+        end
+    end
+    echo $value
+end
+
+complete -c cncli -l epoch -r -f -a '(cncli complete epochs --db (__fish_cncli_option_value ./cncli.db -d --db) 2>/dev/null)'
+complete -c cncli -l pool-id -r -f -a '(cncli complete pool-ids --db (__fish_cncli_option_value ./cncli.db -d --db) --config (__fish_cncli_option_value ./pooltool.json --config) 2>/dev/null)'
+complete -c cncli -l name -r -f -a '(cncli complete snapshot-names 2>/dev/null)'
+"#;
+
+#[cfg(test)]
+mod tests {
+    use structopt::clap::Arg;
+
+    use super::*;
+
+    fn app() -> App<'static, 'static> {
+        App::new("cncli").subcommand(App::new("leaderlog").arg(Arg::with_name("epoch").long("epoch").takes_value(true)))
+    }
+
+    #[test]
+    fn test_completion_script() {
+        let bash = String::from_utf8(completion_script(app(), Shell::Bash)).unwrap();
+        assert!(bash.contains("_cncli() {"));
+        assert!(bash.ends_with("complete -F _cncli_dynamic -o bashdefault -o default cncli\n"));
+
+        let zsh = String::from_utf8(completion_script(app(), Shell::Zsh)).unwrap();
+        assert!(zsh.contains("\n_cncli_static() {"));
+        assert!(zsh.contains("\n_cncli() {\n    local values"));
+        assert!(zsh.trim_end().ends_with("_cncli \"$@\""));
+
+        let powershell = String::from_utf8(completion_script(app(), Shell::PowerShell)).unwrap();
+        assert!(!powershell.contains("cncli complete"));
+    }
+
+    #[test]
+    fn test_completion_values() {
+        assert_eq!(
+            completion_values(
+                &CompletionValues::SnapshotNames,
+                Path::new("/nonexistent/cncli.db"),
+                Path::new("/nonexistent/pooltool.json")
+            ),
+            vec!["mark", "set", "go"]
+        );
+        assert!(completion_values(
+            &CompletionValues::Epochs,
+            Path::new("/nonexistent/cncli.db"),
+            Path::new("/nonexistent/pooltool.json")
+        )
+        .is_empty());
+        assert_eq!("pool-ids".parse::<CompletionValues>(), Ok(CompletionValues::PoolIds));
+    }
+}
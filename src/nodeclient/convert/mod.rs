@@ -0,0 +1,242 @@
+use std::str::FromStr;
+
+use bech32::{Bech32, Hrp};
+use pallas_crypto::hash::Hasher;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::reporter;
+use crate::nodeclient::vrfkey::vrf_vkey_hash;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Bech32(#[from] bech32::primitives::hrp::Error),
+
+    #[error(transparent)]
+    Bech32Encoding(#[from] bech32::EncodeError),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error(transparent)]
+    PoolId(#[from] crate::nodeclient::poolid::Error),
+
+    #[error("Invalid input {0}: {1}")]
+    InvalidInput(String, String),
+}
+
+/// The values convert knows, by their bech32 prefix
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// blake2b-224 of the pool cold verification key
+    PoolId,
+    /// A reward address, a header byte with the network followed by the stake credential hash
+    StakeAddress,
+    PoolVkey,
+    StakeVkey,
+    VrfVkey,
+}
+
+impl Kind {
+    fn from_hrp(hrp: &str) -> Option<Self> {
+        match hrp {
+            "pool" => Some(Kind::PoolId),
+            "stake" | "stake_test" => Some(Kind::StakeAddress),
+            "pool_vk" => Some(Kind::PoolVkey),
+            "stake_vk" => Some(Kind::StakeVkey),
+            "vrf_vk" => Some(Kind::VrfVkey),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::PoolId => "poolId",
+            Kind::StakeAddress => "stakeAddress",
+            Kind::PoolVkey => "poolVkey",
+            Kind::StakeVkey => "stakeVkey",
+            Kind::VrfVkey => "vrfVkey",
+        }
+    }
+
+    fn length(&self) -> usize {
+        match self {
+            Kind::PoolId => 28,
+            Kind::StakeAddress => 29,
+            Kind::PoolVkey | Kind::StakeVkey | Kind::VrfVkey => 32,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Conversion {
+    status: String,
+    #[serde(rename = "type")]
+    kind: String,
+    hex: String,
+    bech32: String,
+    /// blake2b-224 of a stake or pool cold verification key, or the key credential of a stake address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_hash: Option<String>,
+    /// The script credential of a stake address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool_id_bech32: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stake_address: Option<String>,
+    /// blake2b-256 of the vrf verification key, as registered with the pool
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vrf_key_hash: Option<String>,
+}
+
+/// Convert a pool id, stake address or verification key between hex and bech32 and print the hashes derived from
+/// it. Hex input is a pool id or stake address by its length, a verification key needs the bech32 prefix in hrp.
+pub(crate) fn convert(input: &str, hrp: &Option<String>, network_id: u8) -> Result<(), Error> {
+    reporter::report(&conversion(input, hrp, network_id)?)?;
+    Ok(())
+}
+
+fn conversion(input: &str, hrp: &Option<String>, network_id: u8) -> Result<Conversion, Error> {
+    let invalid = |reason: String| Error::InvalidInput(input.to_string(), reason);
+    let (kind, bytes) = match hex::decode(input) {
+        Ok(bytes) => {
+            let kind = match hrp {
+                Some(hrp) => Kind::from_hrp(hrp).ok_or_else(|| {
+                    invalid(format!(
+                        "unknown --hrp {hrp}, expected pool, stake, stake_test, pool_vk, stake_vk or vrf_vk"
+                    ))
+                })?,
+                None => match bytes.len() {
+                    28 => Kind::PoolId,
+                    29 => Kind::StakeAddress,
+                    32 => {
+                        return Err(invalid(
+                            "a 32 byte key needs --hrp pool_vk, stake_vk or vrf_vk to tell what it is".to_string(),
+                        ))
+                    }
+                    length => return Err(invalid(format!("unexpected length of {length} bytes"))),
+                },
+            };
+            (kind, bytes)
+        }
+        Err(_) => {
+            let (hrp, bytes) = bech32::decode(input).map_err(|_| invalid("expected hex or bech32".to_string()))?;
+            let kind =
+                Kind::from_hrp(hrp.as_str()).ok_or_else(|| invalid(format!("unsupported bech32 prefix {hrp}")))?;
+            (kind, bytes)
+        }
+    };
+    if bytes.len() != kind.length() {
+        return Err(invalid(format!(
+            "a {} has {} bytes but got {}",
+            kind.name(),
+            kind.length(),
+            bytes.len()
+        )));
+    }
+
+    let mut conversion = Conversion {
+        status: "ok".to_string(),
+        kind: kind.name().to_string(),
+        hex: hex::encode(&bytes),
+        ..Conversion::default()
+    };
+    match kind {
+        Kind::PoolId => {
+            conversion.bech32 = PoolId::from_str(&conversion.hex)?.to_bech32();
+        }
+        Kind::StakeAddress => {
+            let (hrp, credential) = match (bytes[0] >> 4, bytes[0] & 0x0f) {
+                (0x0e | 0x0f, 0) => ("stake_test", bytes[0] >> 4),
+                (0x0e | 0x0f, 1) => ("stake", bytes[0] >> 4),
+                _ => return Err(invalid(format!("header byte {:02x} is not a stake address", bytes[0]))),
+            };
+            conversion.bech32 = encode_bech32(hrp, &bytes)?;
+            match credential {
+                0x0e => conversion.key_hash = Some(hex::encode(&bytes[1..])),
+                _ => conversion.script_hash = Some(hex::encode(&bytes[1..])),
+            }
+        }
+        Kind::PoolVkey => {
+            conversion.bech32 = encode_bech32("pool_vk", &bytes)?;
+            // blake2b 224 of the cold vkey is the pool_id
+            let pool_id = PoolId::from_str(&hex::encode(Hasher::<224>::hash(&bytes)))?;
+            conversion.key_hash = Some(pool_id.to_string());
+            conversion.pool_id = Some(pool_id.to_string());
+            conversion.pool_id_bech32 = Some(pool_id.to_bech32());
+        }
+        Kind::StakeVkey => {
+            conversion.bech32 = encode_bech32("stake_vk", &bytes)?;
+            let key_hash = Hasher::<224>::hash(&bytes);
+            conversion.key_hash = Some(hex::encode(key_hash));
+            let hrp = if network_id == 1 { "stake" } else { "stake_test" };
+            conversion.stake_address = Some(encode_bech32(
+                hrp,
+                &[[0xe0 | (network_id & 0x0f)].as_slice(), key_hash.as_ref()].concat(),
+            )?);
+        }
+        Kind::VrfVkey => {
+            conversion.bech32 = encode_bech32("vrf_vk", &bytes)?;
+            conversion.vrf_key_hash = Some(hex::encode(vrf_vkey_hash(&bytes)));
+        }
+    }
+
+    Ok(conversion)
+}
+
+fn encode_bech32(hrp: &str, bytes: &[u8]) -> Result<String, Error> {
+    Ok(bech32::encode::<Bech32>(Hrp::parse(hrp)?, bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL_ID: &str = "00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114";
+
+    #[test]
+    fn test_conversion() {
+        let pool_id = conversion(POOL_ID, &None, 1).unwrap();
+        assert_eq!(pool_id.kind, "poolId");
+        assert!(pool_id.bech32.starts_with("pool1"));
+        assert_eq!(conversion(&pool_id.bech32, &None, 1).unwrap(), pool_id);
+
+        let stake_address = conversion(&format!("e1{}", "07".repeat(28)), &None, 1).unwrap();
+        assert!(stake_address.bech32.starts_with("stake1"));
+        assert_eq!(stake_address.key_hash, Some("07".repeat(28)));
+        let stake_test_address = conversion(&format!("f0{}", "07".repeat(28)), &None, 1).unwrap();
+        assert!(stake_test_address.bech32.starts_with("stake_test1"));
+        assert_eq!(stake_test_address.script_hash, Some("07".repeat(28)));
+        assert!(conversion(&format!("01{}", "07".repeat(28)), &None, 1).is_err());
+
+        // a stake vkey derives the key hash of the stake address
+        let stake_vkey = conversion(&"ab".repeat(32), &Some("stake_vk".to_string()), 1).unwrap();
+        assert!(stake_vkey.bech32.starts_with("stake_vk1"));
+        let derived = conversion(stake_vkey.stake_address.as_ref().unwrap(), &None, 1).unwrap();
+        assert_eq!(derived.key_hash, stake_vkey.key_hash);
+
+        let pool_vkey = conversion(&"ab".repeat(32), &Some("pool_vk".to_string()), 1).unwrap();
+        assert_eq!(pool_vkey.pool_id, pool_vkey.key_hash);
+        assert_eq!(
+            conversion(pool_vkey.pool_id_bech32.as_ref().unwrap(), &None, 1)
+                .unwrap()
+                .hex,
+            pool_vkey.pool_id.unwrap()
+        );
+
+        let vrf_vkey = conversion(&"ab".repeat(32), &Some("vrf_vk".to_string()), 1).unwrap();
+        assert_eq!(vrf_vkey.vrf_key_hash.unwrap().len(), 64);
+
+        // a 32 byte key could be any of them
+        assert!(conversion(&"ab".repeat(32), &None, 1).is_err());
+        assert!(conversion(POOL_ID, &Some("addr".to_string()), 1).is_err());
+        assert!(conversion(POOL_ID, &Some("vrf_vk".to_string()), 1).is_err());
+        assert!(conversion("my-pool", &None, 1).is_err());
+    }
+}
@@ -0,0 +1,456 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::Block;
+use crate::nodeclient::http::HttpSettings;
+use crate::nodeclient::leaderlog::{
+    get_first_slot_of_epoch, get_first_slot_of_epoch_number, guess_shelley_transition_epoch, read_byron_genesis,
+    read_shelley_genesis,
+};
+use crate::nodeclient::{http, leaderlog, reporter};
+
+const KOIOS_API_URLS: [(u32, &str); 3] = [
+    (764824073, "https://api.koios.rest/api/v1"),
+    (1, "https://preprod.koios.rest/api/v1"),
+    (2, "https://preview.koios.rest/api/v1"),
+];
+
+const BLOCKFROST_API_URLS: [(u32, &str); 3] = [
+    (764824073, "https://cardano-mainnet.blockfrost.io/api/v0"),
+    (1, "https://cardano-preprod.blockfrost.io/api/v0"),
+    (2, "https://cardano-preview.blockfrost.io/api/v0"),
+];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] leaderlog::Error),
+
+    #[error("HTTP error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Client(#[from] http::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("{name} returned HTTP {status}: {body}")]
+    Http {
+        name: &'static str,
+        status: u16,
+        body: String,
+    },
+
+    #[error("Crosscheck error: {0}")]
+    Crosscheck(String),
+}
+
+/// An external chain index to compare the local blocks with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrosscheckSource {
+    Koios,
+    Blockfrost,
+}
+
+impl FromStr for CrosscheckSource {
+    type Err = String;
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "koios" => Ok(CrosscheckSource::Koios),
+            "blockfrost" => Ok(CrosscheckSource::Blockfrost),
+            _ => Err(format!("Invalid source: {source}, expected koios or blockfrost")),
+        }
+    }
+}
+
+impl CrosscheckSource {
+    fn name(&self) -> &'static str {
+        match self {
+            CrosscheckSource::Koios => "koios",
+            CrosscheckSource::Blockfrost => "blockfrost",
+        }
+    }
+
+    fn default_api_url(&self, network_magic: u32) -> Option<&'static str> {
+        let api_urls = match self {
+            CrosscheckSource::Koios => &KOIOS_API_URLS,
+            CrosscheckSource::Blockfrost => &BLOCKFROST_API_URLS,
+        };
+        api_urls
+            .iter()
+            .find(|(magic, _)| *magic == network_magic)
+            .map(|(_, api_url)| *api_url)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Crosscheck {
+    status: String,
+    epoch: u64,
+    source: String,
+    first_slot: u64,
+    last_slot: u64,
+    local_blocks: u64,
+    /// Only compared once the epoch is over
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_blocks: Option<u64>,
+    sampled: u64,
+    matched: u64,
+    divergences: Vec<Divergence>,
+    missing_blocks: Vec<MissingBlocks>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+/// A sampled block the source has a different hash for, or none at all
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Divergence {
+    block_number: u64,
+    slot_number: u64,
+    local_hash: String,
+    source_hash: Option<String>,
+}
+
+/// A run of block numbers the local db has no block for
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MissingBlocks {
+    from_block_number: u64,
+    to_block_number: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct KoiosBlock {
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KoiosEpochInfo {
+    blk_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockfrostBlock {
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockfrostEpoch {
+    block_count: u64,
+}
+
+struct SourceClient {
+    source: CrosscheckSource,
+    api_url: String,
+    api_key: Option<String>,
+    client: reqwest::blocking::Client,
+}
+
+impl SourceClient {
+    /// The hash of the block with this number on the chain the source follows, None if it has no such block
+    fn block_hash(&self, block_number: u64) -> Result<Option<String>, Error> {
+        match self.source {
+            CrosscheckSource::Koios => {
+                let body = self.get(&format!("/blocks?block_height=eq.{block_number}&select=hash"))?;
+                parse_koios_block(&body.unwrap_or_default())
+            }
+            CrosscheckSource::Blockfrost => match self.get(&format!("/blocks/{block_number}"))? {
+                Some(body) => Ok(Some(serde_json::from_str::<BlockfrostBlock>(&body)?.hash)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn epoch_block_count(&self, epoch: u64) -> Result<Option<u64>, Error> {
+        match self.source {
+            CrosscheckSource::Koios => {
+                let body = self.get(&format!("/epoch_info?_epoch_no={epoch}&select=blk_count"))?;
+                let epoch_info: Vec<KoiosEpochInfo> = serde_json::from_str(&body.unwrap_or_else(|| "[]".to_string()))?;
+                Ok(epoch_info.first().map(|epoch_info| epoch_info.blk_count))
+            }
+            CrosscheckSource::Blockfrost => match self.get(&format!("/epochs/{epoch}"))? {
+                Some(body) => Ok(Some(serde_json::from_str::<BlockfrostEpoch>(&body)?.block_count)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// The body of a GET request, None for a 404
+    fn get(&self, path: &str) -> Result<Option<String>, Error> {
+        let mut request = self.client.get(format!("{}{}", self.api_url, path));
+        if let Some(ref api_key) = self.api_key {
+            request = match self.source {
+                CrosscheckSource::Koios => request.bearer_auth(api_key),
+                CrosscheckSource::Blockfrost => request.header("project_id", api_key),
+            };
+        }
+        let response = request.send()?;
+        let status = response.status();
+        let body = response.text()?;
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(Error::Http {
+                name: self.source.name(),
+                status: status.as_u16(),
+                body,
+            });
+        }
+        Ok(Some(body))
+    }
+}
+
+fn parse_koios_block(body: &str) -> Result<Option<String>, Error> {
+    if body.is_empty() {
+        return Ok(None);
+    }
+    let blocks: Vec<KoiosBlock> = serde_json::from_str(body)?;
+    Ok(blocks.into_iter().next().map(|block| block.hash))
+}
+
+/// Compare the blocks of an epoch in the db with the chain an external source follows. A sample of block hashes is
+/// looked up by block number, and the block numbers are checked for gaps. Returns whether everything matched.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn crosscheck(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    epoch: u64,
+    source: CrosscheckSource,
+    samples: u64,
+    api_url: &Option<String>,
+    api_key: &Option<String>,
+) -> Result<bool, Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let byron = read_byron_genesis(byron_genesis)?;
+    debug!("{:?}", byron);
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    debug!("{:?}", shelley);
+
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+    if epoch < shelley_transition_epoch {
+        return Err(Error::Crosscheck(format!(
+            "Invalid Epoch: --epoch {epoch}, shelley_transition_epoch: {shelley_transition_epoch}"
+        )));
+    }
+
+    let api_url = match api_url {
+        Some(api_url) => api_url.trim_end_matches('/').to_string(),
+        None => source
+            .default_api_url(shelley.network_magic)
+            .ok_or_else(|| {
+                Error::Crosscheck(format!(
+                    "No known {} url for network magic {}, set --api-url",
+                    source.name(),
+                    shelley.network_magic
+                ))
+            })?
+            .to_string(),
+    };
+    if source == CrosscheckSource::Blockfrost && api_key.is_none() {
+        return Err(Error::Crosscheck(
+            "blockfrost needs a project id in --api-key".to_string(),
+        ));
+    }
+
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let (tip_epoch, _) = get_first_slot_of_epoch(&byron, &shelley, tip_slot_number, shelley_transition_epoch);
+    if epoch > tip_epoch {
+        return Err(Error::Crosscheck(format!(
+            "Invalid Epoch: --epoch {epoch}, db tip epoch: {tip_epoch}"
+        )));
+    }
+    let first_slot = get_first_slot_of_epoch_number(&byron, &shelley, epoch, shelley_transition_epoch);
+    // only look as far as the tip if the epoch is still in progress
+    let last_slot = (first_slot + shelley.epoch_length - 1).min(tip_slot_number);
+    debug!("first_slot: {}, last_slot: {}", first_slot, last_slot);
+
+    let mut blocks: Vec<Block> = block_store
+        .get_blocks_in_slot_range(first_slot, last_slot)?
+        .into_iter()
+        .filter(|block| !block.orphaned)
+        .collect();
+    blocks.sort_by_key(|block| block.block_number);
+    if blocks.is_empty() {
+        return Err(Error::Crosscheck(format!("No blocks of epoch {epoch} in the db")));
+    }
+    let sampled_blocks: Vec<&Block> = sample_indexes(blocks.len(), samples as usize)
+        .into_iter()
+        .map(|index| &blocks[index])
+        .collect();
+
+    let source_client = SourceClient {
+        source,
+        api_url,
+        api_key: api_key.clone(),
+        client: http::client(&HttpSettings::from_env()?, None)?,
+    };
+    let epoch_complete = epoch < tip_epoch;
+    // the blocking client must not run on a tokio runtime thread
+    let (source_hashes, source_blocks) = std::thread::scope(|scope| {
+        scope
+            .spawn(|| -> Result<(Vec<Option<String>>, Option<u64>), Error> {
+                let source_hashes = sampled_blocks
+                    .iter()
+                    .map(|block| source_client.block_hash(block.block_number))
+                    .collect::<Result<Vec<Option<String>>, Error>>()?;
+                let source_blocks = if epoch_complete {
+                    source_client.epoch_block_count(epoch)?
+                } else {
+                    None
+                };
+                Ok((source_hashes, source_blocks))
+            })
+            .join()
+            .map_err(|_| Error::Crosscheck("lookup thread panicked".to_string()))?
+    })?;
+
+    let divergences: Vec<Divergence> = sampled_blocks
+        .iter()
+        .zip(source_hashes)
+        .filter(|(block, source_hash)| source_hash.as_deref() != Some(block.hash.as_str()))
+        .map(|(block, source_hash)| Divergence {
+            block_number: block.block_number,
+            slot_number: block.slot_number,
+            local_hash: block.hash.clone(),
+            source_hash,
+        })
+        .collect();
+    let block_numbers: Vec<u64> = blocks.iter().map(|block| block.block_number).collect();
+    let missing_blocks = find_missing_blocks(&block_numbers);
+
+    let local_blocks = blocks.len() as u64;
+    let mut errors: Vec<String> = vec![];
+    if let Some(source_blocks) = source_blocks {
+        if source_blocks != local_blocks {
+            errors.push(format!(
+                "the db has {local_blocks} blocks in epoch {epoch}, {} has {source_blocks}",
+                source.name()
+            ));
+        }
+    }
+    let passed = divergences.is_empty() && missing_blocks.is_empty() && errors.is_empty();
+
+    reporter::report(&Crosscheck {
+        status: if passed { "ok" } else { "error" }.to_string(),
+        epoch,
+        source: source.name().to_string(),
+        first_slot,
+        last_slot,
+        local_blocks,
+        source_blocks,
+        sampled: sampled_blocks.len() as u64,
+        matched: (sampled_blocks.len() - divergences.len()) as u64,
+        divergences,
+        missing_blocks,
+        errors,
+    })?;
+
+    Ok(passed)
+}
+
+/// Indexes of up to samples items spread evenly over len items, always including the last one
+fn sample_indexes(len: usize, samples: usize) -> Vec<usize> {
+    if samples >= len {
+        return (0..len).collect();
+    }
+    if samples <= 1 {
+        return vec![len - 1];
+    }
+    (0..samples).map(|i| i * (len - 1) / (samples - 1)).collect()
+}
+
+/// Find every run of block numbers missing between the lowest and highest one. block_numbers must be sorted in
+/// ascending order.
+fn find_missing_blocks(block_numbers: &[u64]) -> Vec<MissingBlocks> {
+    block_numbers
+        .windows(2)
+        .filter(|pair| pair[1] > pair[0] + 1)
+        .map(|pair| MissingBlocks {
+            from_block_number: pair[0] + 1,
+            to_block_number: pair[1] - 1,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_indexes() {
+        assert_eq!(sample_indexes(3, 20), vec![0, 1, 2]);
+        assert_eq!(sample_indexes(101, 5), vec![0, 25, 50, 75, 100]);
+        assert_eq!(sample_indexes(10, 1), vec![9]);
+    }
+
+    #[test]
+    fn test_find_missing_blocks() {
+        assert_eq!(
+            find_missing_blocks(&[10, 11, 13, 14, 18]),
+            vec![
+                MissingBlocks {
+                    from_block_number: 12,
+                    to_block_number: 12
+                },
+                MissingBlocks {
+                    from_block_number: 15,
+                    to_block_number: 17
+                },
+            ]
+        );
+        assert!(find_missing_blocks(&[10]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_koios_block() {
+        assert_eq!(
+            parse_koios_block(r#"[{"hash": "aa"}]"#).unwrap(),
+            Some("aa".to_string())
+        );
+        assert_eq!(parse_koios_block("[]").unwrap(), None);
+        assert!(parse_koios_block("<html>Bad Gateway</html>").is_err());
+    }
+
+    #[test]
+    fn test_default_api_url() {
+        assert_eq!(
+            CrosscheckSource::from_str("blockfrost").unwrap().default_api_url(1),
+            Some("https://cardano-preprod.blockfrost.io/api/v0")
+        );
+        assert_eq!(CrosscheckSource::Koios.default_api_url(42), None);
+        assert!(CrosscheckSource::from_str("dbsync").is_err());
+    }
+}
@@ -0,0 +1,836 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use pallas_crypto::hash::Hash;
+use pallas_crypto::nonce::generate_rolling_nonce;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{debug, error, info};
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::redb::{is_redb_database, IndexCheck, RedbBlockStore, SalvagedTable};
+use crate::nodeclient::blockstore::sqlite::{SqLiteBlockStore, DEFAULT_BUSY_TIMEOUT_MS};
+use crate::nodeclient::blockstore::{Block, BlockStore};
+use crate::nodeclient::cancel;
+use crate::nodeclient::http;
+use crate::nodeclient::http::HttpSettings;
+use crate::nodeclient::reporter;
+
+/// Number of slots compared at a time so the whole chain never has to be held in memory
+const DIFF_WINDOW_SLOTS: u64 = 100_000;
+
+/// Maximum number of individual differences listed in the diff output
+const MAX_LISTED_DIFFERENCES: usize = 100;
+
+/// Log the download progress every this many bytes
+const DOWNLOAD_PROGRESS_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Every zstd frame starts with these bytes, the magic number 0xFD2FB528 in little endian
+const ZSTD_MAGIC_NUMBER: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How long backup waits for a running sync to take up its request before giving up
+const BACKUP_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often backup checks whether sync wrote the snapshot it asked for
+const BACKUP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] crate::nodeclient::http::Error),
+
+    #[error("Request error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("Hex error: {0}")]
+    Hex(#[from] hex::FromHexError),
+
+    #[error("Db error: {0}")]
+    Db(String),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DbDiff {
+    status: String,
+    identical: bool,
+    db: String,
+    other_db: String,
+    db_tip_slot: u64,
+    other_db_tip_slot: u64,
+    matching_blocks: u64,
+    only_in_db: u64,
+    only_in_other_db: u64,
+    mismatched_blocks: u64,
+    differences: Vec<Difference>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Difference {
+    kind: String,
+    slot_number: u64,
+    hash: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DbSnapshot {
+    status: String,
+    db: String,
+    archive: String,
+    backend: String,
+    tip_slot: u64,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RedbRepair {
+    status: String,
+    db: String,
+    /// redb found the database was not shut down cleanly and repaired it when it was opened
+    repaired_on_open: bool,
+    /// the integrity check found and repaired a problem a clean shutdown does not flag
+    repaired_by_integrity_check: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salvage_file: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    salvaged: Vec<SalvagedTable>,
+    indexes_valid: bool,
+    indexes: IndexCheck,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DbBootstrap {
+    status: String,
+    db: String,
+    url: String,
+    sha256: String,
+    backend: String,
+    tip_slot: u64,
+    /// non-orphaned blocks at the tip whose hash chain and rolling nonce were checked
+    verified_blocks: u64,
+    size: u64,
+}
+
+fn open_block_store(db_path: &Path) -> Result<Box<dyn BlockStore + Send>, Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    Ok(blockstore::open(db_path, true)?)
+}
+
+/// Compare the chain tables of two databases row-by-row, typically a primary db and the --mirror-db written by sync
+pub(crate) fn diff(db_path: &Path, other_db_path: &Path) -> Result<(), Error> {
+    let mut block_store = open_block_store(db_path)?;
+    let mut other_block_store = open_block_store(other_db_path)?;
+
+    let db_tip_slot = block_store.get_tip_slot_number()?;
+    let other_db_tip_slot = other_block_store.get_tip_slot_number()?;
+    let tip_slot = db_tip_slot.max(other_db_tip_slot);
+
+    let mut db_diff = DbDiff {
+        status: "ok".to_string(),
+        identical: false,
+        db: db_path.to_string_lossy().to_string(),
+        other_db: other_db_path.to_string_lossy().to_string(),
+        db_tip_slot,
+        other_db_tip_slot,
+        matching_blocks: 0,
+        only_in_db: 0,
+        only_in_other_db: 0,
+        mismatched_blocks: 0,
+        differences: vec![],
+    };
+
+    let mut first_slot = 0u64;
+    while first_slot <= tip_slot {
+        let last_slot = (first_slot + DIFF_WINDOW_SLOTS - 1).min(tip_slot);
+        debug!("comparing slots {} to {}", first_slot, last_slot);
+        let blocks = block_store.get_blocks_in_slot_range(first_slot, last_slot)?;
+        let mut other_blocks: HashMap<String, Block> = other_block_store
+            .get_blocks_in_slot_range(first_slot, last_slot)?
+            .into_iter()
+            .map(|block| (block.hash.clone(), block))
+            .collect();
+
+        for block in blocks {
+            match other_blocks.remove(&block.hash) {
+                None => {
+                    db_diff.only_in_db += 1;
+                    push_difference(&mut db_diff.differences, "onlyInDb", &block, vec![]);
+                }
+                Some(other_block) => {
+                    if other_block == block {
+                        db_diff.matching_blocks += 1;
+                    } else {
+                        db_diff.mismatched_blocks += 1;
+                        let fields = differing_fields(&block, &other_block);
+                        push_difference(&mut db_diff.differences, "mismatched", &block, fields);
+                    }
+                }
+            }
+        }
+        let mut other_blocks: Vec<Block> = other_blocks.into_values().collect();
+        other_blocks.sort_by_key(|block| block.slot_number);
+        for other_block in other_blocks {
+            db_diff.only_in_other_db += 1;
+            push_difference(&mut db_diff.differences, "onlyInOtherDb", &other_block, vec![]);
+        }
+
+        if last_slot % (DIFF_WINDOW_SLOTS * 100) == DIFF_WINDOW_SLOTS * 100 - 1 {
+            info!("compared up to slot {} of {}", last_slot, tip_slot);
+        }
+        first_slot = last_slot + 1;
+    }
+
+    db_diff.identical = db_diff.only_in_db == 0 && db_diff.only_in_other_db == 0 && db_diff.mismatched_blocks == 0;
+
+    reporter::report(&db_diff)?;
+
+    Ok(())
+}
+
+fn push_difference(differences: &mut Vec<Difference>, kind: &str, block: &Block, fields: Vec<String>) {
+    if differences.len() < MAX_LISTED_DIFFERENCES {
+        differences.push(Difference {
+            kind: kind.to_string(),
+            slot_number: block.slot_number,
+            hash: block.hash.clone(),
+            fields,
+        });
+    }
+}
+
+fn differing_fields(block: &Block, other_block: &Block) -> Vec<String> {
+    let mut fields: Vec<String> = vec![];
+    if block.block_number != other_block.block_number {
+        fields.push("blockNumber".to_string());
+    }
+    if block.slot_number != other_block.slot_number {
+        fields.push("slotNumber".to_string());
+    }
+    if block.prev_hash != other_block.prev_hash {
+        fields.push("prevHash".to_string());
+    }
+    if block.pool_id != other_block.pool_id {
+        fields.push("poolId".to_string());
+    }
+    if block.leader_vrf != other_block.leader_vrf {
+        fields.push("leaderVrf".to_string());
+    }
+    if block.eta_v != other_block.eta_v {
+        fields.push("etaV".to_string());
+    }
+    if block.eta_vrf_0 != other_block.eta_vrf_0 {
+        fields.push("etaVrf0".to_string());
+    }
+    if block.orphaned != other_block.orphaned {
+        fields.push("orphaned".to_string());
+    }
+    fields
+}
+
+/// A path next to the given one where a file is written before it is renamed into place
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+/// Whether a file is zstd compressed, by its first bytes rather than a file name that may not tell
+fn is_zstd(path: &Path) -> Result<bool, Error> {
+    let mut magic_number = [0u8; 4];
+    match File::open(path)?.read_exact(&mut magic_number) {
+        Ok(()) => Ok(magic_number == ZSTD_MAGIC_NUMBER),
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Open a snapshot without writing to it and return its backend and tip slot, which checks that it is usable
+fn inspect_snapshot(path: &Path) -> Result<(String, u64), Error> {
+    if is_redb_database(path)? {
+        let mut block_store = RedbBlockStore::new(path)?;
+        Ok(("redb".to_string(), block_store.get_tip_slot_number()?))
+    } else {
+        let mut block_store = SqLiteBlockStore::new_with_options(path, DEFAULT_BUSY_TIMEOUT_MS, true)?;
+        Ok(("sqlite".to_string(), block_store.get_tip_slot_number()?))
+    }
+}
+
+/// Write a consistent snapshot of the database to a zstd compressed tar archive. Copying the database files of a
+/// running sync can catch a half-written page or leave the WAL behind, which yields a corrupt copy.
+pub(crate) fn backup(db_path: &Path, archive: &Path) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    if archive.exists() {
+        return Err(Error::Db(format!("{} already exists", archive.display())));
+    }
+    let snapshot = sibling_path(archive, ".snapshot");
+    let partial = sibling_path(archive, ".partial");
+    for leftover in [&snapshot, &partial] {
+        if leftover.exists() {
+            // left behind by an interrupted backup
+            std::fs::remove_file(leftover)?;
+        }
+    }
+
+    if is_redb_database(db_path)? {
+        match RedbBlockStore::new_no_wait(db_path) {
+            Ok(block_store) => block_store.backup_to(&snapshot)?,
+            // redb allows only one process to open the database, so a running sync has to take the snapshot
+            Err(error) if error.is_already_open() => request_backup_from_sync(db_path, &snapshot)?,
+            Err(error) => return Err(error.into()),
+        }
+    } else {
+        SqLiteBlockStore::new_with_options(db_path, DEFAULT_BUSY_TIMEOUT_MS, true)?.backup_to(&snapshot)?;
+    }
+
+    let (backend, tip_slot) = inspect_snapshot(&snapshot)?;
+    let name = db_path.file_name().unwrap_or("cncli.db".as_ref());
+    write_archive(&snapshot, Path::new(name), &partial)?;
+    std::fs::remove_file(&snapshot)?;
+    std::fs::rename(&partial, archive)?;
+    info!(
+        "backed up {} to {} at slot {}",
+        db_path.display(),
+        archive.display(),
+        tip_slot
+    );
+
+    reporter::report(&DbSnapshot {
+        status: "ok".to_string(),
+        db: db_path.to_string_lossy().to_string(),
+        archive: archive.to_string_lossy().to_string(),
+        backend,
+        tip_slot,
+        size: std::fs::metadata(archive)?.len(),
+    })?;
+
+    Ok(())
+}
+
+/// Where backup asks a running sync for a snapshot of a redb database, by writing the path to take it to
+pub(crate) fn backup_request_path(db_path: &Path) -> PathBuf {
+    sibling_path(db_path, ".backup-request")
+}
+
+/// Ask the sync that has the redb database open to write a snapshot to the snapshot path and wait until it did
+fn request_backup_from_sync(db_path: &Path, snapshot: &Path) -> Result<(), Error> {
+    let backup_request = backup_request_path(db_path);
+    // sync may run in another directory
+    let snapshot = std::env::current_dir()?.join(snapshot);
+    let writing = sibling_path(&snapshot, ".writing");
+    // sync must never read half a request
+    let partial_request = sibling_path(&backup_request, ".partial");
+    std::fs::write(&partial_request, snapshot.to_string_lossy().as_bytes())?;
+    std::fs::rename(&partial_request, &backup_request)?;
+    info!(
+        "{} is open in another process, asked sync to write the snapshot",
+        db_path.display()
+    );
+
+    let requested_at = Instant::now();
+    while backup_request.exists() {
+        if requested_at.elapsed() > BACKUP_REQUEST_TIMEOUT && !writing.exists() {
+            std::fs::remove_file(&backup_request)?;
+            return Err(Error::Db(format!(
+                "{} is open in another process that did not take up the backup request within {}s, only a running sync can write the snapshot",
+                db_path.display(),
+                BACKUP_REQUEST_TIMEOUT.as_secs()
+            )));
+        }
+        sleep(BACKUP_POLL_INTERVAL);
+    }
+    if !snapshot.exists() {
+        return Err(Error::Db(
+            "sync could not write the snapshot, its log has the reason".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Write the snapshot backup asked for in backup_request, if there is one. The snapshot is written next to the path
+/// asked for and renamed into place once it is complete, and the request is removed either way.
+pub(crate) fn write_requested_backup(block_store: &mut dyn BlockStore, backup_request: &Path) {
+    let snapshot = match std::fs::read_to_string(backup_request) {
+        Ok(snapshot) => PathBuf::from(snapshot.trim()),
+        Err(_) => return,
+    };
+    info!("Writing the snapshot requested by db backup to {}", snapshot.display());
+    let writing = sibling_path(&snapshot, ".writing");
+    let result = block_store
+        .backup_to(&writing)
+        .map_err(Error::from)
+        .and_then(|()| Ok(std::fs::rename(&writing, &snapshot)?));
+    if let Err(error) = result {
+        error!("Could not write the snapshot requested by db backup: {}", error);
+        if writing.exists() {
+            let _ = std::fs::remove_file(&writing);
+        }
+    }
+    if let Err(error) = std::fs::remove_file(backup_request) {
+        error!("Could not remove {}: {}", backup_request.display(), error);
+    }
+}
+
+/// Pack the snapshot into a zstd compressed tar archive under the given name
+fn write_archive(snapshot: &Path, name: &Path, archive: &Path) -> Result<(), Error> {
+    let encoder = zstd::Encoder::new(File::create(archive)?, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+    builder.append_path_with_name(snapshot, name)?;
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Unpack the database file of an archive written by backup to path
+fn extract_archive(archive: &Path, path: &Path) -> Result<(), Error> {
+    let mut entries = tar::Archive::new(zstd::Decoder::new(File::open(archive)?)?);
+    for entry in entries.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() {
+            entry.unpack(path)?;
+            return Ok(());
+        }
+    }
+    Err(Error::Db(format!("{} holds no database file", archive.display())))
+}
+
+/// Replace the database with a snapshot written by backup. Sync must not be running against the database.
+pub(crate) fn restore(db_path: &Path, archive: &Path, force: bool) -> Result<(), Error> {
+    if !archive.exists() {
+        return Err(Error::InvalidPath(archive.to_path_buf()));
+    }
+    if db_path.exists() && !force {
+        return Err(Error::Db(format!(
+            "{} already exists, stop sync and pass --force to replace it",
+            db_path.display()
+        )));
+    }
+
+    // unpack next to the database first so it is only replaced once the whole snapshot is there
+    let partial = sibling_path(db_path, ".partial");
+    if is_zstd(archive)? {
+        extract_archive(archive, &partial)?;
+    } else {
+        // a plain database file, e.g. one copied while sync was stopped
+        std::fs::copy(archive, &partial)?;
+    }
+    let (backend, tip_slot) = match inspect_snapshot(&partial) {
+        Ok(inspected) => inspected,
+        Err(error) => {
+            std::fs::remove_file(&partial)?;
+            return Err(error);
+        }
+    };
+    std::fs::rename(&partial, db_path)?;
+    // a WAL left by the replaced sqlite database would be replayed onto the restored one. It is only removed once the
+    // restored database is in place, a failed restore leaves the old database as it was.
+    for suffix in ["-wal", "-shm"] {
+        let sidecar = sibling_path(db_path, suffix);
+        if sidecar.exists() {
+            std::fs::remove_file(&sidecar)?;
+        }
+    }
+    info!(
+        "restored {} from {} at slot {}",
+        db_path.display(),
+        archive.display(),
+        tip_slot
+    );
+
+    reporter::report(&DbSnapshot {
+        status: "ok".to_string(),
+        db: db_path.to_string_lossy().to_string(),
+        archive: archive.to_string_lossy().to_string(),
+        backend,
+        tip_slot,
+        size: std::fs::metadata(db_path)?.len(),
+    })?;
+
+    Ok(())
+}
+
+/// Repair a redb database explicitly, logging the progress, then check that its indexes match the records. With
+/// salvage_to, every readable record is also copied into a new database with rebuilt indexes, which are checked
+/// instead. Sync must not be running against the database.
+pub(crate) fn repair_redb(db_path: &Path, salvage_to: &Option<PathBuf>) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    if !is_redb_database(db_path)? {
+        return Err(Error::Db(format!("{} is not a redb database", db_path.display())));
+    }
+    if let Some(salvage_to) = salvage_to {
+        if salvage_to.exists() {
+            return Err(Error::Db(format!("{} already exists", salvage_to.display())));
+        }
+    }
+
+    let (mut block_store, repaired_on_open) = RedbBlockStore::open_for_repair(db_path)?;
+    let repaired_by_integrity_check = !block_store.check_integrity()?;
+
+    let (salvaged, indexes) = match salvage_to {
+        Some(salvage_to) => {
+            let partial = sibling_path(salvage_to, ".partial");
+            if partial.exists() {
+                // left behind by an interrupted salvage
+                std::fs::remove_file(&partial)?;
+            }
+            let salvaged = block_store.salvage_to(&partial)?;
+            drop(block_store);
+            let (salvaged_block_store, _) = RedbBlockStore::open_for_repair(&partial)?;
+            let indexes = salvaged_block_store.check_indexes()?;
+            drop(salvaged_block_store);
+            std::fs::rename(&partial, salvage_to)?;
+            info!("salvaged {} to {}", db_path.display(), salvage_to.display());
+            (salvaged, indexes)
+        }
+        None => (vec![], block_store.check_indexes()?),
+    };
+
+    reporter::report(&RedbRepair {
+        status: "ok".to_string(),
+        db: db_path.to_string_lossy().to_string(),
+        repaired_on_open,
+        repaired_by_integrity_check,
+        salvage_file: salvage_to
+            .as_ref()
+            .map(|salvage_to| salvage_to.to_string_lossy().to_string()),
+        salvaged,
+        indexes_valid: indexes.is_valid(),
+        indexes,
+    })?;
+
+    Ok(())
+}
+
+/// Download a pre-synced database snapshot, resuming a download interrupted before, check it against the expected
+/// sha256, decompress it when it is zstd compressed and check the hash chain and rolling nonce of the blocks in the
+/// last verify_slots before putting it in place of the database.
+pub(crate) fn bootstrap(db_path: &Path, url: &str, sha256: &str, verify_slots: u64, force: bool) -> Result<(), Error> {
+    if db_path.exists() && !force {
+        return Err(Error::Db(format!(
+            "{} already exists, stop sync and pass --force to replace it",
+            db_path.display()
+        )));
+    }
+    let sha256 = sha256.to_lowercase();
+    if hex::decode(&sha256)?.len() != 32 {
+        return Err(Error::Db(format!("--sha256 {sha256} is not a sha256 hash")));
+    }
+
+    // the download is kept until it checks out so an interrupted one can be resumed by running bootstrap again
+    let download_path = sibling_path(db_path, ".download");
+    let client = http::download_client(&HttpSettings::from_env()?)?;
+    download(&client, url, &download_path)?;
+
+    let download_sha256 = sha256_file(&download_path)?;
+    if download_sha256 != sha256 {
+        std::fs::remove_file(&download_path)?;
+        return Err(Error::Db(format!(
+            "{url} has sha256 {download_sha256} instead of {sha256}, the download was removed"
+        )));
+    }
+    info!("downloaded {} with sha256 {}", url, sha256);
+
+    if let Some(cancelled) = cancel::cancelled() {
+        return Err(Error::Db(format!(
+            "bootstrap was {cancelled} after the download, run it again to check and put it in place"
+        )));
+    }
+
+    let partial = sibling_path(db_path, ".partial");
+    if is_zstd(&download_path)? {
+        info!("decompressing {}", download_path.display());
+        zstd::stream::copy_decode(File::open(&download_path)?, File::create(&partial)?)?;
+    } else {
+        std::fs::copy(&download_path, &partial)?;
+    }
+
+    let (backend, tip_slot) = inspect_snapshot(&partial)?;
+    let verified_blocks = {
+        let mut block_store = blockstore::open(&partial, true)?;
+        match verify_chain_tail(block_store.as_mut(), tip_slot.saturating_sub(verify_slots), tip_slot) {
+            Ok(verified_blocks) => verified_blocks,
+            Err(error) => {
+                drop(block_store);
+                std::fs::remove_file(&partial)?;
+                return Err(error);
+            }
+        }
+    };
+
+    // a WAL left by the replaced sqlite database would be replayed onto the bootstrapped one
+    for suffix in ["-wal", "-shm"] {
+        let sidecar = sibling_path(db_path, suffix);
+        if sidecar.exists() {
+            std::fs::remove_file(&sidecar)?;
+        }
+    }
+    std::fs::rename(&partial, db_path)?;
+    std::fs::remove_file(&download_path)?;
+    info!("bootstrapped {} from {} at slot {}", db_path.display(), url, tip_slot);
+
+    reporter::report(&DbBootstrap {
+        status: "ok".to_string(),
+        db: db_path.to_string_lossy().to_string(),
+        url: url.to_string(),
+        sha256,
+        backend,
+        tip_slot,
+        verified_blocks,
+        size: std::fs::metadata(db_path)?.len(),
+    })?;
+
+    Ok(())
+}
+
+/// Download url to path. When path already holds the start of it, only the rest is requested. A server that does
+/// not support ranges sends everything again, which replaces what is there.
+fn download(client: &reqwest::blocking::Client, url: &str, path: &Path) -> Result<(), Error> {
+    let offset = match path.exists() {
+        true => std::fs::metadata(path)?.len(),
+        false => 0,
+    };
+    let mut request = client.get(url);
+    if offset > 0 {
+        info!("resuming the download of {} at byte {}", url, offset);
+        request = request.header(reqwest::header::RANGE, format!("bytes={offset}-"));
+    }
+    let mut response = request.send()?;
+    let append = match response.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => true,
+        // nothing left after the offset, the download was already complete
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+        status if status.is_success() => {
+            if offset > 0 {
+                info!("{} does not support resuming, downloading it again", url);
+            }
+            false
+        }
+        status => return Err(Error::Db(format!("GET {url}: {status}"))),
+    };
+    let total = response
+        .content_length()
+        .map(|length| length + if append { offset } else { 0 });
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    let mut downloaded = if append { offset } else { 0 };
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        if let Some(cancelled) = cancel::cancelled() {
+            file.sync_all()?;
+            return Err(Error::Db(format!(
+                "the download of {url} was {cancelled} at byte {downloaded}, run bootstrap again to resume it"
+            )));
+        }
+        let read = response.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buf[..read])?;
+        let before = downloaded;
+        downloaded += read as u64;
+        if before / DOWNLOAD_PROGRESS_BYTES != downloaded / DOWNLOAD_PROGRESS_BYTES {
+            match total {
+                Some(total) => info!("downloaded {} of {} bytes", downloaded, total),
+                None => info!("downloaded {} bytes", downloaded),
+            }
+        }
+    }
+    file.sync_all()?;
+
+    if let Some(total) = total {
+        if downloaded != total {
+            return Err(Error::Db(format!(
+                "the download of {url} stopped at byte {downloaded} of {total}, run bootstrap again to resume it"
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Check that the non-orphaned blocks between first_slot and last_slot each point at the one before them and that
+/// recomputing the rolling nonce from their eta_vrf_0 gives the eta_v stored with them. Returns how many were checked.
+fn verify_chain_tail(block_store: &mut dyn BlockStore, first_slot: u64, last_slot: u64) -> Result<u64, Error> {
+    let mut eta_v: Hash<32> = block_store.get_eta_v_before_slot(first_slot)?;
+    let mut prev_hash: Option<String> = None;
+    let mut verified_blocks = 0u64;
+    for block in block_store
+        .get_blocks_in_slot_range(first_slot, last_slot)?
+        .into_iter()
+        .filter(|block| !block.orphaned)
+    {
+        if let Some(prev_hash) = prev_hash {
+            if block.prev_hash != prev_hash {
+                return Err(Error::Db(format!(
+                    "block {} at slot {} does not follow block {}",
+                    block.hash, block.slot_number, prev_hash
+                )));
+            }
+        }
+        eta_v = generate_rolling_nonce(eta_v, &hex::decode(&block.eta_vrf_0)?);
+        if hex::encode(eta_v) != block.eta_v {
+            return Err(Error::Db(format!(
+                "block {} at slot {} has eta_v {} instead of {}",
+                block.hash,
+                block.slot_number,
+                block.eta_v,
+                hex::encode(eta_v)
+            )));
+        }
+        prev_hash = Some(block.hash);
+        verified_blocks += 1;
+    }
+    if verified_blocks == 0 {
+        return Err(Error::Db(format!(
+            "no blocks between slot {first_slot} and {last_slot} to verify the nonce chain with"
+        )));
+    }
+    Ok(verified_blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nodeclient::blockstore::memory::MemoryBlockStore;
+    use crate::nodeclient::sync::BlockHeader;
+
+    #[test]
+    fn test_verify_chain_tail() {
+        let mut block_store = MemoryBlockStore::new();
+        let mut blocks: Vec<BlockHeader> = (1..=3u8)
+            .map(|i| BlockHeader {
+                block_number: i as u64,
+                slot_number: i as u64 * 10,
+                hash: vec![i; 32],
+                prev_hash: vec![i - 1; 32],
+                node_vkey: vec![1; 32],
+                eta_vrf_0: vec![i; 64],
+                ..BlockHeader::default()
+            })
+            .collect();
+        block_store
+            .save_block(
+                &mut blocks,
+                "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81",
+            )
+            .unwrap();
+
+        assert_eq!(verify_chain_tail(&mut block_store, 11, 30).unwrap(), 2);
+        assert_eq!(verify_chain_tail(&mut block_store, 21, 30).unwrap(), 1);
+        // nothing to verify after the tip
+        assert!(verify_chain_tail(&mut block_store, 31, 40).is_err());
+    }
+
+    #[test]
+    fn test_write_requested_backup() {
+        let dir = std::env::temp_dir().join(format!("cncli_test_backup_request_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("cncli.redb");
+        let snapshot = dir.join("cncli-backup.tar.zst.snapshot");
+        let backup_request = backup_request_path(&db_path);
+        let mut block_store = RedbBlockStore::new(&db_path).unwrap();
+        block_store
+            .save_block(
+                &mut vec![BlockHeader {
+                    block_number: 1,
+                    slot_number: 10,
+                    hash: vec![1; 32],
+                    prev_hash: vec![0; 32],
+                    node_vkey: vec![1; 32],
+                    eta_vrf_0: vec![1; 64],
+                    ..BlockHeader::default()
+                }],
+                "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81",
+            )
+            .unwrap();
+
+        // nothing requested
+        write_requested_backup(&mut block_store, &backup_request);
+        assert!(!snapshot.exists());
+
+        std::fs::write(&backup_request, snapshot.to_string_lossy().as_bytes()).unwrap();
+        write_requested_backup(&mut block_store, &backup_request);
+        assert!(!backup_request.exists());
+        drop(block_store);
+        assert_eq!(inspect_snapshot(&snapshot).unwrap(), ("redb".to_string(), 10));
+
+        // packed and unpacked again
+        let archive = dir.join("cncli-backup.tar.zst");
+        let restored = dir.join("restored.redb");
+        write_archive(&snapshot, Path::new("cncli.redb"), &archive).unwrap();
+        assert!(is_zstd(&archive).unwrap());
+        extract_archive(&archive, &restored).unwrap();
+        assert_eq!(inspect_snapshot(&restored).unwrap(), ("redb".to_string(), 10));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sha256_file() {
+        let path = std::env::temp_dir().join("cncli_test_sha256_file");
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            sha256_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_is_zstd() {
+        let path = std::env::temp_dir().join("cncli_test_is_zstd");
+        std::fs::write(&path, zstd::encode_all(&b"SQLite format 3\0"[..], 3).unwrap()).unwrap();
+        assert!(is_zstd(&path).unwrap());
+        std::fs::write(&path, b"SQLite format 3\0").unwrap();
+        assert!(!is_zstd(&path).unwrap());
+        std::fs::write(&path, b"(").unwrap();
+        assert!(!is_zstd(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
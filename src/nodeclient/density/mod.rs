@@ -0,0 +1,200 @@
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::leaderlog;
+use crate::nodeclient::leaderlog::{
+    get_first_slot_of_epoch, get_first_slot_of_epoch_number, guess_shelley_transition_epoch, read_byron_genesis,
+    read_shelley_genesis,
+};
+use crate::nodeclient::reporter;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] leaderlog::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Density error: {0}")]
+    Density(String),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Density {
+    status: String,
+    epoch: u64,
+    first_slot: u64,
+    last_slot: u64,
+    slots_elapsed: u64,
+    blocks: u64,
+    orphaned_blocks: u64,
+    expected_blocks: f64,
+    density: f64,
+    min_gap_slots: u64,
+    gaps: Vec<Gap>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Gap {
+    from_slot: u64,
+    to_slot: u64,
+    slots: u64,
+}
+
+pub(crate) fn calculate_density(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    epoch: u64,
+    min_gap_slots: u64,
+) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let byron = read_byron_genesis(byron_genesis)?;
+    debug!("{:?}", byron);
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    debug!("{:?}", shelley);
+
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+
+    if epoch < shelley_transition_epoch {
+        return Err(Error::Density(format!(
+            "Invalid Epoch: --epoch {epoch}, shelley_transition_epoch: {shelley_transition_epoch}"
+        )));
+    }
+
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let (tip_epoch, _) = get_first_slot_of_epoch(&byron, &shelley, tip_slot_number, shelley_transition_epoch);
+    if epoch > tip_epoch {
+        return Err(Error::Density(format!(
+            "Invalid Epoch: --epoch {epoch}, db tip epoch: {tip_epoch}"
+        )));
+    }
+
+    let first_slot = get_first_slot_of_epoch_number(&byron, &shelley, epoch, shelley_transition_epoch);
+    // only look as far as the tip if the epoch is still in progress
+    let last_slot = (first_slot + shelley.epoch_length - 1).min(tip_slot_number);
+    debug!("first_slot: {}, last_slot: {}", first_slot, last_slot);
+
+    let blocks = block_store.get_blocks_in_slot_range(first_slot, last_slot)?;
+    let orphaned_blocks = blocks.iter().filter(|block| block.orphaned).count() as u64;
+    let block_slots: Vec<u64> = blocks
+        .iter()
+        .filter(|block| !block.orphaned)
+        .map(|block| block.slot_number)
+        .collect();
+
+    let slots_elapsed = last_slot - first_slot + 1;
+    let expected_blocks = (slots_elapsed as f64 * shelley.active_slots_coeff * 100.0).round() / 100.0;
+    let density = (block_slots.len() as f64 / slots_elapsed as f64 * 100000.0).round() / 100000.0;
+
+    let density = Density {
+        status: "ok".to_string(),
+        epoch,
+        first_slot,
+        last_slot,
+        slots_elapsed,
+        blocks: block_slots.len() as u64,
+        orphaned_blocks,
+        expected_blocks,
+        density,
+        min_gap_slots,
+        gaps: find_gaps(&block_slots, first_slot, last_slot, min_gap_slots),
+    };
+
+    reporter::report(&density)?;
+
+    Ok(())
+}
+
+/// Find every stretch of empty slots between first_slot and last_slot that is longer than min_gap_slots.
+/// block_slots must be sorted in ascending order.
+fn find_gaps(block_slots: &[u64], first_slot: u64, last_slot: u64, min_gap_slots: u64) -> Vec<Gap> {
+    let mut gaps: Vec<Gap> = vec![];
+    let mut next_empty_slot = first_slot;
+    for slot in block_slots.iter().chain(std::iter::once(&(last_slot + 1))) {
+        if *slot > next_empty_slot {
+            let empty_slots = slot - next_empty_slot;
+            if empty_slots > min_gap_slots {
+                gaps.push(Gap {
+                    from_slot: next_empty_slot,
+                    to_slot: slot - 1,
+                    slots: empty_slots,
+                });
+            }
+        }
+        next_empty_slot = next_empty_slot.max(slot + 1);
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_gaps() {
+        let block_slots = [105u64, 110, 300, 301, 900];
+        let gaps = find_gaps(&block_slots, 100, 1000, 50);
+        assert_eq!(
+            gaps,
+            vec![
+                Gap {
+                    from_slot: 111,
+                    to_slot: 299,
+                    slots: 189
+                },
+                Gap {
+                    from_slot: 302,
+                    to_slot: 899,
+                    slots: 598
+                },
+                Gap {
+                    from_slot: 901,
+                    to_slot: 1000,
+                    slots: 100
+                },
+            ]
+        );
+
+        // leading gap and no blocks at all
+        let gaps = find_gaps(&[], 100, 199, 10);
+        assert_eq!(
+            gaps,
+            vec![Gap {
+                from_slot: 100,
+                to_slot: 199,
+                slots: 100
+            }]
+        );
+    }
+}
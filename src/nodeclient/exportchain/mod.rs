@@ -0,0 +1,333 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::Value;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::BlockDetail;
+use crate::nodeclient::reporter;
+
+// slots read from the db at a time, about 6 hours of mainnet blocks, so an export of the whole chain is not held in
+// memory at once
+const PAGE_SLOTS: u64 = 21600;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Export chain error: {0}")]
+    ExportChain(String),
+}
+
+/// A block header field that can be exported, named like the columns of the sqlite chain table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportField {
+    Slot,
+    BlockNumber,
+    Hash,
+    PrevHash,
+    PoolId,
+    EtaV,
+    NodeVkey,
+    NodeVrfVkey,
+    BlockVrf0,
+    BlockVrf1,
+    EtaVrf0,
+    EtaVrf1,
+    LeaderVrf0,
+    LeaderVrf1,
+    BlockSize,
+    BlockBodyHash,
+    OpcertHotVkey,
+    OpcertSequenceNumber,
+    OpcertKesPeriod,
+    OpcertSigma,
+    ProtocolMajorVersion,
+    ProtocolMinorVersion,
+    Orphaned,
+    ReceivedAt,
+    SourcePeer,
+}
+
+const EXPORT_FIELDS: [(&str, ExportField); 25] = [
+    ("slot", ExportField::Slot),
+    ("block_number", ExportField::BlockNumber),
+    ("hash", ExportField::Hash),
+    ("prev_hash", ExportField::PrevHash),
+    ("pool_id", ExportField::PoolId),
+    ("eta_v", ExportField::EtaV),
+    ("node_vkey", ExportField::NodeVkey),
+    ("node_vrf_vkey", ExportField::NodeVrfVkey),
+    ("block_vrf_0", ExportField::BlockVrf0),
+    ("block_vrf_1", ExportField::BlockVrf1),
+    ("eta_vrf_0", ExportField::EtaVrf0),
+    ("eta_vrf_1", ExportField::EtaVrf1),
+    ("leader_vrf_0", ExportField::LeaderVrf0),
+    ("leader_vrf_1", ExportField::LeaderVrf1),
+    ("block_size", ExportField::BlockSize),
+    ("block_body_hash", ExportField::BlockBodyHash),
+    ("opcert_hot_vkey", ExportField::OpcertHotVkey),
+    ("opcert_sequence_number", ExportField::OpcertSequenceNumber),
+    ("opcert_kes_period", ExportField::OpcertKesPeriod),
+    ("opcert_sigma", ExportField::OpcertSigma),
+    ("protocol_major_version", ExportField::ProtocolMajorVersion),
+    ("protocol_minor_version", ExportField::ProtocolMinorVersion),
+    ("orphaned", ExportField::Orphaned),
+    ("received_at", ExportField::ReceivedAt),
+    ("source_peer", ExportField::SourcePeer),
+];
+
+impl FromStr for ExportField {
+    type Err = String;
+    fn from_str(field: &str) -> Result<Self, Self::Err> {
+        EXPORT_FIELDS
+            .iter()
+            .find(|(name, _)| *name == field.trim())
+            .map(|(_, export_field)| *export_field)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid field: {field}, expected one of {}",
+                    EXPORT_FIELDS.map(|(name, _)| name).join(", ")
+                )
+            })
+    }
+}
+
+impl ExportField {
+    fn name(&self) -> &'static str {
+        EXPORT_FIELDS
+            .iter()
+            .find(|(_, export_field)| export_field == self)
+            .map(|(name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    fn value(&self, block_detail: &BlockDetail) -> Value {
+        match self {
+            ExportField::Slot => Value::from(block_detail.slot_number),
+            ExportField::BlockNumber => Value::from(block_detail.block_number),
+            ExportField::Hash => Value::from(block_detail.hash.as_str()),
+            ExportField::PrevHash => Value::from(block_detail.prev_hash.as_str()),
+            ExportField::PoolId => Value::from(pool_id_hex(&block_detail.pool_id)),
+            ExportField::EtaV => Value::from(block_detail.eta_v.as_str()),
+            ExportField::NodeVkey => Value::from(block_detail.node_vkey.as_str()),
+            ExportField::NodeVrfVkey => Value::from(block_detail.node_vrf_vkey.as_str()),
+            ExportField::BlockVrf0 => Value::from(block_detail.block_vrf_0.as_str()),
+            ExportField::BlockVrf1 => Value::from(block_detail.block_vrf_1.as_str()),
+            ExportField::EtaVrf0 => Value::from(block_detail.eta_vrf_0.as_str()),
+            ExportField::EtaVrf1 => Value::from(block_detail.eta_vrf_1.as_str()),
+            ExportField::LeaderVrf0 => Value::from(block_detail.leader_vrf_0.as_str()),
+            ExportField::LeaderVrf1 => Value::from(block_detail.leader_vrf_1.as_str()),
+            ExportField::BlockSize => Value::from(block_detail.block_size),
+            ExportField::BlockBodyHash => Value::from(block_detail.block_body_hash.as_str()),
+            ExportField::OpcertHotVkey => Value::from(block_detail.opcert_hot_vkey.as_str()),
+            ExportField::OpcertSequenceNumber => Value::from(block_detail.opcert_sequence_number),
+            ExportField::OpcertKesPeriod => Value::from(block_detail.opcert_kes_period),
+            ExportField::OpcertSigma => Value::from(block_detail.opcert_sigma.as_str()),
+            ExportField::ProtocolMajorVersion => Value::from(block_detail.protocol_major_version),
+            ExportField::ProtocolMinorVersion => Value::from(block_detail.protocol_minor_version),
+            ExportField::Orphaned => Value::from(block_detail.orphaned),
+            ExportField::ReceivedAt => Value::from(block_detail.received_at.as_deref()),
+            ExportField::SourcePeer => Value::from(block_detail.source_peer.as_deref()),
+        }
+    }
+}
+
+/// Blocks saved to sqlite by sync hold the pool_id hex encoded twice, blocks filled in by the v3 migration and redb
+/// blocks only once
+fn pool_id_hex(pool_id: &str) -> String {
+    if pool_id.len() == 112 {
+        if let Some(decoded) = hex::decode(pool_id)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+        {
+            return decoded;
+        }
+    }
+    pool_id.to_string()
+}
+
+/// Write the fields of the blocks between from_slot and to_slot (inclusive) as CSV or JSON lines. Blocks come ordered
+/// by slot, so a large range can be exported in pages that each start one slot after the last one written.
+pub(crate) fn export_chain(
+    db_path: &Path,
+    from_slot: u64,
+    to_slot: &Option<u64>,
+    fields: &[ExportField],
+    format: &str,
+    include_orphans: bool,
+) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    if fields.is_empty() {
+        return Err(Error::ExportChain("--fields must name at least one field".to_string()));
+    }
+    let jsonl = match format {
+        "csv" => false,
+        "jsonl" => true,
+        _ => return Err(Error::ExportChain(format!("Invalid --format {format}"))),
+    };
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let to_slot = match *to_slot {
+        Some(to_slot) => to_slot,
+        None => block_store.get_tip_slot_number()?,
+    };
+    if from_slot > to_slot {
+        return Err(Error::ExportChain(format!(
+            "--from-slot {from_slot} is after --to-slot {to_slot}"
+        )));
+    }
+
+    if !jsonl {
+        let header: Vec<&str> = fields.iter().map(ExportField::name).collect();
+        reporter::write_report(format!("{}\n", header.join(",")).as_bytes())?;
+    }
+    let mut first_slot = from_slot;
+    loop {
+        let last_slot = first_slot.saturating_add(PAGE_SLOTS - 1).min(to_slot);
+        debug!("first_slot: {}, last_slot: {}", first_slot, last_slot);
+        let block_details = block_store.get_block_details_in_slot_range(first_slot, last_slot)?;
+        let mut out = String::new();
+        for block_detail in block_details
+            .iter()
+            .filter(|block_detail| include_orphans || !block_detail.orphaned)
+        {
+            out.push_str(&render_row(block_detail, fields, jsonl)?);
+            out.push('\n');
+        }
+        if !out.is_empty() {
+            reporter::write_report(out.as_bytes())?;
+        }
+        if last_slot >= to_slot {
+            break;
+        }
+        first_slot = last_slot + 1;
+    }
+
+    Ok(())
+}
+
+/// A block as a CSV row or a JSON object with the fields in the order given. None of the values contain a comma.
+fn render_row(block_detail: &BlockDetail, fields: &[ExportField], jsonl: bool) -> Result<String, Error> {
+    if jsonl {
+        let members = fields
+            .iter()
+            .map(|field| {
+                Ok(format!(
+                    "{}:{}",
+                    serde_json::to_string(field.name())?,
+                    serde_json::to_string(&field.value(block_detail))?
+                ))
+            })
+            .collect::<Result<Vec<String>, serde_json::Error>>()?;
+        return Ok(format!("{{{}}}", members.join(",")));
+    }
+    let values: Vec<String> = fields
+        .iter()
+        .map(|field| match field.value(block_detail) {
+            Value::String(value) => value,
+            // not known for the block
+            Value::Null => String::new(),
+            value => value.to_string(),
+        })
+        .collect();
+    Ok(values.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL_ID: &str = "00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114";
+
+    fn block_detail() -> BlockDetail {
+        BlockDetail {
+            block_number: 10_000_000,
+            slot_number: 137865432,
+            hash: "aa".to_string(),
+            prev_hash: "bb".to_string(),
+            pool_id: hex::encode(POOL_ID),
+            eta_v: String::new(),
+            node_vkey: String::new(),
+            node_vrf_vkey: String::new(),
+            block_vrf_0: String::new(),
+            block_vrf_1: String::new(),
+            eta_vrf_0: String::new(),
+            eta_vrf_1: String::new(),
+            leader_vrf_0: String::new(),
+            leader_vrf_1: String::new(),
+            block_size: 512,
+            block_body_hash: String::new(),
+            opcert_hot_vkey: String::new(),
+            opcert_sequence_number: 7,
+            opcert_kes_period: 1042,
+            opcert_sigma: String::new(),
+            protocol_major_version: 10,
+            protocol_minor_version: 0,
+            orphaned: false,
+            header_cbor: None,
+            received_at: None,
+            source_peer: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_export_field() {
+        assert_eq!(ExportField::from_str("slot").unwrap(), ExportField::Slot);
+        assert_eq!(
+            ExportField::from_str(" opcert_kes_period").unwrap(),
+            ExportField::OpcertKesPeriod
+        );
+        assert!(ExportField::from_str("slot_number").is_err());
+        assert_eq!(ExportField::LeaderVrf1.name(), "leader_vrf_1");
+    }
+
+    #[test]
+    fn test_render_row() {
+        let fields = [
+            ExportField::Slot,
+            ExportField::Hash,
+            ExportField::PoolId,
+            ExportField::Orphaned,
+        ];
+        assert_eq!(
+            render_row(&block_detail(), &fields, false).unwrap(),
+            format!("137865432,aa,{POOL_ID},false")
+        );
+        assert_eq!(
+            render_row(&block_detail(), &fields, true).unwrap(),
+            format!(r#"{{"slot":137865432,"hash":"aa","pool_id":"{POOL_ID}","orphaned":false}}"#)
+        );
+
+        // a receipt that was not recorded is empty in CSV and null in JSON
+        let fields = [ExportField::Slot, ExportField::ReceivedAt, ExportField::SourcePeer];
+        assert_eq!(render_row(&block_detail(), &fields, false).unwrap(), "137865432,,");
+        assert_eq!(
+            render_row(&block_detail(), &fields, true).unwrap(),
+            r#"{"slot":137865432,"received_at":null,"source_peer":null}"#
+        );
+    }
+}
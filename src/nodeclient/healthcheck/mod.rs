@@ -0,0 +1,256 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::error;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::leaderlog::{
+    guess_shelley_transition_epoch, read_byron_genesis, read_shelley_genesis, slot_to_naivedatetime,
+};
+use crate::nodeclient::ping;
+use crate::nodeclient::reporter;
+use crate::nodeclient::runstate;
+use crate::nodeclient::sync::latency::{parse_rtt, KEEPALIVE_OPERATION};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] crate::nodeclient::leaderlog::Error),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HealthCheck {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tip_slot: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tip_age_seconds: Option<i64>,
+    max_tip_age_seconds: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ping_duration_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    keepalive: Vec<KeepaliveRtt>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+/// The last keepalive round trip a running sync or sendtip measured to a peer
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeepaliveRtt {
+    peer: String,
+    rtt_ms: u64,
+    at: String,
+}
+
+fn keepalive_rtts(runs: Vec<runstate::LastRun>) -> Vec<KeepaliveRtt> {
+    runs.into_iter()
+        .filter_map(|run| {
+            Some(KeepaliveRtt {
+                rtt_ms: parse_rtt(&run.outcome)?,
+                peer: run.peer?,
+                at: run.at,
+            })
+        })
+        .collect()
+}
+
+/// Why the keepalive round trips are unhealthy: one took longer than max_rtt_ms, or one was recorded more than
+/// max_age_seconds ago, so the sync or sendtip that measured it has likely stopped and its round trip says nothing
+fn keepalive_errors(
+    keepalive: &[KeepaliveRtt],
+    max_rtt_ms: u64,
+    max_age_seconds: i64,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let mut errors = vec![];
+    for keepalive_rtt in keepalive.iter() {
+        if keepalive_rtt.rtt_ms > max_rtt_ms {
+            errors.push(format!(
+                "keepalive round trip to {} is {} ms",
+                keepalive_rtt.peer, keepalive_rtt.rtt_ms
+            ));
+        }
+        match DateTime::parse_from_rfc3339(&keepalive_rtt.at) {
+            Ok(at) => {
+                let age_seconds = (now - at.with_timezone(&Utc)).num_seconds();
+                if age_seconds > max_age_seconds {
+                    errors.push(format!(
+                        "keepalive round trip to {} is {}s old",
+                        keepalive_rtt.peer, age_seconds
+                    ));
+                }
+            }
+            Err(_) => errors.push(format!(
+                "keepalive round trip to {} has an invalid time {}",
+                keepalive_rtt.peer, keepalive_rtt.at
+            )),
+        }
+    }
+    errors
+}
+
+/// Seconds between the wall clock and the time of the tip slot in the database
+fn tip_age(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+) -> Result<(u64, i64), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let byron = read_byron_genesis(byron_genesis)?;
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let tip_time = slot_to_naivedatetime(&byron, &shelley, tip_slot_number, shelley_transition_epoch)
+        .and_utc()
+        .timestamp();
+    Ok((tip_slot_number, Utc::now().timestamp() - tip_time))
+}
+
+/// Check that the db tip is recent and optionally that a relay answers a handshake. Prints a single-line JSON
+/// verdict and returns whether everything is healthy so the caller can set the process exit code.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn healthcheck(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    max_tip_age_seconds: i64,
+    host: &Option<String>,
+    port: u16,
+    network_magic: u64,
+    timeout_seconds: u64,
+    max_keepalive_rtt_ms: Option<u64>,
+    max_keepalive_age_seconds: i64,
+) -> bool {
+    let mut health_check = HealthCheck {
+        status: "ok".to_string(),
+        tip_slot: None,
+        tip_age_seconds: None,
+        max_tip_age_seconds,
+        ping_duration_ms: None,
+        keepalive: vec![],
+        errors: vec![],
+    };
+
+    match tip_age(db_path, byron_genesis, shelley_genesis, shelley_transition_epoch) {
+        Ok((tip_slot, tip_age_seconds)) => {
+            health_check.tip_slot = Some(tip_slot);
+            health_check.tip_age_seconds = Some(tip_age_seconds);
+            if tip_age_seconds > max_tip_age_seconds {
+                health_check.errors.push(format!("db tip is {tip_age_seconds}s old"));
+            }
+        }
+        Err(error) => health_check.errors.push(error.to_string()),
+    }
+
+    if let Some(host) = host {
+        match ping::handshake_with_node(host, port, network_magic, timeout_seconds, false, 0, &None).await {
+            Ok(ping_result) => health_check.ping_duration_ms = Some(ping_result.total_duration.as_millis()),
+            Err(message) => health_check.errors.push(format!("ping {host}:{port}: {message}")),
+        }
+    }
+
+    match runstate::recorded_runs(KEEPALIVE_OPERATION) {
+        Ok(runs) => health_check.keepalive = keepalive_rtts(runs),
+        Err(error) => health_check.errors.push(format!("keepalive round trips: {error}")),
+    }
+    if let Some(max_keepalive_rtt_ms) = max_keepalive_rtt_ms {
+        health_check.errors.extend(keepalive_errors(
+            &health_check.keepalive,
+            max_keepalive_rtt_ms,
+            max_keepalive_age_seconds,
+            Utc::now(),
+        ));
+    }
+
+    if !health_check.errors.is_empty() {
+        health_check.status = "error".to_string();
+    }
+    if let Err(error) = reporter::report_compact(&health_check) {
+        error!("Could not report health check: {}", error);
+        return false;
+    }
+
+    health_check.errors.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keepalive_rtts() {
+        let run = |peer: Option<&str>, outcome: &str| runstate::LastRun {
+            operation: KEEPALIVE_OPERATION.to_string(),
+            pool_id: None,
+            epoch: None,
+            peer: peer.map(str::to_string),
+            at: "2024-10-05T10:00:00Z".to_string(),
+            outcome: outcome.to_string(),
+        };
+        assert_eq!(
+            keepalive_rtts(vec![
+                run(Some("relay1:3001"), "23 ms"),
+                run(None, "23 ms"),
+                run(Some("relay2:3001"), "unknown")
+            ]),
+            vec![KeepaliveRtt {
+                peer: "relay1:3001".to_string(),
+                rtt_ms: 23,
+                at: "2024-10-05T10:00:00Z".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_keepalive_errors() {
+        let keepalive_rtt = |peer: &str, rtt_ms: u64, at: &str| KeepaliveRtt {
+            peer: peer.to_string(),
+            rtt_ms,
+            at: at.to_string(),
+        };
+        let now = DateTime::parse_from_rfc3339("2024-10-05T10:01:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let keepalive = vec![
+            keepalive_rtt("relay1:3001", 23, "2024-10-05T10:00:40Z"),
+            keepalive_rtt("relay2:3001", 900, "2024-10-05T10:00:40Z"),
+            keepalive_rtt("relay3:3001", 23, "2024-10-05T09:00:00Z"),
+            keepalive_rtt("relay4:3001", 23, "yesterday"),
+        ];
+        assert_eq!(
+            keepalive_errors(&keepalive, 500, 120, now),
+            vec![
+                "keepalive round trip to relay2:3001 is 900 ms",
+                "keepalive round trip to relay3:3001 is 3660s old",
+                "keepalive round trip to relay4:3001 has an invalid time yesterday",
+            ]
+        );
+        assert!(keepalive_errors(&keepalive[..1], 500, 120, now).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::APP_USER_AGENT;
+
+const TIMEOUT_SECONDS_VAR: &str = "CNCLI_HTTP_TIMEOUT_SECONDS";
+const CONNECT_TIMEOUT_SECONDS_VAR: &str = "CNCLI_HTTP_CONNECT_TIMEOUT_SECONDS";
+const POOLTOOL_CERTIFICATE_VAR: &str = "CNCLI_POOLTOOL_CERTIFICATE";
+
+const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_CONNECT_TIMEOUT_SECONDS: u64 = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("HTTP client error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("Could not read certificate {0}: {1}")]
+    Certificate(PathBuf, std::io::Error),
+
+    #[error("Invalid {0}: {1}")]
+    InvalidSetting(&'static str, String),
+}
+
+/// Settings shared by every outbound HTTPS request, read from the environment so they apply to sync, sendslots and
+/// leaderlog alike. Proxies come from HTTPS_PROXY, HTTP_PROXY and NO_PROXY, which reqwest reads by itself.
+#[derive(Debug, PartialEq)]
+pub(crate) struct HttpSettings {
+    pub(crate) timeout: Duration,
+    pub(crate) connect_timeout: Duration,
+    /// A PEM certificate PoolTool's chain must lead to instead of the built-in web roots
+    pub(crate) pooltool_certificate: Option<PathBuf>,
+}
+
+impl HttpSettings {
+    pub(crate) fn from_env() -> Result<Self, Error> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        let seconds = |name: &'static str, default: u64| -> Result<Duration, Error> {
+            match var(name) {
+                Some(value) => match value.parse::<u64>() {
+                    Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+                    _ => Err(Error::InvalidSetting(name, value)),
+                },
+                None => Ok(Duration::from_secs(default)),
+            }
+        };
+        Ok(HttpSettings {
+            timeout: seconds(TIMEOUT_SECONDS_VAR, DEFAULT_TIMEOUT_SECONDS)?,
+            connect_timeout: seconds(CONNECT_TIMEOUT_SECONDS_VAR, DEFAULT_CONNECT_TIMEOUT_SECONDS)?,
+            pooltool_certificate: var(POOLTOOL_CERTIFICATE_VAR)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from),
+        })
+    }
+}
+
+/// Build a blocking client with the timeouts from the settings. With a pinned certificate only server chains leading
+/// to it are trusted.
+pub(crate) fn client(
+    settings: &HttpSettings,
+    pinned_certificate: Option<&Path>,
+) -> Result<reqwest::blocking::Client, Error> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .user_agent(APP_USER_AGENT)
+        .timeout(settings.timeout)
+        .connect_timeout(settings.connect_timeout);
+    if let Some(pinned_certificate) = pinned_certificate {
+        let pem = std::fs::read(pinned_certificate)
+            .map_err(|error| Error::Certificate(pinned_certificate.to_path_buf(), error))?;
+        builder = builder
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Build a blocking client for downloads of any length. Only connecting is limited by the settings, the timeout
+/// would otherwise cut off a large download.
+pub(crate) fn download_client(settings: &HttpSettings) -> Result<reqwest::blocking::Client, Error> {
+    Ok(reqwest::blocking::Client::builder()
+        .user_agent(APP_USER_AGENT)
+        .timeout(None)
+        .connect_timeout(settings.connect_timeout)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_settings_from_vars() {
+        assert_eq!(
+            HttpSettings::from_vars(|_| None).unwrap(),
+            HttpSettings {
+                timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECONDS),
+                connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECONDS),
+                pooltool_certificate: None,
+            }
+        );
+        assert_eq!(
+            HttpSettings::from_vars(|name| match name {
+                TIMEOUT_SECONDS_VAR => Some("5".to_string()),
+                POOLTOOL_CERTIFICATE_VAR => Some("/etc/cncli/pooltool.pem".to_string()),
+                _ => None,
+            })
+            .unwrap(),
+            HttpSettings {
+                timeout: Duration::from_secs(5),
+                connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECONDS),
+                pooltool_certificate: Some(PathBuf::from("/etc/cncli/pooltool.pem")),
+            }
+        );
+        assert!(HttpSettings::from_vars(|name| match name {
+            CONNECT_TIMEOUT_SECONDS_VAR => Some("0".to_string()),
+            _ => None,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use bech32::{Bech32, Hrp};
+use minicbor::Decoder;
+use pallas_crypto::hash::Hasher;
+use pallas_crypto::key::ed25519::SecretKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::nodeclient::reporter;
+use crate::nodeclient::vrfkey::{vrf_vkey_hash, vrf_vkey_of_skey, VRF_SIGNING_KEY_SIZE, VRF_VERIFICATION_KEY_SIZE};
+
+const ED25519_KEY_SIZE: usize = 32;
+const ED25519_SIGNATURE_SIZE: usize = 64;
+// a sum-composition KES key of depth 6 as used by cardano-node
+const KES_SIGNING_KEY_SIZE: usize = 608;
+const KES_VERIFICATION_KEY_SIZE: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid cborHex: {0}")]
+    Hex(#[from] hex::FromHexError),
+
+    #[error("Invalid cborHex: {0}")]
+    CborDecode(#[from] minicbor::decode::Error),
+
+    #[error(transparent)]
+    Bech32(#[from] bech32::primitives::hrp::Error),
+
+    #[error(transparent)]
+    Bech32Encoding(#[from] bech32::EncodeError),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Inspect key error: {0}")]
+    InspectKey(String),
+}
+
+/// The text envelope cardano-cli writes keys and certificates in
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextEnvelope {
+    #[serde(rename = "type")]
+    envelope_type: String,
+    #[serde(default)]
+    description: String,
+    cbor_hex: String,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyInspection {
+    status: String,
+    #[serde(rename = "type")]
+    envelope_type: String,
+    description: String,
+    /// The size of the key, or of the certificate's cbor, in bytes
+    size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vkey: Option<String>,
+    /// blake2b-224 of the ed25519 verification key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool_id_bech32: Option<String>,
+    /// blake2b-256 of the vrf verification key, as registered in the pool's parameters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vrf_vkey_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hot_vkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issue_counter: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kes_period: Option<u64>,
+}
+
+/// Print what a cardano-cli key or certificate file holds so a wrong file can be spotted before it is used
+pub(crate) fn inspect_key(file: &Path) -> Result<(), Error> {
+    if !file.exists() {
+        return Err(Error::InvalidPath(file.to_path_buf()));
+    }
+    let buf = BufReader::new(File::open(file)?);
+    let envelope: TextEnvelope = serde_json::from_reader(buf)?;
+    reporter::report(&inspect_envelope(&envelope)?)?;
+
+    Ok(())
+}
+
+fn inspect_envelope(envelope: &TextEnvelope) -> Result<KeyInspection, Error> {
+    let cbor = hex::decode(&envelope.cbor_hex)?;
+    let mut inspection = KeyInspection {
+        status: "ok".to_string(),
+        envelope_type: envelope.envelope_type.clone(),
+        description: envelope.description.clone(),
+        ..Default::default()
+    };
+    match envelope.envelope_type.as_str() {
+        "NodeOperationalCertificate" => {
+            // [[hot_vkey, issue_counter, kes_period, cold_signature], cold_vkey]
+            let mut decoder = Decoder::new(&cbor);
+            expect_array(&mut decoder, 2)?;
+            expect_array(&mut decoder, 4)?;
+            let hot_vkey = expect_bytes(&mut decoder, KES_VERIFICATION_KEY_SIZE, "hot vkey")?;
+            inspection.issue_counter = Some(decoder.u64()?);
+            inspection.kes_period = Some(decoder.u64()?);
+            expect_bytes(&mut decoder, ED25519_SIGNATURE_SIZE, "cold signature")?;
+            let cold_vkey = expect_bytes(&mut decoder, ED25519_KEY_SIZE, "cold vkey")?;
+            expect_end(&decoder, &cbor)?;
+            inspection.size = cbor.len();
+            inspection.hot_vkey = Some(hex::encode(hot_vkey));
+            add_pool_id(&mut inspection, cold_vkey)?;
+        }
+        "NodeOperationalCertificateIssueCounter" => {
+            // [issue_counter, cold_vkey]
+            let mut decoder = Decoder::new(&cbor);
+            expect_array(&mut decoder, 2)?;
+            inspection.issue_counter = Some(decoder.u64()?);
+            let cold_vkey = expect_bytes(&mut decoder, ED25519_KEY_SIZE, "cold vkey")?;
+            expect_end(&decoder, &cbor)?;
+            inspection.size = cbor.len();
+            add_pool_id(&mut inspection, cold_vkey)?;
+        }
+        envelope_type => {
+            let mut decoder = Decoder::new(&cbor);
+            let key = decoder.bytes()?;
+            expect_end(&decoder, &cbor)?;
+            inspection.size = key.len();
+            match envelope_type {
+                "VrfSigningKey_PraosVRF" => {
+                    check_size(key, VRF_SIGNING_KEY_SIZE, "vrf signing key")?;
+                    let vkey = vrf_vkey_of_skey(key).expect("size checked");
+                    inspection.vkey = Some(hex::encode(vkey));
+                    inspection.vrf_vkey_hash = Some(hex::encode(vrf_vkey_hash(vkey)));
+                }
+                "VrfVerificationKey_PraosVRF" => {
+                    check_size(key, VRF_VERIFICATION_KEY_SIZE, "vrf verification key")?;
+                    inspection.vrf_vkey_hash = Some(hex::encode(vrf_vkey_hash(key)));
+                }
+                "KesSigningKey_ed25519_kes_2^6" => {
+                    check_size(key, KES_SIGNING_KEY_SIZE, "kes signing key")?;
+                }
+                "KesVerificationKey_ed25519_kes_2^6" => {
+                    check_size(key, KES_VERIFICATION_KEY_SIZE, "kes verification key")?;
+                }
+                envelope_type if envelope_type.ends_with("SigningKey_ed25519") => {
+                    check_size(key, ED25519_KEY_SIZE, "ed25519 signing key")?;
+                    let mut secret_key = [0u8; ED25519_KEY_SIZE];
+                    secret_key.copy_from_slice(key);
+                    let vkey = SecretKey::from(secret_key).public_key();
+                    add_key_hash(&mut inspection, vkey.as_ref())?;
+                    inspection.vkey = Some(hex::encode(vkey));
+                }
+                envelope_type if envelope_type.ends_with("VerificationKey_ed25519") => {
+                    check_size(key, ED25519_KEY_SIZE, "ed25519 verification key")?;
+                    add_key_hash(&mut inspection, key)?;
+                }
+                envelope_type => {
+                    return Err(Error::InspectKey(format!("Unsupported envelope type: {envelope_type}")));
+                }
+            }
+        }
+    }
+
+    Ok(inspection)
+}
+
+/// Set the key hash, and for a pool cold key the pool id
+fn add_key_hash(inspection: &mut KeyInspection, vkey: &[u8]) -> Result<(), Error> {
+    if inspection.envelope_type.starts_with("StakePool") {
+        add_pool_id(inspection, vkey)
+    } else {
+        inspection.key_hash = Some(hex::encode(Hasher::<224>::hash(vkey)));
+        Ok(())
+    }
+}
+
+fn add_pool_id(inspection: &mut KeyInspection, cold_vkey: &[u8]) -> Result<(), Error> {
+    // blake2b 224 of the cold vkey is the pool_id
+    let pool_id = Hasher::<224>::hash(cold_vkey);
+    inspection.key_hash = Some(hex::encode(pool_id));
+    inspection.pool_id = Some(hex::encode(pool_id));
+    inspection.pool_id_bech32 = Some(bech32::encode::<Bech32>(Hrp::parse("pool")?, pool_id.as_ref())?);
+    Ok(())
+}
+
+fn check_size(key: &[u8], expected: usize, name: &str) -> Result<(), Error> {
+    if key.len() != expected {
+        return Err(Error::InspectKey(format!(
+            "Invalid {name}: expected {expected} bytes, got {}",
+            key.len()
+        )));
+    }
+    Ok(())
+}
+
+fn expect_array(decoder: &mut Decoder, expected: u64) -> Result<(), Error> {
+    match decoder.array()? {
+        Some(length) if length == expected => Ok(()),
+        length => Err(Error::InspectKey(format!(
+            "Invalid certificate: expected an array of {expected}, got {length:?}"
+        ))),
+    }
+}
+
+fn expect_bytes<'b>(decoder: &mut Decoder<'b>, expected: usize, name: &str) -> Result<&'b [u8], Error> {
+    let bytes = decoder.bytes()?;
+    check_size(bytes, expected, name)?;
+    Ok(bytes)
+}
+
+fn expect_end(decoder: &Decoder, cbor: &[u8]) -> Result<(), Error> {
+    if decoder.position() != cbor.len() {
+        return Err(Error::InspectKey(format!(
+            "Invalid cborHex: {} trailing bytes",
+            cbor.len() - decoder.position()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(envelope_type: &str, cbor: Vec<u8>) -> TextEnvelope {
+        TextEnvelope {
+            envelope_type: envelope_type.to_string(),
+            description: String::new(),
+            cbor_hex: hex::encode(cbor),
+        }
+    }
+
+    fn cbor_bytes(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = minicbor::Encoder::new(Vec::new());
+        encoder.bytes(bytes).unwrap();
+        encoder.into_writer()
+    }
+
+    #[test]
+    fn test_inspect_opcert() {
+        let cold_vkey = [0x01u8; ED25519_KEY_SIZE];
+        let mut encoder = minicbor::Encoder::new(Vec::new());
+        encoder.array(2).unwrap();
+        encoder.array(4).unwrap();
+        encoder.bytes(&[0x02u8; KES_VERIFICATION_KEY_SIZE]).unwrap();
+        encoder.u64(7).unwrap();
+        encoder.u64(1042).unwrap();
+        encoder.bytes(&[0x03u8; ED25519_SIGNATURE_SIZE]).unwrap();
+        encoder.bytes(&cold_vkey).unwrap();
+
+        let inspection = inspect_envelope(&envelope("NodeOperationalCertificate", encoder.into_writer())).unwrap();
+        assert_eq!(inspection.issue_counter, Some(7));
+        assert_eq!(inspection.kes_period, Some(1042));
+        assert_eq!(
+            inspection.hot_vkey,
+            Some(hex::encode([0x02u8; KES_VERIFICATION_KEY_SIZE]))
+        );
+        assert_eq!(inspection.pool_id, Some(hex::encode(Hasher::<224>::hash(&cold_vkey))));
+        assert!(inspection.pool_id_bech32.unwrap().starts_with("pool1"));
+    }
+
+    #[test]
+    fn test_inspect_key_sizes() {
+        let inspection = inspect_envelope(&envelope(
+            "VrfVerificationKey_PraosVRF",
+            cbor_bytes(&[0x04u8; VRF_VERIFICATION_KEY_SIZE]),
+        ))
+        .unwrap();
+        assert_eq!(
+            inspection.vrf_vkey_hash,
+            Some(hex::encode(Hasher::<256>::hash(&[0x04u8; VRF_VERIFICATION_KEY_SIZE])))
+        );
+        // a KES key passed where a VRF key is expected has the wrong size for its claimed type
+        assert!(inspect_envelope(&envelope(
+            "VrfSigningKey_PraosVRF",
+            cbor_bytes(&[0x05u8; KES_SIGNING_KEY_SIZE])
+        ))
+        .is_err());
+        // trailing bytes after the key
+        let mut cbor = cbor_bytes(&[0x06u8; ED25519_KEY_SIZE]);
+        cbor.push(0x00);
+        assert!(inspect_envelope(&envelope("StakePoolVerificationKey_ed25519", cbor)).is_err());
+    }
+}
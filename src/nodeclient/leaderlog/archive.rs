@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use crate::nodeclient::leaderlog::{relative_time, report_leader_log, Error, LeaderLog};
+use crate::nodeclient::poolid::{format_pool_id, PoolId};
+use crate::{ReportFormat, SlotsFormat};
+
+/// The archived leaderlog of a pool for an epoch, e.g. epoch_512_<pool id hex>.json
+fn archive_path(archive_dir: &Path, epoch: u64, pool_id: &PoolId) -> PathBuf {
+    archive_dir.join(format!("epoch_{epoch}_{}.json", pool_id))
+}
+
+/// Write the leaderlog to the archive, replacing the file in one step so a reader never sees it half written
+pub(super) fn write_archive(archive_dir: &Path, pool_id: &PoolId, leader_log: &LeaderLog) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(archive_dir)?;
+    let path = archive_path(archive_dir, leader_log.epoch, pool_id);
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, serde_json::to_vec_pretty(leader_log)?)?;
+    std::fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// The epochs archived for a pool, in ascending order
+fn archived_epochs(archive_dir: &Path, pool_id: &PoolId) -> Result<Vec<u64>, Error> {
+    let suffix = format!("_{pool_id}.json");
+    let mut epochs: Vec<u64> = std::fs::read_dir(archive_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            file_name
+                .strip_prefix("epoch_")
+                .and_then(|rest| rest.strip_suffix(&suffix))
+                .and_then(|epoch| epoch.parse().ok())
+        })
+        .collect();
+    epochs.sort_unstable();
+    Ok(epochs)
+}
+
+/// Read an archived leaderlog, the latest archived epoch of the pool when no epoch is given
+pub(super) fn read_archive(archive_dir: &Path, pool_id: &PoolId, epoch: Option<u64>) -> Result<LeaderLog, Error> {
+    let epoch = match epoch {
+        Some(epoch) => epoch,
+        None => archived_epochs(archive_dir, pool_id)?.pop().ok_or_else(|| {
+            Error::Leaderlog(format!(
+                "No leaderlog archived for pool {pool_id} in {}",
+                archive_dir.display()
+            ))
+        })?,
+    };
+    let path = archive_path(archive_dir, epoch, pool_id);
+    if !path.exists() {
+        return Err(Error::Leaderlog(format!(
+            "No leaderlog archived for pool {pool_id} epoch {epoch}: {} does not exist",
+            path.display()
+        )));
+    }
+    Ok(serde_json::from_slice(&std::fs::read(&path)?)?)
+}
+
+/// Print an archived leaderlog as if it was just calculated. How far each slot is from now is worked out again since
+/// the archived value is relative to when the leaderlog was calculated. Returns the number of assigned slots.
+pub(crate) fn leader_log_from_archive(
+    archive_dir: &Path,
+    pool_id: &PoolId,
+    epoch: Option<u64>,
+    json_compact: bool,
+    output_format: &SlotsFormat,
+    bech32: bool,
+    format: &ReportFormat,
+) -> Result<u64, Error> {
+    let mut leader_log = read_archive(archive_dir, pool_id, epoch)?;
+    leader_log.pool_id = format_pool_id(pool_id.as_str(), bech32);
+    let now = Utc::now();
+    for slot in leader_log.assigned_slots.iter_mut() {
+        if let Ok(slot_time) = DateTime::parse_from_rfc3339(&slot.at_utc) {
+            slot.relative = relative_time((slot_time.with_timezone(&Utc) - now).num_seconds());
+        }
+    }
+    report_leader_log(&leader_log, json_compact, output_format, format)?;
+    Ok(leader_log.epoch_slots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::nodeclient::leaderlog::Slot;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let archive_dir = std::env::temp_dir().join("cncli_test_leaderlog_archive");
+        let _ = std::fs::remove_dir_all(&archive_dir);
+        let pool_id = &PoolId::from_str("00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114").unwrap();
+        let leader_log = |epoch: u64| LeaderLog {
+            status: "ok".to_string(),
+            epoch,
+            epoch_start: "2020-01-01T00:00:00-08:00".to_string(),
+            epoch_start_utc: "2020-01-01T08:00:00Z".to_string(),
+            epoch_end: "2020-01-06T00:00:00-08:00".to_string(),
+            epoch_end_utc: "2020-01-06T08:00:00Z".to_string(),
+            epoch_nonce: "ab".repeat(32),
+            consensus: "praos".to_string(),
+            epoch_slots: 1,
+            epoch_slots_ideal: 0.8,
+            max_performance: 125.0,
+            pool_id: pool_id.to_string(),
+            sigma: 0.0001,
+            active_stake: 1,
+            total_active_stake: 10000,
+            d: 0.0,
+            f: 0.05,
+            warnings: vec![],
+            assigned_slots: vec![Slot {
+                no: 1,
+                slot: 1000,
+                slot_in_epoch: 100,
+                at: "2020-01-01T00:00:00-08:00".to_string(),
+                at_utc: "2020-01-01T08:00:00Z".to_string(),
+                seconds_from_epoch_start: 100,
+                relative: "in 1h".to_string(),
+                reminder_at: None,
+                consensus: None,
+            }],
+        };
+        write_archive(&archive_dir, pool_id, &leader_log(9)).unwrap();
+        let path = write_archive(&archive_dir, pool_id, &leader_log(10)).unwrap();
+        assert_eq!(path, archive_dir.join(format!("epoch_10_{pool_id}.json")));
+        std::fs::write(archive_dir.join("epoch_11_0011.json"), "{}").unwrap();
+
+        assert_eq!(archived_epochs(&archive_dir, pool_id).unwrap(), vec![9, 10]);
+        assert_eq!(read_archive(&archive_dir, pool_id, None).unwrap().epoch, 10);
+        let archived = read_archive(&archive_dir, pool_id, Some(9)).unwrap();
+        assert_eq!(archived.assigned_slots[0].at_utc, "2020-01-01T08:00:00Z");
+        assert!(read_archive(&archive_dir, pool_id, Some(8)).is_err());
+        std::fs::remove_dir_all(&archive_dir).unwrap();
+    }
+}
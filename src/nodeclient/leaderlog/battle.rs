@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use pallas_crypto::hash::Hash;
+use serde::Serialize;
+
+use crate::nodeclient::leaderlog::{
+    mk_input_vrf, mk_seed, read_shelley_genesis, read_vrf_key, vrf_eval_certified, vrf_leader_hash, Error,
+};
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::reporter;
+
+/// Number of competitors listed in the output, the ones most likely to beat us first
+const MAX_LISTED_COMPETITORS: usize = 10;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BattleOdds {
+    status: String,
+    slot: u64,
+    consensus: String,
+    epoch_nonce: String,
+    /// The vrf output, the lower one wins a battle between blocks in the same slot
+    vrf_output: String,
+    leader_value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sigma: Option<f64>,
+    /// Only set when our sigma is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_leader: Option<bool>,
+    competitors: usize,
+    /// Probability that at least one competitor also leads the slot
+    battle_probability: f64,
+    /// Probability that no competitor leads the slot with a lower vrf output
+    win_probability: f64,
+    /// Probability of winning given that there is a battle
+    win_probability_in_battle: f64,
+    top_competitors: Vec<CompetitorOdds>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompetitorOdds {
+    pool_id: String,
+    sigma: f64,
+    leader_probability: f64,
+    beats_us_probability: f64,
+}
+
+#[derive(Debug, PartialEq)]
+struct Competitor {
+    pool_id: String,
+    sigma: f64,
+}
+
+/// Parse a --competitor pool_id:sigma
+fn parse_competitor(competitor: &str) -> Result<Competitor, Error> {
+    let invalid = || {
+        Error::Leaderlog(format!(
+            "Invalid Competitor: --competitor {competitor}, expected pool_id:sigma"
+        ))
+    };
+    let (pool_id, sigma) = competitor.split_once(':').ok_or_else(invalid)?;
+    let sigma = f64::from_str(sigma).map_err(|_| invalid())?;
+    if !(0.0..=1.0).contains(&sigma) {
+        return Err(invalid());
+    }
+    Ok(Competitor {
+        pool_id: PoolId::from_str(pool_id).map_err(|_| invalid())?.to_string(),
+        sigma,
+    })
+}
+
+/// Read the pools of a `cncli pool-stake` csv, lines of pool_id,pool_stake,total_stake,
+fn read_pool_stake(pool_stake: &Path) -> Result<Vec<Competitor>, Error> {
+    std::fs::read_to_string(pool_stake)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || Error::Leaderlog(format!("Invalid pool-stake line: {line}"));
+            let mut fields = line.split(',');
+            let (Some(pool_id), Some(pool_stake), Some(total_stake)) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let pool_stake = u64::from_str(pool_stake).map_err(|_| invalid())?;
+            let total_stake = u64::from_str(total_stake).map_err(|_| invalid())?;
+            if total_stake == 0 {
+                return Err(invalid());
+            }
+            Ok(Competitor {
+                pool_id: PoolId::from_str(pool_id).map_err(|_| invalid())?.to_string(),
+                sigma: pool_stake as f64 / total_stake as f64,
+            })
+        })
+        .collect()
+}
+
+/// A vrf value as a fraction of its maximum. The first 8 bytes are plenty for a probability.
+fn fraction(value: &[u8]) -> f64 {
+    let high: [u8; 8] = value[..8].try_into().expect("Infallible");
+    u64::from_be_bytes(high) as f64 / 2f64.powi(64)
+}
+
+/// Probability that a pool with sigma leads a slot, 1 - (1 - f)^sigma
+fn leader_probability(sigma: f64, f: f64) -> f64 {
+    1.0 - (1.0 - f).powf(sigma)
+}
+
+/// The odds of our block in a slot given the fraction of our vrf output. A competitor beats us when it leads the slot
+/// and its vrf output is lower, which is independent of it leading, so with probability leader_probability * fraction.
+fn battle_odds(vrf_fraction: f64, competitors: &[Competitor], f: f64) -> (f64, f64, Vec<CompetitorOdds>) {
+    let mut no_battle_probability = 1.0;
+    let mut win_probability = 1.0;
+    let mut competitor_odds: Vec<CompetitorOdds> = competitors
+        .iter()
+        .map(|competitor| {
+            let leader_probability = leader_probability(competitor.sigma, f);
+            let beats_us_probability = leader_probability * vrf_fraction;
+            no_battle_probability *= 1.0 - leader_probability;
+            win_probability *= 1.0 - beats_us_probability;
+            CompetitorOdds {
+                pool_id: competitor.pool_id.clone(),
+                sigma: competitor.sigma,
+                leader_probability,
+                beats_us_probability,
+            }
+        })
+        .collect();
+    competitor_odds.sort_by(|a, b| b.beats_us_probability.total_cmp(&a.beats_us_probability));
+    competitor_odds.truncate(MAX_LISTED_COMPETITORS);
+    (1.0 - no_battle_probability, win_probability, competitor_odds)
+}
+
+/// Estimate how likely our block in a slot survives a slot battle. The vrf outputs of other pools are only known once
+/// their blocks show up, so each competitor is assumed to lead with the probability its sigma gives it.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn battle_odds_for_slot(
+    shelley_genesis: &Path,
+    slot: u64,
+    epoch_nonce: &str,
+    pool_vrf_skey_path: &Path,
+    consensus: &str,
+    pool_id: &Option<PoolId>,
+    sigma: &Option<f64>,
+    competitors: &[String],
+    pool_stake: &Option<PathBuf>,
+) -> Result<(), Error> {
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    let epoch_nonce = Hash::<32>::from_str(epoch_nonce)?;
+    let pool_vrf_skey = read_vrf_key(pool_vrf_skey_path)?;
+    if pool_vrf_skey.key_type != "VrfSigningKey_PraosVRF" {
+        return Err(Error::Leaderlog(
+            "Pool VRF Skey must be of type: VrfSigningKey_PraosVRF".to_string(),
+        ));
+    }
+    let pool_id = pool_id.as_ref().map(PoolId::to_string);
+
+    let mut all_competitors = competitors
+        .iter()
+        .map(|competitor| parse_competitor(competitor))
+        .collect::<Result<Vec<Competitor>, Error>>()?;
+    if let Some(pool_stake) = pool_stake {
+        all_competitors.extend(read_pool_stake(pool_stake)?);
+    }
+    // take our own sigma from the competitors if it was not given, then leave us out
+    let sigma = sigma.or_else(|| {
+        all_competitors
+            .iter()
+            .find(|competitor| Some(&competitor.pool_id) == pool_id.as_ref())
+            .map(|competitor| competitor.sigma)
+    });
+    all_competitors.retain(|competitor| Some(&competitor.pool_id) != pool_id.as_ref());
+    if all_competitors.is_empty() {
+        return Err(Error::Leaderlog(
+            "No competitors, pass --competitor or --pool-stake".to_string(),
+        ));
+    }
+
+    let vrf_input = match consensus {
+        "tpraos" => mk_seed(slot, epoch_nonce.as_slice()),
+        "praos" | "cpraos" => mk_input_vrf(slot, epoch_nonce.as_slice()),
+        _ => return Err(Error::Leaderlog(format!("Invalid Consensus: --consensus {consensus}"))),
+    };
+    let vrf_output = vrf_eval_certified(&vrf_input, &pool_vrf_skey.key)?;
+    let leader_value = match consensus {
+        "tpraos" => vrf_output.to_vec(),
+        _ => vrf_leader_hash(vrf_output.as_slice()).to_vec(),
+    };
+
+    let (battle_probability, win_probability, top_competitors) = battle_odds(
+        fraction(vrf_output.as_slice()),
+        &all_competitors,
+        shelley.active_slots_coeff,
+    );
+
+    reporter::report(&BattleOdds {
+        status: "ok".to_string(),
+        slot,
+        consensus: consensus.to_string(),
+        epoch_nonce: hex::encode(epoch_nonce),
+        vrf_output: hex::encode(vrf_output),
+        leader_value: hex::encode(&leader_value),
+        sigma,
+        is_leader: sigma.map(|sigma| fraction(&leader_value) < leader_probability(sigma, shelley.active_slots_coeff)),
+        competitors: all_competitors.len(),
+        battle_probability,
+        win_probability,
+        win_probability_in_battle: if battle_probability > 0.0 {
+            (win_probability - (1.0 - battle_probability)) / battle_probability
+        } else {
+            1.0
+        },
+        top_competitors,
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL_ID: &str = "00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114";
+
+    #[test]
+    fn test_parse_competitor() {
+        assert_eq!(
+            parse_competitor(&format!("{POOL_ID}:0.0012")).unwrap(),
+            Competitor {
+                pool_id: POOL_ID.to_string(),
+                sigma: 0.0012,
+            }
+        );
+        assert!(parse_competitor(POOL_ID).is_err());
+        assert!(parse_competitor(&format!("{POOL_ID}:2")).is_err());
+    }
+
+    #[test]
+    fn test_battle_odds() {
+        let competitors = vec![
+            Competitor {
+                pool_id: "a".to_string(),
+                sigma: 0.01,
+            },
+            Competitor {
+                pool_id: "b".to_string(),
+                sigma: 0.02,
+            },
+        ];
+        // the lowest possible vrf output never loses
+        let (battle_probability, win_probability, _) = battle_odds(0.0, &competitors, 0.05);
+        assert!(battle_probability > 0.0);
+        assert_eq!(win_probability, 1.0);
+
+        // the highest possible one loses every battle
+        let (battle_probability, win_probability, top_competitors) = battle_odds(1.0, &competitors, 0.05);
+        assert!((win_probability - (1.0 - battle_probability)).abs() < 1e-12);
+        assert_eq!(top_competitors[0].pool_id, "b");
+    }
+}
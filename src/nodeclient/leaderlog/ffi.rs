@@ -0,0 +1,307 @@
+#[cfg(target_arch = "wasm32")]
+compile_error!("the ffi feature only covers native builds, cncli does not compile for wasm32");
+
+use std::ffi::{c_char, c_int, CStr};
+use std::slice;
+
+use pallas_crypto::hash::Hash;
+use pallas_crypto::nonce::{generate_epoch_nonce, generate_rolling_nonce};
+use pallas_math::math::FixedDecimal;
+
+use crate::nodeclient::leaderlog::{
+    cert_nat_max, is_leader_recip_q, leader_c_for, mk_input_vrf, mk_seed, sigma, vrf_leader_value,
+};
+
+/// The result was written
+pub const CNCLI_OK: c_int = 0;
+/// A pointer that must be set is null
+pub const CNCLI_NULL_POINTER: c_int = -1;
+/// An unknown consensus, a stake larger than the active stake or an active slots coefficient outside (0, 1]
+pub const CNCLI_INVALID_ARGUMENT: c_int = -2;
+
+const HASH_SIZE: usize = 32;
+const VRF_OUTPUT_SIZE: usize = 64;
+
+fn status(result: Result<(), c_int>) -> c_int {
+    match result {
+        Ok(()) => CNCLI_OK,
+        Err(status) => status,
+    }
+}
+
+unsafe fn consensus_arg<'a>(consensus: *const c_char) -> Result<&'a str, c_int> {
+    if consensus.is_null() {
+        return Err(CNCLI_NULL_POINTER);
+    }
+    match CStr::from_ptr(consensus).to_str() {
+        Ok(consensus @ ("tpraos" | "praos" | "cpraos")) => Ok(consensus),
+        _ => Err(CNCLI_INVALID_ARGUMENT),
+    }
+}
+
+unsafe fn bytes_arg<'a>(bytes: *const u8, len: usize) -> Result<&'a [u8], c_int> {
+    if bytes.is_null() {
+        return Err(CNCLI_NULL_POINTER);
+    }
+    Ok(slice::from_raw_parts(bytes, len))
+}
+
+unsafe fn hash_arg(hash: *const u8) -> Result<Hash<HASH_SIZE>, c_int> {
+    let hash: [u8; HASH_SIZE] = bytes_arg(hash, HASH_SIZE)?.try_into().expect("infallible");
+    Ok(Hash::new(hash))
+}
+
+unsafe fn hash_out<'a>(out: *mut u8) -> Result<&'a mut [u8], c_int> {
+    if out.is_null() {
+        return Err(CNCLI_NULL_POINTER);
+    }
+    Ok(slice::from_raw_parts_mut(out, HASH_SIZE))
+}
+
+/// Write the 32 byte vrf input of a slot: blake2b-256(slot || epoch nonce) with praos and cpraos, xor-ed with the
+/// universal nonce with tpraos.
+///
+/// # Safety
+///
+/// consensus must be a nul terminated string, epoch_nonce must point to 32 readable bytes and vrf_input to 32
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cncli_vrf_input(
+    consensus: *const c_char,
+    slot: u64,
+    epoch_nonce: *const u8,
+    vrf_input: *mut u8,
+) -> c_int {
+    status(try_vrf_input(consensus, slot, epoch_nonce, vrf_input))
+}
+
+unsafe fn try_vrf_input(
+    consensus: *const c_char,
+    slot: u64,
+    epoch_nonce: *const u8,
+    vrf_input: *mut u8,
+) -> Result<(), c_int> {
+    let consensus = consensus_arg(consensus)?;
+    let epoch_nonce = hash_arg(epoch_nonce)?;
+    let seed = match consensus {
+        "tpraos" => mk_seed(slot, epoch_nonce.as_slice()),
+        _ => mk_input_vrf(slot, epoch_nonce.as_slice()),
+    };
+    hash_out(vrf_input)?.copy_from_slice(&seed);
+    Ok(())
+}
+
+/// Write the evolving nonce after a block: blake2b-256(eta_v || blake2b-256(nonce vrf output)), where the nonce vrf
+/// output is the one sync stores for the block.
+///
+/// # Safety
+///
+/// eta_v must point to 32 readable bytes, nonce_vrf_output to nonce_vrf_output_len readable bytes and next_eta_v to
+/// 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cncli_rolling_nonce(
+    eta_v: *const u8,
+    nonce_vrf_output: *const u8,
+    nonce_vrf_output_len: usize,
+    next_eta_v: *mut u8,
+) -> c_int {
+    status(try_rolling_nonce(
+        eta_v,
+        nonce_vrf_output,
+        nonce_vrf_output_len,
+        next_eta_v,
+    ))
+}
+
+unsafe fn try_rolling_nonce(
+    eta_v: *const u8,
+    nonce_vrf_output: *const u8,
+    nonce_vrf_output_len: usize,
+    next_eta_v: *mut u8,
+) -> Result<(), c_int> {
+    let eta_v = hash_arg(eta_v)?;
+    let nonce_vrf_output = bytes_arg(nonce_vrf_output, nonce_vrf_output_len)?;
+    hash_out(next_eta_v)?.copy_from_slice(generate_rolling_nonce(eta_v, nonce_vrf_output).as_slice());
+    Ok(())
+}
+
+/// Write the epoch nonce from the candidate nonce at the end of the stability window and the prev hash of the last
+/// block before the previous epoch, like leaderlog does. extra_entropy is mixed in when it is not null.
+///
+/// # Safety
+///
+/// candidate_nonce and lab_nonce must point to 32 readable bytes, extra_entropy must be null or point to
+/// extra_entropy_len readable bytes and epoch_nonce must point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cncli_epoch_nonce(
+    candidate_nonce: *const u8,
+    lab_nonce: *const u8,
+    extra_entropy: *const u8,
+    extra_entropy_len: usize,
+    epoch_nonce: *mut u8,
+) -> c_int {
+    status(try_epoch_nonce(
+        candidate_nonce,
+        lab_nonce,
+        extra_entropy,
+        extra_entropy_len,
+        epoch_nonce,
+    ))
+}
+
+unsafe fn try_epoch_nonce(
+    candidate_nonce: *const u8,
+    lab_nonce: *const u8,
+    extra_entropy: *const u8,
+    extra_entropy_len: usize,
+    epoch_nonce: *mut u8,
+) -> Result<(), c_int> {
+    let candidate_nonce = hash_arg(candidate_nonce)?;
+    let lab_nonce = hash_arg(lab_nonce)?;
+    let extra_entropy = if extra_entropy.is_null() {
+        None
+    } else {
+        Some(bytes_arg(extra_entropy, extra_entropy_len)?)
+    };
+    hash_out(epoch_nonce)?.copy_from_slice(generate_epoch_nonce(candidate_nonce, lab_nonce, extra_entropy).as_slice());
+    Ok(())
+}
+
+/// Set is_leader to whether a 64 byte vrf output makes a pool with pool_stake of active_stake the slot leader, with
+/// the same threshold check as leaderlog.
+///
+/// # Safety
+///
+/// consensus must be a nul terminated string, vrf_output must point to 64 readable bytes and is_leader to a
+/// writable bool.
+#[no_mangle]
+pub unsafe extern "C" fn cncli_is_slot_leader(
+    consensus: *const c_char,
+    vrf_output: *const u8,
+    pool_stake: u64,
+    active_stake: u64,
+    active_slots_coeff: f64,
+    is_leader: *mut bool,
+) -> c_int {
+    status(try_is_slot_leader(
+        consensus,
+        vrf_output,
+        pool_stake,
+        active_stake,
+        active_slots_coeff,
+        is_leader,
+    ))
+}
+
+unsafe fn try_is_slot_leader(
+    consensus: *const c_char,
+    vrf_output: *const u8,
+    pool_stake: u64,
+    active_stake: u64,
+    active_slots_coeff: f64,
+    is_leader: *mut bool,
+) -> Result<(), c_int> {
+    let consensus = consensus_arg(consensus)?;
+    let vrf_output = bytes_arg(vrf_output, VRF_OUTPUT_SIZE)?;
+    if is_leader.is_null() {
+        return Err(CNCLI_NULL_POINTER);
+    }
+    if active_stake == 0 || pool_stake > active_stake {
+        return Err(CNCLI_INVALID_ARGUMENT);
+    }
+    let c = leader_c_for(active_slots_coeff).map_err(|_| CNCLI_INVALID_ARGUMENT)?;
+    let cert_nat_max = cert_nat_max(consensus).map_err(|_| CNCLI_INVALID_ARGUMENT)?;
+    let leader_value = match consensus {
+        "tpraos" => FixedDecimal::from(vrf_output),
+        _ => vrf_leader_value(vrf_output).map_err(|_| CNCLI_INVALID_ARGUMENT)?,
+    };
+    let recip_q = &cert_nat_max / &(&cert_nat_max - &leader_value);
+    *is_leader = is_leader_recip_q(&recip_q, &sigma(pool_stake, active_stake), &c);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::ptr;
+
+    use super::*;
+    use crate::nodeclient::leaderlog::{is_slot_leader_praos, vrf_eval_certified};
+
+    const EPOCH_NONCE: &str = "9ef9d6fa6e1a1aed5b3a5c5b8f0e8d1fda1a02eec4bd07a47cb3c59e5f4d8dd1";
+    const TEST_VRF_SKEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn test_ffi_matches_leaderlog() {
+        let eta0 = hex::decode(EPOCH_NONCE).unwrap();
+        let praos = CString::new("praos").unwrap();
+        let mut vrf_input = [0u8; HASH_SIZE];
+        unsafe {
+            assert_eq!(
+                cncli_vrf_input(praos.as_ptr(), 4492800, eta0.as_ptr(), vrf_input.as_mut_ptr()),
+                CNCLI_OK
+            );
+        }
+        assert_eq!(vrf_input.to_vec(), mk_input_vrf(4492800, &eta0));
+
+        // with all of the stake and f = 0.05 the test key leads about one in 20 slots
+        let vrf_skey = hex::decode(TEST_VRF_SKEY).unwrap();
+        let praos_cert_nat_max = cert_nat_max("praos").unwrap();
+        let c = leader_c_for(0.05).unwrap();
+        for slot in 4492800..4492820 {
+            let vrf_output = vrf_eval_certified(&mk_input_vrf(slot, &eta0), &vrf_skey).unwrap();
+            let mut is_leader = false;
+            unsafe {
+                assert_eq!(
+                    cncli_is_slot_leader(praos.as_ptr(), vrf_output.as_ptr(), 1, 1, 0.05, &mut is_leader),
+                    CNCLI_OK
+                );
+            }
+            let expected = is_slot_leader_praos(slot, &sigma(1, 1), &eta0, &vrf_skey, &praos_cert_nat_max, &c).unwrap();
+            assert_eq!(is_leader, expected, "slot {slot}");
+        }
+
+        let mut eta_v = [0u8; HASH_SIZE];
+        unsafe {
+            assert_eq!(
+                cncli_rolling_nonce(eta0.as_ptr(), [1u8; 64].as_ptr(), 64, eta_v.as_mut_ptr()),
+                CNCLI_OK
+            );
+        }
+        assert_eq!(
+            eta_v.as_slice(),
+            generate_rolling_nonce(Hash::new(eta0.clone().try_into().unwrap()), &[1u8; 64]).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_ffi_invalid_arguments() {
+        let eta0 = hex::decode(EPOCH_NONCE).unwrap();
+        let unknown = CString::new("ouroboros").unwrap();
+        let tpraos = CString::new("tpraos").unwrap();
+        let mut out = [0u8; HASH_SIZE];
+        let mut is_leader = false;
+        unsafe {
+            assert_eq!(
+                cncli_vrf_input(unknown.as_ptr(), 1, eta0.as_ptr(), out.as_mut_ptr()),
+                CNCLI_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                cncli_vrf_input(tpraos.as_ptr(), 1, ptr::null(), out.as_mut_ptr()),
+                CNCLI_NULL_POINTER
+            );
+            assert_eq!(
+                cncli_epoch_nonce(eta0.as_ptr(), eta0.as_ptr(), ptr::null(), 0, out.as_mut_ptr()),
+                CNCLI_OK
+            );
+            assert_eq!(
+                cncli_is_slot_leader(tpraos.as_ptr(), [0u8; 64].as_ptr(), 2, 1, 0.05, &mut is_leader),
+                CNCLI_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                cncli_is_slot_leader(tpraos.as_ptr(), [0u8; 64].as_ptr(), 1, 1, 1.5, &mut is_leader),
+                CNCLI_INVALID_ARGUMENT
+            );
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::fmt::{Display, Formatter};
+
+use serde_json::{Map, Number, Value};
+
+/// The json type a genesis field is read as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FieldKind {
+    Integer,
+    /// An integer that fits the 32 bits of a network magic
+    U32,
+    Number,
+}
+
+/// A genesis field cncli reads, by its path of keys as the node writes them
+pub(crate) struct GenesisField {
+    pub(crate) path: &'static [&'static str],
+    pub(crate) kind: FieldKind,
+    /// The node writes this number as a string, so reading it from one is not worth a warning
+    pub(crate) string_expected: bool,
+}
+
+pub(crate) const BYRON_FIELDS: &[GenesisField] = &[
+    GenesisField {
+        path: &["startTime"],
+        kind: FieldKind::Integer,
+        string_expected: false,
+    },
+    GenesisField {
+        path: &["protocolConsts", "k"],
+        kind: FieldKind::Integer,
+        string_expected: false,
+    },
+    GenesisField {
+        path: &["blockVersionData", "slotDuration"],
+        kind: FieldKind::Integer,
+        string_expected: true,
+    },
+];
+
+pub(crate) const SHELLEY_FIELDS: &[GenesisField] = &[
+    GenesisField {
+        path: &["activeSlotsCoeff"],
+        kind: FieldKind::Number,
+        string_expected: false,
+    },
+    GenesisField {
+        path: &["networkMagic"],
+        kind: FieldKind::U32,
+        string_expected: false,
+    },
+    GenesisField {
+        path: &["slotLength"],
+        kind: FieldKind::Number,
+        string_expected: false,
+    },
+    GenesisField {
+        path: &["epochLength"],
+        kind: FieldKind::Integer,
+        string_expected: false,
+    },
+];
+
+/// A genesis value that was read differently from how the node writes it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum GenesisCoercion {
+    /// The key was spelled differently, e.g. network_magic for networkMagic
+    Key { field: String, key: String },
+    /// A number was given as a string
+    StringToNumber { field: String, value: String },
+    /// An integer was given with a fraction of 0, e.g. 432000.0
+    FloatToInteger { field: String, value: f64 },
+}
+
+impl GenesisCoercion {
+    pub(crate) fn field(&self) -> &str {
+        match self {
+            GenesisCoercion::Key { field, .. }
+            | GenesisCoercion::StringToNumber { field, .. }
+            | GenesisCoercion::FloatToInteger { field, .. } => field,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            GenesisCoercion::Key { .. } => "key",
+            GenesisCoercion::StringToNumber { .. } => "string-to-number",
+            GenesisCoercion::FloatToInteger { .. } => "float-to-integer",
+        }
+    }
+}
+
+impl Display for GenesisCoercion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenesisCoercion::Key { field, key } => write!(f, "{field} was read from the key \"{key}\""),
+            GenesisCoercion::StringToNumber { field, value } => {
+                write!(f, "{field} was given as the string \"{value}\" and read as a number")
+            }
+            GenesisCoercion::FloatToInteger { field, value } => {
+                write!(f, "{field} was given as {value} and read as an integer")
+            }
+        }
+    }
+}
+
+/// Keys compare the same in camelCase, snake_case, kebab-case or any letter case
+fn key_spelling(key: &str) -> String {
+    key.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Whether object has key, after moving a value found under another spelling of it to key
+fn find_key(object: &mut Map<String, Value>, key: &str, field: &str, coercions: &mut Vec<GenesisCoercion>) -> bool {
+    if !object.contains_key(key) {
+        let spelling = key_spelling(key);
+        let other = object.keys().find(|other| key_spelling(other) == spelling).cloned();
+        if let Some(other) = other {
+            let value = object.remove(&other).expect("key was just found");
+            object.insert(key.to_string(), value);
+            coercions.push(GenesisCoercion::Key {
+                field: field.to_string(),
+                key: other,
+            });
+        }
+    }
+    object.contains_key(key)
+}
+
+/// Coerce a value to kind in place, or describe what is wrong with it
+fn coerce_value(
+    value: &mut Value,
+    field: &GenesisField,
+    path: &str,
+    coercions: &mut Vec<GenesisCoercion>,
+) -> Result<(), String> {
+    if let Value::String(string) = value {
+        let number: Number = serde_json::from_str(string.trim())
+            .map_err(|_| format!("{path} must be a number, got the string \"{string}\""))?;
+        if !field.string_expected {
+            coercions.push(GenesisCoercion::StringToNumber {
+                field: path.to_string(),
+                value: string.clone(),
+            });
+        }
+        *value = Value::Number(number);
+    }
+    let Value::Number(number) = value else {
+        return Err(format!("{path} must be a number, got {value}"));
+    };
+    if field.kind == FieldKind::Number {
+        return Ok(());
+    }
+    let integer = match (number.as_u64(), number.as_f64()) {
+        (Some(integer), _) => integer,
+        (None, Some(float)) if float >= 0.0 && float.fract() == 0.0 && float <= u64::MAX as f64 => {
+            coercions.push(GenesisCoercion::FloatToInteger {
+                field: path.to_string(),
+                value: float,
+            });
+            float as u64
+        }
+        _ => return Err(format!("{path} must be a whole number of 0 or more, got {number}")),
+    };
+    if field.kind == FieldKind::U32 && integer > u32::MAX as u64 {
+        return Err(format!("{path} must fit in 32 bits, got {integer}"));
+    }
+    *value = Value::from(integer);
+    Ok(())
+}
+
+/// Rewrite the fields cncli reads in a parsed genesis file to the spelling and types the node writes, so they
+/// deserialize. Other fields are left as they are. Returns what was coerced, or every field that could not be read.
+pub(crate) fn normalize_genesis(
+    genesis: &mut Value,
+    fields: &[GenesisField],
+) -> Result<Vec<GenesisCoercion>, Vec<String>> {
+    let mut coercions = Vec::new();
+    let mut problems = Vec::new();
+    for field in fields.iter() {
+        // the path as a json pointer, the keys have no / or ~ to escape
+        let mut pointer = String::new();
+        let mut path = String::new();
+        let mut found = true;
+        for key in field.path.iter() {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(key);
+            let Some(Value::Object(object)) = genesis.pointer_mut(&pointer) else {
+                found = false;
+                break;
+            };
+            if !find_key(object, key, &path, &mut coercions) {
+                found = false;
+                break;
+            }
+            pointer.push('/');
+            pointer.push_str(key);
+        }
+        let value = match genesis.pointer_mut(&pointer) {
+            Some(value) if found => value,
+            _ => {
+                problems.push(format!("{} is missing", field.path.join(".")));
+                continue;
+            }
+        };
+        if let Err(problem) = coerce_value(value, field, &path, &mut coercions) {
+            problems.push(problem);
+        }
+    }
+    if problems.is_empty() {
+        Ok(coercions)
+    } else {
+        Err(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_genesis() {
+        let mut byron = serde_json::json!({
+            "start_time": "1506203091",
+            "protocol_consts": {"K": 2160.0},
+            "blockVersionData": {"slotDuration": "20000"},
+            "avvmDistr": {}
+        });
+        let coercions = normalize_genesis(&mut byron, BYRON_FIELDS).unwrap();
+        assert_eq!(
+            byron,
+            serde_json::json!({
+                "startTime": 1506203091,
+                "protocolConsts": {"k": 2160},
+                "blockVersionData": {"slotDuration": 20000},
+                "avvmDistr": {}
+            })
+        );
+        let coercions: Vec<String> = coercions.iter().map(|coercion| coercion.to_string()).collect();
+        assert_eq!(
+            coercions,
+            vec![
+                "startTime was read from the key \"start_time\"",
+                "startTime was given as the string \"1506203091\" and read as a number",
+                "protocolConsts was read from the key \"protocol_consts\"",
+                "protocolConsts.k was read from the key \"K\"",
+                "protocolConsts.k was given as 2160 and read as an integer",
+            ]
+        );
+
+        let mut shelley = serde_json::json!({
+            "activeSlotsCoeff": 0.05,
+            "networkMagic": 4294967296u64,
+            "slotLength": "one",
+            "epochLength": 432000
+        });
+        assert_eq!(
+            normalize_genesis(&mut shelley, SHELLEY_FIELDS).unwrap_err(),
+            vec![
+                "networkMagic must fit in 32 bits, got 4294967296",
+                "slotLength must be a number, got the string \"one\""
+            ]
+        );
+        let mut shelley = serde_json::json!({"activeSlotsCoeff": 0.05, "slotLength": 1, "epochLength": -1});
+        assert_eq!(
+            normalize_genesis(&mut shelley, SHELLEY_FIELDS).unwrap_err(),
+            vec![
+                "networkMagic is missing",
+                "epochLength must be a whole number of 0 or more, got -1"
+            ]
+        );
+    }
+}
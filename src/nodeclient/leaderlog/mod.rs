@@ -1,31 +1,63 @@
 use std::fmt::Display;
 use std::fs::File;
-use std::io::{stdout, BufReader};
-use std::path::Path;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use crate::nodeclient::backup::upload_backup;
 use crate::nodeclient::blockstore;
-use crate::nodeclient::blockstore::redb::{is_redb_database, RedbBlockStore};
-use crate::nodeclient::blockstore::sqlite::SqLiteBlockStore;
-use crate::nodeclient::blockstore::BlockStore;
+use crate::nodeclient::blockstore::cache::CachedBlockStore;
+use crate::nodeclient::blockstore::{BlockStore, SlotsInputs, StakeHistory};
 use crate::nodeclient::leaderlog::deserialize::cbor_hex;
+use crate::nodeclient::leaderlog::genesis::{
+    normalize_genesis, GenesisCoercion, GenesisField, BYRON_FIELDS, SHELLEY_FIELDS,
+};
 use crate::nodeclient::leaderlog::ledgerstate::calculate_ledger_state_sigma_d_and_extra_entropy;
-use crate::{LedgerSet, PooltoolConfig};
-use chrono::{DateTime, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use crate::nodeclient::leaderlog::schedule::{render_schedule, ScheduleExport};
+use crate::nodeclient::leaderlog::timezone::parse_timezone;
+use crate::nodeclient::leaderlog::trusted_nonces::{check_trusted_nonce, TrustedNonce};
+use crate::nodeclient::leaderlog::vrf_keys::{select_vrf_key, VrfKeyVersion};
+use crate::nodeclient::poolid::{format_pool_id, PoolId};
+use crate::nodeclient::pooltool::{PooltoolClient, SendSlotsRequest};
+use crate::nodeclient::reporter;
+use crate::nodeclient::reporter::human;
+use crate::nodeclient::reporter::human::{Cell, Color, Table};
+use crate::nodeclient::runstate;
+use crate::nodeclient::vrfkey::{vrf_vkey_hash, vrf_vkey_of_skey, VRF_SIGNING_KEY_SIZE};
+use crate::{LedgerSet, PooltoolConfig, ReportFormat, SlotsFormat};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, TimeDelta, TimeZone, Utc};
 use chrono_tz::Tz;
-use itertools::sorted;
 use pallas_crypto::hash::{Hash, Hasher};
-use pallas_crypto::nonce::generate_epoch_nonce;
+use pallas_crypto::nonce::{generate_epoch_nonce, generate_rolling_nonce};
 use pallas_crypto::vrf::{VrfSecretKey, VRF_SECRET_KEY_SIZE};
 use pallas_math::math::{ExpOrdering, FixedDecimal, FixedPrecision, DEFAULT_PRECISION};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::deserialize_number_from_string;
 use thiserror::Error;
-use tracing::{debug, error, info, span, trace, Level};
+use tracing::{debug, error, info, span, trace, warn, Level};
 
+pub(crate) mod archive;
+pub(crate) mod battle;
 mod deserialize;
+#[cfg(feature = "ffi")]
+pub(crate) mod ffi;
+mod genesis;
 mod ledgerstate;
+pub(crate) mod schedule;
+pub(crate) mod simulate;
+mod timezone;
+pub(crate) mod trusted_nonces;
+pub(crate) mod vectors;
+pub(crate) mod vrf_keys;
+
+// how old the tip may be for status to count the db as synced
+const SYNCED_TIP_AGE_SECS: i64 = 120;
+// leaderlog --fail-if-zero exit codes, so a script can tell a 0 slot epoch from a failed calculation
+pub(crate) const EXIT_LEADERLOG_FAILED: i32 = 1;
+pub(crate) const EXIT_NO_SLOTS_ASSIGNED: i32 = 2;
+// --check-perms and --strict exit code for a vrf.skey that is missing or accessible by others
+pub(crate) const EXIT_UNSAFE_KEY_PERMISSIONS: i32 = 3;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -58,21 +90,20 @@ pub enum Error {
 
     #[error("ParseFloat error: {0}")]
     ParseFloat(#[from] std::num::ParseFloatError),
-}
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct LeaderLogError {
-    status: String,
-    error_message: String,
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] reporter::Error),
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct ByronGenesis {
+pub(crate) struct ByronGenesis {
     start_time: u64,
     protocol_consts: ProtocolConsts,
     block_version_data: BlockVersionData,
+    /// The values that were read differently from how the node writes them
+    #[serde(skip)]
+    pub(crate) coercions: Vec<GenesisCoercion>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -84,17 +115,59 @@ struct ProtocolConsts {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct BlockVersionData {
+    // milliseconds
     #[serde(deserialize_with = "deserialize_number_from_string")]
     slot_duration: u64,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct ShelleyGenesis {
-    active_slots_coeff: f64,
-    network_magic: u32,
-    slot_length: u64,
-    epoch_length: u64,
+pub(crate) struct ShelleyGenesis {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub(crate) active_slots_coeff: f64,
+    pub(crate) network_magic: u32,
+    /// slotLength is given in seconds and can be a fraction of one on custom networks
+    #[serde(rename = "slotLength", deserialize_with = "deserialize_seconds_as_millis")]
+    pub(crate) slot_length_ms: u64,
+    pub(crate) epoch_length: u64,
+    /// How many genesis delegates have to propose the same protocol parameter update for it to be enacted
+    #[serde(default)]
+    pub(crate) update_quorum: Option<u64>,
+    #[serde(default)]
+    pub(crate) protocol_params: GenesisProtocolParams,
+    /// The values that were read differently from how the node writes them
+    #[serde(skip)]
+    pub(crate) coercions: Vec<GenesisCoercion>,
+}
+
+/// The protocol parameters the chain starts with, in effect until an update proposal replaces them
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GenesisProtocolParams {
+    #[serde(default)]
+    decentralisation_param: f64,
+    #[serde(default)]
+    extra_entropy: Option<GenesisNonce>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "tag", content = "contents")]
+enum GenesisNonce {
+    NeutralNonce,
+    Nonce(String),
+}
+
+fn deserialize_seconds_as_millis<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let seconds: f64 = deserialize_number_from_string(deserializer)?;
+    if seconds.is_nan() || seconds <= 0.0 {
+        return Err(serde::de::Error::custom(format!(
+            "slotLength must be positive, got {seconds}"
+        )));
+    }
+    Ok((seconds * 1000.0).round() as u64)
 }
 
 #[derive(Debug, Deserialize)]
@@ -106,11 +179,21 @@ pub(crate) struct VrfKey {
     pub(crate) key: Vec<u8>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct LeaderLog {
     status: String,
     epoch: u64,
+    /// When the epoch starts in the --tz timezone. Archives written before it was added do not have it.
+    #[serde(default)]
+    epoch_start: String,
+    #[serde(default)]
+    epoch_start_utc: String,
+    /// When the epoch ends, which is when the next one starts, in the --tz timezone
+    #[serde(default)]
+    epoch_end: String,
+    #[serde(default)]
+    epoch_end_utc: String,
     epoch_nonce: String,
     consensus: String,
     epoch_slots: u64,
@@ -122,40 +205,87 @@ struct LeaderLog {
     total_active_stake: u64,
     d: f64,
     f: f64,
+    /// Parameter combinations that are unusual for the consensus, see check_consensus_params
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
     assigned_slots: Vec<Slot>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Slot {
     no: u64,
     slot: u64,
     slot_in_epoch: u64,
+    /// The slot time in the --tz timezone
     at: String,
+    at_utc: String,
+    seconds_from_epoch_start: u64,
+    /// How far the slot was from the time the leaderlog was calculated, e.g. "in 3d 4h" or "5h 12m ago"
+    relative: String,
+    /// --reminder-lead-minutes before the slot in the --tz timezone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reminder_at: Option<String>,
+    /// With --consensus all, the algorithms under which the pool leads this slot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    consensus: Option<Vec<String>>,
 }
 
+/// An entry of `cardano-cli query leadership-schedule` output, for tools written against it
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct PooltoolSendSlots {
-    api_key: String,
-    pool_id: String,
-    epoch: u64,
-    slot_qty: u64,
-    hash: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    override_time: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    prev_slots: Option<String>,
+struct CardanoCliSlot {
+    slot_number: u64,
+    slot_time: String,
 }
 
-fn read_byron_genesis(byron_genesis: &Path) -> Result<ByronGenesis, Error> {
-    let buf = BufReader::new(File::open(byron_genesis)?);
-    Ok(serde_json::from_reader(buf)?)
+/// Parse a genesis file, reading the fields cncli needs from other key spellings and from numbers given as strings.
+/// Each value read that way is logged as a warning.
+fn read_genesis<T: serde::de::DeserializeOwned>(
+    genesis_path: &Path,
+    era: &str,
+    fields: &[GenesisField],
+) -> Result<(T, Vec<GenesisCoercion>), Error> {
+    let buf = BufReader::new(File::open(genesis_path)?);
+    let mut genesis: serde_json::Value = serde_json::from_reader(buf)?;
+    let coercions = normalize_genesis(&mut genesis, fields).map_err(|problems| {
+        Error::Leaderlog(format!(
+            "Invalid {era} genesis {}: {}",
+            genesis_path.display(),
+            problems.join(", ")
+        ))
+    })?;
+    for coercion in coercions.iter() {
+        warn!(
+            genesis = %genesis_path.display(),
+            field = coercion.field(),
+            coercion = coercion.kind(),
+            "{} genesis: {}",
+            era,
+            coercion
+        );
+    }
+    let genesis = serde_json::from_value(genesis)
+        .map_err(|error| Error::Leaderlog(format!("Invalid {era} genesis {}: {error}", genesis_path.display())))?;
+    Ok((genesis, coercions))
 }
 
-fn read_shelley_genesis(shelley_genesis: &Path) -> Result<ShelleyGenesis, Error> {
-    let buf = BufReader::new(File::open(shelley_genesis)?);
-    Ok(serde_json::from_reader(buf)?)
+pub(crate) fn read_byron_genesis(byron_genesis: &Path) -> Result<ByronGenesis, Error> {
+    let (mut byron, coercions): (ByronGenesis, _) = read_genesis(byron_genesis, "byron", BYRON_FIELDS)?;
+    byron.coercions = coercions;
+    if byron.block_version_data.slot_duration == 0 {
+        return Err(Error::Leaderlog(format!(
+            "Invalid byron genesis {}: slotDuration must be positive",
+            byron_genesis.display()
+        )));
+    }
+    Ok(byron)
+}
+
+pub(crate) fn read_shelley_genesis(shelley_genesis: &Path) -> Result<ShelleyGenesis, Error> {
+    let (mut shelley, coercions): (ShelleyGenesis, _) = read_genesis(shelley_genesis, "shelley", SHELLEY_FIELDS)?;
+    shelley.coercions = coercions;
+    Ok(shelley)
 }
 
 pub(crate) fn read_vrf_key(vrf_key_path: &Path) -> Result<VrfKey, Error> {
@@ -163,7 +293,150 @@ pub(crate) fn read_vrf_key(vrf_key_path: &Path) -> Result<VrfKey, Error> {
     Ok(serde_json::from_reader(buf)?)
 }
 
-fn guess_shelley_transition_epoch(network_magic: u32) -> u64 {
+/// Fail when the vrf.skey is not the key the pool registered, which would silently produce a schedule of slots the
+/// pool cannot make blocks in. This happens after a vrf key rotation when the old key file is still configured.
+pub(crate) fn check_vrf_skey_matches(
+    pool_vrf_skey: &VrfKey,
+    pool_id: &PoolId,
+    pool_vrf_vkey_hash: &str,
+) -> Result<(), Error> {
+    let registered = Hash::<32>::from_str(pool_vrf_vkey_hash)
+        .map_err(|_| Error::Leaderlog(format!("Invalid vrf vkey hash: {pool_vrf_vkey_hash}")))?;
+    let vrf_vkey = vrf_vkey_of_skey(&pool_vrf_skey.key).ok_or_else(|| {
+        Error::Leaderlog(format!(
+            "Invalid vrf.skey: expected {} bytes, got {}",
+            VRF_SIGNING_KEY_SIZE,
+            pool_vrf_skey.key.len()
+        ))
+    })?;
+    let vrf_vkey_hash = vrf_vkey_hash(vrf_vkey);
+    if vrf_vkey_hash != registered {
+        return Err(Error::Leaderlog(format!(
+            "The vrf.skey does not belong to pool {pool_id}: its vrf vkey hash is {vrf_vkey_hash} but the registered one is {registered}. Use the vrf.skey the pool is registered with, a rotated key only takes effect once its registration is in the ledger"
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyPermissions {
+    status: String,
+    path: String,
+    warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_message: Option<String>,
+}
+
+/// Check that a signing key file is only accessible by its owner and owned by the user running cncli
+#[cfg(unix)]
+pub(crate) fn check_key_permissions(key_path: &Path) -> Result<Vec<String>, Error> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let metadata = std::fs::metadata(key_path)?;
+    let mut warnings: Vec<String> = vec![];
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        warnings.push(format!(
+            "{} has permissions {:o}, expected 600 or stricter",
+            key_path.to_string_lossy(),
+            mode
+        ));
+    }
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid {
+        warnings.push(format!(
+            "{} is owned by uid {}, but cncli is running as uid {}",
+            key_path.to_string_lossy(),
+            metadata.uid(),
+            uid
+        ));
+    }
+    Ok(warnings)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn check_key_permissions(key_path: &Path) -> Result<Vec<String>, Error> {
+    std::fs::metadata(key_path)?;
+    Ok(vec![])
+}
+
+/// Apply the vrf.skey permission checks for leaderlog and sign. Returns false if the command should stop, either
+/// because --check-perms only asked for the report, because the key is missing or because --strict turned the
+/// warnings into an error. A key that fails --check-perms or --strict exits with EXIT_UNSAFE_KEY_PERMISSIONS, so
+/// scripts do not have to read the report to notice.
+pub(crate) fn enforce_key_permissions(key_path: &Path, strict: bool, check_perms: bool) -> bool {
+    enforce_keys_permissions(&[key_path], strict, check_perms)
+}
+
+/// enforce_key_permissions for every key of a vrf key history. All keys are checked before the command stops, so
+/// one run reports every key that needs fixing. --check-perms reports a single key as an object and several as an
+/// array.
+pub(crate) fn enforce_keys_permissions(key_paths: &[&Path], strict: bool, check_perms: bool) -> bool {
+    let checks: Vec<KeyPermissions> = key_paths
+        .iter()
+        .map(|key_path| {
+            let path = key_path.to_string_lossy().to_string();
+            let checked = if key_path.exists() {
+                check_key_permissions(key_path).map_err(|error| error.to_string())
+            } else {
+                Err(format!("{path} not found"))
+            };
+            match checked {
+                Ok(warnings) => KeyPermissions {
+                    status: if warnings.is_empty() { "ok" } else { "unsafe" }.to_string(),
+                    path,
+                    warnings,
+                    error_message: None,
+                },
+                Err(error_message) => KeyPermissions {
+                    status: "error".to_string(),
+                    path,
+                    warnings: vec![],
+                    error_message: Some(error_message),
+                },
+            }
+        })
+        .collect();
+    let all_ok = checks.iter().all(|check| check.status == "ok");
+
+    if check_perms {
+        let reported = match checks.as_slice() {
+            [check] => reporter::report(check),
+            _ => reporter::report(&checks),
+        };
+        if let Err(error) = reported {
+            handle_error(error);
+        }
+        if !all_ok {
+            crate::exit(EXIT_UNSAFE_KEY_PERMISSIONS);
+        }
+        return false;
+    }
+
+    let errors: Vec<&str> = checks
+        .iter()
+        .filter_map(|check| check.error_message.as_deref())
+        .collect();
+    if !errors.is_empty() {
+        handle_error(errors.join("; "));
+        return false;
+    }
+    let warnings: Vec<String> = checks.into_iter().flat_map(|check| check.warnings).collect();
+    if warnings.is_empty() {
+        return true;
+    }
+    if strict {
+        handle_error(warnings.join("; "));
+        crate::exit(EXIT_UNSAFE_KEY_PERMISSIONS);
+    }
+    for warning in warnings.iter() {
+        warn!("{}", warning);
+    }
+    true
+}
+
+pub(crate) fn guess_shelley_transition_epoch(network_magic: u32) -> u64 {
     match network_magic {
         764824073 => {
             // mainnet
@@ -197,7 +470,7 @@ fn guess_shelley_transition_epoch(network_magic: u32) -> u64 {
 }
 
 /// Calculate the first slot of the epoch and the epoch number for the given slot
-fn get_first_slot_of_epoch(
+pub(crate) fn get_first_slot_of_epoch(
     byron: &ByronGenesis,
     shelley: &ShelleyGenesis,
     current_slot: u64,
@@ -205,6 +478,10 @@ fn get_first_slot_of_epoch(
 ) -> (u64, u64) {
     let byron_epoch_length = 10 * byron.protocol_consts.k;
     let byron_slots = byron_epoch_length * shelley_transition_epoch;
+    if current_slot < byron_slots {
+        let epoch = current_slot / byron_epoch_length;
+        return (epoch, epoch * byron_epoch_length);
+    }
     let shelley_slots = current_slot - byron_slots;
     let shelley_slot_in_epoch = shelley_slots % shelley.epoch_length;
     let first_slot_of_epoch = current_slot - shelley_slot_in_epoch;
@@ -213,28 +490,62 @@ fn get_first_slot_of_epoch(
     (epoch, first_slot_of_epoch)
 }
 
-fn slot_to_naivedatetime(
+/// Calculate the first slot of the given shelley-era epoch number
+pub(crate) fn get_first_slot_of_epoch_number(
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    epoch: u64,
+    shelley_transition_epoch: u64,
+) -> u64 {
+    let byron_epoch_length = 10 * byron.protocol_consts.k;
+    let byron_slots = byron_epoch_length * shelley_transition_epoch;
+    byron_slots + (epoch - shelley_transition_epoch) * shelley.epoch_length
+}
+
+/// The time the first shelley slot starts at, in milliseconds since the unix epoch
+fn byron_end_time_ms(byron: &ByronGenesis, shelley_transition_epoch: u64) -> u64 {
+    let byron_epoch_length = 10 * byron.protocol_consts.k;
+    byron.start_time * 1000 + shelley_transition_epoch * byron_epoch_length * byron.block_version_data.slot_duration
+}
+
+pub(crate) fn slot_to_naivedatetime(
     byron: &ByronGenesis,
     shelley: &ShelleyGenesis,
     slot: u64,
     shelley_transition_epoch: u64,
 ) -> NaiveDateTime {
-    let network_start_time = DateTime::from_timestamp(byron.start_time as i64, 0)
-        .unwrap()
-        .naive_utc();
     let byron_epoch_length = 10 * byron.protocol_consts.k;
     let byron_slots = byron_epoch_length * shelley_transition_epoch;
-    let shelley_slots = slot - byron_slots;
 
-    let byron_secs = (byron.block_version_data.slot_duration * byron_slots) / 1000;
-    let shelley_secs = shelley_slots * shelley.slot_length;
+    // slots before the transition are byron slots of their own length
+    let slot_time_ms = if slot < byron_slots {
+        byron.start_time * 1000 + slot * byron.block_version_data.slot_duration
+    } else {
+        byron_end_time_ms(byron, shelley_transition_epoch) + (slot - byron_slots) * shelley.slot_length_ms
+    };
+
+    DateTime::from_timestamp_millis(slot_time_ms as i64)
+        .unwrap()
+        .naive_utc()
+}
 
-    network_start_time
-        + TimeDelta::try_seconds(byron_secs as i64).unwrap()
-        + TimeDelta::try_seconds(shelley_secs as i64).unwrap()
+/// The slot in progress at a time in milliseconds since the unix epoch, the inverse of slot_to_naivedatetime
+pub(crate) fn slot_at_time_ms(
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    time_ms: u64,
+) -> u64 {
+    let byron_epoch_length = 10 * byron.protocol_consts.k;
+    let byron_slots = byron_epoch_length * shelley_transition_epoch;
+    let byron_end_time_ms = byron_end_time_ms(byron, shelley_transition_epoch);
+    if time_ms < byron_end_time_ms {
+        return time_ms.saturating_sub(byron.start_time * 1000) / byron.block_version_data.slot_duration;
+    }
+    byron_slots + (time_ms - byron_end_time_ms) / shelley.slot_length_ms
 }
 
-fn slot_to_timestamp(
+pub(crate) fn slot_to_timestamp(
     byron: &ByronGenesis,
     shelley: &ShelleyGenesis,
     slot: u64,
@@ -245,6 +556,37 @@ fn slot_to_timestamp(
     tz.from_utc_datetime(&slot_time).to_rfc3339()
 }
 
+/// Describe a time difference in seconds by its two largest units, e.g. "in 3d 4h" for the future or "12m 5s ago"
+/// for the past
+fn relative_time(seconds: i64) -> String {
+    if seconds == 0 {
+        return "now".to_string();
+    }
+    let abs_seconds = seconds.unsigned_abs();
+    let units = [
+        (abs_seconds / 86400, "d"),
+        (abs_seconds % 86400 / 3600, "h"),
+        (abs_seconds % 3600 / 60, "m"),
+        (abs_seconds % 60, "s"),
+    ];
+    let largest = units
+        .iter()
+        .position(|(value, _)| *value > 0)
+        .unwrap_or(units.len() - 1);
+    let relative = units[largest..]
+        .iter()
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .collect::<Vec<String>>()
+        .join(" ");
+    if seconds > 0 {
+        format!("in {relative}")
+    } else {
+        format!("{relative} ago")
+    }
+}
+
 pub fn is_overlay_slot(first_slot_of_epoch: &u64, current_slot: &u64, d: &f64) -> bool {
     let d = FixedDecimal::from((*d * 1000.0).round() as u64) / FixedDecimal::from(1000u64);
     trace!("d: {}", &d);
@@ -301,224 +643,265 @@ fn vrf_eval_certified(seed: &[u8], pool_vrf_skey: &[u8]) -> Result<Hash<64>, Err
     Ok(certified_proof_hash)
 }
 
-fn vrf_leader_value(raw_vrf: &[u8]) -> Result<FixedDecimal, Error> {
+fn vrf_leader_hash(raw_vrf: &[u8]) -> Hash<32> {
     let mut hasher = Hasher::<256>::new();
     hasher.input(vec![0x4C_u8].as_slice()); // "L"
     hasher.input(raw_vrf);
-    Ok(FixedDecimal::from(hasher.finalize().as_slice()))
+    hasher.finalize()
 }
 
-// Determine if our pool is a slot leader for this given slot
+fn vrf_leader_value(raw_vrf: &[u8]) -> Result<FixedDecimal, Error> {
+    Ok(FixedDecimal::from(vrf_leader_hash(raw_vrf).as_slice()))
+}
+
+// Calculate 1 / (1 - leader value) of our vrf output for this given slot. The pool is a slot leader when it is
+// below exp(-sigma * c), so the same value can be compared against any sigma.
 // @param slot The slot to check
-// @param sigma The controlled stake proportion for the pool
 // @param eta0 The epoch nonce value
 // @param pool_vrf_skey The vrf signing key for the pool
 // @param cert_nat_max The value 2^256
-// @param c ln(1-activeSlotsCoeff) - usually ln(1-0.05)
-fn is_slot_leader_praos(
+fn praos_recip_q(
     slot: u64,
-    sigma: &FixedDecimal,
     eta0: &[u8],
     pool_vrf_skey: &[u8],
     cert_nat_max: &FixedDecimal,
-    c: &FixedDecimal,
-) -> Result<bool, Error> {
+) -> Result<FixedDecimal, Error> {
     let seed: Vec<u8> = mk_input_vrf(slot, eta0);
     let cert_nat: Hash<64> = vrf_eval_certified(&seed, pool_vrf_skey)?;
     let cert_leader_vrf: FixedDecimal = vrf_leader_value(cert_nat.as_slice())?;
     let denominator = cert_nat_max - &cert_leader_vrf;
     let recip_q: FixedDecimal = cert_nat_max / &denominator;
-    let x: FixedDecimal = -(sigma * c);
-    let ordering = x.exp_cmp(1000, 3, &recip_q);
 
-    let span = span!(Level::TRACE, "is_slot_leader_praos");
-    let _enter = span.enter();
-    trace!("is_slot_leader_praos: {}", slot);
     trace!("seed: {}", hex::encode(&seed));
     trace!("cert_nat: {}", &cert_nat);
     trace!("cert_leader_vrf: {}", &cert_leader_vrf);
     trace!("recip_q: {}", &recip_q);
-    trace!("c: {}", c);
-    trace!("x: {}", &x);
 
-    Ok(ordering.estimation == ExpOrdering::LT)
+    Ok(recip_q)
 }
 
-// Determine if our pool is a slot leader for this given slot
+// Calculate 1 / (1 - leader value) of our vrf output for this given slot in tpraos
 // @param slot The slot to check
-// @param sigma The controlled stake proportion for the pool
 // @param eta0 The epoch nonce value
 // @param pool_vrf_skey The vrf signing key for the pool
 // @param cert_nat_max The value 2^512
-// @param c 1-activeSlotsCoeff - usually 0.95
-fn is_slot_leader_tpraos(
+fn tpraos_recip_q(
     slot: u64,
-    sigma: &FixedDecimal,
     eta0: &[u8],
     pool_vrf_skey: &[u8],
     cert_nat_max: &FixedDecimal,
-    c: &FixedDecimal,
-) -> Result<bool, Error> {
+) -> Result<FixedDecimal, Error> {
     let seed: Vec<u8> = mk_seed(slot, eta0);
     let cert_nat: FixedDecimal = FixedDecimal::from(vrf_eval_certified(&seed, pool_vrf_skey)?.as_slice());
     let denominator = cert_nat_max - &cert_nat;
     let recip_q: FixedDecimal = cert_nat_max / &denominator;
-    let x: FixedDecimal = -(sigma * c);
-    let ordering = x.exp_cmp(1000, 3, &recip_q);
 
-    let span = span!(Level::TRACE, "is_slot_leader_tpraos");
-    let _enter = span.enter();
-    trace!("is_slot_leader: {}", slot);
     trace!("seed: {}", hex::encode(&seed));
     trace!("cert_nat: {}", &cert_nat);
     trace!("recip_q: {}", &recip_q);
-    trace!("c: {}", c);
-    trace!("x: {}", &x);
 
-    Ok(ordering.estimation == ExpOrdering::LT)
+    Ok(recip_q)
 }
 
-fn get_current_slot(
-    byron: &ByronGenesis,
-    shelley: &ShelleyGenesis,
-    shelley_transition_epoch: u64,
-) -> Result<u64, Error> {
-    // read byron genesis values
-    let byron_slot_length = byron.block_version_data.slot_duration;
-    let byron_k = byron.protocol_consts.k;
-    let byron_start_time_sec = byron.start_time;
-    let byron_epoch_length = 10 * byron_k;
-    let byron_end_time_sec =
-        byron_start_time_sec + ((shelley_transition_epoch * byron_epoch_length * byron_slot_length) / 1000);
-
-    // read shelley genesis values
-    let slot_length = shelley.slot_length;
+// Compare a slot's recip_q against the leader threshold for a sigma
+// @param sigma The controlled stake proportion for the pool
+// @param c ln(1-activeSlotsCoeff) - usually ln(1-0.05), None when activeSlotsCoeff is 1
+fn is_leader_recip_q(recip_q: &FixedDecimal, sigma: &FixedDecimal, c: &Option<FixedDecimal>) -> bool {
+    let c = match c {
+        Some(c) => c,
+        None => return true,
+    };
+    let x: FixedDecimal = -(sigma * c);
+    let ordering = x.exp_cmp(1000, 3, recip_q);
 
-    let current_time_sec = Utc::now().timestamp() as u64;
+    trace!("c: {}", c);
+    trace!("x: {}", &x);
 
-    // Calculate current slot
-    let byron_slots = shelley_transition_epoch * byron_epoch_length;
-    let shelley_slots = (current_time_sec - byron_end_time_sec) / slot_length;
-    Ok(byron_slots + shelley_slots)
+    ordering.estimation == ExpOrdering::LT
 }
 
-fn get_current_epoch(byron: &ByronGenesis, shelley: &ShelleyGenesis, shelley_transition_epoch: u64) -> u64 {
-    // read byron genesis values
-    let byron_slot_length = byron.block_version_data.slot_duration;
-    let byron_k = byron.protocol_consts.k;
-    let byron_start_time_sec = byron.start_time;
-
-    let byron_epoch_length_secs = 10 * byron_k;
-    let byron_end_time_sec =
-        byron_start_time_sec + ((shelley_transition_epoch * byron_epoch_length_secs * byron_slot_length) / 1000);
+// Determine if our pool is a slot leader for this given slot
+// @param slot The slot to check
+// @param sigma The controlled stake proportion for the pool
+// @param eta0 The epoch nonce value
+// @param pool_vrf_skey The vrf signing key for the pool
+// @param cert_nat_max The value 2^256
+// @param c ln(1-activeSlotsCoeff) - usually ln(1-0.05)
+fn is_slot_leader_praos(
+    slot: u64,
+    sigma: &FixedDecimal,
+    eta0: &[u8],
+    pool_vrf_skey: &[u8],
+    cert_nat_max: &FixedDecimal,
+    c: &Option<FixedDecimal>,
+) -> Result<bool, Error> {
+    let span = span!(Level::TRACE, "is_slot_leader_praos");
+    let _enter = span.enter();
+    trace!("is_slot_leader_praos: {}", slot);
 
-    // read shelley genesis values
-    let slot_length = shelley.slot_length;
-    let epoch_length = shelley.epoch_length;
+    let recip_q = praos_recip_q(slot, eta0, pool_vrf_skey, cert_nat_max)?;
+    Ok(is_leader_recip_q(&recip_q, sigma, c))
+}
 
-    let current_time_sec = Utc::now().timestamp() as u64;
+// Determine if our pool is a slot leader for this given slot
+// @param slot The slot to check
+// @param sigma The controlled stake proportion for the pool
+// @param eta0 The epoch nonce value
+// @param pool_vrf_skey The vrf signing key for the pool
+// @param cert_nat_max The value 2^512
+// @param c 1-activeSlotsCoeff - usually 0.95
+fn is_slot_leader_tpraos(
+    slot: u64,
+    sigma: &FixedDecimal,
+    eta0: &[u8],
+    pool_vrf_skey: &[u8],
+    cert_nat_max: &FixedDecimal,
+    c: &Option<FixedDecimal>,
+) -> Result<bool, Error> {
+    let span = span!(Level::TRACE, "is_slot_leader_tpraos");
+    let _enter = span.enter();
+    trace!("is_slot_leader: {}", slot);
 
-    shelley_transition_epoch + ((current_time_sec - byron_end_time_sec) / slot_length / epoch_length)
+    let recip_q = tpraos_recip_q(slot, eta0, pool_vrf_skey, cert_nat_max)?;
+    Ok(is_leader_recip_q(&recip_q, sigma, c))
 }
 
+// The consensus algorithms among the candidates under which the pool leads this slot. praos and cpraos share the vrf
+// input and leader value, so it is evaluated once for both.
 #[allow(clippy::too_many_arguments)]
-pub(crate) fn calculate_leader_logs(
-    db_path: &Path,
-    byron_genesis: &Path,
-    shelley_genesis: &Path,
-    pool_stake: &u64,
-    active_stake: &u64,
-    d: &f64,
-    extra_entropy: &Option<String>,
-    ledger_set: &LedgerSet,
-    pool_id: &str,
-    pool_vrf_skey_path: &Path,
-    timezone: &str,
-    is_just_nonce: bool,
-    consensus: &str,
-    shelley_transition_epoch: &Option<u64>,
-    nonce: &Option<String>,
-    epoch: &Option<u64>,
-) -> Result<(), Error> {
-    debug!("calculate_leader_logs() start");
-    let tz: Tz = timezone.parse::<Tz>().unwrap();
-
-    if !db_path.exists() {
-        return Err(Error::Leaderlog(format!(
-            "Invalid Path: --db {}",
-            db_path.to_string_lossy()
-        )));
+fn leading_consensus(
+    slot: u64,
+    sigma: &FixedDecimal,
+    eta0: &[u8],
+    pool_vrf_skey: &[u8],
+    candidates: &[&'static str],
+    tpraos_cert_nat_max: &FixedDecimal,
+    praos_cert_nat_max: &FixedDecimal,
+    c: &Option<FixedDecimal>,
+) -> Result<Vec<&'static str>, Error> {
+    let mut leading: Vec<&'static str> = Vec::new();
+    if candidates.contains(&"tpraos")
+        && is_slot_leader_tpraos(slot, sigma, eta0, pool_vrf_skey, tpraos_cert_nat_max, c)?
+    {
+        leading.push("tpraos");
     }
-
-    if !byron_genesis.exists() {
-        return Err(Error::Leaderlog(format!(
-            "Invalid Path: --byron-genesis {}",
-            byron_genesis.to_string_lossy()
-        )));
+    if candidates.iter().any(|candidate| *candidate != "tpraos")
+        && is_slot_leader_praos(slot, sigma, eta0, pool_vrf_skey, praos_cert_nat_max, c)?
+    {
+        leading.extend(candidates.iter().filter(|candidate| **candidate != "tpraos"));
     }
+    Ok(leading)
+}
 
-    if !shelley_genesis.exists() {
-        return Err(Error::Leaderlog(format!(
-            "Invalid Path: --shelley-genesis {}",
-            shelley_genesis.to_string_lossy()
-        )));
-    }
+// The active slots coefficient f from the shelley genesis. An f64 displays as the shortest decimal that reads back as
+// the same f64, which is the value written in the genesis, so its digits are taken as they are rather than scaling
+// the f64 and truncating it, which turned e.g. 0.29 into 0.2899.
+fn active_slots_coeff(shelley: &ShelleyGenesis) -> Result<FixedDecimal, Error> {
+    active_slots_coeff_fixed(shelley.active_slots_coeff)
+}
 
-    if !is_just_nonce && !pool_vrf_skey_path.exists() {
+fn active_slots_coeff_fixed(active_slots_coeff: f64) -> Result<FixedDecimal, Error> {
+    if !(active_slots_coeff > 0.0 && active_slots_coeff <= 1.0) {
         return Err(Error::Leaderlog(format!(
-            "Invalid Path: --pool_vrf_skey {}",
-            pool_vrf_skey_path.to_string_lossy()
+            "Invalid activeSlotsCoeff {active_slots_coeff} in the shelley genesis, it must be greater than 0 and at most 1"
         )));
     }
+    decimal_to_fixed(&active_slots_coeff.to_string())
+}
 
-    if consensus != "praos" && consensus != "tpraos" && consensus != "cpraos" {
-        return Err(Error::Leaderlog(format!("Invalid Consensus: --consensus {consensus}")));
+// Parse a plain decimal like 0.075 exactly
+fn decimal_to_fixed(decimal: &str) -> Result<FixedDecimal, Error> {
+    let precision = DEFAULT_PRECISION as usize;
+    let (integer, fraction) = decimal.split_once('.').unwrap_or((decimal, ""));
+    if integer.is_empty()
+        || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+        || fraction.len() > precision
+    {
+        return Err(Error::Leaderlog(format!("Invalid decimal: {decimal}")));
     }
-
-    // check if db_path is a redb database based on magic number
-    let use_redb = is_redb_database(db_path)?;
-
-    let mut block_store: Box<dyn BlockStore + Send> = if use_redb {
-        Box::new(RedbBlockStore::new(db_path)?)
-    } else {
-        Box::new(SqLiteBlockStore::new(db_path)?)
+    let digits = format!("{integer}{fraction:0<precision$}");
+    let digits = match digits.trim_start_matches('0') {
+        "" => "0",
+        digits => digits,
     };
+    Ok(FixedDecimal::from_str(digits, DEFAULT_PRECISION)?)
+}
 
-    let byron = read_byron_genesis(byron_genesis)?;
-    debug!("{:?}", byron);
+// c = ln(1-f) for the leader check. None when f is 1, every slot is then led whatever the stake like in the ledger.
+fn leader_c(shelley: &ShelleyGenesis) -> Result<Option<FixedDecimal>, Error> {
+    leader_c_for(shelley.active_slots_coeff)
+}
 
-    let shelley = read_shelley_genesis(shelley_genesis)?;
-    debug!("{:?}", shelley);
+fn leader_c_for(active_slots_coeff: f64) -> Result<Option<FixedDecimal>, Error> {
+    if active_slots_coeff == 1.0 {
+        return Ok(None);
+    }
+    Ok(Some(
+        (FixedDecimal::from(1u64) - active_slots_coeff_fixed(active_slots_coeff)?).ln(),
+    ))
+}
 
-    let shelley_transition_epoch = match *shelley_transition_epoch {
-        None => guess_shelley_transition_epoch(shelley.network_magic),
-        Some(value) => value,
-    };
+// The largest vrf output value for the consensus algorithm
+fn cert_nat_max(consensus: &str) -> Result<FixedDecimal, Error> {
+    Ok(match consensus {
+        "tpraos" => FixedDecimal::from_str("134078079299425970995740249982058461274793658205923933777235614437217640300735469768018742981669034276900318581864860508537538828119465699464336490060840960000000000000000000000000000000000", DEFAULT_PRECISION)?, // 2^512
+        "praos" | "cpraos" => FixedDecimal::from_str("1157920892373161954235709850086879078532699846656405640394575840079131296399360000000000000000000000000000000000", DEFAULT_PRECISION)?, // 2^256
+        _ => return Err(Error::Leaderlog(format!(
+            "Invalid Consensus: --consensus {consensus}"
+        )))
+    })
+}
 
-    let ledger_info = calculate_ledger_state_sigma_d_and_extra_entropy(pool_stake, active_stake, d, extra_entropy)?;
+/// The epoch a leaderlog is calculated for
+#[derive(Debug, PartialEq)]
+pub(crate) struct TargetEpoch {
+    pub(crate) epoch: u64,
+    pub(crate) first_slot_of_epoch: u64,
+    /// The db tip, or the slot in progress at now when the nonce is supplied
+    pub(crate) tip_slot_number: u64,
+    /// The epoch in progress at now
+    pub(crate) current_epoch: u64,
+}
 
-    let tip_slot_number = match nonce {
-        Some(_) => {
-            // pretend we're on tip
-            let now_slot_number = get_current_slot(&byron, &shelley, shelley_transition_epoch)?;
-            debug!("now_slot_number: {}", now_slot_number);
-            now_slot_number
-        }
-        None => {
-            let tip_slot_number = block_store.get_tip_slot_number()?;
-            debug!("tip_slot_number: {}", tip_slot_number);
-            tip_slot_number
-        }
+/// Work out the epoch of the ledger set, or of --epoch, from the db tip. With a supplied nonce the db is not read
+/// and the slot in progress at now stands in for its tip, which also allows epochs after the current one.
+#[allow(clippy::too_many_arguments)]
+pub fn target_epoch(
+    block_store: &mut dyn BlockStore,
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    ledger_set: &LedgerSet,
+    epoch: &Option<u64>,
+    nonce_supplied: bool,
+    now: DateTime<Utc>,
+) -> Result<TargetEpoch, Error> {
+    if let (LedgerSet::Future(epochs_ahead), false) = (ledger_set, nonce_supplied) {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Ledger Set: the nonce {epochs_ahead} epochs ahead is not known yet, supply it with --nonce"
+        )));
+    }
+
+    let now_slot_number = slot_at_time_ms(byron, shelley, shelley_transition_epoch, now.timestamp_millis() as u64);
+    let tip_slot_number = if nonce_supplied {
+        // pretend we're on tip
+        debug!("now_slot_number: {}", now_slot_number);
+        now_slot_number
+    } else {
+        let tip_slot_number = block_store.get_tip_slot_number()?;
+        debug!("tip_slot_number: {}", tip_slot_number);
+        tip_slot_number
     };
 
-    let current_epoch = get_current_epoch(&byron, &shelley, shelley_transition_epoch);
+    let current_epoch = get_first_slot_of_epoch(byron, shelley, now_slot_number, shelley_transition_epoch).0;
 
-    let epoch_offset = match epoch {
+    // negative for epochs after the current one, which can only be calculated with a supplied nonce
+    let epoch_offset: i64 = match epoch {
         Some(epoch) => {
-            if *epoch > current_epoch || *epoch <= shelley_transition_epoch {
-                return Err(Error::Leaderlog(format!("Invalid Epoch: --epoch {epoch}, current_epoch: {current_epoch}, shelley_transition_epoch: {shelley_transition_epoch}")));
+            if (*epoch > current_epoch && !nonce_supplied) || *epoch <= shelley_transition_epoch {
+                return Err(Error::Leaderlog(format!("Invalid Epoch: --epoch {epoch}, current_epoch: {current_epoch}, shelley_transition_epoch: {shelley_transition_epoch}. Epochs after the current one need --nonce")));
             }
-            current_epoch - *epoch
+            current_epoch as i64 - *epoch as i64
         }
         None => 0,
     };
@@ -530,178 +913,459 @@ pub(crate) fn calculate_leader_logs(
             LedgerSet::Mark => shelley.epoch_length as i64,
             LedgerSet::Set => 0,
             LedgerSet::Go => -(shelley.epoch_length as i64),
+            LedgerSet::Future(epochs_ahead) => (shelley.epoch_length * epochs_ahead) as i64,
         },
-        _ => -((shelley.epoch_length * epoch_offset) as i64),
+        _ => -(shelley.epoch_length as i64 * epoch_offset),
     };
 
     let (epoch, first_slot_of_epoch) = get_first_slot_of_epoch(
-        &byron,
-        &shelley,
+        byron,
+        shelley,
         (tip_slot_number as i64 + additional_slots) as u64,
         shelley_transition_epoch,
     );
     debug!("epoch: {}", epoch);
 
-    let epoch_nonce: Hash<32> = match nonce {
-        Some(nonce) => Hash::<32>::from_str(nonce.as_str())?,
-        None => {
-            // Make sure we're fully sync'd
-            let tip_time = slot_to_naivedatetime(&byron, &shelley, tip_slot_number, shelley_transition_epoch)
-                .and_utc()
-                .timestamp();
-            let system_time = Utc::now().timestamp();
-            if system_time - tip_time > 900 {
-                return Err(Error::Leaderlog(format!(
-                    "db not fully synced! system_time: {system_time}, tip_time: {tip_time}"
-                )));
-            }
+    Ok(TargetEpoch {
+        epoch,
+        first_slot_of_epoch,
+        tip_slot_number,
+        current_epoch,
+    })
+}
 
-            let first_slot_of_prev_epoch = first_slot_of_epoch - shelley.epoch_length;
-            debug!("first_slot_of_epoch: {}", first_slot_of_epoch);
-            debug!("first_slot_of_prev_epoch: {}", first_slot_of_prev_epoch);
-            let stability_window_multiplier = match consensus {
-                "cpraos" => 4u64,
-                _ => 3u64,
-            };
-            let stability_window = ((stability_window_multiplier * byron.protocol_consts.k) as f64
-                / shelley.active_slots_coeff)
-                .ceil() as u64;
-            let stability_window_start = first_slot_of_epoch - stability_window;
-            debug!("stability_window: {}", stability_window);
-            debug!("stability_window_start: {}", stability_window_start);
-            let stability_window_start_plus_1_min = stability_window_start + 60;
-
-            let tip_slot_number = block_store.get_tip_slot_number()?;
-            if tip_slot_number < stability_window_start_plus_1_min {
-                return Err(Error::Leaderlog(format!(
-                    "Not enough blocks sync'd to calculate! Try again later after slot {stability_window_start_plus_1_min} is sync'd."
-                )));
-            }
+/// The nonce of the target epoch, the supplied one or else calculated from the db. The db tip must then be less
+/// than 15 minutes behind now. With --consensus all the nonce follows the rules of the blocks of the epoch before.
+#[allow(clippy::too_many_arguments)]
+pub fn leaderlog_nonce(
+    block_store: &mut dyn BlockStore,
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    target_epoch: &TargetEpoch,
+    consensus: &str,
+    extra_entropy: &Option<String>,
+    nonce: &Option<String>,
+    now: DateTime<Utc>,
+) -> Result<Hash<32>, Error> {
+    if let Some(nonce) = nonce {
+        return Ok(Hash::<32>::from_str(nonce.as_str())?);
+    }
+
+    // Make sure we're fully sync'd
+    let tip_time = slot_to_naivedatetime(byron, shelley, target_epoch.tip_slot_number, shelley_transition_epoch)
+        .and_utc()
+        .timestamp();
+    let system_time = now.timestamp();
+    if system_time - tip_time > 900 {
+        return Err(Error::Leaderlog(format!(
+            "db not fully synced! system_time: {system_time}, tip_time: {tip_time}"
+        )));
+    }
+
+    let nonce_consensus = match consensus {
+        "all" => epoch_consensus(block_store, shelley, target_epoch.first_slot_of_epoch)?,
+        _ => consensus,
+    };
+    epoch_nonce_from_db(
+        block_store,
+        byron,
+        shelley,
+        target_epoch.first_slot_of_epoch,
+        nonce_consensus,
+        extra_entropy,
+    )
+}
+
+/// The pool's share of the active stake
+pub fn sigma(pool_stake: u64, active_stake: u64) -> FixedDecimal {
+    FixedDecimal::from(pool_stake) / FixedDecimal::from(active_stake)
+}
 
-            let nc: Hash<32> = block_store.get_eta_v_before_slot(stability_window_start)?;
-            debug!("nc: {}", nc);
+/// How many slots a pool with sigma of the stake is expected to lead in an epoch, sigma * epoch length * f * (1 - d),
+/// rounded to 2 decimals. d is rounded to 3 decimals like on chain.
+pub fn epoch_slots_ideal(shelley: &ShelleyGenesis, sigma: &FixedDecimal, d: f64) -> Result<f64, Error> {
+    let active_slots_coeff = active_slots_coeff(shelley)?;
+    debug!("active_slots_coeff: {}", &active_slots_coeff);
 
-            let nh: Hash<32> = block_store.get_prev_hash_before_slot(first_slot_of_prev_epoch)?;
-            debug!("nh: {}", nh);
+    let d_multiplier = FixedDecimal::from(((1.0 - d) * 1000.0).round() as u64) / FixedDecimal::from(1000u64);
+    let epoch_slots_ideal = f64::from_str(
+        &(sigma * &(&FixedDecimal::from(shelley.epoch_length) * &active_slots_coeff) * d_multiplier).to_string(),
+    )?;
+    Ok((epoch_slots_ideal * 100.0).round() / 100.0)
+}
 
-            debug!("extra_entropy: {:?}", &ledger_info.extra_entropy);
-            let extra_entropy_vec: Option<Vec<u8>> = ledger_info
-                .extra_entropy
-                .map(|entropy| hex::decode(entropy).expect("Invalid hex string"));
-            generate_epoch_nonce(nc, nh, extra_entropy_vec.as_deref())
+/// The assigned slots as a percentage of the ideal, rounded to 2 decimals
+pub fn max_performance(epoch_slots: u64, epoch_slots_ideal: f64) -> f64 {
+    (epoch_slots as f64 / epoch_slots_ideal * 10000.0).round() / 100.0
+}
+
+/// Format assigned slots the way they are saved for sendslots, a JSON array without spaces, along with the hash
+/// PoolTool uses to verify the slots revealed after the epoch
+pub fn slots_and_hash(assigned_slots: impl Iterator<Item = u64>) -> (String, String) {
+    let mut slots = String::new();
+    slots.push('[');
+    for (i, assigned_slot) in assigned_slots.enumerate() {
+        if i > 0 {
+            slots.push(',');
         }
+        slots.push_str(&assigned_slot.to_string())
+    }
+    slots.push(']');
+
+    let hash = Hasher::<256>::hash(slots.as_bytes()).to_string();
+    (slots, hash)
+}
+
+/// The slots of the epoch starting at first_slot_of_epoch that the pool leads, in order, with the consensus
+/// algorithms among the candidates that assign each of them
+pub fn leader_slots(
+    shelley: &ShelleyGenesis,
+    first_slot_of_epoch: u64,
+    d: f64,
+    sigma: &FixedDecimal,
+    epoch_nonce: &[u8],
+    pool_vrf_skey: &[u8],
+    candidates: &[&'static str],
+) -> Result<Vec<(u64, Vec<&'static str>)>, Error> {
+    let tpraos_cert_nat_max: FixedDecimal = cert_nat_max("tpraos")?;
+    let praos_cert_nat_max: FixedDecimal = cert_nat_max("praos")?;
+    let c: Option<FixedDecimal> = leader_c(shelley)?;
+
+    // Calculate all of our assigned slots in the epoch (in parallel)
+    let mut assigned_slots = (0..shelley.epoch_length)
+        .par_bridge() // <--- use rayon parallel bridge
+        .map(|slot_in_epoch| first_slot_of_epoch + slot_in_epoch)
+        .filter(|epoch_slot| !is_overlay_slot(&first_slot_of_epoch, epoch_slot, &d))
+        .filter_map(|leader_slot| {
+            match leading_consensus(
+                leader_slot,
+                sigma,
+                epoch_nonce,
+                pool_vrf_skey,
+                candidates,
+                &tpraos_cert_nat_max,
+                &praos_cert_nat_max,
+                &c,
+            ) {
+                Ok(leading) if leading.is_empty() => None,
+                Ok(leading) => Some((leader_slot, leading)),
+                Err(msg) => {
+                    handle_error(msg);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    assigned_slots.sort_by_key(|(leader_slot, _)| *leader_slot);
+    Ok(assigned_slots)
+}
+
+/// The inputs of a leaderlog run, as given on the command line
+pub(crate) struct LeaderlogOptions<'a> {
+    pub(crate) db_path: &'a Path,
+    pub(crate) byron_genesis: &'a Path,
+    pub(crate) shelley_genesis: &'a Path,
+    pub(crate) pool_stake: u64,
+    pub(crate) active_stake: u64,
+    pub(crate) d: f64,
+    pub(crate) extra_entropy: &'a Option<String>,
+    pub(crate) ledger_set: &'a LedgerSet,
+    pub(crate) pool_id: &'a PoolId,
+    pub(crate) pool_vrf_skey_path: &'a Path,
+    pub(crate) timezone: &'a str,
+    pub(crate) consensus: &'a str,
+    pub(crate) shelley_transition_epoch: &'a Option<u64>,
+    /// Calculate with this nonce instead of the one from the db
+    pub(crate) nonce: &'a Option<String>,
+    pub(crate) epoch: &'a Option<u64>,
+    /// Take d and the extra entropy from the protocol updates captured by sync
+    pub(crate) protocol_params_from_db: bool,
+    pub(crate) quiet: bool,
+    pub(crate) json_compact: bool,
+    pub(crate) busy_timeout_ms: u64,
+    pub(crate) read_only: bool,
+    pub(crate) backup_url: &'a Option<String>,
+    pub(crate) schedule_export: &'a Option<ScheduleExport>,
+    pub(crate) trusted_nonces: &'a [TrustedNonce],
+    pub(crate) reminder_lead_minutes: &'a Option<u64>,
+    pub(crate) output_format: &'a SlotsFormat,
+    pub(crate) bech32: bool,
+    pub(crate) format: &'a ReportFormat,
+    pub(crate) archive_dir: &'a Option<PathBuf>,
+    /// The vrf key hash registered for the pool, to check the current key against
+    pub(crate) pool_vrf_vkey_hash: &'a Option<String>,
+    pub(crate) pool_vrf_skey_history: &'a [VrfKeyVersion],
+}
+
+/// Check the files every leaderlog and nonce calculation reads
+fn check_input_paths(db_path: &Path, byron_genesis: &Path, shelley_genesis: &Path) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Path: --db {}",
+            db_path.to_string_lossy()
+        )));
+    }
+
+    if !byron_genesis.exists() {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Path: --byron-genesis {}",
+            byron_genesis.to_string_lossy()
+        )));
+    }
+
+    if !shelley_genesis.exists() {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Path: --shelley-genesis {}",
+            shelley_genesis.to_string_lossy()
+        )));
+    }
+    Ok(())
+}
+
+/// Calculate the slots a pool leads in an epoch, report them and save them for sendslots. Returns how many there are.
+pub(crate) fn calculate_leader_logs(options: &LeaderlogOptions) -> Result<u64, Error> {
+    let LeaderlogOptions {
+        db_path,
+        byron_genesis,
+        shelley_genesis,
+        pool_stake,
+        active_stake,
+        d,
+        extra_entropy,
+        ledger_set,
+        pool_id,
+        pool_vrf_skey_path,
+        timezone,
+        consensus,
+        shelley_transition_epoch,
+        nonce,
+        epoch,
+        protocol_params_from_db,
+        quiet,
+        json_compact,
+        busy_timeout_ms,
+        read_only,
+        backup_url,
+        schedule_export,
+        trusted_nonces,
+        reminder_lead_minutes,
+        output_format,
+        bech32,
+        format,
+        archive_dir,
+        pool_vrf_vkey_hash,
+        pool_vrf_skey_history,
+    } = *options;
+    debug!("calculate_leader_logs() start");
+    let tz: Tz = parse_timezone(timezone)?;
+
+    check_input_paths(db_path, byron_genesis, shelley_genesis)?;
+
+    if pool_vrf_skey_history.is_empty() && !pool_vrf_skey_path.exists() {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Path: --pool_vrf_skey {}",
+            pool_vrf_skey_path.to_string_lossy()
+        )));
+    }
+
+    if consensus != "praos" && consensus != "tpraos" && consensus != "cpraos" && consensus != "all" {
+        return Err(Error::Leaderlog(format!("Invalid Consensus: --consensus {consensus}")));
+    }
+
+    let block_store = blockstore::open_with_busy_timeout(db_path, busy_timeout_ms, read_only)?;
+
+    let byron = read_byron_genesis(byron_genesis)?;
+    debug!("{:?}", byron);
+
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    debug!("{:?}", shelley);
+
+    let mut block_store: Box<dyn BlockStore + Send> = Box::new(CachedBlockStore::new(
+        db_path,
+        block_store,
+        rollback_window(&byron, &shelley),
+    ));
+
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
     };
 
-    if is_just_nonce {
-        println!("{}", hex::encode(epoch_nonce));
-        return Ok(());
+    let mut ledger_info =
+        calculate_ledger_state_sigma_d_and_extra_entropy(&pool_stake, &active_stake, &d, extra_entropy)?;
+
+    let now = Utc::now();
+    let target_epoch = target_epoch(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        shelley_transition_epoch,
+        ledger_set,
+        epoch,
+        nonce.is_some(),
+        now,
+    )?;
+    let TargetEpoch {
+        epoch,
+        first_slot_of_epoch,
+        current_epoch,
+        ..
+    } = target_epoch;
+
+    if protocol_params_from_db {
+        let (decentralization, extra_entropy) =
+            get_protocol_params_from_db(block_store.as_mut(), &byron, &shelley, shelley_transition_epoch, epoch)?;
+        debug!("decentralization from db: {}", decentralization);
+        debug!("extra_entropy from db: {:?}", &extra_entropy);
+        ledger_info.decentralization = decentralization;
+        ledger_info.extra_entropy = extra_entropy;
     }
 
+    // the protocol version only ever goes up, so the one at the db tip is only known to apply from the current epoch on
+    let protocol_major_version = match block_store.get_tip_protocol_version()? {
+        Some((protocol_major_version, _)) if epoch >= current_epoch => Some(protocol_major_version),
+        _ => None,
+    };
+    let mut warnings = check_consensus_params(
+        consensus,
+        ledger_info.decentralization,
+        &ledger_info.extra_entropy,
+        protocol_major_version,
+    )?;
+    for warning in warnings.iter() {
+        warn!("{}", warning);
+    }
+    // already logged when the genesis files were read
+    warnings.extend(
+        byron
+            .coercions
+            .iter()
+            .map(|coercion| format!("byron genesis: {coercion}"))
+            .chain(
+                shelley
+                    .coercions
+                    .iter()
+                    .map(|coercion| format!("shelley genesis: {coercion}")),
+            ),
+    );
+
+    let epoch_nonce = leaderlog_nonce(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        shelley_transition_epoch,
+        &target_epoch,
+        consensus,
+        &ledger_info.extra_entropy,
+        nonce,
+        now,
+    )?;
+
+    check_trusted_nonce(trusted_nonces, shelley.network_magic, epoch, &hex::encode(epoch_nonce))?;
+
     debug!("epoch_nonce: {}", hex::encode(epoch_nonce));
 
+    // with a key history the key registered in the epoch, the registered key hash is only that of the current one
+    let (pool_vrf_skey_path, is_current_key) = if pool_vrf_skey_history.is_empty() {
+        (pool_vrf_skey_path, true)
+    } else {
+        let vrf_key_version = select_vrf_key(pool_vrf_skey_history, epoch)?;
+        info!(
+            "Using VRF key {} for epoch {}",
+            vrf_key_version.skey.to_string_lossy(),
+            epoch
+        );
+        (vrf_key_version.skey.as_path(), vrf_key_version.to_epoch.is_none())
+    };
     let pool_vrf_skey = read_vrf_key(pool_vrf_skey_path)?;
     if pool_vrf_skey.key_type != "VrfSigningKey_PraosVRF" {
         return Err(Error::Leaderlog(
             "Pool VRF Skey must be of type: VrfSigningKey_PraosVRF".to_string(),
         ));
     }
+    if let (Some(pool_vrf_vkey_hash), true) = (pool_vrf_vkey_hash, is_current_key) {
+        check_vrf_skey_matches(&pool_vrf_skey, pool_id, pool_vrf_vkey_hash)?;
+    }
 
-    let sigma = FixedDecimal::from(ledger_info.sigma.0) / FixedDecimal::from(ledger_info.sigma.1);
+    let sigma = sigma(ledger_info.sigma.0, ledger_info.sigma.1);
     debug!("sigma: {}", &sigma);
     debug!("decentralization_param: {:?}", &ledger_info.decentralization);
 
     let d: f64 = (ledger_info.decentralization * 1000.0).round() / 1000.0;
     debug!("d: {:?}", &d);
 
-    let active_slots_coeff = (shelley.active_slots_coeff * 10000f64) as u64;
-    let active_slots_coeff = format!("{}000000000000000000000000000000", active_slots_coeff);
-    let active_slots_coeff = FixedDecimal::from_str(&active_slots_coeff.to_string(), DEFAULT_PRECISION)?;
-    debug!("active_slots_coeff: {}", &active_slots_coeff);
-
-    let d_multiplier = FixedDecimal::from(((1.0 - d) * 1000.0).round() as u64) / FixedDecimal::from(1000u64);
-    let epoch_slots_ideal = f64::from_str(
-        &(&sigma * &(&FixedDecimal::from(shelley.epoch_length) * &active_slots_coeff) * d_multiplier).to_string(),
-    )?;
-    let epoch_slots_ideal = (epoch_slots_ideal * 100.0).round() / 100.0;
+    let epoch_slots_ideal = epoch_slots_ideal(&shelley, &sigma, d)?;
 
+    let epoch_start = slot_to_naivedatetime(&byron, &shelley, first_slot_of_epoch, shelley_transition_epoch).and_utc();
+    let epoch_end = slot_to_naivedatetime(
+        &byron,
+        &shelley,
+        first_slot_of_epoch + shelley.epoch_length,
+        shelley_transition_epoch,
+    )
+    .and_utc();
     let mut leader_log = LeaderLog {
         status: "ok".to_string(),
         epoch,
+        epoch_start: epoch_start.with_timezone(&tz).to_rfc3339(),
+        epoch_start_utc: epoch_start.to_rfc3339_opts(SecondsFormat::Secs, true),
+        epoch_end: epoch_end.with_timezone(&tz).to_rfc3339(),
+        epoch_end_utc: epoch_end.to_rfc3339_opts(SecondsFormat::Secs, true),
         epoch_nonce: hex::encode(epoch_nonce),
         consensus: consensus.to_string(),
         epoch_slots: 0,
         epoch_slots_ideal,
         max_performance: 0.0,
-        pool_id: pool_id.to_string(),
+        pool_id: format_pool_id(pool_id.as_str(), bech32),
         sigma: f64::from_str(&sigma.to_string())?,
         active_stake: ledger_info.sigma.0,
         total_active_stake: ledger_info.sigma.1,
         d,
         f: shelley.active_slots_coeff,
+        warnings,
         assigned_slots: vec![],
     };
 
-    let cert_nat_max: FixedDecimal = match consensus {
-        "tpraos" => FixedDecimal::from_str("134078079299425970995740249982058461274793658205923933777235614437217640300735469768018742981669034276900318581864860508537538828119465699464336490060840960000000000000000000000000000000000", DEFAULT_PRECISION)?, // 2^512
-        "praos" | "cpraos" => FixedDecimal::from_str("1157920892373161954235709850086879078532699846656405640394575840079131296399360000000000000000000000000000000000", DEFAULT_PRECISION)?, // 2^256
-        _ => return Err(Error::Leaderlog(format!(
-            "Invalid Consensus: --consensus {consensus}"
-        )))
+    let candidates: Vec<&'static str> = match consensus {
+        "all" => vec!["tpraos", "praos", "cpraos"],
+        "tpraos" => vec!["tpraos"],
+        "cpraos" => vec!["cpraos"],
+        _ => vec!["praos"],
     };
-    let c: FixedDecimal = (FixedDecimal::from(1u64) - active_slots_coeff).ln();
-
-    // Calculate all of our assigned slots in the epoch (in parallel)
-    let assigned_slots = (0..shelley.epoch_length)
-        .par_bridge() // <--- use rayon parallel bridge
-        .map(|slot_in_epoch| first_slot_of_epoch + slot_in_epoch)
-        .filter(|epoch_slot| !is_overlay_slot(&first_slot_of_epoch, epoch_slot, &ledger_info.decentralization))
-        .filter_map(|leader_slot| match consensus {
-            "tpraos" => {
-                match is_slot_leader_tpraos(
-                    leader_slot,
-                    &sigma,
-                    epoch_nonce.as_slice(),
-                    &pool_vrf_skey.key,
-                    &cert_nat_max,
-                    &c,
-                ) {
-                    Ok(true) => Some(leader_slot),
-                    Ok(false) => None,
-                    Err(msg) => {
-                        handle_error(msg);
-                        None
-                    }
-                }
-            }
-            "praos" | "cpraos" => {
-                match is_slot_leader_praos(
-                    leader_slot,
-                    &sigma,
-                    epoch_nonce.as_slice(),
-                    &pool_vrf_skey.key,
-                    &cert_nat_max,
-                    &c,
-                ) {
-                    Ok(true) => Some(leader_slot),
-                    Ok(false) => None,
-                    Err(msg) => {
-                        handle_error(msg);
-                        None
-                    }
-                }
-            }
-            _ => panic!(),
-        })
-        .collect::<Vec<_>>();
+    let assigned_slots = leader_slots(
+        &shelley,
+        first_slot_of_epoch,
+        ledger_info.decentralization,
+        &sigma,
+        epoch_nonce.as_slice(),
+        &pool_vrf_skey.key,
+        &candidates,
+    )?;
 
-    // Update leader log with all assigned slots (sort first)
-    for (i, slot) in sorted(assigned_slots.iter()).enumerate() {
+    // Update leader log with all assigned slots
+    for (i, (slot, leading)) in assigned_slots.iter().enumerate() {
         let no = (i + 1) as u64;
+        let slot_time = slot_to_naivedatetime(&byron, &shelley, *slot, shelley_transition_epoch).and_utc();
         let slot = Slot {
             no,
             slot: *slot,
             slot_in_epoch: slot - first_slot_of_epoch,
             at: slot_to_timestamp(&byron, &shelley, *slot, &tz, shelley_transition_epoch),
+            at_utc: slot_time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            seconds_from_epoch_start: (slot - first_slot_of_epoch) * shelley.slot_length_ms / 1000,
+            relative: relative_time((slot_time - now).num_seconds()),
+            reminder_at: reminder_lead_minutes.map(|reminder_lead_minutes| {
+                (slot_time - TimeDelta::minutes(reminder_lead_minutes as i64))
+                    .with_timezone(&tz)
+                    .to_rfc3339()
+            }),
+            consensus: (consensus == "all").then(|| {
+                leading
+                    .iter()
+                    .map(|leading_consensus| leading_consensus.to_string())
+                    .collect()
+            }),
         };
 
         debug!("Found assigned slot: {:?}", &slot);
@@ -710,40 +1374,684 @@ pub(crate) fn calculate_leader_logs(
     }
 
     // Calculate expected performance
-    leader_log.max_performance = (leader_log.epoch_slots as f64 / epoch_slots_ideal * 10000.0).round() / 100.0;
+    leader_log.max_performance = max_performance(leader_log.epoch_slots, epoch_slots_ideal);
 
     // Save slots to database so we can send to pooltool later
-    let mut slots = String::new();
-    slots.push('[');
-    for (i, assigned_slot) in leader_log.assigned_slots.iter().enumerate() {
-        if i > 0 {
-            slots.push(',');
+    let (slots, hash) = slots_and_hash(leader_log.assigned_slots.iter().map(|assigned_slot| assigned_slot.slot));
+
+    if read_only {
+        warn!(
+            "Database opened read-only, slots for epoch {} are not saved for sendslots",
+            epoch
+        );
+    } else if consensus == "all" {
+        warn!(
+            "Slots for epoch {} are not saved for sendslots with --consensus all, run leaderlog again with the consensus that applies",
+            epoch
+        );
+    } else {
+        let inputs = SlotsInputs {
+            nonce: leader_log.epoch_nonce.clone(),
+            sigma_numerator: ledger_info.sigma.0,
+            sigma_denominator: ledger_info.sigma.1,
+            d,
+            consensus: consensus.to_string(),
+            cncli_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        block_store.save_slots(
+            epoch,
+            pool_id,
+            assigned_slots.len() as u64,
+            slots.as_str(),
+            &hash,
+            Some(&inputs),
+        )?;
+        // keep the stake the schedule was calculated with so stake-history can show it per epoch
+        block_store.save_stake_history(&StakeHistory {
+            epoch,
+            pool_id: pool_id.to_string(),
+            pool_stake: ledger_info.sigma.0,
+            active_stake: ledger_info.sigma.1,
+            sigma: leader_log.sigma,
+        })?;
+    }
+
+    if let Some(backup_url) = backup_url {
+        // keep the previous epoch's chain too so a restored db can calculate the next nonce
+        let first_chain_slot = first_slot_of_epoch.saturating_sub(shelley.epoch_length);
+        if let Err(error) = upload_backup(&mut block_store, backup_url, epoch, first_chain_slot) {
+            error!("Backup error: {}", error);
         }
-        slots.push_str(&assigned_slot.slot.to_string())
     }
-    slots.push(']');
 
-    let hash = Hasher::<256>::hash(slots.as_bytes()).to_string();
+    if let Some(archive_dir) = archive_dir {
+        let path = archive::write_archive(archive_dir, pool_id, &leader_log)?;
+        info!("Archived the leaderlog to {}", path.display());
+    }
 
-    block_store.save_slots(epoch, pool_id, assigned_slots.len() as u64, slots.as_str(), &hash)?;
+    if !quiet {
+        if let Some(schedule_export) = schedule_export {
+            let slot_times: Vec<(u64, DateTime<Utc>)> = leader_log
+                .assigned_slots
+                .iter()
+                .map(|assigned_slot| {
+                    (
+                        assigned_slot.slot,
+                        slot_to_naivedatetime(&byron, &shelley, assigned_slot.slot, shelley_transition_epoch).and_utc(),
+                    )
+                })
+                .collect();
+            reporter::write_report(
+                render_schedule(schedule_export, epoch, pool_id.as_str(), &slot_times, Utc::now()).as_bytes(),
+            )?;
+        } else {
+            report_leader_log(&leader_log, json_compact, output_format, format)?;
+        }
+    }
+    runstate::record(
+        "leaderlog",
+        Some(pool_id.as_str()),
+        Some(epoch),
+        format!("{} slots", leader_log.epoch_slots),
+    );
 
-    println!("{}", serde_json::to_string_pretty(&leader_log)?);
+    Ok(leader_log.epoch_slots)
+}
 
+fn report_leader_log(
+    leader_log: &LeaderLog,
+    json_compact: bool,
+    output_format: &SlotsFormat,
+    format: &ReportFormat,
+) -> Result<(), Error> {
+    if let SlotsFormat::CardanoCli = output_format {
+        let cardano_cli_slots: Vec<CardanoCliSlot> = leader_log
+            .assigned_slots
+            .iter()
+            .map(|assigned_slot| CardanoCliSlot {
+                slot_number: assigned_slot.slot,
+                slot_time: assigned_slot.at_utc.clone(),
+            })
+            .collect();
+        if json_compact {
+            reporter::report_compact(&cardano_cli_slots)?;
+        } else {
+            reporter::report(&cardano_cli_slots)?;
+        }
+    } else if *format == ReportFormat::Human {
+        reporter::write_report(render_leader_log_human(leader_log, human::color_enabled()).as_bytes())?;
+    } else if json_compact {
+        reporter::report_compact(leader_log)?;
+    } else {
+        reporter::report(leader_log)?;
+    }
     Ok(())
 }
 
-pub(crate) fn status(db_path: &Path, byron_genesis: &Path, shelley_genesis: &Path, shelley_trans_epoch: &Option<u64>) {
-    if !db_path.exists() {
-        handle_error("database not found!");
-        return;
+/// The leaderlog as a summary followed by a table of the assigned slots. Slots still to come are green.
+fn render_leader_log_human(leader_log: &LeaderLog, color_enabled: bool) -> String {
+    let mut summary = Table::key_value();
+    summary
+        .row(vec![Cell::plain("Epoch"), Cell::plain(leader_log.epoch)])
+        .row(vec![
+            Cell::plain("Epoch time"),
+            Cell::plain(format!("{} to {}", leader_log.epoch_start, leader_log.epoch_end)),
+        ])
+        .row(vec![Cell::plain("Epoch nonce"), Cell::plain(&leader_log.epoch_nonce)])
+        .row(vec![Cell::plain("Consensus"), Cell::plain(&leader_log.consensus)])
+        .row(vec![Cell::plain("Pool id"), Cell::plain(&leader_log.pool_id)])
+        .row(vec![Cell::plain("Sigma"), Cell::plain(leader_log.sigma)])
+        .row(vec![Cell::plain("d"), Cell::plain(leader_log.d)])
+        .row(vec![
+            Cell::plain("Assigned slots"),
+            Cell::plain(format!(
+                "{} of {} ideal, {}% max performance",
+                leader_log.epoch_slots, leader_log.epoch_slots_ideal, leader_log.max_performance
+            )),
+        ]);
+    let mut out = summary.render(color_enabled);
+    for warning in leader_log.warnings.iter() {
+        out.push_str(&human::paint(
+            &format!("Warning: {warning}"),
+            Some(Color::Yellow),
+            color_enabled,
+        ));
+        out.push('\n');
     }
-    // check if db_path is a redb database based on magic number
-    let use_redb = is_redb_database(db_path).expect("infallible");
+    if leader_log.assigned_slots.is_empty() {
+        return out;
+    }
+
+    let with_consensus = leader_log.consensus == "all";
+    let mut headers = vec!["No", "Slot", "Slot in epoch", "At", "Relative"];
+    if with_consensus {
+        headers.push("Consensus");
+    }
+    let mut slots = Table::new(&headers);
+    for assigned_slot in leader_log.assigned_slots.iter() {
+        let relative = if assigned_slot.relative.starts_with("in ") {
+            Cell::colored(&assigned_slot.relative, Color::Green)
+        } else {
+            Cell::plain(&assigned_slot.relative)
+        };
+        let mut row = vec![
+            Cell::plain(assigned_slot.no),
+            Cell::plain(assigned_slot.slot),
+            Cell::plain(assigned_slot.slot_in_epoch),
+            Cell::plain(&assigned_slot.at),
+            relative,
+        ];
+        if with_consensus {
+            row.push(Cell::plain(
+                assigned_slot.consensus.as_deref().unwrap_or_default().join(","),
+            ));
+        }
+        slots.row(row);
+    }
+    out.push('\n');
+    out.push_str(&slots.render(color_enabled));
+    out
+}
 
-    let mut block_store: Box<dyn BlockStore + Send> = if use_redb {
-        Box::new(RedbBlockStore::new(db_path).expect("infallible"))
+/// The consensus the blocks at the db tip were made under, praos for an empty db
+fn tip_consensus(block_store: &mut dyn BlockStore) -> Result<&'static str, Error> {
+    Ok(match block_store.get_tip_protocol_version()? {
+        Some((protocol_major_version, _)) => consensus_for_protocol_version(protocol_major_version),
+        None => "praos",
+    })
+}
+
+// how far epoch_consensus looks back at a time for the last block of an epoch, a day on mainnet
+const EPOCH_CONSENSUS_SCAN_SLOTS: u64 = 86400;
+
+/// The consensus the blocks of the epoch before the one starting at first_slot_of_epoch were made under. That epoch
+/// holds the stability window, so it decides the rules of the nonce. Falls back to the db tip when none of its blocks
+/// are synced.
+pub(crate) fn epoch_consensus(
+    block_store: &mut dyn BlockStore,
+    shelley: &ShelleyGenesis,
+    first_slot_of_epoch: u64,
+) -> Result<&'static str, Error> {
+    let first_slot_of_prev_epoch = first_slot_of_epoch.saturating_sub(shelley.epoch_length);
+    // the last block of the epoch is usually close to its end, so look back from there a chunk at a time
+    let mut last_slot = first_slot_of_epoch.saturating_sub(1);
+    loop {
+        let first_slot = last_slot
+            .saturating_sub(EPOCH_CONSENSUS_SCAN_SLOTS - 1)
+            .max(first_slot_of_prev_epoch);
+        let block_details = block_store.get_block_details_in_slot_range(first_slot, last_slot)?;
+        if let Some(block_detail) = block_details.iter().rev().find(|block_detail| !block_detail.orphaned) {
+            return Ok(consensus_for_protocol_version(block_detail.protocol_major_version));
+        }
+        if first_slot <= first_slot_of_prev_epoch {
+            break;
+        }
+        last_slot = first_slot - 1;
+    }
+    tip_consensus(block_store)
+}
+
+/// Calculate the nonce of the epoch starting at first_slot_of_epoch from the synced blocks. The db must hold the
+/// blocks up to the stability window of the previous epoch.
+pub(crate) fn epoch_nonce_from_db(
+    block_store: &mut dyn BlockStore,
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    first_slot_of_epoch: u64,
+    consensus: &str,
+    extra_entropy: &Option<String>,
+) -> Result<Hash<32>, Error> {
+    let first_slot_of_prev_epoch = first_slot_of_epoch - shelley.epoch_length;
+    debug!("first_slot_of_epoch: {}", first_slot_of_epoch);
+    debug!("first_slot_of_prev_epoch: {}", first_slot_of_prev_epoch);
+    debug!("nonce_consensus: {}", consensus);
+    let stability_window_start = stability_window_start(byron, shelley, first_slot_of_epoch, consensus);
+    debug!("stability_window_start: {}", stability_window_start);
+    let stability_window_start_plus_1_min = stability_window_start + 60;
+
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    if tip_slot_number < stability_window_start_plus_1_min {
+        return Err(Error::Leaderlog(format!(
+            "Not enough blocks sync'd to calculate! Try again later after slot {stability_window_start_plus_1_min} is sync'd."
+        )));
+    }
+
+    let nc: Hash<32> = block_store.get_eta_v_before_slot(stability_window_start)?;
+    debug!("nc: {}", nc);
+
+    let nh: Hash<32> = block_store.get_prev_hash_before_slot(first_slot_of_prev_epoch)?;
+    debug!("nh: {}", nh);
+
+    debug!("extra_entropy: {:?}", extra_entropy);
+    let extra_entropy_vec: Option<Vec<u8>> = extra_entropy.as_ref().map(hex::decode).transpose()?;
+    Ok(generate_epoch_nonce(nc, nh, extra_entropy_vec.as_deref()))
+}
+
+/// The slot the candidate nonce of the epoch starting at first_slot_of_epoch stops changing at, 3k/f slots before
+/// the epoch, or 4k/f with cpraos
+fn stability_window_start(
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    first_slot_of_epoch: u64,
+    consensus: &str,
+) -> u64 {
+    let stability_window_multiplier = match consensus {
+        "cpraos" => 4u64,
+        _ => 3u64,
+    };
+    let stability_window = slots_for_blocks(byron, shelley, stability_window_multiplier);
+    debug!("stability_window: {}", stability_window);
+    first_slot_of_epoch - stability_window
+}
+
+/// The slots the chain takes to grow by multiplier * k blocks, multiplier * k/f rounded up
+fn slots_for_blocks(byron: &ByronGenesis, shelley: &ShelleyGenesis, multiplier: u64) -> u64 {
+    ((multiplier * byron.protocol_consts.k) as f64 / shelley.active_slots_coeff).ceil() as u64
+}
+
+/// The slots behind the tip a rollback can reach, 3k/f. Passed to CachedBlockStore, results for older slots are final.
+pub(crate) fn rollback_window(byron: &ByronGenesis, shelley: &ShelleyGenesis) -> u64 {
+    slots_for_blocks(byron, shelley, 3)
+}
+
+/// The next epoch nonce as far as the chain got. It is only final from final_slot on, before that every block
+/// until the stability window starts changes it.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NoncePrediction {
+    status: String,
+    epoch: u64,
+    nonce: String,
+    #[serde(rename = "final")]
+    is_final: bool,
+    tip_slot_number: u64,
+    /// The slot from which the nonce no longer changes and `cncli nonce --ledger-set next` calculates it
+    final_slot: u64,
+    final_at: String,
+    slots_until_final: u64,
+}
+
+/// Predict the nonce of the epoch starting at first_slot_of_epoch from the candidate nonce at the db tip. Once the
+/// tip passed the stability window this is the real nonce.
+pub(crate) fn predict_epoch_nonce(
+    block_store: &mut dyn BlockStore,
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    target_epoch: &TargetEpoch,
+    consensus: &str,
+    extra_entropy: &Option<String>,
+) -> Result<NoncePrediction, Error> {
+    let first_slot_of_epoch = target_epoch.first_slot_of_epoch;
+    let final_slot = stability_window_start(byron, shelley, first_slot_of_epoch, consensus) + 60;
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let is_final = tip_slot_number >= final_slot;
+    let nonce = if is_final {
+        epoch_nonce_from_db(
+            block_store,
+            byron,
+            shelley,
+            first_slot_of_epoch,
+            consensus,
+            extra_entropy,
+        )?
     } else {
-        Box::new(SqLiteBlockStore::new(db_path).expect("infallible"))
+        // the candidate nonce freezes at the stability window, until then it is the rolling nonce of the tip
+        let nc: Hash<32> = block_store.get_eta_v_before_slot(tip_slot_number + 1)?;
+        debug!("candidate nc: {}", nc);
+        let nh: Hash<32> = block_store.get_prev_hash_before_slot(first_slot_of_epoch - shelley.epoch_length)?;
+        debug!("nh: {}", nh);
+        let extra_entropy_vec: Option<Vec<u8>> = extra_entropy.as_ref().map(hex::decode).transpose()?;
+        generate_epoch_nonce(nc, nh, extra_entropy_vec.as_deref())
+    };
+
+    Ok(NoncePrediction {
+        status: "ok".to_string(),
+        epoch: target_epoch.epoch,
+        nonce: hex::encode(nonce),
+        is_final,
+        tip_slot_number,
+        final_slot,
+        final_at: slot_to_naivedatetime(byron, shelley, final_slot, shelley_transition_epoch)
+            .and_utc()
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        slots_until_final: final_slot.saturating_sub(tip_slot_number),
+    })
+}
+
+/// Calculate the nonce of the ledger set's epoch, or of --epoch, from the db and report it in hex
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calculate_nonce(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    extra_entropy: &Option<String>,
+    ledger_set: &LedgerSet,
+    consensus: &str,
+    shelley_transition_epoch: &Option<u64>,
+    epoch: &Option<u64>,
+    trusted_nonces: &[TrustedNonce],
+) -> Result<(), Error> {
+    check_input_paths(db_path, byron_genesis, shelley_genesis)?;
+    if consensus != "praos" && consensus != "tpraos" && consensus != "cpraos" && consensus != "all" {
+        return Err(Error::Leaderlog(format!("Invalid Consensus: --consensus {consensus}")));
+    }
+
+    let block_store = blockstore::open(db_path, true)?;
+    let byron = read_byron_genesis(byron_genesis)?;
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    let mut block_store: Box<dyn BlockStore + Send> = Box::new(CachedBlockStore::new(
+        db_path,
+        block_store,
+        rollback_window(&byron, &shelley),
+    ));
+    let shelley_transition_epoch =
+        shelley_transition_epoch.unwrap_or_else(|| guess_shelley_transition_epoch(shelley.network_magic));
+
+    let now = Utc::now();
+    let target_epoch = target_epoch(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        shelley_transition_epoch,
+        ledger_set,
+        epoch,
+        false,
+        now,
+    )?;
+    // the protocol version only ever goes up, so the one at the db tip is only known to apply from the current epoch on
+    let protocol_major_version = match block_store.get_tip_protocol_version()? {
+        Some((protocol_major_version, _)) if target_epoch.epoch >= target_epoch.current_epoch => {
+            Some(protocol_major_version)
+        }
+        _ => None,
+    };
+    for warning in check_consensus_params(consensus, 0.0, extra_entropy, protocol_major_version)? {
+        warn!("{}", warning);
+    }
+
+    let epoch_nonce = leaderlog_nonce(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        shelley_transition_epoch,
+        &target_epoch,
+        consensus,
+        extra_entropy,
+        &None,
+        now,
+    )?;
+    check_trusted_nonce(
+        trusted_nonces,
+        shelley.network_magic,
+        target_epoch.epoch,
+        &hex::encode(epoch_nonce),
+    )?;
+    reporter::write_report(format!("{}\n", hex::encode(epoch_nonce)).as_bytes())?;
+    Ok(())
+}
+
+/// Report the best estimate of the next epoch nonce from the db, final or not
+pub(crate) fn predict_nonce(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    consensus: &str,
+    extra_entropy: &Option<String>,
+) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::Leaderlog("database not found!".to_string()));
+    }
+    if consensus != "praos" && consensus != "tpraos" && consensus != "cpraos" && consensus != "all" {
+        return Err(Error::Leaderlog(format!("Invalid Consensus: --consensus {consensus}")));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+    let byron = read_byron_genesis(byron_genesis)?;
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    let shelley_transition_epoch =
+        shelley_transition_epoch.unwrap_or_else(|| guess_shelley_transition_epoch(shelley.network_magic));
+
+    let now = Utc::now();
+    let target_epoch = target_epoch(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        shelley_transition_epoch,
+        &LedgerSet::Mark,
+        &None,
+        false,
+        now,
+    )?;
+    // a prediction from a stale db is worse than none
+    let tip_time = slot_to_naivedatetime(&byron, &shelley, target_epoch.tip_slot_number, shelley_transition_epoch)
+        .and_utc()
+        .timestamp();
+    if now.timestamp() - tip_time > 900 {
+        return Err(Error::Leaderlog(format!(
+            "db not fully synced! system_time: {}, tip_time: {tip_time}",
+            now.timestamp()
+        )));
+    }
+    let consensus = match consensus {
+        "all" => epoch_consensus(block_store.as_mut(), &shelley, target_epoch.first_slot_of_epoch)?,
+        _ => consensus,
+    };
+
+    let prediction = predict_epoch_nonce(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        shelley_transition_epoch,
+        &target_epoch,
+        consensus,
+        extra_entropy,
+    )?;
+    if !prediction.is_final {
+        warn!(
+            "The nonce of epoch {} is not final, it can change with every block for another {} slots",
+            prediction.epoch, prediction.slots_until_final
+        );
+    }
+    reporter::report(&prediction)?;
+    Ok(())
+}
+
+/// Determine the d and extra entropy parameters in effect for an epoch, starting from the shelley genesis and applying
+/// the update proposals stored during sync. A proposal is enacted when a quorum of genesis delegates proposed it. It
+/// takes effect at the start of the epoch after the one it was submitted in, or one epoch later when it was submitted
+/// in the last 2 * 3k/f slots of its epoch, after the ledger stops accepting proposals for the next epoch.
+pub(crate) fn get_protocol_params_from_db(
+    block_store: &mut dyn BlockStore,
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    epoch: u64,
+) -> Result<(f64, Option<String>), Error> {
+    let mut decentralization = shelley.protocol_params.decentralisation_param;
+    let mut extra_entropy = match shelley.protocol_params.extra_entropy {
+        Some(GenesisNonce::Nonce(ref nonce)) => Some(nonce.clone()),
+        Some(GenesisNonce::NeutralNonce) | None => None,
+    };
+    let protocol_updates = block_store.get_protocol_updates_before_epoch(epoch)?;
+    if protocol_updates.is_empty() {
+        warn!(
+            "No protocol parameter updates found in db before epoch {epoch}, using the shelley genesis ones. Was it synced with --capture-protocol-updates?"
+        );
+    }
+
+    let too_late = slots_for_blocks(byron, shelley, 6);
+    for protocol_update in protocol_updates.iter() {
+        let (submitted_epoch, first_slot_of_submitted_epoch) =
+            get_first_slot_of_epoch(byron, shelley, protocol_update.slot_number, shelley_transition_epoch);
+        let too_late_slot = (first_slot_of_submitted_epoch + shelley.epoch_length).saturating_sub(too_late);
+        let enacted_epoch = if protocol_update.slot_number < too_late_slot {
+            submitted_epoch + 1
+        } else {
+            submitted_epoch + 2
+        };
+        if enacted_epoch > epoch {
+            continue;
+        }
+        // proposals saved before the votes were counted are taken as enacted
+        if let (Some(votes), Some(update_quorum)) = (protocol_update.votes, shelley.update_quorum) {
+            if votes < update_quorum {
+                debug!(
+                    "protocol update in slot {} has {} of {} votes, not enacted",
+                    protocol_update.slot_number, votes, update_quorum
+                );
+                continue;
+            }
+        }
+        if let Some((numerator, denominator)) = protocol_update.decentralization {
+            decentralization = numerator as f64 / denominator as f64;
+        }
+        if let Some(ref proposed_extra_entropy) = protocol_update.extra_entropy {
+            extra_entropy = Some(proposed_extra_entropy.clone()).filter(|extra_entropy| !extra_entropy.is_empty());
+        }
+    }
+
+    Ok((decentralization, extra_entropy))
+}
+
+/// Cross-check d, extra entropy and the consensus against each other and against the protocol major version of the
+/// target epoch when it is known. Combinations that cannot occur on chain are errors, since they would quietly give a
+/// wrong schedule. Combinations that only look like a mistake are returned as warnings.
+fn check_consensus_params(
+    consensus: &str,
+    d: f64,
+    extra_entropy: &Option<String>,
+    protocol_major_version: Option<u64>,
+) -> Result<Vec<String>, Error> {
+    let mut warnings: Vec<String> = vec![];
+    if let Some(protocol_major_version) = protocol_major_version {
+        let era = era_for_protocol_version(protocol_major_version);
+        let expected_consensus = consensus_for_protocol_version(protocol_major_version);
+        // Babbage removed d and extra entropy from the protocol parameters along with tpraos
+        if expected_consensus != "tpraos" {
+            if d > 0.0 {
+                return Err(Error::Leaderlog(format!(
+                    "Invalid Parameters: --d {d} but the db tip is at protocol version {protocol_major_version} ({era}), which has no decentralization parameter"
+                )));
+            }
+            if extra_entropy.is_some() {
+                return Err(Error::Leaderlog(format!(
+                    "Invalid Parameters: --extra-entropy is set but the db tip is at protocol version {protocol_major_version} ({era}), which has no extra entropy"
+                )));
+            }
+            if consensus == "tpraos" {
+                return Err(Error::Leaderlog(format!(
+                    "Invalid Consensus: --consensus tpraos but the db tip is at protocol version {protocol_major_version} ({era}), use --consensus {expected_consensus}"
+                )));
+            }
+        }
+        if consensus != "all" && consensus != expected_consensus {
+            warnings.push(format!(
+                "--consensus {consensus} but the db tip is at protocol version {protocol_major_version} ({era}), which uses {expected_consensus}"
+            ));
+        }
+    }
+    if consensus == "praos" || consensus == "cpraos" {
+        if d > 0.0 {
+            warnings.push(format!(
+                "--d {d} with --consensus {consensus}, only tpraos epochs have overlay slots"
+            ));
+        }
+        if extra_entropy.is_some() {
+            warnings.push(format!(
+                "--extra-entropy with --consensus {consensus}, only tpraos epochs have extra entropy"
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NonceTrace {
+    status: String,
+    first_slot: u64,
+    last_slot: u64,
+    starting_eta_v: String,
+    blocks: Vec<NonceTraceBlock>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NonceTraceBlock {
+    block_number: u64,
+    slot_number: u64,
+    hash: String,
+    eta_vrf_0: String,
+    eta_v: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    db_eta_v_mismatch: bool,
+}
+
+/// Print each block's contribution to the rolling nonce (eta_v) between two slots. The eta_v is recomputed from
+/// the stored eta_vrf_0 values and any block where the db holds a different eta_v is flagged.
+pub(crate) fn trace_nonce(db_path: &Path, first_slot: u64, last_slot: u64) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::Leaderlog("database not found!".to_string()));
+    }
+    if last_slot < first_slot {
+        return Err(Error::Leaderlog(format!(
+            "--to-slot {} is before --trace-from-slot {}",
+            last_slot, first_slot
+        )));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let starting_eta_v = block_store.get_eta_v_before_slot(first_slot)?;
+    let mut eta_v = starting_eta_v;
+    let mut blocks: Vec<NonceTraceBlock> = vec![];
+    for block in block_store
+        .get_blocks_in_slot_range(first_slot, last_slot)?
+        .into_iter()
+        .filter(|block| !block.orphaned)
+    {
+        eta_v = generate_rolling_nonce(eta_v, &hex::decode(&block.eta_vrf_0)?);
+        let computed_eta_v = hex::encode(eta_v);
+        blocks.push(NonceTraceBlock {
+            block_number: block.block_number,
+            slot_number: block.slot_number,
+            hash: block.hash,
+            eta_vrf_0: block.eta_vrf_0,
+            db_eta_v_mismatch: computed_eta_v != block.eta_v,
+            eta_v: computed_eta_v,
+        });
+    }
+
+    reporter::report(&NonceTrace {
+        status: "ok".to_string(),
+        first_slot,
+        last_slot,
+        starting_eta_v: hex::encode(starting_eta_v),
+        blocks,
+    })?;
+
+    Ok(())
+}
+
+pub(crate) fn status(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_trans_epoch: &Option<u64>,
+    busy_timeout_ms: u64,
+    read_only: bool,
+    format: &ReportFormat,
+) {
+    if !db_path.exists() {
+        handle_format_error(format, "database not found!");
+        return;
+    }
+    let mut block_store = match blockstore::open_with_busy_timeout(db_path, busy_timeout_ms, read_only) {
+        Ok(block_store) => block_store,
+        Err(error) => {
+            handle_format_error(format, error);
+            return;
+        }
     };
 
     match read_byron_genesis(byron_genesis) {
@@ -764,22 +2072,56 @@ pub(crate) fn status(db_path: &Path, byron_genesis: &Path, shelley_genesis: &Pat
                                     .and_utc()
                                     .timestamp();
                             let system_time = Utc::now().timestamp();
-                            if system_time - tip_time < 120 {
-                                print_status_synced();
-                            } else {
-                                handle_error("db not fully synced!")
+                            let tip_age = system_time - tip_time;
+                            match format {
+                                // a table for a person at a terminal shows how far behind the db is instead of
+                                // failing
+                                ReportFormat::Human => match block_store.get_tip_protocol_version() {
+                                    Ok(protocol_version) => {
+                                        print_status_human(tip_slot_number, tip_age, protocol_version)
+                                    }
+                                    Err(error) => human::report_error(error),
+                                },
+                                ReportFormat::Json if tip_age < SYNCED_TIP_AGE_SECS => {
+                                    match block_store.get_tip_protocol_version() {
+                                        Ok(protocol_version) => print_status_synced(protocol_version),
+                                        Err(error) => handle_error(error),
+                                    }
+                                }
+                                ReportFormat::Json => handle_error("db not fully synced!"),
                             }
                         }
-                        Err(error) => handle_error(error),
+                        Err(error) => handle_format_error(format, error),
                     }
                 }
-                Err(error) => handle_error(error),
+                Err(error) => handle_format_error(format, error),
             }
         }
-        Err(error) => handle_error(error),
+        Err(error) => handle_format_error(format, error),
     }
 }
 
+/// What sendslots --dry-run would send to pooltool for a pool, with the leaderlog inputs saved with the slots
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SendSlotsPreview {
+    pool_id: PoolId,
+    slot_qty: u64,
+    hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prev_slots: Option<String>,
+    #[serde(flatten)]
+    inputs: Option<SlotsInputs>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SendSlotsDryRun {
+    status: String,
+    epoch: u64,
+    pools: Vec<SendSlotsPreview>,
+}
+
 pub(crate) fn send_slots(
     db_path: &Path,
     byron_genesis: &Path,
@@ -787,18 +2129,18 @@ pub(crate) fn send_slots(
     pooltool_config: PooltoolConfig,
     shelley_trans_epoch: &Option<u64>,
     override_time: &Option<String>,
+    dry_run: bool,
 ) {
     if !db_path.exists() {
         handle_error("database not found!");
         return;
     }
-    // check if db_path is a redb database based on magic number
-    let use_redb = is_redb_database(db_path).expect("infallible");
-
-    let mut block_store: Box<dyn BlockStore + Send> = if use_redb {
-        Box::new(RedbBlockStore::new(db_path).expect("infallible"))
-    } else {
-        Box::new(SqLiteBlockStore::new(db_path).expect("infallible"))
+    let mut block_store = match blockstore::open(db_path, true) {
+        Ok(block_store) => block_store,
+        Err(error) => {
+            handle_error(error);
+            return;
+        }
     };
 
     match read_byron_genesis(byron_genesis) {
@@ -823,52 +2165,54 @@ pub(crate) fn send_slots(
                                 let (epoch, _) =
                                     get_first_slot_of_epoch(&byron, &shelley, tip_slot_number, shelley_trans_epoch);
                                 debug!("epoch: {}", epoch);
+                                let mut previews: Vec<SendSlotsPreview> = vec![];
                                 for pool in pooltool_config.pools.iter() {
-                                    match block_store.get_current_slots(epoch, &pool.pool_id) {
-                                        Ok((slot_qty, hash)) => {
+                                    match block_store.get_slots_to_send(epoch, &pool.pool_id) {
+                                        Ok(((slot_qty, hash), prev_slots)) => {
                                             debug!("slot_qty: {}", slot_qty);
                                             debug!("hash: {}", &hash);
-                                            match block_store.get_previous_slots(epoch - 1, &pool.pool_id) {
-                                                Ok(prev_slots) => {
-                                                    let request = serde_json::ser::to_string(&PooltoolSendSlots {
-                                                        api_key: pooltool_config.api_key.clone(),
+                                            if dry_run {
+                                                match block_store.get_slots_inputs(epoch, &pool.pool_id) {
+                                                    Ok(inputs) => previews.push(SendSlotsPreview {
                                                         pool_id: pool.pool_id.clone(),
-                                                        epoch,
                                                         slot_qty,
                                                         hash,
-                                                        override_time: override_time.clone(),
                                                         prev_slots,
-                                                    })
-                                                    .unwrap();
-                                                    info!("Sending: {}", &request);
-                                                    match reqwest::blocking::Client::builder().build() {
-                                                        Ok(client) => {
-                                                            let pooltool_result = client
-                                                                .post("https://api.pooltool.io/v0/sendslots")
-                                                                .body(request)
-                                                                .send();
-
-                                                            match pooltool_result {
-                                                                Ok(response) => match response.text() {
-                                                                    Ok(text) => {
-                                                                        info!("Pooltool Response: {}", text);
-                                                                    }
-                                                                    Err(error) => {
-                                                                        error!("PoolTool error: {}", error);
-                                                                    }
-                                                                },
-                                                                Err(error) => {
-                                                                    error!("PoolTool error: {}", error);
-                                                                }
-                                                            }
-                                                        }
-                                                        Err(err) => {
-                                                            error!("Could not set up the reqwest client!: {}", err)
-                                                        }
-                                                    }
+                                                        inputs,
+                                                    }),
+                                                    Err(error) => error!(
+                                                        "Cannot find db record for {},{}: {}",
+                                                        epoch, &pool.pool_id, error
+                                                    ),
+                                                }
+                                                continue;
+                                            }
+                                            let request = SendSlotsRequest {
+                                                api_key: pooltool_config.api_key.clone(),
+                                                pool_id: pool.pool_id.clone(),
+                                                epoch,
+                                                slot_qty,
+                                                hash,
+                                                override_time: override_time.clone(),
+                                                prev_slots,
+                                            };
+                                            info!(
+                                                "Sending slots for epoch {}, pool {}: {}",
+                                                epoch, &pool.pool_id, slot_qty
+                                            );
+                                            match PooltoolClient::new().and_then(|client| client.send_slots(&request)) {
+                                                Ok(response) => {
+                                                    let message = response.message.unwrap_or_default();
+                                                    info!("Pooltool Response: {}", message);
+                                                    runstate::record(
+                                                        "sendslots",
+                                                        Some(pool.pool_id.as_str()),
+                                                        Some(epoch),
+                                                        message,
+                                                    );
                                                 }
                                                 Err(error) => {
-                                                    error!("Db Error: {}", error)
+                                                    error!("PoolTool error: {}", error);
                                                 }
                                             }
                                         }
@@ -877,6 +2221,15 @@ pub(crate) fn send_slots(
                                         }
                                     }
                                 }
+                                if dry_run {
+                                    if let Err(error) = reporter::report(&SendSlotsDryRun {
+                                        status: "ok".to_string(),
+                                        epoch,
+                                        pools: previews,
+                                    }) {
+                                        handle_error(error);
+                                    }
+                                }
                             } else {
                                 handle_error("db not fully synced!")
                             }
@@ -891,29 +2244,104 @@ pub(crate) fn send_slots(
     }
 }
 
-fn print_status_synced() {
-    println!(
-        "{{\n\
-            \x20\"status\": \"ok\"\n\
-            }}"
-    );
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusSynced {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    era: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protocol_major_version: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protocol_minor_version: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    consensus: Option<String>,
+}
+
+/// Name of the ledger era for a protocol major version
+fn era_for_protocol_version(protocol_major_version: u64) -> &'static str {
+    match protocol_major_version {
+        0..=1 => "Byron",
+        2 => "Shelley",
+        3 => "Allegra",
+        4 => "Mary",
+        5..=6 => "Alonzo",
+        7..=8 => "Babbage",
+        _ => "Conway",
+    }
+}
+
+/// The --consensus value leaderlog should use for blocks made with a protocol major version
+fn consensus_for_protocol_version(protocol_major_version: u64) -> &'static str {
+    match protocol_major_version {
+        0..=6 => "tpraos",
+        7..=8 => "praos",
+        _ => "cpraos",
+    }
+}
+
+fn print_status_human(tip_slot_number: u64, tip_age: i64, protocol_version: Option<(u64, u64)>) {
+    let synced = tip_age < SYNCED_TIP_AGE_SECS;
+    let color = if synced { Color::Green } else { Color::Red };
+    let mut table = Table::key_value();
+    table
+        .row(vec![
+            Cell::plain("Status"),
+            Cell::colored(if synced { "synced" } else { "not synced" }, color),
+        ])
+        .row(vec![Cell::plain("Tip slot"), Cell::plain(tip_slot_number)])
+        .row(vec![
+            Cell::plain("Tip age"),
+            Cell::colored(relative_time(-tip_age.max(0)), color),
+        ]);
+    if let Some((major, minor)) = protocol_version {
+        table
+            .row(vec![Cell::plain("Era"), Cell::plain(era_for_protocol_version(major))])
+            .row(vec![
+                Cell::plain("Protocol version"),
+                Cell::plain(format!("{major}.{minor}")),
+            ])
+            .row(vec![
+                Cell::plain("Consensus"),
+                Cell::plain(consensus_for_protocol_version(major)),
+            ]);
+    }
+    if let Err(error) = reporter::write_report(table.render(human::color_enabled()).as_bytes()) {
+        human::report_error(error);
+    }
+}
+
+fn print_status_synced(protocol_version: Option<(u64, u64)>) {
+    if let Err(error) = reporter::report(&StatusSynced {
+        status: "ok".to_string(),
+        era: protocol_version.map(|(major, _)| era_for_protocol_version(major).to_string()),
+        protocol_major_version: protocol_version.map(|(major, _)| major),
+        protocol_minor_version: protocol_version.map(|(_, minor)| minor),
+        consensus: protocol_version.map(|(major, _)| consensus_for_protocol_version(major).to_string()),
+    }) {
+        handle_error(error);
+    }
 }
 
 pub fn handle_error<T: Display>(error_message: T) {
-    serde_json::ser::to_writer_pretty(
-        &mut stdout(),
-        &LeaderLogError {
-            status: "error".to_string(),
-            error_message: format!("{error_message}"),
-        },
-    )
-    .unwrap();
+    reporter::report_error(error_message);
+}
+
+/// Report an error as JSON or, with --format human, as a line of text
+fn handle_format_error<T: Display>(format: &ReportFormat, error_message: T) {
+    match format {
+        ReportFormat::Json => handle_error(error_message),
+        ReportFormat::Human => human::report_error(error_message),
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use crate::nodeclient::leaderlog::is_overlay_slot;
     use chrono::{NaiveDateTime, Utc};
+    use pallas_math::math::{FixedDecimal, DEFAULT_PRECISION};
 
     #[test]
     fn test_is_overlay_slot() {
@@ -928,6 +2356,70 @@ mod tests {
         assert!(is_overlay_slot(&first_slot_of_epoch, &current_slot, &d));
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_check_key_permissions() {
+        use crate::nodeclient::leaderlog::check_key_permissions;
+        use std::os::unix::fs::PermissionsExt;
+
+        let key_path = std::env::temp_dir().join("cncli_test_vrf.skey");
+        std::fs::write(&key_path, "{}").unwrap();
+
+        std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(check_key_permissions(&key_path).unwrap().is_empty());
+
+        std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let warnings = check_key_permissions(&key_path).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("644"));
+
+        std::fs::remove_file(&key_path).unwrap();
+    }
+
+    #[test]
+    fn test_relative_time() {
+        assert_eq!(relative_time(0), "now");
+        assert_eq!(relative_time(45), "in 45s");
+        assert_eq!(relative_time(3 * 86400 + 4 * 3600 + 59), "in 3d 4h");
+        assert_eq!(relative_time(86400 + 30), "in 1d");
+        assert_eq!(relative_time(-(12 * 60 + 5)), "12m 5s ago");
+    }
+
+    #[test]
+    fn test_era_and_consensus_for_protocol_version() {
+        use crate::nodeclient::leaderlog::{consensus_for_protocol_version, era_for_protocol_version};
+
+        assert_eq!(era_for_protocol_version(6), "Alonzo");
+        assert_eq!(consensus_for_protocol_version(6), "tpraos");
+        assert_eq!(era_for_protocol_version(8), "Babbage");
+        assert_eq!(consensus_for_protocol_version(8), "praos");
+        assert_eq!(era_for_protocol_version(10), "Conway");
+        assert_eq!(consensus_for_protocol_version(10), "cpraos");
+    }
+
+    #[test]
+    fn test_check_consensus_params() {
+        use crate::nodeclient::leaderlog::check_consensus_params;
+
+        let extra_entropy = Some("d982e06fd33e7440b43cefad529b7ecafbaa255e38178ad4189a37e4ce9bf1fa".to_string());
+        assert!(check_consensus_params("tpraos", 0.32, &extra_entropy, Some(4))
+            .unwrap()
+            .is_empty());
+        assert!(check_consensus_params("cpraos", 0.0, &None, Some(10))
+            .unwrap()
+            .is_empty());
+        assert!(check_consensus_params("praos", 0.5, &None, Some(8)).is_err());
+        assert!(check_consensus_params("cpraos", 0.0, &extra_entropy, Some(10)).is_err());
+        assert!(check_consensus_params("tpraos", 0.0, &None, Some(9)).is_err());
+
+        let warnings = check_consensus_params("praos", 0.0, &None, Some(10)).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("uses cpraos"), "{}", warnings[0]);
+        // without a protocol version only the consensus itself is checked
+        let warnings = check_consensus_params("praos", 0.5, &extra_entropy, None).unwrap();
+        assert_eq!(warnings.len(), 2);
+    }
+
     #[test]
     fn test_date_parsing() {
         let genesis_start_time_sec = NaiveDateTime::parse_from_str("2022-10-25T00:00:00Z", "%Y-%m-%dT%H:%M:%S%.fZ")
@@ -975,4 +2467,434 @@ mod tests {
         let current_epoch = (current_time_sec - genesis_start_time_sec) / 432000;
         println!("current_epoch: {}", current_epoch);
     }
+
+    fn shelley_genesis(active_slots_coeff: &str) -> super::ShelleyGenesis {
+        serde_json::from_str(&format!(
+            r#"{{"activeSlotsCoeff": {active_slots_coeff}, "networkMagic": 42, "slotLength": 1, "epochLength": 500}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_active_slots_coeff() {
+        // f scaled by 10^34, the fixed decimal precision
+        for (active_slots_coeff, expected) in [
+            ("0.05", format!("5{}", "0".repeat(32))),
+            ("0.29", format!("29{}", "0".repeat(32))),
+            ("0.075", format!("75{}", "0".repeat(31))),
+            ("\"0.075\"", format!("75{}", "0".repeat(31))),
+            ("0.000123456789", format!("123456789{}", "0".repeat(22))),
+            ("1.0", format!("1{}", "0".repeat(34))),
+            ("1", format!("1{}", "0".repeat(34))),
+        ] {
+            let shelley = shelley_genesis(active_slots_coeff);
+            assert_eq!(
+                super::active_slots_coeff(&shelley).unwrap().to_string(),
+                FixedDecimal::from_str(&expected, DEFAULT_PRECISION)
+                    .unwrap()
+                    .to_string(),
+                "activeSlotsCoeff {active_slots_coeff}"
+            );
+        }
+        assert!(super::active_slots_coeff(&shelley_genesis("0")).is_err());
+        assert!(super::active_slots_coeff(&shelley_genesis("1.5")).is_err());
+    }
+
+    fn genesis(slot_duration: &str, k: u64, slot_length: &str) -> (super::ByronGenesis, super::ShelleyGenesis) {
+        let byron = serde_json::from_str(&format!(
+            r#"{{"startTime": 1506203091, "protocolConsts": {{"k": {k}}}, "blockVersionData": {{"slotDuration": "{slot_duration}"}}}}"#
+        ))
+        .unwrap();
+        let shelley = serde_json::from_str(&format!(
+            r#"{{"activeSlotsCoeff": 0.05, "networkMagic": 42, "slotLength": {slot_length}, "epochLength": 432000}}"#
+        ))
+        .unwrap();
+        (byron, shelley)
+    }
+
+    #[test]
+    fn test_slot_to_naivedatetime() {
+        // mainnet, the first shelley slot is 4492800
+        let (byron, shelley) = genesis("20000", 2160, "1");
+        for (slot, expected) in [
+            (0, "2017-09-23T21:44:51Z"),
+            (4492799, "2020-07-29T21:44:31Z"),
+            (4492800, "2020-07-29T21:44:51Z"),
+            (4492801, "2020-07-29T21:44:52Z"),
+        ] {
+            let slot_time = super::slot_to_naivedatetime(&byron, &shelley, slot, 208).and_utc();
+            assert_eq!(slot_time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true), expected);
+            assert_eq!(
+                super::slot_at_time_ms(&byron, &shelley, 208, slot_time.timestamp_millis() as u64),
+                slot
+            );
+        }
+
+        // a devnet with 100ms byron slots in 10 slot epochs and 0.2s shelley slots, shelley from epoch 2
+        let (byron, shelley) = genesis("100", 1, "0.2");
+        assert_eq!(shelley.slot_length_ms, 200);
+        for (slot, expected) in [
+            (0, "2017-09-23T21:44:51.000Z"),
+            (19, "2017-09-23T21:44:52.900Z"),
+            (20, "2017-09-23T21:44:53.000Z"),
+            (21, "2017-09-23T21:44:53.200Z"),
+        ] {
+            let slot_time = super::slot_to_naivedatetime(&byron, &shelley, slot, 2).and_utc();
+            assert_eq!(slot_time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true), expected);
+            assert_eq!(
+                super::slot_at_time_ms(&byron, &shelley, 2, slot_time.timestamp_millis() as u64),
+                slot
+            );
+        }
+        // halfway through a slot is still that slot
+        let slot_time_ms = super::slot_to_naivedatetime(&byron, &shelley, 21, 2)
+            .and_utc()
+            .timestamp_millis() as u64;
+        assert_eq!(super::slot_at_time_ms(&byron, &shelley, 2, slot_time_ms + 100), 21);
+        assert_eq!(super::get_first_slot_of_epoch(&byron, &shelley, 19, 2), (1, 10));
+        assert_eq!(super::get_first_slot_of_epoch(&byron, &shelley, 21, 2), (2, 20));
+    }
+
+    #[test]
+    fn test_invalid_slot_length() {
+        let shelley: Result<super::ShelleyGenesis, _> = serde_json::from_str(
+            r#"{"activeSlotsCoeff": 0.05, "networkMagic": 42, "slotLength": 0, "epochLength": 432000}"#,
+        );
+        assert!(shelley.is_err());
+    }
+
+    #[test]
+    fn test_read_custom_network_shelley_genesis() {
+        let path = std::env::temp_dir().join(format!("cncli-shelley-genesis-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"active_slots_coeff": "0.1", "networkMagic": "1097911063", "slotLength": 0.2, "epochLength": 3600.0, "extra": true}"#,
+        )
+        .unwrap();
+        let shelley = super::read_shelley_genesis(&path).unwrap();
+        assert_eq!(shelley.active_slots_coeff, 0.1);
+        assert_eq!(shelley.network_magic, 1097911063);
+        assert_eq!(shelley.slot_length_ms, 200);
+        assert_eq!(shelley.epoch_length, 3600);
+        assert_eq!(shelley.coercions.len(), 4);
+
+        std::fs::write(
+            &path,
+            r#"{"activeSlotsCoeff": 0.05, "slotLength": 1, "epochLength": "long"}"#,
+        )
+        .unwrap();
+        let error = super::read_shelley_genesis(&path).unwrap_err().to_string();
+        assert!(
+            error.ends_with("networkMagic is missing, epochLength must be a number, got the string \"long\""),
+            "{error}"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_leader_c() {
+        let recip_q = FixedDecimal::from(1000u64);
+        let sigma = FixedDecimal::from(1u64) / FixedDecimal::from(1000u64);
+        // with f = 1 every slot is led, even one with a vrf output no stake could lead
+        let c = super::leader_c(&shelley_genesis("1.0")).unwrap();
+        assert!(c.is_none());
+        assert!(super::is_leader_recip_q(&recip_q, &sigma, &c));
+        let c = super::leader_c(&shelley_genesis("0.05")).unwrap();
+        assert!(!super::is_leader_recip_q(&recip_q, &sigma, &c));
+    }
+
+    #[test]
+    fn test_target_epoch_and_nonce_with_memory_store() {
+        use crate::nodeclient::blockstore::memory::MemoryBlockStore;
+        use crate::nodeclient::blockstore::BlockStore;
+        use crate::nodeclient::sync::BlockHeader;
+
+        let (byron, shelley) = genesis("20000", 2160, "1");
+        let first_slot_of_epoch_500 = 4492800 + (500 - 208) * 432000;
+        let tip_slot = first_slot_of_epoch_500 + 1000;
+        let mut block_store = MemoryBlockStore::new();
+        block_store
+            .save_block(
+                &mut vec![BlockHeader {
+                    block_number: 1,
+                    slot_number: tip_slot,
+                    hash: vec![1; 32],
+                    prev_hash: vec![0; 32],
+                    node_vkey: vec![1; 32],
+                    eta_vrf_0: vec![1; 64],
+                    ..BlockHeader::default()
+                }],
+                "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81",
+            )
+            .unwrap();
+        let now = super::slot_to_naivedatetime(&byron, &shelley, tip_slot + 60, 208).and_utc();
+        let target_epoch = |block_store: &mut MemoryBlockStore, ledger_set, epoch, nonce_supplied| {
+            super::target_epoch(
+                block_store,
+                &byron,
+                &shelley,
+                208,
+                &ledger_set,
+                &epoch,
+                nonce_supplied,
+                now,
+            )
+        };
+
+        let set = target_epoch(&mut block_store, super::LedgerSet::Set, None, false).unwrap();
+        assert_eq!(
+            set,
+            super::TargetEpoch {
+                epoch: 500,
+                first_slot_of_epoch: first_slot_of_epoch_500,
+                tip_slot_number: tip_slot,
+                current_epoch: 500,
+            }
+        );
+        assert_eq!(
+            target_epoch(&mut block_store, super::LedgerSet::Mark, None, false)
+                .unwrap()
+                .epoch,
+            501
+        );
+        assert_eq!(
+            target_epoch(&mut block_store, super::LedgerSet::Go, None, false)
+                .unwrap()
+                .epoch,
+            499
+        );
+        assert_eq!(
+            target_epoch(&mut block_store, super::LedgerSet::Set, Some(450), false)
+                .unwrap()
+                .epoch,
+            450
+        );
+        // epochs ahead need a supplied nonce
+        assert!(target_epoch(&mut block_store, super::LedgerSet::Future(2), None, false).is_err());
+        assert!(target_epoch(&mut block_store, super::LedgerSet::Set, Some(501), false).is_err());
+        assert_eq!(
+            target_epoch(&mut block_store, super::LedgerSet::Future(2), None, true)
+                .unwrap()
+                .epoch,
+            502
+        );
+
+        let nonce = "ab".repeat(32);
+        assert_eq!(
+            super::leaderlog_nonce(
+                &mut block_store,
+                &byron,
+                &shelley,
+                208,
+                &set,
+                "praos",
+                &None,
+                &Some(nonce.clone()),
+                now,
+            )
+            .unwrap()
+            .to_string(),
+            nonce
+        );
+        // a db tip more than 15 minutes old is not trusted for the nonce
+        let error = super::leaderlog_nonce(
+            &mut block_store,
+            &byron,
+            &shelley,
+            208,
+            &set,
+            "praos",
+            &None,
+            &None,
+            now + chrono::Duration::minutes(20),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("db not fully synced"));
+    }
+
+    #[test]
+    fn test_predict_epoch_nonce() {
+        use crate::nodeclient::blockstore::memory::MemoryBlockStore;
+        use crate::nodeclient::blockstore::BlockStore;
+        use crate::nodeclient::sync::BlockHeader;
+
+        let (byron, shelley) = genesis("20000", 2160, "1");
+        let first_slot_of_epoch_500 = 4492800 + (500 - 208) * 432000;
+        let first_slot_of_epoch_501 = first_slot_of_epoch_500 + 432000;
+        // 3k/f before epoch 501 plus a minute
+        let final_slot = first_slot_of_epoch_501 - 129600 + 60;
+        let mut block_store = MemoryBlockStore::new();
+        block_store
+            .save_block(
+                &mut vec![
+                    BlockHeader {
+                        block_number: 1,
+                        slot_number: first_slot_of_epoch_500 - 10,
+                        hash: vec![1; 32],
+                        prev_hash: vec![0; 32],
+                        node_vkey: vec![1; 32],
+                        eta_vrf_0: vec![1; 64],
+                        ..BlockHeader::default()
+                    },
+                    BlockHeader {
+                        block_number: 2,
+                        slot_number: first_slot_of_epoch_500 + 1000,
+                        hash: vec![2; 32],
+                        prev_hash: vec![1; 32],
+                        node_vkey: vec![1; 32],
+                        eta_vrf_0: vec![2; 64],
+                        ..BlockHeader::default()
+                    },
+                ],
+                "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81",
+            )
+            .unwrap();
+        let target_epoch = super::TargetEpoch {
+            epoch: 501,
+            first_slot_of_epoch: first_slot_of_epoch_501,
+            tip_slot_number: first_slot_of_epoch_500 + 1000,
+            current_epoch: 500,
+        };
+
+        let prediction =
+            super::predict_epoch_nonce(&mut block_store, &byron, &shelley, 208, &target_epoch, "praos", &None).unwrap();
+        assert!(!prediction.is_final);
+        assert_eq!(prediction.epoch, 501);
+        assert_eq!(prediction.final_slot, final_slot);
+        assert_eq!(
+            prediction.slots_until_final,
+            final_slot - (first_slot_of_epoch_500 + 1000)
+        );
+        // the rolling nonce at the tip with the prev hash of the last block before epoch 500
+        let nc = block_store.get_eta_v_before_slot(u64::MAX).unwrap();
+        let nh = block_store.get_prev_hash_before_slot(first_slot_of_epoch_500).unwrap();
+        assert_eq!(prediction.nonce, hex::encode(super::generate_epoch_nonce(nc, nh, None)));
+    }
+
+    #[test]
+    fn test_epoch_consensus() {
+        use crate::nodeclient::blockstore::memory::MemoryBlockStore;
+        use crate::nodeclient::blockstore::BlockStore;
+        use crate::nodeclient::sync::BlockHeader;
+
+        let (_, shelley) = genesis("20000", 2160, "1");
+        let first_slot_of_epoch_500 = 4492800 + (500 - 208) * 432000;
+        let first_slot_of_epoch_501 = first_slot_of_epoch_500 + 432000;
+        let mut block_store = MemoryBlockStore::new();
+        block_store
+            .save_block(
+                &mut vec![
+                    // babbage blocks early in epoch 499, more than one scan back from its end
+                    BlockHeader {
+                        block_number: 1,
+                        slot_number: first_slot_of_epoch_500 - 400000,
+                        hash: vec![1; 32],
+                        prev_hash: vec![0; 32],
+                        node_vkey: vec![1; 32],
+                        eta_vrf_0: vec![1; 64],
+                        protocol_major_version: 8,
+                        ..BlockHeader::default()
+                    },
+                    // conway blocks from epoch 500 on
+                    BlockHeader {
+                        block_number: 2,
+                        slot_number: first_slot_of_epoch_500 + 1000,
+                        hash: vec![2; 32],
+                        prev_hash: vec![1; 32],
+                        node_vkey: vec![1; 32],
+                        eta_vrf_0: vec![2; 64],
+                        protocol_major_version: 10,
+                        ..BlockHeader::default()
+                    },
+                ],
+                "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81",
+            )
+            .unwrap();
+
+        // the nonce of epoch 500 froze in epoch 499 under babbage rules, whatever the tip is at
+        assert_eq!(
+            super::epoch_consensus(&mut block_store, &shelley, first_slot_of_epoch_500).unwrap(),
+            "praos"
+        );
+        assert_eq!(
+            super::epoch_consensus(&mut block_store, &shelley, first_slot_of_epoch_501).unwrap(),
+            "cpraos"
+        );
+        // no blocks synced in epoch 449, the tip decides
+        let first_slot_of_epoch_450 = 4492800 + (450 - 208) * 432000;
+        assert_eq!(
+            super::epoch_consensus(&mut block_store, &shelley, first_slot_of_epoch_450).unwrap(),
+            "cpraos"
+        );
+    }
+
+    #[test]
+    fn test_get_protocol_params_from_db() {
+        use crate::nodeclient::blockstore::memory::MemoryBlockStore;
+        use crate::nodeclient::blockstore::{BlockStore, ProtocolUpdate};
+
+        let (byron, _) = genesis("20000", 2160, "1");
+        let shelley: super::ShelleyGenesis = serde_json::from_str(
+            r#"{"activeSlotsCoeff": 0.05, "networkMagic": 42, "slotLength": 1, "epochLength": 432000, "updateQuorum": 5,
+            "protocolParams": {"decentralisationParam": 1, "extraEntropy": {"tag": "NeutralNonce"}}}"#,
+        )
+        .unwrap();
+        let first_slot = |epoch: u64| 4492800 + (epoch - 208) * 432000;
+        let mut block_store = MemoryBlockStore::new();
+        let params = |block_store: &mut MemoryBlockStore, epoch| {
+            super::get_protocol_params_from_db(block_store, &byron, &shelley, 208, epoch).unwrap()
+        };
+        assert_eq!(params(&mut block_store, 211), (1.0, None));
+
+        let extra_entropy = "ee".repeat(32);
+        for (epoch, slot_number, decentralization, extra_entropy, votes) in [
+            (210, first_slot(210) + 100, Some((1, 2)), None, Some(5)),
+            // short of the quorum
+            (211, first_slot(211) + 100, Some((0, 1)), None, Some(2)),
+            // after the 6k/f slots before the end of the epoch, so for the next one
+            (212, first_slot(212) - 1000, Some((1, 4)), None, Some(7)),
+            // saved before the votes were counted
+            (212, first_slot(212) + 100, None, Some(extra_entropy.clone()), None),
+        ] {
+            block_store
+                .save_protocol_update(&ProtocolUpdate {
+                    epoch,
+                    slot_number,
+                    decentralization,
+                    extra_entropy,
+                    votes,
+                })
+                .unwrap();
+        }
+        assert_eq!(params(&mut block_store, 211), (0.5, None));
+        assert_eq!(params(&mut block_store, 212), (0.5, None));
+        assert_eq!(params(&mut block_store, 213), (0.25, Some(extra_entropy)));
+    }
+
+    #[test]
+    fn test_epoch_slots_ideal_and_max_performance() {
+        let (_, shelley) = genesis("20000", 2160, "1");
+        let sigma = super::sigma(1_000_000, 1_000_000_000);
+        assert_eq!(super::epoch_slots_ideal(&shelley, &sigma, 0.0).unwrap(), 21.6);
+        assert_eq!(super::epoch_slots_ideal(&shelley, &sigma, 0.5).unwrap(), 10.8);
+        assert_eq!(super::max_performance(20, 21.6), 92.59);
+    }
+
+    #[test]
+    fn test_check_vrf_skey_matches() {
+        let pool_vrf_skey = super::VrfKey {
+            key_type: "VrfSigningKey_PraosVRF".to_string(),
+            key: (0..64u8).collect(),
+        };
+        let registered = crate::nodeclient::vrfkey::vrf_vkey_hash(&pool_vrf_skey.key[32..]).to_string();
+        let pool_id = super::PoolId::from_str(&"ab".repeat(28)).unwrap();
+        assert!(super::check_vrf_skey_matches(&pool_vrf_skey, &pool_id, &registered).is_ok());
+
+        let rotated = "ab".repeat(32);
+        let error = super::check_vrf_skey_matches(&pool_vrf_skey, &pool_id, &rotated)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(&registered) && error.contains(&rotated));
+        assert!(super::check_vrf_skey_matches(&pool_vrf_skey, &pool_id, "not-a-hash").is_err());
+    }
 }
@@ -0,0 +1,165 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, SecondsFormat, TimeDelta, Timelike, Utc};
+
+use crate::ScheduleFormat;
+
+/// How to turn the assigned slots of a leaderlog into timers that run a command shortly before each slot
+#[derive(Debug, Clone)]
+pub(crate) struct ScheduleExport {
+    pub(crate) format: ScheduleFormat,
+    /// `{slot}` and `{at}` are replaced with the slot number and its time in UTC
+    pub(crate) command: String,
+    pub(crate) lead_seconds: u64,
+}
+
+/// Render systemd units or crontab lines for the slots given as (slot, time). Slots whose trigger time is already
+/// past at `now` are left out.
+pub(crate) fn render_schedule(
+    schedule_export: &ScheduleExport,
+    epoch: u64,
+    pool_id: &str,
+    slots: &[(u64, DateTime<Utc>)],
+    now: DateTime<Utc>,
+) -> String {
+    let lead = TimeDelta::seconds(schedule_export.lead_seconds as i64);
+    let mut schedule = String::new();
+    if let ScheduleFormat::Cron = schedule_export.format {
+        writeln!(schedule, "# cncli leader schedule for pool {pool_id} epoch {epoch}").unwrap();
+        writeln!(schedule, "CRON_TZ=UTC").unwrap();
+    }
+    for (slot, at) in slots {
+        let trigger_time = *at - lead;
+        if trigger_time < now {
+            continue;
+        }
+        let at = at.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let command = schedule_export
+            .command
+            .replace("{slot}", &slot.to_string())
+            .replace("{at}", &at);
+        match schedule_export.format {
+            ScheduleFormat::Systemd => {
+                // % starts a specifier in unit files
+                let command = command.replace('%', "%%");
+                writeln!(schedule, "### cncli-slot-{slot}.service").unwrap();
+                writeln!(schedule, "[Unit]").unwrap();
+                writeln!(
+                    schedule,
+                    "Description=Before leader slot {slot} of pool {pool_id} at {at}"
+                )
+                .unwrap();
+                writeln!(schedule).unwrap();
+                writeln!(schedule, "[Service]").unwrap();
+                writeln!(schedule, "Type=oneshot").unwrap();
+                writeln!(schedule, "ExecStart={command}").unwrap();
+                writeln!(schedule, "### cncli-slot-{slot}.timer").unwrap();
+                writeln!(schedule, "[Unit]").unwrap();
+                writeln!(
+                    schedule,
+                    "Description=Before leader slot {slot} of pool {pool_id} at {at}"
+                )
+                .unwrap();
+                writeln!(schedule).unwrap();
+                writeln!(schedule, "[Timer]").unwrap();
+                writeln!(schedule, "OnCalendar={}", trigger_time.format("%Y-%m-%d %H:%M:%S UTC")).unwrap();
+                writeln!(schedule, "AccuracySec=1s").unwrap();
+                writeln!(schedule).unwrap();
+                writeln!(schedule, "[Install]").unwrap();
+                writeln!(schedule, "WantedBy=timers.target").unwrap();
+            }
+            ScheduleFormat::Cron => {
+                // cron has minute resolution so round down to stay ahead of the slot. % ends the command in crontab.
+                let trigger_time = trigger_time.with_second(0).unwrap();
+                let command = command.replace('%', "\\%");
+                writeln!(schedule, "# epoch {epoch} slot {slot} at {at}").unwrap();
+                writeln!(
+                    schedule,
+                    "{} {} {} {} * {command}",
+                    trigger_time.minute(),
+                    trigger_time.hour(),
+                    trigger_time.format("%-d"),
+                    trigger_time.format("%-m"),
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots() -> Vec<(u64, DateTime<Utc>)> {
+        vec![
+            (
+                125000000,
+                DateTime::parse_from_rfc3339("2024-05-01T12:34:56Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+            (
+                125000100,
+                DateTime::parse_from_rfc3339("2024-05-01T12:36:36Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_render_cron_schedule() {
+        let schedule_export = ScheduleExport {
+            format: ScheduleFormat::Cron,
+            command: "/usr/local/bin/pre-slot.sh {slot} 50%".to_string(),
+            lead_seconds: 60,
+        };
+        let now = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            render_schedule(&schedule_export, 480, "00beef", &slots(), now),
+            "# cncli leader schedule for pool 00beef epoch 480\n\
+            CRON_TZ=UTC\n\
+            # epoch 480 slot 125000000 at 2024-05-01T12:34:56Z\n\
+            33 12 1 5 * /usr/local/bin/pre-slot.sh 125000000 50\\%\n\
+            # epoch 480 slot 125000100 at 2024-05-01T12:36:36Z\n\
+            35 12 1 5 * /usr/local/bin/pre-slot.sh 125000100 50\\%\n"
+        );
+    }
+
+    #[test]
+    fn test_render_systemd_schedule_skips_past_slots() {
+        let schedule_export = ScheduleExport {
+            format: ScheduleFormat::Systemd,
+            command: "/usr/local/bin/pre-slot.sh {at}".to_string(),
+            lead_seconds: 30,
+        };
+        let now = DateTime::parse_from_rfc3339("2024-05-01T12:35:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            render_schedule(&schedule_export, 480, "00beef", &slots(), now),
+            "### cncli-slot-125000100.service\n\
+            [Unit]\n\
+            Description=Before leader slot 125000100 of pool 00beef at 2024-05-01T12:36:36Z\n\
+            \n\
+            [Service]\n\
+            Type=oneshot\n\
+            ExecStart=/usr/local/bin/pre-slot.sh 2024-05-01T12:36:36Z\n\
+            ### cncli-slot-125000100.timer\n\
+            [Unit]\n\
+            Description=Before leader slot 125000100 of pool 00beef at 2024-05-01T12:36:36Z\n\
+            \n\
+            [Timer]\n\
+            OnCalendar=2024-05-01 12:36:06 UTC\n\
+            AccuracySec=1s\n\
+            \n\
+            [Install]\n\
+            WantedBy=timers.target\n"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use pallas_crypto::hash::Hash;
+use pallas_math::math::FixedDecimal;
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::nodeclient::leaderlog::{
+    cert_nat_max, get_first_slot_of_epoch_number, guess_shelley_transition_epoch, is_leader_recip_q, is_overlay_slot,
+    leader_c, praos_recip_q, read_byron_genesis, read_shelley_genesis, read_vrf_key, tpraos_recip_q, Error,
+};
+use crate::nodeclient::reporter;
+
+// a sweep is one pass over the epoch no matter how many sigmas, but the output should stay readable
+const MAX_SIGMAS: usize = 1000;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Simulation {
+    status: String,
+    epoch: u64,
+    epoch_nonce: String,
+    consensus: String,
+    d: f64,
+    f: f64,
+    sigmas: Vec<SigmaSimulation>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SigmaSimulation {
+    sigma: f64,
+    epoch_slots_ideal: f64,
+    epoch_slots: u64,
+    max_performance: f64,
+}
+
+/// Parse a decimal like 0.0015 into (15, 4) so it can be stepped without floating point drift
+fn parse_decimal(value: &str) -> Option<(u64, u32)> {
+    let (int_part, frac_part) = value.split_once('.').unwrap_or((value, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    let digits = format!("{int_part}{frac_part}");
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((digits.parse().ok()?, frac_part.len() as u32))
+}
+
+/// Expand start:end:step into the sigmas start, start + step, ... up to and including end as (numerator, denominator)
+fn parse_sigma_range(sigma_range: &str) -> Result<Vec<(u64, u64)>, Error> {
+    let invalid = || {
+        Error::Leaderlog(format!(
+            "Invalid Sigma Range: --sigma-range {sigma_range}, expected start:end:step"
+        ))
+    };
+    let parts: Vec<(u64, u32)> = sigma_range
+        .split(':')
+        .map(parse_decimal)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)?;
+    let [(start, start_scale), (end, end_scale), (step, step_scale)] = parts[..] else {
+        return Err(invalid());
+    };
+    let scale = start_scale.max(end_scale).max(step_scale);
+    let denominator = 10u64.checked_pow(scale).ok_or_else(invalid)?;
+    let start = start * 10u64.pow(scale - start_scale);
+    let end = end * 10u64.pow(scale - end_scale);
+    let step = step * 10u64.pow(scale - step_scale);
+    if start == 0 || step == 0 || end < start || end > denominator {
+        return Err(invalid());
+    }
+    if (end - start) / step >= MAX_SIGMAS as u64 {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Sigma Range: --sigma-range {sigma_range} has more than {MAX_SIGMAS} values"
+        )));
+    }
+
+    Ok((start..=end)
+        .step_by(step as usize)
+        .map(|numerator| (numerator, denominator))
+        .collect())
+}
+
+/// Count the slots the pool would be assigned in an epoch for each sigma in a range. The vrf output of each slot does
+/// not depend on sigma and a larger sigma only adds slots, so every slot is evaluated once and the smallest sigma
+/// that leads it is found with a binary search.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn simulate(
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    epoch: u64,
+    epoch_nonce: &str,
+    pool_vrf_skey_path: &Path,
+    sigma_range: &str,
+    d: f64,
+    consensus: &str,
+) -> Result<(), Error> {
+    let sigmas = parse_sigma_range(sigma_range)?;
+    let byron = read_byron_genesis(byron_genesis)?;
+    debug!("{:?}", byron);
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    debug!("{:?}", shelley);
+
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+    if epoch < shelley_transition_epoch {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Epoch: --epoch {epoch}, shelley_transition_epoch: {shelley_transition_epoch}"
+        )));
+    }
+
+    let epoch_nonce = Hash::<32>::from_str(epoch_nonce)?;
+    let pool_vrf_skey = read_vrf_key(pool_vrf_skey_path)?;
+    if pool_vrf_skey.key_type != "VrfSigningKey_PraosVRF" {
+        return Err(Error::Leaderlog(
+            "Pool VRF Skey must be of type: VrfSigningKey_PraosVRF".to_string(),
+        ));
+    }
+
+    let d: f64 = (d * 1000.0).round() / 1000.0;
+    let cert_nat_max = cert_nat_max(consensus)?;
+    let c: Option<FixedDecimal> = leader_c(&shelley)?;
+    let fixed_sigmas: Vec<FixedDecimal> = sigmas
+        .iter()
+        .map(|(numerator, denominator)| FixedDecimal::from(*numerator) / FixedDecimal::from(*denominator))
+        .collect();
+
+    let first_slot_of_epoch = get_first_slot_of_epoch_number(&byron, &shelley, epoch, shelley_transition_epoch);
+    debug!("first_slot_of_epoch: {}", first_slot_of_epoch);
+
+    // the index of the smallest sigma that leads each slot, if any
+    let first_leader_sigmas = (0..shelley.epoch_length)
+        .par_bridge()
+        .map(|slot_in_epoch| first_slot_of_epoch + slot_in_epoch)
+        .filter(|epoch_slot| !is_overlay_slot(&first_slot_of_epoch, epoch_slot, &d))
+        .map(|leader_slot| -> Result<usize, Error> {
+            let recip_q = match consensus {
+                "tpraos" => tpraos_recip_q(leader_slot, epoch_nonce.as_slice(), &pool_vrf_skey.key, &cert_nat_max)?,
+                _ => praos_recip_q(leader_slot, epoch_nonce.as_slice(), &pool_vrf_skey.key, &cert_nat_max)?,
+            };
+            Ok(fixed_sigmas.partition_point(|sigma| !is_leader_recip_q(&recip_q, sigma, &c)))
+        })
+        .collect::<Result<Vec<usize>, Error>>()?;
+
+    let mut epoch_slots = vec![0u64; sigmas.len() + 1];
+    for first_leader_sigma in first_leader_sigmas {
+        epoch_slots[first_leader_sigma] += 1;
+    }
+    // a slot led by a sigma is led by every larger sigma too
+    let mut led_slots = 0u64;
+    for slots in epoch_slots.iter_mut() {
+        led_slots += *slots;
+        *slots = led_slots;
+    }
+
+    let simulation = Simulation {
+        status: "ok".to_string(),
+        epoch,
+        epoch_nonce: hex::encode(epoch_nonce),
+        consensus: consensus.to_string(),
+        d,
+        f: shelley.active_slots_coeff,
+        sigmas: sigmas
+            .iter()
+            .zip(epoch_slots)
+            .map(|((numerator, denominator), epoch_slots)| {
+                let sigma = *numerator as f64 / *denominator as f64;
+                let epoch_slots_ideal = sigma * shelley.epoch_length as f64 * shelley.active_slots_coeff * (1.0 - d);
+                let epoch_slots_ideal = (epoch_slots_ideal * 100.0).round() / 100.0;
+                SigmaSimulation {
+                    sigma,
+                    epoch_slots_ideal,
+                    epoch_slots,
+                    max_performance: (epoch_slots as f64 / epoch_slots_ideal * 10000.0).round() / 100.0,
+                }
+            })
+            .collect(),
+    };
+
+    reporter::report(&simulation)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sigma_range() {
+        assert_eq!(
+            parse_sigma_range("0.001:0.005:0.001").unwrap(),
+            vec![(1, 1000), (2, 1000), (3, 1000), (4, 1000), (5, 1000)]
+        );
+        assert_eq!(
+            parse_sigma_range("0.001:0.002:.0005").unwrap(),
+            vec![(10, 10000), (15, 10000), (20, 10000)]
+        );
+        // end is not on a step
+        assert_eq!(parse_sigma_range("0.1:0.25:0.1").unwrap(), vec![(1, 10), (2, 10)]);
+        assert!(parse_sigma_range("0.001:0.01").is_err());
+        assert!(parse_sigma_range("0.01:0.001:0.001").is_err());
+        assert!(parse_sigma_range("0:0.01:0.001").is_err());
+        assert!(parse_sigma_range("0.5:1.5:0.5").is_err());
+        assert!(parse_sigma_range("0.000001:0.1:0.000001").is_err());
+    }
+}
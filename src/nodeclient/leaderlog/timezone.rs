@@ -0,0 +1,106 @@
+use chrono_tz::{Tz, TZ_VARIANTS};
+
+use crate::nodeclient::leaderlog::Error;
+
+// how many near misses to suggest for an unknown timezone
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Parse the --tz value, an IANA timezone name or `local` for the timezone of the system
+pub(crate) fn parse_timezone(timezone: &str) -> Result<Tz, Error> {
+    if timezone.eq_ignore_ascii_case("local") {
+        let system_timezone = iana_time_zone::get_timezone().map_err(|error| {
+            Error::Leaderlog(format!("Cannot determine the system timezone for --tz local: {error}"))
+        })?;
+        return system_timezone.parse::<Tz>().map_err(|_| {
+            Error::Leaderlog(format!(
+                "The system timezone {system_timezone} is not in the IANA database, set --tz explicitly"
+            ))
+        });
+    }
+    if let Ok(tz) = timezone.parse::<Tz>() {
+        return Ok(tz);
+    }
+    let suggestions = suggest_timezones(timezone);
+    if suggestions.is_empty() {
+        return Err(Error::Leaderlog(format!(
+            "Invalid Timezone: --tz {timezone}, expected a name from the IANA database such as America/Los_Angeles or local"
+        )));
+    }
+    Err(Error::Leaderlog(format!(
+        "Invalid Timezone: --tz {timezone}, did you mean {}?",
+        suggestions.join(", ")
+    )))
+}
+
+/// The timezone names closest to an unknown one, ignoring case. A name is close when it is a few edits away or when
+/// its city matches the one given, e.g. Tokyo for Asia/Tokyo.
+fn suggest_timezones(timezone: &str) -> Vec<&'static str> {
+    let timezone = timezone.to_lowercase();
+    let max_distance = (timezone.len() / 4).clamp(1, 3);
+    let mut suggestions: Vec<(usize, &'static str)> = TZ_VARIANTS
+        .iter()
+        .filter_map(|tz| {
+            let name = tz.name();
+            let lowercase_name = name.to_lowercase();
+            let city = lowercase_name.rsplit('/').next().unwrap_or_default();
+            let distance = edit_distance(&timezone, &lowercase_name).min(edit_distance(&timezone, city));
+            if distance <= max_distance {
+                Some((distance, name))
+            } else {
+                None
+            }
+        })
+        .collect();
+    suggestions.sort();
+    suggestions
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// The Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous_row[j] + usize::from(a_char != *b_char);
+            row[j + 1] = substitution.min(previous_row[j + 1] + 1).min(row[j] + 1);
+        }
+        previous_row = row;
+    }
+    previous_row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(parse_timezone("America/Los_Angeles").unwrap(), Tz::America__Los_Angeles);
+        let error = parse_timezone("America/Los_Angles").unwrap_err().to_string();
+        assert!(error.contains("did you mean America/Los_Angeles"), "{error}");
+        let error = parse_timezone("europe/berlin").unwrap_err().to_string();
+        assert!(error.contains("Europe/Berlin"), "{error}");
+        assert!(!parse_timezone("Not a timezone at all")
+            .unwrap_err()
+            .to_string()
+            .contains("did you mean"));
+    }
+
+    #[test]
+    fn test_suggest_timezones() {
+        assert_eq!(suggest_timezones("Tokyo"), vec!["Asia/Tokyo"]);
+        assert!(suggest_timezones("Europe/Amsterdm").contains(&"Europe/Amsterdam"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "utc"), 3);
+        assert_eq!(edit_distance("utc", "utc"), 0);
+    }
+}
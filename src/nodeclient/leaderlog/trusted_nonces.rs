@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use pallas_crypto::key::ed25519::{PublicKey, Signature};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::nodeclient::leaderlog::Error;
+
+/// A known-good epoch nonce, e.g. taken from `cardano-cli query protocol-state` on a trusted node
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrustedNonce {
+    pub(crate) network_magic: u32,
+    pub(crate) epoch: u64,
+    pub(crate) nonce: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrustedNonceFile {
+    nonces: Vec<TrustedNonce>,
+    /// ed25519 signature in hex over the lines `<networkMagic>:<epoch>:<nonce>\n` of the nonces in file order
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+/// The message a trusted nonce file is signed over
+fn signed_message(nonces: &[TrustedNonce]) -> String {
+    nonces
+        .iter()
+        .map(|trusted_nonce| {
+            format!(
+                "{}:{}:{}\n",
+                trusted_nonce.network_magic,
+                trusted_nonce.epoch,
+                trusted_nonce.nonce.to_lowercase()
+            )
+        })
+        .collect()
+}
+
+fn verify_signature(nonces: &[TrustedNonce], signature: &str, vkey: &str) -> Result<bool, Error> {
+    let vkey: [u8; 32] = hex::decode(vkey)?
+        .try_into()
+        .map_err(|_| Error::Leaderlog("Trusted nonces vkey must be 32 bytes of hex".to_string()))?;
+    let signature: [u8; 64] = hex::decode(signature)?
+        .try_into()
+        .map_err(|_| Error::Leaderlog("Trusted nonces signature must be 64 bytes of hex".to_string()))?;
+    Ok(PublicKey::from(vkey).verify(signed_message(nonces), &Signature::from(signature)))
+}
+
+/// Read a trusted nonce file. When a vkey is given the file must carry a valid signature from it.
+pub(crate) fn read_trusted_nonces(path: &Path, vkey: &Option<String>) -> Result<Vec<TrustedNonce>, Error> {
+    let buf = BufReader::new(File::open(path)?);
+    let trusted_nonce_file: TrustedNonceFile = serde_json::from_reader(buf)?;
+    match (vkey, &trusted_nonce_file.signature) {
+        (Some(vkey), Some(signature)) => {
+            if !verify_signature(&trusted_nonce_file.nonces, signature, vkey)? {
+                return Err(Error::Leaderlog(format!(
+                    "Invalid signature on trusted nonces file {}",
+                    path.to_string_lossy()
+                )));
+            }
+        }
+        (Some(_), None) => {
+            return Err(Error::Leaderlog(format!(
+                "Trusted nonces file {} is not signed",
+                path.to_string_lossy()
+            )));
+        }
+        (None, _) => {
+            warn!(
+                "Trusted nonces file {} is used without checking a signature",
+                path.to_string_lossy()
+            );
+        }
+    }
+
+    Ok(trusted_nonce_file.nonces)
+}
+
+/// Fail when a trusted nonce exists for the epoch and does not match the nonce we calculated
+pub(crate) fn check_trusted_nonce(
+    trusted_nonces: &[TrustedNonce],
+    network_magic: u32,
+    epoch: u64,
+    epoch_nonce: &str,
+) -> Result<(), Error> {
+    match trusted_nonces
+        .iter()
+        .find(|trusted_nonce| trusted_nonce.network_magic == network_magic && trusted_nonce.epoch == epoch)
+    {
+        Some(trusted_nonce) if !trusted_nonce.nonce.eq_ignore_ascii_case(epoch_nonce) => {
+            Err(Error::Leaderlog(format!(
+                "Epoch {epoch} nonce {epoch_nonce} does not match the trusted nonce {}. The db may be corrupt or out of sync, run `cncli nonce --trace-from-slot` to find where it diverges.",
+                trusted_nonce.nonce
+            )))
+        }
+        Some(_) => {
+            info!("Epoch {} nonce matches the trusted nonce", epoch);
+            Ok(())
+        }
+        None => {
+            if !trusted_nonces.is_empty() {
+                warn!("No trusted nonce for epoch {}, the nonce is not checked", epoch);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted_nonces() -> Vec<TrustedNonce> {
+        vec![TrustedNonce {
+            network_magic: 764824073,
+            epoch: 480,
+            nonce: "c7d8b8d3b3a0cd4e4ba29d76cdaa9f1ad6d0e0fa1e4d1ba3bb4d8e5ab23b2a61".to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_check_trusted_nonce() {
+        let trusted_nonces = trusted_nonces();
+        assert!(check_trusted_nonce(
+            &trusted_nonces,
+            764824073,
+            480,
+            "C7D8B8D3B3A0CD4E4BA29D76CDAA9F1AD6D0E0FA1E4D1BA3BB4D8E5AB23B2A61"
+        )
+        .is_ok());
+        assert!(check_trusted_nonce(
+            &trusted_nonces,
+            764824073,
+            480,
+            "0000000000000000000000000000000000000000000000000000000000000000"
+        )
+        .is_err());
+        // other epochs and networks are not checked
+        assert!(check_trusted_nonce(&trusted_nonces, 764824073, 481, "00").is_ok());
+        assert!(check_trusted_nonce(&trusted_nonces, 1, 480, "00").is_ok());
+    }
+
+    #[test]
+    fn test_signed_message() {
+        assert_eq!(
+            signed_message(&trusted_nonces()),
+            "764824073:480:c7d8b8d3b3a0cd4e4ba29d76cdaa9f1ad6d0e0fa1e4d1ba3bb4d8e5ab23b2a61\n"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use pallas_crypto::hash::Hash;
+use pallas_math::math::FixedDecimal;
+use serde::Serialize;
+
+use crate::nodeclient::leaderlog::{
+    cert_nat_max, mk_input_vrf, mk_seed, read_vrf_key, vrf_eval_certified, vrf_leader_hash, Error,
+};
+use crate::nodeclient::reporter;
+
+/// The secret key of the first ed25519 test vector of RFC 8032, which the ECVRF draft test vectors use as well
+const TEST_VRF_SKEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TestVectors {
+    status: String,
+    consensus: String,
+    epoch_nonce: String,
+    /// Only set for the built-in test key, a pool's own key is never printed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test_vrf_skey: Option<String>,
+    vectors: Vec<TestVector>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct TestVector {
+    slot: u64,
+    /// The seed the vrf is evaluated on: blake2b-256(slot || epoch nonce), xor-ed with the universal nonce in tpraos
+    vrf_input: String,
+    vrf_output: String,
+    /// blake2b-256("L" || vrf output) in praos, the vrf output itself in tpraos
+    leader_value: String,
+    /// 1 / (1 - leader value / max), the pool leads the slot when this is below exp(-sigma * ln(1 - f))
+    recip_q: String,
+}
+
+fn test_vector(slot: u64, eta0: &[u8], vrf_skey: &[u8], consensus: &str) -> Result<TestVector, Error> {
+    let cert_nat_max = cert_nat_max(consensus)?;
+    let vrf_input = match consensus {
+        "tpraos" => mk_seed(slot, eta0),
+        _ => mk_input_vrf(slot, eta0),
+    };
+    let vrf_output = vrf_eval_certified(&vrf_input, vrf_skey)?;
+    let leader_value = match consensus {
+        "tpraos" => vrf_output.to_vec(),
+        _ => vrf_leader_hash(vrf_output.as_slice()).to_vec(),
+    };
+    let leader_value_nat = FixedDecimal::from(leader_value.as_slice());
+    let recip_q = &cert_nat_max / &(&cert_nat_max - &leader_value_nat);
+    Ok(TestVector {
+        slot,
+        vrf_input: hex::encode(vrf_input),
+        vrf_output: hex::encode(vrf_output),
+        leader_value: hex::encode(leader_value),
+        recip_q: recip_q.to_string(),
+    })
+}
+
+/// Print the intermediate leader election values of each slot, to compare against other implementations such as the
+/// Haskell node or to attach to a report about a schedule mismatch. The built-in test key is used unless a vrf.skey
+/// is given.
+pub(crate) fn gen_vectors(
+    epoch_nonce: &str,
+    slots: &[u64],
+    consensus: &str,
+    pool_vrf_skey_path: &Option<PathBuf>,
+) -> Result<(), Error> {
+    let epoch_nonce = Hash::<32>::from_str(epoch_nonce)?;
+    let (vrf_skey, test_vrf_skey) = match pool_vrf_skey_path {
+        Some(pool_vrf_skey_path) => (read_pool_vrf_skey(pool_vrf_skey_path)?, None),
+        None => (hex::decode(TEST_VRF_SKEY)?, Some(TEST_VRF_SKEY.to_string())),
+    };
+
+    let vectors = slots
+        .iter()
+        .map(|slot| test_vector(*slot, epoch_nonce.as_slice(), &vrf_skey, consensus))
+        .collect::<Result<Vec<TestVector>, Error>>()?;
+
+    reporter::report(&TestVectors {
+        status: "ok".to_string(),
+        consensus: consensus.to_string(),
+        epoch_nonce: hex::encode(epoch_nonce),
+        test_vrf_skey,
+        vectors,
+    })?;
+
+    Ok(())
+}
+
+fn read_pool_vrf_skey(pool_vrf_skey_path: &Path) -> Result<Vec<u8>, Error> {
+    let pool_vrf_skey = read_vrf_key(pool_vrf_skey_path)?;
+    if pool_vrf_skey.key_type != "VrfSigningKey_PraosVRF" {
+        return Err(Error::Leaderlog(
+            "Pool VRF Skey must be of type: VrfSigningKey_PraosVRF".to_string(),
+        ));
+    }
+    Ok(pool_vrf_skey.key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH_NONCE: &str = "9ef9d6fa6e1a1aed5b3a5c5b8f0e8d1fda1a02eec4bd07a47cb3c59e5f4d8dd1";
+
+    #[test]
+    fn test_vector_inputs() {
+        let eta0 = hex::decode(EPOCH_NONCE).unwrap();
+        let vrf_skey = hex::decode(TEST_VRF_SKEY).unwrap();
+        let praos = test_vector(4492800, &eta0, &vrf_skey, "praos").unwrap();
+        let tpraos = test_vector(4492800, &eta0, &vrf_skey, "tpraos").unwrap();
+
+        assert_eq!(praos.vrf_input, hex::encode(mk_input_vrf(4492800, &eta0)));
+        assert_eq!(tpraos.vrf_input, hex::encode(mk_seed(4492800, &eta0)));
+        assert_eq!(praos.leader_value.len(), 64);
+        assert_eq!(tpraos.leader_value, tpraos.vrf_output);
+        // the same key and input always give the same values
+        assert_eq!(praos, test_vector(4492800, &eta0, &vrf_skey, "praos").unwrap());
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::nodeclient::leaderlog::Error;
+
+/// A vrf.skey and the epochs it was the pool's registered VRF key in. Without to_epoch it is still registered.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VrfKeyVersion {
+    pub(crate) skey: PathBuf,
+    pub(crate) from_epoch: u64,
+    #[serde(default)]
+    pub(crate) to_epoch: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VrfKeyHistoryFile {
+    keys: Vec<VrfKeyVersion>,
+}
+
+/// Read the VRF keys a pool had over time. Relative skey paths are relative to the history file. The epoch ranges
+/// must not overlap, and only the last key may be open-ended.
+pub(crate) fn read_vrf_key_history(path: &Path) -> Result<Vec<VrfKeyVersion>, Error> {
+    let buf = BufReader::new(File::open(path)?);
+    let history_file: VrfKeyHistoryFile = serde_json::from_reader(buf)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut keys: Vec<VrfKeyVersion> = history_file
+        .keys
+        .into_iter()
+        .map(|key| VrfKeyVersion {
+            skey: base_dir.join(&key.skey),
+            ..key
+        })
+        .collect();
+    check_vrf_key_history(&mut keys)
+        .map_err(|reason| Error::Leaderlog(format!("Invalid VRF key history {}: {reason}", path.to_string_lossy())))?;
+    Ok(keys)
+}
+
+/// Sort the keys by epoch and check that every epoch has at most one of them
+fn check_vrf_key_history(keys: &mut [VrfKeyVersion]) -> Result<(), String> {
+    if keys.is_empty() {
+        return Err("no keys".to_string());
+    }
+    keys.sort_by_key(|key| key.from_epoch);
+    for key in keys.iter() {
+        if key.to_epoch.is_some_and(|to_epoch| to_epoch < key.from_epoch) {
+            return Err(format!("{} ends before it starts", key.skey.to_string_lossy()));
+        }
+    }
+    for pair in keys.windows(2) {
+        match pair[0].to_epoch {
+            Some(to_epoch) if to_epoch < pair[1].from_epoch => {}
+            _ => {
+                return Err(format!(
+                    "{} and {} overlap",
+                    pair[0].skey.to_string_lossy(),
+                    pair[1].skey.to_string_lossy()
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The key that was registered in the epoch
+pub(crate) fn select_vrf_key(keys: &[VrfKeyVersion], epoch: u64) -> Result<&VrfKeyVersion, Error> {
+    keys.iter()
+        .find(|key| key.from_epoch <= epoch && epoch <= key.to_epoch.unwrap_or(u64::MAX))
+        .ok_or_else(|| Error::Leaderlog(format!("No VRF key in the key history covers epoch {epoch}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(skey: &str, from_epoch: u64, to_epoch: Option<u64>) -> VrfKeyVersion {
+        VrfKeyVersion {
+            skey: PathBuf::from(skey),
+            from_epoch,
+            to_epoch,
+        }
+    }
+
+    #[test]
+    fn test_select_vrf_key() {
+        let mut keys = vec![key("vrf.skey", 451, None), key("vrf-2021.skey", 300, Some(450))];
+        check_vrf_key_history(&mut keys).unwrap();
+        assert_eq!(keys[0].skey, PathBuf::from("vrf-2021.skey"));
+        assert_eq!(select_vrf_key(&keys, 300).unwrap().skey, PathBuf::from("vrf-2021.skey"));
+        assert_eq!(select_vrf_key(&keys, 450).unwrap().skey, PathBuf::from("vrf-2021.skey"));
+        assert_eq!(select_vrf_key(&keys, 451).unwrap().skey, PathBuf::from("vrf.skey"));
+        assert_eq!(select_vrf_key(&keys, 600).unwrap().skey, PathBuf::from("vrf.skey"));
+        assert!(select_vrf_key(&keys, 299).is_err());
+
+        assert!(check_vrf_key_history(&mut [key("a", 300, None), key("b", 400, None)]).is_err());
+        assert!(check_vrf_key_history(&mut [key("a", 300, Some(400)), key("b", 400, None)]).is_err());
+        assert!(check_vrf_key_history(&mut [key("a", 300, Some(200))]).is_err());
+        assert!(check_vrf_key_history(&mut []).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+use tracing_appender::non_blocking::{NonBlocking, NonBlockingBuilder, WorkerGuard};
+
+// the worker writing log lines to the file, dropping it flushes the lines still queued
+static GUARD: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Could not open log file {0}: {1}")]
+    Open(String, std::io::Error),
+}
+
+/// When the log file is rotated regardless of its size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    /// The hour or day a point in time falls in, rotation happens when it changes
+    fn period(&self, time: SystemTime) -> Option<u64> {
+        let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        match self {
+            LogRotation::Never => None,
+            LogRotation::Hourly => Some(seconds / 3600),
+            LogRotation::Daily => Some(seconds / 86400),
+        }
+    }
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(rotation: &str) -> Result<Self, Self::Err> {
+        match rotation.to_lowercase().as_str() {
+            "never" => Ok(LogRotation::Never),
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            _ => Err(format!(
+                "Invalid log rotation '{rotation}', expected never, hourly or daily"
+            )),
+        }
+    }
+}
+
+/// A size in bytes, written as a number with an optional K, M or G suffix (powers of 1024)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogSize(pub u64);
+
+impl FromStr for LogSize {
+    type Err = String;
+
+    fn from_str(size: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid log size '{size}', expected e.g. 10485760, 512K, 100M or 1G");
+        let upper = size.trim().to_uppercase();
+        let number = upper.trim_end_matches("IB").trim_end_matches('B');
+        let (digits, multiplier) = match number.chars().last() {
+            Some('K') => (&number[..number.len() - 1], 1u64 << 10),
+            Some('M') => (&number[..number.len() - 1], 1u64 << 20),
+            Some('G') => (&number[..number.len() - 1], 1u64 << 30),
+            _ => (number, 1u64),
+        };
+        let bytes = digits
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|digits| digits.checked_mul(multiplier))
+            .ok_or_else(invalid)?;
+        if bytes == 0 {
+            return Err(invalid());
+        }
+        Ok(LogSize(bytes))
+    }
+}
+
+/// A log file that is moved aside to <path>.1 when it grows past a size or a new hour or day starts. Older files
+/// shift up to <path>.2 and so on, the ones past `keep` are overwritten.
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    rotate_size: Option<u64>,
+    rotation: LogRotation,
+    keep: usize,
+    file: File,
+    size: u64,
+    period: Option<u64>,
+}
+
+impl RotatingFile {
+    pub(crate) fn open(
+        path: &Path,
+        rotate_size: Option<u64>,
+        rotation: LogRotation,
+        keep: usize,
+    ) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        // a file left from an earlier run belongs to the period it was last written in
+        let period = rotation.period(metadata.modified().unwrap_or_else(|_| SystemTime::now()));
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            rotate_size,
+            rotation,
+            keep,
+            file,
+            size: metadata.len(),
+            period,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut file_name = self.path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{index}"));
+        self.path.with_file_name(file_name)
+    }
+
+    fn needs_rotation(&self, len: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_large = matches!(self.rotate_size, Some(rotate_size) if self.size + len as u64 > rotate_size);
+        too_large || self.rotation.period(SystemTime::now()) != self.period
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        self.period = self.rotation.period(SystemTime::now());
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.needs_rotation(buf.len()) {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Open the log file and start the thread writing to it, so a slow disk does not hold up sync. Call flush before the
+/// process exits so the last lines are not lost.
+pub fn init(
+    path: &Path,
+    rotate_size: Option<LogSize>,
+    rotation: LogRotation,
+    keep: usize,
+) -> Result<NonBlocking, Error> {
+    let file = RotatingFile::open(path, rotate_size.map(|size| size.0), rotation, keep)
+        .map_err(|error| Error::Open(path.display().to_string(), error))?;
+    let (writer, guard) = NonBlockingBuilder::default()
+        .lossy(false)
+        .thread_name("cncli-log")
+        .finish(file);
+    *GUARD.lock().unwrap() = Some(guard);
+    Ok(writer)
+}
+
+/// Write out the queued log lines. Nothing is logged to the file afterwards.
+pub fn flush() {
+    if let Ok(mut guard) = GUARD.lock() {
+        guard.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_size() {
+        assert_eq!(LogSize::from_str("10485760").unwrap(), LogSize(10485760));
+        assert_eq!(LogSize::from_str("512K").unwrap(), LogSize(512 * 1024));
+        assert_eq!(LogSize::from_str("100M").unwrap(), LogSize(100 * 1024 * 1024));
+        assert_eq!(LogSize::from_str("100mb").unwrap(), LogSize(100 * 1024 * 1024));
+        assert_eq!(LogSize::from_str("1GiB").unwrap(), LogSize(1024 * 1024 * 1024));
+        assert!(LogSize::from_str("0").is_err());
+        assert!(LogSize::from_str("M").is_err());
+        assert!(LogSize::from_str("ten").is_err());
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = std::env::temp_dir().join("cncli_test_log_rotate");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("cncli.log");
+        let mut file = RotatingFile::open(&path, Some(10), LogRotation::Never, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(std::fs::read_to_string(dir.join("cncli.log.1")).unwrap(), "third\n");
+        assert_eq!(std::fs::read_to_string(dir.join("cncli.log.2")).unwrap(), "second\n");
+        // only two rotated files are kept
+        assert!(!dir.join("cncli.log.3").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,40 @@
+pub(crate) mod audit;
+pub(crate) mod backup;
+pub(crate) mod blockdetail;
 pub(crate) mod blockstore;
+pub(crate) mod cancel;
+pub(crate) mod completions;
+pub(crate) mod convert;
+pub(crate) mod crosscheck;
+pub(crate) mod db;
+pub(crate) mod density;
+pub(crate) mod exportchain;
+pub(crate) mod healthcheck;
+pub(crate) mod http;
+pub(crate) mod inspectkey;
 pub(crate) mod leaderlog;
+pub(crate) mod logfile;
+pub(crate) mod opcert;
+pub(crate) mod otlp;
+pub(crate) mod overlay;
 pub(crate) mod ping;
+pub(crate) mod poolblocks;
+pub(crate) mod poolcheck;
+pub(crate) mod poolid;
+pub(crate) mod pooltool;
+pub(crate) mod proxy;
+pub(crate) mod reporter;
+pub(crate) mod runstate;
+pub(crate) mod serve;
 pub(crate) mod sign;
+pub(crate) mod slots;
 pub(crate) mod snapshot;
+pub(crate) mod stakehistory;
+pub(crate) mod stdinconfig;
 pub(crate) mod sync;
+pub(crate) mod telemetry;
+pub(crate) mod topology;
+pub(crate) mod tui;
 pub(crate) mod validate;
+pub(crate) mod verifyserver;
+pub(crate) mod vrfkey;
@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::OpcertHistory;
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::reporter;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OpcertHistoryResult {
+    status: String,
+    pool_id: String,
+    anomalies: usize,
+    opcerts: Vec<OpcertHistory>,
+}
+
+/// Print the opcert sequence numbers seen for a pool during sync. The status is "anomaly" when any of them went
+/// backwards or skipped ahead.
+pub(crate) fn opcert_history(db_path: &Path, pool_id: &PoolId) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let opcerts = block_store.get_opcert_history(pool_id)?;
+    let anomalies = opcerts.iter().filter(|opcert| opcert.anomaly.is_some()).count();
+    reporter::report(&OpcertHistoryResult {
+        status: if anomalies > 0 { "anomaly" } else { "ok" }.to_string(),
+        pool_id: pool_id.to_string(),
+        anomalies,
+        opcerts,
+    })?;
+
+    Ok(())
+}
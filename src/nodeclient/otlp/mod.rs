@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, error, warn};
+
+use crate::nodeclient::http;
+use crate::nodeclient::http::HttpSettings;
+
+/// How often the spans and counters collected so far are sent to the collector
+const EXPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Spans kept between two exports, the oldest are dropped beyond this
+const MAX_PENDING_SPANS: usize = 10_000;
+
+const SCOPE_NAME: &str = env!("CARGO_PKG_NAME");
+const SCOPE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const SPAN_KIND_INTERNAL: u8 = 1;
+const SPAN_KIND_SERVER: u8 = 2;
+const SPAN_KIND_CLIENT: u8 = 3;
+const STATUS_CODE_ERROR: u8 = 2;
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+pub(crate) const BLOCKS_COUNTER: &str = "cncli.sync.blocks";
+pub(crate) const ROLLBACKS_COUNTER: &str = "cncli.sync.rollbacks";
+pub(crate) const REQUESTS_COUNTER: &str = "cncli.serve.requests";
+
+/// The unit and description of each counter
+const COUNTERS: [(&str, &str, &str); 3] = [
+    (BLOCKS_COUNTER, "{block}", "Blocks saved by sync"),
+    (ROLLBACKS_COUNTER, "{rollback}", "Rollbacks sent by the node"),
+    (REQUESTS_COUNTER, "{request}", "Requests answered by serve"),
+];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("HTTP error: {0}")]
+    Http(#[from] http::Error),
+
+    #[error("HTTP client error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("OTLP collector answered {0} to {1}")]
+    Rejected(reqwest::StatusCode, String),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SpanKind {
+    Internal,
+    Server,
+    Client,
+}
+
+impl SpanKind {
+    fn code(self) -> u8 {
+        match self {
+            SpanKind::Internal => SPAN_KIND_INTERNAL,
+            SpanKind::Server => SPAN_KIND_SERVER,
+            SpanKind::Client => SPAN_KIND_CLIENT,
+        }
+    }
+}
+
+/// Spans and counters sent to an OpenTelemetry collector with OTLP over HTTP, as JSON. The default does nothing, so
+/// code can record into it whether or not --otlp-endpoint is set.
+#[derive(Clone, Default)]
+pub(crate) struct Telemetry {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    service_name: String,
+    start_time_unix_nano: u64,
+    spans: Mutex<Vec<OtlpSpan>>,
+    counters: Mutex<BTreeMap<&'static str, u64>>,
+    exporter: Mutex<Option<(Sender<()>, JoinHandle<()>)>>,
+}
+
+impl Telemetry {
+    /// Start exporting to the collector at endpoint, e.g. http://localhost:4318, every 10 seconds
+    pub(crate) fn start(endpoint: &str, service_name: &str) -> Result<Self, Error> {
+        let settings = HttpSettings::from_env()?;
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let inner = Arc::new(Inner {
+            service_name: service_name.to_string(),
+            start_time_unix_nano: unix_nano(),
+            spans: Mutex::new(Vec::new()),
+            counters: Mutex::new(BTreeMap::new()),
+            exporter: Mutex::new(None),
+        });
+
+        let (stop, stopped) = channel::<()>();
+        let exporter_inner = inner.clone();
+        // the blocking client must not be created or dropped on a tokio worker
+        let exporter = std::thread::spawn(move || {
+            let client = match http::client(&settings, None) {
+                Ok(client) => client,
+                Err(error) => {
+                    error!("OTLP export disabled: {}", error);
+                    return;
+                }
+            };
+            loop {
+                let stopping = match stopped.recv_timeout(EXPORT_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => true,
+                };
+                if let Err(error) = exporter_inner.export(&client, &endpoint) {
+                    warn!("OTLP export to {} failed: {}", endpoint, error);
+                }
+                if stopping {
+                    return;
+                }
+            }
+        });
+        *inner.exporter.lock().unwrap() = Some((stop, exporter));
+
+        Ok(Telemetry { inner: Some(inner) })
+    }
+
+    /// Export to otlp_endpoint when it is set, otherwise record nothing
+    pub(crate) fn from_endpoint(otlp_endpoint: &Option<String>, service_name: &str) -> Result<Self, Error> {
+        match otlp_endpoint {
+            Some(otlp_endpoint) => Self::start(otlp_endpoint, service_name),
+            None => Ok(Telemetry::default()),
+        }
+    }
+
+    /// A span that ends when it is dropped
+    pub(crate) fn span(&self, name: &'static str, kind: SpanKind) -> Span {
+        Span {
+            telemetry: self.clone(),
+            name,
+            kind,
+            start_time_unix_nano: unix_nano(),
+            attributes: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub(crate) fn add(&self, counter: &'static str, value: u64) {
+        if let Some(inner) = self.inner.as_ref() {
+            *inner.counters.lock().unwrap().entry(counter).or_default() += value;
+        }
+    }
+
+    /// Send what was recorded since the last export and stop exporting. This blocks until the collector answered.
+    pub(crate) fn shutdown(&self) {
+        if let Some(inner) = self.inner.as_ref() {
+            if let Some((stop, exporter)) = inner.exporter.lock().unwrap().take() {
+                let _ = stop.send(());
+                let _ = exporter.join();
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn export(&self, client: &reqwest::blocking::Client, endpoint: &str) -> Result<(), Error> {
+        let resource = Resource {
+            attributes: vec![KeyValue::new("service.name", self.service_name.as_str())],
+        };
+        let scope = Scope {
+            name: SCOPE_NAME,
+            version: SCOPE_VERSION,
+        };
+
+        let spans: Vec<OtlpSpan> = std::mem::take(&mut *self.spans.lock().unwrap());
+        if !spans.is_empty() {
+            debug!("exporting {} spans", spans.len());
+            let traces = TracesData {
+                resource_spans: vec![ResourceSpans {
+                    resource: resource.clone(),
+                    scope_spans: vec![ScopeSpans {
+                        scope: scope.clone(),
+                        spans,
+                    }],
+                }],
+            };
+            post(client, &format!("{endpoint}/v1/traces"), &traces)?;
+        }
+
+        let time_unix_nano = unix_nano().to_string();
+        let metrics: Vec<Metric> = self
+            .counters
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, value)| {
+                let (unit, description) = COUNTERS
+                    .iter()
+                    .find(|(counter, _, _)| counter == name)
+                    .map(|(_, unit, description)| (*unit, *description))
+                    .unwrap_or_default();
+                Metric {
+                    name: *name,
+                    unit,
+                    description,
+                    sum: Sum {
+                        data_points: vec![NumberDataPoint {
+                            start_time_unix_nano: self.start_time_unix_nano.to_string(),
+                            time_unix_nano: time_unix_nano.clone(),
+                            as_int: value.to_string(),
+                        }],
+                        aggregation_temporality: AGGREGATION_TEMPORALITY_CUMULATIVE,
+                        is_monotonic: true,
+                    },
+                }
+            })
+            .collect();
+        if !metrics.is_empty() {
+            let metrics = MetricsData {
+                resource_metrics: vec![ResourceMetrics {
+                    resource,
+                    scope_metrics: vec![ScopeMetrics { scope, metrics }],
+                }],
+            };
+            post(client, &format!("{endpoint}/v1/metrics"), &metrics)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn post<T: Serialize>(client: &reqwest::blocking::Client, url: &str, body: &T) -> Result<(), Error> {
+    let response = client.post(url).json(body).send()?;
+    if !response.status().is_success() {
+        return Err(Error::Rejected(response.status(), url.to_string()));
+    }
+    Ok(())
+}
+
+fn unix_nano() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+fn random_hex_id(len: usize) -> String {
+    let mut id = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut id);
+    hex::encode(id)
+}
+
+/// A timed operation, recorded when it is dropped so early returns with ? are recorded too
+pub(crate) struct Span {
+    telemetry: Telemetry,
+    name: &'static str,
+    kind: SpanKind,
+    start_time_unix_nano: u64,
+    attributes: Vec<KeyValue>,
+    error: Option<String>,
+}
+
+impl Span {
+    pub(crate) fn attribute(&mut self, key: &'static str, value: impl Into<AnyValue>) {
+        if self.telemetry.inner.is_some() {
+            self.attributes.push(KeyValue::new(key, value));
+        }
+    }
+
+    pub(crate) fn error(&mut self, message: impl Display) {
+        if self.telemetry.inner.is_some() {
+            self.error = Some(message.to_string());
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(inner) = self.telemetry.inner.as_ref() else {
+            return;
+        };
+        let span = OtlpSpan {
+            trace_id: random_hex_id(16),
+            span_id: random_hex_id(8),
+            name: self.name,
+            kind: self.kind.code(),
+            start_time_unix_nano: self.start_time_unix_nano.to_string(),
+            end_time_unix_nano: unix_nano().to_string(),
+            attributes: std::mem::take(&mut self.attributes),
+            status: self.error.take().map(|message| Status {
+                code: STATUS_CODE_ERROR,
+                message,
+            }),
+        };
+        let mut spans = inner.spans.lock().unwrap();
+        if spans.len() >= MAX_PENDING_SPANS {
+            spans.remove(0);
+        }
+        spans.push(span);
+    }
+}
+
+// The OTLP JSON encoding: 64 bit integers are strings and trace and span ids are hex
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AnyValue {
+    StringValue(String),
+    IntValue(String),
+    BoolValue(bool),
+}
+
+impl From<&str> for AnyValue {
+    fn from(value: &str) -> Self {
+        AnyValue::StringValue(value.to_string())
+    }
+}
+
+impl From<String> for AnyValue {
+    fn from(value: String) -> Self {
+        AnyValue::StringValue(value)
+    }
+}
+
+impl From<u64> for AnyValue {
+    fn from(value: u64) -> Self {
+        AnyValue::IntValue(value.to_string())
+    }
+}
+
+impl From<bool> for AnyValue {
+    fn from(value: bool) -> Self {
+        AnyValue::BoolValue(value)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct KeyValue {
+    key: &'static str,
+    value: AnyValue,
+}
+
+impl KeyValue {
+    fn new(key: &'static str, value: impl Into<AnyValue>) -> Self {
+        KeyValue {
+            key,
+            value: value.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TracesData {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    resource: Resource,
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopeSpans {
+    scope: Scope,
+    spans: Vec<OtlpSpan>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpSpan {
+    trace_id: String,
+    span_id: String,
+    name: &'static str,
+    kind: u8,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    code: u8,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MetricsData {
+    resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceMetrics {
+    resource: Resource,
+    scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScopeMetrics {
+    scope: Scope,
+    metrics: Vec<Metric>,
+}
+
+#[derive(Debug, Serialize)]
+struct Metric {
+    name: &'static str,
+    unit: &'static str,
+    description: &'static str,
+    sum: Sum,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Sum {
+    data_points: Vec<NumberDataPoint>,
+    aggregation_temporality: u8,
+    is_monotonic: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NumberDataPoint {
+    start_time_unix_nano: String,
+    time_unix_nano: String,
+    as_int: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_telemetry_records_nothing() {
+        let telemetry = Telemetry::default();
+        telemetry.add(BLOCKS_COUNTER, 1);
+        let mut span = telemetry.span("sync.save_blocks", SpanKind::Internal);
+        span.attribute("blocks", 1u64);
+        assert!(span.attributes.is_empty());
+        drop(span);
+        telemetry.shutdown();
+    }
+
+    #[test]
+    fn test_span_json() {
+        let span = OtlpSpan {
+            trace_id: random_hex_id(16),
+            span_id: random_hex_id(8),
+            name: "chainsync.request_next",
+            kind: SpanKind::Client.code(),
+            start_time_unix_nano: "1700000000000000000".to_string(),
+            end_time_unix_nano: "1700000000250000000".to_string(),
+            attributes: vec![KeyValue::new("blocks", 3u64), KeyValue::new("peer", "relay:3001")],
+            status: None,
+        };
+        let json = serde_json::to_value(&span).unwrap();
+        assert_eq!(json["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(json["spanId"].as_str().unwrap().len(), 16);
+        assert_eq!(json["kind"], 3);
+        assert_eq!(json["startTimeUnixNano"], "1700000000000000000");
+        assert_eq!(json["attributes"][0]["value"]["intValue"], "3");
+        assert_eq!(json["attributes"][1]["value"]["stringValue"], "relay:3001");
+        assert!(json.get("status").is_none());
+    }
+}
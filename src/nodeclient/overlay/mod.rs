@@ -0,0 +1,183 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::nodeclient::leaderlog;
+use crate::nodeclient::leaderlog::{
+    get_first_slot_of_epoch_number, guess_shelley_transition_epoch, is_overlay_slot, read_byron_genesis,
+    read_shelley_genesis,
+};
+use crate::nodeclient::reporter;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] leaderlog::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Format error: {0}")]
+    Fmt(#[from] std::fmt::Error),
+
+    #[error("Overlay error: {0}")]
+    Overlay(String),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OverlaySchedule {
+    status: String,
+    epoch: u64,
+    first_slot: u64,
+    last_slot: u64,
+    d: f64,
+    f: f64,
+    overlay_slots: u64,
+    active_overlay_slots: u64,
+    praos_slots: u64,
+    slots: Vec<OverlaySlot>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct OverlaySlot {
+    slot: u64,
+    slot_in_epoch: u64,
+    /// Active overlay slots are the ones a genesis delegate makes a block in, the others stay empty
+    active: bool,
+}
+
+/// List the overlay (OBFT) slots of an epoch for the decentralization parameter d. Every other slot is a Praos slot
+/// that stake pools lead.
+pub(crate) fn overlay_schedule(
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    epoch: u64,
+    d: f64,
+    csv: bool,
+) -> Result<(), Error> {
+    if !(0.0..=1.0).contains(&d) {
+        return Err(Error::Overlay(format!("Invalid --d {d}, it must be between 0 and 1")));
+    }
+    let byron = read_byron_genesis(byron_genesis)?;
+    debug!("{:?}", byron);
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    debug!("{:?}", shelley);
+
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+
+    if epoch < shelley_transition_epoch {
+        return Err(Error::Overlay(format!(
+            "Invalid Epoch: --epoch {epoch}, shelley_transition_epoch: {shelley_transition_epoch}"
+        )));
+    }
+
+    let first_slot = get_first_slot_of_epoch_number(&byron, &shelley, epoch, shelley_transition_epoch);
+    let last_slot = first_slot + shelley.epoch_length - 1;
+    debug!("first_slot: {}, last_slot: {}", first_slot, last_slot);
+    let active_slot_inverse = active_slot_inverse(shelley.active_slots_coeff)?;
+
+    if csv {
+        let mut out = String::new();
+        writeln!(out, "slot,slot_in_epoch,schedule")?;
+        for slot in first_slot..=last_slot {
+            let schedule = match overlay_slot(first_slot, slot, d, active_slot_inverse) {
+                Some(overlay_slot) if overlay_slot.active => "overlay-active",
+                Some(_) => "overlay",
+                None => "praos",
+            };
+            writeln!(out, "{},{},{}", slot, slot - first_slot, schedule)?;
+        }
+        reporter::write_report(out.as_bytes())?;
+        return Ok(());
+    }
+
+    let slots: Vec<OverlaySlot> = (first_slot..=last_slot)
+        .filter_map(|slot| overlay_slot(first_slot, slot, d, active_slot_inverse))
+        .collect();
+    reporter::report(&OverlaySchedule {
+        status: "ok".to_string(),
+        epoch,
+        first_slot,
+        last_slot,
+        d,
+        f: shelley.active_slots_coeff,
+        overlay_slots: slots.len() as u64,
+        active_overlay_slots: slots.iter().filter(|slot| slot.active).count() as u64,
+        praos_slots: shelley.epoch_length - slots.len() as u64,
+        slots,
+    })?;
+
+    Ok(())
+}
+
+/// The slot as an overlay slot, None for a Praos slot. Like the ledger, every floor(1/f)th overlay slot is active.
+fn overlay_slot(first_slot: u64, slot: u64, d: f64, active_slot_inverse: u64) -> Option<OverlaySlot> {
+    if !is_overlay_slot(&first_slot, &slot, &d) {
+        return None;
+    }
+    // ceiling(d * (slot - first_slot)) with d rounded to 3 decimals the same way is_overlay_slot does
+    let d_thousandths = (d * 1000.0).round() as u64;
+    let position = (d_thousandths * (slot - first_slot)).div_ceil(1000);
+    Some(OverlaySlot {
+        slot,
+        slot_in_epoch: slot - first_slot,
+        active: position % active_slot_inverse == 0,
+    })
+}
+
+/// floor(1/f) from the decimal value of f as written in the genesis so e.g. 0.05 gives exactly 20
+fn active_slot_inverse(active_slots_coeff: f64) -> Result<u64, Error> {
+    let invalid = || {
+        Error::Overlay(format!(
+            "Invalid activeSlotsCoeff {active_slots_coeff} in the shelley genesis"
+        ))
+    };
+    if !(active_slots_coeff > 0.0 && active_slots_coeff <= 1.0) {
+        return Err(invalid());
+    }
+    let decimal = active_slots_coeff.to_string();
+    let (integer, fraction) = decimal.split_once('.').unwrap_or((&decimal, ""));
+    let numerator: u128 = format!("{integer}{fraction}").parse().map_err(|_| invalid())?;
+    let denominator = 10u128.checked_pow(fraction.len() as u32).ok_or_else(invalid)?;
+    Ok((denominator / numerator) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_slot_inverse() {
+        assert_eq!(active_slot_inverse(0.05).unwrap(), 20);
+        assert_eq!(active_slot_inverse(0.075).unwrap(), 13);
+        assert_eq!(active_slot_inverse(1.0).unwrap(), 1);
+        assert!(active_slot_inverse(0.0).is_err());
+    }
+
+    #[test]
+    fn test_overlay_slot() {
+        // with d = 0.5 every other slot is an overlay slot and every 20th of those is active
+        let overlay_slots: Vec<OverlaySlot> = (1000..1080)
+            .filter_map(|slot| overlay_slot(1000, slot, 0.5, 20))
+            .collect();
+        assert_eq!(overlay_slots.len(), 40);
+        assert_eq!(
+            overlay_slots
+                .iter()
+                .filter(|overlay_slot| overlay_slot.active)
+                .map(|overlay_slot| overlay_slot.slot_in_epoch)
+                .collect::<Vec<u64>>(),
+            vec![0, 40]
+        );
+        assert!(overlay_slot(1000, 1001, 0.0, 20).is_none());
+    }
+}
@@ -1,137 +1,519 @@
+use std::fmt::Display;
 use std::io::Write;
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
-use pallas_network::miniprotocols::handshake::Confirmation;
-use pallas_network::miniprotocols::{handshake, PROTOCOL_N2N_HANDSHAKE};
+use futures::future::join_all;
+use pallas_network::miniprotocols::handshake::{Confirmation, RefuseReason};
+use pallas_network::miniprotocols::{
+    handshake, keepalive, PROTOCOL_N2C_HANDSHAKE, PROTOCOL_N2N_HANDSHAKE, PROTOCOL_N2N_KEEP_ALIVE,
+};
+use pallas_network::multiplexer::AgentChannel;
 use pallas_network::multiplexer::{Bearer, Plexer};
 use serde::Serialize;
 
+use crate::nodeclient::proxy;
+use crate::nodeclient::proxy::SocksProxy;
+
+pub(crate) mod versions;
+
+/// Exit code when the node could not be reached or the handshake failed without a refusal
+pub(crate) const EXIT_CONNECTION_FAILED: i32 = 1;
+/// Exit code when the node runs on another network
+pub(crate) const EXIT_MAGIC_MISMATCH: i32 = 2;
+/// Exit code when the node supports none of the versions we proposed
+pub(crate) const EXIT_VERSION_MISMATCH: i32 = 3;
+/// Exit code when the node refused the handshake for another reason, e.g. it is busy
+pub(crate) const EXIT_REFUSED: i32 = 4;
+/// Exit code when the node could not decode our handshake
+pub(crate) const EXIT_HANDSHAKE_DECODE_ERROR: i32 = 5;
+
+/// The result of pinging one host
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum PingReport {
+    Success(PingSuccess),
+    Error(PingError),
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct PingSuccess {
     status: String,
-    host: String,
-    port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    socket_path: Option<String>,
     network_protocol_version: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handshake_protocol: Option<String>,
     dns_duration_ms: u128,
     connect_duration_ms: u128,
     handshake_duration_ms: u128,
     duration_ms: u128,
+    /// Round trips of the keepalive mini-protocol after the handshake, with --keepalive-count
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    keepalive_rtts_ms: Vec<u128>,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct PingError {
     status: String,
-    host: String,
-    port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    socket_path: Option<String>,
     error_message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refusal_type: Option<String>,
+    /// The versions the node supports, sent when none of ours were acceptable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_versions: Option<Vec<u64>>,
+    /// The network magic of the node, sent when ours did not match it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected_magic: Option<u64>,
 }
 
-pub async fn ping<W: Write>(out: &mut W, host: &str, port: u16, network_magic: u64, timeout_seconds: u64) {
-    let start = Instant::now();
-    let socket_addrs_result = format!("{host}:{port}").to_socket_addrs();
-    match socket_addrs_result {
-        Ok(mut socket_addrs) => {
-            let socket_addr: &SocketAddr = &socket_addrs.next().unwrap();
-            let dns_duration = start.elapsed();
-            match Bearer::connect_tcp_timeout(socket_addr, Duration::from_secs(timeout_seconds)).await {
-                Ok(bearer) => {
-                    let connect_duration = start.elapsed() - dns_duration;
-
-                    let mut plexer = Plexer::new(bearer);
-
-                    let hs_channel = plexer.subscribe_client(PROTOCOL_N2N_HANDSHAKE);
-
-                    let running_plexer = plexer.spawn();
-
-                    let versions = handshake::n2n::VersionTable::v7_and_above(network_magic);
-                    let mut client = handshake::Client::new(hs_channel);
-                    match client.handshake(versions).await {
-                        Ok(confirmation) => match confirmation {
-                            Confirmation::Accepted(version_number, _) => {
-                                let total_duration = start.elapsed();
-                                let handshake_duration = total_duration - connect_duration - dns_duration;
-                                ping_json_success(
-                                    out,
-                                    dns_duration,
-                                    connect_duration,
-                                    handshake_duration,
-                                    total_duration,
-                                    version_number,
-                                    host,
-                                    port,
-                                );
-                            }
-                            Confirmation::Rejected(refuse_reason) => {
-                                ping_json_error(out, format!("{refuse_reason:?}"), host, port);
-                            }
-                            Confirmation::QueryReply(_) => {
-                                ping_json_error(out, "Unexpected QueryReply".to_string(), host, port);
-                            }
-                        },
-                        Err(error) => {
-                            ping_json_error(out, format!("{error}"), host, port);
-                        }
-                    }
-                    running_plexer.abort().await;
-                }
-                Err(error) => {
-                    ping_json_error(out, error.to_string(), host, port);
-                }
-            }
+/// Why a node refused the handshake
+#[derive(Debug, PartialEq)]
+pub(crate) enum Refusal {
+    MagicMismatch { expected_magic: Option<u64> },
+    VersionMismatch { remote_versions: Vec<u64> },
+    HandshakeDecodeError,
+    Refused,
+}
+
+impl Refusal {
+    fn from_refuse_reason(refuse_reason: &RefuseReason) -> Self {
+        match refuse_reason {
+            RefuseReason::VersionMismatch(remote_versions) => Refusal::VersionMismatch {
+                remote_versions: remote_versions.clone(),
+            },
+            RefuseReason::HandshakeDecodeError(_, _) => Refusal::HandshakeDecodeError,
+            // the node refuses a magic that is not its own with "version data mismatch: <its data> /= <our data>"
+            RefuseReason::Refused(_, message) if message.contains("version data mismatch") => Refusal::MagicMismatch {
+                expected_magic: parse_network_magic(message),
+            },
+            RefuseReason::Refused(_, _) => Refusal::Refused,
         }
-        Err(error) => {
-            ping_json_error(out, error.to_string(), host, port);
+    }
+
+    fn refusal_type(&self) -> &'static str {
+        match self {
+            Refusal::MagicMismatch { .. } => "magicMismatch",
+            Refusal::VersionMismatch { .. } => "versionMismatch",
+            Refusal::HandshakeDecodeError => "handshakeDecodeError",
+            Refusal::Refused => "refused",
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            Refusal::MagicMismatch { .. } => EXIT_MAGIC_MISMATCH,
+            Refusal::VersionMismatch { .. } => EXIT_VERSION_MISMATCH,
+            Refusal::HandshakeDecodeError => EXIT_HANDSHAKE_DECODE_ERROR,
+            Refusal::Refused => EXIT_REFUSED,
         }
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn ping_json_success<W: Write>(
+/// The first networkMagic in a refusal message, which is the one of the refusing node
+fn parse_network_magic(message: &str) -> Option<u64> {
+    let (_, rest) = message.split_once("unNetworkMagic = ")?;
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// A failed ping, with the refusal when the node answered the handshake
+#[derive(Debug)]
+pub(crate) struct PingFailure {
+    pub(crate) message: String,
+    pub(crate) refusal: Option<Refusal>,
+}
+
+impl PingFailure {
+    fn exit_code(&self) -> i32 {
+        self.refusal.as_ref().map_or(EXIT_CONNECTION_FAILED, Refusal::exit_code)
+    }
+}
+
+impl From<String> for PingFailure {
+    fn from(message: String) -> Self {
+        PingFailure { message, refusal: None }
+    }
+}
+
+impl Display for PingFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Timings of a successful handshake with a node
+pub(crate) struct PingResult {
+    pub(crate) network_protocol_version: u64,
+    pub(crate) dns_duration: Duration,
+    pub(crate) connect_duration: Duration,
+    pub(crate) handshake_duration: Duration,
+    /// Up to the end of the handshake, without the keepalive round trips
+    pub(crate) total_duration: Duration,
+    pub(crate) keepalive_rtts: Vec<Duration>,
+}
+
+/// An accepted handshake, and the keepalive round trips made after it
+struct Handshake {
+    version: u64,
+    duration: Duration,
+    keepalive_rtts: Vec<Duration>,
+}
+
+/// A node to ping
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PingTarget {
+    /// A node over TCP
+    Node { host: String, port: u16 },
+    /// The local socket of a node, always pinged with the node-to-client handshake
+    Socket(PathBuf),
+}
+
+/// Ping nodes with a node-to-node handshake, or with a node-to-client handshake when client_protocol is set. The
+/// node-to-client handshake is for local sockets exposed over TCP, e.g. forwarded with socat. All targets are pinged
+/// at the same time and written as a json array in their order, a single target as a json object. Returns the exit
+/// code of the first target that failed, which tells a wrong network magic apart from a busy node, or 0.
+pub async fn ping_targets<W: Write>(
     out: &mut W,
-    dns_duration: Duration,
-    connect_duration: Duration,
-    handshake_duration: Duration,
-    total_duration: Duration,
-    version_number: u64,
-    host: &str,
-    port: u16,
-) {
-    serde_json::ser::to_writer_pretty(
-        out,
-        &PingSuccess {
-            status: "ok".to_string(),
+    targets: &[PingTarget],
+    network_magic: u64,
+    timeout_seconds: u64,
+    client_protocol: bool,
+    keepalive_count: usize,
+    proxy: &Option<SocksProxy>,
+) -> i32 {
+    let results = join_all(targets.iter().map(|target| async move {
+        match target {
+            PingTarget::Node { host, port } => {
+                let result = handshake_with_node(
+                    host,
+                    *port,
+                    network_magic,
+                    timeout_seconds,
+                    client_protocol,
+                    keepalive_count,
+                    proxy,
+                )
+                .await;
+                ping_report(result, client_protocol, target)
+            }
+            PingTarget::Socket(socket_path) => {
+                let result = handshake_with_socket(socket_path, network_magic, timeout_seconds).await;
+                ping_report(result, true, target)
+            }
+        }
+    }))
+    .await;
+    write_reports(out, results)
+}
+
+/// Write the report of each target, a single one as a json object and more as a json array. Returns the exit code of
+/// the first target that failed, or 0.
+fn write_reports<W: Write, R: Serialize>(out: &mut W, results: Vec<(R, i32)>) -> i32 {
+    let exit_code = results
+        .iter()
+        .map(|(_, exit_code)| *exit_code)
+        .find(|exit_code| *exit_code != 0)
+        .unwrap_or(0);
+    let reports: Vec<R> = results.into_iter().map(|(report, _)| report).collect();
+    match reports.as_slice() {
+        [report] => serde_json::ser::to_writer_pretty(out, report).unwrap(),
+        _ => serde_json::ser::to_writer_pretty(out, &reports).unwrap(),
+    }
+    exit_code
+}
+
+/// The nodes of hosts given as repeated --host options or comma separated lists, all on port
+pub(crate) fn host_targets(hosts: &[String], port: u16) -> Vec<PingTarget> {
+    hosts
+        .iter()
+        .flat_map(|hosts| hosts.split(','))
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(|host| PingTarget::Node {
             host: host.to_string(),
             port,
-            network_protocol_version: version_number,
-            dns_duration_ms: dns_duration.as_millis(),
-            connect_duration_ms: connect_duration.as_millis(),
-            handshake_duration_ms: handshake_duration.as_millis(),
-            duration_ms: total_duration.as_millis(),
-        },
+        })
+        .collect()
+}
+
+/// Resolve, connect and handshake with a node, returning the negotiated version and the time each step took. A
+/// node-to-node handshake is followed by keepalive_count keepalive round trips.
+pub(crate) async fn handshake_with_node(
+    host: &str,
+    port: u16,
+    network_magic: u64,
+    timeout_seconds: u64,
+    client_protocol: bool,
+    keepalive_count: usize,
+    proxy: &Option<SocksProxy>,
+) -> Result<PingResult, PingFailure> {
+    let start = Instant::now();
+    let (bearer, dns_duration) = connect_node(host, port, timeout_seconds, proxy).await?;
+    let connect_duration = start.elapsed() - dns_duration;
+
+    let handshake = handshake(bearer, network_magic, client_protocol, keepalive_count, timeout_seconds).await?;
+
+    Ok(PingResult {
+        network_protocol_version: handshake.version,
+        dns_duration,
+        connect_duration,
+        handshake_duration: handshake.duration,
+        total_duration: dns_duration + connect_duration + handshake.duration,
+        keepalive_rtts: handshake.keepalive_rtts,
+    })
+}
+
+/// Resolve and connect to a node, returning the connection and how long the DNS lookup took
+async fn connect_node(
+    host: &str,
+    port: u16,
+    timeout_seconds: u64,
+    proxy: &Option<SocksProxy>,
+) -> Result<(Bearer, Duration), PingFailure> {
+    let start = Instant::now();
+    let connected = match proxy {
+        None => {
+            let mut socket_addrs = format!("{host}:{port}")
+                .to_socket_addrs()
+                .map_err(|error| error.to_string())?;
+            let socket_addr: &SocketAddr = &socket_addrs
+                .next()
+                .ok_or_else(|| format!("no address found for {host}"))?;
+            let dns_duration = start.elapsed();
+            let bearer = Bearer::connect_tcp_timeout(socket_addr, Duration::from_secs(timeout_seconds))
+                .await
+                .map_err(|error| error.to_string())?;
+            (bearer, dns_duration)
+        }
+        // the proxy resolves the host, so its lookup is part of the connect time
+        Some(_) => {
+            let bearer = proxy::connect(proxy, host, port, Duration::from_secs(timeout_seconds))
+                .await
+                .map_err(|error| error.to_string())?;
+            (bearer, Duration::ZERO)
+        }
+    };
+    Ok(connected)
+}
+
+/// Connect to the local socket of a node and handshake with the node-to-client protocol, returning the negotiated
+/// version and the time each step took. There is no DNS lookup, so its duration is 0.
+pub(crate) async fn handshake_with_socket(
+    socket_path: &Path,
+    network_magic: u64,
+    timeout_seconds: u64,
+) -> Result<PingResult, PingFailure> {
+    let start = Instant::now();
+    let bearer = connect_socket(socket_path, timeout_seconds).await?;
+    let connect_duration = start.elapsed();
+
+    let handshake = handshake(bearer, network_magic, true, 0, timeout_seconds).await?;
+
+    Ok(PingResult {
+        network_protocol_version: handshake.version,
+        dns_duration: Duration::ZERO,
+        connect_duration,
+        handshake_duration: handshake.duration,
+        total_duration: connect_duration + handshake.duration,
+        keepalive_rtts: handshake.keepalive_rtts,
+    })
+}
+
+/// Connect to the local socket of a node
+#[cfg(unix)]
+async fn connect_socket(socket_path: &Path, timeout_seconds: u64) -> Result<Bearer, PingFailure> {
+    let bearer = tokio::time::timeout(Duration::from_secs(timeout_seconds), Bearer::connect_unix(socket_path))
+        .await
+        .map_err(|_| "connect timeout".to_string())?
+        .map_err(|error| format!("{}: {}", socket_path.display(), error))?;
+    Ok(bearer)
+}
+
+#[cfg(not(unix))]
+async fn connect_socket(socket_path: &Path, _timeout_seconds: u64) -> Result<Bearer, PingFailure> {
+    Err(format!(
+        "--socket-path {} needs a unix socket, which this platform does not have. Expose the node socket over TCP and \
+        use --host with --client-protocol instead",
+        socket_path.display()
     )
-    .unwrap();
+    .into())
 }
 
-fn ping_json_error<W: Write>(out: &mut W, message: String, host: &str, port: u16) {
-    serde_json::ser::to_writer_pretty(
-        out,
-        &PingError {
-            status: "error".to_string(),
-            host: host.to_string(),
-            port,
-            error_message: message,
+/// Handshake over a connected bearer and return the negotiated version. The node-to-client protocol has no keepalive,
+/// so keepalive_count only applies to a node-to-node handshake.
+async fn handshake(
+    bearer: Bearer,
+    network_magic: u64,
+    client_protocol: bool,
+    keepalive_count: usize,
+    timeout_seconds: u64,
+) -> Result<Handshake, PingFailure> {
+    let mut plexer = Plexer::new(bearer);
+
+    if client_protocol {
+        let hs_channel = plexer.subscribe_client(PROTOCOL_N2C_HANDSHAKE);
+
+        let running_plexer = plexer.spawn();
+
+        let start = Instant::now();
+        let versions = handshake::n2c::VersionTable::v10_and_above(network_magic);
+        let mut client = handshake::Client::new(hs_channel);
+        let handshake_result = accepted_version(client.handshake(versions).await).map(|version| Handshake {
+            version,
+            duration: start.elapsed(),
+            keepalive_rtts: Vec::new(),
+        });
+        running_plexer.abort().await;
+        handshake_result
+    } else {
+        let hs_channel = plexer.subscribe_client(PROTOCOL_N2N_HANDSHAKE);
+        let ka_channel = plexer.subscribe_client(PROTOCOL_N2N_KEEP_ALIVE);
+
+        let running_plexer = plexer.spawn();
+
+        let start = Instant::now();
+        let versions = handshake::n2n::VersionTable::v7_and_above(network_magic);
+        let mut client = handshake::Client::new(hs_channel);
+        let handshake_result = match accepted_version(client.handshake(versions).await) {
+            Ok(version) => {
+                let duration = start.elapsed();
+                keepalive_round_trips(ka_channel, keepalive_count, timeout_seconds)
+                    .await
+                    .map(|keepalive_rtts| Handshake {
+                        version,
+                        duration,
+                        keepalive_rtts,
+                    })
+            }
+            Err(ping_failure) => Err(ping_failure),
+        };
+        running_plexer.abort().await;
+        handshake_result
+    }
+}
+
+/// Time count keepalive round trips one after the other. Unlike the handshake, they leave out the version negotiation,
+/// so they measure how fast the node answers on an open connection.
+async fn keepalive_round_trips(
+    channel: AgentChannel,
+    count: usize,
+    timeout_seconds: u64,
+) -> Result<Vec<Duration>, PingFailure> {
+    let mut client = keepalive::Client::new(channel);
+    let mut rtts = Vec::with_capacity(count);
+    for round_trip in 1..=count {
+        let start = Instant::now();
+        match tokio::time::timeout(Duration::from_secs(timeout_seconds), client.keepalive_roundtrip()).await {
+            Ok(Ok(())) => rtts.push(start.elapsed()),
+            Ok(Err(error)) => return Err(format!("keepalive round trip {round_trip} failed: {error}").into()),
+            Err(_) => return Err(format!("keepalive round trip {round_trip} timed out").into()),
+        }
+    }
+    Ok(rtts)
+}
+
+/// The negotiated version of a handshake, or the reason it was not accepted
+fn accepted_version<D, E: Display>(handshake_result: Result<Confirmation<D>, E>) -> Result<u64, PingFailure> {
+    match handshake_result {
+        Ok(confirmation) => match confirmation {
+            Confirmation::Accepted(version_number, _) => Ok(version_number),
+            Confirmation::Rejected(refuse_reason) => Err(PingFailure {
+                message: format!("{refuse_reason:?}"),
+                refusal: Some(Refusal::from_refuse_reason(&refuse_reason)),
+            }),
+            Confirmation::QueryReply(_) => Err("Unexpected QueryReply".to_string().into()),
         },
-    )
-    .unwrap();
+        Err(error) => Err(format!("{error}").into()),
+    }
+}
+
+/// The report of a ping and its exit code
+fn ping_report(
+    result: Result<PingResult, PingFailure>,
+    client_protocol: bool,
+    target: &PingTarget,
+) -> (PingReport, i32) {
+    match result {
+        Ok(ping_result) => (
+            PingReport::Success(ping_success(&ping_result, client_protocol, target)),
+            0,
+        ),
+        Err(ping_failure) => {
+            let exit_code = ping_failure.exit_code();
+            (PingReport::Error(ping_error(ping_failure, target)), exit_code)
+        }
+    }
+}
+
+/// The host, port and socket path fields of a report
+fn target_fields(target: &PingTarget) -> (Option<String>, Option<u16>, Option<String>) {
+    match target {
+        PingTarget::Node { host, port } => (Some(host.clone()), Some(*port), None),
+        PingTarget::Socket(socket_path) => (None, None, Some(socket_path.display().to_string())),
+    }
+}
+
+fn ping_success(ping_result: &PingResult, client_protocol: bool, target: &PingTarget) -> PingSuccess {
+    let (host, port, socket_path) = target_fields(target);
+    PingSuccess {
+        status: "ok".to_string(),
+        host,
+        port,
+        socket_path,
+        network_protocol_version: ping_result.network_protocol_version,
+        handshake_protocol: if client_protocol {
+            Some("node-to-client".to_string())
+        } else {
+            None
+        },
+        dns_duration_ms: ping_result.dns_duration.as_millis(),
+        connect_duration_ms: ping_result.connect_duration.as_millis(),
+        handshake_duration_ms: ping_result.handshake_duration.as_millis(),
+        duration_ms: ping_result.total_duration.as_millis(),
+        keepalive_rtts_ms: ping_result.keepalive_rtts.iter().map(Duration::as_millis).collect(),
+    }
+}
+
+fn ping_error(ping_failure: PingFailure, target: &PingTarget) -> PingError {
+    let (host, port, socket_path) = target_fields(target);
+    let refusal = ping_failure.refusal.as_ref();
+    PingError {
+        status: "error".to_string(),
+        host,
+        port,
+        socket_path,
+        refusal_type: refusal.map(|refusal| refusal.refusal_type().to_string()),
+        remote_versions: match refusal {
+            Some(Refusal::VersionMismatch { remote_versions }) => Some(remote_versions.clone()),
+            _ => None,
+        },
+        expected_magic: match refusal {
+            Some(Refusal::MagicMismatch { expected_magic }) => *expected_magic,
+            _ => None,
+        },
+        error_message: ping_failure.message,
+    }
 }
 
 #[cfg(not(target_os = "windows"))]
 #[cfg(test)]
 mod tests {
     use crate::nodeclient::ping;
+    use pallas_network::miniprotocols::handshake::RefuseReason;
     use regex::Regex;
 
     #[tokio::test]
@@ -141,7 +523,16 @@ mod tests {
         let network_magic = 1;
         let mut stdout: Vec<u8> = Vec::new();
 
-        ping::ping(&mut stdout, &host, port, network_magic, 2).await;
+        ping::ping_targets(
+            &mut stdout,
+            &ping::host_targets(&[host], port),
+            network_magic,
+            2,
+            false,
+            0,
+            &None,
+        )
+        .await;
 
         assert_eq!(
             &std::str::from_utf8(&stdout).unwrap()[..85],
@@ -149,6 +540,19 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_ping_keepalive() {
+        let host = "preprod-node.play.dev.cardano.org".to_string();
+        let mut stdout: Vec<u8> = Vec::new();
+
+        let exit_code =
+            ping::ping_targets(&mut stdout, &ping::host_targets(&[host], 30000), 1, 2, false, 3, &None).await;
+
+        let ping_result: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+        assert_eq!(exit_code, 0);
+        assert_eq!(ping_result["keepaliveRttsMs"].as_array().unwrap().len(), 3);
+    }
+
     #[tokio::test]
     async fn test_ping_failure_address() {
         let host = "murrika.relays-new.cardano-testnet.iohkdev.io".to_string();
@@ -156,7 +560,16 @@ mod tests {
         let network_magic = 1;
         let mut stdout: Vec<u8> = Vec::new();
 
-        ping::ping(&mut stdout, &host, port, network_magic, 2).await;
+        ping::ping_targets(
+            &mut stdout,
+            &ping::host_targets(&[host], port),
+            network_magic,
+            2,
+            false,
+            0,
+            &None,
+        )
+        .await;
 
         let regex_str = ".*failed to lookup address information: .*";
         let regex = Regex::new(regex_str);
@@ -172,7 +585,16 @@ mod tests {
         let network_magic = 1;
         let mut stdout: Vec<u8> = Vec::new();
 
-        ping::ping(&mut stdout, &host, port, network_magic, 2).await;
+        ping::ping_targets(
+            &mut stdout,
+            &ping::host_targets(&[host], port),
+            network_magic,
+            2,
+            false,
+            0,
+            &None,
+        )
+        .await;
 
         let regex_str = ".*connect(ion)? time(out)?.*";
         let regex = Regex::new(regex_str);
@@ -188,12 +610,110 @@ mod tests {
         let network_magic = 111111;
         let mut stdout: Vec<u8> = Vec::new();
 
-        ping::ping(&mut stdout, &host, port, network_magic, 2).await;
+        let exit_code = ping::ping_targets(
+            &mut stdout,
+            &ping::host_targets(&[host], port),
+            network_magic,
+            2,
+            false,
+            0,
+            &None,
+        )
+        .await;
 
         let regex_str = ".*\"Refused\\(\\d+, \\\\\"version data mismatch.*";
         let regex = Regex::new(regex_str);
         let ping_result = std::str::from_utf8(&stdout).unwrap();
         // println!("ping_result: {}", ping_result);
         assert!(regex.unwrap().is_match(ping_result));
+        assert!(ping_result.contains("\"refusalType\": \"magicMismatch\""));
+        assert!(ping_result.contains("\"expectedMagic\": 2"));
+        assert_eq!(exit_code, ping::EXIT_MAGIC_MISMATCH);
+    }
+
+    #[tokio::test]
+    async fn test_ping_hosts() {
+        let hosts = vec![
+            "preprod-node.play.dev.cardano.org".to_string(),
+            "murrika.relays-new.cardano-testnet.iohkdev.io".to_string(),
+        ];
+        let mut stdout: Vec<u8> = Vec::new();
+
+        let exit_code =
+            ping::ping_targets(&mut stdout, &ping::host_targets(&hosts, 30000), 1, 2, false, 0, &None).await;
+
+        let ping_result: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+        let results = ping_result.as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["host"], "preprod-node.play.dev.cardano.org");
+        assert_eq!(results[0]["status"], "ok");
+        assert_eq!(results[1]["status"], "error");
+        assert_eq!(exit_code, ping::EXIT_CONNECTION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn test_ping_failure_socket() {
+        let socket_path = std::env::temp_dir().join(format!("cncli-ping-missing-{}.socket", std::process::id()));
+        let mut stdout: Vec<u8> = Vec::new();
+
+        let exit_code = ping::ping_targets(
+            &mut stdout,
+            &[ping::PingTarget::Socket(socket_path.clone())],
+            1,
+            2,
+            false,
+            0,
+            &None,
+        )
+        .await;
+
+        let ping_result: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+        assert_eq!(ping_result["status"], "error");
+        assert_eq!(ping_result["socketPath"], socket_path.display().to_string());
+        assert!(ping_result.get("host").is_none());
+        assert_eq!(exit_code, ping::EXIT_CONNECTION_FAILED);
+    }
+
+    #[test]
+    fn test_host_targets() {
+        let hosts = vec![
+            "relay1.example.com, relay2.example.com".to_string(),
+            "10.0.0.3,".to_string(),
+        ];
+        let hosts: Vec<String> = ping::host_targets(&hosts, 6000)
+            .into_iter()
+            .map(|target| match target {
+                ping::PingTarget::Node { host, port } => format!("{host}:{port}"),
+                ping::PingTarget::Socket(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            hosts,
+            vec!["relay1.example.com:6000", "relay2.example.com:6000", "10.0.0.3:6000"]
+        );
+    }
+
+    #[test]
+    fn test_refusal_from_refuse_reason() {
+        let message =
+            "version data mismatch: NodeToNodeVersionData {networkMagic = NetworkMagic {unNetworkMagic = 2}, \
+            diffusionMode = InitiatorAndResponderDiffusionMode} /= NodeToNodeVersionData {networkMagic = NetworkMagic \
+            {unNetworkMagic = 111111}, diffusionMode = InitiatorOnlyDiffusionMode}";
+        assert_eq!(
+            ping::Refusal::from_refuse_reason(&RefuseReason::Refused(13, message.to_string())),
+            ping::Refusal::MagicMismatch {
+                expected_magic: Some(2)
+            }
+        );
+        assert_eq!(
+            ping::Refusal::from_refuse_reason(&RefuseReason::VersionMismatch(vec![13, 14])),
+            ping::Refusal::VersionMismatch {
+                remote_versions: vec![13, 14]
+            }
+        );
+        assert_eq!(
+            ping::Refusal::from_refuse_reason(&RefuseReason::Refused(13, "busy".to_string())),
+            ping::Refusal::Refused
+        );
     }
 }
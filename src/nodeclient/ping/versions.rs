@@ -0,0 +1,301 @@
+use std::fmt::{Debug, Display};
+use std::io::Write;
+
+use futures::future::join_all;
+use minicbor::data::Type;
+use minicbor::{Decoder, Encode};
+use pallas_network::miniprotocols::handshake::{Confirmation, VersionTable};
+use pallas_network::miniprotocols::{handshake, PROTOCOL_N2C_HANDSHAKE, PROTOCOL_N2N_HANDSHAKE};
+use pallas_network::multiplexer::{Bearer, Plexer};
+use serde::Serialize;
+
+use crate::nodeclient::ping::{
+    connect_node, connect_socket, ping_error, target_fields, write_reports, PingError, PingFailure, PingTarget, Refusal,
+};
+use crate::nodeclient::proxy::SocksProxy;
+
+// the first node-to-node version with the query flag in its version data
+const N2N_QUERY_VERSION: u64 = 11;
+// node-to-client versions are numbered with bit 15 set, so this is version 15, the first with the query flag
+const N2C_QUERY_VERSION: u64 = 32783;
+const PEER_SHARING_DISABLED: u8 = 0;
+
+/// A protocol version the node supports, with the parameters it sent for it
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RemoteVersion {
+    version: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network_magic: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    initiator_only_diffusion_mode: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer_sharing: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionsSuccess {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    socket_path: Option<String>,
+    handshake_protocol: String,
+    versions: Vec<RemoteVersion>,
+}
+
+/// The versions of one target
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum VersionsReport {
+    Success(VersionsSuccess),
+    Error(PingError),
+}
+
+/// The node-to-node versions we support that can carry the query flag, all with it set
+fn n2n_query_table(network_magic: u64) -> handshake::n2n::VersionTable {
+    let mut versions = handshake::n2n::VersionTable::v7_and_above(network_magic);
+    versions.values = versions
+        .values
+        .into_keys()
+        .filter(|version| *version >= N2N_QUERY_VERSION)
+        .map(|version| {
+            (
+                version,
+                handshake::n2n::VersionData::new(network_magic, true, Some(PEER_SHARING_DISABLED), Some(true)),
+            )
+        })
+        .collect();
+    versions
+}
+
+/// The node-to-client versions we support that can carry the query flag, all with it set
+fn n2c_query_table(network_magic: u64) -> handshake::n2c::VersionTable {
+    let mut versions = handshake::n2c::VersionTable::v10_and_above(network_magic);
+    versions.values = versions
+        .values
+        .into_keys()
+        .filter(|version| *version >= N2C_QUERY_VERSION)
+        .map(|version| (version, handshake::n2c::VersionData::new(network_magic, Some(true))))
+        .collect();
+    versions
+}
+
+/// Read the parameters of a version from its version data as it goes over the wire: the network magic alone for old
+/// versions, else [magic, initiatorOnly, peerSharing, query] for node-to-node and [magic, query] for node-to-client,
+/// shorter for versions before peer sharing.
+fn remote_version(version: u64, cbor: &[u8], client_protocol: bool) -> Result<RemoteVersion, minicbor::decode::Error> {
+    let mut remote_version = RemoteVersion {
+        version,
+        network_magic: None,
+        initiator_only_diffusion_mode: None,
+        peer_sharing: None,
+        query: None,
+    };
+    let mut decoder = Decoder::new(cbor);
+    if decoder.datatype()? != Type::Array {
+        remote_version.network_magic = Some(decoder.u64()?);
+        return Ok(remote_version);
+    }
+    let len = decoder.array()?.unwrap_or_default();
+    if len > 0 {
+        remote_version.network_magic = Some(decoder.u64()?);
+    }
+    if client_protocol {
+        if len > 1 {
+            remote_version.query = Some(decoder.bool()?);
+        }
+    } else {
+        if len > 1 {
+            remote_version.initiator_only_diffusion_mode = Some(decoder.bool()?);
+        }
+        if len > 2 {
+            remote_version.peer_sharing = Some(decoder.u8()?);
+        }
+        if len > 3 {
+            remote_version.query = Some(decoder.bool()?);
+        }
+    }
+    Ok(remote_version)
+}
+
+/// The versions of a query reply, ordered by version
+fn remote_versions<D: Debug + Clone + Encode<()>>(
+    versions: VersionTable<D>,
+    client_protocol: bool,
+) -> Result<Vec<RemoteVersion>, PingFailure> {
+    let mut remote_versions = versions
+        .values
+        .into_iter()
+        .map(|(version, version_data)| {
+            let cbor = minicbor::to_vec(&version_data).map_err(|error| error.to_string())?;
+            remote_version(version, &cbor, client_protocol)
+                .map_err(|error| PingFailure::from(format!("Could not read the data of version {version}: {error}")))
+        })
+        .collect::<Result<Vec<RemoteVersion>, PingFailure>>()?;
+    remote_versions.sort_by_key(|remote_version| remote_version.version);
+    Ok(remote_versions)
+}
+
+/// The versions the node listed in its query reply, or why it did not list them
+fn queried_versions<D: Debug + Clone + Encode<()>, E: Display>(
+    handshake_result: Result<Confirmation<D>, E>,
+    client_protocol: bool,
+) -> Result<Vec<RemoteVersion>, PingFailure> {
+    match handshake_result {
+        Ok(Confirmation::QueryReply(versions)) => remote_versions(versions, client_protocol),
+        Ok(Confirmation::Accepted(version_number, _)) => Err(format!(
+            "The node accepted version {version_number} instead of listing its versions, it does not support the handshake query"
+        )
+        .into()),
+        Ok(Confirmation::Rejected(refuse_reason)) => Err(PingFailure {
+            message: format!("{refuse_reason:?}"),
+            refusal: Some(Refusal::from_refuse_reason(&refuse_reason)),
+        }),
+        Err(error) => Err(format!("{error}").into()),
+    }
+}
+
+/// Propose our versions that can carry the query flag with it set, so the node answers with the versions it supports
+/// instead of accepting one
+async fn query(bearer: Bearer, network_magic: u64, client_protocol: bool) -> Result<Vec<RemoteVersion>, PingFailure> {
+    let mut plexer = Plexer::new(bearer);
+
+    if client_protocol {
+        let hs_channel = plexer.subscribe_client(PROTOCOL_N2C_HANDSHAKE);
+
+        let running_plexer = plexer.spawn();
+
+        let mut client = handshake::Client::new(hs_channel);
+        let query_result = queried_versions(client.handshake(n2c_query_table(network_magic)).await, true);
+        running_plexer.abort().await;
+        query_result
+    } else {
+        let hs_channel = plexer.subscribe_client(PROTOCOL_N2N_HANDSHAKE);
+
+        let running_plexer = plexer.spawn();
+
+        let mut client = handshake::Client::new(hs_channel);
+        let query_result = queried_versions(client.handshake(n2n_query_table(network_magic)).await, false);
+        running_plexer.abort().await;
+        query_result
+    }
+}
+
+async fn query_target(
+    target: &PingTarget,
+    network_magic: u64,
+    timeout_seconds: u64,
+    client_protocol: bool,
+    proxy: &Option<SocksProxy>,
+) -> Result<Vec<RemoteVersion>, PingFailure> {
+    match target {
+        PingTarget::Node { host, port } => {
+            let (bearer, _) = connect_node(host, *port, timeout_seconds, proxy).await?;
+            query(bearer, network_magic, client_protocol).await
+        }
+        PingTarget::Socket(socket_path) => {
+            let bearer = connect_socket(socket_path, timeout_seconds).await?;
+            query(bearer, network_magic, true).await
+        }
+    }
+}
+
+/// Ask each target for the protocol versions it supports with a handshake query, all at the same time, and write them
+/// like ping_targets writes its results. Returns the exit code of the first target that failed, or 0.
+pub(crate) async fn query_targets<W: Write>(
+    out: &mut W,
+    targets: &[PingTarget],
+    network_magic: u64,
+    timeout_seconds: u64,
+    client_protocol: bool,
+    proxy: &Option<SocksProxy>,
+) -> i32 {
+    let results = join_all(targets.iter().map(|target| async move {
+        let client_protocol = client_protocol || matches!(target, PingTarget::Socket(_));
+        match query_target(target, network_magic, timeout_seconds, client_protocol, proxy).await {
+            Ok(versions) => {
+                let (host, port, socket_path) = target_fields(target);
+                let success = VersionsSuccess {
+                    status: "ok".to_string(),
+                    host,
+                    port,
+                    socket_path,
+                    handshake_protocol: if client_protocol {
+                        "node-to-client".to_string()
+                    } else {
+                        "node-to-node".to_string()
+                    },
+                    versions,
+                };
+                (VersionsReport::Success(success), 0)
+            }
+            Err(ping_failure) => {
+                let exit_code = ping_failure.exit_code();
+                (VersionsReport::Error(ping_error(ping_failure, target)), exit_code)
+            }
+        }
+    }))
+    .await;
+    write_reports(out, results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_version() {
+        let mut cbor = Vec::new();
+        minicbor::Encoder::new(&mut cbor)
+            .array(4)
+            .unwrap()
+            .u64(764824073)
+            .unwrap()
+            .bool(false)
+            .unwrap()
+            .u8(1)
+            .unwrap()
+            .bool(false)
+            .unwrap();
+        assert_eq!(
+            remote_version(13, &cbor, false).unwrap(),
+            RemoteVersion {
+                version: 13,
+                network_magic: Some(764824073),
+                initiator_only_diffusion_mode: Some(false),
+                peer_sharing: Some(1),
+                query: Some(false),
+            }
+        );
+
+        let mut cbor = Vec::new();
+        minicbor::Encoder::new(&mut cbor).u64(1).unwrap();
+        let remote_version = remote_version(32778, &cbor, true).unwrap();
+        assert_eq!(remote_version.network_magic, Some(1));
+        assert_eq!(remote_version.query, None);
+        assert_eq!(
+            serde_json::to_string(&remote_version).unwrap(),
+            r#"{"version":32778,"networkMagic":1}"#
+        );
+    }
+
+    #[test]
+    fn test_query_tables() {
+        assert!(n2n_query_table(1)
+            .values
+            .keys()
+            .all(|version| *version >= N2N_QUERY_VERSION));
+        assert!(!n2c_query_table(1).values.is_empty());
+        // our own version data must read back with the query flag set
+        let versions = remote_versions(n2c_query_table(1), true).unwrap();
+        assert!(versions.iter().all(|version| version.query == Some(true)));
+        let versions = remote_versions(n2n_query_table(1), false).unwrap();
+        assert!(versions.iter().all(|version| version.query == Some(true)));
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use chrono::SecondsFormat;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::{BlockDetail, BlockStore};
+use crate::nodeclient::leaderlog;
+use crate::nodeclient::leaderlog::{
+    get_first_slot_of_epoch, get_first_slot_of_epoch_number, guess_shelley_transition_epoch, read_byron_genesis,
+    read_shelley_genesis, slot_to_naivedatetime, ByronGenesis, ShelleyGenesis,
+};
+use crate::nodeclient::poolid::{format_pool_id, PoolId};
+use crate::nodeclient::reporter;
+use crate::Out;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] leaderlog::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Format error: {0}")]
+    Fmt(#[from] std::fmt::Error),
+
+    #[error("Pool blocks error: {0}")]
+    PoolBlocks(String),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PoolBlocks {
+    status: String,
+    pool_id: String,
+    epoch: u64,
+    first_slot: u64,
+    last_slot: u64,
+    blocks: u64,
+    orphaned_blocks: u64,
+    total_size: u64,
+    /// Only set when a leaderlog for the pool and epoch is saved in the db
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduled_slots: Option<u64>,
+    /// Scheduled slots up to last_slot without a non-orphaned block from the pool. This includes lost slot battles.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    missed_slots: Option<Vec<u64>>,
+    minted_blocks: Vec<PoolBlock>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct PoolBlock {
+    block_number: u64,
+    slot_number: u64,
+    at: String,
+    hash: String,
+    block_size: u64,
+    orphaned: bool,
+}
+
+/// List the blocks a pool minted in an epoch with their totals, as JSON or CSV
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pool_blocks(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    pool_id: &PoolId,
+    epoch: u64,
+    csv: bool,
+    bech32: bool,
+) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let byron = read_byron_genesis(byron_genesis)?;
+    debug!("{:?}", byron);
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    debug!("{:?}", shelley);
+
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+
+    let pool_blocks = epoch_pool_blocks(
+        block_store.as_mut(),
+        &byron,
+        &shelley,
+        shelley_transition_epoch,
+        pool_id,
+        epoch,
+        bech32,
+    )?;
+
+    if csv {
+        let mut out = String::new();
+        writeln!(out, "block_number,slot_number,at,hash,block_size,orphaned")?;
+        for block in pool_blocks.minted_blocks.iter() {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                block.block_number, block.slot_number, block.at, block.hash, block.block_size, block.orphaned
+            )?;
+        }
+        reporter::write_report(out.as_bytes())?;
+        return Ok(());
+    }
+
+    reporter::report(&pool_blocks)?;
+
+    Ok(())
+}
+
+/// The blocks a pool minted in an epoch, with the slots it was scheduled for if a leaderlog was saved
+fn epoch_pool_blocks(
+    block_store: &mut dyn BlockStore,
+    byron: &ByronGenesis,
+    shelley: &ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    pool_id: &PoolId,
+    epoch: u64,
+    bech32: bool,
+) -> Result<PoolBlocks, Error> {
+    if epoch < shelley_transition_epoch {
+        return Err(Error::PoolBlocks(format!(
+            "Invalid Epoch: --epoch {epoch}, shelley_transition_epoch: {shelley_transition_epoch}"
+        )));
+    }
+
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let (tip_epoch, _) = get_first_slot_of_epoch(byron, shelley, tip_slot_number, shelley_transition_epoch);
+    if epoch > tip_epoch {
+        return Err(Error::PoolBlocks(format!(
+            "Invalid Epoch: --epoch {epoch}, db tip epoch: {tip_epoch}"
+        )));
+    }
+
+    let first_slot = get_first_slot_of_epoch_number(byron, shelley, epoch, shelley_transition_epoch);
+    // only look as far as the tip if the epoch is still in progress
+    let last_slot = (first_slot + shelley.epoch_length - 1).min(tip_slot_number);
+    debug!("first_slot: {}, last_slot: {}", first_slot, last_slot);
+
+    let minted_blocks: Vec<PoolBlock> = block_store
+        .get_pool_block_details_in_slot_range(pool_id, first_slot, last_slot)?
+        .into_iter()
+        .map(|block_detail: BlockDetail| PoolBlock {
+            block_number: block_detail.block_number,
+            slot_number: block_detail.slot_number,
+            at: slot_to_naivedatetime(byron, shelley, block_detail.slot_number, shelley_transition_epoch)
+                .and_utc()
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            hash: block_detail.hash,
+            block_size: block_detail.block_size,
+            orphaned: block_detail.orphaned,
+        })
+        .collect();
+
+    let scheduled_slots: Option<Vec<u64>> = match block_store.get_previous_slots(epoch, pool_id)? {
+        Some(slots) => Some(serde_json::from_str(&slots)?),
+        None => None,
+    };
+
+    Ok(PoolBlocks {
+        status: "ok".to_string(),
+        epoch,
+        first_slot,
+        last_slot,
+        blocks: minted_blocks.iter().filter(|block| !block.orphaned).count() as u64,
+        orphaned_blocks: minted_blocks.iter().filter(|block| block.orphaned).count() as u64,
+        total_size: minted_blocks
+            .iter()
+            .filter(|block| !block.orphaned)
+            .map(|block| block.block_size)
+            .sum(),
+        scheduled_slots: scheduled_slots.as_ref().map(|slots| slots.len() as u64),
+        missed_slots: scheduled_slots.map(|slots| find_missed_slots(&slots, &minted_blocks, last_slot)),
+        pool_id: format_pool_id(pool_id.as_str(), bech32),
+        minted_blocks,
+    })
+}
+
+/// Where sync sends the block report of the epoch that just ended, and for which pools
+#[derive(Debug)]
+pub(crate) struct PostEpochReport {
+    pub(crate) byron: ByronGenesis,
+    pub(crate) shelley: ShelleyGenesis,
+    pub(crate) shelley_transition_epoch: u64,
+    pub(crate) pool_ids: Vec<PoolId>,
+    pub(crate) out: Out,
+}
+
+impl PostEpochReport {
+    pub(crate) fn new(
+        byron_genesis: &Path,
+        shelley_genesis: &Path,
+        shelley_transition_epoch: &Option<u64>,
+        pool_ids: &[PoolId],
+        out: Out,
+    ) -> Result<Self, Error> {
+        let byron = read_byron_genesis(byron_genesis)?;
+        let shelley = read_shelley_genesis(shelley_genesis)?;
+        let shelley_transition_epoch = match *shelley_transition_epoch {
+            None => guess_shelley_transition_epoch(shelley.network_magic),
+            Some(value) => value,
+        };
+        Ok(PostEpochReport {
+            byron,
+            shelley,
+            shelley_transition_epoch,
+            pool_ids: pool_ids.to_vec(),
+            out,
+        })
+    }
+
+    pub(crate) fn epoch_of_slot(&self, slot_number: u64) -> u64 {
+        get_first_slot_of_epoch(&self.byron, &self.shelley, slot_number, self.shelley_transition_epoch).0
+    }
+
+    /// Write the pool blocks report of each pool for the epoch as a json line
+    pub(crate) fn report(&self, block_store: &mut dyn BlockStore, epoch: u64) -> Result<(), Error> {
+        for pool_id in self.pool_ids.iter() {
+            let pool_blocks = epoch_pool_blocks(
+                block_store,
+                &self.byron,
+                &self.shelley,
+                self.shelley_transition_epoch,
+                pool_id,
+                epoch,
+                false,
+            )?;
+            info!(
+                "Epoch {} report for pool {}: {} of {} scheduled blocks minted, {} orphaned",
+                epoch,
+                pool_id,
+                pool_blocks.blocks,
+                pool_blocks
+                    .scheduled_slots
+                    .map_or_else(|| "?".to_string(), |scheduled_slots| scheduled_slots.to_string()),
+                pool_blocks.orphaned_blocks
+            );
+            let mut body = serde_json::to_vec(&pool_blocks)?;
+            body.push(b'\n');
+            reporter::append_report(&self.out, &body)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the scheduled slots up to last_slot that have no non-orphaned block from the pool
+fn find_missed_slots(scheduled_slots: &[u64], minted_blocks: &[PoolBlock], last_slot: u64) -> Vec<u64> {
+    scheduled_slots
+        .iter()
+        .filter(|slot| **slot <= last_slot)
+        .filter(|slot| {
+            !minted_blocks
+                .iter()
+                .any(|block| !block.orphaned && block.slot_number == **slot)
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(slot_number: u64, orphaned: bool) -> PoolBlock {
+        PoolBlock {
+            block_number: slot_number,
+            slot_number,
+            at: String::new(),
+            hash: String::new(),
+            block_size: 0,
+            orphaned,
+        }
+    }
+
+    #[test]
+    fn test_find_missed_slots() {
+        let minted_blocks = vec![block(10, false), block(20, true), block(30, false)];
+        // slot 20 lost its block to a fork and slot 50 is still in the future
+        assert_eq!(
+            find_missed_slots(&[10, 20, 30, 40, 50], &minted_blocks, 45),
+            vec![20, 40]
+        );
+    }
+}
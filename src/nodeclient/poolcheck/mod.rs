@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::nodeclient::leaderlog::read_vrf_key;
+use crate::nodeclient::reporter;
+use crate::nodeclient::snapshot;
+use crate::nodeclient::snapshot::{PoolRegistrations, QueryTimeouts};
+use crate::nodeclient::sync::pooltool;
+use crate::nodeclient::sync::pooltool::Pool;
+use crate::nodeclient::vrfkey::{vrf_vkey_hash, vrf_vkey_of_skey};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(PathBuf),
+
+    #[error("Config error: {0}")]
+    Config(#[from] pooltool::ConfigError),
+
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] snapshot::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PoolCheck {
+    status: String,
+    epoch: u64,
+    pools: Vec<PoolCheckResult>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PoolCheckResult {
+    status: String,
+    name: String,
+    pool_id: String,
+    registered: bool,
+    /// Set when the pool has a retirement certificate on chain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retiring_epoch: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vrf_vkey_hash: Option<String>,
+    /// The vrf vkey hash of a re-registration that takes effect at the next epoch boundary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    future_vrf_vkey_hash: Option<String>,
+    /// Only set when the pool has a vrf_skey in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_vrf_vkey_hash: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+/// Check every pool of a pooltool config against its registration on chain. Returns whether all pools passed.
+pub(crate) async fn pool_check(socket_path: &PathBuf, network_magic: u64, config: &Path) -> Result<bool, Error> {
+    if !config.exists() {
+        return Err(Error::InvalidPath(config.to_path_buf()));
+    }
+    let pooltool_config = pooltool::get_pooltool_config(config)?;
+    let pool_registrations =
+        snapshot::get_pool_registrations(socket_path, network_magic, &QueryTimeouts::default()).await?;
+
+    let pools: Vec<PoolCheckResult> = pooltool_config
+        .pools
+        .iter()
+        .map(|pool| check_pool(pool, &pool_registrations))
+        .collect();
+    let passed = pools.iter().all(|pool| pool.errors.is_empty());
+    reporter::report(&PoolCheck {
+        status: if passed { "ok" } else { "error" }.to_string(),
+        epoch: pool_registrations.epoch,
+        pools,
+    })?;
+
+    Ok(passed)
+}
+
+fn check_pool(pool: &Pool, pool_registrations: &PoolRegistrations) -> PoolCheckResult {
+    let mut result = PoolCheckResult {
+        name: pool.name.clone(),
+        pool_id: pool.pool_id.to_string(),
+        ..Default::default()
+    };
+    let local_vrf_vkey_hash = match pool.vrf_skey {
+        Some(ref vrf_skey) => match local_vrf_vkey_hash(vrf_skey) {
+            Ok(vrf_vkey_hash) => Some(vrf_vkey_hash),
+            Err(error) => {
+                result.errors.push(error);
+                None
+            }
+        },
+        None => None,
+    };
+    let pool_id = pool.pool_id.to_bytes();
+
+    result.vrf_vkey_hash = pool_registrations.vrf_vkey_hashes.get(&pool_id).cloned();
+    result.future_vrf_vkey_hash = pool_registrations.future_vrf_vkey_hashes.get(&pool_id).cloned();
+    result.retiring_epoch = pool_registrations.retiring.get(&pool_id).copied();
+    result.registered = result.vrf_vkey_hash.is_some();
+    if !result.registered {
+        result
+            .errors
+            .push("pool is not registered on chain or has retired".to_string());
+    }
+    if let Some(retiring_epoch) = result.retiring_epoch {
+        result
+            .errors
+            .push(format!("pool retires at the start of epoch {retiring_epoch}"));
+    }
+    // a re-registered vrf key is the one the pool has to run with from the next epoch on
+    if let (Some(local), Some(registered)) = (
+        local_vrf_vkey_hash.as_ref(),
+        result.future_vrf_vkey_hash.as_ref().or(result.vrf_vkey_hash.as_ref()),
+    ) {
+        if local != registered {
+            result.errors.push(format!(
+                "vrf_skey has vrf vkey hash {local}, the pool registered {registered}"
+            ));
+        }
+    }
+    result.local_vrf_vkey_hash = local_vrf_vkey_hash;
+    result.status = if result.errors.is_empty() { "ok" } else { "error" }.to_string();
+    result
+}
+
+/// The vrf vkey hash of a vrf.skey, which holds the secret seed followed by the verification key
+fn local_vrf_vkey_hash(vrf_skey: &Path) -> Result<String, String> {
+    let vrf_skey = read_vrf_key(vrf_skey).map_err(|error| format!("cannot read {}: {}", vrf_skey.display(), error))?;
+    match vrf_vkey_of_skey(&vrf_skey.key) {
+        Some(vrf_vkey) if vrf_skey.key_type == "VrfSigningKey_PraosVRF" => Ok(hex::encode(vrf_vkey_hash(vrf_vkey))),
+        _ => Err("vrf_skey is not a vrf signing key".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const POOL_ID: &str = "00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114";
+
+    fn pool(pool_id: &str) -> Pool {
+        serde_json::from_str(&format!(
+            r#"{{"name": "TCKR", "pool_id": "{pool_id}", "host": "127.0.0.1", "port": 3001}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_pool() {
+        let pool_id = hex::decode(POOL_ID).unwrap();
+        let mut pool_registrations = PoolRegistrations {
+            epoch: 512,
+            vrf_vkey_hashes: HashMap::from([(pool_id.clone(), "aa".to_string())]),
+            ..Default::default()
+        };
+        let result = check_pool(&pool(POOL_ID), &pool_registrations);
+        assert_eq!(result.status, "ok");
+        assert!(result.registered);
+        assert_eq!(result.vrf_vkey_hash, Some("aa".to_string()));
+
+        pool_registrations.retiring.insert(pool_id, 513);
+        let result = check_pool(&pool(POOL_ID), &pool_registrations);
+        assert_eq!(result.status, "error");
+        assert_eq!(result.retiring_epoch, Some(513));
+
+        let result = check_pool(
+            &pool("00beef8710427e328a29555283c74b202b40bec9a62630a9f03b1e18"),
+            &pool_registrations,
+        );
+        assert_eq!(result.status, "error");
+        assert!(!result.registered);
+    }
+}
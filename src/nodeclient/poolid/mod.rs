@@ -0,0 +1,197 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use bech32::{Bech32, Hrp};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A pool id is the blake2b-224 hash of the pool's cold verification key
+const POOL_ID_LENGTH: usize = 28;
+/// A block hash is the blake2b-256 hash of the block header
+const BLOCK_HASH_LENGTH: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid pool id {0}: {1}")]
+    InvalidPoolId(String, String),
+
+    #[error("Invalid block hash {0}: {1}")]
+    InvalidBlockHash(String, String),
+}
+
+/// A pool id, accepted as hex or bech32 (pool1...) and checked when it is parsed so a mistyped id fails right away
+/// instead of matching nothing deep in a command. It is kept as lower-case hex, the way the db and pooltool store it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PoolId(String);
+
+impl PoolId {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        hex::decode(&self.0).expect("checked when parsed")
+    }
+
+    pub(crate) fn to_bech32(&self) -> String {
+        bech32::encode::<Bech32>(Hrp::parse_unchecked("pool"), &self.to_bytes()).expect("a pool id fits in bech32")
+    }
+}
+
+/// A hex pool id as stored in the db, as bech32 when bech32 is set. Anything that is not a pool id is kept as is.
+pub(crate) fn format_pool_id(pool_id: &str, bech32: bool) -> String {
+    match PoolId::from_str(pool_id) {
+        Ok(parsed_pool_id) if bech32 => parsed_pool_id.to_bech32(),
+        _ => pool_id.to_string(),
+    }
+}
+
+impl FromStr for PoolId {
+    type Err = Error;
+    fn from_str(pool_id: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| Error::InvalidPoolId(pool_id.to_string(), reason);
+        let bytes = match hex::decode(pool_id) {
+            Ok(bytes) => bytes,
+            Err(_) => match bech32::decode(pool_id) {
+                Ok((hrp, bytes)) if hrp.as_str() == "pool" => bytes,
+                Ok((hrp, _)) => {
+                    return Err(invalid(format!(
+                        "this is a bech32 {hrp} value, expected hex or bech32 pool1..."
+                    )))
+                }
+                Err(_) => return Err(invalid("expected hex or bech32 pool1...".to_string())),
+            },
+        };
+        if bytes.len() != POOL_ID_LENGTH {
+            return Err(invalid(format!(
+                "expected {POOL_ID_LENGTH} bytes but got {}",
+                bytes.len()
+            )));
+        }
+        Ok(PoolId(hex::encode(bytes)))
+    }
+}
+
+impl TryFrom<String> for PoolId {
+    type Error = Error;
+    fn try_from(pool_id: String) -> Result<Self, Self::Error> {
+        PoolId::from_str(&pool_id)
+    }
+}
+
+impl From<PoolId> for String {
+    fn from(pool_id: PoolId) -> Self {
+        pool_id.0
+    }
+}
+
+impl Display for PoolId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A full block hash in hex, checked when it is parsed like PoolId. Partial hashes, as validate and block-detail
+/// take, stay plain strings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BlockHash(String);
+
+impl BlockHash {
+    /// The hash of a header as received from the chain
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        BlockHash::from_str(&hex::encode(bytes))
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        hex::decode(&self.0).expect("checked when parsed")
+    }
+}
+
+impl FromStr for BlockHash {
+    type Err = Error;
+    fn from_str(block_hash: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| Error::InvalidBlockHash(block_hash.to_string(), reason);
+        let bytes = hex::decode(block_hash).map_err(|_| invalid("expected hex".to_string()))?;
+        if bytes.len() != BLOCK_HASH_LENGTH {
+            return Err(invalid(format!(
+                "expected {BLOCK_HASH_LENGTH} bytes but got {}",
+                bytes.len()
+            )));
+        }
+        Ok(BlockHash(hex::encode(bytes)))
+    }
+}
+
+impl TryFrom<String> for BlockHash {
+    type Error = Error;
+    fn try_from(block_hash: String) -> Result<Self, Self::Error> {
+        BlockHash::from_str(&block_hash)
+    }
+}
+
+impl From<BlockHash> for String {
+    fn from(block_hash: BlockHash) -> Self {
+        block_hash.0
+    }
+}
+
+impl Display for BlockHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL_ID: &str = "00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114";
+
+    #[test]
+    fn test_parse_pool_id() {
+        let pool_id = PoolId::from_str(POOL_ID).unwrap();
+        assert_eq!(pool_id.as_str(), POOL_ID);
+        assert_eq!(PoolId::from_str(&pool_id.to_bech32()).unwrap(), pool_id);
+        assert_eq!(PoolId::from_str(&POOL_ID.to_uppercase()).unwrap(), pool_id);
+
+        // a vrf key hash is 32 bytes
+        assert!(PoolId::from_str(&"ab".repeat(32)).is_err());
+        let stake_address = bech32::encode::<Bech32>(Hrp::parse_unchecked("stake"), &[0xe1u8; 29]).unwrap();
+        assert!(PoolId::from_str(&stake_address)
+            .unwrap_err()
+            .to_string()
+            .contains("bech32 stake value"));
+        assert!(PoolId::from_str("my-pool").is_err());
+    }
+
+    #[test]
+    fn test_parse_block_hash() {
+        let hash = "5F9E7E".repeat(10) + "abcd";
+        let block_hash = BlockHash::from_str(&hash).unwrap();
+        assert_eq!(block_hash.to_string(), hash.to_lowercase());
+        assert_eq!(block_hash.to_bytes().len(), 32);
+        // a pool id is too short to be a block hash
+        assert!(BlockHash::from_str(POOL_ID)
+            .unwrap_err()
+            .to_string()
+            .contains("expected 32 bytes but got 28"));
+        assert!(BlockHash::from_str("tip").is_err());
+        assert_eq!(BlockHash::from_bytes(&block_hash.to_bytes()).unwrap(), block_hash);
+        assert!(BlockHash::from_bytes(&[0; 28]).is_err());
+    }
+
+    #[test]
+    fn test_format_pool_id() {
+        let bech32_pool_id = format_pool_id(POOL_ID, true);
+        assert!(bech32_pool_id.starts_with("pool1"));
+        assert_eq!(PoolId::from_str(&bech32_pool_id).unwrap().as_str(), POOL_ID);
+        assert_eq!(format_pool_id(POOL_ID, false), POOL_ID);
+        assert_eq!(format_pool_id("", true), "");
+    }
+}
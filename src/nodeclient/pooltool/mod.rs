@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::nodeclient::http;
+use crate::nodeclient::http::HttpSettings;
+use crate::nodeclient::poolid::{BlockHash, PoolId};
+
+const POOLTOOL_API_URL: &str = "https://api.pooltool.io";
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("HTTP error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Client(#[from] http::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("PoolTool returned HTTP {status}: {body}")]
+    Http { status: u16, body: String },
+
+    #[error("PoolTool rejected the request: {0}")]
+    Rejected(String),
+}
+
+/// Tip notification for blocks without a block vrf (tpraos headers)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SendStatsV0Request {
+    pub(crate) api_key: String,
+    pub(crate) pool_id: PoolId,
+    pub(crate) data: SendStatsV0Data,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SendStatsV0Data {
+    pub(crate) node_id: String,
+    pub(crate) version: String,
+    pub(crate) at: String,
+    pub(crate) block_no: u64,
+    pub(crate) slot_no: u64,
+    pub(crate) block_hash: BlockHash,
+    pub(crate) parent_hash: BlockHash,
+    pub(crate) leader_vrf: String,
+    pub(crate) leader_vrf_proof: String,
+    pub(crate) node_v_key: String,
+    pub(crate) protocol_major_version: u64,
+    pub(crate) protocol_minor_version: u64,
+    pub(crate) platform: String,
+}
+
+/// Tip notification for blocks with a block vrf (praos headers)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SendStatsV1Request {
+    pub(crate) api_key: String,
+    pub(crate) pool_id: PoolId,
+    pub(crate) data: SendStatsV1Data,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SendStatsV1Data {
+    pub(crate) node_id: String,
+    pub(crate) version: String,
+    pub(crate) at: String,
+    pub(crate) block_no: u64,
+    pub(crate) slot_no: u64,
+    pub(crate) block_hash: BlockHash,
+    pub(crate) parent_hash: BlockHash,
+    pub(crate) leader_vrf: String,
+    pub(crate) block_vrf: String,
+    pub(crate) block_vrf_proof: String,
+    pub(crate) node_v_key: String,
+    pub(crate) protocol_major_version: u64,
+    pub(crate) protocol_minor_version: u64,
+    pub(crate) platform: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SendSlotsRequest {
+    pub(crate) api_key: String,
+    pub(crate) pool_id: PoolId,
+    pub(crate) epoch: u64,
+    pub(crate) slot_qty: u64,
+    pub(crate) hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) override_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) prev_slots: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PooltoolResponse {
+    pub(crate) success: bool,
+    #[serde(default)]
+    pub(crate) message: Option<String>,
+}
+
+pub(crate) struct PooltoolClient {
+    client: reqwest::blocking::Client,
+    base_url: String,
+}
+
+impl PooltoolClient {
+    pub(crate) fn new() -> Result<Self, Error> {
+        let settings = HttpSettings::from_env()?;
+        Ok(PooltoolClient {
+            client: http::client(&settings, settings.pooltool_certificate.as_deref())?,
+            base_url: POOLTOOL_API_URL.to_string(),
+        })
+    }
+
+    pub(crate) fn send_stats_v0(&self, request: &SendStatsV0Request) -> Result<PooltoolResponse, Error> {
+        self.post("/v0/sendstats", request)
+    }
+
+    pub(crate) fn send_stats_v1(&self, request: &SendStatsV1Request) -> Result<PooltoolResponse, Error> {
+        self.post("/v1/sendstats", request)
+    }
+
+    pub(crate) fn send_slots(&self, request: &SendSlotsRequest) -> Result<PooltoolResponse, Error> {
+        self.post("/v0/sendslots", request)
+    }
+
+    fn post<T: Serialize>(&self, path: &str, request: &T) -> Result<PooltoolResponse, Error> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .body(serde_json::ser::to_string(request)?)
+            .send()?;
+        let status = response.status();
+        let body = response.text()?;
+        if !status.is_success() {
+            return Err(Error::Http {
+                status: status.as_u16(),
+                body,
+            });
+        }
+        parse_response(&body)
+    }
+}
+
+/// Parse a PoolTool response body, treating `"success": false` as an error
+pub(crate) fn parse_response(body: &str) -> Result<PooltoolResponse, Error> {
+    let response: PooltoolResponse = serde_json::from_str(body)?;
+    if !response.success {
+        return Err(Error::Rejected(
+            response.message.unwrap_or_else(|| "no message".to_string()),
+        ));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_sendstats_success() {
+        let response = parse_response(include_str!("fixtures/sendstats_success.json")).unwrap();
+        assert!(response.success);
+        assert_eq!(response.message.as_deref(), Some("Tip received"));
+    }
+
+    #[test]
+    fn test_parse_sendstats_failure() {
+        match parse_response(include_str!("fixtures/sendstats_failure.json")) {
+            Err(Error::Rejected(message)) => assert_eq!(message, "Invalid api key"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_sendslots_success() {
+        let response = parse_response(include_str!("fixtures/sendslots_success.json")).unwrap();
+        assert!(response.success);
+    }
+
+    #[test]
+    fn test_parse_non_json_body() {
+        assert!(matches!(
+            parse_response("<html>Bad Gateway</html>"),
+            Err(Error::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_send_slots_request_serialization() {
+        let request = SendSlotsRequest {
+            api_key: "api-key".to_string(),
+            pool_id: PoolId::from_str("00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114").unwrap(),
+            epoch: 512,
+            slot_qty: 2,
+            hash: "abcd".to_string(),
+            override_time: None,
+            prev_slots: Some("[1,2]".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            "{\"apiKey\":\"api-key\",\"poolId\":\"00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114\",\"epoch\":512,\"slotQty\":2,\"hash\":\"abcd\",\"prevSlots\":\"[1,2]\"}"
+        );
+    }
+}
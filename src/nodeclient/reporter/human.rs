@@ -0,0 +1,181 @@
+use std::fmt::Display;
+use std::io::{stdout, IsTerminal};
+
+use tracing::error;
+
+use crate::nodeclient::reporter;
+
+/// The colors human output highlights values with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Color {
+    Green,
+    Red,
+    Yellow,
+}
+
+impl Color {
+    fn ansi_code(&self) -> &'static str {
+        match self {
+            Color::Green => "32",
+            Color::Red => "31",
+            Color::Yellow => "33",
+        }
+    }
+}
+
+/// Color only goes to a terminal and never with NO_COLOR set, see https://no-color.org
+pub(crate) fn color_enabled() -> bool {
+    reporter::writes_to_stdout() && stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+pub(crate) fn paint(text: &str, color: Option<Color>, color_enabled: bool) -> String {
+    match color {
+        Some(color) if color_enabled => format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), text),
+        _ => text.to_string(),
+    }
+}
+
+/// A table cell, optionally colored
+#[derive(Debug, Clone)]
+pub(crate) struct Cell {
+    text: String,
+    color: Option<Color>,
+}
+
+impl Cell {
+    pub(crate) fn plain<T: Display>(text: T) -> Self {
+        Cell {
+            text: text.to_string(),
+            color: None,
+        }
+    }
+
+    pub(crate) fn colored<T: Display>(text: T, color: Color) -> Self {
+        Cell {
+            text: text.to_string(),
+            color: Some(color),
+        }
+    }
+}
+
+/// Columns of text padded to the widest cell. The widths are measured without the color codes so colored cells line
+/// up with plain ones.
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub(crate) fn new(headers: &[&str]) -> Self {
+        Table {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: vec![],
+        }
+    }
+
+    /// A table without headers for name and value pairs
+    pub(crate) fn key_value() -> Self {
+        Table::default()
+    }
+
+    pub(crate) fn row(&mut self, cells: Vec<Cell>) -> &mut Self {
+        self.rows.push(cells);
+        self
+    }
+
+    pub(crate) fn render(&self, color_enabled: bool) -> String {
+        let columns = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or_default()
+            .max(self.headers.len());
+        let mut widths = vec![0usize; columns];
+        for (column, header) in self.headers.iter().enumerate() {
+            widths[column] = header.chars().count();
+        }
+        for row in self.rows.iter() {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(cell.text.chars().count());
+            }
+        }
+
+        let mut out = String::new();
+        if !self.headers.is_empty() {
+            let headers: Vec<Cell> = self.headers.iter().map(Cell::plain).collect();
+            out.push_str(&render_line(&headers, &widths, color_enabled));
+            let rule: Vec<Cell> = widths.iter().map(|width| Cell::plain("-".repeat(*width))).collect();
+            out.push_str(&render_line(&rule, &widths, color_enabled));
+        }
+        for row in self.rows.iter() {
+            out.push_str(&render_line(row, &widths, color_enabled));
+        }
+        out
+    }
+}
+
+fn render_line(cells: &[Cell], widths: &[usize], color_enabled: bool) -> String {
+    let mut line = String::new();
+    for (column, cell) in cells.iter().enumerate() {
+        if column > 0 {
+            line.push_str("  ");
+        }
+        line.push_str(&paint(&cell.text, cell.color, color_enabled));
+        // the last column is not padded so lines carry no trailing spaces
+        if column + 1 < cells.len() {
+            line.push_str(&" ".repeat(widths[column] - cell.text.chars().count()));
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Write an error as a line of text, the human counterpart of reporter::report_error
+pub(crate) fn report_error<T: Display>(error_message: T) {
+    reporter::mark_error_reported();
+    let line = paint(&format!("Error: {error_message}"), Some(Color::Red), color_enabled());
+    if let Err(error) = reporter::write_report(format!("{line}\n").as_bytes()) {
+        error!("Could not report error: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let mut table = Table::new(&["No", "Slot", "At"]);
+        table
+            .row(vec![
+                Cell::plain(1),
+                Cell::plain(137865432),
+                Cell::plain("2024-10-05 10:00:00"),
+            ])
+            .row(vec![
+                Cell::plain(12),
+                Cell::colored(137900000, Color::Green),
+                Cell::plain("now"),
+            ]);
+        assert_eq!(
+            table.render(false),
+            "No  Slot       At\n--  ---------  -------------------\n1   137865432  2024-10-05 10:00:00\n12  137900000  now\n"
+        );
+        // color codes do not count towards the width
+        assert_eq!(
+            table.render(true).lines().nth(3).unwrap(),
+            "12  \x1b[32m137900000\x1b[0m  now"
+        );
+    }
+
+    #[test]
+    fn test_render_key_value() {
+        let mut table = Table::key_value();
+        table
+            .row(vec![Cell::plain("Status"), Cell::colored("synced", Color::Green)])
+            .row(vec![Cell::plain("Tip slot"), Cell::plain(137865432)]);
+        assert_eq!(table.render(false), "Status    synced\nTip slot  137865432\n");
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::error;
+
+use crate::nodeclient::http;
+use crate::nodeclient::http::HttpSettings;
+use crate::Out;
+
+pub(crate) mod human;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("HTTP client error: {0}")]
+    Client(#[from] http::Error),
+
+    #[error("Webhook error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("Webhook error: {0}")]
+    Webhook(String),
+}
+
+static OUT: OnceLock<Out> = OnceLock::new();
+// the first report truncates an --out file, the ones after it in the same run are appended
+static FILE_STARTED: AtomicBool = AtomicBool::new(false);
+static ERROR_REPORTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportError {
+    status: String,
+    error_message: String,
+}
+
+/// Set where reports go for the rest of the run. Only the first call has an effect.
+pub(crate) fn set_out(out: Out) {
+    let _ = OUT.set(out);
+}
+
+/// Whether reports go to stdout rather than a file or webhook
+pub(crate) fn writes_to_stdout() -> bool {
+    matches!(OUT.get().unwrap_or(&Out::Stdout), Out::Stdout)
+}
+
+/// Write a command result as pretty JSON
+pub(crate) fn report<T: Serialize>(value: &T) -> Result<(), Error> {
+    write_report(&serde_json::to_vec_pretty(value)?)
+}
+
+/// Write a command result as JSON on a single line
+pub(crate) fn report_compact<T: Serialize>(value: &T) -> Result<(), Error> {
+    let mut body = serde_json::to_vec(value)?;
+    body.push(b'\n');
+    write_report(&body)
+}
+
+/// Write `{"status": "error", "errorMessage": ...}`. Errors reporting it are only logged since there is nowhere
+/// else to send them.
+pub(crate) fn report_error<T: Display>(error_message: T) {
+    mark_error_reported();
+    if let Err(error) = report(&ReportError {
+        status: "error".to_string(),
+        error_message: format!("{error_message}"),
+    }) {
+        error!("Could not report error: {}", error);
+    }
+}
+
+/// Whether the run reported an error so far
+pub(crate) fn error_reported() -> bool {
+    ERROR_REPORTED.load(Ordering::SeqCst)
+}
+
+pub(crate) fn mark_error_reported() {
+    ERROR_REPORTED.store(true, Ordering::SeqCst);
+}
+
+/// Write an already rendered report, e.g. a plain text schedule, to the --out destination
+pub(crate) fn write_report(body: &[u8]) -> Result<(), Error> {
+    let out = OUT.get().unwrap_or(&Out::Stdout);
+    let append = matches!(out, Out::File(_)) && FILE_STARTED.swap(true, Ordering::SeqCst);
+    write_to(out, body, append)
+}
+
+/// Write a report to another destination than --out. A file is appended to so it keeps every report written to it.
+pub(crate) fn append_report(out: &Out, body: &[u8]) -> Result<(), Error> {
+    write_to(out, body, true)
+}
+
+fn write_to(out: &Out, body: &[u8], append: bool) -> Result<(), Error> {
+    match out {
+        Out::Stdout => {
+            let mut out = stdout();
+            out.write_all(body)?;
+            out.flush()?;
+        }
+        Out::File(path) => {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(path)?;
+            file.write_all(body)?;
+        }
+        Out::Webhook(url) => {
+            let url = url.clone();
+            let body = body.to_vec();
+            // the blocking client must not run on a tokio runtime thread
+            std::thread::spawn(move || post_report(&url, body))
+                .join()
+                .map_err(|_| Error::Webhook("webhook thread panicked".to_string()))??;
+        }
+    }
+
+    Ok(())
+}
+
+fn post_report(url: &str, body: Vec<u8>) -> Result<(), Error> {
+    // plain text reports like a bare nonce or a rendered schedule are not JSON
+    let content_type = match serde_json::from_slice::<serde::de::IgnoredAny>(&body) {
+        Ok(_) => "application/json",
+        Err(_) => "text/plain",
+    };
+    let client = http::client(&HttpSettings::from_env()?, None)?;
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(body)
+        .send()?;
+    if !response.status().is_success() {
+        return Err(Error::Webhook(format!("{} returned {}", url, response.status())));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::reporter;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Run state error: {0}")]
+    RunState(String),
+}
+
+static STATE_FILE: OnceLock<PathBuf> = OnceLock::new();
+// the sendtip threads of one process take turns to rewrite the file
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RunState {
+    last_runs: Vec<LastRun>,
+}
+
+/// The last successful run of an operation, per pool and epoch for the operations that have them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LastRun {
+    pub(crate) operation: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) pool_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) epoch: Option<u64>,
+    /// The node the operation talked to, for the operations that keep one entry per peer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) peer: Option<String>,
+    pub(crate) at: String,
+    pub(crate) outcome: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LastRuns {
+    status: String,
+    last_runs: Vec<LastRun>,
+}
+
+impl RunState {
+    fn update(&mut self, last_run: LastRun) {
+        self.last_runs.retain(|run| {
+            run.operation != last_run.operation
+                || run.pool_id != last_run.pool_id
+                || run.epoch != last_run.epoch
+                || run.peer != last_run.peer
+        });
+        self.last_runs.push(last_run);
+    }
+
+    fn matching(&self, operation: &Option<String>, pool_id: &Option<String>, epoch: &Option<u64>) -> Vec<LastRun> {
+        self.last_runs
+            .iter()
+            .filter(|run| match operation {
+                Some(operation) => run.operation == *operation,
+                None => true,
+            })
+            .filter(|run| pool_id.is_none() || run.pool_id == *pool_id)
+            .filter(|run| epoch.is_none() || run.epoch == *epoch)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Set the file successful runs are recorded in for the rest of the run. Nothing is recorded without one.
+pub(crate) fn set_state_file(state_file: Option<PathBuf>) {
+    if let Some(state_file) = state_file {
+        let _ = STATE_FILE.set(state_file);
+    }
+}
+
+/// Record a successful run. The operation itself already succeeded, so failing to record it is only logged.
+pub(crate) fn record(operation: &str, pool_id: Option<&str>, epoch: Option<u64>, outcome: String) {
+    write_last_run(LastRun {
+        operation: operation.to_string(),
+        pool_id: pool_id.map(str::to_string),
+        epoch,
+        peer: None,
+        at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        outcome,
+    });
+}
+
+/// Record the last outcome of an operation with a peer, keeping one entry per peer
+pub(crate) fn record_peer(operation: &str, peer: &str, outcome: String) {
+    write_last_run(LastRun {
+        operation: operation.to_string(),
+        pool_id: None,
+        epoch: None,
+        peer: Some(peer.to_string()),
+        at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        outcome,
+    });
+}
+
+fn write_last_run(last_run: LastRun) {
+    let state_file = match STATE_FILE.get() {
+        Some(state_file) => state_file,
+        None => return,
+    };
+    let operation = last_run.operation.clone();
+    if let Err(error) = write_run(state_file, last_run) {
+        warn!(
+            "Could not record the {} run in {}: {}",
+            operation,
+            state_file.display(),
+            error
+        );
+    }
+}
+
+fn read_run_state(state_file: &Path) -> Result<RunState, Error> {
+    if !state_file.exists() {
+        return Ok(RunState::default());
+    }
+    Ok(serde_json::from_slice(&std::fs::read(state_file)?)?)
+}
+
+fn write_run(state_file: &Path, last_run: LastRun) -> Result<(), Error> {
+    let _write_lock = WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut run_state = read_run_state(state_file)?;
+    run_state.update(last_run);
+    // replace the file in one step so a reader never sees it half written
+    let mut partial = state_file.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, serde_json::to_vec_pretty(&run_state)?)?;
+    std::fs::rename(&partial, state_file)?;
+    Ok(())
+}
+
+/// The recorded runs of an operation, none without a state file
+pub(crate) fn recorded_runs(operation: &str) -> Result<Vec<LastRun>, Error> {
+    match STATE_FILE.get() {
+        Some(state_file) => Ok(read_run_state(state_file)?.matching(&Some(operation.to_string()), &None, &None)),
+        None => Ok(vec![]),
+    }
+}
+
+/// Print the last successful runs, optionally only those of an operation, pool or epoch
+pub(crate) fn last_runs(
+    operation: &Option<String>,
+    pool_id: &Option<PoolId>,
+    epoch: &Option<u64>,
+) -> Result<(), Error> {
+    let state_file = STATE_FILE
+        .get()
+        .ok_or_else(|| Error::RunState("--state-file or CNCLI_STATE_FILE is required".to_string()))?;
+    let run_state = read_run_state(state_file)?;
+    reporter::report(&LastRuns {
+        status: "ok".to_string(),
+        last_runs: run_state.matching(operation, &pool_id.as_ref().map(PoolId::to_string), epoch),
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_run(operation: &str, pool_id: Option<&str>, epoch: Option<u64>, outcome: &str) -> LastRun {
+        LastRun {
+            operation: operation.to_string(),
+            pool_id: pool_id.map(str::to_string),
+            epoch,
+            peer: None,
+            at: "2024-10-05T10:00:00Z".to_string(),
+            outcome: outcome.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_run_state() {
+        let mut run_state = RunState::default();
+        run_state.update(last_run("sync", None, None, "block 1"));
+        run_state.update(last_run("leaderlog", Some("pool"), Some(512), "10 slots"));
+        run_state.update(last_run("leaderlog", Some("pool"), Some(513), "11 slots"));
+        run_state.update(last_run("sync", None, None, "block 2"));
+        assert_eq!(run_state.last_runs.len(), 3);
+        assert_eq!(
+            run_state.matching(&Some("sync".to_string()), &None, &None),
+            vec![last_run("sync", None, None, "block 2")]
+        );
+        assert_eq!(
+            run_state.matching(&Some("leaderlog".to_string()), &Some("pool".to_string()), &Some(512)),
+            vec![last_run("leaderlog", Some("pool"), Some(512), "10 slots")]
+        );
+        assert!(run_state
+            .matching(&Some("leaderlog".to_string()), &None, &Some(514))
+            .is_empty());
+
+        // one entry per peer
+        let keepalive = |peer: &str, outcome: &str| LastRun {
+            peer: Some(peer.to_string()),
+            ..last_run("keepalive", None, None, outcome)
+        };
+        run_state.update(keepalive("relay1:3001", "20 ms"));
+        run_state.update(keepalive("relay2:3001", "35 ms"));
+        run_state.update(keepalive("relay1:3001", "22 ms"));
+        assert_eq!(
+            run_state.matching(&Some("keepalive".to_string()), &None, &None),
+            vec![keepalive("relay2:3001", "35 ms"), keepalive("relay1:3001", "22 ms")]
+        );
+    }
+
+    #[test]
+    fn test_write_run() {
+        let state_file = std::env::temp_dir().join("cncli_test_run_state.json");
+        let _ = std::fs::remove_file(&state_file);
+        write_run(&state_file, last_run("sendslots", Some("pool"), Some(512), "ok")).unwrap();
+        write_run(&state_file, last_run("sendtip", Some("pool"), None, "block 1")).unwrap();
+        assert_eq!(read_run_state(&state_file).unwrap().last_runs.len(), 2);
+        std::fs::remove_file(&state_file).unwrap();
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use pallas_crypto::hash::Hasher;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tracing::{debug, error, info};
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::cache::CachedBlockStore;
+use crate::nodeclient::blockstore::BlockStore;
+use crate::nodeclient::leaderlog;
+use crate::nodeclient::leaderlog::{
+    epoch_consensus, epoch_nonce_from_db, get_first_slot_of_epoch, get_first_slot_of_epoch_number,
+    get_protocol_params_from_db, guess_shelley_transition_epoch, read_byron_genesis, read_shelley_genesis,
+    rollback_window, ByronGenesis, ShelleyGenesis,
+};
+use crate::nodeclient::otlp::{SpanKind, Telemetry, REQUESTS_COUNTER};
+use crate::nodeclient::poolid::PoolId;
+
+// requests are a request line and a few headers, anything longer is not for us
+pub(crate) const MAX_REQUEST_SIZE: usize = 8192;
+// a client that has not sent its request by then is not going to, don't hold the connection open for it
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] leaderlog::Error),
+
+    #[error("Invalid pool id: {0}")]
+    PoolId(#[from] crate::nodeclient::poolid::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    BadRequest(String),
+}
+
+struct ServeState {
+    /// Opened once for the process, requests take turns with it
+    block_store: Mutex<Box<dyn BlockStore + Send>>,
+    byron: ByronGenesis,
+    shelley: ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    /// Epoch nonces never change once their epoch has started, so each of those is calculated once
+    nonces: Mutex<HashMap<u64, String>>,
+    telemetry: Telemetry,
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Nonce(u64),
+    Leaderlog(String, u64),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NonceResponse {
+    status: String,
+    epoch: u64,
+    epoch_nonce: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LeaderlogResponse {
+    status: String,
+    epoch: u64,
+    pool_id: PoolId,
+    epoch_slots: u64,
+    assigned_slots: Vec<u64>,
+    /// The stake leaderlog was run with, when it was saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sigma: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool_stake: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active_stake: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorResponse {
+    status: String,
+    error_message: String,
+}
+
+/// Serve epoch nonces and saved leaderlogs from the db over HTTP until the process is stopped
+pub(crate) async fn serve(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    listen: &str,
+    telemetry: Telemetry,
+) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let byron = read_byron_genesis(byron_genesis)?;
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+    let state = Arc::new(ServeState {
+        block_store: Mutex::new(open_block_store(db_path, rollback_window(&byron, &shelley))?),
+        byron,
+        shelley,
+        shelley_transition_epoch,
+        nonces: Mutex::new(HashMap::new()),
+        telemetry,
+    });
+
+    let listener = TcpListener::bind(listen).await?;
+    info!("Serving nonces and leaderlogs on http://{}", listener.local_addr()?);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        debug!("connection from {}", peer);
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(stream, state).await {
+                error!("{}: {}", peer, error);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<ServeState>) -> Result<(), Error> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = match timeout(READ_TIMEOUT, stream.read(&mut buf)).await {
+            Ok(read) => read?,
+            Err(_) => return write_response(&mut stream, 408, &error_body("Request timeout")?, None).await,
+        };
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..read]);
+        if request.len() > MAX_REQUEST_SIZE {
+            return write_response(&mut stream, 431, &error_body("Request header too large")?, None).await;
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut lines = request.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (method, path) = (
+        request_line.next().unwrap_or_default(),
+        request_line.next().unwrap_or_default(),
+    );
+    let if_none_match = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("if-none-match"))
+        .map(|(_, value)| value.trim().to_string());
+
+    if method != "GET" {
+        return write_response(&mut stream, 405, &error_body("Only GET is supported")?, None).await;
+    }
+    let route = match parse_route(path) {
+        Some(route) => route,
+        None => return write_response(&mut stream, 404, &error_body("Not found")?, None).await,
+    };
+    state.telemetry.add(REQUESTS_COUNTER, 1);
+    let mut span = state.telemetry.span("serve.request", SpanKind::Server);
+    span.attribute("url.path", path);
+
+    // the db is read with blocking calls
+    let result = tokio::task::spawn_blocking(move || match route {
+        Route::Nonce(epoch) => nonce_body(&state, epoch),
+        Route::Leaderlog(pool_id, epoch) => leaderlog_body(&state, &pool_id, epoch),
+    })
+    .await
+    .map_err(std::io::Error::other)?;
+
+    match result {
+        Ok(body) => {
+            let etag = etag(&body);
+            if if_none_match.as_deref() == Some(etag.as_str()) {
+                span.attribute("http.response.status_code", 304u64);
+                write_response(&mut stream, 304, "", Some(&etag)).await
+            } else {
+                span.attribute("http.response.status_code", 200u64);
+                write_response(&mut stream, 200, &body, Some(&etag)).await
+            }
+        }
+        Err(error) => {
+            let status = match error {
+                Error::NotFound(_) => 404,
+                Error::BadRequest(_) | Error::PoolId(_) => 400,
+                _ => 500,
+            };
+            span.attribute("http.response.status_code", status as u64);
+            span.error(&error);
+            write_response(&mut stream, status, &error_body(error)?, None).await
+        }
+    }
+}
+
+/// Match /nonce/{epoch} and /leaderlog/{pool_id}/{epoch}, ignoring any query string
+fn parse_route(path: &str) -> Option<Route> {
+    let path = path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments[..] {
+        ["nonce", epoch] => Some(Route::Nonce(epoch.parse().ok()?)),
+        ["leaderlog", pool_id, epoch] => Some(Route::Leaderlog(pool_id.to_string(), epoch.parse().ok()?)),
+        _ => None,
+    }
+}
+
+fn open_block_store(db_path: &Path, stable_slots: u64) -> Result<Box<dyn BlockStore + Send>, Error> {
+    let block_store = blockstore::open(db_path, true)?;
+    Ok(Box::new(CachedBlockStore::new(db_path, block_store, stable_slots)))
+}
+
+fn nonce_body(state: &ServeState, epoch: u64) -> Result<String, Error> {
+    if let Some(body) = state.nonces.lock().unwrap().get(&epoch) {
+        return Ok(body.clone());
+    }
+    if epoch <= state.shelley_transition_epoch {
+        return Err(Error::BadRequest(format!(
+            "Invalid Epoch: {epoch}, shelley_transition_epoch: {}",
+            state.shelley_transition_epoch
+        )));
+    }
+
+    let mut block_store = state
+        .block_store
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    let (tip_epoch, _) = get_first_slot_of_epoch(
+        &state.byron,
+        &state.shelley,
+        tip_slot_number,
+        state.shelley_transition_epoch,
+    );
+    // the next epoch's nonce is known once the db is past the stability window
+    if epoch > tip_epoch + 1 {
+        return Err(Error::NotFound(format!(
+            "The nonce of epoch {epoch} is not known yet, db tip epoch: {tip_epoch}"
+        )));
+    }
+    let first_slot_of_epoch =
+        get_first_slot_of_epoch_number(&state.byron, &state.shelley, epoch, state.shelley_transition_epoch);
+    let consensus = epoch_consensus(block_store.as_mut(), &state.shelley, first_slot_of_epoch)?;
+    // extra entropy only ever applied under tpraos
+    let extra_entropy = if consensus == "tpraos" {
+        get_protocol_params_from_db(
+            block_store.as_mut(),
+            &state.byron,
+            &state.shelley,
+            state.shelley_transition_epoch,
+            epoch,
+        )?
+        .1
+    } else {
+        None
+    };
+    let epoch_nonce = epoch_nonce_from_db(
+        block_store.as_mut(),
+        &state.byron,
+        &state.shelley,
+        first_slot_of_epoch,
+        consensus,
+        &extra_entropy,
+    )
+    .map_err(|error| match error {
+        // not enough blocks sync'd yet
+        leaderlog::Error::Leaderlog(message) => Error::NotFound(message),
+        error => Error::Leaderlog(error),
+    })?;
+
+    let body = serde_json::to_string_pretty(&NonceResponse {
+        status: "ok".to_string(),
+        epoch,
+        epoch_nonce: hex::encode(epoch_nonce),
+    })?;
+    // the next epoch's nonce can still change with a rollback until the epoch starts
+    if epoch <= tip_epoch {
+        state.nonces.lock().unwrap().insert(epoch, body.clone());
+    }
+
+    Ok(body)
+}
+
+/// The leaderlog saved by `cncli leaderlog` or `cncli slots import`. It is read from the db on every request since
+/// running leaderlog again replaces it.
+fn leaderlog_body(state: &ServeState, pool_id: &str, epoch: u64) -> Result<String, Error> {
+    let pool_id = PoolId::from_str(pool_id)?;
+    let mut block_store = state
+        .block_store
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let assigned_slots: Vec<u64> = match block_store.get_previous_slots(epoch, &pool_id)? {
+        Some(slots) => serde_json::from_str(&slots)?,
+        None => {
+            return Err(Error::NotFound(format!(
+                "No leaderlog saved for pool {pool_id} in epoch {epoch}"
+            )))
+        }
+    };
+    let stake_history = block_store
+        .get_stake_history(&pool_id)?
+        .into_iter()
+        .find(|stake_history| stake_history.epoch == epoch);
+
+    Ok(serde_json::to_string_pretty(&LeaderlogResponse {
+        status: "ok".to_string(),
+        epoch,
+        pool_id,
+        epoch_slots: assigned_slots.len() as u64,
+        assigned_slots,
+        sigma: stake_history.as_ref().map(|stake_history| stake_history.sigma),
+        pool_stake: stake_history.as_ref().map(|stake_history| stake_history.pool_stake),
+        active_stake: stake_history.as_ref().map(|stake_history| stake_history.active_stake),
+    })?)
+}
+
+pub(crate) fn error_body<T: std::fmt::Display>(error_message: T) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(&ErrorResponse {
+        status: "error".to_string(),
+        error_message: format!("{error_message}"),
+    })?)
+}
+
+/// A strong ETag from the blake2b hash of the body
+fn etag(body: &str) -> String {
+    format!("\"{}\"", hex::encode(&Hasher::<256>::hash(body.as_bytes())[..16]))
+}
+
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    body: &str,
+    etag: Option<&str>,
+) -> Result<(), Error> {
+    let reason = match status {
+        200 => "OK",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let mut response = format!("HTTP/1.1 {status} {reason}\r\nConnection: close\r\n");
+    if let Some(etag) = etag {
+        response.push_str(&format!("ETag: {etag}\r\n"));
+    }
+    if status != 304 {
+        response.push_str(&format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            body.len()
+        ));
+    }
+    response.push_str("\r\n");
+    if status != 304 {
+        response.push_str(body);
+    }
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_route() {
+        assert_eq!(parse_route("/nonce/512"), Some(Route::Nonce(512)));
+        assert_eq!(
+            parse_route("/leaderlog/pool1qzlw7z5mutmd39ldyjnp8n650weqe55z5p8dl32n6acsgq5dr0q/512/?pretty"),
+            Some(Route::Leaderlog(
+                "pool1qzlw7z5mutmd39ldyjnp8n650weqe55z5p8dl32n6acsgq5dr0q".to_string(),
+                512
+            ))
+        );
+        assert_eq!(parse_route("/nonce/next"), None);
+        assert_eq!(parse_route("/leaderlog/512"), None);
+        assert_eq!(parse_route("/"), None);
+    }
+}
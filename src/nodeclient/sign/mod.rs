@@ -5,18 +5,11 @@ use pallas_crypto::vrf::{
 use rand::{thread_rng, Rng};
 use serde::Serialize;
 use std::fmt::Display;
-use std::io::stdout;
 use std::path::Path;
 use tracing::debug;
 
 use crate::nodeclient::leaderlog::read_vrf_key;
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct SignVerifyError {
-    status: String,
-    error_message: String,
-}
+use crate::nodeclient::reporter;
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -40,22 +33,17 @@ struct VerifySuccess {
 }
 
 pub(crate) fn create_challenge(domain: &str) -> Option<Hash<32>> {
-    let mut nonce_seed = [0u8; 64];
-    thread_rng().fill(&mut nonce_seed);
-    let nonce = hex::encode(nonce_seed);
-    match hex::decode(hex::encode("cip-0022".as_bytes()) + &*hex::encode(domain.as_bytes()) + &*nonce) {
-        Ok(challenge_seed) => {
-            let challenge = Hasher::<256>::hash(&challenge_seed);
+    let nonce = challenge_nonce();
+    match challenge_hash(domain, &nonce) {
+        Ok(challenge) => {
             debug!("challenge: {}", hex::encode(challenge));
-            serde_json::ser::to_writer_pretty(
-                &mut stdout(),
-                &ChallengeSuccess {
-                    status: "ok".to_string(),
-                    domain: domain.to_string(),
-                    nonce,
-                },
-            )
-            .unwrap();
+            if let Err(error) = reporter::report(&ChallengeSuccess {
+                status: "ok".to_string(),
+                domain: domain.to_string(),
+                nonce,
+            }) {
+                handle_error(error);
+            }
             Some(challenge)
         }
         Err(error) => {
@@ -66,33 +54,20 @@ pub(crate) fn create_challenge(domain: &str) -> Option<Hash<32>> {
 }
 
 pub(crate) fn sign_challenge(pool_vrf_skey: &Path, domain: &str, nonce: &str) {
-    let challenge_seed = hex::encode("cip-0022".as_bytes()) + &*hex::encode(domain.as_bytes()) + nonce;
-    match hex::decode(challenge_seed) {
-        Ok(challenge_seed_bytes) => {
-            let challenge_bytes = Hasher::<256>::hash(&challenge_seed_bytes);
-            debug!("challenge: {}", hex::encode(challenge_bytes));
-            match read_vrf_key(pool_vrf_skey) {
-                Ok(vrf_skey) => {
-                    if vrf_skey.key_type != "VrfSigningKey_PraosVRF" {
-                        handle_error("Pool VRF Skey must be of type: VrfSigningKey_PraosVRF");
-                        return;
+    match read_vrf_key(pool_vrf_skey) {
+        Ok(vrf_skey) => {
+            if vrf_skey.key_type != "VrfSigningKey_PraosVRF" {
+                handle_error("Pool VRF Skey must be of type: VrfSigningKey_PraosVRF");
+                return;
+            }
+            match challenge_signature(&vrf_skey.key, domain, nonce) {
+                Ok(signature) => {
+                    if let Err(error) = reporter::report(&SignSuccess {
+                        status: "ok".to_string(),
+                        signature,
+                    }) {
+                        handle_error(error);
                     }
-
-                    let vrf_skey: &[u8; VRF_SECRET_KEY_SIZE] = vrf_skey.key[0..VRF_SECRET_KEY_SIZE]
-                        .try_into()
-                        .expect("Invalid VRF signing key length");
-                    let vrf_skey = VrfSecretKey::from(vrf_skey);
-                    let vrf_proof = vrf_skey.prove(challenge_bytes.as_slice());
-                    let signature = vrf_proof.signature();
-                    debug!("signature: {}", hex::encode(signature));
-                    serde_json::ser::to_writer_pretty(
-                        &mut stdout(),
-                        &SignSuccess {
-                            status: "ok".to_string(),
-                            signature: hex::encode(signature),
-                        },
-                    )
-                    .unwrap();
                 }
                 Err(error) => handle_error(error),
             }
@@ -101,6 +76,20 @@ pub(crate) fn sign_challenge(pool_vrf_skey: &Path, domain: &str, nonce: &str) {
     }
 }
 
+/// Sign the challenge for domain and nonce with the secret key bytes of a pool's vrf.skey, as hex
+pub(crate) fn challenge_signature(vrf_skey: &[u8], domain: &str, nonce: &str) -> Result<String, hex::FromHexError> {
+    let challenge_bytes = challenge_hash(domain, nonce)?;
+    debug!("challenge: {}", hex::encode(challenge_bytes));
+    let vrf_skey: &[u8; VRF_SECRET_KEY_SIZE] = vrf_skey[0..VRF_SECRET_KEY_SIZE]
+        .try_into()
+        .expect("Invalid VRF signing key length");
+    let vrf_skey = VrfSecretKey::from(vrf_skey);
+    let vrf_proof = vrf_skey.prove(challenge_bytes.as_slice());
+    let signature = hex::encode(vrf_proof.signature());
+    debug!("signature: {}", &signature);
+    Ok(signature)
+}
+
 pub(crate) fn verify_challenge(
     pool_vrf_vkey: &Path,
     pool_vrf_vkey_hash: &str,
@@ -108,70 +97,18 @@ pub(crate) fn verify_challenge(
     nonce: &str,
     signature: &str,
 ) {
-    let challenge_seed = hex::encode("cip-0022".as_bytes()) + &*hex::encode(domain.as_bytes()) + nonce;
-    match hex::decode(challenge_seed) {
-        Ok(challenge_seed_bytes) => {
-            let challenge_bytes = Hasher::<256>::hash(&challenge_seed_bytes);
-            debug!("challenge: {}", hex::encode(challenge_bytes));
-            match read_vrf_key(pool_vrf_vkey) {
-                Ok(vrf_vkey) => {
-                    if vrf_vkey.key_type != "VrfVerificationKey_PraosVRF" {
-                        handle_error("Pool VRF Vkey must be of type: VrfVerificationKey_PraosVRF");
-                        return;
-                    }
-                    // Verify that the vkey the client supplied is the same as the one on-chain
-                    let vkey_hash_verify = hex::encode(Hasher::<224>::hash(&vrf_vkey.key[0..VRF_SECRET_KEY_SIZE]));
-                    debug!("vkey_hash_verify: {}", &vkey_hash_verify);
-
-                    if pool_vrf_vkey_hash != vkey_hash_verify {
-                        handle_error(format!(
-                            "Hash of pool-vrf-vkey({vkey_hash_verify}) did not match supplied pool-vrf-vkey-hash({pool_vrf_vkey_hash})"
-                        ));
-                        return;
-                    }
-
-                    let vrf_public_key_bytes: [u8; VRF_PUBLIC_KEY_SIZE] =
-                        match vrf_vkey.key[0..VRF_PUBLIC_KEY_SIZE].try_into() {
-                            Ok(slice) => slice,
-                            Err(_) => {
-                                handle_error("Invalid VRF public key length");
-                                return;
-                            }
-                        };
-
-                    // Verify that the signature is a valid format. This will fail if the signature is mal-formed
-                    match hex::decode(signature) {
-                        Ok(signature_bytes) => {
-                            let signature_slice: [u8; VRF_PROOF_SIZE] = match signature_bytes.as_slice().try_into() {
-                                Ok(slice) => slice,
-                                Err(_) => {
-                                    handle_error("Invalid signature length");
-                                    return;
-                                }
-                            };
-                            let vrf_public_key = VrfPublicKey::from(&vrf_public_key_bytes);
-                            let vrf_proof: VrfProof = VrfProof::from(&signature_slice);
-                            let signature_hash = vrf_proof.to_hash();
-                            debug!("signature_hash: {}", hex::encode(signature_hash));
-                            match vrf_proof.verify(&vrf_public_key, challenge_bytes.as_slice()) {
-                                Ok(verification) => {
-                                    debug!("verification: {}", hex::encode(verification));
-                                    if verification != signature_hash {
-                                        handle_error("Signature failed to match!");
-                                        return;
-                                    }
-                                    serde_json::ser::to_writer_pretty(
-                                        &mut stdout(),
-                                        &VerifySuccess {
-                                            status: "ok".to_string(),
-                                        },
-                                    )
-                                    .unwrap();
-                                }
-                                Err(error) => handle_error(error),
-                            }
-                        }
-                        Err(error) => handle_error(error),
+    match read_vrf_key(pool_vrf_vkey) {
+        Ok(vrf_vkey) => {
+            if vrf_vkey.key_type != "VrfVerificationKey_PraosVRF" {
+                handle_error("Pool VRF Vkey must be of type: VrfVerificationKey_PraosVRF");
+                return;
+            }
+            match check_signature(&vrf_vkey.key, pool_vrf_vkey_hash, domain, nonce, signature) {
+                Ok(()) => {
+                    if let Err(error) = reporter::report(&VerifySuccess {
+                        status: "ok".to_string(),
+                    }) {
+                        handle_error(error);
                     }
                 }
                 Err(error) => handle_error(error),
@@ -181,15 +118,66 @@ pub(crate) fn verify_challenge(
     }
 }
 
+/// 64 random bytes in hex to make a challenge unique
+pub(crate) fn challenge_nonce() -> String {
+    let mut nonce_seed = [0u8; 64];
+    thread_rng().fill(&mut nonce_seed);
+    hex::encode(nonce_seed)
+}
+
+/// The challenge a pool signs, the blake2b-256 hash of "cip-0022", the domain and the nonce
+fn challenge_hash(domain: &str, nonce: &str) -> Result<Hash<32>, hex::FromHexError> {
+    let challenge_seed = hex::encode("cip-0022".as_bytes()) + &*hex::encode(domain.as_bytes()) + nonce;
+    Ok(Hasher::<256>::hash(&hex::decode(challenge_seed)?))
+}
+
+/// Check a signature of the challenge for domain and nonce. The vrf vkey must be the one the pool registered, so it
+/// has to hash to pool_vrf_vkey_hash.
+pub(crate) fn check_signature(
+    vrf_vkey: &[u8],
+    pool_vrf_vkey_hash: &str,
+    domain: &str,
+    nonce: &str,
+    signature: &str,
+) -> Result<(), String> {
+    let challenge_bytes = challenge_hash(domain, nonce).map_err(|error| error.to_string())?;
+    debug!("challenge: {}", hex::encode(challenge_bytes));
+
+    let vrf_public_key_bytes: [u8; VRF_PUBLIC_KEY_SIZE] = vrf_vkey
+        .get(0..VRF_PUBLIC_KEY_SIZE)
+        .and_then(|vrf_vkey| vrf_vkey.try_into().ok())
+        .ok_or("Invalid VRF public key length")?;
+    // Verify that the vkey the client supplied is the same as the one on-chain
+    let vkey_hash_verify = hex::encode(Hasher::<224>::hash(&vrf_public_key_bytes));
+    debug!("vkey_hash_verify: {}", &vkey_hash_verify);
+    if pool_vrf_vkey_hash != vkey_hash_verify {
+        return Err(format!(
+            "Hash of pool-vrf-vkey({vkey_hash_verify}) did not match supplied pool-vrf-vkey-hash({pool_vrf_vkey_hash})"
+        ));
+    }
+
+    // Verify that the signature is a valid format. This will fail if the signature is mal-formed
+    let signature_bytes = hex::decode(signature).map_err(|error| error.to_string())?;
+    let signature_slice: [u8; VRF_PROOF_SIZE] = signature_bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| "Invalid signature length")?;
+    let vrf_public_key = VrfPublicKey::from(&vrf_public_key_bytes);
+    let vrf_proof: VrfProof = VrfProof::from(&signature_slice);
+    let signature_hash = vrf_proof.to_hash();
+    debug!("signature_hash: {}", hex::encode(signature_hash));
+    let verification = vrf_proof
+        .verify(&vrf_public_key, challenge_bytes.as_slice())
+        .map_err(|error| error.to_string())?;
+    debug!("verification: {}", hex::encode(verification));
+    if verification != signature_hash {
+        return Err("Signature failed to match!".to_string());
+    }
+    Ok(())
+}
+
 fn handle_error<T: Display>(error_message: T) {
-    serde_json::ser::to_writer_pretty(
-        &mut stdout(),
-        &SignVerifyError {
-            status: "error".to_string(),
-            error_message: format!("{error_message}"),
-        },
-    )
-    .unwrap();
+    reporter::report_error(error_message);
 }
 
 #[cfg(test)]
@@ -0,0 +1,218 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::leaderlog::slots_and_hash;
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::reporter;
+use crate::SlotsFormat;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("Slots error: {0}")]
+    Slots(String),
+}
+
+/// An entry of `cardano-cli query leadership-schedule --out-file`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CardanoCliSlot {
+    slot_number: u64,
+}
+
+/// The parts of the `cncli leaderlog` output needed to save its slots
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CncliLeaderLog {
+    epoch: u64,
+    pool_id: PoolId,
+    assigned_slots: Vec<CncliSlot>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CncliSlot {
+    slot: u64,
+}
+
+#[derive(Debug, PartialEq)]
+struct ImportedSchedule {
+    epoch: u64,
+    pool_id: PoolId,
+    slots: Vec<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SlotsImport {
+    status: String,
+    epoch: u64,
+    pool_id: PoolId,
+    slot_qty: u64,
+    hash: String,
+}
+
+/// Parse a leadership schedule. cardano-cli schedules carry neither the epoch nor the pool id so both must be given,
+/// cncli leaderlogs carry both and given values must match them.
+fn parse_schedule(
+    schedule: &str,
+    format: &SlotsFormat,
+    pool_id: &Option<PoolId>,
+    epoch: &Option<u64>,
+) -> Result<ImportedSchedule, Error> {
+    let mut imported_schedule = match format {
+        SlotsFormat::CardanoCli => {
+            let cardano_cli_slots: Vec<CardanoCliSlot> = serde_json::from_str(schedule)?;
+            ImportedSchedule {
+                epoch: epoch
+                    .ok_or_else(|| Error::Slots("--epoch is required for cardano-cli schedules".to_string()))?,
+                pool_id: pool_id
+                    .clone()
+                    .ok_or_else(|| Error::Slots("--pool-id is required for cardano-cli schedules".to_string()))?,
+                slots: cardano_cli_slots.iter().map(|slot| slot.slot_number).collect(),
+            }
+        }
+        SlotsFormat::Cncli => {
+            let leader_log: CncliLeaderLog = serde_json::from_str(schedule)?;
+            if let Some(epoch) = epoch.filter(|epoch| *epoch != leader_log.epoch) {
+                return Err(Error::Slots(format!(
+                    "--epoch {epoch} does not match the leaderlog epoch {}",
+                    leader_log.epoch
+                )));
+            }
+            if let Some(pool_id) = pool_id.as_ref().filter(|pool_id| **pool_id != leader_log.pool_id) {
+                return Err(Error::Slots(format!(
+                    "--pool-id {pool_id} does not match the leaderlog pool id {}",
+                    leader_log.pool_id
+                )));
+            }
+            ImportedSchedule {
+                epoch: leader_log.epoch,
+                pool_id: leader_log.pool_id,
+                slots: leader_log.assigned_slots.iter().map(|slot| slot.slot).collect(),
+            }
+        }
+    };
+    imported_schedule.slots.sort_unstable();
+    imported_schedule.slots.dedup();
+
+    Ok(imported_schedule)
+}
+
+/// Save a leadership schedule computed elsewhere, e.g. by cardano-cli on the block producer, so sendslots can send it
+pub(crate) fn import_slots(
+    db_path: &Path,
+    file: &Path,
+    format: &SlotsFormat,
+    pool_id: &Option<PoolId>,
+    epoch: &Option<u64>,
+) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    if !file.exists() {
+        return Err(Error::InvalidPath(file.to_path_buf()));
+    }
+    let imported_schedule = parse_schedule(&std::fs::read_to_string(file)?, format, pool_id, epoch)?;
+
+    let mut block_store = blockstore::open(db_path, false)?;
+
+    let (slots, hash) = slots_and_hash(imported_schedule.slots.iter().copied());
+    if let Some(previous_slots) = block_store.get_previous_slots(imported_schedule.epoch, &imported_schedule.pool_id)? {
+        if previous_slots != slots {
+            warn!(
+                "Replacing the saved slots for epoch {}, pool {}: {}",
+                imported_schedule.epoch, imported_schedule.pool_id, previous_slots
+            );
+        }
+    }
+    let slot_qty = imported_schedule.slots.len() as u64;
+    block_store.save_slots(
+        imported_schedule.epoch,
+        &imported_schedule.pool_id,
+        slot_qty,
+        &slots,
+        &hash,
+        None,
+    )?;
+
+    reporter::report(&SlotsImport {
+        status: "ok".to_string(),
+        epoch: imported_schedule.epoch,
+        pool_id: imported_schedule.pool_id,
+        slot_qty,
+        hash,
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL_ID: &str = "00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114";
+
+    #[test]
+    fn test_parse_cardano_cli_schedule() {
+        let schedule = r#"[
+            {"slotNumber": 137865500, "slotTime": "2024-10-05T10:03:11Z"},
+            {"slotNumber": 137865432, "slotTime": "2024-10-05T10:02:03Z"}
+        ]"#;
+        assert_eq!(
+            parse_schedule(
+                schedule,
+                &SlotsFormat::CardanoCli,
+                &Some(POOL_ID.parse().unwrap()),
+                &Some(512)
+            )
+            .unwrap(),
+            ImportedSchedule {
+                epoch: 512,
+                pool_id: POOL_ID.parse().unwrap(),
+                slots: vec![137865432, 137865500],
+            }
+        );
+        assert!(parse_schedule(schedule, &SlotsFormat::CardanoCli, &None, &Some(512)).is_err());
+    }
+
+    #[test]
+    fn test_parse_cncli_schedule() {
+        let schedule = format!(
+            r#"{{"status": "ok", "epoch": 512, "poolId": "{POOL_ID}", "epochSlots": 1,
+            "assignedSlots": [{{"no": 1, "slot": 137865432, "slotInEpoch": 57432, "at": "2024-10-05T03:02:03-07:00"}}]}}"#
+        );
+        assert_eq!(
+            parse_schedule(&schedule, &SlotsFormat::Cncli, &None, &None).unwrap(),
+            ImportedSchedule {
+                epoch: 512,
+                pool_id: POOL_ID.parse().unwrap(),
+                slots: vec![137865432],
+            }
+        );
+        assert!(parse_schedule(&schedule, &SlotsFormat::Cncli, &None, &Some(513)).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::nodeclient::cancel;
+use crate::nodeclient::reporter;
+use crate::nodeclient::snapshot::output::SnapshotOutput;
+use crate::nodeclient::snapshot::{
+    ledger_epoch, query_new_epoch_state, write_snapshot, DumpKind, Error, QueryTimeouts, Snapshot, SnapshotPosition,
+};
+
+/// A job that writes the named snapshots of several epochs. The snapshots written so far are recorded in the manifest
+/// file after each one, so running the job again after an interruption only writes the ones left.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Manifest {
+    pub(crate) epochs: Vec<u64>,
+    #[serde(default = "default_names")]
+    pub(crate) names: Vec<String>,
+    /// The snapshots written, with how many rows each one has
+    #[serde(default)]
+    pub(crate) done: Vec<ManifestEntry>,
+    /// The snapshots that were no longer in the ledger state when the job got to them
+    #[serde(default)]
+    pub(crate) missed: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ManifestEntry {
+    pub(crate) epoch: u64,
+    pub(crate) name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) rows: Option<u64>,
+}
+
+fn default_names() -> Vec<String> {
+    vec!["mark".to_string(), "set".to_string(), "go".to_string()]
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestReport {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ledger_epoch: Option<u64>,
+    written: Vec<ManifestEntry>,
+    pending: Vec<ManifestEntry>,
+    missed: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    fn load(path: &Path) -> Result<Self, Error> {
+        let manifest: Manifest = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if manifest.epochs.is_empty() || manifest.names.is_empty() {
+            return Err(Error::Snapshot(format!(
+                "The manifest {} needs at least one epoch and one name",
+                path.display()
+            )));
+        }
+        for name in manifest.names.iter() {
+            Snapshot::from_name(name)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Write the manifest next to the file and rename it into place, so an interruption never leaves it half written
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        let partial = PathBuf::from(format!("{}.partial", path.display()));
+        std::fs::write(&partial, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Every snapshot of the job, by epoch and then in the order of the names
+    fn entries(&self) -> Vec<ManifestEntry> {
+        self.epochs
+            .iter()
+            .flat_map(|epoch| {
+                self.names.iter().map(|name| ManifestEntry {
+                    epoch: *epoch,
+                    name: name.clone(),
+                    rows: None,
+                })
+            })
+            .collect()
+    }
+
+    /// The snapshots that are neither written nor missed yet
+    fn pending(&self) -> Vec<ManifestEntry> {
+        let finished: HashSet<(u64, &str)> = self
+            .done
+            .iter()
+            .chain(self.missed.iter())
+            .map(|entry| (entry.epoch, entry.name.as_str()))
+            .collect();
+        self.entries()
+            .into_iter()
+            .filter(|entry| !finished.contains(&(entry.epoch, entry.name.as_str())))
+            .collect()
+    }
+}
+
+/// The output of one snapshot of a job. Database rows carry the epoch and snapshot name already, a CSV file gets them
+/// from the {epoch} and {name} placeholders in its path.
+fn entry_output(output: &SnapshotOutput, entry: &ManifestEntry) -> SnapshotOutput {
+    match output {
+        SnapshotOutput::Csv(path) => SnapshotOutput::Csv(PathBuf::from(
+            path.display()
+                .to_string()
+                .replace("{epoch}", &entry.epoch.to_string())
+                .replace("{name}", &entry.name),
+        )),
+        _ => output.clone(),
+    }
+}
+
+/// Check that no two snapshots of the job would be written to the same CSV file
+fn check_outputs(manifest: &Manifest, output: &SnapshotOutput) -> Result<(), Error> {
+    let entries = manifest.entries();
+    let outputs: HashSet<String> = entries
+        .iter()
+        .filter_map(|entry| match entry_output(output, entry) {
+            SnapshotOutput::Csv(path) => Some(path.display().to_string()),
+            _ => None,
+        })
+        .collect();
+    if !outputs.is_empty() && outputs.len() != entries.len() {
+        return Err(Error::Snapshot(
+            "Put {epoch} and {name} in --output-file so each snapshot of the manifest gets its own file".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Run the job of a manifest file: query the ledger state once, write each pending snapshot it still holds and
+/// record it in the manifest. Snapshots of epochs to come stay pending for a later run.
+pub(crate) async fn run(
+    manifest_path: &Path,
+    socket_path: &PathBuf,
+    network_magic: u64,
+    output: &SnapshotOutput,
+    kind: &DumpKind<'_>,
+    timeouts: &QueryTimeouts,
+) -> Result<(), Error> {
+    let mut manifest = Manifest::load(manifest_path)?;
+    check_outputs(&manifest, output)?;
+
+    let pending = manifest.pending();
+    let mut written: Vec<ManifestEntry> = Vec::new();
+    let mut ledger_epoch_queried = None;
+    if !pending.is_empty() {
+        let cbor = query_new_epoch_state(socket_path, network_magic, timeouts).await?;
+        let ledger_epoch = ledger_epoch(&cbor)?;
+        ledger_epoch_queried = Some(ledger_epoch);
+        for entry in pending {
+            if let Some(cancelled) = cancel::cancelled() {
+                return Err(Error::ManifestCancelled(cancelled, written.len()));
+            }
+            match Snapshot::from_name(&entry.name)?.position(ledger_epoch, entry.epoch) {
+                SnapshotPosition::Index(index) => {
+                    info!("Writing the {} snapshot of epoch {}", entry.name, entry.epoch);
+                    let rows = write_snapshot(
+                        &cbor,
+                        index,
+                        entry.epoch,
+                        &entry.name,
+                        kind,
+                        &entry_output(output, &entry),
+                    )
+                    .await?;
+                    let entry = ManifestEntry {
+                        rows: Some(rows),
+                        ..entry
+                    };
+                    manifest.done.push(entry.clone());
+                    manifest.save(manifest_path)?;
+                    written.push(entry);
+                    cancel::progress("snapshotsWritten", written.len() as u64);
+                }
+                SnapshotPosition::RotatedOut => {
+                    warn!(
+                        "The {} snapshot of epoch {} is no longer in the ledger state at epoch {}",
+                        entry.name, entry.epoch, ledger_epoch
+                    );
+                    manifest.missed.push(entry);
+                    manifest.save(manifest_path)?;
+                }
+                SnapshotPosition::NotTaken => {}
+            }
+        }
+    }
+
+    reporter::report(&ManifestReport {
+        status: "ok".to_string(),
+        ledger_epoch: ledger_epoch_queried,
+        written,
+        pending: manifest.pending(),
+        missed: manifest.missed.clone(),
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_pending() {
+        let path = std::env::temp_dir().join(format!("cncli-snapshot-manifest-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"epochs": [500, 501], "names": ["mark", "set"]}"#).unwrap();
+        let mut manifest = Manifest::load(&path).unwrap();
+        assert_eq!(manifest.pending().len(), 4);
+
+        manifest.done.push(ManifestEntry {
+            epoch: 500,
+            name: "mark".to_string(),
+            rows: Some(3000),
+        });
+        manifest.missed.push(ManifestEntry {
+            epoch: 500,
+            name: "set".to_string(),
+            rows: None,
+        });
+        manifest.save(&path).unwrap();
+
+        let manifest = Manifest::load(&path).unwrap();
+        let pending: Vec<(u64, String)> = manifest
+            .pending()
+            .into_iter()
+            .map(|entry| (entry.epoch, entry.name))
+            .collect();
+        assert_eq!(pending, vec![(501, "mark".to_string()), (501, "set".to_string())]);
+        assert_eq!(manifest.done[0].rows, Some(3000));
+
+        std::fs::write(&path, r#"{"epochs": [500], "names": ["now"]}"#).unwrap();
+        assert!(Manifest::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_outputs() {
+        let manifest = Manifest {
+            epochs: vec![500, 501],
+            names: default_names(),
+            done: vec![],
+            missed: vec![],
+        };
+        let entry = &manifest.entries()[4];
+        assert_eq!(
+            entry_output(&SnapshotOutput::Csv(PathBuf::from("stake-{epoch}-{name}.csv")), entry),
+            SnapshotOutput::Csv(PathBuf::from("stake-501-set.csv"))
+        );
+        assert!(check_outputs(
+            &manifest,
+            &SnapshotOutput::Csv(PathBuf::from("stake-{epoch}-{name}.csv"))
+        )
+        .is_ok());
+        assert!(check_outputs(&manifest, &SnapshotOutput::Csv(PathBuf::from("stake-{epoch}.csv"))).is_err());
+        assert!(check_outputs(&manifest, &"sqlite://stake.db".parse().unwrap()).is_ok());
+    }
+}
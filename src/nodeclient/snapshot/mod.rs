@@ -1,22 +1,41 @@
-use std::collections::HashMap;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use bech32::{Bech32, Hrp};
 use minicbor::data::Type;
+use minicbor::Decoder;
 use pallas_network::facades::NodeClient;
 use pallas_network::miniprotocols::localstate::queries_v16::BlockQuery;
 use pallas_network::miniprotocols::localstate::{queries_v16, ClientError};
+use serde::Serialize;
 use thiserror::Error;
-use tracing::debug;
+use tracing::{debug, warn};
 
+use crate::nodeclient::cancel::Cancelled;
+use crate::nodeclient::poolid::{format_pool_id, PoolId};
+use crate::nodeclient::reporter;
+use crate::nodeclient::snapshot::output::{Row, RowWriter, SnapshotOutput, POOL_STAKE_SCHEMA, STAKE_SCHEMA};
 use crate::nodeclient::snapshot::Error::UnexpectedCborType;
 
+pub(crate) mod manifest;
+pub(crate) mod output;
+
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Error in Client")]
+    #[error("Local state query failed: {0}")]
     ClientFailure(#[from] ClientError),
 
+    #[error("Could not connect to the node socket {0}: {1}")]
+    Connect(String, String),
+
+    #[error("Timed out after {1} seconds waiting for the node to {0}")]
+    Timeout(String, u64),
+
+    #[error("Could not acquire the ledger state after {0} attempts: {1}")]
+    Acquire(u32, String),
+
     #[error(transparent)]
     CborDecode(#[from] minicbor::decode::Error),
 
@@ -32,11 +51,121 @@ pub enum Error {
     #[error(transparent)]
     Bech32Encoding(#[from] bech32::EncodeError),
 
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
+    #[error(transparent)]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
     #[error("Snapshot error: {0}")]
     Snapshot(String),
+
+    #[error("Invalid --address {0}: {1}")]
+    InvalidAddress(String, String),
+
+    #[error("Writing the snapshot was {0} after {1} rows, the output is incomplete")]
+    Cancelled(Cancelled, u64),
+
+    #[error("The manifest run was {0} after {1} snapshots, run it again to write the rest")]
+    ManifestCancelled(Cancelled, usize),
+
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[cfg(feature = "postgres")]
+    #[error(transparent)]
+    Postgres(#[from] tokio_postgres::Error),
+}
+
+/// How long to wait on the node while querying the ledger state. Decoding the new epoch state takes the node a while
+/// on mainnet, so the query timeout is much longer than the connect one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct QueryTimeouts {
+    pub(crate) connect: Duration,
+    pub(crate) query: Duration,
+}
+
+impl QueryTimeouts {
+    pub(crate) fn new(connect_seconds: u64, query_seconds: u64) -> Self {
+        QueryTimeouts {
+            connect: Duration::from_secs(connect_seconds),
+            query: Duration::from_secs(query_seconds),
+        }
+    }
+}
+
+impl Default for QueryTimeouts {
+    fn default() -> Self {
+        QueryTimeouts::new(10, 600)
+    }
+}
+
+/// Acquiring fails when the point moves while the node answers, so it is worth a few more tries
+const ACQUIRE_ATTEMPTS: u32 = 3;
+const ACQUIRE_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+async fn within<T, E, F>(timeout: Duration, step: &str, future: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, E>>,
+    Error: From<E>,
+{
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| Error::Timeout(step.to_string(), timeout.as_secs()))?
+        .map_err(Error::from)
+}
+
+/// Query the node for the cbor of the new epoch state at its tip
+async fn query_new_epoch_state(
+    socket_path: &PathBuf,
+    network_magic: u64,
+    timeouts: &QueryTimeouts,
+) -> Result<Vec<u8>, Error> {
+    let socket = socket_path.display().to_string();
+    let mut client = tokio::time::timeout(timeouts.connect, NodeClient::connect(socket_path, network_magic))
+        .await
+        .map_err(|_| Error::Timeout(format!("accept the connection on {socket}"), timeouts.connect.as_secs()))?
+        .map_err(|error| Error::Connect(socket, error.to_string()))?;
+    let client = client.statequery();
+
+    let mut attempt = 1;
+    loop {
+        match within(timeouts.query, "acquire the ledger state", client.acquire(None)).await {
+            Err(Error::ClientFailure(ClientError::AcquireFailure(failure))) if attempt < ACQUIRE_ATTEMPTS => {
+                warn!("Acquiring the ledger state failed: {:?}, retrying", failure);
+                tokio::time::sleep(ACQUIRE_RETRY_DELAY).await;
+                attempt += 1;
+            }
+            Err(Error::ClientFailure(ClientError::AcquireFailure(failure))) => {
+                return Err(Error::Acquire(attempt, format!("{failure:?}")));
+            }
+            result => break result?,
+        }
+    }
+
+    let era = within(
+        timeouts.query,
+        "answer the current era",
+        queries_v16::get_current_era(client),
+    )
+    .await?;
+    debug!("Current era: {}", era);
+
+    let cbor = within(
+        timeouts.query,
+        "answer the ledger state query",
+        queries_v16::get_cbor(client, era, BlockQuery::DebugNewEpochState),
+    )
+    .await?;
+    within(timeouts.query, "release the ledger state", client.send_release()).await?;
+
+    cbor.into_iter()
+        .next()
+        .map(|cbor| cbor.0.into())
+        .ok_or_else(|| Error::Snapshot("The node answered with an empty ledger state".to_string()))
 }
 
 #[derive(Debug)]
@@ -46,38 +175,160 @@ enum Snapshot {
     Go,
 }
 
+/// Where the snapshot of an epoch is in a ledger state
+#[derive(Debug, PartialEq)]
+enum SnapshotPosition {
+    /// At this index of the mark, set and go snapshots
+    Index(u64),
+    /// The epoch is after the one of the ledger state, so the snapshot is not taken yet
+    NotTaken,
+    /// The snapshot is no longer in the ledger state
+    RotatedOut,
+}
+
+impl Snapshot {
+    fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "mark" => Ok(Snapshot::Mark),
+            "set" => Ok(Snapshot::Set),
+            "go" => Ok(Snapshot::Go),
+            _ => Err(Error::Snapshot(format!("Unknown snapshot name: {}", name))),
+        }
+    }
+
+    /// The index of the snapshot in the ledger state of its own epoch
+    fn index(&self) -> u64 {
+        match self {
+            Snapshot::Mark => 0,
+            Snapshot::Set => 1,
+            Snapshot::Go => 2,
+        }
+    }
+
+    /// Where the snapshot as it was in epoch is in a ledger state of ledger_epoch. At each epoch boundary the set
+    /// snapshot becomes the go snapshot and the mark snapshot becomes the set snapshot, so the mark snapshot of an
+    /// epoch is still there two epochs later.
+    fn position(&self, ledger_epoch: u64, epoch: u64) -> SnapshotPosition {
+        if epoch > ledger_epoch {
+            return SnapshotPosition::NotTaken;
+        }
+        match self.index().saturating_add(ledger_epoch - epoch) {
+            index @ 0..=2 => SnapshotPosition::Index(index),
+            _ => SnapshotPosition::RotatedOut,
+        }
+    }
+}
+
+/// Which snapshot entries to write, checked as each entry is decoded so a filtered dump never builds the full list
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotFilter {
+    min_lovelace: u64,
+    /// Only these stake credentials when not empty
+    credentials: HashSet<StakeCredential>,
+}
+
+impl SnapshotFilter {
+    pub(crate) fn new(min_lovelace: Option<u64>, addresses: &[String]) -> Result<Self, Error> {
+        let credentials = addresses
+            .iter()
+            .map(|address| decode_stake_address(address))
+            .collect::<Result<HashSet<StakeCredential>, Error>>()?;
+        Ok(SnapshotFilter {
+            min_lovelace: min_lovelace.unwrap_or_default(),
+            credentials,
+        })
+    }
+
+    fn matches(&self, credential: &StakeCredential, lovelace: u64) -> bool {
+        lovelace >= self.min_lovelace && (self.credentials.is_empty() || self.credentials.contains(credential))
+    }
+}
+
+/// The stake credential of a stake1... or stake_test1... address. The network is ignored so an address matches
+/// whatever --network-id the snapshot is written with.
+fn decode_stake_address(address: &str) -> Result<StakeCredential, Error> {
+    let invalid = |message: &str| Error::InvalidAddress(address.to_string(), message.to_string());
+    let (_, bytes) = bech32::decode(address).map_err(|error| invalid(&error.to_string()))?;
+    if bytes.len() != 29 {
+        return Err(invalid("expected a 29 byte stake address"));
+    }
+    let credential_type = match bytes[0] >> 4 {
+        0x0e => 0u8,
+        0x0f => 1u8,
+        _ => return Err(invalid("not a stake address")),
+    };
+    let hash: [u8; 28] = bytes[1..].try_into().map_err(|_| invalid("expected a 28 byte hash"))?;
+    Ok((credential_type, hash))
+}
+
+/// What a dump writes for a snapshot: the stake of each stake address, or the stake delegated to each pool
+pub(crate) enum DumpKind<'a> {
+    Stake {
+        network_id: u8,
+        stake_prefix: &'a str,
+        filter: &'a SnapshotFilter,
+    },
+    PoolStake {
+        bech32: bool,
+    },
+}
+
 pub(crate) async fn dump(
     socket_path: &PathBuf,
     network_magic: u64,
     name: &str,
-    network_id: u8,
-    stake_prefix: &str,
-    output_file: &str,
+    epoch: Option<u64>,
+    output: &SnapshotOutput,
+    kind: &DumpKind<'_>,
+    timeouts: &QueryTimeouts,
 ) -> Result<(), Error> {
-    let mut client = NodeClient::connect(socket_path, network_magic).await.unwrap();
-
-    // convert name into a Snapshot enum
-    let snapshot = match name {
-        "mark" => Snapshot::Mark,
-        "set" => Snapshot::Set,
-        "go" => Snapshot::Go,
-        _ => return Err(Error::Snapshot(format!("Unknown snapshot name: {}", name))),
-    };
+    let snapshot = Snapshot::from_name(name)?;
 
-    let client = client.statequery();
+    let cbor = query_new_epoch_state(socket_path, network_magic, timeouts).await?;
 
-    client.acquire(None).await?;
+    let ledger_epoch = ledger_epoch(&cbor)?;
+    let (epoch, index) = match epoch {
+        None => (ledger_epoch, snapshot.index()),
+        Some(epoch) => match snapshot.position(ledger_epoch, epoch) {
+            SnapshotPosition::Index(index) => (epoch, index),
+            SnapshotPosition::NotTaken => {
+                return Err(Error::Snapshot(format!(
+                    "The {name} snapshot of epoch {epoch} is not taken yet, the ledger state is at epoch {ledger_epoch}"
+                )));
+            }
+            SnapshotPosition::RotatedOut => {
+                return Err(Error::Snapshot(format!(
+                    "The {name} snapshot of epoch {epoch} is no longer in the ledger state at epoch {ledger_epoch}, \
+                     the oldest one left is the mark snapshot of epoch {}",
+                    ledger_epoch.saturating_sub(2)
+                )));
+            }
+        },
+    };
 
-    let era = queries_v16::get_current_era(client).await?;
-    debug!("Current era: {}", era);
+    write_snapshot(&cbor, index, epoch, name, kind, output).await?;
 
-    let cbor = queries_v16::get_cbor(client, era, BlockQuery::DebugNewEpochState).await?;
-    client.send_release().await?;
+    Ok(())
+}
 
-    // Save the CBOR to a file
-    let cbor_bytes = &cbor[0].0;
-    // std::fs::write(output_file, cbor_bytes.as_slice()).unwrap();
+/// The epoch of a new epoch state
+fn ledger_epoch(cbor_bytes: &[u8]) -> Result<u64, Error> {
+    let mut decoder = minicbor::Decoder::new(cbor_bytes);
+    // top level is an array
+    let stake_array_len = decoder
+        .array()?
+        .ok_or(Error::UnexpectedArrayLength { expected: 7, actual: 0 })?;
+    if stake_array_len != 7 {
+        return Err(Error::UnexpectedArrayLength {
+            expected: 7,
+            actual: stake_array_len,
+        });
+    }
+    Ok(decoder.u64()?) // the 0th element is the epoch of the ledger state
+}
 
+/// A decoder at the start of the snapshot at index (0 for mark, 1 for set, 2 for go) of a new epoch state
+fn snapshot_decoder(cbor_bytes: &[u8], index: u64) -> Result<Decoder<'_>, Error> {
     let mut decoder = minicbor::Decoder::new(cbor_bytes);
     // top level is an array
     let stake_array_len = decoder
@@ -89,7 +340,7 @@ pub(crate) async fn dump(
             actual: stake_array_len,
         });
     }
-    decoder.skip()?; // skip the 0th element
+    decoder.skip()?; // skip the epoch
     decoder.skip()?; // skip the 1st element
     decoder.skip()?; // skip the 2nd element
 
@@ -105,7 +356,8 @@ pub(crate) async fn dump(
     }
     decoder.skip()?; // skip the 0th element
     decoder.skip()?; // skip the 1st element
-                     // array element [3][2]
+
+    // array element [3][2]
     let inner_array_len = decoder
         .array()?
         .ok_or(Error::UnexpectedArrayLength { expected: 4, actual: 0 })?;
@@ -116,22 +368,12 @@ pub(crate) async fn dump(
         });
     }
 
-    match snapshot {
-        Snapshot::Mark => {
-            // mark snapshot is at index 0 so no skips needed
-        }
-        Snapshot::Set => {
-            // set snapshot is at index 1 so skip the mark snapshot
-            decoder.skip()?;
-        }
-        Snapshot::Go => {
-            // go snapshot is at index 2 so skip the mark and set snapshots
-            decoder.skip()?;
-            decoder.skip()?;
-        }
+    // mark is at index 0, set at index 1 and go at index 2
+    for _ in 0..index {
+        decoder.skip()?;
     }
 
-    // array element [3][2][snapshot]
+    // array element [3][2][index]
     let snapshot_array_len = decoder
         .array()?
         .ok_or(Error::UnexpectedArrayLength { expected: 3, actual: 0 })?;
@@ -141,9 +383,40 @@ pub(crate) async fn dump(
             actual: snapshot_array_len,
         });
     }
+    Ok(decoder)
+}
+
+/// Write the snapshot at index of a new epoch state with its rows labelled epoch and name, returning how many rows
+/// were written
+async fn write_snapshot(
+    cbor_bytes: &[u8],
+    index: u64,
+    epoch: u64,
+    name: &str,
+    kind: &DumpKind<'_>,
+    output: &SnapshotOutput,
+) -> Result<u64, Error> {
+    let mut decoder = snapshot_decoder(cbor_bytes, index)?;
+    match kind {
+        DumpKind::Stake {
+            network_id,
+            stake_prefix,
+            filter,
+        } => write_stake(&mut decoder, epoch, name, *network_id, stake_prefix, filter, output).await,
+        DumpKind::PoolStake { bech32 } => write_pool_stake(&mut decoder, epoch, name, *bech32, output).await,
+    }
+}
 
-    let output_file = std::fs::File::create(output_file)?;
-    let mut output_file = std::io::BufWriter::new(output_file);
+async fn write_stake(
+    decoder: &mut Decoder<'_>,
+    epoch: u64,
+    name: &str,
+    network_id: u8,
+    stake_prefix: &str,
+    filter: &SnapshotFilter,
+    output: &SnapshotOutput,
+) -> Result<u64, Error> {
+    let mut writer = RowWriter::open(output, &STAKE_SCHEMA, epoch, name).await?;
 
     let hrp = Hrp::parse(stake_prefix)?;
 
@@ -154,18 +427,24 @@ pub(crate) async fn dump(
         let datatype = decoder.datatype()?;
         match datatype {
             Type::Array => {
-                decoder.array()?;
-                let address_type = decoder.u8()?; // the type of stake address
+                let credential = decode_stake_credential(decoder)?;
+                let lovelace = decoder.u64()?;
+                if !filter.matches(&credential, lovelace) {
+                    continue;
+                }
+                let (address_type, stake_key_bytes) = credential;
                 let stake_key_prefix = [match address_type {
                     0 => 0xe0u8, // key-based stake address
-                    1 => 0xf0u8, // script-based stake address
-                    _ => return Err(Error::Snapshot(format!("Unknown address type: {}", address_type))),
+                    _ => 0xf0u8, // script-based stake address
                 } | network_id];
-                let stake_key_bytes = decoder.bytes()?;
-                let stake_key_bytes = [&stake_key_prefix, stake_key_bytes].concat();
+                let stake_key_bytes = [&stake_key_prefix, &stake_key_bytes[..]].concat();
                 let stake_address = encode_bech32(&stake_key_bytes, hrp)?;
-                let lovelace = decoder.u64()?;
-                writeln!(output_file, "{},{},", stake_address, lovelace)?;
+                writer
+                    .write(Row {
+                        id: stake_address,
+                        amounts: vec![lovelace],
+                    })
+                    .await?;
             }
             Type::Break => {
                 decoder.skip()?;
@@ -177,45 +456,153 @@ pub(crate) async fn dump(
         }
     }
 
-    output_file.flush()?;
-
-    Ok(())
+    writer.finish().await
 }
 
-pub(crate) async fn pool_stake_dump(
-    socket_path: &PathBuf,
-    network_magic: u64,
+async fn write_pool_stake(
+    decoder: &mut Decoder<'_>,
+    epoch: u64,
     name: &str,
-    network_id: u8,
-    output_file: &str,
+    bech32: bool,
+    output: &SnapshotOutput,
+) -> Result<u64, Error> {
+    // array element [3][2][snapshot][0] is the stake map and [1] the delegations map
+    let pool_stakes = aggregate_pool_stake(decoder)?;
+    let total_stake: u64 = pool_stakes.iter().map(|(_, pool_stake)| pool_stake).sum();
+
+    let mut writer = RowWriter::open(output, &POOL_STAKE_SCHEMA, epoch, name).await?;
+    for (pool_id, pool_stake) in pool_stakes.iter() {
+        writer
+            .write(Row {
+                id: format_pool_id(&hex::encode(pool_id), bech32),
+                amounts: vec![*pool_stake, total_stake],
+            })
+            .await?;
+    }
+    writer.finish().await
+}
+
+/// A stake credential as (0 for a key hash or 1 for a script hash, hash)
+type StakeCredential = (u8, [u8; 28]);
+
+fn decode_stake_credential(decoder: &mut Decoder) -> Result<StakeCredential, Error> {
+    decoder.array()?;
+    let credential_type = decoder.u8()?;
+    if credential_type > 1 {
+        return Err(Error::Snapshot(format!("Unknown address type: {}", credential_type)));
+    }
+    let hash: [u8; 28] = decoder
+        .bytes()?
+        .try_into()
+        .map_err(|_| Error::Snapshot("Stake credential hash must be 28 bytes".to_string()))?;
+    Ok((credential_type, hash))
+}
+
+/// Call f with the decoder positioned at each value of a definite or indefinite length map after decoding its key
+/// with decode_key
+fn for_each_map_entry<K>(
+    decoder: &mut Decoder,
+    decode_key: fn(&mut Decoder) -> Result<K, Error>,
+    mut f: impl FnMut(K, &mut Decoder) -> Result<(), Error>,
 ) -> Result<(), Error> {
-    let mut client = NodeClient::connect(socket_path, network_magic).await.unwrap();
-
-    // convert name into a Snapshot enum
-    let snapshot = match name {
-        "mark" => Snapshot::Mark,
-        "set" => Snapshot::Set,
-        "go" => Snapshot::Go,
-        _ => return Err(Error::Snapshot(format!("Unknown snapshot name: {}", name))),
-    };
+    let map_len = decoder.map()?;
+    let mut index = 0u64;
+    loop {
+        if map_len == Some(index) {
+            break;
+        }
+        let datatype = decoder.datatype()?;
+        match datatype {
+            Type::Break => {
+                decoder.skip()?;
+                break;
+            }
+            Type::Array | Type::Bytes => {
+                let key = decode_key(decoder)?;
+                f(key, decoder)?;
+            }
+            _ => {
+                return Err(UnexpectedCborType { value: datatype });
+            }
+        }
+        index += 1;
+    }
+    Ok(())
+}
 
-    let client = client.statequery();
+/// Sum the stake delegated to each pool from a snapshot's stake map and delegations map, sorted by stake in
+/// descending order. The stake map is skipped on a first pass so the delegations can be collected with pools
+/// interned to an index, then the stake map is decoded again and added straight into the pool totals. This keeps
+/// only one fixed-size entry per delegation in memory instead of two maps of heap allocated stake addresses.
+fn aggregate_pool_stake(decoder: &mut Decoder) -> Result<Vec<([u8; 28], u64)>, Error> {
+    let stake_map_position = decoder.position();
+    decoder.skip()?;
 
-    client.acquire(None).await?;
+    let mut pool_ids: Vec<[u8; 28]> = Vec::new();
+    let mut pool_indexes: HashMap<[u8; 28], u32> = HashMap::new();
+    let mut delegations: HashMap<StakeCredential, u32> = HashMap::new();
+    for_each_map_entry(decoder, decode_stake_credential, |stake_credential, decoder| {
+        let pool_id: [u8; 28] = decoder
+            .bytes()?
+            .try_into()
+            .map_err(|_| Error::Snapshot("Pool id must be 28 bytes".to_string()))?;
+        let pool_index = *pool_indexes.entry(pool_id).or_insert_with(|| {
+            pool_ids.push(pool_id);
+            (pool_ids.len() - 1) as u32
+        });
+        delegations.insert(stake_credential, pool_index);
+        Ok(())
+    })?;
+    let end_position = decoder.position();
+    drop(pool_indexes);
 
-    let era = queries_v16::get_current_era(client).await?;
-    debug!("Current era: {}", era);
+    let mut pool_stakes: Vec<u64> = vec![0; pool_ids.len()];
+    decoder.set_position(stake_map_position);
+    for_each_map_entry(decoder, decode_stake_credential, |stake_credential, decoder| {
+        let lovelace = decoder.u64()?;
+        if let Some(pool_index) = delegations.get(&stake_credential) {
+            pool_stakes[*pool_index as usize] += lovelace;
+        }
+        Ok(())
+    })?;
+    decoder.set_position(end_position);
 
-    let cbor = queries_v16::get_cbor(client, era, BlockQuery::DebugNewEpochState).await?;
-    client.send_release().await?;
+    let mut pool_stakes: Vec<([u8; 28], u64)> = pool_ids.into_iter().zip(pool_stakes).collect();
+    // Sort in descending order by stake
+    pool_stakes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    Ok(pool_stakes)
+}
 
-    // Save the CBOR to a file
-    let cbor_bytes = &cbor[0].0;
-    // let output_file = std::fs::File::create(output_file)?;
-    // let mut output_file = std::io::BufWriter::new(output_file);
-    // output_file.write_all(cbor_bytes)?;
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PoolVrfHash {
+    status: String,
+    pool_id: String,
+    vrf_vkey_hash: String,
+}
 
-    let mut decoder = minicbor::Decoder::new(cbor_bytes);
+pub(crate) async fn pool_vrf_hash(socket_path: &PathBuf, network_magic: u64, pool_id: &PoolId) -> Result<(), Error> {
+    let vrf_vkey_hash = get_pool_vrf_hash(socket_path, network_magic, pool_id, &QueryTimeouts::default()).await?;
+    reporter::report(&PoolVrfHash {
+        status: "ok".to_string(),
+        pool_id: pool_id.to_string(),
+        vrf_vkey_hash,
+    })?;
+    Ok(())
+}
+
+/// Look up the vrf vkey hash registered for a pool. The value comes from the pool params stored in the mark
+/// snapshot, so a re-registered vrf key shows up here after the next epoch boundary.
+pub(crate) async fn get_pool_vrf_hash(
+    socket_path: &PathBuf,
+    network_magic: u64,
+    pool_id: &PoolId,
+    timeouts: &QueryTimeouts,
+) -> Result<String, Error> {
+    let pool_id = pool_id.to_bytes();
+
+    let cbor = query_new_epoch_state(socket_path, network_magic, timeouts).await?;
+    let mut decoder = minicbor::Decoder::new(&cbor);
     // top level is an array
     let stake_array_len = decoder
         .array()?
@@ -229,7 +616,8 @@ pub(crate) async fn pool_stake_dump(
     decoder.skip()?; // skip the 0th element
     decoder.skip()?; // skip the 1st element
     decoder.skip()?; // skip the 2nd element
-                     // array element [3]
+
+    // array element [3]
     let snapshots_array_len = decoder
         .array()?
         .ok_or(Error::UnexpectedArrayLength { expected: 4, actual: 0 })?;
@@ -241,7 +629,8 @@ pub(crate) async fn pool_stake_dump(
     }
     decoder.skip()?; // skip the 0th element
     decoder.skip()?; // skip the 1st element
-                     // array element [3][2]
+
+    // array element [3][2]
     let inner_array_len = decoder
         .array()?
         .ok_or(Error::UnexpectedArrayLength { expected: 4, actual: 0 })?;
@@ -252,22 +641,7 @@ pub(crate) async fn pool_stake_dump(
         });
     }
 
-    match snapshot {
-        Snapshot::Mark => {
-            // mark snapshot is at index 0 so no skips needed
-        }
-        Snapshot::Set => {
-            // set snapshot is at index 1 so skip the mark snapshot
-            decoder.skip()?;
-        }
-        Snapshot::Go => {
-            // go snapshot is at index 2 so skip the mark and set snapshots
-            decoder.skip()?;
-            decoder.skip()?;
-        }
-    }
-
-    // array element [3][2][snapshot]
+    // array element [3][2][0] is the mark snapshot
     let snapshot_array_len = decoder
         .array()?
         .ok_or(Error::UnexpectedArrayLength { expected: 3, actual: 0 })?;
@@ -277,32 +651,121 @@ pub(crate) async fn pool_stake_dump(
             actual: snapshot_array_len,
         });
     }
+    decoder.skip()?; // skip the stake map
+    decoder.skip()?; // skip the delegations map
 
-    let output_file = std::fs::File::create(output_file)?;
-    let mut output_file = std::io::BufWriter::new(output_file);
+    // array element [3][2][0][2] is the map of pool id to pool params
+    find_pool_vrf_hash(&mut decoder, &pool_id)?
+        .ok_or_else(|| Error::Snapshot(format!("Pool not found: {}", hex::encode(&pool_id))))
+}
 
-    let mut stake_address_to_amount_map: HashMap<Vec<u8>, u64> = HashMap::new();
-    let mut stake_address_to_pool_map: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+/// The pool registrations of the ledger state, from the pool state rather than a snapshot so a registration,
+/// re-registration or retirement shows up as soon as its certificate is on chain
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PoolRegistrations {
+    pub(crate) epoch: u64,
+    /// The vrf vkey hash of every registered pool
+    pub(crate) vrf_vkey_hashes: HashMap<Vec<u8>, String>,
+    /// The vrf vkey hash of re-registrations that take effect at the next epoch boundary
+    pub(crate) future_vrf_vkey_hashes: HashMap<Vec<u8>, String>,
+    /// The epoch pools with a retirement certificate retire in
+    pub(crate) retiring: HashMap<Vec<u8>, u64>,
+}
 
-    // loop through each map item
-    // array element [3][2][snapshot][0] is an indeterminate-length map
-    decoder.map()?;
+pub(crate) async fn get_pool_registrations(
+    socket_path: &PathBuf,
+    network_magic: u64,
+    timeouts: &QueryTimeouts,
+) -> Result<PoolRegistrations, Error> {
+    let cbor = query_new_epoch_state(socket_path, network_magic, timeouts).await?;
+    decode_pool_registrations(&cbor)
+}
+
+fn decode_pool_registrations(cbor_bytes: &[u8]) -> Result<PoolRegistrations, Error> {
+    let mut decoder = minicbor::Decoder::new(cbor_bytes);
+    // top level is an array
+    let stake_array_len = decoder
+        .array()?
+        .ok_or(Error::UnexpectedArrayLength { expected: 7, actual: 0 })?;
+    if stake_array_len != 7 {
+        return Err(Error::UnexpectedArrayLength {
+            expected: 7,
+            actual: stake_array_len,
+        });
+    }
+    let epoch = decoder.u64()?; // array element [0] is the epoch
+    decoder.skip()?; // skip the 1st element
+    decoder.skip()?; // skip the 2nd element
+
+    // array element [3]
+    let epoch_state_array_len = decoder
+        .array()?
+        .ok_or(Error::UnexpectedArrayLength { expected: 4, actual: 0 })?;
+    if epoch_state_array_len != 4 {
+        return Err(Error::UnexpectedArrayLength {
+            expected: 4,
+            actual: epoch_state_array_len,
+        });
+    }
+    decoder.skip()?; // skip the account state
+
+    // array element [3][1] is the ledger state
+    let ledger_state_array_len = decoder
+        .array()?
+        .ok_or(Error::UnexpectedArrayLength { expected: 2, actual: 0 })?;
+    if ledger_state_array_len != 2 {
+        return Err(Error::UnexpectedArrayLength {
+            expected: 2,
+            actual: ledger_state_array_len,
+        });
+    }
+
+    // array element [3][1][0] is the cert state, [vstate, pstate, dstate] since conway and [dstate, pstate] before
+    let cert_state_array_len = decoder
+        .array()?
+        .ok_or(Error::UnexpectedArrayLength { expected: 3, actual: 0 })?;
+    match cert_state_array_len {
+        2 | 3 => decoder.skip()?,
+        actual => return Err(Error::UnexpectedArrayLength { expected: 3, actual }),
+    }
+
+    // the pool state is [pool params, future pool params, retiring, deposits]
+    let pool_state_array_len = decoder
+        .array()?
+        .ok_or(Error::UnexpectedArrayLength { expected: 4, actual: 0 })?;
+    if pool_state_array_len < 3 {
+        return Err(Error::UnexpectedArrayLength {
+            expected: 4,
+            actual: pool_state_array_len,
+        });
+    }
+    let vrf_vkey_hashes = read_pool_vrf_hashes(&mut decoder)?;
+    let future_vrf_vkey_hashes = read_pool_vrf_hashes(&mut decoder)?;
+    let retiring = read_pool_retirements(&mut decoder)?;
+
+    Ok(PoolRegistrations {
+        epoch,
+        vrf_vkey_hashes,
+        future_vrf_vkey_hashes,
+        retiring,
+    })
+}
+
+/// Read a map of pool id to pool params into the vrf vkey hash of each pool
+fn read_pool_vrf_hashes(decoder: &mut Decoder) -> Result<HashMap<Vec<u8>, String>, Error> {
+    let mut vrf_vkey_hashes = HashMap::new();
+    let map_len = decoder.map()?;
+    let mut index = 0u64;
     loop {
+        if map_len == Some(index) {
+            break;
+        }
         let datatype = decoder.datatype()?;
         match datatype {
-            Type::Array => {
-                decoder.array()?;
-                let address_type = decoder.u8()?; // the type of stake address
-                let stake_key_prefix = [match address_type {
-                    0 => 0xe0u8, // key-based stake address
-                    1 => 0xf0u8, // script-based stake address
-                    _ => return Err(Error::Snapshot(format!("Unknown address type: {}", address_type))),
-                } | network_id];
-                let stake_key_bytes = decoder.bytes()?;
-                let stake_key_bytes = [&stake_key_prefix, stake_key_bytes].concat();
-                let lovelace = decoder.u64()?;
-                stake_address_to_amount_map.insert(stake_key_bytes, lovelace);
-                // writeln!(output_file, "{},{},", hex::encode(stake_key_bytes), lovelace)?;
+            Type::Bytes => {
+                let key = decoder.bytes()?.to_vec();
+                let vrf_vkey_hash = read_vrf_hash(decoder)?;
+                vrf_vkey_hashes.insert(key, vrf_vkey_hash);
             }
             Type::Break => {
                 decoder.skip()?;
@@ -312,24 +775,25 @@ pub(crate) async fn pool_stake_dump(
                 return Err(UnexpectedCborType { value: datatype });
             }
         }
+        index += 1;
     }
-    // array element [3][2][snapshot][1] is an indeterminate-length map
-    decoder.map()?;
+    Ok(vrf_vkey_hashes)
+}
+
+/// Read a map of pool id to the epoch the pool retires in
+fn read_pool_retirements(decoder: &mut Decoder) -> Result<HashMap<Vec<u8>, u64>, Error> {
+    let mut retiring = HashMap::new();
+    let map_len = decoder.map()?;
+    let mut index = 0u64;
     loop {
+        if map_len == Some(index) {
+            break;
+        }
         let datatype = decoder.datatype()?;
         match datatype {
-            Type::Array => {
-                decoder.array()?;
-                let address_type = decoder.u8()?; // the type of stake address
-                let stake_key_prefix = [match address_type {
-                    0 => 0xe0u8, // key-based stake address
-                    1 => 0xf0u8, // script-based stake address
-                    _ => return Err(Error::Snapshot(format!("Unknown address type: {}", address_type))),
-                } | network_id];
-                let stake_key_bytes = decoder.bytes()?;
-                let stake_key_bytes = [&stake_key_prefix, stake_key_bytes].concat();
-                let pool_id = decoder.bytes()?;
-                stake_address_to_pool_map.insert(stake_key_bytes, pool_id.to_vec());
+            Type::Bytes => {
+                let key = decoder.bytes()?.to_vec();
+                retiring.insert(key, decoder.u64()?);
             }
             Type::Break => {
                 decoder.skip()?;
@@ -339,35 +803,248 @@ pub(crate) async fn pool_stake_dump(
                 return Err(UnexpectedCborType { value: datatype });
             }
         }
+        index += 1;
     }
+    Ok(retiring)
+}
 
-    let pool_id_to_total_stake_map: HashMap<Vec<u8>, u64> =
-        stake_address_to_pool_map
-            .iter()
-            .fold(HashMap::new(), |mut acc, (stake_key_bytes, pool_id)| {
-                let lovelace = stake_address_to_amount_map.get(stake_key_bytes).unwrap();
-                let total_stake = acc.entry(pool_id.to_vec()).or_insert(0);
-                *total_stake += lovelace;
-                acc
-            });
-
-    let total_stake = pool_id_to_total_stake_map
-        .iter()
-        .fold(0, |acc, (_, total_stake)| acc + total_stake);
-
-    let mut pool_id_to_total_stake_vec: Vec<(&Vec<u8>, &u64)> = pool_id_to_total_stake_map.iter().collect();
-    pool_id_to_total_stake_vec.sort_by(|a, b| b.1.cmp(a.1)); // Sort in descending order by stake
-
-    pool_id_to_total_stake_vec.iter().for_each(|(pool_id, pool_stake)| {
-        writeln!(output_file, "{},{},{},", hex::encode(pool_id), pool_stake, total_stake).unwrap();
-    });
-
-    output_file.flush()?;
+/// Read pool params, [operator, vrf, pledge, cost, margin, reward_account, owners, relays, metadata], into the vrf
+/// vkey hash
+fn read_vrf_hash(decoder: &mut Decoder) -> Result<String, Error> {
+    let pool_params_len = decoder
+        .array()?
+        .ok_or(Error::UnexpectedArrayLength { expected: 9, actual: 0 })?;
+    if pool_params_len != 9 {
+        return Err(Error::UnexpectedArrayLength {
+            expected: 9,
+            actual: pool_params_len,
+        });
+    }
+    decoder.skip()?; // skip the operator
+    let vrf_vkey_hash = hex::encode(decoder.bytes()?);
+    for _ in 2..pool_params_len {
+        decoder.skip()?;
+    }
+    Ok(vrf_vkey_hash)
+}
 
-    Ok(())
+fn find_pool_vrf_hash(decoder: &mut Decoder, pool_id: &[u8]) -> Result<Option<String>, Error> {
+    let map_len = decoder.map()?;
+    let mut index = 0u64;
+    loop {
+        if map_len == Some(index) {
+            break;
+        }
+        let datatype = decoder.datatype()?;
+        match datatype {
+            Type::Bytes => {
+                let key = decoder.bytes()?;
+                let vrf_vkey_hash = read_vrf_hash(decoder)?;
+                if key == pool_id {
+                    return Ok(Some(vrf_vkey_hash));
+                }
+            }
+            Type::Break => {
+                decoder.skip()?;
+                break;
+            }
+            _ => {
+                return Err(UnexpectedCborType { value: datatype });
+            }
+        }
+        index += 1;
+    }
+    Ok(None)
 }
 
 fn encode_bech32(addr: &[u8], hrp: Hrp) -> Result<String, Error> {
     let encoded = bech32::encode::<Bech32>(hrp, addr)?;
     Ok(encoded)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_pool_vrf_hash() {
+        let pool_id = vec![0x01u8; 28];
+        let vrf_vkey_hash = vec![0x02u8; 32];
+        let mut encoder = minicbor::Encoder::new(Vec::new());
+        encoder.begin_map().unwrap();
+        for (key, vrf) in [
+            (vec![0x00u8; 28], vec![0xffu8; 32]),
+            (pool_id.clone(), vrf_vkey_hash.clone()),
+        ] {
+            encoder.bytes(&key).unwrap();
+            encoder.array(9).unwrap();
+            encoder.bytes(&key).unwrap();
+            encoder.bytes(&vrf).unwrap();
+            encoder.u64(500_000_000).unwrap();
+            encoder.u64(340_000_000).unwrap();
+            encoder.array(2).unwrap().u64(1).unwrap().u64(100).unwrap();
+            encoder.bytes(&[0xe1u8; 29]).unwrap();
+            encoder.array(0).unwrap();
+            encoder.array(0).unwrap();
+            encoder.null().unwrap();
+        }
+        encoder.end().unwrap();
+        let buffer = encoder.into_writer();
+
+        let mut decoder = Decoder::new(&buffer);
+        assert_eq!(
+            find_pool_vrf_hash(&mut decoder, &pool_id).unwrap(),
+            Some(hex::encode(&vrf_vkey_hash))
+        );
+        let mut decoder = Decoder::new(&buffer);
+        assert_eq!(find_pool_vrf_hash(&mut decoder, &[0x03u8; 28]).unwrap(), None);
+    }
+
+    fn encode_pool_params(encoder: &mut minicbor::Encoder<Vec<u8>>, pool_id: &[u8], vrf_vkey_hash: &[u8]) {
+        encoder.array(9).unwrap();
+        encoder.bytes(pool_id).unwrap();
+        encoder.bytes(vrf_vkey_hash).unwrap();
+        encoder.u64(500_000_000).unwrap();
+        encoder.u64(340_000_000).unwrap();
+        encoder.array(2).unwrap().u64(1).unwrap().u64(100).unwrap();
+        encoder.bytes(&[0xe1u8; 29]).unwrap();
+        encoder.array(0).unwrap();
+        encoder.array(0).unwrap();
+        encoder.null().unwrap();
+    }
+
+    #[test]
+    fn test_decode_pool_registrations() {
+        let mut encoder = minicbor::Encoder::new(Vec::new());
+        encoder.array(7).unwrap();
+        encoder.u64(512).unwrap();
+        encoder.map(0).unwrap();
+        encoder.map(0).unwrap();
+        encoder.array(4).unwrap(); // epoch state
+        encoder.array(2).unwrap().u64(0).unwrap().u64(0).unwrap(); // account state
+        encoder.array(2).unwrap(); // ledger state
+        encoder.array(3).unwrap(); // cert state
+        encoder.array(0).unwrap(); // vstate
+        encoder.array(4).unwrap(); // pstate
+        encoder.map(2).unwrap();
+        encoder.bytes(&[0x01u8; 28]).unwrap();
+        encode_pool_params(&mut encoder, &[0x01u8; 28], &[0x11u8; 32]);
+        encoder.bytes(&[0x02u8; 28]).unwrap();
+        encode_pool_params(&mut encoder, &[0x02u8; 28], &[0x22u8; 32]);
+        encoder.map(1).unwrap();
+        encoder.bytes(&[0x01u8; 28]).unwrap();
+        encode_pool_params(&mut encoder, &[0x01u8; 28], &[0x33u8; 32]);
+        encoder.map(1).unwrap().bytes(&[0x02u8; 28]).unwrap().u64(514).unwrap();
+        encoder.map(0).unwrap();
+        encoder.array(0).unwrap(); // dstate
+        encoder.null().unwrap(); // utxo state
+        encoder.null().unwrap(); // snapshots
+        encoder.null().unwrap(); // non-myopic
+        encoder.null().unwrap();
+        encoder.null().unwrap();
+        encoder.null().unwrap();
+        let buffer = encoder.into_writer();
+
+        let pool_registrations = decode_pool_registrations(&buffer).unwrap();
+        assert_eq!(pool_registrations.epoch, 512);
+        assert_eq!(
+            pool_registrations.vrf_vkey_hashes,
+            HashMap::from([
+                (vec![0x01u8; 28], hex::encode([0x11u8; 32])),
+                (vec![0x02u8; 28], hex::encode([0x22u8; 32]))
+            ])
+        );
+        assert_eq!(
+            pool_registrations.future_vrf_vkey_hashes,
+            HashMap::from([(vec![0x01u8; 28], hex::encode([0x33u8; 32]))])
+        );
+        assert_eq!(pool_registrations.retiring, HashMap::from([(vec![0x02u8; 28], 514)]));
+    }
+
+    fn encode_stake_credential(encoder: &mut minicbor::Encoder<Vec<u8>>, credential_type: u8, hash: u8) {
+        encoder.array(2).unwrap();
+        encoder.u8(credential_type).unwrap();
+        encoder.bytes(&[hash; 28]).unwrap();
+    }
+
+    #[test]
+    fn test_aggregate_pool_stake() {
+        let mut encoder = minicbor::Encoder::new(Vec::new());
+        // stake map
+        encoder.begin_map().unwrap();
+        for (credential_type, hash, lovelace) in [(0u8, 1u8, 100u64), (1, 1, 20), (0, 2, 300), (0, 3, 4000)] {
+            encode_stake_credential(&mut encoder, credential_type, hash);
+            encoder.u64(lovelace).unwrap();
+        }
+        encoder.end().unwrap();
+        // delegations map, the key and script credentials with the same hash delegate to different pools and
+        // credential 3 is not delegated
+        encoder.map(3).unwrap();
+        for (credential_type, hash, pool_id) in [(0u8, 1u8, 0xaau8), (1, 1, 0xbb), (0, 2, 0xaa)] {
+            encode_stake_credential(&mut encoder, credential_type, hash);
+            encoder.bytes(&[pool_id; 28]).unwrap();
+        }
+        encoder.u8(42).unwrap();
+        let buffer = encoder.into_writer();
+
+        let mut decoder = Decoder::new(&buffer);
+        assert_eq!(
+            aggregate_pool_stake(&mut decoder).unwrap(),
+            vec![([0xaau8; 28], 400), ([0xbbu8; 28], 20)]
+        );
+        // the decoder is left after the delegations map
+        assert_eq!(decoder.u8().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_snapshot_position() {
+        assert_eq!(Snapshot::Mark.position(500, 500), SnapshotPosition::Index(0));
+        assert_eq!(Snapshot::Go.position(500, 500), SnapshotPosition::Index(2));
+        // the mark snapshot of epoch 498 is the go snapshot of epoch 500
+        assert_eq!(Snapshot::Mark.position(500, 498), SnapshotPosition::Index(2));
+        assert_eq!(Snapshot::Set.position(500, 499), SnapshotPosition::Index(2));
+        assert_eq!(Snapshot::Set.position(500, 498), SnapshotPosition::RotatedOut);
+        assert_eq!(Snapshot::Mark.position(500, 0), SnapshotPosition::RotatedOut);
+        assert_eq!(Snapshot::Mark.position(500, 501), SnapshotPosition::NotTaken);
+    }
+
+    #[test]
+    fn test_snapshot_filter() {
+        let address = encode_bech32(
+            &[[0xe1u8].as_slice(), &[7u8; 28]].concat(),
+            Hrp::parse("stake").unwrap(),
+        )
+        .unwrap();
+        let filter = SnapshotFilter::new(Some(1_000_000), &[address]).unwrap();
+        assert!(filter.matches(&(0, [7u8; 28]), 1_000_000));
+        assert!(!filter.matches(&(0, [7u8; 28]), 999_999));
+        assert!(!filter.matches(&(1, [7u8; 28]), 1_000_000));
+        assert!(!filter.matches(&(0, [8u8; 28]), 1_000_000));
+
+        let filter = SnapshotFilter::new(None, &[]).unwrap();
+        assert!(filter.matches(&(1, [8u8; 28]), 0));
+
+        let pool_id = encode_bech32(&[1u8; 28], Hrp::parse("pool").unwrap()).unwrap();
+        assert!(SnapshotFilter::new(None, &[pool_id]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_missing_socket() {
+        let socket_path = std::env::temp_dir().join("cncli_test_missing_node.socket");
+        match query_new_epoch_state(&socket_path, 764824073, &QueryTimeouts::default()).await {
+            Err(Error::Connect(socket, _)) => assert_eq!(socket, socket_path.display().to_string()),
+            other => panic!("expected a connect error, got {other:?}"),
+        }
+
+        let timed_out = within(
+            Duration::from_millis(10),
+            "answer",
+            std::future::pending::<Result<(), Error>>(),
+        )
+        .await;
+        assert_eq!(
+            timed_out.unwrap_err().to_string(),
+            "Timed out after 0 seconds waiting for the node to answer"
+        );
+    }
+}
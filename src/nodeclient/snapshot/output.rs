@@ -0,0 +1,378 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rusqlite::{params_from_iter, Connection};
+use tracing::info;
+
+use crate::nodeclient::cancel;
+use crate::nodeclient::snapshot::Error;
+
+// rows per insert statement or transaction, well below the 65535 bind parameters postgres allows per statement
+const BATCH_SIZE: usize = 10_000;
+
+/// Where snapshot and pool-stake write their rows: a CSV file, or a table of a sqlite or postgres database
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotOutput {
+    Csv(PathBuf),
+    /// sqlite://<path>[#table]
+    Sqlite {
+        path: PathBuf,
+        table: Option<String>,
+    },
+    /// postgres://<connection string>[#table]
+    Postgres {
+        url: String,
+        table: Option<String>,
+    },
+}
+
+impl FromStr for SnapshotOutput {
+    type Err = String;
+    fn from_str(output: &str) -> Result<Self, Self::Err> {
+        let (location, table) = match output.rsplit_once('#') {
+            Some((location, table)) => (location, Some(table.to_string())),
+            None => (output, None),
+        };
+        if let Some(table) = table.as_deref() {
+            check_table_name(table)?;
+        }
+        if let Some(path) = location.strip_prefix("sqlite://") {
+            if path.is_empty() {
+                return Err(format!("Invalid output: {output}, expected sqlite://<path>[#table]"));
+            }
+            return Ok(SnapshotOutput::Sqlite {
+                path: PathBuf::from(path),
+                table,
+            });
+        }
+        if location.starts_with("postgres://") || location.starts_with("postgresql://") {
+            return Ok(SnapshotOutput::Postgres {
+                url: location.to_string(),
+                table,
+            });
+        }
+        match table {
+            Some(_) => Err(format!(
+                "Invalid output: {output}, a #table needs a sqlite:// or postgres:// output"
+            )),
+            None => Ok(SnapshotOutput::Csv(PathBuf::from(output))),
+        }
+    }
+}
+
+/// The table name goes into the SQL as is, so it is kept to letters, digits and underscores
+fn check_table_name(table: &str) -> Result<(), String> {
+    let mut chars = table.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid table name: {table}, use letters, digits and underscores"
+        ))
+    }
+}
+
+/// The columns of a dump after epoch and snapshot: what a row is about, and its lovelace amounts
+pub(crate) struct Schema {
+    id_column: &'static str,
+    amount_columns: &'static [&'static str],
+    default_table: &'static str,
+}
+
+pub(crate) const STAKE_SCHEMA: Schema = Schema {
+    id_column: "stake_address",
+    amount_columns: &["lovelace"],
+    default_table: "stake_snapshot",
+};
+
+pub(crate) const POOL_STAKE_SCHEMA: Schema = Schema {
+    id_column: "pool_id",
+    amount_columns: &["pool_stake", "total_stake"],
+    default_table: "pool_stake",
+};
+
+impl Schema {
+    fn columns(&self) -> Vec<&'static str> {
+        let mut columns = vec!["epoch", "snapshot", self.id_column];
+        columns.extend(self.amount_columns);
+        columns
+    }
+
+    fn create_table_sql(&self, table: &str, integer_type: &str) -> String {
+        let amount_columns: Vec<String> = self
+            .amount_columns
+            .iter()
+            .map(|column| format!("{column} {integer_type} NOT NULL"))
+            .collect();
+        format!(
+            "CREATE TABLE IF NOT EXISTS {table} (epoch {integer_type} NOT NULL, snapshot TEXT NOT NULL, {} TEXT NOT NULL, {}, PRIMARY KEY (epoch, snapshot, {}))",
+            self.id_column,
+            amount_columns.join(", "),
+            self.id_column
+        )
+    }
+
+    /// An insert of rows rows that replaces the amounts of rows already in the table, so a dump can be run again.
+    /// placeholder gives the bind parameter for a 0-based index.
+    fn upsert_sql(&self, table: &str, rows: usize, placeholder: impl Fn(usize) -> String) -> String {
+        let columns = self.columns();
+        let values: Vec<String> = (0..rows)
+            .map(|row| {
+                let placeholders: Vec<String> = (0..columns.len())
+                    .map(|column| placeholder(row * columns.len() + column))
+                    .collect();
+                format!("({})", placeholders.join(", "))
+            })
+            .collect();
+        let updates: Vec<String> = self
+            .amount_columns
+            .iter()
+            .map(|column| format!("{column} = excluded.{column}"))
+            .collect();
+        format!(
+            "INSERT INTO {table} ({}) VALUES {} ON CONFLICT (epoch, snapshot, {}) DO UPDATE SET {}",
+            columns.join(", "),
+            values.join(", "),
+            self.id_column,
+            updates.join(", ")
+        )
+    }
+}
+
+/// A stake address or pool id and its lovelace amounts, in the order of the schema's amount columns
+pub(crate) struct Row {
+    pub(crate) id: String,
+    pub(crate) amounts: Vec<u64>,
+}
+
+enum Sink {
+    Csv(BufWriter<File>),
+    Sqlite(Connection),
+    #[cfg(feature = "postgres")]
+    Postgres(tokio_postgres::Client),
+}
+
+/// Writes the rows of a dump, in batches to a database
+pub(crate) struct RowWriter {
+    sink: Sink,
+    schema: &'static Schema,
+    table: String,
+    epoch: u64,
+    snapshot: String,
+    batch: Vec<Row>,
+    written: u64,
+}
+
+impl RowWriter {
+    /// Open the output. Database tables are created if they don't exist yet. From here on the run stops on its own
+    /// when it is cancelled, after the batch it is writing, so a database output is never left with half a batch.
+    pub(crate) async fn open(
+        output: &SnapshotOutput,
+        schema: &'static Schema,
+        epoch: u64,
+        snapshot: &str,
+    ) -> Result<Self, Error> {
+        cancel::set_cooperative();
+        let (sink, table) = match output {
+            SnapshotOutput::Csv(path) => (Sink::Csv(BufWriter::new(File::create(path)?)), String::new()),
+            SnapshotOutput::Sqlite { path, table } => {
+                let table = table.clone().unwrap_or_else(|| schema.default_table.to_string());
+                let connection = Connection::open(path)?;
+                connection.execute_batch(&schema.create_table_sql(&table, "INTEGER"))?;
+                (Sink::Sqlite(connection), table)
+            }
+            SnapshotOutput::Postgres { url, table } => {
+                let table = table.clone().unwrap_or_else(|| schema.default_table.to_string());
+                (open_postgres(url, schema, &table).await?, table)
+            }
+        };
+        Ok(RowWriter {
+            sink,
+            schema,
+            table,
+            epoch,
+            snapshot: snapshot.to_string(),
+            batch: Vec::new(),
+            written: 0,
+        })
+    }
+
+    pub(crate) async fn write(&mut self, row: Row) -> Result<(), Error> {
+        if let Sink::Csv(writer) = &mut self.sink {
+            // the trailing comma is kept from the original format
+            write!(writer, "{},", row.id)?;
+            for amount in row.amounts.iter() {
+                write!(writer, "{},", amount)?;
+            }
+            writeln!(writer)?;
+            self.written += 1;
+            if self.written % BATCH_SIZE as u64 == 0 {
+                cancel::progress("rowsWritten", self.written);
+                self.check_cancelled()?;
+            }
+            return Ok(());
+        }
+        self.batch.push(row);
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush().await?;
+            self.check_cancelled()?;
+        }
+        Ok(())
+    }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        match cancel::cancelled() {
+            Some(cancelled) => Err(Error::Cancelled(cancelled, self.written)),
+            None => Ok(()),
+        }
+    }
+
+    /// Write what is left and return how many rows were written
+    pub(crate) async fn finish(mut self) -> Result<u64, Error> {
+        self.flush().await?;
+        if let Sink::Csv(writer) = &mut self.sink {
+            writer.flush()?;
+        }
+        info!("Wrote {} rows", self.written);
+        Ok(self.written)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let epoch = self.epoch as i64;
+        match &mut self.sink {
+            Sink::Csv(_) => {}
+            Sink::Sqlite(connection) => {
+                let transaction = connection.transaction()?;
+                {
+                    let mut statement =
+                        transaction.prepare_cached(&self.schema.upsert_sql(&self.table, 1, |_| "?".to_string()))?;
+                    for row in self.batch.iter() {
+                        let mut values: Vec<rusqlite::types::Value> =
+                            vec![epoch.into(), self.snapshot.clone().into(), row.id.clone().into()];
+                        values.extend(row.amounts.iter().map(|amount| (*amount as i64).into()));
+                        statement.execute(params_from_iter(values))?;
+                    }
+                }
+                transaction.commit()?;
+            }
+            #[cfg(feature = "postgres")]
+            Sink::Postgres(client) => {
+                let sql = self
+                    .schema
+                    .upsert_sql(&self.table, self.batch.len(), |index| format!("${}", index + 1));
+                let amounts: Vec<Vec<i64>> = self
+                    .batch
+                    .iter()
+                    .map(|row| row.amounts.iter().map(|amount| *amount as i64).collect())
+                    .collect();
+                let mut params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = Vec::new();
+                for (row, amounts) in self.batch.iter().zip(amounts.iter()) {
+                    params.push(&epoch);
+                    params.push(&self.snapshot);
+                    params.push(&row.id);
+                    for amount in amounts.iter() {
+                        params.push(amount);
+                    }
+                }
+                client.execute(sql.as_str(), &params).await?;
+            }
+        }
+        self.written += self.batch.len() as u64;
+        self.batch.clear();
+        cancel::progress("rowsWritten", self.written);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "postgres")]
+async fn open_postgres(url: &str, schema: &Schema, table: &str) -> Result<Sink, Error> {
+    let (client, connection) = tokio_postgres::connect(url, tokio_postgres::NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            tracing::error!("postgres connection error: {}", error);
+        }
+    });
+    client.batch_execute(&schema.create_table_sql(table, "BIGINT")).await?;
+    Ok(Sink::Postgres(client))
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn open_postgres(_url: &str, _schema: &Schema, _table: &str) -> Result<Sink, Error> {
+    Err(Error::Snapshot(
+        "This cncli was built without postgres support, build it with --features postgres".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_snapshot_output() {
+        assert_eq!(
+            "mark.csv".parse::<SnapshotOutput>(),
+            Ok(SnapshotOutput::Csv(PathBuf::from("mark.csv")))
+        );
+        assert_eq!(
+            "sqlite:///tmp/stake.db#mark_snapshot".parse::<SnapshotOutput>(),
+            Ok(SnapshotOutput::Sqlite {
+                path: PathBuf::from("/tmp/stake.db"),
+                table: Some("mark_snapshot".to_string())
+            })
+        );
+        assert_eq!(
+            "postgres://cncli@localhost/stake".parse::<SnapshotOutput>(),
+            Ok(SnapshotOutput::Postgres {
+                url: "postgres://cncli@localhost/stake".to_string(),
+                table: None
+            })
+        );
+        assert!("sqlite://stake.db#stake; DROP TABLE x"
+            .parse::<SnapshotOutput>()
+            .is_err());
+        assert!("sqlite://#stake".parse::<SnapshotOutput>().is_err());
+        assert!("mark.csv#stake".parse::<SnapshotOutput>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_sqlite_rows() {
+        let path = std::env::temp_dir().join(format!("cncli-snapshot-output-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let output = SnapshotOutput::Sqlite {
+            path: path.clone(),
+            table: None,
+        };
+        for total_stake in [100u64, 200] {
+            let mut writer = RowWriter::open(&output, &POOL_STAKE_SCHEMA, 500, "mark").await.unwrap();
+            for pool in 0..BATCH_SIZE + 1 {
+                writer
+                    .write(Row {
+                        id: format!("pool{pool}"),
+                        amounts: vec![pool as u64, total_stake],
+                    })
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(writer.finish().await.unwrap(), BATCH_SIZE as u64 + 1);
+        }
+
+        // running the dump again replaced the amounts instead of adding rows
+        let connection = Connection::open(&path).unwrap();
+        let (rows, total_stake): (i64, i64) = connection
+            .query_row("SELECT COUNT(*), MIN(total_stake) FROM pool_stake", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((rows, total_stake), (BATCH_SIZE as i64 + 1, 200));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::StakeHistory;
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::reporter;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StakeHistoryResult {
+    status: String,
+    pool_id: String,
+    epochs: Vec<StakeHistory>,
+}
+
+/// Print the pool stake, total active stake and sigma that leaderlog was run with for a pool, one entry per epoch
+pub(crate) fn stake_history(db_path: &Path, pool_id: &PoolId) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+    let mut block_store = blockstore::open(db_path, true)?;
+
+    let epochs = block_store.get_stake_history(pool_id)?;
+    reporter::report(&StakeHistoryResult {
+        status: "ok".to_string(),
+        pool_id: pool_id.to_string(),
+        epochs,
+    })?;
+
+    Ok(())
+}
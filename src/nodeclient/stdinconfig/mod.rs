@@ -0,0 +1,64 @@
+use std::io::{stdin, IsTerminal, Read};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error in the --stdin-config document: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("--stdin-config expects a JSON document piped to stdin")]
+    NotPiped,
+}
+
+/// The leaderlog values that can be passed on stdin instead of as arguments, where every user on the host can read
+/// them from the process list
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct LeaderlogConfig {
+    pub(crate) pool_stake: Option<u64>,
+    pub(crate) active_stake: Option<u64>,
+    pub(crate) nonce: Option<String>,
+    pub(crate) extra_entropy: Option<String>,
+}
+
+/// Read the single JSON document on stdin. A terminal is refused rather than waited on.
+pub(crate) fn read<T: DeserializeOwned>() -> Result<T, Error> {
+    let stdin = stdin();
+    if stdin.is_terminal() {
+        return Err(Error::NotPiped);
+    }
+    let mut document = String::new();
+    stdin.lock().read_to_string(&mut document)?;
+    parse(&document)
+}
+
+fn parse<T: DeserializeOwned>(document: &str) -> Result<T, Error> {
+    Ok(serde_json::from_str(document)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_leaderlog_config() {
+        let config: LeaderlogConfig = parse(r#"{"poolStake": 1000, "activeStake": 2000000, "nonce": "ab"}"#).unwrap();
+        assert_eq!(
+            config,
+            LeaderlogConfig {
+                pool_stake: Some(1000),
+                active_stake: Some(2000000),
+                nonce: Some("ab".to_string()),
+                extra_entropy: None,
+            }
+        );
+        // a misspelt key would otherwise quietly fall back to the arguments
+        assert!(parse::<LeaderlogConfig>(r#"{"poolstake": 1000}"#).is_err());
+    }
+}
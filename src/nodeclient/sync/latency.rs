@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use pallas_network::facades::DEFAULT_KEEP_ALIVE_INTERVAL_SEC;
+use pallas_network::miniprotocols::keepalive;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::nodeclient::runstate;
+
+pub(crate) const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(DEFAULT_KEEP_ALIVE_INTERVAL_SEC);
+// round trips summarized per log line, every 5 minutes at the keepalive interval
+const ROUND_TRIPS_PER_LOG: usize = 15;
+pub(crate) const KEEPALIVE_OPERATION: &str = "keepalive";
+
+/// Send a keepalive cookie to the peer every KEEP_ALIVE_INTERVAL and time until the node echoes it back. Unlike the
+/// TCP connect time, this includes the node answering on the mux, so it is the latency sync and sendtip see. The
+/// last round trip is recorded in the state file per peer for healthcheck, and a summary is logged every
+/// ROUND_TRIPS_PER_LOG round trips.
+pub(crate) fn spawn_keepalive(mut client: keepalive::Client, peer: String) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut round_trips = RoundTrips::default();
+        loop {
+            tokio::time::sleep(KEEP_ALIVE_INTERVAL).await;
+            let start = Instant::now();
+            if let Err(error) = client.keepalive_roundtrip().await {
+                warn!("Keepalive to {} failed: {}", peer, error);
+                return;
+            }
+            let rtt_ms = start.elapsed().as_millis() as u64;
+            debug!("Keepalive round trip to {}: {} ms", peer, rtt_ms);
+            runstate::record_peer(KEEPALIVE_OPERATION, &peer, format_rtt(rtt_ms));
+            round_trips.push(rtt_ms);
+            if round_trips.len() == ROUND_TRIPS_PER_LOG {
+                info!(
+                    peer = peer.as_str(),
+                    "Keepalive round trip to {}: {}",
+                    peer,
+                    round_trips.summary()
+                );
+                round_trips = RoundTrips::default();
+            }
+        }
+    })
+}
+
+/// How a round trip is recorded as the outcome of a keepalive run
+pub(crate) fn format_rtt(rtt_ms: u64) -> String {
+    format!("{rtt_ms} ms")
+}
+
+pub(crate) fn parse_rtt(outcome: &str) -> Option<u64> {
+    outcome.strip_suffix(" ms")?.parse().ok()
+}
+
+#[derive(Debug, Default)]
+struct RoundTrips {
+    rtts_ms: Vec<u64>,
+}
+
+impl RoundTrips {
+    fn push(&mut self, rtt_ms: u64) {
+        self.rtts_ms.push(rtt_ms);
+    }
+
+    fn len(&self) -> usize {
+        self.rtts_ms.len()
+    }
+
+    fn summary(&self) -> String {
+        let min = self.rtts_ms.iter().min().copied().unwrap_or_default();
+        let max = self.rtts_ms.iter().max().copied().unwrap_or_default();
+        let avg = self.rtts_ms.iter().sum::<u64>() / self.rtts_ms.len().max(1) as u64;
+        format!(
+            "min {} ms, avg {} ms, max {} ms over {} round trips",
+            min,
+            avg,
+            max,
+            self.rtts_ms.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips() {
+        let mut round_trips = RoundTrips::default();
+        for rtt_ms in [40, 20, 30] {
+            round_trips.push(rtt_ms);
+        }
+        assert_eq!(
+            round_trips.summary(),
+            "min 20 ms, avg 30 ms, max 40 ms over 3 round trips"
+        );
+        assert_eq!(parse_rtt(&format_rtt(42)), Some(42));
+        assert_eq!(parse_rtt("block 42"), None);
+    }
+}
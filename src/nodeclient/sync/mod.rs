@@ -1,29 +1,58 @@
 use std::cmp::max;
-use std::net::ToSocketAddrs;
 use std::ops::Sub;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use pallas_network::facades::{KeepAliveLoop, PeerClient, DEFAULT_KEEP_ALIVE_INTERVAL_SEC};
+use chrono::Utc;
+use futures::future::join_all;
+
+use pallas_crypto::hash::Hasher;
 use pallas_network::miniprotocols::chainsync::{HeaderContent, NextResponse, Tip};
 use pallas_network::miniprotocols::handshake::Confirmation;
 use pallas_network::miniprotocols::{
     blockfetch, chainsync, handshake, keepalive, txsubmission, Point, MAINNET_MAGIC, PROTOCOL_N2N_BLOCK_FETCH,
     PROTOCOL_N2N_CHAIN_SYNC, PROTOCOL_N2N_HANDSHAKE, PROTOCOL_N2N_KEEP_ALIVE, PROTOCOL_N2N_TX_SUBMISSION,
 };
-use pallas_network::multiplexer::{Bearer, Plexer};
-use pallas_traverse::MultiEraHeader;
+use pallas_network::multiplexer::{AgentChannel, Plexer};
+use pallas_traverse::{MultiEraBlock, MultiEraHeader, MultiEraUpdate};
+use serde::Serialize;
 use thiserror::Error;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 use tracing::{debug, error, info, warn};
 
 use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::memory::{MemoryBlockStore, MEMORY_DB};
+use crate::nodeclient::blockstore::mirror::MirrorBlockStore;
 use crate::nodeclient::blockstore::redb::RedbBlockStore;
-use crate::nodeclient::blockstore::sqlite::SqLiteBlockStore;
-use crate::nodeclient::blockstore::BlockStore;
+use crate::nodeclient::blockstore::sqlite::{SqLiteBlockStore, SqliteTuning};
+use crate::nodeclient::blockstore::{BlockStore, ProtocolUpdate};
+use crate::nodeclient::db;
+use crate::nodeclient::otlp::{SpanKind, Telemetry, BLOCKS_COUNTER, ROLLBACKS_COUNTER};
+use crate::nodeclient::poolblocks::PostEpochReport;
+use crate::nodeclient::poolid::{BlockHash, PoolId};
+use crate::nodeclient::proxy;
+use crate::nodeclient::proxy::SocksProxy;
+use crate::nodeclient::reporter;
+use crate::nodeclient::runstate;
+use crate::nodeclient::sync::latency::spawn_keepalive;
+use crate::nodeclient::sync::peers::{Peer, PeerTip, CHAIN_SELECTION_INTERVAL, MINORITY_FORK_BLOCKS};
+use crate::nodeclient::sync::pipeline::{PipelinedChainSync, DEFAULT_MAX_PIPELINE_DEPTH};
+use crate::nodeclient::sync::pooltool::{Relay, SeenTips};
+use crate::nodeclient::sync::recent_hashes::RecentHashes;
+use crate::nodeclient::sync::session::{SessionMessage, SessionRecorder};
 
+pub(crate) mod latency;
+pub(crate) mod peers;
+mod pipeline;
 pub(crate) mod pooltool;
+mod recent_hashes;
+mod session;
 
 const FIVE_SECS: Duration = Duration::from_secs(5);
+const MAINNET_SHELLEY_GENESIS_HASH: &str = "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81";
+// blocks remembered to resume from after a reconnect when syncing without a db
+const MAX_RESUME_POINTS: usize = 10;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -39,11 +68,107 @@ pub enum Error {
     #[error("chainsync error occurred: {0}")]
     ChainSync(#[from] chainsync::ClientError),
 
+    #[error("chainsync error occurred: {0}")]
+    ChainSyncPipeline(String),
+
+    #[error("blockfetch error occurred: {0}")]
+    BlockFetch(#[from] blockfetch::ClientError),
+
     #[error("blockstore error occurred: {0}")]
     BlockStore(#[from] blockstore::Error),
+
+    #[error("reporter error occurred: {0}")]
+    Reporter(#[from] reporter::Error),
+
+    #[error("sqlite error occurred: {0}")]
+    Sqlite(#[from] blockstore::sqlite::Error),
+
+    #[error("redb error occurred: {0}")]
+    Redb(#[from] blockstore::redb::Error),
+
+    #[error("header decode error occurred: {0}")]
+    HeaderDecode(String),
+
+    #[error("session file error occurred: {0}")]
+    Session(String),
 }
 
-#[derive(Debug, Clone)]
+impl Error {
+    /// Whether sync has to stop. Anything else is a problem with the connection to the node, which is retried.
+    fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Error::BlockStore(_) | Error::Reporter(_) | Error::Sqlite(_) | Error::Redb(_) | Error::Session(_)
+        )
+    }
+}
+
+/// Something that happened while following the chain, passed to the callbacks of a SyncService
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncEvent {
+    /// A block header was saved to the db, or received when syncing without one. Headers are saved in batches while
+    /// catching up, so these come in bursts.
+    BlockStored(SyncedBlock),
+    /// The node rolled the chain back to this point, blocks after it are replaced by the ones that follow. Every
+    /// connection starts with a rollback to the intersection point. Slot 0 with an empty hash is the origin.
+    RollBack { slot_number: u64, hash: Vec<u8> },
+    /// A header of the node's tip was processed. This happens again for every new block at the tip.
+    TipReached { block_number: u64, slot_number: u64 },
+    /// The connection to the node was lost or could not be made. Unless sync exits at the tip, it reconnects.
+    Disconnected { reason: String },
+    /// Sync follows another peer, because the one it followed could not be reached or serves a minority fork.
+    /// Peers are host:port.
+    PeerSwitched { from: String, to: String, reason: String },
+}
+
+/// The header fields of a block that was synced
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncedBlock {
+    pub block_number: u64,
+    pub slot_number: u64,
+    pub hash: Vec<u8>,
+    pub prev_hash: Vec<u8>,
+    /// blake2b 224 of the issuer vkey
+    pub pool_id: Vec<u8>,
+    pub block_size: u64,
+    pub protocol_major_version: u64,
+    pub protocol_minor_version: u64,
+}
+
+impl From<&BlockHeader> for SyncedBlock {
+    fn from(block_header: &BlockHeader) -> Self {
+        SyncedBlock {
+            block_number: block_header.block_number,
+            slot_number: block_header.slot_number,
+            hash: block_header.hash.clone(),
+            prev_hash: block_header.prev_hash.clone(),
+            pool_id: Hasher::<224>::hash(&block_header.node_vkey).to_vec(),
+            block_size: block_header.block_size,
+            protocol_major_version: block_header.protocol_major_version,
+            protocol_minor_version: block_header.protocol_minor_version,
+        }
+    }
+}
+
+type EventCallback = Arc<dyn Fn(&SyncEvent) + Send + Sync>;
+
+/// A `--progress-json` line, written each time sync logs its progress
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncProgress {
+    status: String,
+    block_number: u64,
+    tip_block_number: u64,
+    percent: f64,
+    blocks_per_second: f64,
+    /// Not set until a rate is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eta_seconds: Option<u64>,
+    /// How long saving the blocks received since the previous line took
+    db_write_ms: u64,
+}
+
+#[derive(Debug, Clone, Default)]
 pub(crate) struct BlockHeader {
     pub block_number: u64,
     pub slot_number: u64,
@@ -65,6 +190,36 @@ pub(crate) struct BlockHeader {
     pub unknown_2: Vec<u8>,
     pub protocol_major_version: u64,
     pub protocol_minor_version: u64,
+    /// The header as received, empty unless raw headers are stored
+    pub header_cbor: Vec<u8>,
+    /// Unix time in milliseconds sync received the header at
+    pub received_at: Option<u64>,
+    /// The node the header was received from, as host:port
+    pub source_peer: Option<String>,
+}
+
+/// How sync batches its writes and keeps the sqlite WAL from growing
+#[derive(Debug, Clone)]
+pub(crate) struct SyncTuning {
+    pub(crate) sqlite: SqliteTuning,
+    /// How long blocks are collected before they are written while catching up. At the tip every block is written
+    /// right away.
+    pub(crate) flush_interval: Duration,
+    /// How often the sqlite WAL is checkpointed and truncated, None leaves it to wal_autocheckpoint
+    pub(crate) checkpoint_interval: Option<Duration>,
+    /// How many chainsync requests are kept in flight at most while catching up, 1 to not pipeline
+    pub(crate) max_pipeline_depth: usize,
+}
+
+impl Default for SyncTuning {
+    fn default() -> Self {
+        SyncTuning {
+            sqlite: SqliteTuning::default(),
+            flush_interval: FIVE_SECS,
+            checkpoint_interval: None,
+            max_pipeline_depth: DEFAULT_MAX_PIPELINE_DEPTH,
+        }
+    }
 }
 
 struct LoggingObserver {
@@ -73,6 +228,35 @@ struct LoggingObserver {
     pub block_store: Option<Box<dyn BlockStore + Send>>,
     pub shelley_genesis_hash: String,
     pub pending_blocks: Vec<BlockHeader>,
+    pub capture_protocol_updates: bool,
+    pub pending_body_fetch: Option<Point>,
+    pub recent_hashes: RecentHashes,
+    /// When not empty, only blocks from these pools are stored with their full header
+    pub filter_pool_ids: Vec<Vec<u8>>,
+    /// Keep the cbor of each header so it can be parsed again later without syncing again
+    pub store_raw_headers: bool,
+    pub progress_json: bool,
+    /// The block number at last_log_time, to measure the sync rate
+    pub last_log_block_number: Option<u64>,
+    /// Record reaching the tip in the run state. sendtip follows the tip too but stores nothing.
+    pub record_sync_tip: bool,
+    pub flush_interval: Duration,
+    pub checkpoint_interval: Option<Duration>,
+    pub last_checkpoint_time: Instant,
+    pub on_event: Option<EventCallback>,
+    /// Where headers that cannot be decoded are kept, None to only log them
+    pub quarantine_dir: Option<PathBuf>,
+    /// Where `cncli db backup` asks for a snapshot of a database only this process can open, None for no database
+    pub backup_request: Option<PathBuf>,
+    pub post_epoch_report: Option<Arc<PostEpochReport>>,
+    pub tip_reached: bool,
+    /// The node headers are received from, as host:port, recorded with each block
+    pub source_peer: Option<String>,
+    /// The epoch of the last block saved, to notice when a new one starts
+    pub last_saved_epoch: Option<u64>,
+    pub telemetry: Telemetry,
+    /// When replaying a session, the unix time in milliseconds the current header was originally received at
+    pub received_at: Option<u64>,
 }
 
 impl Default for LoggingObserver {
@@ -81,10 +265,233 @@ impl Default for LoggingObserver {
             last_log_time: Instant::now().sub(Duration::from_secs(6)),
             exit_when_tip_reached: false,
             block_store: None,
-            shelley_genesis_hash: String::from("1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81"),
+            shelley_genesis_hash: MAINNET_SHELLEY_GENESIS_HASH.to_string(),
             pending_blocks: Vec::new(),
+            capture_protocol_updates: false,
+            pending_body_fetch: None,
+            recent_hashes: RecentHashes::default(),
+            filter_pool_ids: Vec::new(),
+            store_raw_headers: false,
+            progress_json: false,
+            last_log_block_number: None,
+            record_sync_tip: false,
+            flush_interval: FIVE_SECS,
+            checkpoint_interval: None,
+            last_checkpoint_time: Instant::now(),
+            on_event: None,
+            quarantine_dir: None,
+            backup_request: None,
+            post_epoch_report: None,
+            tip_reached: false,
+            source_peer: None,
+            last_saved_epoch: None,
+            telemetry: Telemetry::default(),
+            received_at: None,
+        }
+    }
+}
+
+/// Drop the header fields that only matter for inspecting a block, keeping what the nonce calculation, fork handling,
+/// pool ids and opcert tracking need
+fn minimal_block_header(block_header: BlockHeader) -> BlockHeader {
+    BlockHeader {
+        node_vrf_vkey: vec![],
+        block_vrf_0: vec![],
+        block_vrf_1: vec![],
+        eta_vrf_1: vec![],
+        leader_vrf_0: vec![],
+        leader_vrf_1: vec![],
+        block_body_hash: vec![],
+        pool_opcert: vec![],
+        unknown_2: vec![],
+        header_cbor: vec![],
+        ..block_header
+    }
+}
+
+impl LoggingObserver {
+    /// Queue a header for saving unless it is one we already stored and are being sent again after a reconnect.
+    /// Returns whether the header was queued.
+    fn push_pending_block(&mut self, block_header: BlockHeader) -> bool {
+        if self.pending_blocks.is_empty()
+            && self
+                .recent_hashes
+                .contains(block_header.slot_number, &block_header.hash)
+        {
+            debug!(
+                "skipping known block {} at slot {}",
+                block_header.block_number, block_header.slot_number
+            );
+            return false;
+        }
+        let block_header = BlockHeader {
+            received_at: Some(self.received_at.unwrap_or_else(|| Utc::now().timestamp_millis() as u64)),
+            source_peer: self.source_peer.clone(),
+            ..block_header
+        };
+        // blake2b 224 of node_vkey is the pool_id
+        let pool_id = Hasher::<224>::hash(&block_header.node_vkey);
+        let block_header = if self.filter_pool_ids.is_empty()
+            || self
+                .filter_pool_ids
+                .iter()
+                .any(|filter_pool_id| filter_pool_id.as_slice() == &pool_id[..])
+        {
+            block_header
+        } else {
+            minimal_block_header(block_header)
+        };
+        self.pending_blocks.push(block_header);
+        true
+    }
+
+    fn save_pending_blocks(&mut self) -> Result<(), Error> {
+        if self.pending_blocks.is_empty() {
+            return Ok(());
+        }
+        let saved_blocks = self.pending_blocks.clone();
+        match self.block_store.as_mut() {
+            None => self.pending_blocks.clear(),
+            Some(store) => {
+                let mut span = self.telemetry.span("sync.save_blocks", SpanKind::Internal);
+                span.attribute("blocks", saved_blocks.len() as u64);
+                if let Err(error) = store.save_block(&mut self.pending_blocks, &self.shelley_genesis_hash) {
+                    span.error(&error);
+                    return Err(error.into());
+                }
+                self.recent_hashes.saved(&saved_blocks);
+            }
+        }
+        self.telemetry.add(BLOCKS_COUNTER, saved_blocks.len() as u64);
+        for saved_block in saved_blocks.iter() {
+            self.emit(SyncEvent::BlockStored(SyncedBlock::from(saved_block)));
+        }
+        if let Some(last_saved_block) = saved_blocks.last() {
+            self.report_finished_epoch(last_saved_block.slot_number);
+        }
+        Ok(())
+    }
+
+    /// Report the blocks of the epoch that ended when a block of the next one was saved. Epochs that ended while
+    /// catching up are not reported.
+    fn report_finished_epoch(&mut self, slot_number: u64) {
+        let post_epoch_report = match self.post_epoch_report.clone() {
+            Some(post_epoch_report) => post_epoch_report,
+            None => return,
+        };
+        let epoch = post_epoch_report.epoch_of_slot(slot_number);
+        if let (true, Some(finished_epoch), Some(store)) =
+            (self.tip_reached, self.last_saved_epoch, self.block_store.as_mut())
+        {
+            if epoch > finished_epoch {
+                if let Err(error) = post_epoch_report.report(store.as_mut(), finished_epoch) {
+                    error!("Could not write the report of epoch {}: {}", finished_epoch, error);
+                }
+            }
+        }
+        self.last_saved_epoch = Some(epoch);
+    }
+
+    /// Keep the raw cbor of a header that could not be decoded so it can be looked at, and a fixed cncli can be
+    /// tested against it. Returns where it was written.
+    fn quarantine_header(&self, content: &HeaderContent) -> Option<PathBuf> {
+        let quarantine_dir = self.quarantine_dir.as_ref()?;
+        let path = quarantine_dir.join(format!(
+            "header-{}-{}.cbor",
+            content.variant,
+            hex::encode(Hasher::<256>::hash(&content.cbor))
+        ));
+        match std::fs::create_dir_all(quarantine_dir).and_then(|_| std::fs::write(&path, &content.cbor)) {
+            Ok(()) => Some(path),
+            Err(error) => {
+                error!("Could not quarantine header to {}: {}", path.display(), error);
+                None
+            }
+        }
+    }
+
+    fn header_cbor(&self, content: &HeaderContent) -> Vec<u8> {
+        if self.store_raw_headers {
+            content.cbor.clone()
+        } else {
+            vec![]
+        }
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        if let Some(on_event) = self.on_event.as_ref() {
+            on_event(&event);
         }
     }
+
+    /// Save the pending blocks and log how far sync is, also as a json line with --progress-json
+    fn log_progress(&mut self, block_number: u64, tip_block_number: u64) -> Result<(), Error> {
+        let write_start = Instant::now();
+        self.save_pending_blocks()?;
+        let db_write_ms = write_start.elapsed().as_millis() as u64;
+        self.checkpoint_if_due()?;
+        if let (Some(store), Some(backup_request)) = (self.block_store.as_mut(), self.backup_request.as_ref()) {
+            db::write_requested_backup(store.as_mut(), backup_request);
+        }
+
+        let tip_block_number = max(block_number, tip_block_number);
+        let percent = (block_number as f64 / tip_block_number as f64 * 10000.0).floor() / 100.0;
+        info!(
+            "block {} of {}: {:>6.*}% sync'd",
+            block_number, tip_block_number, 2, percent,
+        );
+        if self.progress_json {
+            let blocks_per_second = match self.last_log_block_number {
+                Some(last_log_block_number) => {
+                    block_number.saturating_sub(last_log_block_number) as f64
+                        / self.last_log_time.elapsed().as_secs_f64()
+                }
+                None => 0.0,
+            };
+            reporter::report_compact(&SyncProgress {
+                status: if block_number >= tip_block_number {
+                    "synced"
+                } else {
+                    "syncing"
+                }
+                .to_string(),
+                block_number,
+                tip_block_number,
+                percent,
+                blocks_per_second: (blocks_per_second * 100.0).round() / 100.0,
+                eta_seconds: if blocks_per_second > 0.0 {
+                    Some(((tip_block_number - block_number) as f64 / blocks_per_second).ceil() as u64)
+                } else {
+                    None
+                },
+                db_write_ms,
+            })?;
+        }
+        if self.record_sync_tip && block_number >= tip_block_number {
+            runstate::record("sync", None, None, format!("block {block_number}"));
+        }
+        self.last_log_time = Instant::now();
+        self.last_log_block_number = Some(block_number);
+        Ok(())
+    }
+
+    /// Checkpoint the WAL once checkpoint_interval has passed since the last one
+    fn checkpoint_if_due(&mut self) -> Result<(), Error> {
+        let checkpoint_interval = match self.checkpoint_interval {
+            Some(checkpoint_interval) => checkpoint_interval,
+            None => return Ok(()),
+        };
+        if self.last_checkpoint_time.elapsed() < checkpoint_interval {
+            return Ok(());
+        }
+        if let Some(store) = self.block_store.as_mut() {
+            let checkpoint_start = Instant::now();
+            store.checkpoint()?;
+            debug!("WAL checkpoint took: {:?}", checkpoint_start.elapsed());
+        }
+        self.last_checkpoint_time = Instant::now();
+        Ok(())
+    }
 }
 
 enum Continuation {
@@ -95,7 +502,8 @@ enum Continuation {
 trait Observer<V> {
     fn on_roll_forward(&mut self, content: &HeaderContent, tip: &Tip) -> Result<Continuation, Error>;
     fn on_rollback(&mut self, point: &Point) -> Result<Continuation, Error>;
-    fn on_tip_reached(&mut self) -> Result<Continuation, Error>;
+    fn on_tip_reached(&mut self, block_number: u64, slot_number: u64) -> Result<Continuation, Error>;
+    fn on_block_body(&mut self, point: &Point, body: &[u8]) -> Result<(), Error>;
 }
 
 impl Observer<HeaderContent> for LoggingObserver {
@@ -119,7 +527,7 @@ impl Observer<HeaderContent> for LoggingObserver {
                             }
                             MultiEraHeader::ShelleyCompatible(header) => {
                                 //sqlite only handles signed values so some casting is done here
-                                self.pending_blocks.push(BlockHeader {
+                                let is_new_block = self.push_pending_block(BlockHeader {
                                     block_number: header.header_body.block_number,
                                     slot_number: slot,
                                     hash: hash.to_vec(),
@@ -143,34 +551,23 @@ impl Observer<HeaderContent> for LoggingObserver {
                                     unknown_2: header.header_body.operational_cert_sigma.to_vec(),
                                     protocol_major_version: header.header_body.protocol_major,
                                     protocol_minor_version: header.header_body.protocol_minor,
+                                    header_cbor: self.header_cbor(content),
                                 });
-                                let block_number: f64 = header.header_body.block_number as f64;
-                                let tip_block_number: f64 = tip.1 as f64;
+                                if is_new_block && self.capture_protocol_updates {
+                                    // tpraos era blocks can carry d and extra entropy update proposals
+                                    self.pending_body_fetch = Some(Point::Specific(slot, hash.to_vec()));
+                                }
                                 let is_tip = header.header_body.block_number >= tip.1;
-                                if is_tip || self.last_log_time.elapsed() > FIVE_SECS {
-                                    match self.block_store.as_mut() {
-                                        None => {}
-                                        Some(store) => {
-                                            store.save_block(&mut self.pending_blocks, &self.shelley_genesis_hash)?;
-                                        }
-                                    }
-
-                                    info!(
-                                        "block {} of {}: {:>6.*}% sync'd",
-                                        header.header_body.block_number,
-                                        tip.1,
-                                        2,
-                                        (block_number / tip_block_number * 10000.0).floor() / 100.0,
-                                    );
-                                    self.last_log_time = Instant::now();
+                                if is_tip || self.last_log_time.elapsed() > self.flush_interval {
+                                    self.log_progress(header.header_body.block_number, tip.1)?;
                                 }
                                 if is_tip {
-                                    result = self.on_tip_reached();
+                                    result = self.on_tip_reached(header.header_body.block_number, slot);
                                 }
                             }
                             MultiEraHeader::BabbageCompatible(header) => {
                                 //sqlite only handles signed values so some casting is done here
-                                self.pending_blocks.push(BlockHeader {
+                                self.push_pending_block(BlockHeader {
                                     block_number: header.header_body.block_number,
                                     slot_number: slot,
                                     hash: hash.to_vec(),
@@ -194,37 +591,30 @@ impl Observer<HeaderContent> for LoggingObserver {
                                     unknown_2: header.header_body.operational_cert.operational_cert_sigma.to_vec(),
                                     protocol_major_version: header.header_body.protocol_version.0,
                                     protocol_minor_version: header.header_body.protocol_version.1,
+                                    header_cbor: self.header_cbor(content),
                                 });
-                                let block_number: f64 = header.header_body.block_number as f64;
-                                let tip_block_number = max(header.header_body.block_number, tip.1);
-                                let tip_f64: f64 = tip_block_number as f64;
                                 let is_tip = header.header_body.block_number >= tip.1;
-                                if is_tip || self.last_log_time.elapsed() > FIVE_SECS {
-                                    match self.block_store.as_mut() {
-                                        None => {}
-                                        Some(store) => {
-                                            store.save_block(&mut self.pending_blocks, &self.shelley_genesis_hash)?;
-                                        }
-                                    }
-
-                                    info!(
-                                        "block {} of {}: {:>6.*}% sync'd",
-                                        header.header_body.block_number,
-                                        tip_block_number,
-                                        2,
-                                        (block_number / tip_f64 * 10000.0).floor() / 100.0,
-                                    );
-                                    self.last_log_time = Instant::now();
+                                if is_tip || self.last_log_time.elapsed() > self.flush_interval {
+                                    self.log_progress(header.header_body.block_number, tip.1)?;
                                 }
                                 if is_tip {
-                                    result = self.on_tip_reached();
+                                    result = self.on_tip_reached(header.header_body.block_number, slot);
                                 }
                             }
                         }
                     }
                     Err(error) => {
-                        error!("{:?}", error);
-                        std::process::exit(1);
+                        // likely a header of an era this cncli does not know yet. Keep what was synced before it and
+                        // reconnect, which gets the same header again until cncli is updated, instead of exiting.
+                        self.save_pending_blocks()?;
+                        let quarantined = match self.quarantine_header(content) {
+                            Some(path) => format!(", its cbor was saved to {}", path.display()),
+                            None => String::new(),
+                        };
+                        return Err(Error::HeaderDecode(format!(
+                            "cannot decode header of variant {}: {:?}{}",
+                            content.variant, error, quarantined
+                        )));
                     }
                 }
             }
@@ -238,12 +628,54 @@ impl Observer<HeaderContent> for LoggingObserver {
 
     fn on_rollback(&mut self, point: &Point) -> Result<Continuation, Error> {
         debug!("asked to roll back {:?}", point);
+        self.telemetry.add(ROLLBACKS_COUNTER, 1);
+        self.emit(SyncEvent::RollBack {
+            slot_number: point.slot_or_default(),
+            hash: match point {
+                Point::Origin => vec![],
+                Point::Specific(_, hash) => hash.clone(),
+            },
+        });
 
         Ok(Continuation::Proceed)
     }
 
-    fn on_tip_reached(&mut self) -> Result<Continuation, Error> {
+    fn on_block_body(&mut self, point: &Point, body: &[u8]) -> Result<(), Error> {
+        let block = MultiEraBlock::decode(body)?;
+        for tx in block.txs() {
+            if let Some(update) = tx.update() {
+                let decentralization = update
+                    .first_proposed_decentralization_constant()
+                    .map(|d| (d.numerator, d.denominator));
+                let extra_entropy = update
+                    .first_proposed_extra_entropy()
+                    .map(|extra_entropy| extra_entropy.hash.map(hex::encode).unwrap_or_default());
+                if decentralization.is_none() && extra_entropy.is_none() {
+                    continue;
+                }
+                let protocol_update = ProtocolUpdate {
+                    epoch: update.epoch(),
+                    slot_number: point.slot_or_default(),
+                    votes: protocol_update_votes(&update, decentralization, &extra_entropy),
+                    decentralization,
+                    extra_entropy,
+                };
+                info!("protocol update proposal: {:?}", &protocol_update);
+                if let Some(store) = self.block_store.as_mut() {
+                    store.save_protocol_update(&protocol_update)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_tip_reached(&mut self, block_number: u64, slot_number: u64) -> Result<Continuation, Error> {
         debug!("tip was reached");
+        self.tip_reached = true;
+        self.emit(SyncEvent::TipReached {
+            block_number,
+            slot_number,
+        });
         if self.exit_when_tip_reached {
             info!("Exiting...");
             Ok(Continuation::DropOut)
@@ -253,7 +685,19 @@ impl Observer<HeaderContent> for LoggingObserver {
     }
 }
 
-fn get_intersect_blocks(block_store: &mut Box<dyn BlockStore + Send>) -> Result<Vec<Point>, Error> {
+/// A trusted point to start syncing from without the chain history before it. eta_v is the rolling nonce after
+/// the block at slot/hash, e.g. the evolvingNonce from `cardano-cli query protocol-state` taken at that tip.
+#[derive(Debug, Clone)]
+pub(crate) struct NonceCheckpoint {
+    pub(crate) slot: u64,
+    pub(crate) hash: BlockHash,
+    pub(crate) eta_v: String,
+}
+
+fn get_intersect_blocks(
+    block_store: &mut Box<dyn BlockStore + Send>,
+    checkpoint: &Option<NonceCheckpoint>,
+) -> Result<Vec<Point>, Error> {
     let start = Instant::now();
     debug!("get_intersect_blocks");
 
@@ -268,6 +712,13 @@ fn get_intersect_blocks(block_store: &mut Box<dyn BlockStore + Send>) -> Result<
         }
     }
 
+    if let Some(checkpoint) = checkpoint {
+        // partial sync: never fall back to history before the checkpoint
+        chain_blocks.push(Point::Specific(checkpoint.slot, checkpoint.hash.to_bytes()));
+        info!("get_intersect_blocks took: {:?}", start.elapsed());
+        return Ok(chain_blocks);
+    }
+
     // add known points
     chain_blocks.push(
         // Last byron block of mainnet
@@ -304,14 +755,29 @@ fn get_intersect_blocks(block_store: &mut Box<dyn BlockStore + Send>) -> Result<
     Ok(chain_blocks)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn do_chainsync(
-    mut client: chainsync::N2NClient,
+    channel: AgentChannel,
+    mut blockfetch: Option<blockfetch::Client>,
     skip_to_tip: bool,
     exit_when_tip_reached: bool,
     chain_blocks: Option<Vec<Point>>,
     block_store: Option<Box<dyn BlockStore + 'static + Send>>,
     shelley_genesis_hash: String,
+    recent_hashes: RecentHashes,
+    filter_pool_ids: Vec<Vec<u8>>,
+    store_raw_headers: bool,
+    progress_json: bool,
+    sync_tuning: &SyncTuning,
+    on_event: Option<EventCallback>,
+    quarantine_dir: Option<PathBuf>,
+    backup_request: Option<PathBuf>,
+    post_epoch_report: Option<Arc<PostEpochReport>>,
+    source_peer: Option<String>,
+    telemetry: Telemetry,
+    mut recorder: Option<SessionRecorder>,
 ) -> Result<(), Error> {
+    let mut client = PipelinedChainSync::new(channel, sync_tuning.max_pipeline_depth);
     if skip_to_tip {
         client.intersect_tip().await?;
     } else {
@@ -322,14 +788,45 @@ async fn do_chainsync(
         exit_when_tip_reached,
         block_store,
         shelley_genesis_hash,
+        capture_protocol_updates: blockfetch.is_some(),
+        recent_hashes,
+        filter_pool_ids,
+        store_raw_headers,
+        progress_json,
+        record_sync_tip: !skip_to_tip,
+        flush_interval: sync_tuning.flush_interval,
+        checkpoint_interval: sync_tuning.checkpoint_interval,
+        on_event,
+        quarantine_dir,
+        backup_request,
+        post_epoch_report,
+        source_peer,
+        telemetry: telemetry.clone(),
         ..Default::default()
     };
-    let mut next = client.request_next().await?;
+    let mut next = request_next(&mut client, &telemetry).await?;
     loop {
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record_response(&next)?;
+        }
         match &next {
             NextResponse::RollForward(header_content, tip) => {
-                match logging_observer.on_roll_forward(header_content, tip)? {
-                    Continuation::Proceed => next = client.request_next().await?,
+                let continuation = logging_observer.on_roll_forward(header_content, tip)?;
+                if let (Some(point), Some(blockfetch)) =
+                    (logging_observer.pending_body_fetch.take(), blockfetch.as_mut())
+                {
+                    let body = {
+                        let mut span = telemetry.span("blockfetch.fetch_single", SpanKind::Client);
+                        span.attribute("slot", point.slot_or_default());
+                        blockfetch.fetch_single(point.clone()).await?
+                    };
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.record_block_body(&point, &body)?;
+                    }
+                    logging_observer.on_block_body(&point, &body)?;
+                }
+                match continuation {
+                    Continuation::Proceed => next = request_next(&mut client, &telemetry).await?,
                     Continuation::DropOut => {
                         client.send_done().await?;
                         return Ok(());
@@ -337,143 +834,649 @@ async fn do_chainsync(
                 }
             }
             NextResponse::RollBackward(point, _tip) => match logging_observer.on_rollback(point)? {
-                Continuation::Proceed => next = client.request_next().await?,
+                Continuation::Proceed => next = request_next(&mut client, &telemetry).await?,
                 Continuation::DropOut => {
                     client.send_done().await?;
                     return Ok(());
                 }
             },
-            NextResponse::Await => next = client.recv_while_must_reply().await?,
+            // the request stays with the node, which answers it with the next block
+            NextResponse::Await => next = request_next(&mut client, &telemetry).await?,
         }
     }
 }
 
-pub(crate) async fn sync(
-    db: &Path,
-    host: &str,
-    port: u16,
+/// Ask the node for the next header, timing the round trip
+async fn request_next(
+    client: &mut PipelinedChainSync,
+    telemetry: &Telemetry,
+) -> Result<NextResponse<HeaderContent>, Error> {
+    let _span = telemetry.span("chainsync.request_next", SpanKind::Client);
+    client.request_next().await
+}
+
+/// Follows the chain of a cardano-node over node-to-node chainsync, saving every header to a sqlite or redb db
+/// unless it runs without one, and telling callbacks about each block, rollback, tip and disconnect. It reconnects
+/// until the tip is reached with exit_when_tip_reached, or forever.
+///
+/// ```ignore
+/// let (sync_service, mut events) = SyncService::new("relay.example.com", 3001).db("cncli.db").event_stream();
+/// tokio::spawn(sync_service.run());
+/// while let Some(event) = events.recv().await {
+///     println!("{event:?}");
+/// }
+/// ```
+pub struct SyncService {
+    /// The peer given to new first, then the ones added with peer
+    peers: Vec<Peer>,
     network_magic: u64,
-    shelley_genesis_hash: &str,
-    no_service: bool,
+    db: Option<PathBuf>,
+    memory_block_store: MemoryBlockStore,
     use_redb: bool,
-) {
-    loop {
-        // Retry to establish connection forever
-        let mut block_store: Box<dyn BlockStore + Send> = if use_redb {
-            Box::new(RedbBlockStore::new(db).unwrap())
-        } else {
-            Box::new(SqLiteBlockStore::new(db).unwrap())
-        };
-        let chain_blocks = get_intersect_blocks(&mut block_store).unwrap();
-        match Bearer::connect_tcp_timeout(
-            &format!("{host}:{port}").to_socket_addrs().unwrap().next().unwrap(),
-            FIVE_SECS,
-        )
-        .await
-        {
-            Ok(bearer) => {
-                let mut plexer = Plexer::new(bearer);
+    mirror_db: Option<PathBuf>,
+    shelley_genesis_hash: String,
+    exit_when_tip_reached: bool,
+    capture_protocol_updates: bool,
+    checkpoint: Option<NonceCheckpoint>,
+    filter_pool_ids: Vec<Vec<u8>>,
+    store_raw_headers: bool,
+    progress_json: bool,
+    proxy: Option<SocksProxy>,
+    sync_tuning: SyncTuning,
+    on_event: Vec<EventCallback>,
+    post_epoch_report: Option<Arc<PostEpochReport>>,
+    telemetry: Telemetry,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
 
-                let channel = plexer.subscribe_client(PROTOCOL_N2N_HANDSHAKE);
-                let mut handshake = handshake::Client::new(channel);
+impl SyncService {
+    /// Sync mainnet from a node without storing anything. Use the other methods to change that.
+    pub fn new(host: &str, port: u16) -> Self {
+        SyncService {
+            peers: vec![Peer {
+                host: host.to_string(),
+                port,
+            }],
+            network_magic: MAINNET_MAGIC,
+            db: None,
+            memory_block_store: MemoryBlockStore::new(),
+            use_redb: false,
+            mirror_db: None,
+            shelley_genesis_hash: MAINNET_SHELLEY_GENESIS_HASH.to_string(),
+            exit_when_tip_reached: false,
+            capture_protocol_updates: false,
+            checkpoint: None,
+            filter_pool_ids: Vec::new(),
+            store_raw_headers: false,
+            progress_json: false,
+            proxy: None,
+            sync_tuning: SyncTuning::default(),
+            on_event: Vec::new(),
+            post_epoch_report: None,
+            telemetry: Telemetry::default(),
+            record: None,
+            replay: None,
+        }
+    }
 
-                let cs_channel = plexer.subscribe_client(PROTOCOL_N2N_CHAIN_SYNC);
-                let bf_channel = plexer.subscribe_client(PROTOCOL_N2N_BLOCK_FETCH);
-                let txsub_channel = plexer.subscribe_client(PROTOCOL_N2N_TX_SUBMISSION);
+    /// Save the headers to a sqlite db, or a redb one with use_redb. ":memory:" keeps them in memory until the
+    /// service ends.
+    pub fn db(mut self, db: impl Into<PathBuf>) -> Self {
+        self.db = Some(db.into());
+        self
+    }
 
-                let ka_channel = plexer.subscribe_client(PROTOCOL_N2N_KEEP_ALIVE);
-                let keepalive = keepalive::Client::new(ka_channel);
+    pub fn use_redb(mut self, use_redb: bool) -> Self {
+        self.use_redb = use_redb;
+        self
+    }
 
-                let plexer = plexer.spawn();
+    /// Also save the headers to a db of the other backend, e.g. to compare both with `cncli db diff`
+    pub fn mirror_db(mut self, mirror_db: impl Into<PathBuf>) -> Self {
+        self.mirror_db = Some(mirror_db.into());
+        self
+    }
 
-                let versions = handshake::n2n::VersionTable::v7_and_above(network_magic);
-                let handshake = handshake.handshake(versions).await;
+    /// Another node to sync from. With more than one, sync starts with the first and compares the tips of all of them
+    /// every minute. It switches to another when the followed one cannot be reached, or when most of the others are
+    /// more than a few blocks ahead of it, so a single relay on a minority fork cannot feed it blocks.
+    pub fn peer(mut self, host: &str, port: u16) -> Self {
+        self.peers.push(Peer {
+            host: host.to_string(),
+            port,
+        });
+        self
+    }
 
-                match handshake {
-                    Ok(confirmation) => match confirmation {
-                        Confirmation::Accepted(_, _) => {
-                            let keepalive =
-                                KeepAliveLoop::client(keepalive, Duration::from_secs(DEFAULT_KEEP_ALIVE_INTERVAL_SEC))
-                                    .spawn();
-
-                            let peer = PeerClient {
-                                plexer,
-                                keepalive,
-                                chainsync: chainsync::Client::new(cs_channel),
-                                blockfetch: blockfetch::Client::new(bf_channel),
-                                txsubmission: txsubmission::Client::new(txsub_channel),
-                            };
-
-                            let PeerClient {
-                                plexer,
-                                keepalive: _keepalive,
-                                chainsync,
-                                blockfetch: _blockfetch,
-                                txsubmission: _txsubmission,
-                            } = peer;
-
-                            let shelley_genesis_hash = shelley_genesis_hash.to_string();
-                            do_chainsync(
-                                chainsync,
-                                false,
-                                no_service,
-                                Some(chain_blocks),
-                                Some(block_store),
-                                shelley_genesis_hash,
-                            )
-                            .await
-                            .unwrap();
+    pub fn network_magic(mut self, network_magic: u64) -> Self {
+        self.network_magic = network_magic;
+        self
+    }
 
-                            plexer.abort().await;
-                        }
-                        Confirmation::Rejected(refuse_reason) => {
-                            error!("{:?}", refuse_reason);
-                        }
-                        Confirmation::QueryReply(_) => {
-                            error!("Unexpected QueryReply");
+    /// The rolling nonce of the first shelley block starts from this hash
+    pub fn shelley_genesis_hash(mut self, shelley_genesis_hash: &str) -> Self {
+        self.shelley_genesis_hash = shelley_genesis_hash.to_string();
+        self
+    }
+
+    /// Stop once the node's tip is reached instead of following it
+    pub fn exit_when_tip_reached(mut self, exit_when_tip_reached: bool) -> Self {
+        self.exit_when_tip_reached = exit_when_tip_reached;
+        self
+    }
+
+    /// Fetch the bodies of tpraos era blocks to save their d and extra entropy update proposals
+    pub fn capture_protocol_updates(mut self, capture_protocol_updates: bool) -> Self {
+        self.capture_protocol_updates = capture_protocol_updates;
+        self
+    }
+
+    /// Start from a trusted block instead of the chain history before it. eta_v is the rolling nonce after it.
+    pub fn checkpoint(mut self, slot: u64, hash: BlockHash, eta_v: &str) -> Self {
+        self.checkpoint = Some(NonceCheckpoint {
+            slot,
+            hash,
+            eta_v: eta_v.to_lowercase(),
+        });
+        self
+    }
+
+    /// Only store the full header of blocks from these pools
+    pub fn filter_pool_ids(mut self, filter_pool_ids: Vec<Vec<u8>>) -> Self {
+        self.filter_pool_ids = filter_pool_ids;
+        self
+    }
+
+    /// Also save the cbor of each header as received, so fields cncli does not parse yet can be read later without
+    /// syncing again. Blocks of pools left out by filter_pool_ids are stored without it.
+    pub fn store_raw_headers(mut self, store_raw_headers: bool) -> Self {
+        self.store_raw_headers = store_raw_headers;
+        self
+    }
+
+    /// Report the progress as json lines too
+    pub fn progress_json(mut self, progress_json: bool) -> Self {
+        self.progress_json = progress_json;
+        self
+    }
+
+    /// How long blocks are collected before they are written while catching up
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.sync_tuning.flush_interval = flush_interval;
+        self
+    }
+
+    /// How often the sqlite WAL is checkpointed and truncated, None leaves it to sqlite
+    pub fn checkpoint_interval(mut self, checkpoint_interval: Option<Duration>) -> Self {
+        self.sync_tuning.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
+    /// How many chainsync requests are kept in flight at most while catching up, 1 to not pipeline
+    pub fn max_pipeline_depth(mut self, max_pipeline_depth: usize) -> Self {
+        self.sync_tuning.max_pipeline_depth = max_pipeline_depth;
+        self
+    }
+
+    pub(crate) fn sqlite_tuning(mut self, sqlite_tuning: SqliteTuning) -> Self {
+        self.sync_tuning.sqlite = sqlite_tuning;
+        self
+    }
+
+    pub(crate) fn proxy(mut self, proxy: Option<SocksProxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Write the block report of each finished epoch once sync follows the tip
+    pub(crate) fn post_epoch_report(mut self, post_epoch_report: Option<PostEpochReport>) -> Self {
+        self.post_epoch_report = post_epoch_report.map(Arc::new);
+        self
+    }
+
+    /// Record spans for block persistence and chainsync round trips and count blocks and rollbacks
+    pub(crate) fn telemetry(mut self, telemetry: Telemetry) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    /// Append every chainsync message and block body received to a session file, which replay can run through sync
+    /// again without the node
+    pub fn record(mut self, session: impl Into<PathBuf>) -> Self {
+        self.record = Some(session.into());
+        self
+    }
+
+    /// Run the messages of a session file made with record through sync and the db instead of following a node.
+    /// It ends after the last message.
+    pub fn replay(mut self, session: impl Into<PathBuf>) -> Self {
+        self.replay = Some(session.into());
+        self
+    }
+
+    /// Call f with every event. Callbacks run on the sync task, so a slow one holds up syncing.
+    pub fn on_event<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&SyncEvent) + Send + Sync + 'static,
+    {
+        self.on_event.push(Arc::new(f));
+        self
+    }
+
+    /// Receive every event on a channel. Events are queued without limit until they are received.
+    pub fn event_stream(self) -> (Self, UnboundedReceiver<SyncEvent>) {
+        let (sender, receiver) = unbounded_channel();
+        let sync_service = self.on_event(move |event| {
+            // nobody is listening any more once the receiver is dropped
+            let _ = sender.send(event.clone());
+        });
+        (sync_service, receiver)
+    }
+
+    /// Sync until the tip is reached with exit_when_tip_reached, or forever. Lost connections are retried every 5
+    /// seconds, errors of the db end it.
+    pub async fn run(self) -> Result<(), Error> {
+        let resume_points: Arc<Mutex<Vec<Point>>> = Arc::new(Mutex::new(Vec::new()));
+        let on_event = self.event_callback(resume_points.clone());
+        if let Some(session) = self.replay.as_ref() {
+            return self.replay_session(session, on_event);
+        }
+        let mut current = 0;
+        loop {
+            if self.peers.len() > 1 {
+                current = self.choose_peer(current, on_event.as_ref()).await;
+            }
+            let mut block_store = self.open_block_store()?;
+            let (chain_blocks, recent_hashes) = match block_store.as_mut() {
+                Some(block_store) => {
+                    let chain_blocks = get_intersect_blocks(block_store, &self.checkpoint)?;
+                    // headers replayed from the intersection point that are already stored are skipped
+                    let recent_hashes = RecentHashes::from_blocks(block_store.load_blocks()?);
+                    (Some(chain_blocks), recent_hashes)
+                }
+                None => {
+                    // without a db, continue after the last blocks seen, or at the checkpoint or the tip
+                    let mut chain_blocks: Vec<Point> = resume_points.lock().unwrap().iter().rev().cloned().collect();
+                    if let Some(checkpoint) = self.checkpoint.as_ref() {
+                        chain_blocks.push(Point::Specific(checkpoint.slot, checkpoint.hash.to_bytes()));
+                    }
+                    let chain_blocks = if chain_blocks.is_empty() {
+                        None
+                    } else {
+                        Some(chain_blocks)
+                    };
+                    (chain_blocks, RecentHashes::default())
+                }
+            };
+
+            match self
+                .follow(current, block_store, chain_blocks, recent_hashes, on_event.clone())
+                .await?
+            {
+                FollowEnd::TipReached => return Ok(()),
+                FollowEnd::Disconnected(reason) => {
+                    error!("{}", reason);
+                    if let Some(on_event) = on_event.as_ref() {
+                        on_event(&SyncEvent::Disconnected { reason });
+                    }
+                }
+                // choose_peer switches right away
+                FollowEnd::MinorityFork(reason) => {
+                    warn!("{}", reason);
+                    continue;
+                }
+            }
+
+            if self.exit_when_tip_reached {
+                return Ok(());
+            }
+
+            warn!("Disconnected... retry in 5 secs...");
+            tokio::time::sleep(FIVE_SECS).await;
+        }
+    }
+
+    /// Feed the messages of a recorded session to the observer as if they came from a node. The headers keep the
+    /// time they were originally received at, so the same session on the same db gives the same result.
+    fn replay_session(&self, session: &Path, on_event: Option<EventCallback>) -> Result<(), Error> {
+        let messages = session::read_session(session)?;
+        let mut block_store = self.open_block_store()?;
+        let recent_hashes = match block_store.as_mut() {
+            Some(block_store) => RecentHashes::from_blocks(block_store.load_blocks()?),
+            None => RecentHashes::default(),
+        };
+        info!("Replaying {} messages from {}", messages.len(), session.display());
+        let mut logging_observer = LoggingObserver {
+            block_store,
+            shelley_genesis_hash: self.initial_nonce(),
+            capture_protocol_updates: self.capture_protocol_updates,
+            recent_hashes,
+            filter_pool_ids: self.filter_pool_ids.clone(),
+            store_raw_headers: self.store_raw_headers,
+            progress_json: self.progress_json,
+            flush_interval: self.sync_tuning.flush_interval,
+            checkpoint_interval: self.sync_tuning.checkpoint_interval,
+            on_event,
+            post_epoch_report: self.post_epoch_report.clone(),
+            telemetry: self.telemetry.clone(),
+            ..Default::default()
+        };
+        for message in messages {
+            match message {
+                SessionMessage::RollForward {
+                    received_at,
+                    header,
+                    tip,
+                } => {
+                    logging_observer.received_at = Some(received_at);
+                    logging_observer.on_roll_forward(&header, &tip)?;
+                }
+                SessionMessage::RollBackward { point } => {
+                    logging_observer.on_rollback(&point)?;
+                }
+                SessionMessage::BlockBody { point, body } => {
+                    // the header before it asked for it, unless this replay does not capture protocol updates
+                    if logging_observer.pending_body_fetch.take().is_some() {
+                        logging_observer.on_block_body(&point, &body)?;
+                    }
+                }
+            }
+        }
+        logging_observer.save_pending_blocks()
+    }
+
+    /// Blocks without a stored predecessor start their rolling nonce from here
+    fn initial_nonce(&self) -> String {
+        match self.checkpoint.as_ref() {
+            Some(checkpoint) => checkpoint.eta_v.clone(),
+            None => self.shelley_genesis_hash.clone(),
+        }
+    }
+
+    /// Compare the tips of all peers and switch to another one if the current one cannot be reached or serves a
+    /// minority fork
+    async fn choose_peer(&self, current: usize, on_event: Option<&EventCallback>) -> usize {
+        let tips = peers::probe_tips(&self.peers, &self.proxy, self.network_magic).await;
+        let selected = peers::select_peer(current, &tips);
+        if selected == current {
+            return current;
+        }
+        let (from, to) = (&self.peers[current], &self.peers[selected]);
+        let to_tip = tips[selected].map(|tip| tip.block_number);
+        let reason = match tips[current] {
+            Some(from_tip) => minority_fork_reason(from, from_tip),
+            None => format!("{from} cannot be reached"),
+        };
+        warn!(
+            from = %from,
+            to = %to,
+            from_tip = ?tips[current].map(|tip| tip.block_number),
+            to_tip = ?to_tip,
+            "Switching to peer {}: {}", to, reason
+        );
+        if let Some(on_event) = on_event {
+            on_event(&SyncEvent::PeerSwitched {
+                from: from.to_string(),
+                to: to.to_string(),
+                reason,
+            });
+        }
+        selected
+    }
+
+    /// While following a peer, compare the tips of all peers every CHAIN_SELECTION_INTERVAL and return why once the
+    /// followed one serves a minority fork. It never returns with a single peer. A followed peer that cannot be
+    /// reached is left to the chainsync connection to notice.
+    async fn watch_peers(&self, current: usize) -> String {
+        if self.peers.len() < 2 {
+            return std::future::pending().await;
+        }
+        loop {
+            tokio::time::sleep(CHAIN_SELECTION_INTERVAL).await;
+            let tips = peers::probe_tips(&self.peers, &self.proxy, self.network_magic).await;
+            if let Some(current_tip) = tips[current] {
+                if peers::select_peer(current, &tips) != current {
+                    return minority_fork_reason(&self.peers[current], current_tip);
+                }
+            }
+        }
+    }
+
+    /// The callbacks in one, which also remembers the last blocks seen to resume from without a db
+    fn event_callback(&self, resume_points: Arc<Mutex<Vec<Point>>>) -> Option<EventCallback> {
+        let callbacks = self.on_event.clone();
+        let remember = self.db.is_none();
+        if callbacks.is_empty() && !remember {
+            return None;
+        }
+        Some(Arc::new(move |event: &SyncEvent| {
+            if remember {
+                let mut resume_points = resume_points.lock().unwrap();
+                match event {
+                    SyncEvent::BlockStored(block) => {
+                        resume_points.push(Point::Specific(block.slot_number, block.hash.clone()));
+                        if resume_points.len() > MAX_RESUME_POINTS {
+                            resume_points.remove(0);
                         }
-                    },
-                    Err(error) => {
-                        error!("{:?}", error);
                     }
+                    SyncEvent::RollBack { slot_number, .. } => {
+                        resume_points.retain(|point| point.slot_or_default() <= *slot_number)
+                    }
+                    _ => {}
                 }
             }
-            Err(error) => {
-                error!("{}", error);
+            for callback in callbacks.iter() {
+                callback(event);
             }
+        }))
+    }
+
+    fn open_block_store(&self) -> Result<Option<Box<dyn BlockStore + Send>>, Error> {
+        let db = match self.db.as_ref() {
+            Some(db) => db,
+            None => return Ok(None),
+        };
+        let mut block_store: Box<dyn BlockStore + Send> = if db.as_os_str() == MEMORY_DB {
+            // the same blocks again after a reconnect
+            Box::new(self.memory_block_store.clone())
+        } else if self.use_redb {
+            Box::new(RedbBlockStore::new(db)?)
+        } else {
+            let sqlite_block_store = SqLiteBlockStore::new(db)?;
+            sqlite_block_store.tune(&self.sync_tuning.sqlite)?;
+            Box::new(sqlite_block_store)
+        };
+        if let Some(mirror_db) = self.mirror_db.as_ref() {
+            // mirror to the other backend so both can be compared with `cncli db diff`
+            let mirror_block_store: Box<dyn BlockStore + Send> = if self.use_redb {
+                let sqlite_block_store = SqLiteBlockStore::new(mirror_db)?;
+                sqlite_block_store.tune(&self.sync_tuning.sqlite)?;
+                Box::new(sqlite_block_store)
+            } else {
+                Box::new(RedbBlockStore::new(mirror_db)?)
+            };
+            block_store = Box::new(MirrorBlockStore::new(block_store, mirror_block_store));
         }
+        Ok(Some(block_store))
+    }
+
+    /// Connect to a peer and follow its chain until the tip is reached with exit_when_tip_reached, the connection
+    /// ends, or the peer turns out to serve a minority fork
+    async fn follow(
+        &self,
+        current: usize,
+        block_store: Option<Box<dyn BlockStore + Send>>,
+        chain_blocks: Option<Vec<Point>>,
+        recent_hashes: RecentHashes,
+        on_event: Option<EventCallback>,
+    ) -> Result<FollowEnd, Error> {
+        let recorder = match self.record.as_deref() {
+            Some(session) => Some(SessionRecorder::open(session)?),
+            None => None,
+        };
+        let peer = &self.peers[current];
+        let bearer = match proxy::connect(&self.proxy, &peer.host, peer.port, FIVE_SECS).await {
+            Ok(bearer) => bearer,
+            Err(error) => return Ok(FollowEnd::Disconnected(error.to_string())),
+        };
+        let mut plexer = Plexer::new(bearer);
+
+        let channel = plexer.subscribe_client(PROTOCOL_N2N_HANDSHAKE);
+        let mut handshake = handshake::Client::new(channel);
 
-        if no_service {
-            break;
+        let cs_channel = plexer.subscribe_client(PROTOCOL_N2N_CHAIN_SYNC);
+        let bf_channel = plexer.subscribe_client(PROTOCOL_N2N_BLOCK_FETCH);
+        let txsub_channel = plexer.subscribe_client(PROTOCOL_N2N_TX_SUBMISSION);
+
+        let ka_channel = plexer.subscribe_client(PROTOCOL_N2N_KEEP_ALIVE);
+        let keepalive = keepalive::Client::new(ka_channel);
+
+        let plexer = plexer.spawn();
+
+        let versions = handshake::n2n::VersionTable::v7_and_above(self.network_magic);
+        let confirmation = match handshake.handshake(versions).await {
+            Ok(confirmation) => confirmation,
+            Err(error) => return Ok(FollowEnd::Disconnected(format!("{error:?}"))),
+        };
+        match confirmation {
+            Confirmation::Accepted(_, _) => {}
+            Confirmation::Rejected(refuse_reason) => return Ok(FollowEnd::Disconnected(format!("{refuse_reason:?}"))),
+            Confirmation::QueryReply(_) => return Ok(FollowEnd::Disconnected("Unexpected QueryReply".to_string())),
         }
+        let keepalive = spawn_keepalive(keepalive, peer.to_string());
+        let blockfetch = blockfetch::Client::new(bf_channel);
+        let _txsubmission = txsubmission::Client::new(txsub_channel);
 
-        warn!("Disconnected... retry in 5 secs...");
-        tokio::time::sleep(FIVE_SECS).await;
+        let following = do_chainsync(
+            cs_channel,
+            if self.capture_protocol_updates {
+                Some(blockfetch)
+            } else {
+                None
+            },
+            chain_blocks.is_none(),
+            self.exit_when_tip_reached,
+            chain_blocks,
+            block_store,
+            self.initial_nonce(),
+            recent_hashes,
+            self.filter_pool_ids.clone(),
+            self.store_raw_headers,
+            self.progress_json,
+            &self.sync_tuning,
+            on_event,
+            self.db
+                .as_deref()
+                .filter(|db| db.as_os_str() != MEMORY_DB)
+                .map(quarantine_dir),
+            self.db
+                .as_deref()
+                .filter(|db| db.as_os_str() != MEMORY_DB)
+                .map(db::backup_request_path),
+            self.post_epoch_report.clone(),
+            Some(peer.to_string()),
+            self.telemetry.clone(),
+            recorder,
+        );
+        // the pending blocks are dropped when the peer is left for another one, which sends them again
+        let result = tokio::select! {
+            result = following => result.map(|()| FollowEnd::TipReached),
+            reason = self.watch_peers(current) => Ok(FollowEnd::MinorityFork(reason)),
+        };
+
+        keepalive.abort();
+        plexer.abort().await;
+        match result {
+            Ok(follow_end) => Ok(follow_end),
+            Err(error) if error.is_fatal() => Err(error),
+            Err(error) => Ok(FollowEnd::Disconnected(error.to_string())),
+        }
     }
 }
 
+/// Why following a peer ended
+enum FollowEnd {
+    /// The tip was reached with exit_when_tip_reached
+    TipReached,
+    Disconnected(String),
+    MinorityFork(String),
+}
+
+fn minority_fork_reason(peer: &Peer, tip: PeerTip) -> String {
+    format!(
+        "{} serves a minority fork, its tip is block {} and most other peers are more than {} blocks ahead",
+        peer, tip.block_number, MINORITY_FORK_BLOCKS
+    )
+}
+
+/// Headers that cannot be decoded are kept next to the db in <db>.quarantine
+fn quarantine_dir(db: &Path) -> PathBuf {
+    let mut quarantine_dir = db.as_os_str().to_os_string();
+    quarantine_dir.push(".quarantine");
+    PathBuf::from(quarantine_dir)
+}
+
+/// How many genesis delegates proposed the same decentralization and extra entropy as the first proposal of an
+/// update. The ledger only enacts an update that a quorum of them proposed.
+fn protocol_update_votes(
+    update: &MultiEraUpdate,
+    decentralization: Option<(u64, u64)>,
+    extra_entropy: &Option<String>,
+) -> Option<u64> {
+    update.as_alonzo().map(|update| {
+        update
+            .proposed_protocol_parameter_updates
+            .iter()
+            .filter(|(_, proposal)| {
+                proposal
+                    .decentralization_constant
+                    .as_ref()
+                    .map(|d| (d.numerator, d.denominator))
+                    == decentralization
+                    && proposal
+                        .extra_entropy
+                        .as_ref()
+                        .map(|extra_entropy| extra_entropy.hash.as_ref().map(hex::encode).unwrap_or_default())
+                        == *extra_entropy
+            })
+            .count() as u64
+    })
+}
+
+/// Follow the tip on every relay of a pool. The relays share the blocks they have seen so each block is sent to
+/// pooltool once, with the time the first relay saw it.
 pub(crate) async fn sendtip(
     pool_name: String,
-    pool_id: String,
-    host: String,
-    port: u16,
+    pool_id: PoolId,
+    relays: Vec<Relay>,
+    quorum: usize,
     api_key: String,
     cardano_node_path: &Path,
+    proxy: &Option<SocksProxy>,
 ) {
+    let seen_tips = Arc::new(Mutex::new(SeenTips::default()));
+    join_all(relays.into_iter().map(|relay| {
+        let pooltool_notifier = pooltool::PoolToolNotifier::new(
+            pool_name.clone(),
+            pool_id.clone(),
+            api_key.clone(),
+            cardano_node_path.to_path_buf(),
+            relay,
+            quorum,
+            seen_tips.clone(),
+        );
+        sendtip_relay(pooltool_notifier, proxy)
+    }))
+    .await;
+}
+
+async fn sendtip_relay(pooltool_notifier: pooltool::PoolToolNotifier, proxy: &Option<SocksProxy>) {
+    let host = pooltool_notifier.relay.host.clone();
+    let port = pooltool_notifier.relay.port;
     loop {
-        let pooltool_notifier = pooltool::PoolToolNotifier {
-            pool_name: pool_name.clone(),
-            pool_id: pool_id.clone(),
-            api_key: api_key.clone(),
-            cardano_node_path: cardano_node_path.to_path_buf(),
-            ..Default::default()
-        };
-        match Bearer::connect_tcp_timeout(
-            &format!("{host}:{port}").to_socket_addrs().unwrap().next().unwrap(),
-            FIVE_SECS,
-        )
-        .await
-        {
+        let pooltool_notifier = pooltool_notifier.clone();
+        match proxy::connect(proxy, &host, port, FIVE_SECS).await {
             Ok(bearer) => {
                 let mut plexer = Plexer::new(bearer);
 
@@ -495,37 +1498,37 @@ pub(crate) async fn sendtip(
                 match handshake {
                     Ok(confirmation) => match confirmation {
                         Confirmation::Accepted(_, _) => {
-                            let keepalive =
-                                KeepAliveLoop::client(keepalive, Duration::from_secs(DEFAULT_KEEP_ALIVE_INTERVAL_SEC))
-                                    .spawn();
-
-                            let peer = PeerClient {
-                                plexer,
-                                keepalive,
-                                chainsync: chainsync::Client::new(cs_channel),
-                                blockfetch: blockfetch::Client::new(bf_channel),
-                                txsubmission: txsubmission::Client::new(txsub_channel),
-                            };
-
-                            let PeerClient {
-                                plexer,
-                                keepalive: _keepalive,
-                                chainsync,
-                                blockfetch: _blockfetch,
-                                txsubmission: _txsubmission,
-                            } = peer;
-
-                            do_chainsync(
-                                chainsync,
+                            let keepalive = spawn_keepalive(keepalive, format!("{host}:{port}"));
+                            let _blockfetch = blockfetch::Client::new(bf_channel);
+                            let _txsubmission = txsubmission::Client::new(txsub_channel);
+
+                            if let Err(error) = do_chainsync(
+                                cs_channel,
+                                None,
                                 true,
                                 false,
                                 None,
                                 Some(Box::new(pooltool_notifier)),
-                                "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81".to_string(),
+                                MAINNET_SHELLEY_GENESIS_HASH.to_string(),
+                                RecentHashes::default(),
+                                Vec::new(),
+                                false,
+                                false,
+                                &SyncTuning::default(),
+                                None,
+                                None,
+                                None,
+                                None,
+                                None,
+                                Telemetry::default(),
+                                None,
                             )
                             .await
-                            .unwrap();
+                            {
+                                error!("{}", error);
+                            }
 
+                            keepalive.abort();
                             plexer.abort().await;
                         }
                         Confirmation::Rejected(refuse_reason) => {
@@ -545,7 +1548,78 @@ pub(crate) async fn sendtip(
             }
         }
 
-        warn!("Disconnected... retry in 5 secs...");
+        warn!("Disconnected from {}:{}... retry in 5 secs...", host, port);
         tokio::time::sleep(FIVE_SECS).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(slot_number: u64) -> SyncEvent {
+        SyncEvent::BlockStored(SyncedBlock {
+            block_number: slot_number,
+            slot_number,
+            hash: vec![slot_number as u8; 32],
+            prev_hash: vec![],
+            pool_id: vec![],
+            block_size: 0,
+            protocol_major_version: 10,
+            protocol_minor_version: 0,
+        })
+    }
+
+    #[test]
+    fn test_event_callback_remembers_resume_points() {
+        let received = Arc::new(Mutex::new(0));
+        let counter = received.clone();
+        let sync_service = SyncService::new("127.0.0.1", 3001).on_event(move |_| *counter.lock().unwrap() += 1);
+        let resume_points = Arc::new(Mutex::new(Vec::new()));
+        let on_event = sync_service.event_callback(resume_points.clone()).unwrap();
+
+        for slot_number in 0..MAX_RESUME_POINTS as u64 + 5 {
+            on_event(&block(slot_number));
+        }
+        assert_eq!(resume_points.lock().unwrap().len(), MAX_RESUME_POINTS);
+        assert_eq!(resume_points.lock().unwrap()[0].slot_or_default(), 5);
+
+        // blocks after a rollback are replaced, so they cannot be resumed from
+        on_event(&SyncEvent::RollBack {
+            slot_number: 10,
+            hash: vec![10; 32],
+        });
+        assert_eq!(resume_points.lock().unwrap().last().unwrap().slot_or_default(), 10);
+        assert_eq!(*received.lock().unwrap(), MAX_RESUME_POINTS + 6);
+
+        // with a db the points come from the db and nothing needs to be remembered
+        assert!(SyncService::new("127.0.0.1", 3001)
+            .db("cncli.db")
+            .event_callback(resume_points)
+            .is_none());
+    }
+
+    #[test]
+    fn test_quarantine_header() {
+        let db = std::env::temp_dir().join("cncli_test_quarantine.db");
+        let quarantine_dir = quarantine_dir(&db);
+        assert_eq!(
+            quarantine_dir,
+            std::env::temp_dir().join("cncli_test_quarantine.db.quarantine")
+        );
+        let _ = std::fs::remove_dir_all(&quarantine_dir);
+        let logging_observer = LoggingObserver {
+            quarantine_dir: Some(quarantine_dir.clone()),
+            ..Default::default()
+        };
+        let content = HeaderContent {
+            variant: 7,
+            byron_prefix: None,
+            cbor: vec![0x82, 0x01, 0x02],
+        };
+        let path = logging_observer.quarantine_header(&content).unwrap();
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("header-7-"));
+        assert_eq!(std::fs::read(&path).unwrap(), content.cbor);
+        std::fs::remove_dir_all(&quarantine_dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures::future::join_all;
+use pallas_network::miniprotocols::chainsync::Tip;
+use pallas_network::miniprotocols::handshake::Confirmation;
+use pallas_network::miniprotocols::{chainsync, handshake, Point, PROTOCOL_N2N_CHAIN_SYNC, PROTOCOL_N2N_HANDSHAKE};
+use pallas_network::multiplexer::Plexer;
+use tracing::warn;
+
+use crate::nodeclient::proxy;
+use crate::nodeclient::proxy::SocksProxy;
+use crate::nodeclient::sync::FIVE_SECS;
+
+// the tips of peers on the same chain differ by a block or two while a new block propagates
+pub(crate) const MINORITY_FORK_BLOCKS: u64 = 5;
+// how often the tips of the other peers are compared with the followed one
+pub(crate) const CHAIN_SELECTION_INTERVAL: Duration = Duration::from_secs(60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A node to sync from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    pub host: String,
+    pub port: u16,
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+impl FromStr for Peer {
+    type Err = String;
+    fn from_str(peer: &str) -> Result<Self, Self::Err> {
+        match peer.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() => match port.parse() {
+                Ok(port) => Ok(Peer {
+                    host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+                    port,
+                }),
+                Err(_) => Err(format!("Invalid peer port: {peer}")),
+            },
+            _ => Err(format!("Invalid peer: {peer}, expected host:port")),
+        }
+    }
+}
+
+/// The tip a peer reported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PeerTip {
+    pub(crate) block_number: u64,
+    pub(crate) slot_number: u64,
+}
+
+/// Ask every peer for its tip at the same time. Peers that cannot be reached have none.
+pub(crate) async fn probe_tips(peers: &[Peer], proxy: &Option<SocksProxy>, network_magic: u64) -> Vec<Option<PeerTip>> {
+    join_all(peers.iter().map(|peer| async move {
+        match tokio::time::timeout(PROBE_TIMEOUT, probe_tip(peer, proxy, network_magic)).await {
+            Ok(Ok(tip)) => Some(tip),
+            Ok(Err(reason)) => {
+                warn!("Could not get the tip of {}: {}", peer, reason);
+                None
+            }
+            Err(_) => {
+                warn!("Could not get the tip of {}: timed out", peer);
+                None
+            }
+        }
+    }))
+    .await
+}
+
+/// Connect just long enough to read the peer's tip from a chainsync intersection reply
+async fn probe_tip(peer: &Peer, proxy: &Option<SocksProxy>, network_magic: u64) -> Result<PeerTip, String> {
+    let bearer = proxy::connect(proxy, &peer.host, peer.port, FIVE_SECS)
+        .await
+        .map_err(|error| error.to_string())?;
+    let mut plexer = Plexer::new(bearer);
+    let mut handshake = handshake::Client::new(plexer.subscribe_client(PROTOCOL_N2N_HANDSHAKE));
+    let mut chainsync = chainsync::N2NClient::new(plexer.subscribe_client(PROTOCOL_N2N_CHAIN_SYNC));
+    let plexer = plexer.spawn();
+
+    let result = async {
+        let versions = handshake::n2n::VersionTable::v7_and_above(network_magic);
+        match handshake
+            .handshake(versions)
+            .await
+            .map_err(|error| format!("{error:?}"))?
+        {
+            Confirmation::Accepted(_, _) => {}
+            Confirmation::Rejected(refuse_reason) => return Err(format!("{refuse_reason:?}")),
+            Confirmation::QueryReply(_) => return Err("Unexpected QueryReply".to_string()),
+        }
+        // the origin is on every chain, so the reply always comes with the tip
+        let (_, Tip(point, block_number)) = chainsync
+            .find_intersect(vec![Point::Origin])
+            .await
+            .map_err(|error| error.to_string())?;
+        Ok(PeerTip {
+            block_number,
+            slot_number: point.slot_or_default(),
+        })
+    }
+    .await;
+
+    plexer.abort().await;
+    result
+}
+
+/// The peer to follow given the tips of all of them. The current one is kept unless it could not be reached, or
+/// most of the other reachable peers are more than MINORITY_FORK_BLOCKS blocks ahead of it, which means it serves a
+/// minority fork. The replacement is the median of the candidates by chain length rather than the longest, so one
+/// peer claiming a far ahead tip cannot pull sync over to itself. Of chains as long, the one that got there in fewer
+/// slots is denser and preferred.
+pub(crate) fn select_peer(current: usize, tips: &[Option<PeerTip>]) -> usize {
+    let others: Vec<(usize, PeerTip)> = tips
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != current)
+        .filter_map(|(index, tip)| tip.map(|tip| (index, tip)))
+        .collect();
+    let current_tip = match tips.get(current).copied().flatten() {
+        Some(current_tip) => current_tip,
+        None => return upper_median(others).unwrap_or(current),
+    };
+    let ahead: Vec<(usize, PeerTip)> = others
+        .iter()
+        .copied()
+        .filter(|(_, tip)| tip.block_number > current_tip.block_number + MINORITY_FORK_BLOCKS)
+        .collect();
+    if ahead.len() * 2 > others.len() {
+        upper_median(ahead).unwrap_or(current)
+    } else {
+        current
+    }
+}
+
+fn upper_median(mut peers: Vec<(usize, PeerTip)>) -> Option<usize> {
+    peers.sort_by_key(|(_, tip)| (tip.block_number, Reverse(tip.slot_number)));
+    peers.get(peers.len() / 2).map(|(index, _)| *index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tip(block_number: u64, slot_number: u64) -> Option<PeerTip> {
+        Some(PeerTip {
+            block_number,
+            slot_number,
+        })
+    }
+
+    #[test]
+    fn test_parse_peer() {
+        assert_eq!(
+            "relay1.example.com:3001".parse::<Peer>(),
+            Ok(Peer {
+                host: "relay1.example.com".to_string(),
+                port: 3001
+            })
+        );
+        assert_eq!("[::1]:6000".parse::<Peer>().unwrap().host, "::1");
+        assert!("relay1.example.com".parse::<Peer>().is_err());
+        assert!(":3001".parse::<Peer>().is_err());
+        assert!("relay1.example.com:port".parse::<Peer>().is_err());
+    }
+
+    #[test]
+    fn test_select_peer() {
+        // a block or two behind while blocks propagate is not a fork
+        assert_eq!(select_peer(0, &[tip(100, 1000), tip(102, 1040), tip(101, 1020)]), 0);
+        // most others are well ahead, follow the median of them
+        assert_eq!(
+            select_peer(0, &[tip(90, 1000), tip(120, 1500), tip(110, 1400), tip(200, 1500)]),
+            1
+        );
+        assert_eq!(select_peer(0, &[tip(90, 1000), tip(110, 1400), tip(112, 1420)]), 2);
+        // a single peer far ahead is outvoted
+        assert_eq!(select_peer(0, &[tip(100, 1000), tip(5000, 1000), tip(100, 1000)]), 0);
+        // with one other peer the longer chain wins
+        assert_eq!(select_peer(0, &[tip(90, 1000), tip(110, 1000)]), 1);
+        // as long a chain in fewer slots is denser
+        assert_eq!(select_peer(0, &[tip(90, 1000), tip(110, 1400), tip(110, 1300)]), 2);
+        // the current peer is unreachable
+        assert_eq!(select_peer(1, &[tip(100, 1000), None, tip(101, 1020)]), 2);
+        assert_eq!(select_peer(1, &[None, None]), 1);
+        assert_eq!(select_peer(0, &[tip(100, 1000), None, None]), 0);
+    }
+}
@@ -0,0 +1,224 @@
+use pallas_network::miniprotocols::chainsync::{HeaderContent, Message, NextResponse, Tip};
+use pallas_network::miniprotocols::Point;
+use pallas_network::multiplexer::{AgentChannel, ChannelBuffer};
+
+use crate::nodeclient::sync::Error;
+
+/// How many request_next messages are kept in flight at most by default. The node answers them in order, so while
+/// catching up each round trip to a distant relay brings this many headers instead of one.
+pub(crate) const DEFAULT_MAX_PIPELINE_DEPTH: usize = 50;
+
+/// The number of requests to keep in flight. It doubles with every header the node has ready, up to max, and drops
+/// to 1 as soon as the node has to wait for a new block. At the tip a single request waits at the node, which sends
+/// the next header the moment it has it, so there is nothing left to pipeline and no burst of requests to answer.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PipelineDepth {
+    depth: usize,
+    max: usize,
+}
+
+impl PipelineDepth {
+    pub(crate) fn new(max: usize) -> Self {
+        PipelineDepth {
+            depth: 1,
+            max: max.max(1),
+        }
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The node sent a header or rollback without waiting, so more of the chain is ready
+    fn on_ready(&mut self) {
+        self.depth = (self.depth * 2).min(self.max);
+    }
+
+    /// The node had nothing to send and waits for a new block, so we are at the tip
+    fn on_await(&mut self) {
+        self.depth = 1;
+    }
+}
+
+/// A node-to-node chainsync client that pipelines request_next with an adaptive depth. The pallas client allows one
+/// request at a time, so this one speaks the mini-protocol on the channel directly.
+pub(crate) struct PipelinedChainSync {
+    buffer: ChannelBuffer,
+    in_flight: usize,
+    depth: PipelineDepth,
+}
+
+fn channel_error(error: impl std::fmt::Display) -> Error {
+    Error::ChainSyncPipeline(error.to_string())
+}
+
+impl PipelinedChainSync {
+    pub(crate) fn new(channel: AgentChannel, max_depth: usize) -> Self {
+        PipelinedChainSync {
+            buffer: ChannelBuffer::new(channel),
+            in_flight: 0,
+            depth: PipelineDepth::new(max_depth),
+        }
+    }
+
+    async fn send(&mut self, message: &Message<HeaderContent>) -> Result<(), Error> {
+        self.buffer.send_msg_chunks(message).await.map_err(channel_error)
+    }
+
+    async fn recv(&mut self) -> Result<Message<HeaderContent>, Error> {
+        self.buffer.recv_full_msg().await.map_err(channel_error)
+    }
+
+    /// Find the first of points the node has, returning it and the tip of the node
+    pub(crate) async fn find_intersect(&mut self, points: Vec<Point>) -> Result<(Option<Point>, Tip), Error> {
+        if self.in_flight > 0 {
+            return Err(Error::ChainSyncPipeline(
+                "cannot find an intersection with requests in flight".to_string(),
+            ));
+        }
+        self.send(&Message::FindIntersect(points)).await?;
+        match self.recv().await? {
+            Message::IntersectFound(point, tip) => Ok((Some(point), tip)),
+            Message::IntersectNotFound(tip) => Ok((None, tip)),
+            message => Err(Error::ChainSyncPipeline(format!(
+                "unexpected answer to find intersect: {message:?}"
+            ))),
+        }
+    }
+
+    /// Start following from the tip of the node
+    pub(crate) async fn intersect_tip(&mut self) -> Result<Point, Error> {
+        let (_, Tip(tip, _)) = self.find_intersect(vec![Point::Origin]).await?;
+        match self.find_intersect(vec![tip.clone()]).await? {
+            (Some(point), _) => Ok(point),
+            (None, _) => Err(Error::ChainSyncPipeline(format!(
+                "the node did not find its own tip {tip:?}"
+            ))),
+        }
+    }
+
+    /// Top up the requests in flight to the current depth and return the next answer of the node. After Await the
+    /// next call waits for the header the node sends once it has a new block.
+    pub(crate) async fn request_next(&mut self) -> Result<NextResponse<HeaderContent>, Error> {
+        while self.in_flight < self.depth.depth() {
+            self.send(&Message::RequestNext).await?;
+            self.in_flight += 1;
+        }
+        match self.recv().await? {
+            Message::RollForward(header, tip) => {
+                self.in_flight -= 1;
+                self.depth.on_ready();
+                Ok(NextResponse::RollForward(header, tip))
+            }
+            Message::RollBackward(point, tip) => {
+                self.in_flight -= 1;
+                self.depth.on_ready();
+                Ok(NextResponse::RollBackward(point, tip))
+            }
+            Message::AwaitReply => {
+                self.depth.on_await();
+                Ok(NextResponse::Await)
+            }
+            message => Err(Error::ChainSyncPipeline(format!(
+                "unexpected answer to request next: {message:?}"
+            ))),
+        }
+    }
+
+    /// End chainsync. Done may only be sent with no requests in flight, and those left wait at the node for blocks
+    /// that may be long in coming, so with any in flight the connection is simply dropped.
+    pub(crate) async fn send_done(&mut self) -> Result<(), Error> {
+        if self.in_flight == 0 {
+            self.send(&Message::Done).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas_network::miniprotocols::PROTOCOL_N2N_CHAIN_SYNC;
+    use pallas_network::multiplexer::{Bearer, Plexer, RunningPlexer};
+    use tokio::net::TcpListener;
+    use tokio::time::{timeout, Duration};
+
+    use super::*;
+
+    /// A pipelined client connected over loopback to a node side that answers by hand
+    async fn connect(max_depth: usize) -> (PipelinedChainSync, ChannelBuffer, RunningPlexer, RunningPlexer) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client_bearer, node_bearer) = tokio::join!(Bearer::connect_tcp(addr), Bearer::accept_tcp(&listener));
+        let mut client_plexer = Plexer::new(client_bearer.unwrap());
+        let mut node_plexer = Plexer::new(node_bearer.unwrap().0);
+        let client = PipelinedChainSync::new(client_plexer.subscribe_client(PROTOCOL_N2N_CHAIN_SYNC), max_depth);
+        let node = ChannelBuffer::new(node_plexer.subscribe_server(PROTOCOL_N2N_CHAIN_SYNC));
+        (client, node, client_plexer.spawn(), node_plexer.spawn())
+    }
+
+    async fn recv(node: &mut ChannelBuffer) -> Message<HeaderContent> {
+        node.recv_full_msg().await.unwrap()
+    }
+
+    async fn roll_backward(node: &mut ChannelBuffer) {
+        let message: Message<HeaderContent> = Message::RollBackward(Point::Origin, Tip(Point::Origin, 0));
+        node.send_msg_chunks(&message).await.unwrap();
+    }
+
+    #[test]
+    fn test_pipeline_depth() {
+        let mut depth = PipelineDepth::new(50);
+        assert_eq!(depth.depth(), 1);
+        let depths: Vec<usize> = (0..7)
+            .map(|_| {
+                depth.on_ready();
+                depth.depth()
+            })
+            .collect();
+        assert_eq!(depths, vec![2, 4, 8, 16, 32, 50, 50]);
+        depth.on_await();
+        assert_eq!(depth.depth(), 1);
+
+        // a max of 0 or 1 turns pipelining off
+        let mut depth = PipelineDepth::new(0);
+        depth.on_ready();
+        assert_eq!(depth.depth(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_chainsync_in_flight() {
+        let (mut client, mut node, _client_plexer, _node_plexer) = connect(4).await;
+
+        // one request at first, answered right away
+        let (next, message) = tokio::join!(client.request_next(), async {
+            let message = recv(&mut node).await;
+            roll_backward(&mut node).await;
+            message
+        });
+        assert!(matches!(next.unwrap(), NextResponse::RollBackward(..)));
+        assert!(matches!(message, Message::RequestNext));
+        assert_eq!(client.in_flight, 0);
+        assert_eq!(client.depth.depth(), 2);
+
+        // then two, of which one is answered
+        let (next, messages) = tokio::join!(client.request_next(), async {
+            let messages = vec![recv(&mut node).await, recv(&mut node).await];
+            roll_backward(&mut node).await;
+            messages
+        });
+        assert!(matches!(next.unwrap(), NextResponse::RollBackward(..)));
+        assert!(messages.iter().all(|message| matches!(message, Message::RequestNext)));
+        assert_eq!(client.in_flight, 1);
+        assert_eq!(client.depth.depth(), 4);
+
+        // done is not sent while a request waits at the node
+        client.send_done().await.unwrap();
+        assert!(timeout(Duration::from_millis(200), recv(&mut node)).await.is_err());
+        assert!(client.find_intersect(vec![Point::Origin]).await.is_err());
+
+        // with nothing in flight it is
+        let (mut client, mut node, _client_plexer, _node_plexer) = connect(4).await;
+        client.send_done().await.unwrap();
+        assert!(matches!(recv(&mut node).await, Message::Done));
+    }
+}
@@ -1,23 +1,119 @@
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::BufReader;
 use std::ops::Sub;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use crate::nodeclient::blockstore;
-use crate::nodeclient::blockstore::{Block, BlockStore, Error};
+use crate::nodeclient::blockstore::{
+    Block, BlockDetail, BlockStore, Error, OpcertHistory, ProtocolUpdate, SavedSlots, SlotsInputs, StakeHistory,
+};
+use crate::nodeclient::poolid::{BlockHash, PoolId};
+use crate::nodeclient::pooltool::{
+    PooltoolClient, SendStatsV0Data, SendStatsV0Request, SendStatsV1Data, SendStatsV1Request,
+};
+use crate::nodeclient::reporter;
+use crate::nodeclient::runstate;
+use crate::nodeclient::stdinconfig;
 use crate::nodeclient::sync::BlockHeader;
-use crate::APP_USER_AGENT;
 use chrono::{SecondsFormat, Utc};
 use pallas_crypto::hash::Hash;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::{error, info, warn};
 
-pub(crate) fn get_pooltool_config(config: &Path) -> PooltoolConfig {
-    let buf = BufReader::new(File::open(config).unwrap());
-    serde_json::from_reader(buf).unwrap()
+// relays see a block within seconds of each other, only the latest blocks have to be remembered
+const MAX_SEEN_TIPS: usize = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Environment variable {0} referenced in pooltool config is not set")]
+    MissingEnvVar(String),
+
+    #[error("Unable to read secret file {0}: {1}")]
+    SecretFile(String, std::io::Error),
+
+    #[error("Invalid relays for pool {0}: {1}")]
+    Relays(String, String),
+
+    #[error("{0}")]
+    StdinConfig(#[from] crate::nodeclient::stdinconfig::Error),
+}
+
+pub(crate) fn get_pooltool_config(config: &Path) -> Result<PooltoolConfig, ConfigError> {
+    let buf = BufReader::new(File::open(config)?);
+    resolve_pooltool_config(serde_json::from_reader(buf)?)
+}
+
+/// Take the pooltool config from the --stdin-config document instead of a file
+pub(crate) fn get_pooltool_config_from_stdin() -> Result<PooltoolConfig, ConfigError> {
+    resolve_pooltool_config(stdinconfig::read()?)
+}
+
+fn resolve_pooltool_config(mut pooltool_config: PooltoolConfig) -> Result<PooltoolConfig, ConfigError> {
+    pooltool_config.api_key = resolve_config_value(&pooltool_config.api_key)?;
+    for pool in pooltool_config.pools.iter_mut() {
+        resolve_relays(pool)?;
+    }
+    Ok(pooltool_config)
+}
+
+/// Merge the single host/port of older configs into the relays and resolve their hosts
+fn resolve_relays(pool: &mut Pool) -> Result<(), ConfigError> {
+    let pool_name = pool.name.clone();
+    let invalid = |message: String| ConfigError::Relays(pool_name.clone(), message);
+    match (pool.host.take(), pool.port.take()) {
+        (Some(host), Some(port)) => pool.relays.insert(0, Relay { name: None, host, port }),
+        (None, None) => {}
+        _ => return Err(invalid("host and port must be given together".to_string())),
+    }
+    if pool.relays.is_empty() {
+        return Err(invalid("set host and port or relays".to_string()));
+    }
+    if pool.quorum == 0 || pool.quorum > pool.relays.len() {
+        return Err(invalid(format!("quorum must be between 1 and {}", pool.relays.len())));
+    }
+    for relay in pool.relays.iter_mut() {
+        relay.host = resolve_config_value(&relay.host)?;
+    }
+    Ok(())
+}
+
+/// Resolve a config value that may reference a secret instead of holding it directly.
+/// `file:/path/to/secret` is replaced by the trimmed contents of that file and every
+/// `${ENV_VAR}` occurrence is replaced by the value of the environment variable.
+pub(crate) fn resolve_config_value(value: &str) -> Result<String, ConfigError> {
+    if let Some(path) = value.strip_prefix("file:") {
+        return match std::fs::read_to_string(path) {
+            Ok(contents) => Ok(contents.trim().to_string()),
+            Err(error) => Err(ConfigError::SecretFile(path.to_string(), error)),
+        };
+    }
+
+    let env_regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)}").unwrap();
+    let mut resolved = String::with_capacity(value.len());
+    let mut last_end = 0;
+    for cap in env_regex.captures_iter(value) {
+        let whole = cap.get(0).unwrap();
+        let name = &cap[1];
+        let env_value = std::env::var(name).map_err(|_| ConfigError::MissingEnvVar(name.to_string()))?;
+        resolved.push_str(&value[last_end..whole.start()]);
+        resolved.push_str(&env_value);
+        last_end = whole.end();
+    }
+    resolved.push_str(&value[last_end..]);
+    Ok(resolved)
 }
 
 #[derive(Debug, Deserialize)]
@@ -29,88 +125,215 @@ pub(crate) struct PooltoolConfig {
 #[derive(Debug, Deserialize)]
 pub(crate) struct Pool {
     pub(crate) name: String,
-    pub(crate) pool_id: String,
+    #[serde(deserialize_with = "deserialize_pool_id")]
+    pub(crate) pool_id: PoolId,
+    // a single relay, merged into relays when the config is loaded
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    pub(crate) relays: Vec<Relay>,
+    /// How many relays must have seen a block before sendtip sends it
+    #[serde(default = "default_quorum")]
+    pub(crate) quorum: usize,
+    /// The pool's vrf.skey, for pool-check to compare with the registered vrf key
+    #[serde(default)]
+    pub(crate) vrf_skey: Option<PathBuf>,
+}
+
+fn default_quorum() -> usize {
+    1
+}
+
+/// The pool id is checked once it is resolved, since it may only be known then
+fn deserialize_pool_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PoolId, D::Error> {
+    let pool_id = resolve_config_value(&String::deserialize(deserializer)?).map_err(D::Error::custom)?;
+    PoolId::from_str(&pool_id).map_err(D::Error::custom)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub(crate) struct Relay {
+    /// Sent to pooltool as the node id of the tips this relay saw first
+    #[serde(default)]
+    pub(crate) name: Option<String>,
     pub(crate) host: String,
     pub(crate) port: u16,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct PooltoolStats0 {
-    api_key: String,
-    pool_id: String,
-    data: PooltoolData0,
+impl Relay {
+    pub(crate) fn label(&self) -> String {
+        match self.name {
+            Some(ref name) => name.clone(),
+            None => format!("{}:{}", self.host, self.port),
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct PooltoolData0 {
-    node_id: String,
-    version: String,
+/// The blocks seen by the relays of a pool. A block is sent once, as soon as quorum relays have seen it, with the time
+/// and relay it was seen first so a slow relay does not make the propagation look worse than it is.
+#[derive(Debug, Default)]
+pub(crate) struct SeenTips {
+    tips: VecDeque<SeenTip>,
+    sent: VecDeque<SentTip>,
+}
+
+#[derive(Debug)]
+struct SeenTip {
+    hash: BlockHash,
     at: String,
-    block_no: u64,
-    slot_no: u64,
-    block_hash: String,
-    parent_hash: String,
-    leader_vrf: String,
-    leader_vrf_proof: String,
-    node_v_key: String,
-    protocol_major_version: u64,
-    protocol_minor_version: u64,
-    platform: String,
+    relay: Relay,
+    seen_by: Vec<String>,
+    sent: bool,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct PooltoolStats1 {
-    api_key: String,
-    pool_id: String,
-    data: PooltoolData1,
+/// A block that was sent to pooltool, to tell when a fork leaves it off the chain or takes it back
+#[derive(Debug)]
+struct SentTip {
+    hash: BlockHash,
+    block_number: u64,
+    slot_number: u64,
+    orphaned: bool,
 }
 
-#[derive(Debug, Serialize)]
+/// A block sent to pooltool earlier that is no longer on the chain the relays follow, or that is back on it
+#[derive(Debug, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct PooltoolData1 {
-    node_id: String,
-    version: String,
-    at: String,
-    block_no: u64,
-    slot_no: u64,
-    block_hash: String,
-    parent_hash: String,
-    leader_vrf: String,
-    block_vrf: String,
-    block_vrf_proof: String,
-    node_v_key: String,
-    protocol_major_version: u64,
-    protocol_minor_version: u64,
-    platform: String,
+pub(crate) struct TipStatusUpdate {
+    /// orphaned or adopted
+    pub(crate) status: String,
+    pub(crate) block_number: u64,
+    pub(crate) slot_number: u64,
+    pub(crate) block_hash: BlockHash,
+    /// The block now on the chain at the same block number, when it is known
+    pub(crate) replaced_by: Option<BlockHash>,
+}
+
+impl SeenTips {
+    /// Record that relay saw the block at the given time. Returns the time and relay the block was seen first when
+    /// it should be sent now.
+    pub(crate) fn seen(
+        &mut self,
+        hash: &BlockHash,
+        relay: &Relay,
+        at: String,
+        quorum: usize,
+    ) -> Option<(String, Relay)> {
+        let index = match self.tips.iter().position(|tip| tip.hash == *hash) {
+            Some(index) => index,
+            None => {
+                self.tips.push_back(SeenTip {
+                    hash: hash.clone(),
+                    at,
+                    relay: relay.clone(),
+                    seen_by: vec![],
+                    sent: false,
+                });
+                if self.tips.len() > MAX_SEEN_TIPS {
+                    self.tips.pop_front();
+                }
+                self.tips.len() - 1
+            }
+        };
+        let tip = &mut self.tips[index];
+        let label = relay.label();
+        if !tip.seen_by.contains(&label) {
+            tip.seen_by.push(label);
+        }
+        if tip.sent || tip.seen_by.len() < quorum {
+            return None;
+        }
+        tip.sent = true;
+        Some((tip.at.clone(), tip.relay.clone()))
+    }
+
+    /// Record that the block was sent and compare it with the blocks sent before. A block at the same or a higher
+    /// block number, or a parent other than the one sent at the block number before, was orphaned by a fork. An
+    /// orphaned parent is adopted again when the chain switches back.
+    pub(crate) fn sent(
+        &mut self,
+        header: &BlockHeader,
+        hash: &BlockHash,
+        prev_hash: &BlockHash,
+    ) -> Vec<TipStatusUpdate> {
+        let mut updates = vec![];
+        for tip in self.sent.iter_mut() {
+            let (on_chain, replaced_by) = if tip.block_number >= header.block_number {
+                (false, (tip.block_number == header.block_number).then(|| hash.clone()))
+            } else if tip.block_number + 1 == header.block_number {
+                (tip.hash == *prev_hash, Some(prev_hash.clone()))
+            } else {
+                continue;
+            };
+            if on_chain == !tip.orphaned {
+                continue;
+            }
+            tip.orphaned = !on_chain;
+            updates.push(TipStatusUpdate {
+                status: if on_chain { "adopted" } else { "orphaned" }.to_string(),
+                block_number: tip.block_number,
+                slot_number: tip.slot_number,
+                block_hash: tip.hash.clone(),
+                replaced_by: if on_chain { None } else { replaced_by },
+            });
+        }
+        self.sent.push_back(SentTip {
+            hash: hash.clone(),
+            block_number: header.block_number,
+            slot_number: header.slot_number,
+            orphaned: false,
+        });
+        if self.sent.len() > MAX_SEEN_TIPS {
+            self.sent.pop_front();
+        }
+        updates
+    }
 }
 
+#[derive(Clone)]
 pub struct PoolToolNotifier {
     pub pool_name: String,
-    pub pool_id: String,
+    pub pool_id: PoolId,
     pub api_key: String,
     pub cardano_node_path: PathBuf,
     pub last_node_version_time: Instant,
     pub node_version: String,
+    pub(crate) relay: Relay,
+    pub(crate) quorum: usize,
+    pub(crate) seen_tips: Arc<Mutex<SeenTips>>,
 }
 
-impl Default for PoolToolNotifier {
-    fn default() -> Self {
+impl PoolToolNotifier {
+    pub(crate) fn new(
+        pool_name: String,
+        pool_id: PoolId,
+        api_key: String,
+        cardano_node_path: PathBuf,
+        relay: Relay,
+        quorum: usize,
+        seen_tips: Arc<Mutex<SeenTips>>,
+    ) -> Self {
         PoolToolNotifier {
-            pool_name: String::new(),
-            pool_id: String::new(),
-            api_key: String::new(),
-            cardano_node_path: PathBuf::new(),
+            pool_name,
+            pool_id,
+            api_key,
+            cardano_node_path,
             last_node_version_time: Instant::now().sub(Duration::from_secs(7200)), // 2 hours ago
             node_version: String::new(),
+            relay,
+            quorum,
+            seen_tips,
         }
     }
-}
 
-impl PoolToolNotifier {
-    pub fn send_to_pooltool(&mut self, header: &BlockHeader) {
+    pub(crate) fn send_to_pooltool(
+        &mut self,
+        header: &BlockHeader,
+        hash: &BlockHash,
+        prev_hash: &BlockHash,
+        at: String,
+        relay: &Relay,
+    ) {
         if self.last_node_version_time.elapsed() > Duration::from_secs(3600) {
             // Our node version is outdated. Make a call to update it.
             match Command::new(&self.cardano_node_path)
@@ -139,89 +362,123 @@ impl PoolToolNotifier {
             }
         }
 
-        match reqwest::blocking::Client::builder().user_agent(APP_USER_AGENT).build() {
-            Ok(client) => {
-                let pooltool_result = if header.block_vrf_0.is_empty() {
-                    client
-                        .post("https://api.pooltool.io/v0/sendstats")
-                        .body(
-                            serde_json::ser::to_string(&PooltoolStats0 {
-                                api_key: self.api_key.clone(),
-                                pool_id: self.pool_id.clone(),
-                                data: PooltoolData0 {
-                                    node_id: "".to_string(),
-                                    version: self.node_version.clone(),
-                                    at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
-                                    block_no: header.block_number,
-                                    slot_no: header.slot_number,
-                                    block_hash: hex::encode(&header.hash),
-                                    parent_hash: hex::encode(&header.prev_hash),
-                                    leader_vrf: hex::encode(&header.leader_vrf_0),
-                                    leader_vrf_proof: hex::encode(&header.leader_vrf_1),
-                                    protocol_major_version: header.protocol_major_version,
-                                    protocol_minor_version: header.protocol_minor_version,
-                                    node_v_key: hex::encode(&header.node_vkey),
-                                    platform: "cncli".to_string(),
-                                },
-                            })
-                            .unwrap(),
-                        )
-                        .send()
-                } else {
-                    client
-                        .post("https://api.pooltool.io/v1/sendstats")
-                        .body(
-                            serde_json::ser::to_string(&PooltoolStats1 {
-                                api_key: self.api_key.clone(),
-                                pool_id: self.pool_id.clone(),
-                                data: PooltoolData1 {
-                                    node_id: "".to_string(),
-                                    version: self.node_version.clone(),
-                                    at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
-                                    block_no: header.block_number,
-                                    slot_no: header.slot_number,
-                                    block_hash: hex::encode(&header.hash),
-                                    parent_hash: hex::encode(&header.prev_hash),
-                                    leader_vrf: hex::encode(&header.leader_vrf_0),
-                                    block_vrf: hex::encode(&header.block_vrf_0),
-                                    block_vrf_proof: hex::encode(&header.block_vrf_1),
-                                    node_v_key: hex::encode(&header.node_vkey),
-                                    protocol_major_version: header.protocol_major_version,
-                                    protocol_minor_version: header.protocol_minor_version,
-                                    platform: "cncli".to_string(),
-                                },
-                            })
-                            .unwrap(),
-                        )
-                        .send()
-                };
-
-                match pooltool_result {
-                    Ok(response) => match response.text() {
-                        Ok(text) => {
-                            info!(
-                                "Pooltool ({}, {}): ({}, {}), json: {}",
-                                &self.pool_name,
-                                &self.pool_id[..8],
-                                &header.block_number,
-                                hex::encode(&header.hash[..8]),
-                                text
-                            );
-                        }
-                        Err(error) => {
-                            error!("PoolTool error: {}", error);
-                        }
-                    },
-                    Err(error) => {
-                        error!("PoolTool error: {}", error);
-                    }
-                }
+        let client = match PooltoolClient::new() {
+            Ok(client) => client,
+            Err(error) => {
+                error!("Could not set up the pooltool client!: {}", error);
+                return;
             }
-            Err(err) => {
-                error!("Could not set up the reqwest client!: {}", err)
+        };
+
+        let node_id = relay.name.clone().unwrap_or_default();
+        let pooltool_result = if header.block_vrf_0.is_empty() {
+            client.send_stats_v0(&SendStatsV0Request {
+                api_key: self.api_key.clone(),
+                pool_id: self.pool_id.clone(),
+                data: SendStatsV0Data {
+                    node_id: node_id.clone(),
+                    version: self.node_version.clone(),
+                    at,
+                    block_no: header.block_number,
+                    slot_no: header.slot_number,
+                    block_hash: hash.clone(),
+                    parent_hash: prev_hash.clone(),
+                    leader_vrf: hex::encode(&header.leader_vrf_0),
+                    leader_vrf_proof: hex::encode(&header.leader_vrf_1),
+                    protocol_major_version: header.protocol_major_version,
+                    protocol_minor_version: header.protocol_minor_version,
+                    node_v_key: hex::encode(&header.node_vkey),
+                    platform: "cncli".to_string(),
+                },
+            })
+        } else {
+            client.send_stats_v1(&SendStatsV1Request {
+                api_key: self.api_key.clone(),
+                pool_id: self.pool_id.clone(),
+                data: SendStatsV1Data {
+                    node_id,
+                    version: self.node_version.clone(),
+                    at,
+                    block_no: header.block_number,
+                    slot_no: header.slot_number,
+                    block_hash: hash.clone(),
+                    parent_hash: prev_hash.clone(),
+                    leader_vrf: hex::encode(&header.leader_vrf_0),
+                    block_vrf: hex::encode(&header.block_vrf_0),
+                    block_vrf_proof: hex::encode(&header.block_vrf_1),
+                    node_v_key: hex::encode(&header.node_vkey),
+                    protocol_major_version: header.protocol_major_version,
+                    protocol_minor_version: header.protocol_minor_version,
+                    platform: "cncli".to_string(),
+                },
+            })
+        };
+
+        match pooltool_result {
+            Ok(response) => {
+                info!(
+                    "Pooltool ({}, {}): ({}, {}) via {}, message: {}",
+                    &self.pool_name,
+                    &self.pool_id.as_str()[..8],
+                    &header.block_number,
+                    hex::encode(&header.hash[..8]),
+                    relay.label(),
+                    response.message.unwrap_or_default()
+                );
+                runstate::record(
+                    "sendtip",
+                    Some(self.pool_id.as_str()),
+                    None,
+                    format!("block {} via {}", header.block_number, relay.label()),
+                );
+            }
+            Err(error) => {
+                error!(
+                    "PoolTool error ({}, {}): ({}, {}): {}",
+                    &self.pool_name,
+                    &self.pool_id.as_str()[..8],
+                    &header.block_number,
+                    hex::encode(&header.hash[..8]),
+                    error
+                );
             }
         }
     }
+
+    /// Log a block sent earlier being orphaned or adopted and write it as a json line to --out
+    fn report_tip_status(&self, update: &TipStatusUpdate) {
+        let message = format!(
+            "Pooltool ({}, {}): ({}, {}) {}",
+            &self.pool_name,
+            &self.pool_id.as_str()[..8],
+            update.block_number,
+            &update.block_hash.as_str()[..16],
+            update.status
+        );
+        match update.replaced_by {
+            Some(ref replaced_by) => warn!("{}, replaced by {}", message, &replaced_by.as_str()[..16]),
+            None if update.status == "orphaned" => warn!("{}", message),
+            None => info!("{}", message),
+        }
+        if let Err(error) = reporter::report_compact(&TipStatusEvent {
+            pool_name: &self.pool_name,
+            pool_id: self.pool_id.as_str(),
+            at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            update,
+        }) {
+            error!("Could not report tip status: {}", error);
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TipStatusEvent<'a> {
+    pool_name: &'a str,
+    pool_id: &'a str,
+    at: String,
+    #[serde(flatten)]
+    update: &'a TipStatusUpdate,
 }
 
 impl BlockStore for PoolToolNotifier {
@@ -230,7 +487,19 @@ impl BlockStore for PoolToolNotifier {
         pending_blocks: &mut Vec<BlockHeader>,
         _shelley_genesis_hash: &str,
     ) -> Result<(), blockstore::Error> {
-        self.send_to_pooltool(pending_blocks.last().unwrap());
+        let header = pending_blocks.last().unwrap();
+        let block_hash =
+            |hash: &[u8]| BlockHash::from_bytes(hash).map_err(|error| Error::Blockstore(error.to_string()));
+        let (hash, prev_hash) = (block_hash(&header.hash)?, block_hash(&header.prev_hash)?);
+        let at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let first_seen = self.seen_tips.lock().unwrap().seen(&hash, &self.relay, at, self.quorum);
+        if let Some((at, relay)) = first_seen {
+            self.send_to_pooltool(header, &hash, &prev_hash, at, &relay);
+            let updates = self.seen_tips.lock().unwrap().sent(header, &hash, &prev_hash);
+            for update in updates.iter() {
+                self.report_tip_status(update);
+            }
+        }
         Ok(())
     }
 
@@ -242,10 +511,55 @@ impl BlockStore for PoolToolNotifier {
         Err(Error::Blockstore("Not implemented".to_string()))
     }
 
+    fn find_block_details_by_hash(&mut self, _hash_start: &str) -> Result<Vec<BlockDetail>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_block_details_at_slot(&mut self, _slot_number: u64) -> Result<Vec<BlockDetail>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_blocks_in_slot_range(&mut self, _first_slot: u64, _last_slot: u64) -> Result<Vec<Block>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_competing_blocks(&mut self, _slot_number: u64, _block_number: u64) -> Result<Vec<Block>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_pool_block_details_in_slot_range(
+        &mut self,
+        _pool_id: &PoolId,
+        _first_slot: u64,
+        _last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_block_details_in_slot_range(
+        &mut self,
+        _first_slot: u64,
+        _last_slot: u64,
+    ) -> Result<Vec<BlockDetail>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn checkpoint(&mut self) -> Result<(), Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn backup_to(&mut self, _backup_path: &Path) -> Result<(), Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
     fn get_tip_slot_number(&mut self) -> Result<u64, Error> {
         Err(Error::Blockstore("Not implemented".to_string()))
     }
 
+    fn get_tip_protocol_version(&mut self) -> Result<Option<(u64, u64)>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
     fn get_eta_v_before_slot(&mut self, _slot_number: u64) -> Result<Hash<32>, Error> {
         Err(Error::Blockstore("Not implemented".to_string()))
     }
@@ -257,19 +571,195 @@ impl BlockStore for PoolToolNotifier {
     fn save_slots(
         &mut self,
         _epoch: u64,
-        _pool_id: &str,
+        _pool_id: &PoolId,
         _slot_qty: u64,
         _slots: &str,
         _hash: &str,
+        _inputs: Option<&SlotsInputs>,
     ) -> Result<(), Error> {
         Err(Error::Blockstore("Not implemented".to_string()))
     }
 
-    fn get_current_slots(&mut self, _epoch: u64, _pool_id: &str) -> Result<(u64, String), Error> {
+    fn get_current_slots(&mut self, _epoch: u64, _pool_id: &PoolId) -> Result<(u64, String), Error> {
         Err(Error::Blockstore("Not implemented".to_string()))
     }
 
-    fn get_previous_slots(&mut self, _epoch: u64, _pool_id: &str) -> Result<Option<String>, Error> {
+    fn get_previous_slots(&mut self, _epoch: u64, _pool_id: &PoolId) -> Result<Option<String>, Error> {
         Err(Error::Blockstore("Not implemented".to_string()))
     }
+
+    fn get_slots_to_send(&mut self, _epoch: u64, _pool_id: &PoolId) -> Result<((u64, String), Option<String>), Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_slots_inputs(&mut self, _epoch: u64, _pool_id: &PoolId) -> Result<Option<SlotsInputs>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_all_slots(&mut self) -> Result<Vec<SavedSlots>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_opcert_history(&mut self, _pool_id: &PoolId) -> Result<Vec<OpcertHistory>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn save_stake_history(&mut self, _stake_history: &StakeHistory) -> Result<(), Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_stake_history(&mut self, _pool_id: &PoolId) -> Result<Vec<StakeHistory>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn save_protocol_update(&mut self, _protocol_update: &ProtocolUpdate) -> Result<(), Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+
+    fn get_protocol_updates_before_epoch(&mut self, _epoch: u64) -> Result<Vec<ProtocolUpdate>, Error> {
+        Err(Error::Blockstore("Not implemented".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_config_value() {
+        std::env::set_var("CNCLI_TEST_POOLTOOL_API_KEY", "secret-key");
+        assert_eq!(resolve_config_value("plain-value").unwrap(), "plain-value");
+        assert_eq!(
+            resolve_config_value("${CNCLI_TEST_POOLTOOL_API_KEY}").unwrap(),
+            "secret-key"
+        );
+        assert_eq!(
+            resolve_config_value("prefix-${CNCLI_TEST_POOLTOOL_API_KEY}-suffix").unwrap(),
+            "prefix-secret-key-suffix"
+        );
+        assert!(matches!(
+            resolve_config_value("${CNCLI_TEST_POOLTOOL_UNSET_VAR}"),
+            Err(ConfigError::MissingEnvVar(_))
+        ));
+
+        let secret_path = std::env::temp_dir().join("cncli_test_pooltool_secret");
+        std::fs::write(&secret_path, "file-secret\n").unwrap();
+        assert_eq!(
+            resolve_config_value(&format!("file:{}", secret_path.to_string_lossy())).unwrap(),
+            "file-secret"
+        );
+        std::fs::remove_file(&secret_path).unwrap();
+    }
+
+    const POOL_ID: &str = "00beef0a9be2f6d897ed24a613cf547bb20cd282a04edfc53d477114";
+
+    fn pool(config: &str) -> Result<Pool, ConfigError> {
+        let mut pool: Pool = serde_json::from_str(config).unwrap();
+        resolve_relays(&mut pool)?;
+        Ok(pool)
+    }
+
+    #[test]
+    fn test_pool_id_is_resolved_and_checked() {
+        std::env::set_var("CNCLI_TEST_POOLTOOL_POOL_ID", POOL_ID.to_uppercase());
+        let pool: Pool = serde_json::from_str(
+            r#"{"name": "TCKR", "pool_id": "${CNCLI_TEST_POOLTOOL_POOL_ID}", "host": "10.0.0.1", "port": 3001}"#,
+        )
+        .unwrap();
+        assert_eq!(pool.pool_id.as_str(), POOL_ID);
+        assert!(serde_json::from_str::<Pool>(r#"{"name": "TCKR", "pool_id": "", "host": "10.0.0.1"}"#).is_err());
+    }
+
+    #[test]
+    fn test_resolve_relays() {
+        let pool = pool(&format!(
+            r#"{{"name": "TCKR", "pool_id": "{POOL_ID}", "host": "10.0.0.1", "port": 3001,
+            "relays": [{{"name": "relay2", "host": "10.0.0.2", "port": 3001}}], "quorum": 2}}"#
+        ))
+        .unwrap();
+        assert_eq!(pool.relays.len(), 2);
+        assert_eq!(pool.relays[0].label(), "10.0.0.1:3001");
+        assert_eq!(pool.relays[1].label(), "relay2");
+        assert!(pool(&format!(r#"{{"name": "TCKR", "pool_id": "{POOL_ID}"}}"#)).is_err());
+        assert!(pool(&format!(
+            r#"{{"name": "TCKR", "pool_id": "{POOL_ID}", "host": "10.0.0.1"}}"#
+        ))
+        .is_err());
+        assert!(pool(&format!(
+            r#"{{"name": "TCKR", "pool_id": "{POOL_ID}", "host": "10.0.0.1", "port": 3001, "quorum": 2}}"#
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_seen_tips() {
+        let relay = |name: &str| Relay {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        let hash = |byte: u8| BlockHash::from_bytes(&[byte; 32]).unwrap();
+        let mut seen_tips = SeenTips::default();
+        // the slow relay's time is not used and the block is sent once
+        assert_eq!(seen_tips.seen(&hash(1), &relay("fast"), "t1".to_string(), 2), None);
+        assert_eq!(seen_tips.seen(&hash(1), &relay("fast"), "t2".to_string(), 2), None);
+        assert_eq!(
+            seen_tips.seen(&hash(1), &relay("slow"), "t3".to_string(), 2),
+            Some(("t1".to_string(), relay("fast")))
+        );
+        assert_eq!(seen_tips.seen(&hash(1), &relay("third"), "t4".to_string(), 2), None);
+
+        assert_eq!(
+            seen_tips.seen(&hash(2), &relay("slow"), "t5".to_string(), 1),
+            Some(("t5".to_string(), relay("slow")))
+        );
+        assert_eq!(seen_tips.seen(&hash(2), &relay("fast"), "t6".to_string(), 1), None);
+    }
+
+    #[test]
+    fn test_sent_tips_orphaned_and_adopted() {
+        let hash = |byte: u8| BlockHash::from_bytes(&[byte; 32]).unwrap();
+        let sent = |seen_tips: &mut SeenTips, block_number: u64, byte: u8, prev_byte: u8| {
+            let header = BlockHeader {
+                block_number,
+                slot_number: block_number * 20,
+                ..Default::default()
+            };
+            seen_tips.sent(&header, &hash(byte), &hash(prev_byte))
+        };
+        let mut seen_tips = SeenTips::default();
+        assert!(sent(&mut seen_tips, 100, 1, 0).is_empty());
+        assert!(sent(&mut seen_tips, 101, 2, 1).is_empty());
+
+        // another block at 101 wins the slot battle
+        let updates = sent(&mut seen_tips, 101, 3, 1);
+        assert_eq!(
+            updates,
+            vec![TipStatusUpdate {
+                status: "orphaned".to_string(),
+                block_number: 101,
+                slot_number: 2020,
+                block_hash: hash(2),
+                replaced_by: Some(hash(3)),
+            }]
+        );
+        // reported once
+        assert!(sent(&mut seen_tips, 102, 4, 3).is_empty());
+
+        // the chain switches back to the first block at 101
+        let updates = sent(&mut seen_tips, 102, 5, 2);
+        let statuses: Vec<(&str, u64, u8)> = updates
+            .iter()
+            .map(|update| {
+                (
+                    update.status.as_str(),
+                    update.block_number,
+                    update.block_hash.to_bytes()[0],
+                )
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![("adopted", 101, 2), ("orphaned", 101, 3), ("orphaned", 102, 4)]
+        );
+    }
 }
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use crate::nodeclient::sync::BlockHeader;
+
+// the security parameter k, no rollback can go deeper than this many blocks
+const MAX_RECENT_HASHES: usize = 2160;
+
+/// The hashes of the most recent non-orphaned blocks in the blockstore keyed by slot. After a reconnect the node
+/// replays headers from the intersection point and the ones we already stored can be skipped instead of being
+/// written again and marking the stored copies orphaned.
+#[derive(Debug, Default)]
+pub(crate) struct RecentHashes {
+    hashes: BTreeMap<u64, Vec<u8>>,
+}
+
+impl RecentHashes {
+    pub(crate) fn from_blocks(blocks: Vec<(u64, Vec<u8>)>) -> Self {
+        let mut recent_hashes = RecentHashes::default();
+        recent_hashes.hashes.extend(blocks);
+        recent_hashes.trim();
+        recent_hashes
+    }
+
+    pub(crate) fn contains(&self, slot: u64, hash: &[u8]) -> bool {
+        self.hashes
+            .get(&slot)
+            .is_some_and(|known_hash| known_hash.as_slice() == hash)
+    }
+
+    /// Record blocks that were just saved. Anything at or after the first saved slot was orphaned by the save.
+    pub(crate) fn saved(&mut self, blocks: &[BlockHeader]) {
+        if let Some(first) = blocks.first() {
+            self.hashes.retain(|slot, _| *slot < first.slot_number);
+        }
+        for block in blocks {
+            self.hashes.insert(block.slot_number, block.hash.clone());
+        }
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.hashes.len() > MAX_RECENT_HASHES {
+            self.hashes.pop_first();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(slot_number: u64, hash: u8) -> BlockHeader {
+        BlockHeader {
+            block_number: slot_number,
+            slot_number,
+            hash: vec![hash; 32],
+            prev_hash: vec![],
+            node_vkey: vec![],
+            node_vrf_vkey: vec![],
+            block_vrf_0: vec![],
+            block_vrf_1: vec![],
+            eta_vrf_0: vec![],
+            eta_vrf_1: vec![],
+            leader_vrf_0: vec![],
+            leader_vrf_1: vec![],
+            block_size: 0,
+            block_body_hash: vec![],
+            pool_opcert: vec![],
+            unknown_0: 0,
+            unknown_1: 0,
+            unknown_2: vec![],
+            protocol_major_version: 0,
+            protocol_minor_version: 0,
+            header_cbor: vec![],
+            received_at: None,
+            source_peer: None,
+        }
+    }
+
+    #[test]
+    fn test_contains_matches_slot_and_hash() {
+        let recent_hashes = RecentHashes::from_blocks(vec![(10, vec![1; 32]), (20, vec![2; 32])]);
+        assert!(recent_hashes.contains(10, &[1; 32]));
+        assert!(!recent_hashes.contains(10, &[2; 32]));
+        assert!(!recent_hashes.contains(30, &[2; 32]));
+    }
+
+    #[test]
+    fn test_saved_drops_orphaned_hashes() {
+        let mut recent_hashes =
+            RecentHashes::from_blocks(vec![(10, vec![1; 32]), (20, vec![2; 32]), (30, vec![3; 32])]);
+        recent_hashes.saved(&[header(25, 4)]);
+        assert!(recent_hashes.contains(10, &[1; 32]));
+        assert!(recent_hashes.contains(20, &[2; 32]));
+        assert!(recent_hashes.contains(25, &[4; 32]));
+        assert!(!recent_hashes.contains(30, &[3; 32]));
+    }
+
+    #[test]
+    fn test_trim_keeps_newest() {
+        let mut recent_hashes = RecentHashes::default();
+        let blocks: Vec<BlockHeader> = (0..MAX_RECENT_HASHES as u64 + 10).map(|slot| header(slot, 1)).collect();
+        recent_hashes.saved(&blocks);
+        assert_eq!(recent_hashes.hashes.len(), MAX_RECENT_HASHES);
+        assert!(!recent_hashes.contains(9, &[1; 32]));
+        assert!(recent_hashes.contains(10, &[1; 32]));
+    }
+}
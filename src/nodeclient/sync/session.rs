@@ -0,0 +1,290 @@
+use std::convert::Infallible;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use minicbor::data::Type;
+use minicbor::{Decoder, Encoder};
+use pallas_network::miniprotocols::chainsync::{HeaderContent, NextResponse, Tip};
+use pallas_network::miniprotocols::Point;
+use tracing::warn;
+
+use crate::nodeclient::sync::Error;
+
+const SESSION_MAGIC: &str = "cncli-session";
+const SESSION_FORMAT_VERSION: u8 = 1;
+
+const ROLL_FORWARD: u8 = 0;
+const ROLL_BACKWARD: u8 = 1;
+const BLOCK_BODY: u8 = 2;
+
+type EncodeError = minicbor::encode::Error<Infallible>;
+
+/// What sync received from the node. Every message is recorded with the unix time in milliseconds it arrived at,
+/// replay only needs the one of headers.
+#[derive(Debug)]
+pub(crate) enum SessionMessage {
+    RollForward {
+        received_at: u64,
+        header: HeaderContent,
+        tip: Tip,
+    },
+    RollBackward {
+        point: Point,
+    },
+    /// A block body fetched for the header before it, with --capture-protocol-updates
+    BlockBody {
+        point: Point,
+        body: Vec<u8>,
+    },
+}
+
+/// Appends the chainsync messages and block bodies sync receives to a session file, a cbor sequence that starts
+/// with ["cncli-session", version]. A reconnect carries on in the same file, starting with the rollback to the
+/// intersection point like any connection.
+pub(crate) struct SessionRecorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl SessionRecorder {
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|error| session_error(path, error))?;
+        let is_new = file.metadata().map_err(|error| session_error(path, error))?.len() == 0;
+        let mut recorder = SessionRecorder {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+        };
+        if is_new {
+            recorder.write(&encoded(|encoder| {
+                encoder.array(2)?.str(SESSION_MAGIC)?.u8(SESSION_FORMAT_VERSION)?;
+                Ok(())
+            }))?;
+        }
+        Ok(recorder)
+    }
+
+    /// Record a reply to a request for the next header. Await carries nothing, the buffered messages are written out
+    /// instead so the session is complete up to the tip while sync waits for the next block.
+    pub(crate) fn record_response(&mut self, response: &NextResponse<HeaderContent>) -> Result<(), Error> {
+        let received_at = Utc::now().timestamp_millis() as u64;
+        match response {
+            NextResponse::RollForward(header, tip) => self.write(&encoded(|encoder| {
+                encoder
+                    .array(6)?
+                    .u8(ROLL_FORWARD)?
+                    .u64(received_at)?
+                    .u8(header.variant)?;
+                match header.byron_prefix {
+                    None => encoder.null()?,
+                    Some((prefix, size)) => encoder.array(2)?.u8(prefix)?.u64(size)?,
+                };
+                encoder.bytes(&header.cbor)?;
+                encode_tip(encoder, tip)
+            })),
+            NextResponse::RollBackward(point, tip) => self.write(&encoded(|encoder| {
+                encoder.array(4)?.u8(ROLL_BACKWARD)?.u64(received_at)?;
+                encode_point(encoder, point)?;
+                encode_tip(encoder, tip)
+            })),
+            NextResponse::Await => self.writer.flush().map_err(|error| session_error(&self.path, error)),
+        }
+    }
+
+    pub(crate) fn record_block_body(&mut self, point: &Point, body: &[u8]) -> Result<(), Error> {
+        let received_at = Utc::now().timestamp_millis() as u64;
+        self.write(&encoded(|encoder| {
+            encoder.array(4)?.u8(BLOCK_BODY)?.u64(received_at)?;
+            encode_point(encoder, point)?;
+            encoder.bytes(body)?;
+            Ok(())
+        }))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.writer
+            .write_all(bytes)
+            .map_err(|error| session_error(&self.path, error))
+    }
+}
+
+/// Read every message of a recorded session. A last message cut short, because sync was killed while writing it,
+/// is left out.
+pub(crate) fn read_session(path: &Path) -> Result<Vec<SessionMessage>, Error> {
+    let bytes = std::fs::read(path).map_err(|error| session_error(path, error))?;
+    decode_session(&bytes).map_err(|error| session_error(path, error))
+}
+
+fn decode_session(bytes: &[u8]) -> Result<Vec<SessionMessage>, minicbor::decode::Error> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.array()?;
+    if decoder.str()? != SESSION_MAGIC {
+        return Err(minicbor::decode::Error::message("not a cncli session"));
+    }
+    let version = decoder.u8()?;
+    if version != SESSION_FORMAT_VERSION {
+        return Err(minicbor::decode::Error::message(format!(
+            "session format version {version} is not supported, this cncli reads version {SESSION_FORMAT_VERSION}"
+        )));
+    }
+    let mut messages = Vec::new();
+    while decoder.position() < bytes.len() {
+        match decode_message(&mut decoder) {
+            Ok(message) => messages.push(message),
+            Err(error) if error.is_end_of_input() => {
+                warn!("The last message of the session is incomplete and was skipped");
+                break;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(messages)
+}
+
+fn decode_message(decoder: &mut Decoder) -> Result<SessionMessage, minicbor::decode::Error> {
+    decoder.array()?;
+    let kind = decoder.u8()?;
+    let received_at = decoder.u64()?;
+    match kind {
+        ROLL_FORWARD => {
+            let variant = decoder.u8()?;
+            let byron_prefix = match decoder.datatype()? {
+                Type::Null => {
+                    decoder.skip()?;
+                    None
+                }
+                _ => {
+                    decoder.array()?;
+                    Some((decoder.u8()?, decoder.u64()?))
+                }
+            };
+            let cbor = decoder.bytes()?.to_vec();
+            Ok(SessionMessage::RollForward {
+                received_at,
+                header: HeaderContent {
+                    variant,
+                    byron_prefix,
+                    cbor,
+                },
+                tip: decode_tip(decoder)?,
+            })
+        }
+        ROLL_BACKWARD => {
+            let point = decode_point(decoder)?;
+            // the tip, which a rollback does not need
+            decoder.skip()?;
+            Ok(SessionMessage::RollBackward { point })
+        }
+        BLOCK_BODY => Ok(SessionMessage::BlockBody {
+            point: decode_point(decoder)?,
+            body: decoder.bytes()?.to_vec(),
+        }),
+        _ => Err(minicbor::decode::Error::message(format!(
+            "unknown session message {kind}"
+        ))),
+    }
+}
+
+/// Points are [] for the origin and [slot, hash] otherwise, as on the wire
+fn encode_point(encoder: &mut Encoder<Vec<u8>>, point: &Point) -> Result<(), EncodeError> {
+    match point {
+        Point::Origin => encoder.array(0)?,
+        Point::Specific(slot, hash) => encoder.array(2)?.u64(*slot)?.bytes(hash)?,
+    };
+    Ok(())
+}
+
+fn decode_point(decoder: &mut Decoder) -> Result<Point, minicbor::decode::Error> {
+    match decoder.array()? {
+        Some(0) => Ok(Point::Origin),
+        _ => Ok(Point::Specific(decoder.u64()?, decoder.bytes()?.to_vec())),
+    }
+}
+
+fn encode_tip(encoder: &mut Encoder<Vec<u8>>, tip: &Tip) -> Result<(), EncodeError> {
+    encoder.array(2)?;
+    encode_point(encoder, &tip.0)?;
+    encoder.u64(tip.1)?;
+    Ok(())
+}
+
+fn decode_tip(decoder: &mut Decoder) -> Result<Tip, minicbor::decode::Error> {
+    decoder.array()?;
+    let point = decode_point(decoder)?;
+    Ok(Tip(point, decoder.u64()?))
+}
+
+fn encoded(encode: impl FnOnce(&mut Encoder<Vec<u8>>) -> Result<(), EncodeError>) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new());
+    encode(&mut encoder).expect("encoding to a Vec cannot fail");
+    encoder.into_writer()
+}
+
+fn session_error(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Session(format!("{}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_read_session() {
+        let path = std::env::temp_dir().join(format!("cncli-session-{}.cbor", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let header = HeaderContent {
+            variant: 6,
+            byron_prefix: None,
+            cbor: vec![0x82, 0x01, 0x02],
+        };
+        let tip = || Tip(Point::Specific(1200, vec![0xab; 32]), 42);
+
+        let mut recorder = SessionRecorder::open(&path).unwrap();
+        recorder
+            .record_response(&NextResponse::RollBackward(Point::Origin, tip()))
+            .unwrap();
+        recorder
+            .record_response(&NextResponse::RollForward(header, tip()))
+            .unwrap();
+        recorder.record_response(&NextResponse::Await).unwrap();
+        drop(recorder);
+        // a reconnect appends without a second session header
+        let mut recorder = SessionRecorder::open(&path).unwrap();
+        recorder
+            .record_block_body(&Point::Specific(1200, vec![0xab; 32]), &[0x80])
+            .unwrap();
+        drop(recorder);
+
+        let messages = read_session(&path).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(
+            &messages[0],
+            SessionMessage::RollBackward { point: Point::Origin }
+        ));
+        match &messages[1] {
+            SessionMessage::RollForward { header, tip, .. } => {
+                assert_eq!((header.variant, header.byron_prefix), (6, None));
+                assert_eq!(header.cbor, vec![0x82, 0x01, 0x02]);
+                assert!(matches!(tip, Tip(Point::Specific(1200, hash), 42) if hash == &vec![0xab; 32]));
+            }
+            message => panic!("unexpected {message:?}"),
+        }
+        assert!(
+            matches!(&messages[2], SessionMessage::BlockBody { point: Point::Specific(1200, _), body } if body == &vec![0x80])
+        );
+
+        // sync killed while writing the last message
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(read_session(&path).unwrap().len(), 2);
+
+        std::fs::write(&path, [0x82, 0x61, 0x78, 0x01]).unwrap();
+        assert!(read_session(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
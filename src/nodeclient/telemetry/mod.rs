@@ -0,0 +1,300 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::nodeclient::http;
+use crate::nodeclient::http::HttpSettings;
+use crate::nodeclient::reporter;
+
+const CONSENT_FILE_VAR: &str = "CNCLI_TELEMETRY_FILE";
+const ENDPOINT_VAR: &str = "CNCLI_TELEMETRY_URL";
+// the community endpoint, runs only reach it after an explicit opt-in
+const DEFAULT_ENDPOINT: &str = "https://telemetry.cncli.io/v1/runs";
+// telemetry must never hold up a command noticeably, a run that can't be sent in time is dropped
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("HTTP client error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] http::Error),
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] crate::nodeclient::reporter::Error),
+
+    #[error("No telemetry consent file, set {0} or HOME")]
+    NoConsentFile(&'static str),
+}
+
+/// Whether the user opted in, as remembered between runs
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Consent {
+    enabled: bool,
+    changed_at: String,
+}
+
+/// What is sent about one run. There is no pool id, host, path, address or install id in it, so runs can't be told
+/// apart by who made them.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunMetrics {
+    command: String,
+    success: bool,
+    duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network: Option<String>,
+    version: String,
+    os: String,
+    arch: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TelemetryStatus {
+    status: String,
+    telemetry: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed_at: Option<String>,
+    consent_file: String,
+    endpoint: String,
+}
+
+struct Run {
+    command: &'static str,
+    network_magic: Option<u64>,
+    started: Instant,
+}
+
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+
+/// Start timing a command if the user opted in, now with --telemetry or on an earlier run
+pub(crate) fn start(command: &'static str, network_magic: Option<u64>, opt_in: bool) {
+    let Some(consent_file) = consent_file() else {
+        if opt_in {
+            warn!("{}", Error::NoConsentFile(CONSENT_FILE_VAR));
+        }
+        return;
+    };
+    if opt_in {
+        match write_consent(&consent_file, true) {
+            Ok(()) => info!("Telemetry is on until `cncli telemetry off`"),
+            Err(error) => warn!(
+                "Could not record the telemetry opt-in in {}: {}",
+                consent_file.display(),
+                error
+            ),
+        }
+    }
+    match read_consent(&consent_file) {
+        Ok(consent) if consent.enabled => {
+            *RUN.lock().unwrap() = Some(Run {
+                command,
+                network_magic,
+                started: Instant::now(),
+            });
+        }
+        Ok(_) => {}
+        Err(error) => debug!("Could not read {}: {}", consent_file.display(), error),
+    }
+}
+
+/// Send the metrics of the command started with start, if it was timed. Failures are only logged at debug level.
+pub(crate) fn finish(success: bool) {
+    let Some(run) = RUN.lock().unwrap().take() else {
+        return;
+    };
+    let endpoint = endpoint();
+    let metrics = run_metrics(&run, success);
+    // a blocking client can't be used on a tokio worker thread
+    let sent = std::thread::spawn(move || send(&endpoint, &metrics)).join();
+    match sent {
+        Ok(Ok(())) => debug!("Sent telemetry of {}", run.command),
+        Ok(Err(error)) => debug!("Could not send telemetry: {}", error),
+        Err(_) => debug!("Could not send telemetry"),
+    }
+}
+
+/// Report whether telemetry is on, where the choice is kept and where runs are sent to
+pub(crate) fn status() -> Result<(), Error> {
+    let consent_file = consent_file().ok_or(Error::NoConsentFile(CONSENT_FILE_VAR))?;
+    report_status(&consent_file, read_consent(&consent_file)?)
+}
+
+/// Turn telemetry off until the next --telemetry
+pub(crate) fn off() -> Result<(), Error> {
+    let consent_file = consent_file().ok_or(Error::NoConsentFile(CONSENT_FILE_VAR))?;
+    write_consent(&consent_file, false)?;
+    report_status(&consent_file, read_consent(&consent_file)?)
+}
+
+fn report_status(consent_file: &Path, consent: Consent) -> Result<(), Error> {
+    reporter::report(&TelemetryStatus {
+        status: "ok".to_string(),
+        telemetry: if consent.enabled { "on" } else { "off" }.to_string(),
+        changed_at: Some(consent.changed_at).filter(|changed_at| !changed_at.is_empty()),
+        consent_file: consent_file.display().to_string(),
+        endpoint: endpoint(),
+    })?;
+    Ok(())
+}
+
+fn run_metrics(run: &Run, success: bool) -> RunMetrics {
+    RunMetrics {
+        command: run.command.to_string(),
+        success,
+        duration_ms: run.started.elapsed().as_millis() as u64,
+        network: run
+            .network_magic
+            .map(|network_magic| network_name(network_magic).to_string()),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+    }
+}
+
+/// The public networks by name, any other magic as other so a private testnet can't be recognized
+fn network_name(network_magic: u64) -> &'static str {
+    match network_magic {
+        764824073 => "mainnet",
+        1 => "preprod",
+        2 => "preview",
+        4 => "sanchonet",
+        _ => "other",
+    }
+}
+
+fn send(endpoint: &str, metrics: &RunMetrics) -> Result<(), Error> {
+    http::client(&HttpSettings::from_env()?, None)?
+        .post(endpoint)
+        .timeout(SEND_TIMEOUT)
+        .json(metrics)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn endpoint() -> String {
+    endpoint_from(std::env::var(ENDPOINT_VAR).ok())
+}
+
+/// CNCLI_TELEMETRY_URL, or the community endpoint
+fn endpoint_from(endpoint: Option<String>) -> String {
+    endpoint
+        .filter(|endpoint| !endpoint.is_empty())
+        .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string())
+}
+
+fn consent_file() -> Option<PathBuf> {
+    consent_file_from(|name| std::env::var(name).ok())
+}
+
+/// CNCLI_TELEMETRY_FILE, or cncli/telemetry.json in the XDG config directory
+fn consent_file_from(var: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+    if let Some(consent_file) = var(CONSENT_FILE_VAR) {
+        return Some(PathBuf::from(consent_file));
+    }
+    var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config_dir| config_dir.join("cncli").join("telemetry.json"))
+}
+
+fn read_consent(consent_file: &Path) -> Result<Consent, Error> {
+    if !consent_file.exists() {
+        return Ok(Consent::default());
+    }
+    Ok(serde_json::from_slice(&std::fs::read(consent_file)?)?)
+}
+
+fn write_consent(consent_file: &Path, enabled: bool) -> Result<(), Error> {
+    if let Some(parent) = consent_file.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let consent = Consent {
+        enabled,
+        changed_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+    };
+    std::fs::write(consent_file, serde_json::to_vec_pretty(&consent)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consent_file_from() {
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(
+            consent_file_from(vars(&[(CONSENT_FILE_VAR, "/tmp/t.json"), ("HOME", "/home/spo")])),
+            Some(PathBuf::from("/tmp/t.json"))
+        );
+        assert_eq!(
+            consent_file_from(vars(&[("XDG_CONFIG_HOME", "/etc/xdg"), ("HOME", "/home/spo")])),
+            Some(PathBuf::from("/etc/xdg/cncli/telemetry.json"))
+        );
+        assert_eq!(
+            consent_file_from(vars(&[(CONSENT_FILE_VAR, ""), ("HOME", "/home/spo")])),
+            Some(PathBuf::from("/home/spo/.config/cncli/telemetry.json"))
+        );
+        assert_eq!(consent_file_from(vars(&[])), None);
+    }
+
+    #[test]
+    fn test_endpoint_from() {
+        assert_eq!(endpoint_from(None), DEFAULT_ENDPOINT);
+        assert_eq!(endpoint_from(Some(String::new())), DEFAULT_ENDPOINT);
+        assert_eq!(
+            endpoint_from(Some("https://telemetry.example.org/v1/runs".to_string())),
+            "https://telemetry.example.org/v1/runs"
+        );
+    }
+
+    #[test]
+    fn test_consent_and_run_metrics() {
+        let consent_file = std::env::temp_dir()
+            .join(format!("cncli-telemetry-{}", std::process::id()))
+            .join("telemetry.json");
+        assert!(!read_consent(&consent_file).unwrap().enabled);
+        write_consent(&consent_file, true).unwrap();
+        assert!(read_consent(&consent_file).unwrap().enabled);
+        write_consent(&consent_file, false).unwrap();
+        assert!(!read_consent(&consent_file).unwrap().enabled);
+        std::fs::remove_dir_all(consent_file.parent().unwrap()).unwrap();
+
+        let run = Run {
+            command: "leaderlog",
+            network_magic: Some(42),
+            started: Instant::now(),
+        };
+        let metrics = serde_json::to_value(run_metrics(&run, true)).unwrap();
+        assert_eq!(metrics["command"], "leaderlog");
+        assert_eq!(metrics["network"], "other");
+        assert_eq!(
+            metrics.as_object().unwrap().keys().cloned().collect::<Vec<_>>(),
+            ["arch", "command", "durationMs", "network", "os", "success", "version"]
+        );
+        assert_eq!(network_name(764824073), "mainnet");
+    }
+}
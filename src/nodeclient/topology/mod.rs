@@ -0,0 +1,355 @@
+use std::path::Path;
+use std::time::Duration;
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::nodeclient::ping;
+use crate::nodeclient::reporter;
+use crate::nodeclient::reporter::human;
+use crate::nodeclient::reporter::human::{Cell, Color, Table};
+use crate::ReportFormat;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Could not read topology file {0}: {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Could not parse topology file {0}: {1}")]
+    Parse(String, serde_json::Error),
+
+    #[error("Invalid relay '{0}', expected host:port")]
+    InvalidRelay(String),
+
+    #[error("No relays given, use --relay or --topology")]
+    NoRelays,
+
+    #[error("Reporter error: {0}")]
+    Reporter(#[from] reporter::Error),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RelayAddress {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+impl RelayAddress {
+    /// Parse host:port, with an ipv6 address in brackets as in [::1]:3001
+    pub(crate) fn parse(relay: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidRelay(relay.to_string());
+        let (host, port) = relay.trim().rsplit_once(':').ok_or_else(invalid)?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(RelayAddress {
+            host: host.to_string(),
+            port: port.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// The parts of a cardano-node topology file listing relays, both the legacy and the p2p format
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TopologyFile {
+    #[serde(default, rename = "Producers")]
+    producers: Vec<LegacyProducer>,
+    #[serde(default)]
+    local_roots: Vec<RootGroup>,
+    #[serde(default)]
+    public_roots: Vec<RootGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LegacyProducer {
+    addr: String,
+    port: u16,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RootGroup {
+    #[serde(default)]
+    access_points: Vec<AccessPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessPoint {
+    address: String,
+    port: u16,
+}
+
+/// The relays in a topology file, each listed once
+pub(crate) fn read_topology(topology_file: &Path) -> Result<Vec<RelayAddress>, Error> {
+    let path = topology_file.display().to_string();
+    let contents = std::fs::read_to_string(topology_file).map_err(|error| Error::Read(path.clone(), error))?;
+    let topology: TopologyFile = serde_json::from_str(&contents).map_err(|error| Error::Parse(path, error))?;
+    let producers = topology.producers.into_iter().map(|producer| RelayAddress {
+        host: producer.addr,
+        port: producer.port,
+    });
+    let access_points = topology
+        .local_roots
+        .into_iter()
+        .chain(topology.public_roots)
+        .flat_map(|root_group| root_group.access_points)
+        .map(|access_point| RelayAddress {
+            host: access_point.address,
+            port: access_point.port,
+        });
+    let mut relays: Vec<RelayAddress> = Vec::new();
+    for relay in producers.chain(access_points) {
+        if !relays.contains(&relay) {
+            relays.push(relay);
+        }
+    }
+    Ok(relays)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PingMesh {
+    status: String,
+    count: usize,
+    relays: Vec<RelayLatencies>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct RelayLatencies {
+    host: String,
+    port: u16,
+    /// Percentage of the rounds the handshake succeeded in
+    availability: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avg_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_ms: Option<f64>,
+    /// Connect and handshake time of each round, null when it failed
+    latencies_ms: Vec<Option<f64>>,
+    /// The distinct reasons rounds failed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+fn round_ms(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 100000.0).round() / 100.0
+}
+
+impl RelayLatencies {
+    fn new(relay: &RelayAddress, rounds: Vec<Result<Duration, String>>) -> Self {
+        let mut errors: Vec<String> = Vec::new();
+        let latencies_ms: Vec<Option<f64>> = rounds
+            .into_iter()
+            .map(|round| match round {
+                Ok(latency) => Some(round_ms(latency)),
+                Err(error) => {
+                    if !errors.contains(&error) {
+                        errors.push(error);
+                    }
+                    None
+                }
+            })
+            .collect();
+        let successes: Vec<f64> = latencies_ms.iter().flatten().copied().collect();
+        let availability = if latencies_ms.is_empty() {
+            0.0
+        } else {
+            (successes.len() as f64 / latencies_ms.len() as f64 * 10000.0).round() / 100.0
+        };
+        let avg_ms = if successes.is_empty() {
+            None
+        } else {
+            Some((successes.iter().sum::<f64>() / successes.len() as f64 * 100.0).round() / 100.0)
+        };
+        RelayLatencies {
+            host: relay.host.clone(),
+            port: relay.port,
+            availability,
+            min_ms: successes.iter().copied().reduce(f64::min),
+            avg_ms,
+            max_ms: successes.iter().copied().reduce(f64::max),
+            latencies_ms,
+            errors,
+        }
+    }
+}
+
+/// Handshake with every relay once per round, all relays of a round at the same time, and report the latencies and
+/// how often each relay answered. DNS lookups are left out of the latency since they depend on the local resolver.
+pub(crate) async fn ping_mesh(
+    relays: &[RelayAddress],
+    count: usize,
+    interval: Duration,
+    network_magic: u64,
+    timeout_seconds: u64,
+    format: &ReportFormat,
+) -> Result<(), Error> {
+    if relays.is_empty() {
+        return Err(Error::NoRelays);
+    }
+    let mut rounds: Vec<Vec<Result<Duration, String>>> = vec![Vec::new(); relays.len()];
+    for round in 0..count {
+        if round > 0 {
+            tokio::time::sleep(interval).await;
+        }
+        let results = join_all(relays.iter().map(|relay| async move {
+            ping::handshake_with_node(&relay.host, relay.port, network_magic, timeout_seconds, false, 0, &None)
+                .await
+                .map(|ping_result| ping_result.total_duration - ping_result.dns_duration)
+                .map_err(|ping_failure| ping_failure.to_string())
+        }))
+        .await;
+        for (relay_rounds, result) in rounds.iter_mut().zip(results) {
+            relay_rounds.push(result);
+        }
+    }
+
+    let ping_mesh = PingMesh {
+        status: "ok".to_string(),
+        count,
+        relays: relays
+            .iter()
+            .zip(rounds)
+            .map(|(relay, relay_rounds)| RelayLatencies::new(relay, relay_rounds))
+            .collect(),
+    };
+    match format {
+        ReportFormat::Json => reporter::report(&ping_mesh)?,
+        ReportFormat::Human => {
+            reporter::write_report(render_ping_mesh_human(&ping_mesh, human::color_enabled()).as_bytes())?
+        }
+    }
+    Ok(())
+}
+
+fn availability_cell(availability: f64) -> Cell {
+    let text = format!("{availability}%");
+    if availability >= 100.0 {
+        Cell::colored(text, Color::Green)
+    } else if availability > 0.0 {
+        Cell::colored(text, Color::Yellow)
+    } else {
+        Cell::colored(text, Color::Red)
+    }
+}
+
+fn ms_cell(ms: Option<f64>) -> Cell {
+    match ms {
+        Some(ms) => Cell::plain(format!("{ms:.1}")),
+        None => Cell::plain("-"),
+    }
+}
+
+/// One row per relay with a column per round in milliseconds, a failed round shown as x
+fn render_ping_mesh_human(ping_mesh: &PingMesh, color_enabled: bool) -> String {
+    let round_headers: Vec<String> = (1..=ping_mesh.count).map(|round| round.to_string()).collect();
+    let mut headers: Vec<&str> = vec!["Relay"];
+    headers.extend(round_headers.iter().map(String::as_str));
+    headers.extend(["Available", "Min ms", "Avg ms", "Max ms"]);
+    let mut table = Table::new(&headers);
+    for relay in ping_mesh.relays.iter() {
+        let mut cells = vec![Cell::plain(format!("{}:{}", relay.host, relay.port))];
+        cells.extend(relay.latencies_ms.iter().map(|latency_ms| match latency_ms {
+            Some(_) => ms_cell(*latency_ms),
+            None => Cell::colored("x", Color::Red),
+        }));
+        cells.extend([
+            availability_cell(relay.availability),
+            ms_cell(relay.min_ms),
+            ms_cell(relay.avg_ms),
+            ms_cell(relay.max_ms),
+        ]);
+        table.row(cells);
+    }
+    table.render(color_enabled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_relay() {
+        assert_eq!(
+            RelayAddress::parse("relay1.example.com:3001").unwrap(),
+            RelayAddress {
+                host: "relay1.example.com".to_string(),
+                port: 3001
+            }
+        );
+        assert_eq!(
+            RelayAddress::parse("[2001:db8::1]:6000").unwrap(),
+            RelayAddress {
+                host: "2001:db8::1".to_string(),
+                port: 6000
+            }
+        );
+        assert!(RelayAddress::parse("relay1.example.com").is_err());
+        assert!(RelayAddress::parse(":3001").is_err());
+        assert!(RelayAddress::parse("relay1.example.com:port").is_err());
+    }
+
+    #[test]
+    fn test_read_topology() {
+        let topology_file = std::env::temp_dir().join("cncli_test_topology.json");
+        std::fs::write(
+            &topology_file,
+            r#"{
+                "Producers": [{"addr": "10.0.0.1", "port": 3001, "valency": 1}],
+                "localRoots": [{"accessPoints": [{"address": "10.0.0.2", "port": 3001}, {"address": "10.0.0.1", "port": 3001}], "advertise": false, "valency": 2}],
+                "publicRoots": [{"accessPoints": [{"address": "relay.example.com", "port": 6000}], "advertise": false}],
+                "useLedgerAfterSlot": -1
+            }"#,
+        )
+        .unwrap();
+        let relays = read_topology(&topology_file).unwrap();
+        std::fs::remove_file(&topology_file).unwrap();
+
+        let relays: Vec<String> = relays
+            .iter()
+            .map(|relay| format!("{}:{}", relay.host, relay.port))
+            .collect();
+        assert_eq!(relays, vec!["10.0.0.1:3001", "10.0.0.2:3001", "relay.example.com:6000"]);
+    }
+
+    #[test]
+    fn test_relay_latencies() {
+        let relay = RelayAddress::parse("10.0.0.1:3001").unwrap();
+        let relay_latencies = RelayLatencies::new(
+            &relay,
+            vec![
+                Ok(Duration::from_micros(12_340)),
+                Err("connection refused".to_string()),
+                Ok(Duration::from_millis(21)),
+                Err("connection refused".to_string()),
+            ],
+        );
+        assert_eq!(relay_latencies.availability, 50.0);
+        assert_eq!(relay_latencies.latencies_ms, vec![Some(12.34), None, Some(21.0), None]);
+        assert_eq!(relay_latencies.min_ms, Some(12.34));
+        assert_eq!(relay_latencies.avg_ms, Some(16.67));
+        assert_eq!(relay_latencies.max_ms, Some(21.0));
+        assert_eq!(relay_latencies.errors, vec!["connection refused".to_string()]);
+
+        let rendered = render_ping_mesh_human(
+            &PingMesh {
+                status: "ok".to_string(),
+                count: 4,
+                relays: vec![relay_latencies],
+            },
+            false,
+        );
+        assert_eq!(
+            rendered,
+            "Relay          1     2  3     4  Available  Min ms  Avg ms  Max ms\n\
+             -------------  ----  -  ----  -  ---------  ------  ------  ------\n\
+             10.0.0.1:3001  12.3  x  21.0  x  50%        12.3    16.7    21.0\n"
+        );
+    }
+}
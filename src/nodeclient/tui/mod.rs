@@ -0,0 +1,660 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::redb::{is_redb_database, RedbBlockStore};
+use crate::nodeclient::blockstore::{BlockDetail, BlockStore};
+use crate::nodeclient::http;
+use crate::nodeclient::http::HttpSettings;
+use crate::nodeclient::leaderlog::{
+    get_first_slot_of_epoch, get_first_slot_of_epoch_number, guess_shelley_transition_epoch, read_byron_genesis,
+    read_shelley_genesis, slot_at_time_ms, slot_to_naivedatetime, ByronGenesis, ShelleyGenesis,
+};
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::runstate;
+use crate::nodeclient::runstate::LastRun;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid path: {0}")]
+    InvalidPath(std::path::PathBuf),
+
+    #[error("the redb database is open in another process, e.g. sync, use a sqlite database next to sync")]
+    DbInUse,
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Redb error: {0}")]
+    Redb(#[from] crate::nodeclient::blockstore::redb::Error),
+
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] crate::nodeclient::blockstore::sqlite::Error),
+
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] crate::nodeclient::blockstore::Error),
+
+    #[error("Leaderlog error: {0}")]
+    Leaderlog(#[from] crate::nodeclient::leaderlog::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] crate::nodeclient::http::Error),
+
+    #[error("Request error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("Run state error: {0}")]
+    RunState(#[from] crate::nodeclient::runstate::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The db tip is shown as synced while it is at most this old, like the healthcheck default
+const SYNCED_TIP_AGE_SECONDS: i64 = 120;
+const NEXT_SLOTS: usize = 5;
+const RECENT_BLOCKS: usize = 10;
+
+struct Context {
+    byron: ByronGenesis,
+    shelley: ShelleyGenesis,
+    shelley_transition_epoch: u64,
+    pool_ids: Vec<PoolId>,
+    /// The serve API to read schedules from that are not saved in the db, with its client
+    serve: Option<(reqwest::blocking::Client, String)>,
+}
+
+/// Everything on screen, gathered again on every refresh. Failures end up in errors so the rest still shows.
+#[derive(Debug, Default)]
+struct Dashboard {
+    tip: Option<Tip>,
+    epoch: u64,
+    slot_in_epoch: u64,
+    epoch_length: u64,
+    pools: Vec<PoolSlots>,
+    /// The blocks of our pools from the previous epoch on, newest first
+    recent_blocks: Vec<BlockDetail>,
+    pooltool_runs: Vec<LastRun>,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Tip {
+    block_number: u64,
+    slot_number: u64,
+    at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct PoolSlots {
+    pool_id: String,
+    /// The number of slots assigned in the current epoch, None without a saved or served leaderlog
+    epoch_slots: Option<u64>,
+    next_slots: Vec<NextSlot>,
+}
+
+#[derive(Debug, PartialEq)]
+struct NextSlot {
+    slot_number: u64,
+    at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServedLeaderlog {
+    assigned_slots: Vec<u64>,
+}
+
+/// Show a dashboard of the sync status, the upcoming slots and recent blocks of our pools and the pooltool
+/// submissions recorded in the --state-file until q is pressed
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn tui(
+    db_path: &Path,
+    byron_genesis: &Path,
+    shelley_genesis: &Path,
+    shelley_transition_epoch: &Option<u64>,
+    pool_ids: &[PoolId],
+    serve_url: &Option<String>,
+    refresh_seconds: u64,
+) -> Result<(), Error> {
+    if !db_path.exists() {
+        return Err(Error::InvalidPath(db_path.to_path_buf()));
+    }
+
+    let byron = read_byron_genesis(byron_genesis)?;
+    let shelley = read_shelley_genesis(shelley_genesis)?;
+    let shelley_transition_epoch = match *shelley_transition_epoch {
+        None => guess_shelley_transition_epoch(shelley.network_magic),
+        Some(value) => value,
+    };
+    let serve = match serve_url {
+        Some(serve_url) => Some((
+            http::client(&HttpSettings::from_env()?, None)?,
+            serve_url.trim_end_matches('/').to_string(),
+        )),
+        None => None,
+    };
+    let context = Context {
+        byron,
+        shelley,
+        shelley_transition_epoch,
+        pool_ids: pool_ids.to_vec(),
+        serve,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run(
+        &mut terminal,
+        db_path,
+        &context,
+        Duration::from_secs(refresh_seconds.max(1)),
+    );
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, db_path: &Path, context: &Context, refresh: Duration) -> Result<(), Error> {
+    let mut dashboard = refresh_dashboard(db_path, context, Utc::now());
+    let mut refreshed_at = Instant::now();
+    loop {
+        if refreshed_at.elapsed() >= refresh {
+            dashboard = refresh_dashboard(db_path, context, Utc::now());
+            refreshed_at = Instant::now();
+        }
+        terminal.draw(|frame| draw(frame, &dashboard, Utc::now()))?;
+
+        // redraw at least every second so the countdowns tick between refreshes
+        if event::poll(Duration::from_secs(1))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char('r') => {
+                        dashboard = refresh_dashboard(db_path, context, Utc::now());
+                        refreshed_at = Instant::now();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// The db is opened for every refresh and closed again right after, so a sqlite db is never held while sync writes
+fn refresh_dashboard(db_path: &Path, context: &Context, now: DateTime<Utc>) -> Dashboard {
+    match open_block_store(db_path) {
+        Ok(mut block_store) => collect(block_store.as_mut(), context, now),
+        Err(error) => Dashboard {
+            errors: vec![format!("Could not open {}: {}", db_path.display(), error)],
+            ..Dashboard::default()
+        },
+    }
+}
+
+/// A redb db is not waited for when another process has it open, the refresh would freeze the dashboard for seconds
+/// and a running sync never closes it anyway
+fn open_block_store(db_path: &Path) -> Result<Box<dyn BlockStore + Send>, Error> {
+    if is_redb_database(db_path)? {
+        match RedbBlockStore::new_no_wait(db_path) {
+            Ok(block_store) => Ok(Box::new(block_store)),
+            Err(error) if error.is_already_open() => Err(Error::DbInUse),
+            Err(error) => Err(error.into()),
+        }
+    } else {
+        Ok(blockstore::open(db_path, true)?)
+    }
+}
+
+fn collect(block_store: &mut dyn BlockStore, context: &Context, now: DateTime<Utc>) -> Dashboard {
+    let mut dashboard = Dashboard {
+        epoch_length: context.shelley.epoch_length,
+        ..Dashboard::default()
+    };
+    let now_slot = slot_at_time_ms(
+        &context.byron,
+        &context.shelley,
+        context.shelley_transition_epoch,
+        now.timestamp_millis() as u64,
+    );
+    let (epoch, first_slot_of_epoch) = get_first_slot_of_epoch(
+        &context.byron,
+        &context.shelley,
+        now_slot,
+        context.shelley_transition_epoch,
+    );
+    dashboard.epoch = epoch;
+    dashboard.slot_in_epoch = now_slot - first_slot_of_epoch;
+
+    match tip(block_store, context) {
+        Ok(tip) => dashboard.tip = tip,
+        Err(error) => dashboard.errors.push(format!("tip: {error}")),
+    }
+
+    for pool_id in context.pool_ids.iter() {
+        let mut pool_slots = PoolSlots {
+            pool_id: pool_id.to_string(),
+            ..PoolSlots::default()
+        };
+        // the schedule of the next epoch is there once leaderlog could calculate it
+        for schedule_epoch in [epoch, epoch + 1] {
+            match schedule(block_store, context, pool_id, schedule_epoch) {
+                Ok(Some(assigned_slots)) => {
+                    if schedule_epoch == epoch {
+                        pool_slots.epoch_slots = Some(assigned_slots.len() as u64);
+                    }
+                    pool_slots.next_slots.extend(
+                        assigned_slots
+                            .into_iter()
+                            .filter(|slot_number| *slot_number >= now_slot)
+                            .map(|slot_number| NextSlot {
+                                slot_number,
+                                at: slot_time(context, slot_number),
+                            }),
+                    );
+                }
+                Ok(None) => {}
+                Err(error) => dashboard
+                    .errors
+                    .push(format!("leaderlog of {pool_id} for epoch {schedule_epoch}: {error}")),
+            }
+        }
+        pool_slots.next_slots.truncate(NEXT_SLOTS);
+        dashboard.pools.push(pool_slots);
+    }
+
+    let first_slot_of_previous_epoch = get_first_slot_of_epoch_number(
+        &context.byron,
+        &context.shelley,
+        epoch.saturating_sub(1),
+        context.shelley_transition_epoch,
+    );
+    for pool_id in context.pool_ids.iter() {
+        match block_store.get_pool_block_details_in_slot_range(pool_id, first_slot_of_previous_epoch, now_slot) {
+            Ok(block_details) => dashboard.recent_blocks.extend(block_details),
+            Err(error) => dashboard.errors.push(format!("blocks of {pool_id}: {error}")),
+        }
+    }
+    dashboard
+        .recent_blocks
+        .sort_by(|a, b| b.slot_number.cmp(&a.slot_number));
+    dashboard.recent_blocks.truncate(RECENT_BLOCKS);
+
+    for operation in ["sendtip", "sendslots"] {
+        match runstate::recorded_runs(operation) {
+            Ok(runs) => dashboard.pooltool_runs.extend(runs),
+            Err(error) => dashboard.errors.push(format!("{operation} runs: {error}")),
+        }
+    }
+
+    dashboard
+}
+
+/// The newest non-orphaned block, None for an empty db
+fn tip(block_store: &mut dyn BlockStore, context: &Context) -> Result<Option<Tip>, Error> {
+    let tip_slot_number = block_store.get_tip_slot_number()?;
+    Ok(block_store
+        .get_block_details_at_slot(tip_slot_number)?
+        .into_iter()
+        .find(|block_detail| !block_detail.orphaned)
+        .map(|block_detail| Tip {
+            block_number: block_detail.block_number,
+            slot_number: block_detail.slot_number,
+            at: slot_time(context, block_detail.slot_number),
+        }))
+}
+
+/// The assigned slots saved in the db, or else served by the serve API
+fn schedule(
+    block_store: &mut dyn BlockStore,
+    context: &Context,
+    pool_id: &PoolId,
+    epoch: u64,
+) -> Result<Option<Vec<u64>>, Error> {
+    if let Some(slots) = block_store.get_previous_slots(epoch, pool_id)? {
+        return Ok(Some(serde_json::from_str(&slots)?));
+    }
+    let (client, serve_url) = match &context.serve {
+        Some(serve) => serve,
+        None => return Ok(None),
+    };
+    let response = client.get(format!("{serve_url}/leaderlog/{pool_id}/{epoch}")).send()?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let served_leaderlog: ServedLeaderlog = response.error_for_status()?.json()?;
+    Ok(Some(served_leaderlog.assigned_slots))
+}
+
+fn slot_time(context: &Context, slot_number: u64) -> DateTime<Utc> {
+    slot_to_naivedatetime(
+        &context.byron,
+        &context.shelley,
+        slot_number,
+        context.shelley_transition_epoch,
+    )
+    .and_utc()
+}
+
+/// A duration as [Nd ]HH:MM:SS, negative ones as 00:00:00
+fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    let days = seconds / 86400;
+    let hms = format!(
+        "{:02}:{:02}:{:02}",
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+    if days > 0 {
+        format!("{days}d {hms}")
+    } else {
+        hms
+    }
+}
+
+fn format_time(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard, now: DateTime<Utc>) {
+    let next_slot_rows: usize = dashboard
+        .pools
+        .iter()
+        .map(|pool_slots| pool_slots.next_slots.len().max(1))
+        .sum();
+    let [epoch_area, sync_area, slots_area, blocks_area, pooltool_area, footer_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(next_slot_rows as u16 + 3),
+        Constraint::Min(4),
+        Constraint::Length(dashboard.pooltool_runs.len().max(1) as u16 + 3),
+        Constraint::Length(dashboard.errors.len() as u16 + 1),
+    ])
+    .areas(frame.area());
+
+    let ratio = if dashboard.epoch_length == 0 {
+        0.0
+    } else {
+        (dashboard.slot_in_epoch as f64 / dashboard.epoch_length as f64).min(1.0)
+    };
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title(format!(" Epoch {} ", dashboard.epoch)))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(ratio)
+            .label(format!(
+                "slot {} of {} ({:.1}%)",
+                dashboard.slot_in_epoch,
+                dashboard.epoch_length,
+                ratio * 100.0
+            )),
+        epoch_area,
+    );
+
+    let sync_line = match &dashboard.tip {
+        Some(tip) => {
+            let tip_age = (now - tip.at).num_seconds();
+            let (status, color) = if tip_age <= SYNCED_TIP_AGE_SECONDS {
+                ("synced", Color::Green)
+            } else {
+                ("behind", Color::Red)
+            };
+            Line::from(vec![
+                status.fg(color).bold(),
+                format!(
+                    "  block {}, slot {}, {} ({} ago)",
+                    tip.block_number,
+                    tip.slot_number,
+                    format_time(&tip.at),
+                    format_duration(tip_age)
+                )
+                .into(),
+            ])
+        }
+        None => Line::from("no blocks in the db yet".fg(Color::Yellow)),
+    };
+    frame.render_widget(
+        Paragraph::new(sync_line).block(Block::bordered().title(" Sync ")),
+        sync_area,
+    );
+
+    let mut slot_rows = vec![];
+    for pool_slots in dashboard.pools.iter() {
+        let epoch_slots = match pool_slots.epoch_slots {
+            Some(epoch_slots) => epoch_slots.to_string(),
+            None => "no leaderlog".to_string(),
+        };
+        if pool_slots.next_slots.is_empty() {
+            slot_rows.push(Row::new(vec![pool_slots.pool_id.clone(), epoch_slots, "-".to_string()]));
+        }
+        for (i, next_slot) in pool_slots.next_slots.iter().enumerate() {
+            let (pool_id, epoch_slots) = match i {
+                0 => (pool_slots.pool_id.clone(), epoch_slots.clone()),
+                _ => (String::new(), String::new()),
+            };
+            slot_rows.push(Row::new(vec![
+                pool_id,
+                epoch_slots,
+                next_slot.slot_number.to_string(),
+                format_time(&next_slot.at),
+                format_duration((next_slot.at - now).num_seconds()),
+            ]));
+        }
+    }
+    frame.render_widget(
+        Table::new(
+            slot_rows,
+            [
+                Constraint::Length(56),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(20),
+                Constraint::Min(12),
+            ],
+        )
+        .header(Row::new(vec!["Pool", "Epoch slots", "Next slot", "At", "In"]).bold())
+        .block(Block::bordered().title(" Assigned slots ")),
+        slots_area,
+    );
+
+    let block_rows = dashboard.recent_blocks.iter().map(|block_detail| {
+        let row = Row::new(vec![
+            block_detail.pool_id.clone(),
+            block_detail.block_number.to_string(),
+            block_detail.slot_number.to_string(),
+            block_detail.block_size.to_string(),
+            if block_detail.orphaned { "orphaned" } else { "" }.to_string(),
+        ]);
+        if block_detail.orphaned {
+            row.fg(Color::Red)
+        } else {
+            row
+        }
+    });
+    frame.render_widget(
+        Table::new(
+            block_rows,
+            [
+                Constraint::Length(56),
+                Constraint::Length(10),
+                Constraint::Length(12),
+                Constraint::Length(8),
+                Constraint::Min(8),
+            ],
+        )
+        .header(Row::new(vec!["Pool", "Block", "Slot", "Size", ""]).bold())
+        .block(Block::bordered().title(" Recent blocks ")),
+        blocks_area,
+    );
+
+    let pooltool_rows: Vec<Row> = if dashboard.pooltool_runs.is_empty() {
+        vec![Row::new(vec![
+            "no sendtip or sendslots runs recorded, see --state-file",
+        ])]
+    } else {
+        dashboard
+            .pooltool_runs
+            .iter()
+            .map(|run| {
+                Row::new(vec![
+                    run.operation.clone(),
+                    run.pool_id.clone().unwrap_or_default(),
+                    run.epoch.map(|epoch| epoch.to_string()).unwrap_or_default(),
+                    run.at.clone(),
+                    run.outcome.clone(),
+                ])
+            })
+            .collect()
+    };
+    frame.render_widget(
+        Table::new(
+            pooltool_rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(56),
+                Constraint::Length(6),
+                Constraint::Length(20),
+                Constraint::Min(10),
+            ],
+        )
+        .header(Row::new(vec!["Operation", "Pool", "Epoch", "At", "Outcome"]).bold())
+        .block(Block::bordered().title(" PoolTool ")),
+        pooltool_area,
+    );
+
+    let mut footer: Vec<Line> = dashboard
+        .errors
+        .iter()
+        .map(|error| Line::from(error.clone().fg(Color::Red)))
+        .collect();
+    footer.push(Line::from(" q quit  r refresh ".dim()));
+    frame.render_widget(Paragraph::new(footer), footer_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::nodeclient::blockstore::memory::MemoryBlockStore;
+    use crate::nodeclient::sync::BlockHeader;
+
+    fn context(pool_ids: Vec<PoolId>) -> Context {
+        // mainnet, the first shelley slot is 4492800
+        let byron = serde_json::from_str(
+            r#"{"startTime": 1506203091, "protocolConsts": {"k": 2160}, "blockVersionData": {"slotDuration": "20000"}}"#,
+        )
+        .unwrap();
+        let shelley = serde_json::from_str(
+            r#"{"activeSlotsCoeff": 0.05, "networkMagic": 764824073, "slotLength": 1, "epochLength": 432000}"#,
+        )
+        .unwrap();
+        Context {
+            byron,
+            shelley,
+            shelley_transition_epoch: 208,
+            pool_ids,
+            serve: None,
+        }
+    }
+
+    #[test]
+    fn test_collect() {
+        let first_slot_of_epoch_500 = 4492800 + (500 - 208) * 432000;
+        let mut block_store = MemoryBlockStore::new();
+        let mut blocks: Vec<BlockHeader> = (1..=3u64)
+            .map(|block_number| BlockHeader {
+                block_number,
+                slot_number: first_slot_of_epoch_500 + block_number * 20,
+                hash: vec![block_number as u8; 32],
+                prev_hash: vec![block_number as u8 - 1; 32],
+                // only the second block is ours
+                node_vkey: vec![if block_number == 2 { 2 } else { 1 }; 32],
+                eta_vrf_0: vec![1; 64],
+                ..BlockHeader::default()
+            })
+            .collect();
+        block_store
+            .save_block(
+                &mut blocks,
+                "1a3be38bcbb7911969283716ad7aa550250226b76a61fc51cc9a9a35d9276d81",
+            )
+            .unwrap();
+        let pool_id = PoolId::from_str(
+            &block_store
+                .get_block_details_at_slot(first_slot_of_epoch_500 + 40)
+                .unwrap()
+                .remove(0)
+                .pool_id,
+        )
+        .unwrap();
+        let slots = format!(
+            "[{},{},{}]",
+            first_slot_of_epoch_500 + 40,
+            first_slot_of_epoch_500 + 100,
+            first_slot_of_epoch_500 + 200
+        );
+        block_store.save_slots(500, &pool_id, 3, &slots, "hash", None).unwrap();
+
+        let context = context(vec![pool_id.clone()]);
+        let now = slot_time(&context, first_slot_of_epoch_500 + 90);
+        let dashboard = collect(&mut block_store, &context, now);
+
+        assert!(dashboard.errors.is_empty(), "{:?}", dashboard.errors);
+        assert_eq!(dashboard.epoch, 500);
+        assert_eq!(dashboard.slot_in_epoch, 90);
+        assert_eq!(
+            dashboard.tip,
+            Some(Tip {
+                block_number: 3,
+                slot_number: first_slot_of_epoch_500 + 60,
+                at: slot_time(&context, first_slot_of_epoch_500 + 60),
+            })
+        );
+        assert_eq!(dashboard.pools.len(), 1);
+        assert_eq!(dashboard.pools[0].epoch_slots, Some(3));
+        assert_eq!(
+            dashboard.pools[0]
+                .next_slots
+                .iter()
+                .map(|next_slot| next_slot.slot_number)
+                .collect::<Vec<_>>(),
+            vec![first_slot_of_epoch_500 + 100, first_slot_of_epoch_500 + 200]
+        );
+        assert_eq!(dashboard.recent_blocks.len(), 1);
+        assert_eq!(dashboard.recent_blocks[0].block_number, 2);
+    }
+
+    #[test]
+    fn test_open_block_store_does_not_wait_for_redb() {
+        let db_path = std::env::temp_dir().join(format!("cncli_test_tui_{}.redb", std::process::id()));
+        let _ = std::fs::remove_file(&db_path);
+        let block_store = RedbBlockStore::new(&db_path).unwrap();
+        let started = Instant::now();
+        assert!(matches!(open_block_store(&db_path), Err(Error::DbInUse)));
+        assert!(started.elapsed() < Duration::from_millis(500));
+        drop(block_store);
+        assert!(open_block_store(&db_path).is_ok());
+        std::fs::remove_file(&db_path).unwrap();
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(-5), "00:00:00");
+        assert_eq!(format_duration(3723), "01:02:03");
+        assert_eq!(format_duration(2 * 86400 + 59), "2d 00:00:59");
+    }
+}
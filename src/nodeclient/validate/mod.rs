@@ -1,10 +1,15 @@
 use std::path::Path;
 
+use serde::Serialize;
 use thiserror::Error;
 
-use crate::nodeclient::blockstore::redb::{is_redb_database, RedbBlockStore};
-use crate::nodeclient::blockstore::sqlite::SqLiteBlockStore;
-use crate::nodeclient::blockstore::{Block, BlockStore};
+use crate::nodeclient::blockstore;
+use crate::nodeclient::blockstore::Block;
+use crate::nodeclient::poolid::format_pool_id;
+use crate::nodeclient::reporter;
+use crate::nodeclient::reporter::human;
+use crate::nodeclient::reporter::human::{Cell, Color, Table};
+use crate::ReportFormat;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -21,62 +26,192 @@ pub enum Error {
     Blockstore(#[from] crate::nodeclient::blockstore::Error),
 }
 
-pub fn validate_block(db_path: &Path, hash: &str) {
+/// The keys stay snake_case and the numbers quoted as validate has always printed them
+#[derive(Debug, Serialize)]
+struct ValidateResult {
+    status: String,
+    block_number: String,
+    slot_number: String,
+    pool_id: String,
+    hash: String,
+    prev_hash: String,
+    leader_vrf: String,
+    /// Only set for an orphaned block: its position among the blocks received for the same slot or height
+    #[serde(skip_serializing_if = "Option::is_none")]
+    received_order: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    received_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_peer: Option<String>,
+    /// Only set for an orphaned block: the other blocks received for the same slot or height
+    #[serde(skip_serializing_if = "Option::is_none")]
+    competing_blocks: Option<Vec<CompetingBlock>>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompetingBlock {
+    status: String,
+    block_number: String,
+    slot_number: String,
+    pool_id: String,
+    hash: String,
+    received_order: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    received_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_peer: Option<String>,
+}
+
+pub fn validate_block(
+    db_path: &Path,
+    hash: &str,
+    busy_timeout_ms: u64,
+    read_only: bool,
+    bech32: bool,
+    format: &ReportFormat,
+) {
+    let report_error = match format {
+        ReportFormat::Json => reporter::report_error::<String>,
+        ReportFormat::Human => human::report_error::<String>,
+    };
     let like = format!("{hash}%");
-    match query_block(db_path, like) {
-        Ok(block) => match block {
-            Some(block) => {
-                println!(
-                    "{{\n\
-                    \x20\"status\": \"{}\",\n\
-                    \x20\"block_number\": \"{}\",\n\
-                    \x20\"slot_number\": \"{}\",\n\
-                    \x20\"pool_id\": \"{}\",\n\
-                    \x20\"hash\": \"{}\",\n\
-                    \x20\"prev_hash\": \"{}\",\n\
-                    \x20\"leader_vrf\": \"{}\"\n\
-                    }}",
-                    if block.orphaned { "orphaned" } else { "ok" },
-                    block.block_number,
-                    block.slot_number,
-                    block.pool_id,
-                    block.hash,
-                    block.prev_hash,
-                    block.leader_vrf,
-                );
+    match query_block(db_path, like, busy_timeout_ms, read_only) {
+        Ok(Some((block, competing_blocks))) => {
+            // received_order counts from 1 and includes the orphaned block itself
+            let received_order = competing_blocks
+                .iter()
+                .position(|competing_block| competing_block.hash == block.hash)
+                .map(|index| (index + 1).to_string());
+            let competing_blocks = if block.orphaned {
+                Some(
+                    competing_blocks
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, competing_block)| competing_block.hash != block.hash)
+                        .map(|(index, competing_block)| CompetingBlock {
+                            status: if competing_block.orphaned { "orphaned" } else { "ok" }.to_string(),
+                            block_number: competing_block.block_number.to_string(),
+                            slot_number: competing_block.slot_number.to_string(),
+                            pool_id: format_pool_id(&competing_block.pool_id, bech32),
+                            hash: competing_block.hash.clone(),
+                            received_order: (index + 1).to_string(),
+                            received_at: competing_block.received_at.clone(),
+                            source_peer: competing_block.source_peer.clone(),
+                        })
+                        .collect(),
+                )
+            } else {
+                None
+            };
+            let validate_result = ValidateResult {
+                status: if block.orphaned { "orphaned" } else { "ok" }.to_string(),
+                block_number: block.block_number.to_string(),
+                slot_number: block.slot_number.to_string(),
+                pool_id: format_pool_id(&block.pool_id, bech32),
+                hash: block.hash,
+                prev_hash: block.prev_hash,
+                leader_vrf: block.leader_vrf,
+                received_order,
+                received_at: block.received_at,
+                source_peer: block.source_peer,
+                competing_blocks,
+            };
+            let reported = match format {
+                ReportFormat::Json => reporter::report(&validate_result),
+                ReportFormat::Human => reporter::write_report(
+                    render_validate_result_human(&validate_result, human::color_enabled()).as_bytes(),
+                ),
+            };
+            if let Err(error) = reported {
+                report_error(error.to_string());
             }
-            None => {
-                println!(
-                    "{{\n\
-                    \x20\"status\": \"error\",\n\
-                    \x20\"errorMessage\": \"Block not found\"\n\
-                    }}"
-                );
-            }
-        },
-        Err(error) => {
-            println!(
-                "{{\n\
-            \x20\"status\": \"error\",\n\
-            \x20\"errorMessage\": \"{error}\"\n\
-            }}"
-            );
         }
+        Ok(None) => report_error("Block not found".to_string()),
+        Err(error) => report_error(error.to_string()),
+    }
+}
+
+fn status_cell(status: &str) -> Cell {
+    match status {
+        "ok" => Cell::colored(status, Color::Green),
+        _ => Cell::colored(status, Color::Red),
+    }
+}
+
+/// The block as name and value pairs, followed by a table of the blocks it competed with when it was orphaned
+fn render_validate_result_human(validate_result: &ValidateResult, color_enabled: bool) -> String {
+    let mut block = Table::key_value();
+    block
+        .row(vec![Cell::plain("Status"), status_cell(&validate_result.status)])
+        .row(vec![
+            Cell::plain("Block number"),
+            Cell::plain(&validate_result.block_number),
+        ])
+        .row(vec![Cell::plain("Slot"), Cell::plain(&validate_result.slot_number)])
+        .row(vec![Cell::plain("Pool id"), Cell::plain(&validate_result.pool_id)])
+        .row(vec![Cell::plain("Hash"), Cell::plain(&validate_result.hash)])
+        .row(vec![Cell::plain("Prev hash"), Cell::plain(&validate_result.prev_hash)])
+        .row(vec![
+            Cell::plain("Leader vrf"),
+            Cell::plain(&validate_result.leader_vrf),
+        ]);
+    if let Some(ref received_order) = validate_result.received_order {
+        block.row(vec![Cell::plain("Received order"), Cell::plain(received_order)]);
+    }
+    if let Some(ref received_at) = validate_result.received_at {
+        block.row(vec![Cell::plain("Received at"), Cell::plain(received_at)]);
+    }
+    if let Some(ref source_peer) = validate_result.source_peer {
+        block.row(vec![Cell::plain("Source peer"), Cell::plain(source_peer)]);
+    }
+    let mut out = block.render(color_enabled);
+    if let Some(ref competing_blocks) = validate_result.competing_blocks {
+        let mut table = Table::new(&[
+            "Status",
+            "Block number",
+            "Slot",
+            "Pool id",
+            "Hash",
+            "Received order",
+            "Received at",
+            "Source peer",
+        ]);
+        for competing_block in competing_blocks.iter() {
+            table.row(vec![
+                status_cell(&competing_block.status),
+                Cell::plain(&competing_block.block_number),
+                Cell::plain(&competing_block.slot_number),
+                Cell::plain(&competing_block.pool_id),
+                Cell::plain(&competing_block.hash),
+                Cell::plain(&competing_block.received_order),
+                Cell::plain(competing_block.received_at.as_deref().unwrap_or("-")),
+                Cell::plain(competing_block.source_peer.as_deref().unwrap_or("-")),
+            ]);
+        }
+        out.push('\n');
+        out.push_str(&table.render(color_enabled));
     }
+    out
 }
 
-fn query_block(db_path: &Path, hash_start: String) -> Result<Option<Block>, Error> {
+fn query_block(
+    db_path: &Path,
+    hash_start: String,
+    busy_timeout_ms: u64,
+    read_only: bool,
+) -> Result<Option<(Block, Vec<Block>)>, Error> {
     if !db_path.exists() {
         return Err(Error::InvalidPath(db_path.to_path_buf()));
     }
-    // check if db_path is a redb database based on magic number
-    let use_redb = is_redb_database(db_path)?;
-
-    let mut block_store: Box<dyn BlockStore + Send> = if use_redb {
-        Box::new(RedbBlockStore::new(db_path)?)
-    } else {
-        Box::new(SqLiteBlockStore::new(db_path)?)
-    };
+    let mut block_store = blockstore::open_with_busy_timeout(db_path, busy_timeout_ms, read_only)?;
 
-    Ok(block_store.find_block_by_hash(&hash_start)?)
+    match block_store.find_block_by_hash(&hash_start)? {
+        // only an orphaned block lost a battle worth showing
+        Some(block) if block.orphaned => {
+            let competing_blocks = block_store.get_competing_blocks(block.slot_number, block.block_number)?;
+            Ok(Some((block, competing_blocks)))
+        }
+        Some(block) => Ok(Some((block, vec![]))),
+        None => Ok(None),
+    }
 }
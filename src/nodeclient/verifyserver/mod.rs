@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info};
+
+use crate::nodeclient::poolid::PoolId;
+use crate::nodeclient::serve;
+use crate::nodeclient::serve::{error_body, write_response, MAX_REQUEST_SIZE};
+use crate::nodeclient::sign;
+use crate::nodeclient::snapshot;
+use crate::nodeclient::snapshot::QueryTimeouts;
+
+// outstanding challenges are kept in memory, this bounds how much a client asking for many can take
+const MAX_CHALLENGES: usize = 10000;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] snapshot::Error),
+
+    #[error("Serve error: {0}")]
+    Serve(#[from] serve::Error),
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    TooLarge(String),
+
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+struct VerifyServerState {
+    domain: String,
+    challenge_ttl: Duration,
+    /// Set to look up the registered vrf vkey hash of a pool id on chain
+    socket_path: Option<PathBuf>,
+    network_magic: u64,
+    /// The nonces handed out and when they expire. A nonce can be verified once.
+    challenges: Mutex<HashMap<String, Instant>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChallengeResponse {
+    status: String,
+    domain: String,
+    nonce: String,
+    expires_in_seconds: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VerifyRequest {
+    nonce: String,
+    signature: String,
+    /// The pool's vrf vkey, raw or as the cborHex of its vrf.vkey file
+    pool_vrf_vkey: String,
+    pool_vrf_vkey_hash: Option<String>,
+    pool_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VerifyResponse {
+    status: String,
+    domain: String,
+    pool_vrf_vkey_hash: String,
+}
+
+/// Hand out CIP-0022 challenges for a domain and verify the pools' signatures of them over HTTP until the process is
+/// stopped
+pub(crate) async fn verify_server(
+    host: &str,
+    port: u16,
+    domain: &str,
+    challenge_ttl_secs: u64,
+    socket_path: &Option<PathBuf>,
+    network_magic: u64,
+) -> Result<(), Error> {
+    let state = Arc::new(VerifyServerState {
+        domain: domain.to_string(),
+        challenge_ttl: Duration::from_secs(challenge_ttl_secs),
+        socket_path: socket_path.clone(),
+        network_magic,
+        challenges: Mutex::new(HashMap::new()),
+    });
+
+    let listener = TcpListener::bind((host, port)).await?;
+    info!(
+        "Serving CIP-0022 challenges for {} on http://{}",
+        domain,
+        listener.local_addr()?
+    );
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        debug!("connection from {}", peer);
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(stream, state).await {
+                error!("{}: {}", peer, error);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<VerifyServerState>) -> Result<(), Error> {
+    let (method, path, body) = match read_request(&mut stream).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(Error::TooLarge(error)) => return Ok(write_response(&mut stream, 413, &error_body(error)?, None).await?),
+        Err(error) => return Err(error),
+    };
+    let path = path.split('?').next().unwrap_or_default().trim_end_matches('/');
+    let result = match (method.as_str(), path) {
+        ("POST", "/challenge") => challenge_body(&state),
+        ("POST", "/verify") => verify_body(&state, &body).await,
+        (_, "/challenge") | (_, "/verify") => {
+            return Ok(write_response(&mut stream, 405, &error_body("Only POST is supported")?, None).await?)
+        }
+        _ => return Ok(write_response(&mut stream, 404, &error_body("Not found")?, None).await?),
+    };
+
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(error) => {
+            let status = match error {
+                Error::BadRequest(_) | Error::Json(_) => 400,
+                Error::Unauthorized(_) => 401,
+                Error::NotFound(_) => 404,
+                Error::Unavailable(_) => 503,
+                _ => 500,
+            };
+            (status, error_body(error)?)
+        }
+    };
+    Ok(write_response(&mut stream, status, &body, None).await?)
+}
+
+/// Read a request line, headers and a body of Content-Length bytes. None when the client closed the connection.
+async fn read_request(stream: &mut TcpStream) -> Result<Option<(String, String, Vec<u8>)>, Error> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    let header_end = loop {
+        if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(Error::TooLarge("Request header too large".to_string()));
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buf[..read]);
+    };
+    let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let content_length: usize = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or_default();
+    if content_length > MAX_REQUEST_SIZE {
+        return Err(Error::TooLarge("Request body too large".to_string()));
+    }
+    let mut body = request[header_end..].to_vec();
+    while body.len() < content_length {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+    body.truncate(content_length);
+    Ok(Some((method, path, body)))
+}
+
+fn challenge_body(state: &VerifyServerState) -> Result<String, Error> {
+    let nonce = sign::challenge_nonce();
+    let now = Instant::now();
+    {
+        let mut challenges = state.challenges.lock().unwrap();
+        challenges.retain(|_, expires_at| *expires_at > now);
+        if challenges.len() >= MAX_CHALLENGES {
+            return Err(Error::Unavailable("Too many outstanding challenges".to_string()));
+        }
+        challenges.insert(nonce.clone(), now + state.challenge_ttl);
+    }
+    Ok(serde_json::to_string_pretty(&ChallengeResponse {
+        status: "ok".to_string(),
+        domain: state.domain.clone(),
+        nonce,
+        expires_in_seconds: state.challenge_ttl.as_secs(),
+    })?)
+}
+
+async fn verify_body(state: &VerifyServerState, body: &[u8]) -> Result<String, Error> {
+    let verify_request: VerifyRequest = serde_json::from_slice(body)?;
+    let pool_vrf_vkey = decode_vrf_vkey(&verify_request.pool_vrf_vkey)?;
+    let pool_vrf_vkey_hash = match (
+        &verify_request.pool_vrf_vkey_hash,
+        &verify_request.pool_id,
+        &state.socket_path,
+    ) {
+        (Some(pool_vrf_vkey_hash), _, _) => pool_vrf_vkey_hash.to_lowercase(),
+        (None, Some(pool_id), Some(socket_path)) => {
+            let pool_id = PoolId::from_str(pool_id).map_err(|error| Error::BadRequest(error.to_string()))?;
+            snapshot::get_pool_vrf_hash(socket_path, state.network_magic, &pool_id, &QueryTimeouts::default()).await?
+        }
+        (None, Some(_), None) => {
+            return Err(Error::BadRequest(
+                "poolId needs the server to run with --socket-path, send poolVrfVkeyHash instead".to_string(),
+            ))
+        }
+        (None, None, _) => return Err(Error::BadRequest("poolVrfVkeyHash or poolId is required".to_string())),
+    };
+
+    let expires_at = state.challenges.lock().unwrap().get(&verify_request.nonce).copied();
+    match expires_at {
+        Some(expires_at) if expires_at > Instant::now() => {}
+        _ => return Err(Error::NotFound("Unknown or expired challenge nonce".to_string())),
+    }
+    sign::check_signature(
+        &pool_vrf_vkey,
+        &pool_vrf_vkey_hash,
+        &state.domain,
+        &verify_request.nonce,
+        &verify_request.signature,
+    )
+    .map_err(Error::Unauthorized)?;
+    // a signed challenge cannot be replayed
+    state.challenges.lock().unwrap().remove(&verify_request.nonce);
+
+    Ok(serde_json::to_string_pretty(&VerifyResponse {
+        status: "ok".to_string(),
+        domain: state.domain.clone(),
+        pool_vrf_vkey_hash,
+    })?)
+}
+
+/// A vrf vkey as 32 bytes of hex or as the cborHex of a vrf.vkey file, which wraps them in a cbor byte string
+fn decode_vrf_vkey(pool_vrf_vkey: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::BadRequest("poolVrfVkey must be 32 bytes of hex or the cborHex of a vrf.vkey".to_string());
+    let bytes = hex::decode(pool_vrf_vkey).map_err(|_| invalid())?;
+    match bytes.len() {
+        32 => Ok(bytes),
+        34 if bytes[..2] == [0x58, 0x20] => Ok(bytes[2..].to_vec()),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_vrf_vkey() {
+        let vrf_vkey = "e0ff2371508ac339431b50af7d69cde0f120d952bb876806d3136f9a7fda4381";
+        assert_eq!(decode_vrf_vkey(vrf_vkey).unwrap(), hex::decode(vrf_vkey).unwrap());
+        assert_eq!(
+            decode_vrf_vkey(&format!("5820{vrf_vkey}")).unwrap(),
+            hex::decode(vrf_vkey).unwrap()
+        );
+        assert!(decode_vrf_vkey("5840e0ff").is_err());
+        assert!(decode_vrf_vkey("not hex").is_err());
+    }
+
+    #[test]
+    fn test_challenge_expires() {
+        let state = VerifyServerState {
+            domain: "pooltool.io".to_string(),
+            challenge_ttl: Duration::from_secs(0),
+            socket_path: None,
+            network_magic: 764824073,
+            challenges: Mutex::new(HashMap::new()),
+        };
+        challenge_body(&state).unwrap();
+        // the next challenge prunes the expired one
+        challenge_body(&state).unwrap();
+        assert_eq!(state.challenges.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,32 @@
+use pallas_crypto::hash::{Hash, Hasher};
+
+/// A vrf signing key holds the 32 byte secret seed followed by the verification key
+pub(crate) const VRF_SIGNING_KEY_SIZE: usize = 64;
+pub(crate) const VRF_VERIFICATION_KEY_SIZE: usize = 32;
+
+/// The verification key at the end of a vrf signing key, None when the signing key has the wrong size
+pub(crate) fn vrf_vkey_of_skey(vrf_skey: &[u8]) -> Option<&[u8]> {
+    if vrf_skey.len() != VRF_SIGNING_KEY_SIZE {
+        return None;
+    }
+    Some(&vrf_skey[VRF_SIGNING_KEY_SIZE - VRF_VERIFICATION_KEY_SIZE..])
+}
+
+/// The blake2b-256 hash of a vrf verification key, which is what a pool registers on chain
+pub(crate) fn vrf_vkey_hash(vrf_vkey: &[u8]) -> Hash<32> {
+    Hasher::<256>::hash(vrf_vkey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vrf_vkey_of_skey() {
+        let vrf_skey: Vec<u8> = (0..64u8).collect();
+        let vrf_vkey = vrf_vkey_of_skey(&vrf_skey).unwrap();
+        assert_eq!(vrf_vkey, &vrf_skey[32..]);
+        assert_eq!(vrf_vkey_hash(vrf_vkey), Hasher::<256>::hash(&vrf_skey[32..]));
+        assert_eq!(vrf_vkey_of_skey(&vrf_skey[..32]), None);
+    }
+}